@@ -7,6 +7,7 @@
 -- EXTENSIONES
 CREATE EXTENSION IF NOT EXISTS "uuid-ossp";
 CREATE EXTENSION IF NOT EXISTS "postgis";
+CREATE EXTENSION IF NOT EXISTS "btree_gist";
 
 -- =====================================================
 -- NIVEL 1 - COMPANIES (Tabla raíz)
@@ -46,9 +47,13 @@ CREATE TABLE users (
     -- Específicos para drivers
     tournee_number VARCHAR(20),
     driver_license VARCHAR(50),
+    -- Categorías del permiso (B, C1, C...) y furgoneta preferida para el planificador
+    license_categories VARCHAR(5)[] NOT NULL DEFAULT '{B}',
+    preferred_vehicle_id UUID,
     hire_date DATE,
     device_token VARCHAR(255),
     last_location POINT,
+    last_location_at TIMESTAMP WITH TIME ZONE,
     shift_start_time TIME,
     shift_end_time TIME,
     
@@ -86,6 +91,12 @@ CREATE TABLE vehicles (
     fuel_type VARCHAR(20) NOT NULL DEFAULT 'diesel',
     fuel_capacity DECIMAL(5,2),
     weekly_fuel_allocation DECIMAL(5,2),
+    parcel_capacity INTEGER CHECK (parcel_capacity > 0),
+    -- Consumo de referencia (L/100 km) y tarjeta de combustible asignada
+    expected_consumption DECIMAL(4,2) CHECK (expected_consumption > 0),
+    fuel_card_number VARCHAR(50),
+    -- Categoría de permiso necesaria para conducirlo
+    required_license VARCHAR(5) NOT NULL DEFAULT 'B',
     
     -- Métricas de daños
     total_damage_cost DECIMAL(10,2) NOT NULL DEFAULT 0,
@@ -103,9 +114,14 @@ CREATE TABLE vehicles (
     
     -- Constraints
     CONSTRAINT unique_license_plate_per_company UNIQUE (company_id, license_plate),
+    CONSTRAINT unique_fuel_card_per_company UNIQUE (company_id, fuel_card_number),
     CONSTRAINT positive_mileage CHECK (current_mileage >= 0)
 );
 
+-- Furgoneta preferida del chofer (users se crea antes)
+ALTER TABLE users ADD CONSTRAINT fk_users_preferred_vehicle
+    FOREIGN KEY (preferred_vehicle_id) REFERENCES vehicles(id) ON DELETE SET NULL;
+
 -- =====================================================
 -- NIVEL 2C - API_INTEGRATIONS
 -- =====================================================
@@ -309,6 +325,10 @@ CREATE TABLE tournees (
     external_tournee_id VARCHAR(100),
     integration_id UUID REFERENCES api_integrations(id) ON DELETE SET NULL,
     
+    -- Cierre del día: el compte-rendu firmado bloquea la tournée
+    locked_at TIMESTAMP WITH TIME ZONE,
+    locked_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    
     -- Metadatos
     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
     updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
@@ -318,6 +338,10 @@ CREATE TABLE tournees (
     CONSTRAINT unique_tournee_per_driver_date UNIQUE (driver_id, tournee_date)
 );
 
+-- Tournée en la que se declaró el daño (vehicle_damages se crea antes)
+ALTER TABLE vehicle_damages ADD CONSTRAINT fk_vehicle_damages_tournee
+    FOREIGN KEY (tournee_id) REFERENCES tournees(id) ON DELETE SET NULL;
+
 -- =====================================================
 -- NIVEL 4A - PACKAGES
 -- =====================================================
@@ -363,6 +387,7 @@ CREATE TABLE packages (
     failure_reason delivery_failure_reason,
     failure_notes TEXT,
     reschedule_date DATE,
+    next_action VARCHAR(30) CHECK (next_action IN ('reschedule', 'leave_notice', 'redirect_to_relay', 'return_to_sender')),
     
     -- Evidencia de entrega
     delivery_photo TEXT,
@@ -378,6 +403,10 @@ CREATE TABLE packages (
     driver_notes TEXT,
     package_condition TEXT,
     
+    -- Reloj del dispositivo del último cambio (last-writer-wins en la sincronización)
+    status_changed_at TIMESTAMP WITH TIME ZONE,
+    notes_changed_at TIMESTAMP WITH TIME ZONE,
+    
     -- Metadatos
     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
     updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
@@ -397,6 +426,7 @@ CREATE TABLE driver_field_data (
     
     -- Ubicación
     address TEXT NOT NULL,
+    normalized_address TEXT, -- Dirección normalizada para lookups (ver models::driver_field_data)
     postal_code VARCHAR(20),
     city VARCHAR(100),
     coordinates POINT,
@@ -424,14 +454,15 @@ CREATE TABLE driver_field_data (
     -- Última actualización
     last_updated_by UUID REFERENCES users(id) ON DELETE SET NULL,
     last_verified_date DATE,
+    version INTEGER NOT NULL DEFAULT 1, -- Control de concurrencia optimista (= última revisión)
     
     -- Metadatos
     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
     updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
-    deleted_at TIMESTAMP WITH TIME ZONE,
+    deleted_at TIMESTAMP WITH TIME ZONE
     
-    -- Constraints
-    CONSTRAINT unique_address_per_company UNIQUE (company_id, address)
+    -- Unicidad (company_id, address) solo entre entradas activas:
+    -- ver idx_driver_field_data_unique_address
 );
 
 -- =====================================================
@@ -459,7 +490,7 @@ CREATE TABLE performance_analytics (
     fuel_efficiency DECIMAL(6,2),
     
     -- Métricas de tiempo
-    total_working_hours DECIMAL(4,2),
+    total_working_hours DECIMAL(5,2),
     average_delivery_time_minutes DECIMAL(5,2),
     route_optimization_score DECIMAL(3,2),
     
@@ -566,3 +597,640 @@ CREATE TABLE sync_log (
     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
 );
 
+
+-- =====================================================
+-- NIVEL 6A - FIELD_DATA_ACCESS_LOG
+-- Auditoría de lecturas de datos sensibles de driver_field_data
+-- =====================================================
+CREATE TABLE field_data_access_log (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    field_data_id UUID NOT NULL REFERENCES driver_field_data(id) ON DELETE CASCADE,
+    user_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    
+    -- Qué se leyó y desde dónde
+    field_name VARCHAR(50) NOT NULL,
+    access_context VARCHAR(50) NOT NULL,
+    
+    -- Metadatos
+    accessed_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+
+-- =====================================================
+-- NIVEL 6B - MEDIA_OBJECTS
+-- Fotos de entrega, firmas, fotos de inspección y evidencias de daños
+-- =====================================================
+CREATE TABLE media_objects (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    
+    -- Entidad propietaria (packages, tournees, vehicle_damages o vehicle_documents según target)
+    target VARCHAR(50) NOT NULL,
+    entity_id UUID NOT NULL,
+    
+    -- Almacenamiento
+    storage_backend VARCHAR(20) NOT NULL,
+    storage_key TEXT NOT NULL UNIQUE,
+    thumbnail_key TEXT,
+    
+    -- Contenido
+    content_type VARCHAR(50) NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    content_sha256 VARCHAR(64),
+    width INTEGER,
+    height INTEGER,
+    original_filename VARCHAR(255),
+    
+    -- Metadatos
+    uploaded_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+
+-- =====================================================
+-- NIVEL 6C - DRIVER_FIELD_DATA_REVISIONS
+-- Historial inmutable de cambios de driver_field_data
+-- =====================================================
+CREATE TABLE driver_field_data_revisions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    field_data_id UUID NOT NULL REFERENCES driver_field_data(id) ON DELETE CASCADE,
+    revision_number INTEGER NOT NULL,
+    
+    -- Cambio (los campos sensibles se guardan cifrados)
+    change_type VARCHAR(20) NOT NULL CHECK (change_type IN ('create', 'update', 'revert', 'delete')),
+    author_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    diff JSONB NOT NULL,
+    snapshot JSONB NOT NULL,
+    reverted_from_revision INTEGER,
+    
+    -- Verificación (admin)
+    verified_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    verified_at TIMESTAMP WITH TIME ZONE,
+    
+    -- Metadatos
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    
+    -- Constraints
+    CONSTRAINT unique_revision_per_field_data UNIQUE (field_data_id, revision_number)
+);
+
+
+-- =====================================================
+-- NIVEL 6D - DELIVERY_PROOFS
+-- Prueba de entrega por paquete con cadena de hashes SHA-256 por empresa
+-- =====================================================
+CREATE TABLE delivery_proofs (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    package_id UUID NOT NULL UNIQUE REFERENCES packages(id) ON DELETE CASCADE,
+    tournee_id UUID NOT NULL REFERENCES tournees(id) ON DELETE CASCADE,
+    captured_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    
+    -- Evidencia
+    tracking_number VARCHAR(100) NOT NULL,
+    recipient_name VARCHAR(255) NOT NULL,
+    photo_media_ids UUID[] NOT NULL DEFAULT '{}',
+    photo_sha256 TEXT[] NOT NULL DEFAULT '{}',
+    signature_media_id UUID,
+    signature_sha256 VARCHAR(64),
+    
+    -- Posición GPS y tiempos
+    latitude DOUBLE PRECISION NOT NULL,
+    longitude DOUBLE PRECISION NOT NULL,
+    gps_accuracy_meters DOUBLE PRECISION NOT NULL,
+    device_id VARCHAR(100),
+    device_timestamp TIMESTAMP WITH TIME ZONE NOT NULL,
+    server_timestamp TIMESTAMP WITH TIME ZONE NOT NULL,
+    
+    -- Cadena de hashes
+    chain_position BIGINT NOT NULL,
+    previous_hash VARCHAR(64) NOT NULL,
+    evidence_hash VARCHAR(64) NOT NULL,
+    record_hash VARCHAR(64) NOT NULL,
+    
+    -- Metadatos
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    
+    -- Constraints
+    CONSTRAINT unique_delivery_proof_chain_position UNIQUE (company_id, chain_position),
+    CONSTRAINT valid_delivery_proof_coordinates CHECK (latitude BETWEEN -90 AND 90 AND longitude BETWEEN -180 AND 180),
+    CONSTRAINT valid_delivery_proof_accuracy CHECK (gps_accuracy_meters >= 0)
+);
+
+
+-- =====================================================
+-- NIVEL 6E - RELAY_POINTS
+-- Puntos relais a los que se redirigen los paquetes no entregados
+-- =====================================================
+CREATE TABLE relay_points (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    
+    -- Identificación
+    name VARCHAR(255) NOT NULL,
+    external_code VARCHAR(50),
+    address TEXT NOT NULL,
+    latitude DOUBLE PRECISION NOT NULL,
+    longitude DOUBLE PRECISION NOT NULL,
+    
+    -- Operación
+    capacity INTEGER CHECK (capacity > 0),
+    opening_hours TEXT,
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    
+    -- Metadatos
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    
+    -- Constraints
+    CONSTRAINT unique_relay_point_code_per_company UNIQUE (company_id, external_code),
+    CONSTRAINT valid_relay_point_coordinates CHECK (latitude BETWEEN -90 AND 90 AND longitude BETWEEN -180 AND 180)
+);
+
+-- Punto relais al que se redirigió el paquete
+ALTER TABLE packages ADD COLUMN relay_point_id UUID REFERENCES relay_points(id) ON DELETE SET NULL;
+
+
+-- =====================================================
+-- NIVEL 6F - SYNC_MUTATIONS
+-- Mutaciones recibidas de la app en modo offline (idempotencia y conflictos)
+-- =====================================================
+CREATE TABLE sync_mutations (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    device_id VARCHAR(100),
+    
+    -- Mutación
+    idempotency_key VARCHAR(100) NOT NULL,
+    mutation_type VARCHAR(20) NOT NULL,
+    entity_id UUID,
+    payload JSONB NOT NULL,
+    device_timestamp TIMESTAMP WITH TIME ZONE NOT NULL,
+    
+    -- Resultado (se devuelve tal cual si el lote se reenvía)
+    outcome VARCHAR(20) NOT NULL CHECK (outcome IN ('applied', 'conflict', 'rejected')),
+    result JSONB NOT NULL,
+    
+    -- Metadatos
+    received_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    
+    -- Constraints
+    CONSTRAINT unique_sync_mutation_key UNIQUE (company_id, user_id, idempotency_key)
+);
+
+
+-- =====================================================
+-- NIVEL 6G - SCAN_EVENTS
+-- Lecturas de código de barras (carga, entrega, fallo, devolución, recogida)
+-- =====================================================
+CREATE TABLE scan_events (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    tournee_id UUID REFERENCES tournees(id) ON DELETE SET NULL,
+    package_id UUID REFERENCES packages(id) ON DELETE SET NULL,
+    scanned_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    
+    -- Lectura
+    barcode VARCHAR(100) NOT NULL,
+    scan_type VARCHAR(20) NOT NULL CHECK (scan_type IN ('load', 'deliver', 'fail', 'return', 'pickup')),
+    scanned_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    latitude DOUBLE PRECISION,
+    longitude DOUBLE PRECISION,
+    device_id VARCHAR(100),
+    
+    -- Validación contra el contenido de la tournée
+    validation_status VARCHAR(20) NOT NULL CHECK (validation_status IN ('valid', 'unknown_barcode', 'wrong_tournee', 'duplicate')),
+    duplicate_of UUID REFERENCES scan_events(id) ON DELETE SET NULL,
+    
+    -- Metadatos
+    received_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    
+    -- Constraints
+    CONSTRAINT valid_scan_event_coordinates CHECK (
+        (latitude IS NULL OR latitude BETWEEN -90 AND 90) AND
+        (longitude IS NULL OR longitude BETWEEN -180 AND 180)
+    )
+);
+
+
+-- =====================================================
+-- NIVEL 6H - STATUS_HISTORY
+-- Historial inmutable de transiciones de estado de tournées y paquetes
+-- =====================================================
+CREATE TABLE status_history (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    
+    -- Entidad y transición
+    entity_type VARCHAR(20) NOT NULL CHECK (entity_type IN ('tournee', 'package', 'vehicle_damage')),
+    entity_id UUID NOT NULL,
+    from_status VARCHAR(30),
+    to_status VARCHAR(30) NOT NULL,
+    
+    -- Contexto
+    changed_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    forced BOOLEAN NOT NULL DEFAULT FALSE,
+    reason TEXT,
+    metadata JSONB NOT NULL DEFAULT '{}',
+    
+    -- Metadatos
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+
+-- =====================================================
+-- NIVEL 6I - LOCATION_BREADCRUMBS
+-- Posiciones GPS de los choferes, una partición por día de recorded_at
+-- (services::location_tracking crea las particiones y borra las antiguas)
+-- =====================================================
+CREATE TABLE location_breadcrumbs (
+    tournee_id UUID NOT NULL REFERENCES tournees(id) ON DELETE CASCADE,
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    driver_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    
+    -- Posición
+    recorded_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    latitude DOUBLE PRECISION NOT NULL,
+    longitude DOUBLE PRECISION NOT NULL,
+    accuracy_meters DOUBLE PRECISION,
+    speed_mps DOUBLE PRECISION,
+    heading_degrees DOUBLE PRECISION,
+    
+    -- Metadatos
+    received_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    
+    -- Constraints (los reenvíos del mismo punto se descartan)
+    PRIMARY KEY (tournee_id, driver_id, recorded_at),
+    CONSTRAINT valid_breadcrumb_coordinates CHECK (latitude BETWEEN -90 AND 90 AND longitude BETWEEN -180 AND 180),
+    CONSTRAINT valid_breadcrumb_accuracy CHECK (accuracy_meters IS NULL OR accuracy_meters >= 0),
+    CONSTRAINT valid_breadcrumb_speed CHECK (speed_mps IS NULL OR speed_mps >= 0),
+    CONSTRAINT valid_breadcrumb_heading CHECK (heading_degrees IS NULL OR (heading_degrees >= 0 AND heading_degrees < 360))
+) PARTITION BY RANGE (recorded_at);
+
+-- =====================================================
+-- NIVEL 6J - STOP_VISITS
+-- Llegadas y salidas de las paradas detectadas por geocerca
+-- (services::geofencing)
+-- =====================================================
+CREATE TABLE stop_visits (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    tournee_id UUID NOT NULL REFERENCES tournees(id) ON DELETE CASCADE,
+    driver_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    
+    -- Parada (paquetes con las mismas coordenadas)
+    latitude DOUBLE PRECISION NOT NULL,
+    longitude DOUBLE PRECISION NOT NULL,
+    package_ids UUID[] NOT NULL,
+    
+    -- Visita: entered_at es la primera posición dentro de la geocerca; la
+    -- llegada se confirma tras el tiempo mínimo de permanencia
+    entered_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    last_seen_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    arrival_confirmed BOOLEAN NOT NULL DEFAULT FALSE,
+    departed_at TIMESTAMP WITH TIME ZONE,
+    dwell_seconds INTEGER,
+    
+    -- Metadatos
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    
+    -- Constraints
+    CONSTRAINT valid_stop_visit_times CHECK (last_seen_at >= entered_at AND (departed_at IS NULL OR departed_at >= entered_at)),
+    CONSTRAINT valid_stop_visit_dwell CHECK (dwell_seconds IS NULL OR dwell_seconds >= 0)
+);
+
+-- =====================================================
+-- NIVEL 6K - COMPTE_RENDUS
+-- Compte-rendu de fin de día por tournée: recuento propio (estados de los
+-- paquetes y lecturas), recuento del transportista y diferencias
+-- (services::compte_rendu)
+-- =====================================================
+CREATE TABLE compte_rendus (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    tournee_id UUID NOT NULL UNIQUE REFERENCES tournees(id) ON DELETE CASCADE,
+    driver_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    tournee_date DATE NOT NULL,
+    
+    -- Recuento propio
+    expected_packages INTEGER NOT NULL DEFAULT 0,
+    delivered_count INTEGER NOT NULL DEFAULT 0,
+    failed_count INTEGER NOT NULL DEFAULT 0,
+    relay_count INTEGER NOT NULL DEFAULT 0,
+    returned_count INTEGER NOT NULL DEFAULT 0,
+    carried_over_count INTEGER NOT NULL DEFAULT 0,
+    unresolved_count INTEGER NOT NULL DEFAULT 0,
+    cancelled_count INTEGER NOT NULL DEFAULT 0,
+    loaded_scans INTEGER NOT NULL DEFAULT 0,
+    delivered_scans INTEGER NOT NULL DEFAULT 0,
+    flagged_scans INTEGER NOT NULL DEFAULT 0,
+    pickup_expected_parcels INTEGER NOT NULL DEFAULT 0,
+    collected_parcels INTEGER NOT NULL DEFAULT 0,
+    
+    -- Recuento del transportista (BeanTournee) y diferencias
+    carrier_counts JSONB,
+    mismatches JSONB NOT NULL DEFAULT '[]',
+    
+    -- Firma
+    report_status VARCHAR(20) NOT NULL DEFAULT 'draft',
+    signed_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    signed_at TIMESTAMP WITH TIME ZONE,
+    signature_name VARCHAR(255),
+    signature_comment TEXT,
+    
+    -- Metadatos
+    generated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    
+    -- Constraints
+    CONSTRAINT valid_compte_rendu_status CHECK (report_status IN ('draft', 'signed')),
+    CONSTRAINT signed_compte_rendu_complete CHECK (report_status = 'draft' OR (signed_at IS NOT NULL AND signature_name IS NOT NULL))
+);
+
+-- =====================================================
+-- NIVEL 6L - PICKUPS
+-- Recogidas (collectes) planificadas como paradas de la tournée; los bultos
+-- recogidos se confirman leyendo sus códigos (services::pickups)
+-- =====================================================
+CREATE TABLE pickups (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    tournee_id UUID NOT NULL REFERENCES tournees(id) ON DELETE CASCADE,
+    
+    -- Identificación
+    pickup_reference VARCHAR(100) NOT NULL,
+    pickup_type VARCHAR(20) NOT NULL DEFAULT 'merchant',
+    contact_name VARCHAR(255),
+    contact_phone VARCHAR(20),
+    pickup_address TEXT NOT NULL,
+    latitude DOUBLE PRECISION,
+    longitude DOUBLE PRECISION,
+    
+    -- Planificación
+    expected_parcels INTEGER NOT NULL,
+    window_start TIMESTAMP WITH TIME ZONE,
+    window_end TIMESTAMP WITH TIME ZONE,
+    pickup_instructions TEXT,
+    
+    -- Resultado
+    pickup_status VARCHAR(20) NOT NULL DEFAULT 'pending',
+    collected_parcels INTEGER NOT NULL DEFAULT 0,
+    collected_at TIMESTAMP WITH TIME ZONE,
+    failure_notes TEXT,
+    
+    -- Metadatos
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    deleted_at TIMESTAMP WITH TIME ZONE,
+    
+    -- Constraints
+    CONSTRAINT unique_pickup_reference_per_tournee UNIQUE (tournee_id, pickup_reference),
+    CONSTRAINT valid_pickup_type CHECK (pickup_type IN ('merchant', 'relay_point', 'customer_return')),
+    CONSTRAINT valid_pickup_status CHECK (pickup_status IN ('pending', 'collected', 'partial', 'failed', 'cancelled')),
+    CONSTRAINT positive_expected_parcels CHECK (expected_parcels > 0),
+    CONSTRAINT non_negative_collected_parcels CHECK (collected_parcels >= 0),
+    CONSTRAINT valid_pickup_window CHECK (window_start IS NULL OR window_end IS NULL OR window_start < window_end),
+    CONSTRAINT valid_pickup_coordinates CHECK (
+        (latitude IS NULL AND longitude IS NULL) OR
+        (latitude BETWEEN -90 AND 90 AND longitude BETWEEN -180 AND 180)
+    )
+);
+
+-- Recogida a la que corresponde una lectura de tipo 'pickup'
+ALTER TABLE scan_events ADD COLUMN pickup_id UUID REFERENCES pickups(id) ON DELETE SET NULL;
+
+
+-- =====================================================
+-- NIVEL 6M - FUEL_CARD_TRANSACTIONS
+-- Repostajes importados del CSV de la tarjeta de combustible
+-- =====================================================
+CREATE TABLE fuel_card_transactions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    
+    -- Datos del fichero
+    card_number VARCHAR(50) NOT NULL,
+    license_plate VARCHAR(20),
+    transacted_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    station VARCHAR(255),
+    product VARCHAR(100),
+    liters DECIMAL(7,2) NOT NULL,
+    amount DECIMAL(8,2) NOT NULL,
+    
+    -- Asociación (por matrícula o por tarjeta del vehículo; tournée del día)
+    vehicle_id UUID REFERENCES vehicles(id) ON DELETE SET NULL,
+    tournee_id UUID REFERENCES tournees(id) ON DELETE SET NULL,
+    driver_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    match_status VARCHAR(20) NOT NULL,
+    import_batch UUID NOT NULL,
+    imported_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    
+    -- Metadatos
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    
+    -- Constraints (reimportar el mismo fichero no duplica)
+    CONSTRAINT unique_fuel_card_transaction UNIQUE (company_id, card_number, transacted_at, amount),
+    CONSTRAINT valid_fuel_match_status CHECK (match_status IN ('matched', 'vehicle_only', 'unmatched')),
+    CONSTRAINT positive_fuel_liters CHECK (liters > 0)
+);
+
+-- =====================================================
+-- NIVEL 6N - FUEL_RECONCILIATIONS
+-- Cuadre de kilometraje y combustible de una tournée cerrada
+-- =====================================================
+CREATE TABLE fuel_reconciliations (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    tournee_id UUID NOT NULL UNIQUE REFERENCES tournees(id) ON DELETE CASCADE,
+    vehicle_id UUID NOT NULL REFERENCES vehicles(id) ON DELETE CASCADE,
+    driver_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    tournee_date DATE NOT NULL,
+    
+    -- Distancias (km)
+    odometer_km DECIMAL(8,2),
+    planned_km DECIMAL(8,2),
+    planned_source VARCHAR(20),
+    gps_km DECIMAL(8,2),
+    odometer_gps_deviation_pct DECIMAL(7,2),
+    odometer_planned_deviation_pct DECIMAL(7,2),
+    
+    -- Combustible (L)
+    fuel_consumed DECIMAL(5,2),
+    expected_consumption DECIMAL(4,2),
+    expected_fuel DECIMAL(6,2),
+    fuel_deviation_pct DECIMAL(7,2),
+    week_fuel_consumed DECIMAL(7,2),
+    weekly_fuel_allocation DECIMAL(5,2),
+    card_liters DECIMAL(7,2) NOT NULL DEFAULT 0,
+    card_amount DECIMAL(8,2) NOT NULL DEFAULT 0,
+    
+    -- Resultado
+    flags JSONB NOT NULL DEFAULT '[]',
+    alerted_at TIMESTAMP WITH TIME ZONE,
+    computed_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    
+    -- Metadatos
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    
+    CONSTRAINT valid_planned_source CHECK (planned_source IS NULL OR planned_source IN ('route', 'stops'))
+);
+
+
+-- =====================================================
+-- NIVEL 6O - MAINTENANCE_PLANS
+-- Plan de mantenimiento preventivo por modelo de vehículo (una fila por tarea)
+-- =====================================================
+CREATE TABLE maintenance_plans (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    
+    -- Modelo al que aplica (vehicles.brand / vehicles.model)
+    brand VARCHAR(100) NOT NULL,
+    model VARCHAR(100) NOT NULL,
+    
+    -- Tarea y periodicidad (lo que llegue antes)
+    task_name VARCHAR(100) NOT NULL,
+    description TEXT,
+    interval_km INTEGER CHECK (interval_km > 0),
+    interval_months INTEGER CHECK (interval_months > 0),
+    estimated_hours DECIMAL(4,1) CHECK (estimated_hours > 0),
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    
+    -- Metadatos
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    deleted_at TIMESTAMP WITH TIME ZONE,
+    
+    CONSTRAINT maintenance_plan_has_interval CHECK (interval_km IS NOT NULL OR interval_months IS NOT NULL)
+);
+
+-- =====================================================
+-- NIVEL 6P - VEHICLE_MAINTENANCE
+-- Citas de taller: el vehículo no se puede asignar durante la ventana
+-- =====================================================
+CREATE TABLE vehicle_maintenance (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    vehicle_id UUID NOT NULL REFERENCES vehicles(id) ON DELETE CASCADE,
+    plan_id UUID REFERENCES maintenance_plans(id) ON DELETE SET NULL,
+    task_name VARCHAR(100) NOT NULL,
+    
+    -- Ventana reservada
+    maintenance_status VARCHAR(20) NOT NULL DEFAULT 'scheduled',
+    scheduled_start TIMESTAMP WITH TIME ZONE NOT NULL,
+    scheduled_end TIMESTAMP WITH TIME ZONE NOT NULL,
+    started_at TIMESTAMP WITH TIME ZONE,
+    completed_at TIMESTAMP WITH TIME ZONE,
+    
+    -- Resultado
+    mileage_at_service DECIMAL(10,2),
+    cost DECIMAL(10,2),
+    workshop VARCHAR(255),
+    notes TEXT,
+    
+    -- Metadatos
+    created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    
+    CONSTRAINT valid_maintenance_status CHECK (maintenance_status IN ('scheduled', 'in_progress', 'completed', 'cancelled')),
+    CONSTRAINT valid_maintenance_window CHECK (scheduled_end > scheduled_start),
+    -- Sin citas activas solapadas del mismo vehículo
+    CONSTRAINT no_overlapping_maintenance EXCLUDE USING gist (
+        vehicle_id WITH =,
+        tstzrange(scheduled_start, scheduled_end) WITH &&
+    ) WHERE (maintenance_status IN ('scheduled', 'in_progress'))
+);
+
+-- =====================================================
+-- NIVEL 6Q - VEHICLE_MAINTENANCE_SCHEDULE
+-- Próximo vencimiento de cada tarea del plan por vehículo (job diario)
+-- =====================================================
+CREATE TABLE vehicle_maintenance_schedule (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    vehicle_id UUID NOT NULL REFERENCES vehicles(id) ON DELETE CASCADE,
+    plan_id UUID NOT NULL REFERENCES maintenance_plans(id) ON DELETE CASCADE,
+    
+    -- Último servicio (NULL si nunca se ha hecho)
+    last_service_date DATE,
+    last_service_mileage DECIMAL(10,2),
+    
+    -- Vencimiento y previsión
+    due_mileage DECIMAL(10,2),
+    due_date DATE,
+    projected_date DATE,
+    avg_daily_km DECIMAL(7,2),
+    due_state VARCHAR(20) NOT NULL DEFAULT 'ok',
+    
+    -- Avisos (uno por vencimiento; vuelven a FALSE al completar el servicio)
+    reminder_sent BOOLEAN NOT NULL DEFAULT FALSE,
+    overdue_sent BOOLEAN NOT NULL DEFAULT FALSE,
+    
+    -- Metadatos
+    computed_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    
+    CONSTRAINT unique_vehicle_maintenance_plan UNIQUE (vehicle_id, plan_id),
+    CONSTRAINT valid_maintenance_due_state CHECK (due_state IN ('ok', 'upcoming', 'overdue'))
+);
+
+
+-- =====================================================
+-- NIVEL 6R - VEHICLE_ASSIGNMENTS
+-- Asignación de furgoneta aceptada por el despacho (propuesta y cambios)
+-- =====================================================
+CREATE TABLE vehicle_assignments (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    tournee_id UUID NOT NULL UNIQUE REFERENCES tournees(id) ON DELETE CASCADE,
+    driver_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    assignment_date DATE NOT NULL,
+    
+    -- Vehículo asignado y el que proponía el planificador
+    vehicle_id UUID NOT NULL REFERENCES vehicles(id) ON DELETE CASCADE,
+    proposed_vehicle_id UUID REFERENCES vehicles(id) ON DELETE SET NULL,
+    overridden BOOLEAN NOT NULL DEFAULT FALSE,
+    override_reason TEXT,
+    
+    -- Metadatos
+    accepted_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    accepted_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+
+-- =====================================================
+-- NIVEL 6S - VEHICLE_PERFORMANCE_WEEKS
+-- Consumo y coste semanales de cada vehículo (referencia de sus anomalías)
+-- =====================================================
+CREATE TABLE vehicle_performance_weeks (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    company_id UUID NOT NULL REFERENCES companies(id) ON DELETE CASCADE,
+    vehicle_id UUID NOT NULL REFERENCES vehicles(id) ON DELETE CASCADE,
+    week_start_date DATE NOT NULL,
+    
+    -- Métricas de las tournées completadas de la semana
+    km_driven DECIMAL(8,2) NOT NULL DEFAULT 0,
+    fuel_consumed DECIMAL(7,2) NOT NULL DEFAULT 0,
+    fuel_cost DECIMAL(8,2) NOT NULL DEFAULT 0,
+    fuel_efficiency DECIMAL(6,2),
+    cost_per_km DECIMAL(6,3),
+    
+    -- Banderas de anomalías
+    anomaly_flags JSONB DEFAULT '{}',
+    
+    -- Metadatos
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    
+    CONSTRAINT unique_vehicle_week UNIQUE (vehicle_id, week_start_date)
+);
@@ -56,6 +56,9 @@ CREATE INDEX idx_tournees_deleted_at ON tournees(deleted_at);
 CREATE INDEX idx_tournees_driver_date ON tournees(driver_id, tournee_date);
 CREATE INDEX idx_tournees_company_date ON tournees(company_id, tournee_date);
 CREATE INDEX idx_tournees_traffic_conditions ON tournees USING GIN(traffic_conditions);
+-- Una sola tournée en curso (o en pausa) por chofer
+CREATE UNIQUE INDEX idx_tournees_one_active_per_driver ON tournees(driver_id)
+    WHERE tournee_status IN ('in_progress', 'paused') AND deleted_at IS NULL;
 CREATE INDEX idx_tournees_weather_conditions ON tournees USING GIN(weather_conditions);
 
 -- Índices para packages
@@ -72,6 +75,8 @@ CREATE INDEX idx_packages_company_status_date ON packages(company_id, delivery_s
 CREATE INDEX idx_driver_field_data_company_id ON driver_field_data(company_id);
 CREATE INDEX idx_driver_field_data_driver_id ON driver_field_data(driver_id);
 CREATE INDEX idx_driver_field_data_address ON driver_field_data(address);
+CREATE UNIQUE INDEX idx_driver_field_data_unique_address ON driver_field_data(company_id, address) WHERE deleted_at IS NULL;
+CREATE INDEX idx_driver_field_data_normalized_address ON driver_field_data(company_id, normalized_address);
 CREATE INDEX idx_driver_field_data_postal_code ON driver_field_data(postal_code);
 CREATE INDEX idx_driver_field_data_city ON driver_field_data(city);
 CREATE INDEX idx_driver_field_data_coordinates ON driver_field_data USING GIST(coordinates);
@@ -84,6 +89,7 @@ CREATE INDEX idx_performance_analytics_week_start ON performance_analytics(week_
 CREATE INDEX idx_performance_analytics_week_end ON performance_analytics(week_end_date);
 CREATE INDEX idx_performance_analytics_driver_week ON performance_analytics(driver_id, week_start_date);
 CREATE INDEX idx_performance_analytics_company_week ON performance_analytics(company_id, week_start_date);
+CREATE INDEX idx_vehicle_performance_weeks_company_week ON vehicle_performance_weeks(company_id, week_start_date);
 
 -- Índices para notifications_log
 CREATE INDEX idx_notifications_log_company_id ON notifications_log(company_id);
@@ -103,6 +109,82 @@ CREATE INDEX idx_sync_log_sync_type ON sync_log(sync_type);
 CREATE INDEX idx_sync_log_errors_count ON sync_log(errors_count);
 CREATE INDEX idx_sync_log_company_date ON sync_log(company_id, sync_date);
 
+-- Índices para field_data_access_log
+CREATE INDEX idx_field_data_access_log_field_data_id ON field_data_access_log(field_data_id);
+CREATE INDEX idx_field_data_access_log_company_date ON field_data_access_log(company_id, accessed_at);
+CREATE INDEX idx_field_data_access_log_user_id ON field_data_access_log(user_id);
+
+-- Índices para media_objects
+CREATE INDEX idx_media_objects_entity ON media_objects(company_id, target, entity_id);
+CREATE INDEX idx_media_objects_uploaded_by ON media_objects(uploaded_by);
+
+-- Índices para driver_field_data_revisions
+CREATE INDEX idx_driver_field_data_revisions_company ON driver_field_data_revisions(company_id, created_at);
+CREATE INDEX idx_driver_field_data_revisions_author ON driver_field_data_revisions(author_id);
+
+-- Índices para delivery_proofs
+CREATE INDEX idx_delivery_proofs_tournee_id ON delivery_proofs(tournee_id);
+CREATE INDEX idx_delivery_proofs_captured_by ON delivery_proofs(captured_by);
+
+-- Índices para relay_points
+CREATE INDEX idx_relay_points_company_active ON relay_points(company_id, is_active);
+
+-- Índices para el flujo de paquetes no entregados
+CREATE INDEX idx_packages_relay_point_id ON packages(relay_point_id);
+CREATE INDEX idx_packages_company_reschedule ON packages(company_id, reschedule_date) WHERE delivery_status = 'failed';
+
+-- Índices para la sincronización offline
+CREATE INDEX idx_packages_tournee_updated ON packages(tournee_id, updated_at, id);
+CREATE INDEX idx_sync_mutations_user_received ON sync_mutations(user_id, received_at);
+CREATE INDEX idx_sync_mutations_entity ON sync_mutations(entity_id);
+
+-- Índices para scan_events
+CREATE INDEX idx_scan_events_tournee_type ON scan_events(tournee_id, scan_type);
+CREATE INDEX idx_scan_events_package_type ON scan_events(package_id, scan_type);
+CREATE INDEX idx_scan_events_company_scanned ON scan_events(company_id, scanned_at);
+CREATE INDEX idx_scan_events_flagged ON scan_events(company_id, validation_status) WHERE validation_status <> 'valid';
+
+-- Índices para status_history
+CREATE INDEX idx_status_history_entity ON status_history(entity_type, entity_id, created_at);
+CREATE INDEX idx_status_history_company_created ON status_history(company_id, created_at);
+
+-- Índices para location_breadcrumbs (se propagan a cada partición)
+CREATE INDEX idx_location_breadcrumbs_driver_recorded ON location_breadcrumbs(driver_id, recorded_at);
+
+-- Índices para stop_visits
+CREATE INDEX idx_stop_visits_tournee ON stop_visits(tournee_id, entered_at);
+CREATE INDEX idx_stop_visits_driver_departed ON stop_visits(driver_id, departed_at) WHERE arrival_confirmed;
+-- Una sola visita abierta por chofer y tournée
+CREATE UNIQUE INDEX idx_stop_visits_open ON stop_visits(tournee_id, driver_id) WHERE departed_at IS NULL;
+
+-- Índices para compte_rendus
+CREATE INDEX idx_compte_rendus_company_date ON compte_rendus(company_id, tournee_date);
+CREATE INDEX idx_compte_rendus_driver_date ON compte_rendus(driver_id, tournee_date);
+
+-- Índices para pickups
+CREATE INDEX idx_pickups_tournee_status ON pickups(tournee_id, pickup_status) WHERE deleted_at IS NULL;
+CREATE INDEX idx_pickups_company_window ON pickups(company_id, window_start);
+CREATE INDEX idx_scan_events_pickup ON scan_events(pickup_id) WHERE pickup_id IS NOT NULL;
+
+-- Índices para fuel_card_transactions
+CREATE INDEX idx_fuel_card_transactions_company_date ON fuel_card_transactions(company_id, transacted_at);
+CREATE INDEX idx_fuel_card_transactions_tournee ON fuel_card_transactions(tournee_id) WHERE tournee_id IS NOT NULL;
+CREATE INDEX idx_fuel_card_transactions_vehicle ON fuel_card_transactions(vehicle_id, transacted_at);
+
+-- Índices para fuel_reconciliations
+CREATE INDEX idx_fuel_reconciliations_company_date ON fuel_reconciliations(company_id, tournee_date);
+CREATE INDEX idx_fuel_reconciliations_vehicle_date ON fuel_reconciliations(vehicle_id, tournee_date);
+
+-- Índices para el mantenimiento preventivo
+CREATE UNIQUE INDEX idx_maintenance_plans_task ON maintenance_plans(company_id, lower(brand), lower(model), lower(task_name)) WHERE deleted_at IS NULL;
+CREATE INDEX idx_vehicle_maintenance_window ON vehicle_maintenance(vehicle_id, scheduled_start, scheduled_end) WHERE maintenance_status IN ('scheduled', 'in_progress');
+CREATE INDEX idx_vehicle_maintenance_company ON vehicle_maintenance(company_id, scheduled_start);
+CREATE INDEX idx_vehicle_maintenance_schedule_due ON vehicle_maintenance_schedule(company_id, due_state, projected_date);
+
+-- Índices para vehicle_assignments
+CREATE INDEX idx_vehicle_assignments_company_date ON vehicle_assignments(company_id, assignment_date);
+CREATE INDEX idx_vehicle_assignments_vehicle ON vehicle_assignments(vehicle_id, assignment_date);
+
 -- =====================================================
 -- FUNCIONES Y TRIGGERS AUTOMÁTICOS
 -- =====================================================
@@ -152,6 +234,56 @@ BEGIN
 END;
 $$ language 'plpgsql';
 
+-- Función para mantener inmutables las filas de una tabla (pruebas de entrega,
+-- historial de estados, revisiones de driver_field_data)
+-- TG_ARGV lista las excepciones:
+--   'columna'          solo puede pasar a NULL (ON DELETE SET NULL de una FK)
+--   'once:columna'     puede asignarse mientras sea NULL
+--   'mutable:columna'  puede cambiar (p. ej. re-cifrado en la rotación de claves)
+-- Enganchada también a DELETE, solo deja borrar en cascada al eliminar la
+-- empresa entera.
+CREATE OR REPLACE FUNCTION prevent_immutable_row_changes()
+RETURNS TRIGGER AS $$
+DECLARE
+    old_row JSONB := to_jsonb(OLD);
+    new_row JSONB := to_jsonb(NEW);
+    exception_spec TEXT;
+    rule TEXT;
+    column_name TEXT;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        IF old_row ? 'company_id' AND NOT EXISTS (
+            SELECT 1 FROM companies WHERE id = (old_row ->> 'company_id')::uuid
+        ) THEN
+            RETURN OLD;
+        END IF;
+        RAISE EXCEPTION 'Las filas de % no se pueden borrar', TG_TABLE_NAME;
+    END IF;
+
+    FOREACH exception_spec IN ARRAY TG_ARGV LOOP
+        IF position(':' IN exception_spec) > 0 THEN
+            rule := split_part(exception_spec, ':', 1);
+            column_name := split_part(exception_spec, ':', 2);
+        ELSE
+            rule := 'nullable';
+            column_name := exception_spec;
+        END IF;
+
+        IF (rule = 'nullable' AND new_row -> column_name = 'null'::jsonb)
+            OR (rule = 'once' AND old_row -> column_name = 'null'::jsonb)
+            OR rule = 'mutable'
+        THEN
+            new_row := new_row || jsonb_build_object(column_name, old_row -> column_name);
+        END IF;
+    END LOOP;
+
+    IF new_row IS DISTINCT FROM old_row THEN
+        RAISE EXCEPTION 'Las filas de % son inmutables', TG_TABLE_NAME;
+    END IF;
+    RETURN NEW;
+END;
+$$ language 'plpgsql';
+
 -- =====================================================
 -- TRIGGERS
 -- =====================================================
@@ -187,6 +319,36 @@ CREATE TRIGGER update_driver_field_data_updated_at BEFORE UPDATE ON driver_field
 CREATE TRIGGER update_performance_analytics_updated_at BEFORE UPDATE ON performance_analytics
     FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
 
+CREATE TRIGGER update_relay_points_updated_at BEFORE UPDATE ON relay_points
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
+CREATE TRIGGER update_stop_visits_updated_at BEFORE UPDATE ON stop_visits
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
+CREATE TRIGGER update_compte_rendus_updated_at BEFORE UPDATE ON compte_rendus
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
+CREATE TRIGGER update_pickups_updated_at BEFORE UPDATE ON pickups
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
+CREATE TRIGGER update_fuel_reconciliations_updated_at BEFORE UPDATE ON fuel_reconciliations
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
+CREATE TRIGGER update_maintenance_plans_updated_at BEFORE UPDATE ON maintenance_plans
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
+CREATE TRIGGER update_vehicle_maintenance_updated_at BEFORE UPDATE ON vehicle_maintenance
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
+CREATE TRIGGER update_vehicle_maintenance_schedule_updated_at BEFORE UPDATE ON vehicle_maintenance_schedule
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
+CREATE TRIGGER update_vehicle_assignments_updated_at BEFORE UPDATE ON vehicle_assignments
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
+CREATE TRIGGER update_vehicle_performance_weeks_updated_at BEFORE UPDATE ON vehicle_performance_weeks
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
 -- Trigger para calcular distancia de tournée
 CREATE TRIGGER calculate_tournee_distance_trigger
     BEFORE INSERT OR UPDATE ON tournees
@@ -197,3 +359,20 @@ CREATE TRIGGER update_document_status_trigger
     BEFORE INSERT OR UPDATE ON vehicle_documents
     FOR EACH ROW EXECUTE FUNCTION update_document_status();
 
+-- Triggers de inmutabilidad (ver prevent_immutable_row_changes)
+CREATE TRIGGER prevent_delivery_proof_changes_trigger
+    BEFORE UPDATE OR DELETE ON delivery_proofs
+    FOR EACH ROW EXECUTE FUNCTION prevent_immutable_row_changes('captured_by');
+
+CREATE TRIGGER prevent_status_history_changes_trigger
+    BEFORE UPDATE OR DELETE ON status_history
+    FOR EACH ROW EXECUTE FUNCTION prevent_immutable_row_changes('changed_by');
+
+-- Revisiones: verificar una vez y re-cifrar diff/snapshot en la rotación
+CREATE TRIGGER prevent_field_data_revision_changes_trigger
+    BEFORE UPDATE ON driver_field_data_revisions
+    FOR EACH ROW EXECUTE FUNCTION prevent_immutable_row_changes(
+        'author_id', 'verified_by', 'once:verified_by', 'once:verified_at',
+        'mutable:diff', 'mutable:snapshot'
+    );
+
@@ -0,0 +1,282 @@
+//! API de jobs de validación de direcciones en lote
+//!
+//! Permite enviar una lista de direcciones (o una tournée) para validarlas en
+//! background, consultar el progreso y descargar los resultados. Las rutas
+//! van bajo `/api/v1` (JWT) y cada job solo es visible para la empresa que lo
+//! creó.
+
+use axum::{
+    extract::{Extension, Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+    routing::{get, post},
+    Router,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::api::driver_scope;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::driver_field_data::normalize_address;
+use crate::services::address_validation::AddressContext;
+use crate::services::address_validation_jobs::{validated_addresses_to_csv, AddressValidationJobSnapshot};
+use crate::services::driver_field_data_service;
+use crate::state::AppState;
+
+/// Máximo de direcciones aceptadas por job
+const MAX_ADDRESSES_PER_JOB: usize = 1000;
+
+/// Request para crear un job de validación
+#[derive(Debug, Deserialize)]
+pub struct CreateValidationJobRequest {
+    /// Lista explícita de direcciones
+    pub addresses: Option<Vec<String>>,
+    /// Tournée cuyas direcciones de paquetes se validan
+    pub tournee_id: Option<Uuid>,
+}
+
+/// Response con el estado de un job
+#[derive(Debug, Serialize)]
+pub struct ValidationJobResponse {
+    pub success: bool,
+    pub job: Option<AddressValidationJobSnapshot>,
+    pub message: Option<String>,
+    pub error: Option<String>,
+}
+
+impl ValidationJobResponse {
+    fn error(message: impl Into<String>) -> Json<Self> {
+        Json(Self {
+            success: false,
+            job: None,
+            message: None,
+            error: Some(message.into()),
+        })
+    }
+}
+
+/// Parámetros de exportación de resultados
+#[derive(Debug, Deserialize)]
+pub struct ValidationResultsQuery {
+    /// `json` (por defecto) o `csv`
+    pub format: Option<String>,
+    /// `manual` para exportar solo las direcciones `ManualRequired`
+    pub filter: Option<String>,
+    /// Exportar solo direcciones con score de confianza menor o igual
+    pub max_score: Option<u8>,
+}
+
+pub fn create_address_validation_jobs_router() -> Router<AppState> {
+    Router::new()
+        .route("/address-validation/jobs", post(create_validation_job))
+        .route("/address-validation/jobs/:id", get(get_validation_job))
+        .route("/address-validation/jobs/:id/results", get(get_validation_job_results))
+}
+
+/// POST /api/v1/address-validation/jobs - Crear un job de validación
+pub async fn create_validation_job(
+    Extension(user): Extension<AuthenticatedUser>,
+    State(state): State<AppState>,
+    Json(request): Json<CreateValidationJobRequest>,
+) -> Result<(StatusCode, Json<ValidationJobResponse>), StatusCode> {
+    let mapbox_token = match &state.config.mapbox_token {
+        Some(token) => token.clone(),
+        None => {
+            log::error!("❌ Mapbox token not configured");
+            return Ok((StatusCode::OK, ValidationJobResponse::error("Mapbox token not configured")));
+        }
+    };
+
+    // El job corre a nombre del usuario autenticado (su sector completa las direcciones)
+    let username = sqlx::query_scalar::<_, String>(
+        "SELECT username FROM users WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL",
+    )
+    .bind(user.user_id)
+    .bind(user.company_id)
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| {
+        log::error!("❌ Error cargando el usuario {}: {}", user.user_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let mut addresses = request.addresses.unwrap_or_default();
+
+    if let Some(tournee_id) = request.tournee_id {
+        // La tournée tiene que ser de la empresa (y del chofer, si lo es)
+        let tournee_exists = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM tournees
+                WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
+                AND ($3::uuid IS NULL OR driver_id = $3)
+            )
+            "#,
+        )
+        .bind(tournee_id)
+        .bind(user.company_id)
+        .bind(driver_scope(&user))
+        .fetch_one(&state.pool)
+        .await
+        .map_err(|e| {
+            log::error!("❌ Error comprobando la tournée {}: {}", tournee_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        if !tournee_exists {
+            return Err(StatusCode::NOT_FOUND);
+        }
+
+        let tournee_addresses: Vec<String> = sqlx::query_scalar(
+            r#"
+            SELECT delivery_address
+            FROM packages
+            WHERE tournee_id = $1 AND company_id = $2 AND deleted_at IS NULL
+            ORDER BY created_at
+            "#,
+        )
+        .bind(tournee_id)
+        .bind(user.company_id)
+        .fetch_all(&state.pool)
+        .await
+        .map_err(|e| {
+            log::error!("❌ Error cargando direcciones de la tournée {}: {}", tournee_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        addresses.extend(tournee_addresses);
+    }
+
+    addresses.retain(|a| !a.trim().is_empty());
+
+    if addresses.is_empty() {
+        log::warn!("⚠️ No addresses provided for validation job");
+        return Ok((StatusCode::OK, ValidationJobResponse::error("No addresses provided")));
+    }
+
+    if addresses.len() > MAX_ADDRESSES_PER_JOB {
+        log::warn!("⚠️ Too many addresses for validation job: {}", addresses.len());
+        return Ok((
+            StatusCode::OK,
+            ValidationJobResponse::error(format!("Maximum {} addresses allowed per job", MAX_ADDRESSES_PER_JOB)),
+        ));
+    }
+
+    // Las verificaciones de los choferes de la empresa entran en el score
+    let verifications = driver_field_data_service::verification_counts(&state.pool, user.company_id, &addresses)
+        .await
+        .map_err(|e| {
+            log::error!("❌ Error cargando verificaciones de choferes: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let addresses = addresses
+        .into_iter()
+        .map(|address| {
+            let context = AddressContext {
+                driver_verification_count: verifications.get(&normalize_address(&address)).copied(),
+                ..AddressContext::default()
+            };
+            (address, context)
+        })
+        .collect();
+
+    let job = state
+        .address_validation_jobs
+        .spawn(
+            mapbox_token,
+            state.config.address_confidence_weights.clone(),
+            addresses,
+            user.company_id,
+            username,
+            request.tournee_id,
+        )
+        .await;
+
+    log::info!("📋 Job de validación {} creado ({} direcciones)", job.id, job.total);
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(ValidationJobResponse {
+            success: true,
+            job: Some(job.snapshot()),
+            message: Some("Validation job queued".to_string()),
+            error: None,
+        }),
+    ))
+}
+
+/// GET /api/v1/address-validation/jobs/:id - Estado y progreso del job
+pub async fn get_validation_job(
+    Extension(user): Extension<AuthenticatedUser>,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ValidationJobResponse>, StatusCode> {
+    let job = state
+        .address_validation_jobs
+        .get(id, user.company_id)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(ValidationJobResponse {
+        success: true,
+        job: Some(job.snapshot()),
+        message: None,
+        error: None,
+    }))
+}
+
+/// GET /api/v1/address-validation/jobs/:id/results - Descargar resultados (JSON o CSV)
+pub async fn get_validation_job_results(
+    Extension(user): Extension<AuthenticatedUser>,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ValidationResultsQuery>,
+) -> Result<Response, StatusCode> {
+    let job = state
+        .address_validation_jobs
+        .get(id, user.company_id)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let Some(result) = job.result.as_ref() else {
+        // Todavía en curso (o fallido): no hay resultados que descargar
+        return Err(StatusCode::CONFLICT);
+    };
+
+    let only_manual = query.filter.as_deref() == Some("manual");
+    let mut addresses: Vec<_> = if only_manual {
+        result.manual_required()
+    } else {
+        result.validated_addresses.iter().collect()
+    };
+
+    if let Some(max_score) = query.max_score {
+        addresses.retain(|a| a.confidence_score <= max_score);
+    }
+
+    match query.format.as_deref().unwrap_or("json") {
+        "csv" => {
+            let filename = if only_manual {
+                format!("validation_{}_manual.csv", id)
+            } else {
+                format!("validation_{}.csv", id)
+            };
+
+            Ok((
+                [
+                    (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+                    (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+                ],
+                validated_addresses_to_csv(addresses),
+            )
+                .into_response())
+        }
+        "json" => Ok(Json(serde_json::json!({
+            "success": true,
+            "job": job.snapshot(),
+            "validated_addresses": addresses,
+        }))
+        .into_response()),
+        _ => Err(StatusCode::BAD_REQUEST),
+    }
+}
@@ -1,107 +1,111 @@
 //! Handlers de Analytics
-//! 
-//! Este módulo maneja las operaciones para métricas y analytics.
+//!
+//! Este módulo maneja las operaciones para métricas y analytics de la empresa
+//! del usuario autenticado (solo admins).
 
 use axum::{
     extract::{Query, State},
     Json,
 };
+use chrono::{Duration, NaiveDate, Utc};
 use sqlx::PgPool;
-use validator::Validate;
+use uuid::Uuid;
 
 use crate::{
+    api::require_admin,
     models::analytics::{
-        PerformanceAnalytics, DashboardSummary, AnalyticsResponse,
-        CreateAnalyticsRequest, AnalyticsFilters,
+        DashboardSummary, AnalyticsResponse, AnalyticsFilters, DriverWeekPerformance, RecomputeWeeksRequest,
+        WeeklyAggregationSummary, WeeklyPerformanceFilters, DRIVER_WEEK_PERFORMANCE_COLUMNS,
     },
+    services::performance_analytics,
     utils::errors::{AppError, AppResult},
     middleware::auth::AuthenticatedUser,
 };
 
+/// Agrupación de las métricas de rendimiento
+#[derive(Debug, Clone, Copy)]
+enum PerformanceGroup {
+    Tournee,
+    Driver,
+    Vehicle,
+}
+
+impl PerformanceGroup {
+    /// (id, tournee_id, driver_id, vehicle_id, date, GROUP BY)
+    fn columns(self) -> (&'static str, &'static str, &'static str, &'static str, &'static str, &'static str) {
+        match self {
+            PerformanceGroup::Tournee => (
+                "id", "id", "driver_id", "vehicle_id", "tournee_date",
+                "id, company_id, driver_id, vehicle_id, tournee_date",
+            ),
+            PerformanceGroup::Driver => (
+                "driver_id", "NULL::uuid", "driver_id", "NULL::uuid", "$3::date",
+                "driver_id, company_id",
+            ),
+            PerformanceGroup::Vehicle => (
+                "vehicle_id", "NULL::uuid", "NULL::uuid", "vehicle_id", "$3::date",
+                "vehicle_id, company_id",
+            ),
+        }
+    }
+}
+
 /// Obtener resumen del dashboard
 pub async fn get_dashboard_summary(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
     State(state): State<crate::state::AppState>,
 ) -> AppResult<Json<DashboardSummary>> {
-    let today = chrono::Utc::now().date_naive();
-
-    // Obtener resumen de tournées
-    let tournees_summary = sqlx::query!(
-        r#"
-        SELECT 
-            COUNT(*) as total_tournees,
-            COUNT(CASE WHEN tournee_status = 'completed' THEN 1 END) as completed_tournees,
-            COUNT(CASE WHEN tournee_status = 'in_progress' THEN 1 END) as active_tournees
-        FROM tournees 
-        WHERE company_id = $1 
-        AND tournee_date = $2
-        AND deleted_at IS NULL
-        "#,
-        user.company_id,
-        today
-    )
-    .fetch_one(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
+    require_admin(&user)?;
+    let today = Utc::now().date_naive();
 
-    // Obtener resumen de paquetes
-    let packages_summary = sqlx::query!(
+    let (
+        total_tournees,
+        completed_tournees,
+        active_tournees,
+        total_packages,
+        delivered_packages,
+        failed_packages,
+        avg_delivery_time,
+        avg_route_efficiency,
+        total_distance,
+    ) = sqlx::query_as::<_, (i64, i64, i64, i64, i64, i64, Option<f64>, Option<f64>, Option<f64>)>(
         r#"
-        SELECT 
-            COUNT(*) as total_packages,
-            COUNT(CASE WHEN delivery_status = 'delivered' THEN 1 END) as delivered_packages,
-            COUNT(CASE WHEN delivery_status = 'failed' THEN 1 END) as failed_packages
-        FROM packages p
-        JOIN tournees t ON p.tournee_id = t.id
-        WHERE p.company_id = $1 
-        AND t.tournee_date = $2
-        AND p.deleted_at IS NULL
+        WITH today AS (
+            SELECT * FROM tournees
+            WHERE company_id = $1 AND tournee_date = $2 AND deleted_at IS NULL
+        )
+        SELECT
+            (SELECT COUNT(*) FROM today),
+            (SELECT COUNT(*) FROM today WHERE tournee_status = 'completed'),
+            (SELECT COUNT(*) FROM today WHERE tournee_status = 'in_progress'),
+            (SELECT COUNT(*) FROM packages p JOIN today t ON p.tournee_id = t.id
+             WHERE p.deleted_at IS NULL),
+            (SELECT COUNT(*) FROM packages p JOIN today t ON p.tournee_id = t.id
+             WHERE p.deleted_at IS NULL AND p.delivery_status = 'delivered'),
+            (SELECT COUNT(*) FROM packages p JOIN today t ON p.tournee_id = t.id
+             WHERE p.deleted_at IS NULL AND p.delivery_status = 'failed'),
+            (SELECT AVG(actual_duration_minutes)::float8 FROM today WHERE tournee_status = 'completed'),
+            (SELECT AVG(route_optimization_score)::float8 FROM today WHERE tournee_status = 'completed'),
+            (SELECT SUM(total_distance)::float8 FROM today WHERE tournee_status = 'completed')
         "#,
-        user.company_id,
-        today
     )
+    .bind(user.company_id)
+    .bind(today)
     .fetch_one(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
-
-    // Obtener métricas de rendimiento
-    let performance_metrics = sqlx::query!(
-        r#"
-        SELECT 
-            AVG(actual_duration_minutes) as avg_delivery_time,
-            AVG(route_optimization_score) as avg_route_efficiency,
-            SUM(total_distance) as total_distance
-        FROM tournees 
-        WHERE company_id = $1 
-        AND tournee_date = $2
-        AND tournee_status = 'completed'
-        AND deleted_at IS NULL
-        "#,
-        user.company_id,
-        today
-    )
-    .fetch_one(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
+    .await?;
 
     let summary = DashboardSummary {
         company_id: user.company_id,
         date: today,
-        total_tournees: tournees_summary.total_tournees.unwrap_or(0) as i32,
-        completed_tournees: tournees_summary.completed_tournees.unwrap_or(0) as i32,
-        active_tournees: tournees_summary.active_tournees.unwrap_or(0) as i32,
-        total_packages: packages_summary.total_packages.unwrap_or(0) as i32,
-        delivered_packages: packages_summary.delivered_packages.unwrap_or(0) as i32,
-        failed_packages: packages_summary.failed_packages.unwrap_or(0) as i32,
-        average_delivery_time_minutes: performance_metrics.avg_delivery_time
-            .map(|d| d.to_string().parse::<f64>().unwrap_or(0.0))
-            .unwrap_or(0.0),
-        average_route_efficiency: performance_metrics.avg_route_efficiency
-            .map(|d| d.to_string().parse::<f64>().unwrap_or(0.0))
-            .unwrap_or(0.0),
-        total_distance_km: performance_metrics.total_distance
-            .map(|d| d.to_string().parse::<f64>().unwrap_or(0.0))
-            .unwrap_or(0.0),
+        total_tournees: total_tournees as i32,
+        completed_tournees: completed_tournees as i32,
+        active_tournees: active_tournees as i32,
+        total_packages: total_packages as i32,
+        delivered_packages: delivered_packages as i32,
+        failed_packages: failed_packages as i32,
+        average_delivery_time_minutes: avg_delivery_time.unwrap_or(0.0),
+        average_route_efficiency: avg_route_efficiency.unwrap_or(0.0),
+        total_distance_km: total_distance.unwrap_or(0.0),
         total_revenue: None,
         total_costs: None,
         profit_margin: None,
@@ -118,107 +122,8 @@ pub async fn get_performance_by_tournee(
     State(state): State<crate::state::AppState>,
     Query(filters): Query<AnalyticsFilters>,
 ) -> AppResult<Json<Vec<AnalyticsResponse>>> {
-    let date_from = filters.date_from.unwrap_or_else(|| {
-        chrono::Utc::now().date_naive() - chrono::Duration::days(30)
-    });
-    let date_to = filters.date_to.unwrap_or_else(|| chrono::Utc::now().date_naive());
-
-    let rows = sqlx::query!(
-        r#"
-        SELECT 
-            t.id,
-            t.company_id,
-            t.id as tournee_id,
-            t.driver_id,
-            t.vehicle_id,
-            COALESCE(t.actual_duration_minutes, 0) as total_time_minutes,
-            COALESCE(t.actual_duration_minutes, 0) as driving_time_minutes,
-            0 as waiting_time_minutes,
-            COALESCE(t.total_distance, 0) as total_distance_km,
-            COALESCE(t.route_optimization_score, 0) as route_efficiency,
-            COUNT(p.id) FILTER (WHERE p.delivery_status = 'delivered') as packages_delivered,
-            COUNT(p.id) FILTER (WHERE p.delivery_status = 'failed') as packages_failed,
-            CASE 
-                WHEN COUNT(p.id) > 0 THEN 
-                    (COUNT(p.id) FILTER (WHERE p.delivery_status = 'delivered')::float / COUNT(p.id)::float) * 100
-                ELSE 0 
-            END as delivery_success_rate,
-            COALESCE(t.fuel_consumed, 0) as fuel_consumed_liters,
-            CASE 
-                WHEN t.fuel_consumed > 0 AND t.total_distance > 0 THEN 
-                    t.total_distance / t.fuel_consumed
-                ELSE 0 
-            END as fuel_efficiency_km_l,
-            COALESCE(t.fuel_cost, 0) as total_cost,
-            CASE 
-                WHEN COUNT(p.id) > 0 THEN 
-                    COALESCE(t.fuel_cost, 0) / COUNT(p.id)
-                ELSE 0 
-            END as cost_per_package,
-            CASE 
-                WHEN t.total_distance > 0 THEN 
-                    COALESCE(t.fuel_cost, 0) / t.total_distance
-                ELSE 0 
-            END as cost_per_km,
-            NULL::float as customer_rating,
-            0 as complaints_count,
-            t.tournee_date as date,
-            t.created_at,
-            t.updated_at
-        FROM tournees t
-        LEFT JOIN packages p ON t.id = p.tournee_id AND p.deleted_at IS NULL
-        WHERE t.company_id = $1
-        AND t.tournee_date BETWEEN $2 AND $3
-        AND t.tournee_status = 'completed'
-        AND t.deleted_at IS NULL
-        GROUP BY t.id, t.company_id, t.driver_id, t.vehicle_id, t.tournee_date,
-                 t.actual_duration_minutes, t.total_distance, t.route_optimization_score,
-                 t.fuel_consumed, t.fuel_cost, t.created_at, t.updated_at
-        ORDER BY t.tournee_date DESC, t.created_at DESC
-        "#,
-        user.company_id,
-        date_from,
-        date_to
-    )
-    .fetch_all(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let analytics: Vec<AnalyticsResponse> = rows
-        .into_iter()
-        .map(|row| AnalyticsResponse {
-            id: row.id,
-            company_id: row.company_id,
-            tournee_id: Some(row.tournee_id),
-            driver_id: Some(row.driver_id),
-            vehicle_id: Some(row.vehicle_id),
-            total_time_minutes: row.total_time_minutes.unwrap_or(0) as i32,
-            driving_time_minutes: row.driving_time_minutes.unwrap_or(0) as i32,
-            waiting_time_minutes: row.waiting_time_minutes.expect("waiting_time_minutes should not be null"),
-            total_distance_km: row.total_distance_km
-                .map(|d| d.to_string().parse::<f64>().unwrap_or(0.0))
-                .unwrap_or(0.0),
-            route_efficiency: row.route_efficiency
-                .map(|e| e.to_string().parse::<f64>().unwrap_or(0.0))
-                .unwrap_or(0.0),
-            packages_delivered: row.packages_delivered.unwrap_or(0) as i32,
-            packages_failed: row.packages_failed.unwrap_or(0) as i32,
-            delivery_success_rate: row.delivery_success_rate.unwrap_or(0.0),
-            fuel_consumed_liters: row.fuel_consumed_liters
-                .map(|f| f.to_string().parse::<f64>().unwrap_or(0.0)),
-            fuel_efficiency_km_l: row.fuel_efficiency_km_l.map(|d| d.to_string().parse::<f64>().unwrap_or(0.0)),
-            total_cost: row.total_cost,
-            cost_per_package: row.cost_per_package,
-            cost_per_km: row.cost_per_km,
-            customer_rating: row.customer_rating,
-            complaints_count: row.complaints_count.expect("complaints_count should not be null"),
-            date: row.date,
-            created_at: row.created_at.unwrap_or_else(|| chrono::Utc::now()),
-            updated_at: row.updated_at.unwrap_or_else(|| chrono::Utc::now()),
-        })
-        .collect();
-
-    Ok(Json(analytics))
+    require_admin(&user)?;
+    Ok(Json(fetch_performance(&state.pool, user.company_id, &filters, PerformanceGroup::Tournee).await?))
 }
 
 /// Obtener métricas agregadas por conductor
@@ -227,105 +132,8 @@ pub async fn get_driver_performance(
     State(state): State<crate::state::AppState>,
     Query(filters): Query<AnalyticsFilters>,
 ) -> AppResult<Json<Vec<AnalyticsResponse>>> {
-    let date_from = filters.date_from.unwrap_or_else(|| {
-        chrono::Utc::now().date_naive() - chrono::Duration::days(30)
-    });
-    let date_to = filters.date_to.unwrap_or_else(|| chrono::Utc::now().date_naive());
-
-    let rows = sqlx::query!(
-        r#"
-        SELECT 
-            t.driver_id as id,
-            t.company_id,
-            NULL::uuid as tournee_id,
-            t.driver_id,
-            NULL::uuid as vehicle_id,
-            SUM(COALESCE(t.actual_duration_minutes, 0)) as total_time_minutes,
-            SUM(COALESCE(t.actual_duration_minutes, 0)) as driving_time_minutes,
-            0 as waiting_time_minutes,
-            SUM(COALESCE(t.total_distance, 0)) as total_distance_km,
-            AVG(COALESCE(t.route_optimization_score, 0)) as route_efficiency,
-            COUNT(p.id) FILTER (WHERE p.delivery_status = 'delivered') as packages_delivered,
-            COUNT(p.id) FILTER (WHERE p.delivery_status = 'failed') as packages_failed,
-            CASE 
-                WHEN COUNT(p.id) > 0 THEN 
-                    (COUNT(p.id) FILTER (WHERE p.delivery_status = 'delivered')::float / COUNT(p.id)::float) * 100
-                ELSE 0 
-            END as delivery_success_rate,
-            SUM(COALESCE(t.fuel_consumed, 0)) as fuel_consumed_liters,
-            CASE 
-                WHEN SUM(t.fuel_consumed) > 0 AND SUM(t.total_distance) > 0 THEN 
-                    SUM(t.total_distance) / SUM(t.fuel_consumed)
-                ELSE 0 
-            END as fuel_efficiency_km_l,
-            SUM(COALESCE(t.fuel_cost, 0)) as total_cost,
-            CASE 
-                WHEN COUNT(p.id) > 0 THEN 
-                    SUM(COALESCE(t.fuel_cost, 0)) / COUNT(p.id)
-                ELSE 0 
-            END as cost_per_package,
-            CASE 
-                WHEN SUM(t.total_distance) > 0 THEN 
-                    SUM(COALESCE(t.fuel_cost, 0)) / SUM(t.total_distance)
-                ELSE 0 
-            END as cost_per_km,
-            NULL::float as customer_rating,
-            0 as complaints_count,
-            $3::date as date,
-            MIN(t.created_at) as created_at,
-            MAX(t.updated_at) as updated_at
-        FROM tournees t
-        LEFT JOIN packages p ON t.id = p.tournee_id AND p.deleted_at IS NULL
-        WHERE t.company_id = $1
-        AND t.tournee_date BETWEEN $2 AND $3
-        AND t.tournee_status = 'completed'
-        AND t.deleted_at IS NULL
-        GROUP BY t.driver_id, t.company_id
-        ORDER BY packages_delivered DESC
-        "#,
-        user.company_id,
-        date_from,
-        date_to
-    )
-    .fetch_all(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let analytics: Vec<AnalyticsResponse> = rows
-        .into_iter()
-        .map(|row| AnalyticsResponse {
-            id: row.id,
-            company_id: row.company_id,
-            tournee_id: row.tournee_id,
-            driver_id: Some(row.driver_id),
-            vehicle_id: row.vehicle_id,
-            total_time_minutes: row.total_time_minutes.unwrap_or(0) as i32,
-            driving_time_minutes: row.driving_time_minutes.unwrap_or(0) as i32,
-            waiting_time_minutes: row.waiting_time_minutes.expect("waiting_time_minutes should not be null"),
-            total_distance_km: row.total_distance_km
-                .map(|d| d.to_string().parse::<f64>().unwrap_or(0.0))
-                .unwrap_or(0.0),
-            route_efficiency: row.route_efficiency
-                .map(|e| e.to_string().parse::<f64>().unwrap_or(0.0))
-                .unwrap_or(0.0),
-            packages_delivered: row.packages_delivered.unwrap_or(0) as i32,
-            packages_failed: row.packages_failed.unwrap_or(0) as i32,
-            delivery_success_rate: row.delivery_success_rate.unwrap_or(0.0),
-            fuel_consumed_liters: row.fuel_consumed_liters
-                .map(|f| f.to_string().parse::<f64>().unwrap_or(0.0)),
-            fuel_efficiency_km_l: row.fuel_efficiency_km_l.map(|d| d.to_string().parse::<f64>().unwrap_or(0.0)),
-            total_cost: row.total_cost,
-            cost_per_package: row.cost_per_package,
-            cost_per_km: row.cost_per_km,
-            customer_rating: row.customer_rating,
-            complaints_count: row.complaints_count.expect("complaints_count should not be null"),
-            date: row.date.expect("date should not be null"),
-            created_at: row.created_at.unwrap_or_else(|| chrono::Utc::now()),
-            updated_at: row.updated_at.unwrap_or_else(|| chrono::Utc::now()),
-        })
-        .collect();
-
-    Ok(Json(analytics))
+    require_admin(&user)?;
+    Ok(Json(fetch_performance(&state.pool, user.company_id, &filters, PerformanceGroup::Driver).await?))
 }
 
 /// Obtener métricas agregadas por vehículo
@@ -334,103 +142,157 @@ pub async fn get_vehicle_performance(
     State(state): State<crate::state::AppState>,
     Query(filters): Query<AnalyticsFilters>,
 ) -> AppResult<Json<Vec<AnalyticsResponse>>> {
-    let date_from = filters.date_from.unwrap_or_else(|| {
-        chrono::Utc::now().date_naive() - chrono::Duration::days(30)
-    });
-    let date_to = filters.date_to.unwrap_or_else(|| chrono::Utc::now().date_naive());
+    require_admin(&user)?;
+    Ok(Json(fetch_performance(&state.pool, user.company_id, &filters, PerformanceGroup::Vehicle).await?))
+}
 
-    let rows = sqlx::query!(
+/// Rendimiento semanal por chofer (12 semanas por defecto)
+pub async fn get_weekly_performance(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Query(filters): Query<WeeklyPerformanceFilters>,
+) -> AppResult<Json<Vec<DriverWeekPerformance>>> {
+    require_admin(&user)?;
+
+    let to = filters.to.unwrap_or_else(|| Utc::now().date_naive());
+    let from = filters.from.unwrap_or(to - Duration::weeks(12));
+
+    let weeks = sqlx::query_as::<_, DriverWeekPerformance>(&format!(
         r#"
-        SELECT 
-            t.vehicle_id as id,
-            t.company_id,
-            NULL::uuid as tournee_id,
-            NULL::uuid as driver_id,
-            t.vehicle_id,
-            SUM(COALESCE(t.actual_duration_minutes, 0)) as total_time_minutes,
-            SUM(COALESCE(t.actual_duration_minutes, 0)) as driving_time_minutes,
-            0 as waiting_time_minutes,
-            SUM(COALESCE(t.total_distance, 0)) as total_distance_km,
-            AVG(COALESCE(t.route_optimization_score, 0)) as route_efficiency,
-            COUNT(p.id) FILTER (WHERE p.delivery_status = 'delivered') as packages_delivered,
-            COUNT(p.id) FILTER (WHERE p.delivery_status = 'failed') as packages_failed,
-            CASE 
-                WHEN COUNT(p.id) > 0 THEN 
-                    (COUNT(p.id) FILTER (WHERE p.delivery_status = 'delivered')::float / COUNT(p.id)::float) * 100
-                ELSE 0 
-            END as delivery_success_rate,
-            SUM(COALESCE(t.fuel_consumed, 0)) as fuel_consumed_liters,
-            CASE 
-                WHEN SUM(t.fuel_consumed) > 0 AND SUM(t.total_distance) > 0 THEN 
-                    SUM(t.total_distance) / SUM(t.fuel_consumed)
-                ELSE 0 
-            END as fuel_efficiency_km_l,
-            SUM(COALESCE(t.fuel_cost, 0)) as total_cost,
-            CASE 
-                WHEN COUNT(p.id) > 0 THEN 
-                    SUM(COALESCE(t.fuel_cost, 0)) / COUNT(p.id)
-                ELSE 0 
-            END as cost_per_package,
-            CASE 
-                WHEN SUM(t.total_distance) > 0 THEN 
-                    SUM(COALESCE(t.fuel_cost, 0)) / SUM(t.total_distance)
-                ELSE 0 
-            END as cost_per_km,
-            NULL::float as customer_rating,
-            0 as complaints_count,
-            $3::date as date,
-            MIN(t.created_at) as created_at,
-            MAX(t.updated_at) as updated_at
-        FROM tournees t
-        LEFT JOIN packages p ON t.id = p.tournee_id AND p.deleted_at IS NULL
-        WHERE t.company_id = $1
-        AND t.tournee_date BETWEEN $2 AND $3
-        AND t.tournee_status = 'completed'
-        AND t.deleted_at IS NULL
-        GROUP BY t.vehicle_id, t.company_id
-        ORDER BY packages_delivered DESC
+        SELECT {}
+        FROM performance_analytics
+        WHERE company_id = $1
+        AND ($2::uuid IS NULL OR driver_id = $2)
+        AND week_start_date BETWEEN $3 AND $4
+        ORDER BY week_start_date DESC, driver_id
         "#,
-        user.company_id,
-        date_from,
-        date_to
-    )
+        DRIVER_WEEK_PERFORMANCE_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(filters.driver_id)
+    .bind(performance_analytics::week_start(from))
+    .bind(to)
     .fetch_all(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
+    .await?;
 
-    let analytics: Vec<AnalyticsResponse> = rows
-        .into_iter()
-        .map(|row| AnalyticsResponse {
-            id: row.id,
-            company_id: row.company_id,
-            tournee_id: row.tournee_id,
-            driver_id: row.driver_id,
-            vehicle_id: Some(row.vehicle_id),
-            total_time_minutes: row.total_time_minutes.unwrap_or(0) as i32,
-            driving_time_minutes: row.driving_time_minutes.unwrap_or(0) as i32,
-            waiting_time_minutes: row.waiting_time_minutes.expect("waiting_time_minutes should not be null"),
-            total_distance_km: row.total_distance_km
-                .map(|d| d.to_string().parse::<f64>().unwrap_or(0.0))
-                .unwrap_or(0.0),
-            route_efficiency: row.route_efficiency
-                .map(|e| e.to_string().parse::<f64>().unwrap_or(0.0))
-                .unwrap_or(0.0),
-            packages_delivered: row.packages_delivered.unwrap_or(0) as i32,
-            packages_failed: row.packages_failed.unwrap_or(0) as i32,
-            delivery_success_rate: row.delivery_success_rate.unwrap_or(0.0),
-            fuel_consumed_liters: row.fuel_consumed_liters
-                .map(|f| f.to_string().parse::<f64>().unwrap_or(0.0)),
-            fuel_efficiency_km_l: row.fuel_efficiency_km_l.map(|d| d.to_string().parse::<f64>().unwrap_or(0.0)),
-            total_cost: row.total_cost,
-            cost_per_package: row.cost_per_package,
-            cost_per_km: row.cost_per_km,
-            customer_rating: row.customer_rating,
-            complaints_count: row.complaints_count.expect("complaints_count should not be null"),
-            date: row.date.expect("date should not be null"),
-            created_at: row.created_at.unwrap_or_else(|| chrono::Utc::now()),
-            updated_at: row.updated_at.unwrap_or_else(|| chrono::Utc::now()),
-        })
-        .collect();
+    Ok(Json(weeks))
+}
 
-    Ok(Json(analytics))
-}
\ No newline at end of file
+/// Recalcular semanas pasadas tras corregir tournées, paquetes o daños
+pub async fn recompute_weekly_performance(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Json(request): Json<RecomputeWeeksRequest>,
+) -> AppResult<Json<WeeklyAggregationSummary>> {
+    require_admin(&user)?;
+
+    let summary =
+        performance_analytics::recompute(
+            &state.pool,
+            user.company_id,
+            request.from,
+            request.to,
+            request.driver_id,
+            &state.config.analytics,
+        )
+        .await?;
+    Ok(Json(summary))
+}
+
+/// Métricas de las tournées completadas en el rango (30 días por defecto)
+///
+/// Los paquetes se agregan primero por tournée para que las distancias y
+/// costes no se multipliquen por el número de paquetes.
+async fn fetch_performance(
+    pool: &PgPool,
+    company_id: Uuid,
+    filters: &AnalyticsFilters,
+    group: PerformanceGroup,
+) -> AppResult<Vec<AnalyticsResponse>> {
+    let date_to = filters.date_to.unwrap_or_else(|| Utc::now().date_naive());
+    let date_from: NaiveDate = filters.date_from.unwrap_or(date_to - Duration::days(30));
+    if date_from > date_to {
+        return Err(AppError::BadRequest("date_from debe ser anterior a date_to".to_string()));
+    }
+
+    let (id, tournee_id, driver_id, vehicle_id, date, group_by) = group.columns();
+    let sql = format!(
+        r#"
+        WITH per_tournee AS (
+            SELECT
+                t.id, t.company_id, t.driver_id, t.vehicle_id, t.tournee_date,
+                t.actual_duration_minutes, t.total_distance, t.route_optimization_score,
+                t.fuel_consumed, t.fuel_cost, t.created_at, t.updated_at,
+                COALESCE(p.delivered, 0) AS delivered,
+                COALESCE(p.failed, 0) AS failed,
+                COALESCE(p.total, 0) AS total_packages
+            FROM tournees t
+            LEFT JOIN (
+                SELECT
+                    tournee_id,
+                    COUNT(*) FILTER (WHERE delivery_status = 'delivered') AS delivered,
+                    COUNT(*) FILTER (WHERE delivery_status = 'failed') AS failed,
+                    COUNT(*) AS total
+                FROM packages
+                WHERE deleted_at IS NULL
+                GROUP BY tournee_id
+            ) p ON p.tournee_id = t.id
+            WHERE t.company_id = $1
+            AND t.tournee_date BETWEEN $2 AND $3
+            AND t.tournee_status = 'completed'
+            AND t.deleted_at IS NULL
+            AND ($4::uuid IS NULL OR t.id = $4)
+            AND ($5::uuid IS NULL OR t.driver_id = $5)
+            AND ($6::uuid IS NULL OR t.vehicle_id = $6)
+        )
+        SELECT
+            {id} AS id,
+            company_id,
+            {tournee_id} AS tournee_id,
+            {driver_id} AS driver_id,
+            {vehicle_id} AS vehicle_id,
+            SUM(COALESCE(actual_duration_minutes, 0))::int AS total_time_minutes,
+            SUM(COALESCE(actual_duration_minutes, 0))::int AS driving_time_minutes,
+            0 AS waiting_time_minutes,
+            COALESCE(SUM(total_distance), 0)::float8 AS total_distance_km,
+            COALESCE(AVG(route_optimization_score), 0)::float8 AS route_efficiency,
+            SUM(delivered)::int AS packages_delivered,
+            SUM(failed)::int AS packages_failed,
+            CASE WHEN SUM(total_packages) > 0
+                THEN SUM(delivered)::float8 / SUM(total_packages)::float8 * 100
+                ELSE 0
+            END::float8 AS delivery_success_rate,
+            SUM(fuel_consumed)::float8 AS fuel_consumed_liters,
+            CASE WHEN SUM(fuel_consumed) > 0
+                THEN (SUM(total_distance) / SUM(fuel_consumed))::float8
+            END AS fuel_efficiency_km_l,
+            SUM(fuel_cost) AS total_cost,
+            CASE WHEN SUM(total_packages) > 0 THEN SUM(fuel_cost) / SUM(total_packages) END AS cost_per_package,
+            CASE WHEN SUM(total_distance) > 0 THEN SUM(fuel_cost) / SUM(total_distance) END AS cost_per_km,
+            NULL::float8 AS customer_rating,
+            0 AS complaints_count,
+            {date} AS date,
+            COALESCE(MIN(created_at), NOW()) AS created_at,
+            COALESCE(MAX(updated_at), NOW()) AS updated_at
+        FROM per_tournee
+        GROUP BY {group_by}
+        HAVING ($7::float8 IS NULL OR COALESCE(AVG(route_optimization_score), 0) >= $7)
+        AND ($8::float8 IS NULL OR COALESCE(AVG(route_optimization_score), 0) <= $8)
+        ORDER BY packages_delivered DESC, id
+        "#
+    );
+
+    let rows = sqlx::query_as::<_, AnalyticsResponse>(&sql)
+        .bind(company_id)
+        .bind(date_from)
+        .bind(date_to)
+        .bind(filters.tournee_id)
+        .bind(filters.driver_id)
+        .bind(filters.vehicle_id)
+        .bind(filters.min_efficiency)
+        .bind(filters.max_efficiency)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows)
+}
@@ -0,0 +1,95 @@
+//! Handlers del planificador de asignación de vehículos
+//!
+//! El despachador pide la propuesta del día, la revisa y la acepta tal cual o
+//! cambiando vehículos (ver `services::assignment_planner`). Solo admins.
+
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::{
+    api::require_admin,
+    models::assignment::{
+        AcceptAssignmentsRequest, AssignmentFilters, AssignmentProposal, VehicleAssignment,
+        VEHICLE_ASSIGNMENT_COLUMNS,
+    },
+    services::assignment_planner,
+    utils::errors::{AppError, AppResult},
+    middleware::auth::AuthenticatedUser,
+};
+
+/// Día de la propuesta (`?date=`, por defecto mañana)
+#[derive(Debug, Deserialize)]
+pub struct ProposalQuery {
+    pub date: Option<NaiveDate>,
+}
+
+/// Propuesta de asignación para un día
+pub async fn get_proposal(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Query(query): Query<ProposalQuery>,
+) -> AppResult<Json<AssignmentProposal>> {
+    require_admin(&user)?;
+
+    let date = query
+        .date
+        .unwrap_or_else(|| chrono::Utc::now().date_naive() + chrono::Duration::days(1));
+    let mut conn = state.pool.acquire().await?;
+    let proposal = assignment_planner::proposal(&mut conn, user.company_id, date).await?;
+
+    Ok(Json(proposal))
+}
+
+/// Aceptar la propuesta (o cambiar vehículos indicando el motivo)
+pub async fn accept_assignments(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Json(request): Json<AcceptAssignmentsRequest>,
+) -> AppResult<Json<Vec<VehicleAssignment>>> {
+    require_admin(&user)?;
+    request.validate()
+        .map_err(AppError::Validation)?;
+
+    let mut tx = state.pool.begin().await?;
+    let accepted = assignment_planner::accept(&mut tx, &user, &request).await?;
+    tx.commit().await?;
+
+    let overridden = accepted.iter().filter(|assignment| assignment.overridden).count();
+    log::info!(
+        "🚐 {} asignaciones aceptadas para el {} ({} cambiadas por el despachador)",
+        accepted.len(),
+        request.tournee_date,
+        overridden
+    );
+    Ok(Json(accepted))
+}
+
+/// Asignaciones aceptadas de un día (`?date=`)
+pub async fn get_assignments(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Query(filters): Query<AssignmentFilters>,
+) -> AppResult<Json<Vec<VehicleAssignment>>> {
+    require_admin(&user)?;
+
+    let assignments = sqlx::query_as::<_, VehicleAssignment>(&format!(
+        r#"
+        SELECT {}
+        FROM vehicle_assignments
+        WHERE company_id = $1 AND assignment_date = $2
+        ORDER BY accepted_at
+        "#,
+        VEHICLE_ASSIGNMENT_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(filters.date)
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(assignments))
+}
@@ -5,20 +5,21 @@
 use axum::{
     extract::{Extension, State},
     http::StatusCode,
-    Json,
+    middleware::from_fn_with_state,
+    routing::{get, post},
+    Json, Router,
 };
 use bcrypt::{hash, verify, DEFAULT_COST};
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
-    config::EnvironmentConfig,
-    models::user::{User, UserType, UserStatus, CreateUserRequest, UserResponse},
+    models::auth::RegisterRequest,
+    models::user::{User, UserStatus, UserResponse, USER_COLUMNS},
     utils::errors::{AppError, AppResult},
     utils::jwt::{generate_token, JwtConfig},
-    middleware::auth::AuthenticatedUser,
+    middleware::auth::{auth_middleware, AuthenticatedUser},
     state::AppState,
 };
 
@@ -30,15 +31,9 @@ pub struct LoginRequest {
     
     #[validate(length(min = 6, max = 100))]
     pub password: String,
-}
-
-/// Response de login exitoso
-#[derive(Debug, Serialize)]
-pub struct LoginResponse {
-    pub access_token: String,
-    pub token_type: String,
-    pub expires_in: u64,
-    pub user: UserResponse,
+    
+    /// Empresa del usuario (necesaria si el username existe en varias)
+    pub company_id: Option<Uuid>,
 }
 
 /// Response de login optimizado para Android con token en múltiples ubicaciones
@@ -84,21 +79,6 @@ pub struct RefreshTokenResponse {
     pub expires_in: u64,
 }
 
-/// Request de registro
-#[derive(Debug, Deserialize, Validate)]
-pub struct RegisterRequest {
-    #[validate(length(min = 3, max = 50))]
-    pub username: String,
-    
-    #[validate(email)]
-    pub email: String,
-    
-    #[validate(length(min = 6, max = 100))]
-    pub password: String,
-    
-    pub user_type: UserType,
-}
-
 /// Handler de login
 pub async fn login(
     State(app_state): State<AppState>,
@@ -110,193 +90,150 @@ pub async fn login(
     login_data.validate()
         .map_err(AppError::Validation)?;
 
-    // Buscar usuario por username
-    let row = sqlx::query!(
+    // Buscar usuario por username (único por empresa)
+    let candidates = sqlx::query_as::<_, User>(&format!(
         r#"
-        SELECT 
-            id, company_id, user_type as "user_type: String", user_status as "user_status: String", username, 
-            password_hash, created_at, updated_at, deleted_at
-        FROM users 
-        WHERE username = $1 
+        SELECT {}
+        FROM users
+        WHERE username = $1
+        AND ($2::uuid IS NULL OR company_id = $2)
         AND deleted_at IS NULL
         "#,
-        login_data.username
-    )
-    .fetch_optional(pool)
-    .await
-    .map_err(|e| AppError::Database(e))?
-    .ok_or_else(|| AppError::Unauthorized("Credenciales inválidas".to_string()))?;
-
-    let user = User {
-        id: row.id,
-        company_id: row.company_id,
-        user_type: match row.user_type.as_str() {
-            "admin" => UserType::Admin,
-            "driver" => UserType::Driver,
-            _ => return Err(AppError::Database(sqlx::Error::Decode("Invalid user_type".into()))),
-        },
-        user_status: match row.user_status.as_str() {
-            "active" => UserStatus::Active,
-            "inactive" => UserStatus::Inactive,
-            "suspended" => UserStatus::Suspended,
-            _ => return Err(AppError::Database(sqlx::Error::Decode("Invalid user_status".into()))),
-        },
-        username: row.username,
-        password_hash: row.password_hash,
-        created_at: row.created_at.expect("created_at should not be null"),
-        updated_at: row.updated_at.expect("updated_at should not be null"),
-        deleted_at: row.deleted_at,
-    };
+        USER_COLUMNS
+    ))
+    .bind(&login_data.username)
+    .bind(login_data.company_id)
+    .fetch_all(pool)
+    .await?;
+
+    // Verificar password antes de revelar nada sobre la cuenta
+    let mut matching = Vec::new();
+    for candidate in candidates {
+        let password_valid = verify(&login_data.password, &candidate.password_hash)
+            .map_err(|e| AppError::Hash(format!("Error verificando password: {}", e)))?;
+        if password_valid {
+            matching.push(candidate);
+        }
+    }
+
+    if matching.len() > 1 {
+        return Err(AppError::BadRequest(
+            "El usuario existe en varias empresas: indica company_id".to_string(),
+        ));
+    }
+    let user = matching
+        .pop()
+        .ok_or_else(|| AppError::Unauthorized("Credenciales inválidas".to_string()))?;
 
     // Verificar que el usuario esté activo
     if user.user_status != UserStatus::Active {
         return Err(AppError::Unauthorized("Usuario inactivo o suspendido".to_string()));
     }
 
-    // Verificar password
-    let password_valid = verify(&login_data.password, &user.password_hash)
-        .map_err(|e| AppError::Hash(format!("Error verificando password: {}", e)))?;
-
-    if !password_valid {
-        return Err(AppError::Unauthorized("Credenciales inválidas".to_string()));
-    }
+    sqlx::query("UPDATE users SET last_login = NOW() WHERE id = $1")
+        .bind(user.id)
+        .execute(pool)
+        .await?;
 
     // Generar JWT token
     let jwt_config = JwtConfig::from(config);
     let access_token = generate_token(user.id, user.company_id, user.user_type.clone(), &jwt_config)?;
 
-    // Convertir a response
-    let user_response = UserResponse::from(user);
-
-    // Respuesta MÁS COMPATIBLE para Android
-    let response = LoginResponseFlexible {
-        success: true,
-        status: "200".to_string(),
-        code: "200".to_string(),
-        token: access_token.clone(),      // TOKEN EN RAÍZ
-        message: "Login exitoso".to_string(),  // MESSAGE EN RAÍZ
-        authentication: Some(AuthInfo {
-            token: access_token.clone(),   // TOKEN TAMBIÉN EN AUTH
-            matricule: user_response.username.clone(),
-            message: "Autenticación exitosa".to_string(),
-        }),
-        credentials_used: Some(CredentialsUsed {
-            username: login_data.username.clone(),
-            timestamp: chrono::Utc::now().to_rfc3339(),
-        }),
-        timestamp: chrono::Utc::now().to_rfc3339(),
-    };
-
-    Ok(Json(response))
+    Ok(Json(flexible_response(
+        access_token,
+        UserResponse::from(user),
+        "Login exitoso",
+        "Autenticación exitosa",
+    )))
 }
 
-/// Handler de registro
+/// Handler de registro: crea la empresa y su primer admin
 pub async fn register(
     State(app_state): State<AppState>,
     Json(register_data): Json<RegisterRequest>,
-) -> AppResult<Json<LoginResponseFlexible>> {
+) -> AppResult<(StatusCode, Json<LoginResponseFlexible>)> {
     let pool = &app_state.pool;
     let config = &app_state.config;
     // Validar datos de entrada
     register_data.validate()
         .map_err(AppError::Validation)?;
 
-    // Verificar que el username no exista
-    let existing_user = sqlx::query!(
-        "SELECT id FROM users WHERE username = $1 AND deleted_at IS NULL",
-        register_data.username
-    )
-    .fetch_optional(pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
-
-    if existing_user.is_some() {
-        return Err(AppError::Conflict("Username ya existe".to_string()));
-    }
-
-    // Verificar que el email no exista
-    let existing_email = sqlx::query!(
-        "SELECT id FROM users WHERE email = $1 AND deleted_at IS NULL",
-        register_data.email
-    )
-    .fetch_optional(pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
-
-    if existing_email.is_some() {
-        return Err(AppError::Conflict("Email ya existe".to_string()));
-    }
-
     // Hash del password
-    let password_hash = hash(&register_data.password, DEFAULT_COST)
+    let password_hash = hash(&register_data.admin_password, DEFAULT_COST)
         .map_err(|e| AppError::Hash(format!("Error hasheando password: {}", e)))?;
 
-    // Crear usuario (asumiendo que se crea en una empresa por defecto)
-    // En producción, esto debería ser manejado por un admin o proceso de onboarding
-    let company_id = Uuid::new_v4(); // Placeholder - en producción esto vendría del contexto
+    let mut tx = pool.begin().await?;
+
+    let company_id = sqlx::query_scalar::<_, Uuid>(
+        "INSERT INTO companies (name, address) VALUES ($1, $2) RETURNING id",
+    )
+    .bind(&register_data.company_name)
+    .bind(&register_data.company_address)
+    .fetch_one(&mut *tx)
+    .await?;
 
-    let row = sqlx::query!(
+    let new_user = sqlx::query_as::<_, User>(&format!(
         r#"
         INSERT INTO users (
-            company_id, user_type, user_status, username, 
-            password_hash, created_at, updated_at
-        ) VALUES ($1, ($2::text)::user_type, ($3::text)::user_status, $4, $5, NOW(), NOW())
-        RETURNING 
-            id, company_id, user_type as "user_type: crate::models::user::UserType", user_status as "user_status: crate::models::user::UserStatus", username, 
-            password_hash, created_at, updated_at, deleted_at
+            company_id, user_type, user_status, username, password_hash,
+            full_name, email, phone
+        ) VALUES ($1, 'admin', 'active', $2, $3, $4, $5, $6)
+        RETURNING {}
         "#,
-        company_id,
-        match register_data.user_type {
-            UserType::Admin => "admin",
-            UserType::Driver => "driver",
-        },
-        "active",
-        register_data.username,
-        password_hash
-    )
-    .fetch_one(pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let new_user = User {
-        id: row.id,
-        company_id: row.company_id,
-        user_type: row.user_type,
-        user_status: row.user_status,
-        username: row.username,
-        password_hash: row.password_hash,
-        created_at: row.created_at.expect("created_at should not be null"),
-        updated_at: row.updated_at.expect("updated_at should not be null"),
-        deleted_at: row.deleted_at,
-    };
+        USER_COLUMNS
+    ))
+    .bind(company_id)
+    .bind(&register_data.admin_username)
+    .bind(password_hash)
+    .bind(&register_data.admin_full_name)
+    .bind(&register_data.admin_email)
+    .bind(&register_data.admin_phone)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    log::info!("🏢 Empresa '{}' registrada con admin '{}'", register_data.company_name, new_user.username);
 
     // Generar JWT token
     let jwt_config = JwtConfig::from(config);
     let access_token = generate_token(new_user.id, new_user.company_id, new_user.user_type.clone(), &jwt_config)?;
 
-    // Convertir a response
-    let user_response = UserResponse::from(new_user);
+    Ok((
+        StatusCode::CREATED,
+        Json(flexible_response(
+            access_token,
+            UserResponse::from(new_user),
+            "Registro exitoso",
+            "Usuario registrado exitosamente",
+        )),
+    ))
+}
 
-    // Respuesta MÁS COMPATIBLE para Android
-    let response = LoginResponseFlexible {
+/// Respuesta MÁS COMPATIBLE para Android (token en raíz y en `authentication`)
+fn flexible_response(
+    access_token: String,
+    user: UserResponse,
+    message: &str,
+    auth_message: &str,
+) -> LoginResponseFlexible {
+    let now = chrono::Utc::now().to_rfc3339();
+    LoginResponseFlexible {
         success: true,
         status: "200".to_string(),
         code: "200".to_string(),
-        token: access_token.clone(),      // TOKEN EN RAÍZ
-        message: "Registro exitoso".to_string(),  // MESSAGE EN RAÍZ
+        token: access_token.clone(),
+        message: message.to_string(),
         authentication: Some(AuthInfo {
-            token: access_token.clone(),   // TOKEN TAMBIÉN EN AUTH
-            matricule: user_response.username.clone(),
-            message: "Usuario registrado exitosamente".to_string(),
+            token: access_token,
+            matricule: user.username.clone(),
+            message: auth_message.to_string(),
         }),
         credentials_used: Some(CredentialsUsed {
-            username: register_data.username.clone(),
-            timestamp: chrono::Utc::now().to_rfc3339(),
+            username: user.username,
+            timestamp: now.clone(),
         }),
-        timestamp: chrono::Utc::now().to_rfc3339(),
-    };
-
-    Ok(Json(response))
+        timestamp: now,
+    }
 }
 
 /// Handler para obtener información del usuario autenticado
@@ -304,37 +241,18 @@ pub async fn me(
     Extension(user): Extension<AuthenticatedUser>,
     State(app_state): State<AppState>,
 ) -> AppResult<Json<UserResponse>> {
-    let pool = &app_state.pool;
     // Buscar usuario completo
-    let row = sqlx::query!(
-        r#"
-        SELECT 
-            id, company_id, user_type as "user_type: crate::models::user::UserType", user_status as "user_status: crate::models::user::UserStatus", username, 
-            password_hash, created_at, updated_at, deleted_at
-        FROM users 
-        WHERE id = $1 
-        AND deleted_at IS NULL
-        "#,
-        user.user_id
-    )
-    .fetch_one(pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let user_data = User {
-        id: row.id,
-        company_id: row.company_id,
-        user_type: row.user_type,
-        user_status: row.user_status,
-        username: row.username,
-        password_hash: row.password_hash,
-        created_at: row.created_at.expect("created_at should not be null"),
-        updated_at: row.updated_at.expect("updated_at should not be null"),
-        deleted_at: row.deleted_at,
-    };
-
-    let user_response = UserResponse::from(user_data);
-    Ok(Json(user_response))
+    let user_data = sqlx::query_as::<_, User>(&format!(
+        "SELECT {} FROM users WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL",
+        USER_COLUMNS
+    ))
+    .bind(user.user_id)
+    .bind(user.company_id)
+    .fetch_optional(&app_state.pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Usuario no encontrado".to_string()))?;
+
+    Ok(Json(UserResponse::from(user_data)))
 }
 
 /// Handler de refresh token
@@ -360,14 +278,18 @@ pub async fn logout() -> StatusCode {
     StatusCode::OK
 }
 
-use axum::Router;
+/// Crear el router de autenticación (montado bajo `/api/v1/auth`)
+///
+/// Login, registro y refresh son públicos; `/me` y `/logout` requieren JWT.
+pub fn create_auth_router(state: AppState) -> Router<AppState> {
+    let protected = Router::new()
+        .route("/me", get(me))
+        .route("/logout", post(logout))
+        .route_layer(from_fn_with_state(state, auth_middleware));
 
-/// Crear el router de autenticación
-pub fn create_auth_router() -> Router<crate::state::AppState> {
     Router::new()
-        .route("/login", axum::routing::post(login))
-        .route("/register", axum::routing::post(register))
-        .route("/me", axum::routing::get(me))
-        .route("/refresh", axum::routing::post(refresh_token))
-        .route("/logout", axum::routing::post(logout))
+        .route("/login", post(login))
+        .route("/register", post(register))
+        .route("/refresh", post(refresh_token))
+        .merge(protected)
 }
@@ -4,7 +4,7 @@
 //! Todas las funciones móviles han sido comentadas para simplificar el backend.
 
 use axum::{
-    extract::State,
+    extract::{Extension, State},
     http::StatusCode,
     Json,
 };
@@ -15,7 +15,10 @@ use crate::{
     config::environment::EnvironmentConfig,
     services::colis_prive_service::{ColisPriveAuthRequest, GetTourneeRequest, GetPackagesRequest, ColisPriveAuthResponse},
     services::colis_prive_companies_service::ColisPriveCompaniesService,
+    services::field_knowledge::{self, CarrierFieldHints, FieldKnowledgeQuery, FieldKnowledgeViewer},
+    middleware::auth::AuthenticatedUser,
     models::colis_prive_company::ColisPriveCompanyListResponse,
+    models::user::UserType,
 };
 
 /// POST /api/colis-prive/auth - Autenticar con Colis Privé
@@ -243,8 +246,12 @@ pub async fn test_packages_endpoint() -> Result<Json<serde_json::Value>, StatusC
 }
 
 /// POST /api/colis-prive/packages - Obtener paquetes desde Colis Privé (IMPLEMENTACIÓN REAL)
+///
+/// Acepta un JWT opcional: sin él los paquetes llevan solo los datos del
+/// transportista, sin la información de terreno de los choferes.
 pub async fn get_packages(
     State(state): State<AppState>,
+    user: Option<Extension<AuthenticatedUser>>,
     Json(request): Json<GetPackagesRequest>,
 ) -> Result<Json<crate::services::GetPackagesResponse>, StatusCode> {
     use tracing::info;
@@ -386,7 +393,7 @@ pub async fn get_packages(
                     
                     // Procesar solo paquetes de tipo "COLIS"
                     if metier == "COLIS" {
-                        Some(PackageData {
+                        Some((PackageData {
                             id: package.get("idArticle")?.as_str()?.to_string(),
                             tracking_number: package.get("refExterneArticle")?.as_str()?.to_string(),
                             recipient_name: package.get("nomDestinataire")?.as_str()?.to_string(),
@@ -417,8 +424,10 @@ pub async fn get_packages(
                             formatted_address: None,
                             validation_method: None,
                             validation_confidence: None,
+                            validation_confidence_score: None,
                             validation_warnings: None,
-                        })
+                            field_knowledge: None,
+                        }, carrier_address_context(package), CarrierFieldHints::from_carrier_json(package)))
                     } else {
                         None
                     }
@@ -495,6 +504,14 @@ pub async fn get_packages(
         }
     }
 
+    // 🗝️ CONOCIMIENTO DE TERRENO (digicodes del transportista + base de choferes)
+    let viewer = user.map(|Extension(user)| FieldKnowledgeViewer {
+        user_id: user.user_id,
+        company_id: user.company_id,
+        is_driver: user.user_type == UserType::Driver,
+    });
+    let packages = attach_package_field_knowledge(&state, viewer, packages).await;
+
     // 🆕 VALIDACIÓN INTELIGENTE DE DIRECCIONES
     log::info!("🔍 Iniciando validación inteligente de direcciones para {} paquetes", packages.len());
     
@@ -512,11 +529,12 @@ pub async fn get_packages(
     // Crear el validador de direcciones
     if let Some(mapbox_token) = &state.config.mapbox_token {
         let geocoding_service = crate::services::GeocodingService::new(mapbox_token.clone());
-        let address_validator = crate::services::AddressValidator::new(geocoding_service);
+        let address_validator = crate::services::AddressValidator::new(geocoding_service)
+            .with_confidence_weights(state.config.address_confidence_weights.clone());
         
         // Validar cada paquete
-        for mut package in packages {
-            match address_validator.validate_address(&package.address, &request.matricule).await {
+        for (mut package, context) in packages {
+            match address_validator.validate_address_with_context(&package.address, &request.matricule, &context).await {
                 Ok(validated) => {
                     // Actualizar el paquete con la información de validación
                     package.latitude = validated.latitude;
@@ -524,6 +542,7 @@ pub async fn get_packages(
                     package.formatted_address = validated.formatted_address;
                     package.validation_method = Some(format!("{:?}", validated.validation_method));
                     package.validation_confidence = Some(format!("{:?}", validated.confidence));
+                    package.validation_confidence_score = Some(validated.confidence_score);
                     package.validation_warnings = Some(validated.warnings.clone());
                     
                     // Actualizar estadísticas
@@ -545,6 +564,7 @@ pub async fn get_packages(
                     validation_summary.requires_manual += 1;
                     package.validation_method = Some("ManualRequired".to_string());
                     package.validation_confidence = Some("None".to_string());
+                    package.validation_confidence_score = Some(0);
                     package.validation_warnings = Some(vec![format!("Error de validación: {}", e)]);
                     validated_packages.push(package);
                 }
@@ -561,7 +581,7 @@ pub async fn get_packages(
     } else {
         log::warn!("⚠️ MAPBOX_TOKEN no configurado, saltando validación de direcciones");
         validation_summary.requires_manual = packages.len();
-        validated_packages = packages;
+        validated_packages = packages.into_iter().map(|(package, _)| package).collect();
     }
 
     Ok(Json(GetPackagesResponse {
@@ -573,6 +593,79 @@ pub async fn get_packages(
     }))
 }
 
+/// Adjuntar a cada paquete el conocimiento de terreno combinado
+///
+/// Sin usuario autenticado solo se adjuntan los datos del transportista. Un
+/// fallo de base de datos no bloquea la carga de la tournée.
+async fn attach_package_field_knowledge(
+    state: &AppState,
+    viewer: Option<FieldKnowledgeViewer>,
+    packages: Vec<(crate::services::PackageData, crate::services::AddressContext, CarrierFieldHints)>,
+) -> Vec<(crate::services::PackageData, crate::services::AddressContext)> {
+    let queries: Vec<FieldKnowledgeQuery> = packages
+        .iter()
+        .map(|(package, context, hints)| FieldKnowledgeQuery {
+            address: package.address.clone(),
+            latitude: context.carrier_coordinates.map(|(lat, _)| lat),
+            longitude: context.carrier_coordinates.map(|(_, lng)| lng),
+            carrier: hints.clone(),
+        })
+        .collect();
+
+    let knowledge = match field_knowledge::attach_field_knowledge(
+        &state.pool,
+        state.field_cipher.as_deref(),
+        viewer,
+        &queries,
+        "tournee_load",
+    )
+    .await
+    {
+        Ok(knowledge) => knowledge,
+        Err(e) => {
+            log::warn!("⚠️ Error obteniendo información de terreno, se usan solo datos del transportista: {}", e);
+            queries
+                .iter()
+                .map(|query| field_knowledge::merge_field_knowledge(&query.carrier, None))
+                .collect()
+        }
+    };
+
+    let attached = knowledge.iter().filter(|k| k.is_some()).count();
+    log::info!("🗝️ Información de terreno adjunta a {}/{} paquetes", attached, packages.len());
+
+    packages
+        .into_iter()
+        .zip(knowledge)
+        .map(|((mut package, mut context, _), knowledge)| {
+            context.driver_verification_count = knowledge.as_ref().and_then(|k| k.driver_verification_count);
+            package.field_knowledge = knowledge;
+            (package, context)
+        })
+        .collect()
+}
+
+/// Extraer las señales de geocodificación del transportista para el score de confianza
+fn carrier_address_context(package: &serde_json::Value) -> crate::services::AddressContext {
+    // Colis Privé envía algunos valores numéricos como string
+    let number = |key: &str| -> Option<f64> {
+        package.get(key).and_then(|v| {
+            v.as_f64().or_else(|| v.as_str().and_then(|s| s.trim().replace(',', ".").parse().ok()))
+        })
+    };
+
+    let carrier_coordinates = match (number("coordYDestinataire"), number("coordXDestinataire")) {
+        (Some(lat), Some(lng)) if lat != 0.0 && lng != 0.0 => Some((lat, lng)),
+        _ => None,
+    };
+
+    crate::services::AddressContext {
+        carrier_score_geocodage: number("scoreGeocodageDestinataire"),
+        carrier_coordinates,
+        driver_verification_count: None,
+    }
+}
+
 /// POST /api/colis-prive/tournee - Obtener tournée (IMPLEMENTACIÓN COMPLETA)
 pub async fn get_tournee_data(
     State(state): State<AppState>,
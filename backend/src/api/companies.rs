@@ -1,18 +1,20 @@
 //! Handlers de Companies
-//! 
-//! Este módulo maneja las operaciones CRUD para empresas.
+//!
+//! Este módulo maneja las operaciones CRUD para empresas. Cada usuario solo
+//! ve y gestiona su propia empresa; el alta de empresas se hace con
+//! `POST /api/v1/auth/register`.
 
 use axum::{
     extract::{Extension, Path, State},
     http::StatusCode,
     Json,
 };
-use sqlx::PgPool;
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
-    models::company::{Company, CompanyResponse, CreateCompanyRequest},
+    api::require_admin,
+    models::company::{Company, CompanyResponse, CreateCompanyRequest, UpdateCompanyRequest, COMPANY_COLUMNS},
     utils::errors::{AppError, AppResult},
     middleware::auth::AuthenticatedUser,
 };
@@ -22,48 +24,28 @@ pub async fn get_companies(
     Extension(user): Extension<AuthenticatedUser>,
     State(state): State<crate::state::AppState>,
 ) -> AppResult<Json<Vec<CompanyResponse>>> {
-    let row = sqlx::query!(
-        r#"
-        SELECT 
-            id, name, address, subscription_plan, subscription_status, 
-            max_drivers, max_vehicles, created_at, updated_at, deleted_at
-        FROM companies 
-        WHERE id = $1 
-        AND deleted_at IS NULL
-        "#,
-        user.company_id
-    )
+    let company = sqlx::query_as::<_, Company>(&format!(
+        "SELECT {} FROM companies WHERE id = $1 AND deleted_at IS NULL",
+        COMPANY_COLUMNS
+    ))
+    .bind(user.company_id)
     .fetch_optional(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
+    .await?;
 
-    let companies = if let Some(row) = row {
-        vec![CompanyResponse {
-            id: row.id,
-            name: row.name,
-            address: row.address,
-            subscription_plan: row.subscription_plan,
-            subscription_status: row.subscription_status,
-            max_drivers: row.max_drivers,
-            max_vehicles: row.max_vehicles,
-            created_at: row.created_at.unwrap_or_else(|| chrono::Utc::now()),
-            updated_at: row.updated_at.unwrap_or_else(|| chrono::Utc::now()),
-        }]
-    } else {
-        vec![]
-    };
-
-    Ok(Json(companies))
+    Ok(Json(company.into_iter().map(CompanyResponse::from).collect()))
 }
 
-/// Handler para crear empresa (solo admins)
+/// Handler para crear empresa
+///
+/// Las empresas se crean junto con su primer admin en el registro público.
 pub async fn create_company(
     Extension(_user): Extension<AuthenticatedUser>,
     State(_state): State<crate::state::AppState>,
     Json(_company_data): Json<CreateCompanyRequest>,
 ) -> AppResult<Json<CompanyResponse>> {
-    // Por ahora retorna Not Implemented
-    Err(AppError::NotImplemented("Create company no implementado aún".to_string()))
+    Err(AppError::Forbidden(
+        "Las empresas se crean mediante /api/v1/auth/register".to_string(),
+    ))
 }
 
 /// Handler para obtener empresa por ID
@@ -77,45 +59,63 @@ pub async fn get_company(
         return Err(AppError::Forbidden("No tienes acceso a esta empresa".to_string()));
     }
 
-    let row = sqlx::query!(
-        r#"
-        SELECT 
-            id, name, address, subscription_plan, subscription_status, 
-            max_drivers, max_vehicles, created_at, updated_at, deleted_at
-        FROM companies 
-        WHERE id = $1 
-        AND deleted_at IS NULL
-        "#,
-        id
-    )
+    let company = sqlx::query_as::<_, Company>(&format!(
+        "SELECT {} FROM companies WHERE id = $1 AND deleted_at IS NULL",
+        COMPANY_COLUMNS
+    ))
+    .bind(id)
     .fetch_optional(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?
+    .await?
     .ok_or_else(|| AppError::NotFound("Empresa no encontrada".to_string()))?;
 
-    let company_response = CompanyResponse {
-        id: row.id,
-        name: row.name,
-        address: row.address,
-        subscription_plan: row.subscription_plan,
-        subscription_status: row.subscription_status,
-        max_drivers: row.max_drivers,
-        max_vehicles: row.max_vehicles,
-        created_at: row.created_at.unwrap_or_else(|| chrono::Utc::now()),
-        updated_at: row.updated_at.unwrap_or_else(|| chrono::Utc::now()),
-    };
-
-    Ok(Json(company_response))
+    Ok(Json(CompanyResponse::from(company)))
 }
 
-/// Handler para actualizar empresa (versión simplificada)
+/// Handler para actualizar empresa (solo admins de la propia empresa)
+///
+/// El plan y los límites de la suscripción no se modifican desde aquí.
 pub async fn update_company(
-    Extension(_user): Extension<AuthenticatedUser>,
-    State(_state): State<crate::state::AppState>,
-    Path(_id): Path<Uuid>,
-) -> AppResult<StatusCode> {
-    // Por ahora retorna Not Implemented
-    Err(AppError::NotImplemented("Update company no implementado aún".to_string()))
+    Extension(user): Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+    Json(company_data): Json<UpdateCompanyRequest>,
+) -> AppResult<Json<CompanyResponse>> {
+    require_admin(&user)?;
+    if id != user.company_id {
+        return Err(AppError::Forbidden("No tienes acceso a esta empresa".to_string()));
+    }
+    company_data.validate()
+        .map_err(AppError::Validation)?;
+
+    if company_data.subscription_plan.is_some()
+        || company_data.subscription_status.is_some()
+        || company_data.max_drivers.is_some()
+        || company_data.max_vehicles.is_some()
+    {
+        return Err(AppError::Forbidden(
+            "La suscripción de la empresa no se puede modificar desde la API".to_string(),
+        ));
+    }
+
+    let company = sqlx::query_as::<_, Company>(&format!(
+        r#"
+        UPDATE companies SET
+            name = COALESCE($2, name),
+            address = COALESCE($3, address),
+            updated_at = NOW()
+        WHERE id = $1 AND deleted_at IS NULL
+        RETURNING {}
+        "#,
+        COMPANY_COLUMNS
+    ))
+    .bind(id)
+    .bind(company_data.name)
+    .bind(company_data.address)
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Empresa no encontrada".to_string()))?;
+
+    Ok(Json(CompanyResponse::from(company)))
 }
 
 /// Handler para eliminar empresa (soft delete)
@@ -124,6 +124,6 @@ pub async fn delete_company(
     State(_state): State<crate::state::AppState>,
     Path(_id): Path<Uuid>,
 ) -> AppResult<StatusCode> {
-    // Por ahora retorna Not Implemented - eliminar empresa es operación muy delicada
-    Err(AppError::NotImplemented("Delete company no implementado aún".to_string()))
+    // Eliminar una empresa es una operación muy delicada: no se expone en la API
+    Err(AppError::Forbidden("La eliminación de empresas no está permitida".to_string()))
 }
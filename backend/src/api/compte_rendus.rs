@@ -0,0 +1,99 @@
+//! Handlers del compte-rendu de fin de día
+//!
+//! Generación al cerrar la tournée, consulta y exportación (JSON, CSV, PDF) y
+//! firma, que bloquea la tournée (ver `services::compte_rendu`).
+
+use axum::{
+    extract::{Path, Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    api::tournees::fetch_tournee,
+    models::compte_rendu::{CompteRenduParams, CompteRenduReport, GenerateCompteRenduRequest, SignCompteRenduRequest},
+    models::dispatch::DispatchEvent,
+    services::compte_rendu,
+    utils::errors::{AppError, AppResult},
+    middleware::auth::AuthenticatedUser,
+};
+
+/// Generar (o regenerar) el compte-rendu de una tournée cerrada
+pub async fn generate_compte_rendu(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<GenerateCompteRenduRequest>,
+) -> AppResult<Json<CompteRenduReport>> {
+    request.validate()
+        .map_err(AppError::Validation)?;
+
+    let mut tx = state.pool.begin().await?;
+    let report = compte_rendu::generate(&mut tx, &user, id, request.carrier_counts).await?;
+    tx.commit().await?;
+
+    Ok(Json(report))
+}
+
+/// Compte-rendu de la tournée (`?format=json|csv|pdf`)
+pub async fn get_compte_rendu(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<CompteRenduParams>,
+) -> AppResult<Response> {
+    let tournee = fetch_tournee(&state.pool, &user, id).await?;
+    let report = compte_rendu::find(&state.pool, &tournee).await?;
+    let filename = format!("compte_rendu_{}_{}", report.compte_rendu.tournee_date, tournee.id);
+
+    match params.format.as_deref().unwrap_or("json") {
+        "json" => Ok(Json(report).into_response()),
+        "csv" => Ok((
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+                (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}.csv\"", filename)),
+            ],
+            compte_rendu::render_csv(&report),
+        )
+            .into_response()),
+        "pdf" => Ok((
+            [
+                (header::CONTENT_TYPE, "application/pdf".to_string()),
+                (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}.pdf\"", filename)),
+            ],
+            compte_rendu::render_pdf(&report),
+        )
+            .into_response()),
+        other => Err(AppError::BadRequest(format!("Formato desconocido: {}", other))),
+    }
+}
+
+/// Firmar el compte-rendu y bloquear la tournée
+pub async fn sign_compte_rendu(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<SignCompteRenduRequest>,
+) -> AppResult<Json<CompteRenduReport>> {
+    request.validate()
+        .map_err(AppError::Validation)?;
+
+    let mut tx = state.pool.begin().await?;
+    let report = compte_rendu::sign(&mut tx, &user, id, &request).await?;
+    tx.commit().await?;
+
+    let signed = &report.compte_rendu;
+    state.dispatch.publish(DispatchEvent::alert(
+        signed.company_id,
+        Some(signed.tournee_id),
+        "compte_rendu_signed",
+        format!("Compte-rendu firmado por {}", request.signature_name),
+        json!({ "mismatches": signed.mismatches }),
+    )).await;
+
+    Ok(Json(report))
+}
@@ -0,0 +1,76 @@
+//! Handler del canal de seguimiento en vivo
+//!
+//! Server-Sent Events para el back-office: posiciones, estados de paquetes y
+//! tournées, ETAs y alertas de la empresa o de una tournée (ver
+//! `services::dispatch_feed`).
+
+use axum::{
+    extract::{Query, State},
+    response::sse::{Event, KeepAlive, Sse},
+    Json,
+};
+use futures::stream::{self, Stream};
+use std::convert::Infallible;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    api::{require_admin, tournees::fetch_tournee},
+    models::dispatch::{DispatchStreamParams, DispatchStreamToken},
+    services::dispatch_feed,
+    utils::errors::AppResult,
+    middleware::auth::{generate_stream_token, AuthenticatedUser, DISPATCH_STREAM_TOKEN_TTL_SECONDS},
+};
+
+/// Pedir un token para abrir el canal con `EventSource`, que no puede enviar
+/// el header `Authorization`
+pub async fn create_stream_token(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+) -> AppResult<Json<DispatchStreamToken>> {
+    require_admin(&user)?;
+
+    Ok(Json(DispatchStreamToken {
+        token: generate_stream_token(&user, &state.config.jwt_secret)?,
+        expires_in: DISPATCH_STREAM_TOKEN_TTL_SECONDS,
+    }))
+}
+
+/// Suscribirse a los eventos en vivo
+///
+/// Si el cliente se retrasa y pierde eventos recibe un evento `lagged` con
+/// el número de eventos perdidos y debe recargar el estado por la API.
+/// Acepta el JWT de sesión o el token de `create_stream_token` en
+/// `?access_token=` (ver `stream_auth_middleware`).
+pub async fn stream_dispatch(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Query(params): Query<DispatchStreamParams>,
+) -> AppResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    require_admin(&user)?;
+    if let Some(tournee_id) = params.tournee_id {
+        fetch_tournee(&state.pool, &user, tournee_id).await?;
+    }
+
+    let company_id = user.company_id;
+    let tournee_id = params.tournee_id;
+    let events = stream::unfold(state.dispatch.subscribe(), move |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) if dispatch_feed::matches(&event, company_id, tournee_id) => {
+                    if let Ok(sse) = Event::default().event(event.event_type.as_str()).json_data(&*event) {
+                        return Some((Ok(sse), receiver));
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(missed)) => {
+                    let sse = Event::default().event("lagged").data(missed.to_string());
+                    return Some((Ok(sse), receiver));
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    log::info!("📡 Dispatcher {} conectado al canal en vivo", user.user_id);
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
@@ -0,0 +1,292 @@
+//! Handlers de DriverFieldData
+//!
+//! CRUD y búsqueda sobre la base de conocimiento de terreno de los choferes.
+//! Todas las operaciones están acotadas a la empresa del usuario autenticado.
+//!
+//! Los datos de acceso (códigos de puerta, instrucciones, notas de seguridad)
+//! solo se descifran para los choferes de la empresa propietaria; cada lectura
+//! de un código de puerta queda registrada en `field_data_access_log`.
+//!
+//! Las actualizaciones requieren la `version` leída (409 si otro usuario la
+//! modificó); los administradores pueden consultar el historial de
+//! revisiones, revertir una entrada y verificar revisiones.
+
+use axum::{
+    extract::{Extension, Path, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::json;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    api::require_admin,
+    middleware::auth::AuthenticatedUser,
+    models::driver_field_data::{
+        CreateDriverFieldDataRequest, DriverFieldData, DriverFieldDataFilters, DriverFieldDataLookup,
+        DriverFieldDataMatch, DriverFieldDataRevision, FieldDataAccessLog, RevertDriverFieldDataRequest,
+        UpdateDriverFieldDataRequest,
+    },
+    models::user::UserType,
+    services::{driver_field_data_service, field_data_revisions},
+    state::AppState,
+    utils::errors::{AppError, AppResult},
+};
+
+pub fn create_driver_field_data_router() -> Router<AppState> {
+    Router::new()
+        .route("/field-data", get(list_field_data).post(create_field_data))
+        .route("/field-data/lookup", get(lookup_field_data))
+        .route("/field-data/encryption/rotate", post(rotate_field_data_encryption))
+        .route(
+            "/field-data/:id",
+            get(get_field_data).put(update_field_data).delete(delete_field_data),
+        )
+        .route("/field-data/:id/access-log", get(get_field_data_access_log))
+        .route("/field-data/:id/history", get(get_field_data_history))
+        .route("/field-data/:id/revert", post(revert_field_data))
+        .route(
+            "/field-data/:id/revisions/:revision_number/verify",
+            post(verify_field_data_revision),
+        )
+}
+
+/// Preparar entradas para la respuesta: descifrar para los choferes de la
+/// empresa propietaria (auditando los códigos de puerta) u ocultar
+async fn present_entries(
+    state: &AppState,
+    user: &AuthenticatedUser,
+    entries: &mut [&mut DriverFieldData],
+    access_context: &str,
+) -> AppResult<()> {
+    let can_decrypt = user.user_type == UserType::Driver;
+    let cipher = state.field_cipher.as_deref();
+
+    let mut door_code_reads = Vec::new();
+    for entry in entries.iter_mut() {
+        match cipher {
+            Some(cipher) if can_decrypt && entry.company_id == user.company_id => {
+                driver_field_data_service::reveal_sensitive(cipher, entry)?;
+                if entry.door_codes.is_some() {
+                    door_code_reads.push(entry.id);
+                }
+            }
+            _ => driver_field_data_service::redact_sensitive(entry),
+        }
+    }
+
+    driver_field_data_service::log_door_code_reads(
+        &state.pool,
+        user.company_id,
+        user.user_id,
+        &door_code_reads,
+        access_context,
+    )
+    .await
+}
+
+/// GET /api/v1/field-data - Listar/buscar información de terreno
+pub async fn list_field_data(
+    Extension(user): Extension<AuthenticatedUser>,
+    State(state): State<AppState>,
+    Query(filters): Query<DriverFieldDataFilters>,
+) -> AppResult<Json<Vec<DriverFieldData>>> {
+    let mut entries = driver_field_data_service::search(&state.pool, user.company_id, &filters).await?;
+
+    let mut refs: Vec<&mut DriverFieldData> = entries.iter_mut().collect();
+    present_entries(&state, &user, &mut refs, "list").await?;
+
+    Ok(Json(entries))
+}
+
+/// GET /api/v1/field-data/lookup - Entrada más confiable por dirección o proximidad
+pub async fn lookup_field_data(
+    Extension(user): Extension<AuthenticatedUser>,
+    State(state): State<AppState>,
+    Query(params): Query<DriverFieldDataLookup>,
+) -> AppResult<Json<DriverFieldDataMatch>> {
+    let mut found = driver_field_data_service::lookup(&state.pool, user.company_id, &params)
+        .await?
+        .ok_or_else(|| AppError::NotFound("No hay información de terreno para esta ubicación".to_string()))?;
+
+    present_entries(&state, &user, &mut [&mut found.entry], "lookup").await?;
+
+    Ok(Json(found))
+}
+
+/// GET /api/v1/field-data/:id - Obtener una entrada
+pub async fn get_field_data(
+    Extension(user): Extension<AuthenticatedUser>,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<DriverFieldData>> {
+    let mut entry = driver_field_data_service::get(&state.pool, user.company_id, id).await?;
+    present_entries(&state, &user, &mut [&mut entry], "detail").await?;
+
+    Ok(Json(entry))
+}
+
+/// POST /api/v1/field-data - Crear una entrada
+pub async fn create_field_data(
+    Extension(user): Extension<AuthenticatedUser>,
+    State(state): State<AppState>,
+    Json(request): Json<CreateDriverFieldDataRequest>,
+) -> AppResult<(StatusCode, Json<DriverFieldData>)> {
+    request.validate()?;
+
+    let mut entry = driver_field_data_service::create(
+        &state.pool,
+        state.field_cipher.as_deref(),
+        user.company_id,
+        user.user_id,
+        &request,
+    )
+    .await?;
+    log::info!("📝 Información de terreno creada: {} ({})", entry.address, entry.id);
+
+    present_entries(&state, &user, &mut [&mut entry], "create").await?;
+
+    Ok((StatusCode::CREATED, Json(entry)))
+}
+
+/// PUT /api/v1/field-data/:id - Actualizar una entrada
+pub async fn update_field_data(
+    Extension(user): Extension<AuthenticatedUser>,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<UpdateDriverFieldDataRequest>,
+) -> AppResult<Json<DriverFieldData>> {
+    request.validate()?;
+
+    let mut entry = driver_field_data_service::update(
+        &state.pool,
+        state.field_cipher.as_deref(),
+        user.company_id,
+        user.user_id,
+        id,
+        &request,
+    )
+    .await?;
+
+    present_entries(&state, &user, &mut [&mut entry], "update").await?;
+
+    Ok(Json(entry))
+}
+
+/// DELETE /api/v1/field-data/:id - Eliminar una entrada (admin o autor)
+pub async fn delete_field_data(
+    Extension(user): Extension<AuthenticatedUser>,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<StatusCode> {
+    let entry = driver_field_data_service::get(&state.pool, user.company_id, id).await?;
+
+    if user.user_type != UserType::Admin && entry.driver_id != user.user_id {
+        return Err(AppError::Forbidden(
+            "Solo el autor o un administrador puede eliminar esta información".to_string(),
+        ));
+    }
+
+    driver_field_data_service::delete(&state.pool, user.company_id, user.user_id, id).await?;
+    log::info!("🗑️ Información de terreno eliminada: {}", id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /api/v1/field-data/:id/access-log - Auditoría de lecturas (admin)
+pub async fn get_field_data_access_log(
+    Extension(user): Extension<AuthenticatedUser>,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Vec<FieldDataAccessLog>>> {
+    require_admin(&user)?;
+
+    let log = driver_field_data_service::access_log(&state.pool, user.company_id, id).await?;
+    Ok(Json(log))
+}
+
+/// POST /api/v1/field-data/encryption/rotate - Re-cifrar con la clave activa (admin)
+pub async fn rotate_field_data_encryption(
+    Extension(user): Extension<AuthenticatedUser>,
+    State(state): State<AppState>,
+) -> AppResult<Json<serde_json::Value>> {
+    require_admin(&user)?;
+
+    let cipher = state.field_cipher()?;
+    let rotated = driver_field_data_service::rotate_encryption(&state.pool, cipher, user.company_id).await?;
+    let rotated_revisions = field_data_revisions::rotate_encryption(&state.pool, cipher, user.company_id).await?;
+    log::info!(
+        "🔐 Rotación de claves completada para empresa {}: {} entradas, {} revisiones (clave '{}')",
+        user.company_id,
+        rotated,
+        rotated_revisions,
+        cipher.active_key_id()
+    );
+
+    Ok(Json(json!({
+        "success": true,
+        "active_key_id": cipher.active_key_id(),
+        "rotated_entries": rotated,
+        "rotated_revisions": rotated_revisions,
+    })))
+}
+
+/// GET /api/v1/field-data/:id/history - Historial de revisiones (admin)
+pub async fn get_field_data_history(
+    Extension(user): Extension<AuthenticatedUser>,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Vec<DriverFieldDataRevision>>> {
+    require_admin(&user)?;
+
+    let mut revisions = field_data_revisions::history(&state.pool, user.company_id, id).await?;
+    if revisions.is_empty() {
+        // Distinguir entradas sin historial de entradas inexistentes
+        driver_field_data_service::get(&state.pool, user.company_id, id).await?;
+    }
+    revisions.iter_mut().for_each(field_data_revisions::redact);
+
+    Ok(Json(revisions))
+}
+
+/// POST /api/v1/field-data/:id/revert - Volver al estado de una revisión (admin)
+pub async fn revert_field_data(
+    Extension(user): Extension<AuthenticatedUser>,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<RevertDriverFieldDataRequest>,
+) -> AppResult<Json<DriverFieldData>> {
+    require_admin(&user)?;
+    request.validate()?;
+
+    let mut entry =
+        driver_field_data_service::revert(&state.pool, user.company_id, user.user_id, id, &request).await?;
+    log::info!(
+        "⏪ Información de terreno {} revertida a la revisión {} (versión {})",
+        id,
+        request.revision_number,
+        entry.version
+    );
+
+    present_entries(&state, &user, &mut [&mut entry], "revert").await?;
+
+    Ok(Json(entry))
+}
+
+/// POST /api/v1/field-data/:id/revisions/:revision_number/verify - Verificar una revisión (admin)
+pub async fn verify_field_data_revision(
+    Extension(user): Extension<AuthenticatedUser>,
+    State(state): State<AppState>,
+    Path((id, revision_number)): Path<(Uuid, i32)>,
+) -> AppResult<Json<DriverFieldDataRevision>> {
+    require_admin(&user)?;
+
+    let mut revision =
+        field_data_revisions::verify(&state.pool, user.company_id, id, revision_number, user.user_id).await?;
+    log::info!("✅ Revisión {} de la información de terreno {} verificada", revision_number, id);
+
+    field_data_revisions::redact(&mut revision);
+    Ok(Json(revision))
+}
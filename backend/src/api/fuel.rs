@@ -0,0 +1,154 @@
+//! Handlers del cuadre de combustible
+//!
+//! Importación del CSV de la tarjeta de combustible y consulta de los cuadres
+//! por tournée (ver `services::fuel_reconciliation`). El cuadre se calcula
+//! solo al finalizar la tournée; `POST /tournees/:id/reconciliation` lo
+//! recalcula tras corregir kilometraje o litros.
+
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    api::require_admin,
+    api::tournees::fetch_tournee,
+    models::dispatch::DispatchEvent,
+    models::fuel::{
+        FuelCardTransaction, FuelImportSummary, FuelReconciliation, FuelReconciliationFilters,
+        FuelTransactionFilters, FUEL_CARD_TRANSACTION_COLUMNS, FUEL_RECONCILIATION_COLUMNS,
+    },
+    services::fuel_reconciliation,
+    utils::errors::{AppError, AppResult},
+    middleware::auth::AuthenticatedUser,
+};
+
+/// Importar el CSV de la tarjeta de combustible (cuerpo en texto)
+pub async fn import_fuel_card_csv(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    body: String,
+) -> AppResult<Json<FuelImportSummary>> {
+    require_admin(&user)?;
+
+    let summary = fuel_reconciliation::import_transactions(&state.pool, &user, &body, &state.config.fuel).await?;
+    Ok(Json(summary))
+}
+
+/// Repostajes importados (`?from=&to=&vehicle_id=&match_status=unmatched`)
+pub async fn get_fuel_transactions(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Query(filters): Query<FuelTransactionFilters>,
+) -> AppResult<Json<Vec<FuelCardTransaction>>> {
+    require_admin(&user)?;
+
+    let transactions = sqlx::query_as::<_, FuelCardTransaction>(&format!(
+        r#"
+        SELECT {}
+        FROM fuel_card_transactions
+        WHERE company_id = $1
+        AND ($2::date IS NULL OR transacted_at >= $2::date)
+        AND ($3::date IS NULL OR transacted_at < $3::date + 1)
+        AND ($4::uuid IS NULL OR vehicle_id = $4)
+        AND ($5::text IS NULL OR match_status = $5)
+        ORDER BY transacted_at DESC
+        "#,
+        FUEL_CARD_TRANSACTION_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(filters.from)
+    .bind(filters.to)
+    .bind(filters.vehicle_id)
+    .bind(filters.match_status.map(|status| status.as_str()))
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(transactions))
+}
+
+/// Cuadres de la empresa (`?from=&to=&vehicle_id=&flagged=true`)
+pub async fn get_fuel_reconciliations(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Query(filters): Query<FuelReconciliationFilters>,
+) -> AppResult<Json<Vec<FuelReconciliation>>> {
+    require_admin(&user)?;
+
+    let reconciliations = sqlx::query_as::<_, FuelReconciliation>(&format!(
+        r#"
+        SELECT {}
+        FROM fuel_reconciliations
+        WHERE company_id = $1
+        AND ($2::date IS NULL OR tournee_date >= $2)
+        AND ($3::date IS NULL OR tournee_date <= $3)
+        AND ($4::uuid IS NULL OR vehicle_id = $4)
+        AND ($5::boolean IS NULL OR (jsonb_array_length(flags) > 0) = $5)
+        ORDER BY tournee_date DESC, computed_at DESC
+        "#,
+        FUEL_RECONCILIATION_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(filters.from)
+    .bind(filters.to)
+    .bind(filters.vehicle_id)
+    .bind(filters.flagged)
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(reconciliations))
+}
+
+/// Cuadre de una tournée (el chofer ve el de las suyas)
+pub async fn get_tournee_reconciliation(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<FuelReconciliation>> {
+    let tournee = fetch_tournee(&state.pool, &user, id).await?;
+
+    let reconciliation = sqlx::query_as::<_, FuelReconciliation>(&format!(
+        "SELECT {} FROM fuel_reconciliations WHERE tournee_id = $1",
+        FUEL_RECONCILIATION_COLUMNS
+    ))
+    .bind(tournee.id)
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("La tournée no tiene cuadre de combustible".to_string()))?;
+
+    Ok(Json(reconciliation))
+}
+
+/// Recalcular el cuadre de una tournée finalizada
+pub async fn reconcile_tournee(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<FuelReconciliation>> {
+    require_admin(&user)?;
+
+    let (reconciliation, alerted) =
+        fuel_reconciliation::reconcile_closed_tournee(&state.pool, user.company_id, id, &state.config.fuel).await?;
+    if alerted {
+        publish_alert(&state, &reconciliation).await;
+    }
+
+    Ok(Json(reconciliation))
+}
+
+/// Avisar al panel de despacho de un cuadre con anomalías
+pub async fn publish_alert(state: &crate::state::AppState, reconciliation: &FuelReconciliation) {
+    state.dispatch.publish(DispatchEvent::alert(
+        reconciliation.company_id,
+        Some(reconciliation.tournee_id),
+        "fuel_anomaly",
+        "Cuadre de kilometraje o combustible con anomalías".to_string(),
+        json!({
+            "reconciliation_id": reconciliation.id,
+            "vehicle_id": reconciliation.vehicle_id,
+            "flags": reconciliation.flags,
+        }),
+    )).await;
+}
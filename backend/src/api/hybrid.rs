@@ -4,7 +4,7 @@
 
 use anyhow::Result;
 use axum::{
-    extract::State,
+    extract::{Extension, State},
     response::Json,
     routing::post,
     Router,
@@ -13,7 +13,10 @@ use serde::{Deserialize, Serialize};
 use tracing::{info, warn};
 
 use crate::services::hybrid_processor::{HybridProcessor, HybridProcessingResult};
+use crate::services::field_knowledge::{self, CarrierFieldHints, FieldKnowledgeQuery, FieldKnowledgeViewer};
 use crate::cache::CacheStrategy;
+use crate::middleware::auth::{optional_auth_middleware, AuthenticatedUser};
+use crate::models::user::UserType;
 use crate::state::AppState;
 
 /// Request para procesamiento híbrido
@@ -66,9 +69,16 @@ pub struct CacheCleanupResponse {
 }
 
 /// Crear router para endpoints híbridos
-pub fn create_router() -> Router<AppState> {
+///
+/// `/api/hybrid/process` acepta un JWT opcional: con él se adjunta la
+/// información de terreno de los choferes de la empresa.
+pub fn create_router(state: AppState) -> Router<AppState> {
     Router::new()
-        .route("/api/hybrid/process", post(process_packages_hybrid))
+        .route(
+            "/api/hybrid/process",
+            post(process_packages_hybrid)
+                .route_layer(axum::middleware::from_fn_with_state(state, optional_auth_middleware)),
+        )
         .route("/api/hybrid/package-detail", post(get_package_detail))
         .route("/api/hybrid/cache/cleanup", post(cleanup_cache))
         .route("/api/hybrid/cache/stats", post(get_cache_stats))
@@ -77,6 +87,7 @@ pub fn create_router() -> Router<AppState> {
 /// Procesar paquetes con estrategia híbrida
 pub async fn process_packages_hybrid(
     State(_state): State<AppState>,
+    user: Option<Extension<AuthenticatedUser>>,
     Json(request): Json<HybridProcessingRequest>,
 ) -> Result<Json<HybridProcessingResponse>, Json<HybridProcessingResponse>> {
     let start_time = std::time::Instant::now();
@@ -111,7 +122,11 @@ pub async fn process_packages_hybrid(
     
     // Procesar paquetes
     match processor.process_packages(mock_packages, &request.sso_token).await {
-        Ok(result) => {
+        Ok(mut result) => {
+            if let Some(Extension(user)) = user {
+                attach_driver_field_knowledge(&_state, &user, &mut result).await;
+            }
+
             let processing_time = start_time.elapsed().as_millis() as u64;
             info!("Procesamiento híbrido completado en {}ms", processing_time);
             
@@ -134,6 +149,48 @@ pub async fn process_packages_hybrid(
     }
 }
 
+/// Completar el conocimiento de terreno con la base de choferes de la empresa
+async fn attach_driver_field_knowledge(state: &AppState, user: &AuthenticatedUser, result: &mut HybridProcessingResult) {
+    let viewer = FieldKnowledgeViewer {
+        user_id: user.user_id,
+        company_id: user.company_id,
+        is_driver: user.user_type == UserType::Driver,
+    };
+
+    let queries: Vec<FieldKnowledgeQuery> = result
+        .processed_packages
+        .iter()
+        .map(|package| FieldKnowledgeQuery {
+            address: package.enriched_data.complete_address.clone(),
+            latitude: package.basic_data.delivery_coordinates.as_ref().map(|point| point.y),
+            longitude: package.basic_data.delivery_coordinates.as_ref().map(|point| point.x),
+            carrier: package
+                .detail_data
+                .as_ref()
+                .and_then(|detail| detail.data.as_ref())
+                .map(CarrierFieldHints::from_detail)
+                .unwrap_or_default(),
+        })
+        .collect();
+
+    match field_knowledge::attach_field_knowledge(
+        &state.pool,
+        state.field_cipher.as_deref(),
+        Some(viewer),
+        &queries,
+        "tournee_load",
+    )
+    .await
+    {
+        Ok(knowledge) => {
+            for (package, knowledge) in result.processed_packages.iter_mut().zip(knowledge) {
+                package.enriched_data.field_knowledge = knowledge;
+            }
+        }
+        Err(e) => warn!("Error adjuntando información de terreno: {}", e),
+    }
+}
+
 /// Obtener datos detallados de un paquete específico
 pub async fn get_package_detail(
     State(_state): State<AppState>,
@@ -312,6 +369,8 @@ fn create_mock_packages(ref_colis_list: &[String]) -> Vec<crate::models::package
             failure_reason: None,
             failure_notes: None,
             reschedule_date: None,
+            next_action: None,
+            relay_point_id: None,
             delivery_photo: None,
             signature_required: true,
             signature_image: None,
@@ -323,6 +382,8 @@ fn create_mock_packages(ref_colis_list: &[String]) -> Vec<crate::models::package
             delivery_duration_minutes: None,
             driver_notes: None,
             package_condition: None,
+            status_changed_at: None,
+            notes_changed_at: None,
             created_at: Some(chrono::Utc::now()),
             updated_at: Some(chrono::Utc::now()),
             deleted_at: None,
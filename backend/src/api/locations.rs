@@ -0,0 +1,143 @@
+//! Handlers de posiciones GPS
+//!
+//! Ingesta de lotes de posiciones desde la app, consulta del recorrido de un
+//! chofer y de las visitas y ETAs de las paradas de una tournée (ver
+//! `services::location_tracking`, `services::geofencing` y `services::eta`).
+
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    api::{driver_scope, tournees::fetch_tournee},
+    models::dispatch::DispatchEvent,
+    models::location::{DriverTrack, LocationBatchRequest, LocationBatchResponse, TrackParams},
+    models::stop_visit::{StopVisit, TourneeEta},
+    services::{eta, geofencing, location_tracking},
+    utils::errors::{AppError, AppResult},
+    middleware::auth::AuthenticatedUser,
+};
+
+/// Intervalo máximo de un recorrido
+const MAX_TRACK_DAYS: i64 = 7;
+
+/// Recibir un lote de posiciones del chofer autenticado
+pub async fn ingest_locations(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Json(batch): Json<LocationBatchRequest>,
+) -> AppResult<Json<LocationBatchResponse>> {
+    batch.validate()
+        .map_err(AppError::Validation)?;
+
+    let response = location_tracking::ingest(
+        &state.pool,
+        &user,
+        batch.tournee_id,
+        &batch.fixes,
+        &state.config.location,
+        &state.config.geofence,
+    )
+    .await?;
+
+    if response.last_location_updated {
+        let latest = location_tracking::latest_fix(&batch.fixes, Utc::now(), state.config.location.max_accuracy_meters);
+        if let Some(fix) = latest {
+            state.dispatch.publish(DispatchEvent::driver_position(
+                user.company_id,
+                user.user_id,
+                response.tournee_id,
+                fix.latitude,
+                fix.longitude,
+                fix.recorded_at,
+            )).await;
+        }
+    }
+
+    if let (Some(tournee_id), false) = (response.tournee_id, response.stop_events.is_empty()) {
+        let visits: Vec<DispatchEvent> = response
+            .stop_events
+            .iter()
+            .map(|event| DispatchEvent::stop_visit(user.company_id, user.user_id, event))
+            .collect();
+        state.dispatch.publish_all(visits).await;
+
+        // Llegadas y salidas cambian la hora prevista de las paradas siguientes
+        let tournee = fetch_tournee(&state.pool, &user, tournee_id).await?;
+        let mut conn = state.pool.acquire().await?;
+        match eta::tournee_eta(&mut conn, &tournee, &state.config.geofence).await {
+            Ok(etas) => state.dispatch.publish(DispatchEvent::eta(user.company_id, user.user_id, &etas)).await,
+            Err(e) => log::warn!("⚠️ No se pudieron recalcular las ETAs de la tournée {}: {}", tournee_id, e),
+        }
+    }
+
+    Ok(Json(response))
+}
+
+/// Recorrido de un chofer (`?from=&to=` en RFC 3339, `tournee_id` opcional)
+pub async fn get_driver_track(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(driver_id): Path<Uuid>,
+    Query(params): Query<TrackParams>,
+) -> AppResult<Json<DriverTrack>> {
+    if driver_scope(&user).is_some_and(|own| own != driver_id) {
+        return Err(AppError::Forbidden("Solo puedes consultar tu propio recorrido".to_string()));
+    }
+    if params.to - params.from > Duration::days(MAX_TRACK_DAYS) {
+        return Err(AppError::BadRequest(format!(
+            "El intervalo no puede superar {} días",
+            MAX_TRACK_DAYS
+        )));
+    }
+
+    let exists = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(SELECT 1 FROM users WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL)",
+    )
+    .bind(driver_id)
+    .bind(user.company_id)
+    .fetch_one(&state.pool)
+    .await?;
+    if !exists {
+        return Err(AppError::NotFound("Usuario no encontrado".to_string()));
+    }
+
+    let track = location_tracking::driver_track(
+        &state.pool,
+        user.company_id,
+        driver_id,
+        &params,
+        &state.config.location,
+    )
+    .await?;
+
+    Ok(Json(track))
+}
+
+/// Visitas a paradas detectadas por geocerca en una tournée
+pub async fn get_tournee_stop_visits(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Vec<StopVisit>>> {
+    let tournee = fetch_tournee(&state.pool, &user, id).await?;
+    let mut conn = state.pool.acquire().await?;
+
+    Ok(Json(geofencing::list_for_tournee(&mut conn, tournee.id).await?))
+}
+
+/// ETAs de las paradas pendientes de una tournée
+pub async fn get_tournee_eta(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<TourneeEta>> {
+    let tournee = fetch_tournee(&state.pool, &user, id).await?;
+    let mut conn = state.pool.acquire().await?;
+
+    Ok(Json(eta::tournee_eta(&mut conn, &tournee, &state.config.geofence).await?))
+}
@@ -0,0 +1,301 @@
+//! Handlers del mantenimiento preventivo
+//!
+//! Planes por modelo de vehículo, previsión de vencimientos y citas de
+//! taller (ver `services::maintenance`). Todo es cosa del admin salvo la
+//! previsión de un vehículo.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    api::{map_unique_violation, require_admin},
+    models::maintenance::{
+        BookMaintenanceRequest, CreateMaintenancePlanRequest, MaintenanceDue, MaintenanceFilters, MaintenanceForecast,
+        MaintenancePlan, MaintenanceTransitionRequest, UpdateMaintenancePlanRequest, VehicleMaintenance,
+        MAINTENANCE_PLAN_COLUMNS, VEHICLE_MAINTENANCE_COLUMNS,
+    },
+    services::maintenance,
+    utils::errors::{AppError, AppResult},
+    middleware::auth::AuthenticatedUser,
+};
+
+const DUPLICATE_TASK: &str = "El plan de ese modelo ya tiene una tarea con ese nombre";
+
+/// Tareas de mantenimiento de la empresa
+pub async fn get_maintenance_plans(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+) -> AppResult<Json<Vec<MaintenancePlan>>> {
+    require_admin(&user)?;
+
+    let plans = sqlx::query_as::<_, MaintenancePlan>(&format!(
+        r#"
+        SELECT {}
+        FROM maintenance_plans
+        WHERE company_id = $1 AND deleted_at IS NULL
+        ORDER BY brand, model, task_name
+        "#,
+        MAINTENANCE_PLAN_COLUMNS
+    ))
+    .bind(user.company_id)
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(plans))
+}
+
+/// Añadir una tarea al plan de un modelo
+pub async fn create_maintenance_plan(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Json(plan_data): Json<CreateMaintenancePlanRequest>,
+) -> AppResult<(StatusCode, Json<MaintenancePlan>)> {
+    require_admin(&user)?;
+    plan_data.validate()
+        .map_err(AppError::Validation)?;
+    if plan_data.interval_km.is_none() && plan_data.interval_months.is_none() {
+        return Err(AppError::BadRequest("Indica la periodicidad en km, en meses o ambas".to_string()));
+    }
+
+    let plan = sqlx::query_as::<_, MaintenancePlan>(&format!(
+        r#"
+        INSERT INTO maintenance_plans (
+            company_id, brand, model, task_name, description, interval_km,
+            interval_months, estimated_hours
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING {}
+        "#,
+        MAINTENANCE_PLAN_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(plan_data.brand.trim())
+    .bind(plan_data.model.trim())
+    .bind(plan_data.task_name.trim())
+    .bind(&plan_data.description)
+    .bind(plan_data.interval_km)
+    .bind(plan_data.interval_months)
+    .bind(plan_data.estimated_hours)
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| map_unique_violation(e, DUPLICATE_TASK))?;
+
+    log::info!("🔧 Tarea '{}' añadida al plan de {} {}", plan.task_name, plan.brand, plan.model);
+    Ok((StatusCode::CREATED, Json(plan)))
+}
+
+/// Modificar una tarea del plan
+pub async fn update_maintenance_plan(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+    Json(plan_data): Json<UpdateMaintenancePlanRequest>,
+) -> AppResult<Json<MaintenancePlan>> {
+    require_admin(&user)?;
+    plan_data.validate()
+        .map_err(AppError::Validation)?;
+
+    let plan = sqlx::query_as::<_, MaintenancePlan>(&format!(
+        r#"
+        UPDATE maintenance_plans SET
+            task_name = COALESCE($3, task_name),
+            description = COALESCE($4, description),
+            interval_km = COALESCE($5, interval_km),
+            interval_months = COALESCE($6, interval_months),
+            estimated_hours = COALESCE($7, estimated_hours),
+            is_active = COALESCE($8, is_active),
+            updated_at = NOW()
+        WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
+        RETURNING {}
+        "#,
+        MAINTENANCE_PLAN_COLUMNS
+    ))
+    .bind(id)
+    .bind(user.company_id)
+    .bind(plan_data.task_name.as_deref().map(str::trim))
+    .bind(&plan_data.description)
+    .bind(plan_data.interval_km)
+    .bind(plan_data.interval_months)
+    .bind(plan_data.estimated_hours)
+    .bind(plan_data.is_active)
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| map_unique_violation(e, DUPLICATE_TASK))?
+    .ok_or_else(|| AppError::NotFound("Tarea de mantenimiento no encontrada".to_string()))?;
+
+    Ok(Json(plan))
+}
+
+/// Eliminar una tarea del plan (soft delete; el histórico de citas se conserva)
+pub async fn delete_maintenance_plan(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<StatusCode> {
+    require_admin(&user)?;
+
+    let result = sqlx::query(
+        r#"
+        UPDATE maintenance_plans SET deleted_at = NOW(), updated_at = NOW()
+        WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
+        "#,
+    )
+    .bind(id)
+    .bind(user.company_id)
+    .execute(&state.pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("Tarea de mantenimiento no encontrada".to_string()));
+    }
+
+    sqlx::query("DELETE FROM vehicle_maintenance_schedule WHERE plan_id = $1")
+        .bind(id)
+        .execute(&state.pool)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Próximos mantenimientos de un vehículo
+pub async fn get_vehicle_forecast(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(vehicle_id): Path<Uuid>,
+) -> AppResult<Json<Vec<MaintenanceForecast>>> {
+    let mut conn = state.pool.acquire().await?;
+    let forecast = maintenance::vehicle_forecast(&mut conn, user.company_id, vehicle_id, &state.config.maintenance).await?;
+    Ok(Json(forecast))
+}
+
+/// Tareas próximas o vencidas de la empresa (última pasada del job)
+pub async fn get_maintenance_due(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+) -> AppResult<Json<Vec<MaintenanceDue>>> {
+    require_admin(&user)?;
+
+    let due = sqlx::query_as::<_, MaintenanceDue>(
+        r#"
+        SELECT s.vehicle_id, v.license_plate, s.plan_id, p.task_name, v.current_mileage,
+               s.due_mileage, s.due_date, s.projected_date, s.due_state,
+               EXISTS (
+                   SELECT 1 FROM vehicle_maintenance m
+                   WHERE m.vehicle_id = s.vehicle_id AND m.plan_id = s.plan_id
+                   AND m.maintenance_status IN ('scheduled', 'in_progress')
+               ) AS booked
+        FROM vehicle_maintenance_schedule s
+        JOIN vehicles v ON v.id = s.vehicle_id AND v.deleted_at IS NULL
+        JOIN maintenance_plans p ON p.id = s.plan_id AND p.deleted_at IS NULL AND p.is_active
+        WHERE s.company_id = $1 AND s.due_state <> 'ok'
+        ORDER BY s.due_state = 'overdue' DESC, s.projected_date NULLS LAST
+        "#,
+    )
+    .bind(user.company_id)
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(due))
+}
+
+/// Citas de taller (`?vehicle_id=&maintenance_status=&from=&to=`)
+pub async fn get_maintenance(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Query(filters): Query<MaintenanceFilters>,
+) -> AppResult<Json<Vec<VehicleMaintenance>>> {
+    require_admin(&user)?;
+
+    let bookings = sqlx::query_as::<_, VehicleMaintenance>(&format!(
+        r#"
+        SELECT {}
+        FROM vehicle_maintenance
+        WHERE company_id = $1
+        AND ($2::uuid IS NULL OR vehicle_id = $2)
+        AND ($3::text IS NULL OR maintenance_status = $3)
+        AND ($4::date IS NULL OR scheduled_end >= $4::date)
+        AND ($5::date IS NULL OR scheduled_start < $5::date + 1)
+        ORDER BY scheduled_start DESC
+        "#,
+        VEHICLE_MAINTENANCE_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(filters.vehicle_id)
+    .bind(filters.maintenance_status.map(|status| status.as_str()))
+    .bind(filters.from)
+    .bind(filters.to)
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(bookings))
+}
+
+/// Citas de taller de un vehículo
+pub async fn get_vehicle_maintenance(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(vehicle_id): Path<Uuid>,
+) -> AppResult<Json<Vec<VehicleMaintenance>>> {
+    let bookings = sqlx::query_as::<_, VehicleMaintenance>(&format!(
+        r#"
+        SELECT {}
+        FROM vehicle_maintenance
+        WHERE vehicle_id = $1 AND company_id = $2
+        ORDER BY scheduled_start DESC
+        "#,
+        VEHICLE_MAINTENANCE_COLUMNS
+    ))
+    .bind(vehicle_id)
+    .bind(user.company_id)
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(bookings))
+}
+
+/// Reservar una cita de taller
+pub async fn book_maintenance(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(vehicle_id): Path<Uuid>,
+    Json(request): Json<BookMaintenanceRequest>,
+) -> AppResult<(StatusCode, Json<VehicleMaintenance>)> {
+    require_admin(&user)?;
+    request.validate()
+        .map_err(AppError::Validation)?;
+
+    let mut tx = state.pool.begin().await?;
+    let booking = maintenance::book(&mut tx, &user, vehicle_id, &request).await?;
+    tx.commit().await?;
+
+    log::info!(
+        "🔧 Cita de taller '{}' reservada para el vehículo {} ({} - {})",
+        booking.task_name,
+        vehicle_id,
+        booking.scheduled_start,
+        booking.scheduled_end
+    );
+    Ok((StatusCode::CREATED, Json(booking)))
+}
+
+/// Empezar, completar o cancelar una cita de taller
+pub async fn transition_maintenance(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<MaintenanceTransitionRequest>,
+) -> AppResult<Json<VehicleMaintenance>> {
+    require_admin(&user)?;
+    request.validate()
+        .map_err(AppError::Validation)?;
+
+    let mut tx = state.pool.begin().await?;
+    let booking = maintenance::transition(&mut tx, &user, id, &request).await?;
+    tx.commit().await?;
+
+    Ok(Json(booking))
+}
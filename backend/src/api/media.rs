@@ -0,0 +1,130 @@
+//! Handlers de archivos multimedia
+//!
+//! Subida multipart de fotos de entrega, firmas, fotos de inspección,
+//! evidencias de daños y documentos de vehículo (JWT requerido), y descarga
+//! mediante URL firmada (sin JWT, para poder usarla directamente en
+//! `<img src>`).
+
+use axum::{
+    extract::{DefaultBodyLimit, Extension, Multipart, Path, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    middleware::auth::AuthenticatedUser,
+    models::media::{MediaObjectResponse, MediaTarget},
+    services::media_service::content_type_for_key,
+    services::media_storage::validate_key,
+    state::AppState,
+    utils::errors::{AppError, AppResult},
+};
+
+/// Margen para las cabeceras multipart sobre el tamaño máximo del archivo
+const MULTIPART_OVERHEAD_BYTES: usize = 64 * 1024;
+
+/// Rutas protegidas (montadas bajo `/api/v1`)
+pub fn create_media_router(max_upload_bytes: usize) -> Router<AppState> {
+    Router::new()
+        .route(
+            "/media/:target/:entity_id",
+            post(upload_media).layer(DefaultBodyLimit::max(max_upload_bytes + MULTIPART_OVERHEAD_BYTES)),
+        )
+        .route("/media/:id", get(get_media))
+}
+
+/// Ruta pública de descarga con firma
+pub fn create_media_download_router() -> Router<AppState> {
+    Router::new().route("/api/media/*key", get(download_media))
+}
+
+/// Parámetros de la URL firmada
+#[derive(Debug, Deserialize)]
+pub struct SignedUrlParams {
+    pub expires: i64,
+    pub signature: String,
+}
+
+/// POST /api/v1/media/:target/:entity_id - Subir una imagen (campo `file`)
+pub async fn upload_media(
+    Extension(user): Extension<AuthenticatedUser>,
+    State(state): State<AppState>,
+    Path((target, entity_id)): Path<(String, Uuid)>,
+    mut multipart: Multipart,
+) -> AppResult<(StatusCode, Json<MediaObjectResponse>)> {
+    let media = state.media()?;
+    let target = MediaTarget::parse(&target)
+        .ok_or_else(|| AppError::BadRequest(format!("Destino de archivo desconocido: {}", target)))?;
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| AppError::BadRequest(format!("Multipart inválido: {}", e)))?
+    {
+        if field.name() != Some("file") {
+            continue;
+        }
+
+        let filename = field.file_name().map(str::to_string);
+        let content_type = field.content_type().map(str::to_string);
+        let bytes = field
+            .bytes()
+            .await
+            .map_err(|e| AppError::BadRequest(format!("No se pudo leer el archivo: {}", e)))?;
+
+        let response = media
+            .upload(
+                &state.pool,
+                user.company_id,
+                user.user_id,
+                target,
+                entity_id,
+                filename,
+                content_type,
+                bytes.to_vec(),
+            )
+            .await?;
+
+        return Ok((StatusCode::CREATED, Json(response)));
+    }
+
+    Err(AppError::BadRequest("Falta el campo 'file'".to_string()))
+}
+
+/// GET /api/v1/media/:id - Metadatos y URLs firmadas nuevas
+pub async fn get_media(
+    Extension(user): Extension<AuthenticatedUser>,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<MediaObjectResponse>> {
+    let media = state.media()?;
+    Ok(Json(media.get(&state.pool, user.company_id, id).await?))
+}
+
+/// GET /api/media/*key?expires=&signature= - Descargar con URL firmada
+pub async fn download_media(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Query(params): Query<SignedUrlParams>,
+) -> AppResult<impl IntoResponse> {
+    let media = state.media()?;
+    validate_key(&key).map_err(|_| AppError::BadRequest("Clave inválida".to_string()))?;
+
+    if !media.verify_signature(&key, params.expires, &params.signature, Utc::now()) {
+        return Err(AppError::Forbidden("URL de descarga inválida o expirada".to_string()));
+    }
+
+    let bytes = media.download(&key).await?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type_for_key(&key)),
+            (header::CACHE_CONTROL, "private, max-age=3600"),
+        ],
+        bytes,
+    ))
+}
@@ -3,22 +3,96 @@
 //! Este módulo contiene todos los handlers HTTP para la API Web de Colis Privé,
 //! organizados por entidad del negocio.
 
+pub mod address_validation_jobs;
+pub mod analytics;
+pub mod assignments;
+pub mod auth;
 pub mod colis_prive;
 pub mod colis_prive_router;
+pub mod companies;
+pub mod compte_rendus;
+pub mod dispatch;
+pub mod driver_field_data;
+pub mod fuel;
 pub mod geocoding;
 pub mod hybrid;
+pub mod locations;
+pub mod maintenance;
+pub mod media;
+pub mod packages;
+pub mod pickups;
+pub mod relay_points;
+pub mod routers;
+pub mod scans;
+pub mod sync;
+pub mod tournees;
+pub mod users;
+pub mod vehicles;
+pub mod vehicle_damages;
+pub mod vehicle_documents;
 // mobile module removed - using web API only
 
 pub use colis_prive_router::*;
 
 use axum::Router;
+use uuid::Uuid;
+pub(crate) use crate::middleware::auth::driver_scope;
+pub(crate) use crate::utils::validation::parse_date;
+pub(crate) use crate::utils::errors::map_unique_violation;
+use crate::middleware::auth::{auth_middleware, AuthenticatedUser};
+use crate::models::user::UserType;
 use crate::state::AppState;
+use crate::utils::errors::{AppError, AppResult};
 
 /// Crear el router principal de la API
-pub fn create_api_router() -> Router<AppState> {
+pub fn create_api_router(state: AppState) -> Router<AppState> {
     Router::new()
         .nest("/colis-prive", create_colis_prive_router())
         .nest("/api", geocoding::create_geocoding_router())
-        .merge(hybrid::create_router())
+        .nest("/api/v1", create_v1_router(state.clone()))
+        .merge(hybrid::create_router(state.clone()))
+        .merge(media::create_media_download_router())
         // mobile router removed - using web API only
 }
+
+/// Rutas `/api/v1`: todas protegidas por JWT (acotadas a la empresa del
+/// usuario) salvo login, registro y refresh de `/auth`; el canal en vivo
+/// acepta además un token de vida corta en la URL
+fn create_v1_router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .merge(routers::create_companies_router())
+        .merge(routers::create_users_router())
+        .merge(routers::create_vehicles_router())
+        .merge(routers::create_vehicle_damages_router())
+        .merge(routers::create_fuel_router())
+        .merge(routers::create_maintenance_router())
+        .merge(routers::create_assignments_router())
+        .merge(routers::create_tournees_router())
+        .merge(routers::create_packages_router())
+        .merge(routers::create_analytics_router())
+        .merge(routers::create_relay_points_router())
+        .merge(routers::create_pickups_router())
+        .merge(routers::create_sync_router())
+        .merge(routers::create_scans_router())
+        .merge(routers::create_compte_rendus_router())
+        .merge(routers::create_locations_router())
+        .merge(routers::create_dispatch_router())
+        .merge(driver_field_data::create_driver_field_data_router())
+        .merge(address_validation_jobs::create_address_validation_jobs_router())
+        .merge(media::create_media_router(state.config.media.max_upload_bytes))
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), auth_middleware))
+        .merge(routers::create_dispatch_stream_router(state.clone()))
+        .nest("/auth", auth::create_auth_router(state))
+}
+
+/// Exigir que el usuario sea admin de su empresa
+pub(crate) fn require_admin(user: &AuthenticatedUser) -> AppResult<()> {
+    if user.user_type != UserType::Admin {
+        return Err(AppError::Forbidden("Se requieren permisos de administrador".to_string()));
+    }
+    Ok(())
+}
+
+pub(crate) fn parse_uuid(value: &str, field: &str) -> AppResult<Uuid> {
+    Uuid::parse_str(value).map_err(|_| AppError::BadRequest(format!("{} inválido: {}", field, value)))
+}
@@ -1,27 +1,38 @@
 //! Handlers de Packages
-//! 
-//! Este módulo maneja las operaciones CRUD para paquetes.
+//!
+//! Este módulo maneja las operaciones CRUD para paquetes de la empresa del
+//! usuario autenticado. Los choferes solo ven y entregan los paquetes de sus
+//! propias tournées; las altas, cambios y bajas requieren un admin.
 
 use axum::{
     extract::{Path, Query, State},
     http::StatusCode,
+    response::{Html, IntoResponse, Response},
     Json,
 };
-use chrono::Utc;
+use chrono::{NaiveTime, Utc};
+use serde_json::json;
 use sqlx::PgPool;
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
+    api::{driver_scope, map_unique_violation, parse_date, parse_uuid, require_admin},
     models::package::{
-        Package, DeliveryStatus, DeliveryFailureReason, PackageResponse, PackageListResponse,
+        Package, DeliveryStatus, PackageResponse, PackageListResponse,
         CreatePackageRequest, UpdatePackageRequest, PackageFilters,
-        MarkDeliveredRequest, MarkFailedRequest,
+        MarkDeliveredRequest, MarkFailedRequest, FailedDeliveryResponse, PACKAGE_COLUMNS,
     },
+    models::delivery_proof::{ProofChainVerification, ProofSummaryParams, ProofVerification},
+    models::dispatch::DispatchEvent,
+    models::status_history::{StatusTransition, TransitionEntity},
+    services::{delivery_proof, lifecycle, package_delivery},
     utils::errors::{AppError, AppResult},
     middleware::auth::AuthenticatedUser,
 };
 
+const DUPLICATE_TRACKING: &str = "Ya existe un paquete con ese número de seguimiento en la tournée";
+
 /// Obtener todos los paquetes con filtros
 pub async fn get_packages(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
@@ -31,89 +42,54 @@ pub async fn get_packages(
     let limit = filters.limit.unwrap_or(50).min(100);
     let offset = filters.offset.unwrap_or(0);
 
-    let rows = sqlx::query!(
+    let status = filters.delivery_status.as_deref().map(parse_status).transpose()?;
+    let failure_reason = filters.failure_reason.as_deref().map(package_delivery::parse_failure_reason).transpose()?;
+    let tournee_id = filters.tournee_id.as_deref().map(|id| parse_uuid(id, "tournee_id")).transpose()?;
+    let date_from = filters.delivery_date_from.as_deref().map(parse_date).transpose()?;
+    let date_to = filters.delivery_date_to.as_deref().map(parse_date).transpose()?;
+    let created_after = filters.created_after.as_deref().map(parse_date).transpose()?;
+    let created_before = filters.created_before.as_deref().map(parse_date).transpose()?;
+
+    let packages = sqlx::query_as::<_, Package>(&format!(
         r#"
-        SELECT 
-            id, company_id, tournee_id, tracking_number, external_tracking_number,
-            package_origin, external_package_id, delivery_status as "delivery_status: String",
-            delivery_date, delivery_attempts, recipient_name, delivery_address,
-            failure_reason as "failure_reason: String", created_at, updated_at, deleted_at
-        FROM packages 
-        WHERE company_id = $1 
+        SELECT {}
+        FROM packages
+        WHERE company_id = $1
         AND deleted_at IS NULL
+        AND ($2::uuid IS NULL OR tournee_id = $2)
+        AND {}
+        AND ($4::delivery_status IS NULL OR delivery_status = $4)
+        AND ($5::delivery_failure_reason IS NULL OR failure_reason = $5)
+        AND ($6::text IS NULL OR tracking_number = $6)
+        AND ($7::text IS NULL OR external_tracking_number = $7)
+        AND ($8::text IS NULL OR package_origin = $8)
+        AND ($9::date IS NULL OR delivery_date >= $9)
+        AND ($10::date IS NULL OR delivery_date <= $10)
+        AND ($11::date IS NULL OR (created_at AT TIME ZONE 'Europe/Paris')::date >= $11)
+        AND ($12::date IS NULL OR (created_at AT TIME ZONE 'Europe/Paris')::date <= $12)
         ORDER BY created_at DESC
-        LIMIT $2 OFFSET $3
+        LIMIT $13 OFFSET $14
         "#,
-        user.company_id,
-        limit,
-        offset
-    )
+        PACKAGE_COLUMNS, package_delivery::DRIVER_SCOPE_FILTER
+    ))
+    .bind(user.company_id)
+    .bind(tournee_id)
+    .bind(driver_scope(&user))
+    .bind(status)
+    .bind(failure_reason)
+    .bind(filters.tracking_number)
+    .bind(filters.external_tracking_number)
+    .bind(filters.package_origin)
+    .bind(date_from)
+    .bind(date_to)
+    .bind(created_after)
+    .bind(created_before)
+    .bind(limit)
+    .bind(offset)
     .fetch_all(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let packages: Vec<PackageListResponse> = rows
-        .into_iter()
-        .map(|row| {
-            let package = Package {
-                id: row.id,
-                company_id: row.company_id,
-                tournee_id: row.tournee_id,
-                tracking_number: row.tracking_number,
-                external_tracking_number: row.external_tracking_number,
-                package_origin: row.package_origin,
-                external_package_id: row.external_package_id,
-                integration_id: None,
-                package_type: None,
-                package_weight: None,
-                package_dimensions: None,
-                delivery_status: match row.delivery_status.as_str() {
-                    "pending" => DeliveryStatus::Pending,
-                    "intransit" => DeliveryStatus::InTransit,
-                    "outfordelivery" => DeliveryStatus::OutForDelivery,
-                    "delivered" => DeliveryStatus::Delivered,
-                    "failed" => DeliveryStatus::Failed,
-                    "returned" => DeliveryStatus::Returned,
-                    "cancelled" => DeliveryStatus::Cancelled,
-                    _ => DeliveryStatus::Pending,
-                },
-                delivery_date: row.delivery_date,
-                delivery_time: None,
-                delivery_attempts: row.delivery_attempts.expect("delivery_attempts should not be null"),
-                recipient_name: row.recipient_name,
-                recipient_phone: None,
-                delivery_address: row.delivery_address,
-                delivery_instructions: None,
-                failure_reason: row.failure_reason.map(|r| match r.as_str() {
-                    "recipientnothome" => DeliveryFailureReason::RecipientNotHome,
-                    "wrongaddress" => DeliveryFailureReason::WrongAddress,
-                    "packagedamaged" => DeliveryFailureReason::PackageDamaged,
-                    "refuseddelivery" => DeliveryFailureReason::RefusedDelivery,
-                    "securityrestriction" => DeliveryFailureReason::SecurityRestriction,
-                    "weatherconditions" => DeliveryFailureReason::WeatherConditions,
-                    "vehiclebreakdown" => DeliveryFailureReason::VehicleBreakdown,
-                    "driveremergency" => DeliveryFailureReason::DriverEmergency,
-                    _ => DeliveryFailureReason::RecipientNotHome,
-                }),
-                failure_notes: None,
-                reschedule_date: None,
-                delivery_photo: None,
-                signature_required: false,
-                signature_image: None,
-                signature_photo: None,
-                delivery_coordinates: None,
-                delivery_duration_minutes: None,
-                driver_notes: None,
-                package_condition: None,
-                created_at: row.created_at,
-                updated_at: row.updated_at,
-                deleted_at: row.deleted_at,
-            };
-            PackageListResponse::from(package)
-        })
-        .collect();
+    .await?;
 
-    Ok(Json(packages))
+    Ok(Json(packages.into_iter().map(PackageListResponse::from).collect()))
 }
 
 /// Obtener un paquete por ID
@@ -122,67 +98,7 @@ pub async fn get_package(
     State(state): State<crate::state::AppState>,
     Path(id): Path<Uuid>,
 ) -> AppResult<Json<PackageResponse>> {
-    let row = sqlx::query!(
-        r#"
-        SELECT 
-            id, company_id, tournee_id, tracking_number, external_tracking_number,
-            package_origin, external_package_id, integration_id, package_type,
-            package_weight, package_dimensions, delivery_status as "delivery_status: crate::models::package::DeliveryStatus",
-            delivery_date, delivery_time, delivery_attempts, recipient_name,
-            recipient_phone, delivery_address, delivery_instructions,
-            failure_reason as "failure_reason: crate::models::package::DeliveryFailureReason", failure_notes, reschedule_date,
-            delivery_photo, signature_required, signature_image, signature_photo,
-            delivery_duration_minutes, driver_notes, package_condition,
-            created_at, updated_at, deleted_at
-        FROM packages 
-        WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
-        "#,
-        id,
-        user.company_id
-    )
-    .fetch_one(&state.pool)
-    .await
-    .map_err(|e| match e {
-        sqlx::Error::RowNotFound => AppError::NotFound("Paquete no encontrado".to_string()),
-        _ => AppError::Database(e),
-    })?;
-
-    let package = Package {
-        id: row.id,
-        company_id: row.company_id,
-        tournee_id: row.tournee_id,
-        tracking_number: row.tracking_number,
-        external_tracking_number: row.external_tracking_number,
-        package_origin: row.package_origin,
-        external_package_id: row.external_package_id,
-        integration_id: row.integration_id,
-        package_type: row.package_type,
-        package_weight: row.package_weight,
-        package_dimensions: row.package_dimensions,
-        delivery_status: row.delivery_status,
-        delivery_date: row.delivery_date,
-        delivery_time: row.delivery_time,
-        delivery_attempts: row.delivery_attempts.expect("delivery_attempts should not be null"),
-        recipient_name: row.recipient_name,
-        recipient_phone: row.recipient_phone,
-        delivery_address: row.delivery_address,
-        delivery_instructions: row.delivery_instructions,
-        failure_reason: row.failure_reason,
-        failure_notes: row.failure_notes,
-        reschedule_date: row.reschedule_date,
-        delivery_photo: row.delivery_photo,
-        signature_required: row.signature_required.expect("signature_required should not be null"),
-        signature_image: row.signature_image,
-        signature_photo: row.signature_photo,
-        delivery_coordinates: None,
-        delivery_duration_minutes: row.delivery_duration_minutes,
-        driver_notes: row.driver_notes,
-        package_condition: row.package_condition,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-        deleted_at: row.deleted_at,
-    };
-
+    let package = fetch_package(&state.pool, &user, id).await?;
     Ok(Json(PackageResponse::from(package)))
 }
 
@@ -191,289 +107,265 @@ pub async fn create_package(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
     State(state): State<crate::state::AppState>,
     Json(package_data): Json<CreatePackageRequest>,
-) -> AppResult<Json<PackageResponse>> {
-    // Validar datos de entrada
+) -> AppResult<(StatusCode, Json<PackageResponse>)> {
+    require_admin(&user)?;
     package_data.validate()
         .map_err(AppError::Validation)?;
 
-    let tournee_id = Uuid::parse_str(&package_data.tournee_id)
-        .map_err(|_| AppError::BadRequest("ID de tournée inválido".to_string()))?;
+    let tournee_id = parse_uuid(&package_data.tournee_id, "tournee_id")?;
+    let tournee_exists = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM tournees WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL)",
+    )
+    .bind(tournee_id)
+    .bind(user.company_id)
+    .fetch_one(&state.pool)
+    .await?;
+    if !tournee_exists {
+        return Err(AppError::NotFound("Tournée no encontrada".to_string()));
+    }
+    lifecycle::ensure_tournee_unlocked(&state.pool, tournee_id).await?;
 
-    let row = sqlx::query!(
+    let package = sqlx::query_as::<_, Package>(&format!(
         r#"
         INSERT INTO packages (
             company_id, tournee_id, tracking_number, external_tracking_number,
             package_origin, external_package_id, package_type, package_weight,
-            package_dimensions, delivery_status, delivery_attempts,
-            recipient_name, recipient_phone, delivery_address, delivery_instructions,
-            signature_required, created_at, updated_at
-        ) VALUES (
-            $1, $2, $3, $4, $5, $6, $7, $8, $9, ($10::text)::delivery_status, $11, $12, $13, $14, $15, $16, NOW(), NOW()
-        )
-        RETURNING 
-            id, company_id, tournee_id, tracking_number, external_tracking_number,
-            package_origin, external_package_id, integration_id, package_type,
-            package_weight, package_dimensions, delivery_status as "delivery_status: crate::models::package::DeliveryStatus",
-            delivery_date, delivery_time, delivery_attempts, recipient_name,
-            recipient_phone, delivery_address, delivery_instructions,
-            failure_reason as "failure_reason: crate::models::package::DeliveryFailureReason", failure_notes, reschedule_date,
-            delivery_photo, signature_required, signature_image, signature_photo,
-            delivery_duration_minutes, driver_notes, package_condition,
-            created_at, updated_at, deleted_at
+            package_dimensions, recipient_name, recipient_phone, delivery_address,
+            delivery_instructions, signature_required
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+        RETURNING {}
         "#,
-        user.company_id,
-        tournee_id,
-        package_data.tracking_number,
-        package_data.external_tracking_number,
-        package_data.package_origin.unwrap_or_else(|| "manual".to_string()),
-        package_data.external_package_id,
-        package_data.package_type,
-        package_data.package_weight,
-        package_data.package_dimensions,
-        "pending",
-        0, // delivery_attempts = 0
-        package_data.recipient_name,
-        package_data.recipient_phone,
-        package_data.delivery_address,
-        package_data.delivery_instructions,
-        package_data.signature_required.unwrap_or(false)
-    )
+        PACKAGE_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(tournee_id)
+    .bind(&package_data.tracking_number)
+    .bind(&package_data.external_tracking_number)
+    .bind(package_data.package_origin.as_deref().unwrap_or("manual"))
+    .bind(&package_data.external_package_id)
+    .bind(&package_data.package_type)
+    .bind(package_data.package_weight)
+    .bind(&package_data.package_dimensions)
+    .bind(&package_data.recipient_name)
+    .bind(&package_data.recipient_phone)
+    .bind(&package_data.delivery_address)
+    .bind(&package_data.delivery_instructions)
+    .bind(package_data.signature_required.unwrap_or(false))
     .fetch_one(&state.pool)
     .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let package = Package {
-        id: row.id,
-        company_id: row.company_id,
-        tournee_id: row.tournee_id,
-        tracking_number: row.tracking_number,
-        external_tracking_number: row.external_tracking_number,
-        package_origin: row.package_origin,
-        external_package_id: row.external_package_id,
-        integration_id: row.integration_id,
-        package_type: row.package_type,
-        package_weight: row.package_weight,
-        package_dimensions: row.package_dimensions,
-        delivery_status: row.delivery_status,
-        delivery_date: row.delivery_date,
-        delivery_time: row.delivery_time,
-        delivery_attempts: row.delivery_attempts.expect("delivery_attempts should not be null"),
-        recipient_name: row.recipient_name,
-        recipient_phone: row.recipient_phone,
-        delivery_address: row.delivery_address,
-        delivery_instructions: row.delivery_instructions,
-        failure_reason: row.failure_reason,
-        failure_notes: row.failure_notes,
-        reschedule_date: row.reschedule_date,
-        delivery_photo: row.delivery_photo,
-        signature_required: row.signature_required.expect("signature_required should not be null"),
-        signature_image: row.signature_image,
-        signature_photo: row.signature_photo,
-        delivery_coordinates: None,
-        delivery_duration_minutes: row.delivery_duration_minutes,
-        driver_notes: row.driver_notes,
-        package_condition: row.package_condition,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-        deleted_at: row.deleted_at,
-    };
+    .map_err(|e| map_unique_violation(e, DUPLICATE_TRACKING))?;
+
+    Ok((StatusCode::CREATED, Json(PackageResponse::from(package))))
+}
+
+/// Actualizar un paquete existente
+pub async fn update_package(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+    Json(package_data): Json<UpdatePackageRequest>,
+) -> AppResult<Json<PackageResponse>> {
+    require_admin(&user)?;
+    package_data.validate()
+        .map_err(AppError::Validation)?;
+
+    let status = package_data.delivery_status.as_deref().map(parse_status).transpose()?;
+    let failure_reason = package_data.failure_reason.as_deref().map(package_delivery::parse_failure_reason).transpose()?;
+    let delivery_date = package_data.delivery_date.as_deref().map(parse_date).transpose()?;
+    let reschedule_date = package_data.reschedule_date.as_deref().map(parse_date).transpose()?;
+    let delivery_time = package_data
+        .delivery_time
+        .as_deref()
+        .map(|t| {
+            NaiveTime::parse_from_str(t, "%H:%M:%S")
+                .or_else(|_| NaiveTime::parse_from_str(t, "%H:%M"))
+                .map_err(|_| AppError::BadRequest(format!("Hora inválida (HH:MM): {}", t)))
+        })
+        .transpose()?;
+
+    let mut tx = state.pool.begin().await?;
+    let current = package_delivery::lock_package(&mut tx, &user, id).await?;
+    lifecycle::ensure_tournee_unlocked(&mut *tx, current.tournee_id).await?;
+    let status_change = status.filter(|status| *status != current.delivery_status);
+    if let Some(status) = &status_change {
+        lifecycle::ensure_package_transition(&mut tx, &current, status).await?;
+    }
+
+    let package = sqlx::query_as::<_, Package>(&format!(
+        r#"
+        UPDATE packages SET
+            delivery_status = COALESCE($3, delivery_status),
+            delivery_date = COALESCE($4, delivery_date),
+            delivery_time = COALESCE($5, delivery_time),
+            delivery_address = COALESCE($6, delivery_address),
+            recipient_name = COALESCE($7, recipient_name),
+            recipient_phone = COALESCE($8, recipient_phone),
+            delivery_instructions = COALESCE($9, delivery_instructions),
+            failure_reason = COALESCE($10, failure_reason),
+            failure_notes = COALESCE($11, failure_notes),
+            reschedule_date = COALESCE($12, reschedule_date),
+            driver_notes = COALESCE($13, driver_notes),
+            package_condition = COALESCE($14, package_condition),
+            status_changed_at = CASE WHEN $3 IS NULL THEN status_changed_at ELSE NOW() END,
+            notes_changed_at = CASE WHEN $13 IS NULL THEN notes_changed_at ELSE NOW() END,
+            updated_at = NOW()
+        WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
+        RETURNING {}
+        "#,
+        PACKAGE_COLUMNS
+    ))
+    .bind(id)
+    .bind(user.company_id)
+    .bind(&status_change)
+    .bind(delivery_date)
+    .bind(delivery_time)
+    .bind(&package_data.delivery_address)
+    .bind(&package_data.recipient_name)
+    .bind(&package_data.recipient_phone)
+    .bind(&package_data.delivery_instructions)
+    .bind(failure_reason)
+    .bind(&package_data.failure_notes)
+    .bind(reschedule_date)
+    .bind(&package_data.driver_notes)
+    .bind(&package_data.package_condition)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if status_change.is_some() {
+        lifecycle::record_package(
+            &mut tx,
+            &user,
+            package.id,
+            &current.delivery_status,
+            &package.delivery_status,
+            json!({}),
+        )
+        .await?;
+    }
+    // Una entrega manual pasa por la misma exigencia de prueba que /delivered
+    if status_change == Some(DeliveryStatus::Delivered) {
+        package_delivery::record_delivery_proof(&mut tx, &user, &package, None).await?;
+    }
+    tx.commit().await?;
+
+    if status_change.is_some() {
+        state.dispatch.publish(DispatchEvent::package_status(&package)).await;
+    }
 
     Ok(Json(PackageResponse::from(package)))
 }
 
 /// Marcar paquete como entregado
+///
+/// Si se envía `proof`, la prueba de entrega se registra en la misma
+/// transacción; es obligatoria (con firma) para los paquetes con
+/// `signature_required`.
 pub async fn mark_delivered(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
     State(state): State<crate::state::AppState>,
     Path(id): Path<Uuid>,
     Json(delivery_data): Json<MarkDeliveredRequest>,
 ) -> AppResult<Json<PackageResponse>> {
-    // Validar datos de entrada
     delivery_data.validate()
         .map_err(AppError::Validation)?;
 
-    let row = sqlx::query!(
-        r#"
-        UPDATE packages SET
-            delivery_status = 'delivered',
-            delivery_date = CURRENT_DATE,
-            delivery_time = CURRENT_TIME,
-            delivery_photo = $2,
-            signature_image = $3,
-            signature_photo = $4,
-            delivery_duration_minutes = $5,
-            driver_notes = $6,
-            package_condition = $7,
-            updated_at = NOW()
-        WHERE id = $1 AND company_id = $8
-        RETURNING 
-            id, company_id, tournee_id, tracking_number, external_tracking_number,
-            package_origin, external_package_id, integration_id, package_type,
-            package_weight, package_dimensions, delivery_status as "delivery_status: crate::models::package::DeliveryStatus",
-            delivery_date, delivery_time, delivery_attempts, recipient_name,
-            recipient_phone, delivery_address, delivery_instructions,
-            failure_reason as "failure_reason: crate::models::package::DeliveryFailureReason", failure_notes, reschedule_date,
-            delivery_photo, signature_required, signature_image, signature_photo,
-            delivery_duration_minutes, driver_notes, package_condition,
-            created_at, updated_at, deleted_at
-        "#,
-        id,
-        delivery_data.delivery_photo,
-        delivery_data.signature_image,
-        delivery_data.signature_photo,
-        delivery_data.delivery_duration_minutes,
-        delivery_data.driver_notes,
-        delivery_data.package_condition,
-        user.company_id
-    )
-    .fetch_one(&state.pool)
-    .await
-    .map_err(|e| match e {
-        sqlx::Error::RowNotFound => AppError::NotFound("Paquete no encontrado".to_string()),
-        _ => AppError::Database(e),
-    })?;
-
-    let package = Package {
-        id: row.id,
-        company_id: row.company_id,
-        tournee_id: row.tournee_id,
-        tracking_number: row.tracking_number,
-        external_tracking_number: row.external_tracking_number,
-        package_origin: row.package_origin,
-        external_package_id: row.external_package_id,
-        integration_id: row.integration_id,
-        package_type: row.package_type,
-        package_weight: row.package_weight,
-        package_dimensions: row.package_dimensions,
-        delivery_status: row.delivery_status,
-        delivery_date: row.delivery_date,
-        delivery_time: row.delivery_time,
-        delivery_attempts: row.delivery_attempts.expect("delivery_attempts should not be null"),
-        recipient_name: row.recipient_name,
-        recipient_phone: row.recipient_phone,
-        delivery_address: row.delivery_address,
-        delivery_instructions: row.delivery_instructions,
-        failure_reason: None,
-        failure_notes: row.failure_notes,
-        reschedule_date: row.reschedule_date,
-        delivery_photo: row.delivery_photo,
-        signature_required: row.signature_required.expect("signature_required should not be null"),
-        signature_image: row.signature_image,
-        signature_photo: row.signature_photo,
-        delivery_coordinates: None,
-        delivery_duration_minutes: row.delivery_duration_minutes,
-        driver_notes: row.driver_notes,
-        package_condition: row.package_condition,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-        deleted_at: row.deleted_at,
-    };
+    let mut tx = state.pool.begin().await?;
+    let package = package_delivery::deliver_package(&mut tx, &user, id, &delivery_data, Utc::now()).await?;
+    tx.commit().await?;
+
+    state.dispatch.publish(DispatchEvent::package_status(&package)).await;
 
     Ok(Json(PackageResponse::from(package)))
 }
 
 /// Marcar paquete como fallido
+///
+/// El flujo de `services::failed_delivery` decide la siguiente acción
+/// (nueva presentación, aviso de paso, punto relais o devolución) y mueve el
+/// paquete a la tournée correspondiente.
 pub async fn mark_failed(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
     State(state): State<crate::state::AppState>,
     Path(id): Path<Uuid>,
     Json(failure_data): Json<MarkFailedRequest>,
-) -> AppResult<Json<PackageResponse>> {
-    // Validar datos de entrada
+) -> AppResult<Json<FailedDeliveryResponse>> {
     failure_data.validate()
         .map_err(AppError::Validation)?;
 
-    let failure_reason_enum = match failure_data.failure_reason.as_str() {
-        "recipient_not_home" => "recipient_not_home",
-        "wrong_address" => "wrong_address",
-        "package_damaged" => "package_damaged",
-        "refused_delivery" => "refused_delivery",
-        "security_restriction" => "security_restriction",
-        "weather_conditions" => "weather_conditions",
-        "vehicle_breakdown" => "vehicle_breakdown",
-        "driver_emergency" => "driver_emergency",
-        _ => "recipient_not_home",
-    };
+    let mut tx = state.pool.begin().await?;
+    let outcome = package_delivery::fail_package(&mut tx, &user, id, &failure_data, Utc::now(), &state.config.failed_delivery).await?;
+    tx.commit().await?;
 
-    let reschedule_date = if let Some(date_str) = &failure_data.reschedule_date {
-        chrono::NaiveDate::parse_from_str(date_str, "%Y-%m-%d").ok()
-    } else {
-        None
-    };
+    state.dispatch.publish(DispatchEvent::package_status(&outcome.package)).await;
 
-    let row = sqlx::query!(
-        r#"
-        UPDATE packages SET
-            delivery_status = 'failed',
-            failure_reason = ($2::text)::delivery_failure_reason,
-            failure_notes = $3,
-            reschedule_date = $4,
-            driver_notes = $5,
-            delivery_attempts = delivery_attempts + 1,
-            updated_at = NOW()
-        WHERE id = $1 AND company_id = $6
-        RETURNING 
-            id, company_id, tournee_id, tracking_number, external_tracking_number,
-            package_origin, external_package_id, integration_id, package_type,
-            package_weight, package_dimensions, delivery_status as "delivery_status: crate::models::package::DeliveryStatus",
-            delivery_date, delivery_time, delivery_attempts, recipient_name,
-            recipient_phone, delivery_address, delivery_instructions,
-            failure_reason as "failure_reason: crate::models::package::DeliveryFailureReason", failure_notes, reschedule_date,
-            delivery_photo, signature_required, signature_image, signature_photo,
-            delivery_duration_minutes, driver_notes, package_condition,
-            created_at, updated_at, deleted_at
-        "#,
-        id,
-        failure_reason_enum,
-        failure_data.failure_notes,
-        reschedule_date,
-        failure_data.driver_notes,
-        user.company_id
-    )
-    .fetch_one(&state.pool)
-    .await
-    .map_err(|e| match e {
-        sqlx::Error::RowNotFound => AppError::NotFound("Paquete no encontrado".to_string()),
-        _ => AppError::Database(e),
-    })?;
-
-    let package = Package {
-        id: row.id,
-        company_id: row.company_id,
-        tournee_id: row.tournee_id,
-        tracking_number: row.tracking_number,
-        external_tracking_number: row.external_tracking_number,
-        package_origin: row.package_origin,
-        external_package_id: row.external_package_id,
-        integration_id: row.integration_id,
-        package_type: row.package_type,
-        package_weight: row.package_weight,
-        package_dimensions: row.package_dimensions,
-        delivery_status: row.delivery_status,
-        delivery_date: row.delivery_date,
-        delivery_time: row.delivery_time,
-        delivery_attempts: row.delivery_attempts.expect("delivery_attempts should not be null"),
-        recipient_name: row.recipient_name,
-        recipient_phone: row.recipient_phone,
-        delivery_address: row.delivery_address,
-        delivery_instructions: row.delivery_instructions,
-        failure_reason: row.failure_reason,
-        failure_notes: row.failure_notes,
-        reschedule_date: row.reschedule_date,
-        delivery_photo: row.delivery_photo,
-        signature_required: row.signature_required.expect("signature_required should not be null"),
-        signature_image: row.signature_image,
-        signature_photo: row.signature_photo,
-        delivery_coordinates: None,
-        delivery_duration_minutes: row.delivery_duration_minutes,
-        driver_notes: row.driver_notes,
-        package_condition: row.package_condition,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-        deleted_at: row.deleted_at,
+    let (relay_point, relay_distance_meters) = match outcome.relay_point {
+        Some((relay, distance)) => (Some(relay), Some(distance)),
+        None => (None, None),
     };
+    Ok(Json(FailedDeliveryResponse {
+        package: PackageResponse::from(outcome.package),
+        action: outcome.action,
+        next_tournee_id: outcome.next_tournee_id,
+        relay_point,
+        relay_distance_meters,
+    }))
+}
 
-    Ok(Json(PackageResponse::from(package)))
+/// Historial de estados de un paquete
+pub async fn get_package_history(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Vec<StatusTransition>>> {
+    let package = fetch_package(&state.pool, &user, id).await?;
+    let history = lifecycle::history(&state.pool, user.company_id, TransitionEntity::Package, package.id).await?;
+
+    Ok(Json(history))
+}
+
+/// Resumen de la prueba de entrega (`?format=html` para imprimir)
+pub async fn get_delivery_proof(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<ProofSummaryParams>,
+) -> AppResult<Response> {
+    let package = fetch_package(&state.pool, &user, id).await?;
+    let proof = delivery_proof::find_by_package(&state.pool, user.company_id, package.id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("El paquete no tiene prueba de entrega".to_string()))?;
+
+    let summary = delivery_proof::summary(&state.pool, state.media.as_deref(), &package, &proof).await?;
+
+    match params.format.as_deref().unwrap_or("json") {
+        "json" => Ok(Json(summary).into_response()),
+        "html" => Ok(Html(delivery_proof::render_html(&summary)).into_response()),
+        other => Err(AppError::BadRequest(format!("Formato desconocido: {}", other))),
+    }
+}
+
+/// Verificar la integridad de la prueba de entrega de un paquete (admin)
+pub async fn verify_delivery_proof(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<ProofVerification>> {
+    require_admin(&user)?;
+
+    let proof = delivery_proof::find_by_package(&state.pool, user.company_id, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("El paquete no tiene prueba de entrega".to_string()))?;
+
+    Ok(Json(delivery_proof::verify(&state.pool, state.media.as_deref(), &proof).await?))
+}
+
+/// Verificar la cadena completa de pruebas de entrega de la empresa (admin)
+pub async fn verify_delivery_proof_chain(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+) -> AppResult<Json<ProofChainVerification>> {
+    require_admin(&user)?;
+    Ok(Json(delivery_proof::verify_chain(&state.pool, user.company_id).await?))
 }
 
 /// Eliminar un paquete (soft delete)
@@ -482,22 +374,50 @@ pub async fn delete_package(
     State(state): State<crate::state::AppState>,
     Path(id): Path<Uuid>,
 ) -> AppResult<StatusCode> {
-    let result = sqlx::query!(
+    require_admin(&user)?;
+
+    let package = fetch_package(&state.pool, &user, id).await?;
+    lifecycle::ensure_tournee_unlocked(&state.pool, package.tournee_id).await?;
+
+    let result = sqlx::query(
         r#"
-        UPDATE packages 
+        UPDATE packages
         SET deleted_at = NOW(), updated_at = NOW()
         WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
         "#,
-        id,
-        user.company_id
     )
+    .bind(package.id)
+    .bind(user.company_id)
     .execute(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
+    .await?;
 
     if result.rows_affected() == 0 {
         return Err(AppError::NotFound("Paquete no encontrado".to_string()));
     }
 
     Ok(StatusCode::NO_CONTENT)
-}
\ No newline at end of file
+}
+
+/// Obtener un paquete visible para el usuario (los choferes solo los suyos)
+pub async fn fetch_package(pool: &PgPool, user: &AuthenticatedUser, id: Uuid) -> AppResult<Package> {
+    sqlx::query_as::<_, Package>(&format!(
+        r#"
+        SELECT {}
+        FROM packages
+        WHERE id = $1 AND company_id = $2 AND {}
+        AND deleted_at IS NULL
+        "#,
+        PACKAGE_COLUMNS, package_delivery::DRIVER_SCOPE_FILTER
+    ))
+    .bind(id)
+    .bind(user.company_id)
+    .bind(driver_scope(user))
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Paquete no encontrado".to_string()))
+}
+
+fn parse_status(value: &str) -> AppResult<DeliveryStatus> {
+    DeliveryStatus::parse(value)
+        .ok_or_else(|| AppError::BadRequest(format!("Estado de entrega desconocido: {}", value)))
+}
@@ -0,0 +1,306 @@
+//! Handlers de recogidas (collectes)
+//!
+//! La planificación (alta, cambios y cancelación) requiere un admin; el
+//! chofer consulta sus recogidas, las confirma tras leer los bultos o las da
+//! por fallidas (ver `services::pickups`).
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde_json::json;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    api::{driver_scope, map_unique_violation, require_admin, tournees::fetch_tournee},
+    models::dispatch::DispatchEvent,
+    models::pickup::{
+        CreatePickupRequest, FailPickupRequest, Pickup, PickupFilters, PickupStatus, PickupType, UpdatePickupRequest,
+        PICKUP_COLUMNS,
+    },
+    services::{lifecycle, pickups},
+    utils::errors::{AppError, AppResult},
+    middleware::auth::AuthenticatedUser,
+};
+
+const DUPLICATE_REFERENCE: &str = "Ya existe una recogida con esa referencia en la tournée";
+
+fn ensure_window(start: Option<chrono::DateTime<chrono::Utc>>, end: Option<chrono::DateTime<chrono::Utc>>) -> AppResult<()> {
+    match (start, end) {
+        (Some(start), Some(end)) if start >= end => Err(AppError::BadRequest(
+            "La franja horaria debe empezar antes de terminar".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn ensure_coordinates(latitude: Option<f64>, longitude: Option<f64>) -> AppResult<()> {
+    if latitude.is_some() != longitude.is_some() {
+        return Err(AppError::BadRequest("Latitud y longitud van juntas".to_string()));
+    }
+    Ok(())
+}
+
+/// Comprobar que los bultos previstos caben en el vehículo de la tournée
+async fn ensure_fits_vehicle(state: &crate::state::AppState, tournee_id: Uuid, expected_parcels: i32) -> AppResult<()> {
+    let capacity = sqlx::query_scalar::<_, Option<i32>>(
+        "SELECT v.parcel_capacity FROM tournees t JOIN vehicles v ON v.id = t.vehicle_id WHERE t.id = $1",
+    )
+    .bind(tournee_id)
+    .fetch_optional(&state.pool)
+    .await?
+    .flatten();
+
+    match capacity {
+        Some(capacity) if expected_parcels > capacity => Err(AppError::BadRequest(format!(
+            "La recogida ({} bultos) no cabe en el vehículo de la tournée ({} bultos)",
+            expected_parcels, capacity
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Listar recogidas (`?tournee_id=&date=&pickup_status=`)
+pub async fn get_pickups(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Query(filters): Query<PickupFilters>,
+) -> AppResult<Json<Vec<Pickup>>> {
+    if let Some(status) = filters.pickup_status.as_deref() {
+        PickupStatus::parse(status)
+            .ok_or_else(|| AppError::BadRequest(format!("Estado de recogida desconocido: {}", status)))?;
+    }
+
+    let pickups = sqlx::query_as::<_, Pickup>(&format!(
+        r#"
+        SELECT {}
+        FROM pickups
+        WHERE company_id = $1 AND deleted_at IS NULL
+        AND tournee_id IN (
+            SELECT id FROM tournees
+            WHERE company_id = $1 AND ($2::uuid IS NULL OR driver_id = $2)
+            AND ($3::uuid IS NULL OR id = $3)
+            AND ($4::date IS NULL OR tournee_date = $4)
+        )
+        AND ($5::text IS NULL OR pickup_status = $5)
+        ORDER BY window_start NULLS LAST, created_at
+        "#,
+        PICKUP_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(driver_scope(&user))
+    .bind(filters.tournee_id)
+    .bind(filters.date)
+    .bind(&filters.pickup_status)
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(pickups))
+}
+
+/// Obtener una recogida
+pub async fn get_pickup(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Pickup>> {
+    let mut conn = state.pool.acquire().await?;
+    Ok(Json(pickups::lock(&mut conn, &user, id).await?))
+}
+
+/// Planificar una recogida en una tournée abierta
+pub async fn create_pickup(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Json(pickup_data): Json<CreatePickupRequest>,
+) -> AppResult<(StatusCode, Json<Pickup>)> {
+    require_admin(&user)?;
+    pickup_data.validate()
+        .map_err(AppError::Validation)?;
+    ensure_window(pickup_data.window_start, pickup_data.window_end)?;
+    ensure_coordinates(pickup_data.latitude, pickup_data.longitude)?;
+
+    let tournee = fetch_tournee(&state.pool, &user, pickup_data.tournee_id).await?;
+    if !lifecycle::accepts_planning(&tournee.tournee_status) {
+        return Err(AppError::Conflict(format!(
+            "La tournée está {}: no admite nuevas recogidas",
+            tournee.tournee_status.as_str()
+        )));
+    }
+    lifecycle::ensure_tournee_unlocked(&state.pool, tournee.id).await?;
+    ensure_fits_vehicle(&state, tournee.id, pickup_data.expected_parcels).await?;
+
+    let pickup = sqlx::query_as::<_, Pickup>(&format!(
+        r#"
+        INSERT INTO pickups (
+            company_id, tournee_id, pickup_reference, pickup_type, contact_name,
+            contact_phone, pickup_address, latitude, longitude, expected_parcels,
+            window_start, window_end, pickup_instructions
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+        RETURNING {}
+        "#,
+        PICKUP_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(tournee.id)
+    .bind(&pickup_data.pickup_reference)
+    .bind(pickup_data.pickup_type.unwrap_or(PickupType::Merchant).as_str())
+    .bind(&pickup_data.contact_name)
+    .bind(&pickup_data.contact_phone)
+    .bind(&pickup_data.pickup_address)
+    .bind(pickup_data.latitude)
+    .bind(pickup_data.longitude)
+    .bind(pickup_data.expected_parcels)
+    .bind(pickup_data.window_start)
+    .bind(pickup_data.window_end)
+    .bind(&pickup_data.pickup_instructions)
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| map_unique_violation(e, DUPLICATE_REFERENCE))?;
+
+    log::info!(
+        "📥 Recogida {} ({} bultos) planificada en la tournée {}",
+        pickup.pickup_reference,
+        pickup.expected_parcels,
+        tournee.id
+    );
+    Ok((StatusCode::CREATED, Json(pickup)))
+}
+
+/// Actualizar una recogida pendiente
+pub async fn update_pickup(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+    Json(pickup_data): Json<UpdatePickupRequest>,
+) -> AppResult<Json<Pickup>> {
+    require_admin(&user)?;
+    pickup_data.validate()
+        .map_err(AppError::Validation)?;
+    ensure_coordinates(pickup_data.latitude, pickup_data.longitude)?;
+
+    let mut tx = state.pool.begin().await?;
+    let current = pickups::lock(&mut tx, &user, id).await?;
+    if current.pickup_status != PickupStatus::Pending.as_str() {
+        return Err(AppError::Conflict(format!("La recogida ya está {}", current.pickup_status)));
+    }
+    lifecycle::ensure_tournee_unlocked(&mut *tx, current.tournee_id).await?;
+    ensure_window(
+        pickup_data.window_start.or(current.window_start),
+        pickup_data.window_end.or(current.window_end),
+    )?;
+    if let Some(expected_parcels) = pickup_data.expected_parcels {
+        ensure_fits_vehicle(&state, current.tournee_id, expected_parcels).await?;
+    }
+
+    let pickup = sqlx::query_as::<_, Pickup>(&format!(
+        r#"
+        UPDATE pickups SET
+            contact_name = COALESCE($2, contact_name),
+            contact_phone = COALESCE($3, contact_phone),
+            pickup_address = COALESCE($4, pickup_address),
+            latitude = COALESCE($5, latitude),
+            longitude = COALESCE($6, longitude),
+            expected_parcels = COALESCE($7, expected_parcels),
+            window_start = COALESCE($8, window_start),
+            window_end = COALESCE($9, window_end),
+            pickup_instructions = COALESCE($10, pickup_instructions),
+            updated_at = NOW()
+        WHERE id = $1
+        RETURNING {}
+        "#,
+        PICKUP_COLUMNS
+    ))
+    .bind(current.id)
+    .bind(&pickup_data.contact_name)
+    .bind(&pickup_data.contact_phone)
+    .bind(&pickup_data.pickup_address)
+    .bind(pickup_data.latitude)
+    .bind(pickup_data.longitude)
+    .bind(pickup_data.expected_parcels)
+    .bind(pickup_data.window_start)
+    .bind(pickup_data.window_end)
+    .bind(&pickup_data.pickup_instructions)
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(Json(pickup))
+}
+
+/// Cancelar una recogida pendiente (se conserva para el compte-rendu)
+pub async fn cancel_pickup(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<StatusCode> {
+    require_admin(&user)?;
+
+    let mut tx = state.pool.begin().await?;
+    let current = pickups::lock(&mut tx, &user, id).await?;
+    if current.pickup_status != PickupStatus::Pending.as_str() {
+        return Err(AppError::Conflict(format!("La recogida ya está {}", current.pickup_status)));
+    }
+    lifecycle::ensure_tournee_unlocked(&mut *tx, current.tournee_id).await?;
+
+    sqlx::query("UPDATE pickups SET pickup_status = 'cancelled', updated_at = NOW() WHERE id = $1")
+        .bind(current.id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Confirmar una recogida con los bultos leídos
+pub async fn confirm_pickup(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Pickup>> {
+    let mut tx = state.pool.begin().await?;
+    let pickup = pickups::confirm(&mut tx, &user, id).await?;
+    tx.commit().await?;
+
+    if pickup.pickup_status == PickupStatus::Partial.as_str() {
+        state.dispatch.publish(DispatchEvent::alert(
+            pickup.company_id,
+            Some(pickup.tournee_id),
+            "pickup_partial",
+            format!(
+                "Recogida {} incompleta: {}/{} bultos",
+                pickup.pickup_reference, pickup.collected_parcels, pickup.expected_parcels
+            ),
+            json!({ "pickup_id": pickup.id }),
+        )).await;
+    }
+
+    Ok(Json(pickup))
+}
+
+/// Dar una recogida por fallida
+pub async fn fail_pickup(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+    Json(failure_data): Json<FailPickupRequest>,
+) -> AppResult<Json<Pickup>> {
+    failure_data.validate()
+        .map_err(AppError::Validation)?;
+
+    let mut tx = state.pool.begin().await?;
+    let pickup = pickups::fail(&mut tx, &user, id, &failure_data.failure_notes).await?;
+    tx.commit().await?;
+
+    state.dispatch.publish(DispatchEvent::alert(
+        pickup.company_id,
+        Some(pickup.tournee_id),
+        "pickup_failed",
+        format!("Recogida {} fallida", pickup.pickup_reference),
+        json!({ "pickup_id": pickup.id, "failure_notes": pickup.failure_notes }),
+    )).await;
+
+    Ok(Json(pickup))
+}
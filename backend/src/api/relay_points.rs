@@ -0,0 +1,152 @@
+//! Handlers de Relay Points
+//!
+//! Puntos relais de la empresa del usuario autenticado, usados por el flujo de
+//! entregas fallidas. Consultar está abierto a los choferes; las altas,
+//! cambios y bajas requieren un admin.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    api::{map_unique_violation, require_admin},
+    models::relay_point::{
+        CreateRelayPointRequest, RelayPoint, RelayPointWithLoad, UpdateRelayPointRequest,
+        RELAY_POINT_COLUMNS,
+    },
+    utils::errors::{AppError, AppResult},
+    middleware::auth::AuthenticatedUser,
+};
+
+const DUPLICATE_CODE: &str = "Ya existe un punto relais con ese código";
+
+/// Listar los puntos relais con su ocupación actual
+pub async fn get_relay_points(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+) -> AppResult<Json<Vec<RelayPointWithLoad>>> {
+    let relay_points = sqlx::query_as::<_, RelayPointWithLoad>(&format!(
+        r#"
+        SELECT {},
+            (
+                SELECT COUNT(*) FROM packages p
+                WHERE p.relay_point_id = relay_points.id
+                AND p.deleted_at IS NULL
+                AND p.delivery_status NOT IN ('delivered', 'returned', 'cancelled')
+            ) AS current_load
+        FROM relay_points
+        WHERE company_id = $1
+        ORDER BY is_active DESC, name
+        "#,
+        RELAY_POINT_COLUMNS
+    ))
+    .bind(user.company_id)
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(relay_points))
+}
+
+/// Dar de alta un punto relais
+pub async fn create_relay_point(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Json(relay_data): Json<CreateRelayPointRequest>,
+) -> AppResult<(StatusCode, Json<RelayPoint>)> {
+    require_admin(&user)?;
+    relay_data.validate()
+        .map_err(AppError::Validation)?;
+
+    let relay_point = sqlx::query_as::<_, RelayPoint>(&format!(
+        r#"
+        INSERT INTO relay_points (
+            company_id, name, external_code, address, latitude, longitude, capacity, opening_hours
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING {}
+        "#,
+        RELAY_POINT_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(&relay_data.name)
+    .bind(&relay_data.external_code)
+    .bind(&relay_data.address)
+    .bind(relay_data.latitude)
+    .bind(relay_data.longitude)
+    .bind(relay_data.capacity)
+    .bind(&relay_data.opening_hours)
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| map_unique_violation(e, DUPLICATE_CODE))?;
+
+    Ok((StatusCode::CREATED, Json(relay_point)))
+}
+
+/// Actualizar un punto relais
+pub async fn update_relay_point(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+    Json(relay_data): Json<UpdateRelayPointRequest>,
+) -> AppResult<Json<RelayPoint>> {
+    require_admin(&user)?;
+    relay_data.validate()
+        .map_err(AppError::Validation)?;
+
+    let relay_point = sqlx::query_as::<_, RelayPoint>(&format!(
+        r#"
+        UPDATE relay_points SET
+            name = COALESCE($3, name),
+            address = COALESCE($4, address),
+            latitude = COALESCE($5, latitude),
+            longitude = COALESCE($6, longitude),
+            capacity = COALESCE($7, capacity),
+            opening_hours = COALESCE($8, opening_hours),
+            is_active = COALESCE($9, is_active),
+            updated_at = NOW()
+        WHERE id = $1 AND company_id = $2
+        RETURNING {}
+        "#,
+        RELAY_POINT_COLUMNS
+    ))
+    .bind(id)
+    .bind(user.company_id)
+    .bind(&relay_data.name)
+    .bind(&relay_data.address)
+    .bind(relay_data.latitude)
+    .bind(relay_data.longitude)
+    .bind(relay_data.capacity)
+    .bind(&relay_data.opening_hours)
+    .bind(relay_data.is_active)
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Punto relais no encontrado".to_string()))?;
+
+    Ok(Json(relay_point))
+}
+
+/// Desactivar un punto relais (los paquetes ya depositados lo conservan)
+pub async fn delete_relay_point(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<StatusCode> {
+    require_admin(&user)?;
+
+    let result = sqlx::query(
+        "UPDATE relay_points SET is_active = FALSE, updated_at = NOW() WHERE id = $1 AND company_id = $2",
+    )
+    .bind(id)
+    .bind(user.company_id)
+    .execute(&state.pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("Punto relais no encontrado".to_string()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
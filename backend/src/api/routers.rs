@@ -1,32 +1,232 @@
-use axum::Router;
+//! Routers CRUD de la API v1
+//!
+//! Rutas relativas a `/api/v1`; el middleware JWT se aplica en
+//! `create_v1_router` (salvo el canal en vivo, que trae el suyo).
+
+use axum::{
+    routing::{get, post, put},
+    Router,
+};
+use crate::api::{analytics, assignments, companies, compte_rendus, dispatch, fuel, locations, maintenance, packages, pickups, relay_points, scans, sync, tournees, users, vehicle_damages, vehicle_documents, vehicles};
+use crate::middleware::auth::stream_auth_middleware;
 use crate::state::AppState;
 
 /// Crear el router de companies
 pub fn create_companies_router() -> Router<AppState> {
     Router::new()
+        .route("/companies", get(companies::get_companies).post(companies::create_company))
+        .route(
+            "/companies/:id",
+            get(companies::get_company)
+                .put(companies::update_company)
+                .delete(companies::delete_company),
+        )
 }
 
 /// Crear el router de users
 pub fn create_users_router() -> Router<AppState> {
     Router::new()
+        .route("/users", get(users::get_users).post(users::create_user))
+        .route(
+            "/users/:id",
+            get(users::get_user).put(users::update_user).delete(users::delete_user),
+        )
 }
 
 /// Crear el router de vehicles
 pub fn create_vehicles_router() -> Router<AppState> {
     Router::new()
+        .route("/vehicles", get(vehicles::get_vehicles).post(vehicles::create_vehicle))
+        .route(
+            "/vehicles/:id",
+            get(vehicles::get_vehicle)
+                .put(vehicles::update_vehicle)
+                .delete(vehicles::delete_vehicle),
+        )
+        .route(
+            "/vehicles/:id/documents",
+            get(vehicle_documents::get_vehicle_documents).post(vehicle_documents::create_vehicle_document),
+        )
+        .route(
+            "/vehicles/:id/documents/:document_id/renew",
+            post(vehicle_documents::renew_vehicle_document),
+        )
+        .route("/vehicle-documents", get(vehicle_documents::get_company_documents))
+}
+
+/// Crear el router de daños de vehículo
+pub fn create_vehicle_damages_router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/vehicle-damages",
+            get(vehicle_damages::get_damages).post(vehicle_damages::declare_damage),
+        )
+        .route(
+            "/vehicle-damages/:id",
+            get(vehicle_damages::get_damage)
+                .put(vehicle_damages::update_damage)
+                .delete(vehicle_damages::delete_damage),
+        )
+        .route("/vehicle-damages/:id/status", post(vehicle_damages::transition_damage))
+        .route("/vehicle-damages/:id/history", get(vehicle_damages::get_damage_history))
+        .route("/users/:id/damage-liability", get(vehicle_damages::get_driver_liability))
+}
+
+/// Crear el router del cuadre de combustible
+pub fn create_fuel_router() -> Router<AppState> {
+    Router::new()
+        .route("/fuel-cards/import", post(fuel::import_fuel_card_csv))
+        .route("/fuel-cards/transactions", get(fuel::get_fuel_transactions))
+        .route("/fuel/reconciliations", get(fuel::get_fuel_reconciliations))
+        .route(
+            "/tournees/:id/reconciliation",
+            get(fuel::get_tournee_reconciliation).post(fuel::reconcile_tournee),
+        )
+}
+
+/// Crear el router del mantenimiento preventivo
+pub fn create_maintenance_router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/maintenance-plans",
+            get(maintenance::get_maintenance_plans).post(maintenance::create_maintenance_plan),
+        )
+        .route(
+            "/maintenance-plans/:id",
+            put(maintenance::update_maintenance_plan).delete(maintenance::delete_maintenance_plan),
+        )
+        .route("/maintenance", get(maintenance::get_maintenance))
+        .route("/maintenance/due", get(maintenance::get_maintenance_due))
+        .route("/maintenance/:id/status", post(maintenance::transition_maintenance))
+        .route("/vehicles/:id/maintenance-forecast", get(maintenance::get_vehicle_forecast))
+        .route(
+            "/vehicles/:id/maintenance",
+            get(maintenance::get_vehicle_maintenance).post(maintenance::book_maintenance),
+        )
+}
+
+/// Crear el router del planificador de asignación de vehículos
+pub fn create_assignments_router() -> Router<AppState> {
+    Router::new()
+        .route("/assignments", get(assignments::get_assignments))
+        .route("/assignments/proposal", get(assignments::get_proposal))
+        .route("/assignments/accept", post(assignments::accept_assignments))
 }
 
 /// Crear el router de tournees
 pub fn create_tournees_router() -> Router<AppState> {
     Router::new()
+        .route("/tournees", get(tournees::get_tournees).post(tournees::create_tournee))
+        .route(
+            "/tournees/:id",
+            get(tournees::get_tournee)
+                .put(tournees::update_tournee)
+                .delete(tournees::delete_tournee),
+        )
+        .route("/tournees/:id/start", post(tournees::start_tournee))
+        .route("/tournees/:id/end", post(tournees::end_tournee))
+        .route("/tournees/:id/pause", post(tournees::pause_tournee))
+        .route("/tournees/:id/resume", post(tournees::resume_tournee))
+        .route("/tournees/:id/cancel", post(tournees::cancel_tournee))
+        .route("/tournees/:id/history", get(tournees::get_tournee_history))
 }
 
 /// Crear el router de packages
 pub fn create_packages_router() -> Router<AppState> {
     Router::new()
+        .route("/packages", get(packages::get_packages).post(packages::create_package))
+        .route(
+            "/packages/:id",
+            get(packages::get_package)
+                .put(packages::update_package)
+                .delete(packages::delete_package),
+        )
+        .route("/packages/:id/delivered", post(packages::mark_delivered))
+        .route("/packages/:id/failed", post(packages::mark_failed))
+        .route("/packages/:id/history", get(packages::get_package_history))
+        .route("/packages/:id/proof", get(packages::get_delivery_proof))
+        .route("/packages/:id/proof/verify", get(packages::verify_delivery_proof))
+        .route("/delivery-proofs/verify", get(packages::verify_delivery_proof_chain))
+}
+
+/// Crear el router de relay points
+pub fn create_relay_points_router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/relay-points",
+            get(relay_points::get_relay_points).post(relay_points::create_relay_point),
+        )
+        .route(
+            "/relay-points/:id",
+            put(relay_points::update_relay_point).delete(relay_points::delete_relay_point),
+        )
+}
+
+/// Crear el router de recogidas
+pub fn create_pickups_router() -> Router<AppState> {
+    Router::new()
+        .route("/pickups", get(pickups::get_pickups).post(pickups::create_pickup))
+        .route(
+            "/pickups/:id",
+            get(pickups::get_pickup).put(pickups::update_pickup).delete(pickups::cancel_pickup),
+        )
+        .route("/pickups/:id/confirm", post(pickups::confirm_pickup))
+        .route("/pickups/:id/failed", post(pickups::fail_pickup))
+}
+
+/// Crear el router de sincronización offline
+pub fn create_sync_router() -> Router<AppState> {
+    Router::new().route("/sync", post(sync::sync))
+}
+
+/// Crear el router de lecturas de código de barras
+pub fn create_scans_router() -> Router<AppState> {
+    Router::new()
+        .route("/scans", post(scans::create_scan))
+        .route("/scans/unassigned", get(scans::get_unassigned_scans))
+        .route("/tournees/:id/scans", get(scans::get_tournee_scans))
+        .route("/tournees/:id/scan-report", get(scans::get_tournee_scan_report))
+}
+
+/// Crear el router del compte-rendu de fin de día
+pub fn create_compte_rendus_router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/tournees/:id/compte-rendu",
+            get(compte_rendus::get_compte_rendu).post(compte_rendus::generate_compte_rendu),
+        )
+        .route("/tournees/:id/compte-rendu/sign", post(compte_rendus::sign_compte_rendu))
+}
+
+/// Crear el router de posiciones GPS
+pub fn create_locations_router() -> Router<AppState> {
+    Router::new()
+        .route("/locations", post(locations::ingest_locations))
+        .route("/users/:id/track", get(locations::get_driver_track))
+        .route("/tournees/:id/stop-visits", get(locations::get_tournee_stop_visits))
+        .route("/tournees/:id/eta", get(locations::get_tournee_eta))
+}
+
+/// Crear el router del token del canal en vivo (detrás del JWT de sesión)
+pub fn create_dispatch_router() -> Router<AppState> {
+    Router::new().route("/dispatch/stream-token", post(dispatch::create_stream_token))
+}
+
+/// Crear el router del canal de seguimiento en vivo, con su propia
+/// autenticación: `EventSource` no envía el header `Authorization`
+pub fn create_dispatch_stream_router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/dispatch/stream", get(dispatch::stream_dispatch))
+        .route_layer(axum::middleware::from_fn_with_state(state, stream_auth_middleware))
 }
 
 /// Crear el router de analytics
 pub fn create_analytics_router() -> Router<AppState> {
     Router::new()
+        .route("/analytics/dashboard", get(analytics::get_dashboard_summary))
+        .route("/analytics/tournees", get(analytics::get_performance_by_tournee))
+        .route("/analytics/drivers", get(analytics::get_driver_performance))
+        .route("/analytics/vehicles", get(analytics::get_vehicle_performance))
+        .route("/analytics/weekly", get(analytics::get_weekly_performance))
+        .route("/analytics/weekly/recompute", post(analytics::recompute_weekly_performance))
 }
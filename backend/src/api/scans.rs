@@ -0,0 +1,116 @@
+//! Handlers de lecturas de código de barras
+//!
+//! Registro de lecturas desde la app, consulta de las lecturas e informe de
+//! validación de una tournée y de las lecturas sin tournée (ver
+//! `services::scan_events`).
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde_json::json;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    api::{require_admin, tournees::fetch_tournee},
+    models::dispatch::DispatchEvent,
+    models::scan_event::{ScanEvent, ScanEventFilters, ScanReport, ScanRequest, ScanType, ScanValidation},
+    services::scan_events,
+    utils::errors::{AppError, AppResult},
+    middleware::auth::AuthenticatedUser,
+};
+
+/// Registrar una lectura
+pub async fn create_scan(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Json(scan_data): Json<ScanRequest>,
+) -> AppResult<(StatusCode, Json<ScanEvent>)> {
+    scan_data.validate()
+        .map_err(AppError::Validation)?;
+
+    // Una lectura de recogida también actualiza los bultos recogidos
+    let mut tx = state.pool.begin().await?;
+    let event = scan_events::record(&mut tx, &user, &scan_data).await?;
+    tx.commit().await?;
+
+    if event.validation_status != ScanValidation::Valid.as_str() {
+        state.dispatch.publish(DispatchEvent::alert(
+            event.company_id,
+            event.tournee_id,
+            "scan_flagged",
+            format!("Lectura {} marcada: {}", event.barcode, event.validation_status),
+            json!({ "scan_event_id": event.id, "scan_type": event.scan_type, "package_id": event.package_id }),
+        )).await;
+    }
+
+    Ok((StatusCode::CREATED, Json(event)))
+}
+
+/// Lecturas de una tournée (`?flagged=true` para ver solo las marcadas)
+pub async fn get_tournee_scans(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+    Query(filters): Query<ScanEventFilters>,
+) -> AppResult<Json<Vec<ScanEvent>>> {
+    let tournee = fetch_tournee(&state.pool, &user, id).await?;
+    let scan_type = parse_scan_type(&filters)?;
+
+    let events = scan_events::list_for_tournee(
+        &state.pool,
+        user.company_id,
+        tournee.id,
+        scan_type,
+        filters.flagged.unwrap_or(false),
+    )
+    .await?;
+
+    Ok(Json(events))
+}
+
+/// Lecturas sin tournée de la empresa (admin): códigos desconocidos leídos
+/// en el depósito o sin tournée del día
+pub async fn get_unassigned_scans(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Query(filters): Query<ScanEventFilters>,
+) -> AppResult<Json<Vec<ScanEvent>>> {
+    require_admin(&user)?;
+    let scan_type = parse_scan_type(&filters)?;
+
+    let events = scan_events::list_unassigned(
+        &state.pool,
+        user.company_id,
+        scan_type,
+        filters.flagged.unwrap_or(false),
+    )
+    .await?;
+
+    Ok(Json(events))
+}
+
+fn parse_scan_type(filters: &ScanEventFilters) -> AppResult<Option<ScanType>> {
+    filters
+        .scan_type
+        .as_deref()
+        .map(|s| {
+            ScanType::parse(s).ok_or_else(|| AppError::BadRequest(format!("Tipo de lectura desconocido: {}", s)))
+        })
+        .transpose()
+}
+
+/// Informe de lecturas: paquetes sin cargar, cargados sin lectura de entrega y
+/// lecturas marcadas
+pub async fn get_tournee_scan_report(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<ScanReport>> {
+    let tournee = fetch_tournee(&state.pool, &user, id).await?;
+    let report = scan_events::tournee_report(&state.pool, user.company_id, tournee.id).await?;
+
+    Ok(Json(report))
+}
@@ -0,0 +1,51 @@
+//! Handler de sincronización offline
+//!
+//! La app envía en un solo lote las mutaciones acumuladas sin cobertura y
+//! recibe el resultado de cada una y los cambios del servidor desde su último
+//! cursor (ver `services::offline_sync`).
+
+use axum::{extract::State, Json};
+use chrono::Utc;
+use validator::Validate;
+
+use crate::{
+    models::dispatch::DispatchEvent,
+    models::sync::{SyncOutcome, SyncRequest, SyncResponse},
+    services::offline_sync,
+    utils::errors::{AppError, AppResult},
+    middleware::auth::AuthenticatedUser,
+};
+
+/// Sincronizar: aplicar las mutaciones en orden y devolver el delta
+pub async fn sync(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Json(sync_data): Json<SyncRequest>,
+) -> AppResult<Json<SyncResponse>> {
+    sync_data.validate()
+        .map_err(AppError::Validation)?;
+
+    let started_at = Utc::now();
+    let results = offline_sync::apply_batch(
+        &state.pool,
+        &user,
+        sync_data.device_id.as_deref(),
+        &sync_data.mutations,
+        &state.config.failed_delivery,
+    )
+    .await?;
+
+    let changed = offline_sync::applied_packages(&state.pool, user.company_id, &results).await?;
+    state.dispatch.publish_all(changed.iter().map(DispatchEvent::package_status)).await;
+
+    let (delta, cursor) =
+        offline_sync::delta(&state.pool, &user, sync_data.cursor.as_deref(), started_at).await?;
+
+    Ok(Json(SyncResponse {
+        conflicts: results.iter().filter(|r| r.outcome == SyncOutcome::Conflict).count(),
+        results,
+        delta,
+        cursor,
+        server_time: started_at,
+    }))
+}
@@ -1,6 +1,8 @@
 //! Handlers de Tournees
-//! 
-//! Este módulo maneja las operaciones CRUD para tournées.
+//!
+//! Este módulo maneja las operaciones CRUD para tournées de la empresa del
+//! usuario autenticado. Los choferes solo ven y operan sus propias tournées;
+//! la planificación (alta, cambios y bajas) requiere un admin.
 
 use axum::{
     extract::{Path, Query, State},
@@ -8,20 +10,27 @@ use axum::{
     Json,
 };
 use chrono::Utc;
+use serde_json::json;
 use sqlx::PgPool;
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
+    api::{driver_scope, fuel, map_unique_violation, parse_date, parse_uuid, require_admin},
     models::tournee::{
         Tournee, TourneeStatus, TourneeResponse, TourneeListResponse,
         CreateTourneeRequest, UpdateTourneeRequest, TourneeFilters,
-        StartTourneeRequest, EndTourneeRequest,
+        StartTourneeRequest, EndTourneeRequest, TOURNEE_COLUMNS,
     },
+    models::dispatch::DispatchEvent,
+    models::status_history::{StatusTransition, TourneeTransitionRequest, TransitionEntity},
+    services::{failed_delivery, fuel_reconciliation, lifecycle, maintenance, vehicle_documents},
     utils::errors::{AppError, AppResult},
     middleware::auth::AuthenticatedUser,
 };
 
+const DUPLICATE_TOURNEE: &str = "El chofer ya tiene una tournée ese día";
+
 /// Obtener todas las tournées con filtros
 pub async fn get_tournees(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
@@ -31,77 +40,58 @@ pub async fn get_tournees(
     let limit = filters.limit.unwrap_or(50).min(100);
     let offset = filters.offset.unwrap_or(0);
 
-    let rows = sqlx::query!(
+    let status = filters
+        .tournee_status
+        .as_deref()
+        .map(|s| {
+            TourneeStatus::parse(s)
+                .ok_or_else(|| AppError::BadRequest(format!("Estado de tournée desconocido: {}", s)))
+        })
+        .transpose()?;
+    let driver_id = match driver_scope(&user) {
+        Some(own_id) => Some(own_id),
+        None => filters.driver_id.as_deref().map(|id| parse_uuid(id, "driver_id")).transpose()?,
+    };
+    let vehicle_id = filters.vehicle_id.as_deref().map(|id| parse_uuid(id, "vehicle_id")).transpose()?;
+    let date_from = filters.tournee_date_from.as_deref().map(parse_date).transpose()?;
+    let date_to = filters.tournee_date_to.as_deref().map(parse_date).transpose()?;
+    let created_after = filters.created_after.as_deref().map(parse_date).transpose()?;
+    let created_before = filters.created_before.as_deref().map(parse_date).transpose()?;
+
+    let tournees = sqlx::query_as::<_, Tournee>(&format!(
         r#"
-        SELECT 
-            id, company_id, driver_id, vehicle_id, tournee_date, tournee_number,
-            start_location, end_location, tournee_status as "tournee_status: String",
-            start_time, end_time, start_mileage, end_mileage, total_distance,
-            fuel_consumed, fuel_cost, tournee_origin, external_tournee_id,
-            created_at, updated_at, deleted_at
-        FROM tournees 
-        WHERE company_id = $1 
+        SELECT {}
+        FROM tournees
+        WHERE company_id = $1
         AND deleted_at IS NULL
-        ORDER BY created_at DESC
-        LIMIT $2 OFFSET $3
+        AND ($2::tournee_status IS NULL OR tournee_status = $2)
+        AND ($3::uuid IS NULL OR driver_id = $3)
+        AND ($4::uuid IS NULL OR vehicle_id = $4)
+        AND ($5::date IS NULL OR tournee_date >= $5)
+        AND ($6::date IS NULL OR tournee_date <= $6)
+        AND ($7::text IS NULL OR tournee_origin = $7)
+        AND ($8::date IS NULL OR (created_at AT TIME ZONE 'Europe/Paris')::date >= $8)
+        AND ($9::date IS NULL OR (created_at AT TIME ZONE 'Europe/Paris')::date <= $9)
+        ORDER BY tournee_date DESC, created_at DESC
+        LIMIT $10 OFFSET $11
         "#,
-        user.company_id,
-        limit,
-        offset
-    )
+        TOURNEE_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(status)
+    .bind(driver_id)
+    .bind(vehicle_id)
+    .bind(date_from)
+    .bind(date_to)
+    .bind(filters.tournee_origin)
+    .bind(created_after)
+    .bind(created_before)
+    .bind(limit)
+    .bind(offset)
     .fetch_all(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let tournees: Vec<TourneeListResponse> = rows
-        .into_iter()
-        .map(|row| {
-            let tournee = Tournee {
-                id: row.id,
-                company_id: row.company_id,
-                driver_id: row.driver_id,
-                vehicle_id: row.vehicle_id,
-                tournee_date: row.tournee_date,
-                tournee_number: row.tournee_number,
-                start_location: row.start_location,
-                end_location: row.end_location,
-                tournee_status: match row.tournee_status.as_str() {
-                    "pending" => TourneeStatus::Pending,
-                    "in_progress" => TourneeStatus::InProgress,
-                    "completed" => TourneeStatus::Completed,
-                    "cancelled" => TourneeStatus::Cancelled,
-                    "paused" => TourneeStatus::Paused,
-                    _ => TourneeStatus::Pending,
-                },
-                start_time: row.start_time,
-                end_time: row.end_time,
-                start_mileage: row.start_mileage,
-                end_mileage: row.end_mileage,
-                total_distance: row.total_distance,
-                fuel_consumed: row.fuel_consumed,
-                fuel_cost: row.fuel_cost,
-                pre_inspection_notes: None,
-                post_inspection_notes: None,
-                pre_inspection_photos: None,
-                post_inspection_photos: None,
-                route_optimization_score: None,
-                estimated_duration_minutes: None,
-                actual_duration_minutes: None,
-                route_coordinates: None,
-                traffic_conditions: None,
-                weather_conditions: None,
-                tournee_origin: row.tournee_origin,
-                external_tournee_id: row.external_tournee_id,
-                integration_id: None,
-                created_at: row.created_at,
-                updated_at: row.updated_at,
-                deleted_at: row.deleted_at,
-            };
-            TourneeListResponse::from(tournee)
-        })
-        .collect();
+    .await?;
 
-    Ok(Json(tournees))
+    Ok(Json(tournees.into_iter().map(TourneeListResponse::from).collect()))
 }
 
 /// Obtener una tournée por ID
@@ -110,71 +100,7 @@ pub async fn get_tournee(
     State(state): State<crate::state::AppState>,
     Path(id): Path<Uuid>,
 ) -> AppResult<Json<TourneeResponse>> {
-    let row = sqlx::query!(
-        r#"
-        SELECT 
-            id, company_id, driver_id, vehicle_id, tournee_date, tournee_number,
-            start_location, end_location, tournee_status as "tournee_status: String",
-            start_time, end_time, start_mileage, end_mileage, total_distance,
-            fuel_consumed, fuel_cost, pre_inspection_notes, post_inspection_notes,
-            route_optimization_score, estimated_duration_minutes, actual_duration_minutes,
-            tournee_origin, external_tournee_id, integration_id,
-            created_at, updated_at, deleted_at
-        FROM tournees 
-        WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
-        "#,
-        id,
-        user.company_id
-    )
-    .fetch_one(&state.pool)
-    .await
-    .map_err(|e| match e {
-        sqlx::Error::RowNotFound => AppError::NotFound("Tournée no encontrada".to_string()),
-        _ => AppError::Database(e),
-    })?;
-
-    let tournee = Tournee {
-        id: row.id,
-        company_id: row.company_id,
-        driver_id: row.driver_id,
-        vehicle_id: row.vehicle_id,
-        tournee_date: row.tournee_date,
-        tournee_number: row.tournee_number,
-        start_location: row.start_location,
-        end_location: row.end_location,
-        tournee_status: match row.tournee_status.as_str() {
-            "pending" => TourneeStatus::Pending,
-            "in_progress" => TourneeStatus::InProgress,
-            "completed" => TourneeStatus::Completed,
-            "cancelled" => TourneeStatus::Cancelled,
-            "paused" => TourneeStatus::Paused,
-            _ => TourneeStatus::Pending,
-        },
-        start_time: row.start_time,
-        end_time: row.end_time,
-        start_mileage: row.start_mileage,
-        end_mileage: row.end_mileage,
-        total_distance: row.total_distance,
-        fuel_consumed: row.fuel_consumed,
-        fuel_cost: row.fuel_cost,
-        pre_inspection_notes: row.pre_inspection_notes,
-        post_inspection_notes: row.post_inspection_notes,
-        pre_inspection_photos: None,
-        post_inspection_photos: None,
-        route_optimization_score: row.route_optimization_score,
-        estimated_duration_minutes: row.estimated_duration_minutes,
-        actual_duration_minutes: row.actual_duration_minutes,
-        route_coordinates: None,
-        traffic_conditions: None,
-        weather_conditions: None,
-        tournee_origin: row.tournee_origin,
-        external_tournee_id: row.external_tournee_id,
-        integration_id: row.integration_id,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-        deleted_at: row.deleted_at,
-    };
-
+    let tournee = fetch_tournee(&state.pool, &user, id).await?;
     Ok(Json(TourneeResponse::from(tournee)))
 }
 
@@ -183,291 +109,338 @@ pub async fn create_tournee(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
     State(state): State<crate::state::AppState>,
     Json(tournee_data): Json<CreateTourneeRequest>,
-) -> AppResult<Json<TourneeResponse>> {
-    // Validar datos de entrada
+) -> AppResult<(StatusCode, Json<TourneeResponse>)> {
+    require_admin(&user)?;
     tournee_data.validate()
         .map_err(AppError::Validation)?;
 
-    let driver_id = Uuid::parse_str(&tournee_data.driver_id)
-        .map_err(|_| AppError::BadRequest("ID de conductor inválido".to_string()))?;
-    let vehicle_id = Uuid::parse_str(&tournee_data.vehicle_id)
-        .map_err(|_| AppError::BadRequest("ID de vehículo inválido".to_string()))?;
+    let driver_id = parse_uuid(&tournee_data.driver_id, "driver_id")?;
+    let vehicle_id = parse_uuid(&tournee_data.vehicle_id, "vehicle_id")?;
+    let tournee_date = tournee_data.tournee_date.unwrap_or_else(|| Utc::now().date_naive());
+    ensure_assignable(&state.pool, user.company_id, Some(driver_id), Some(vehicle_id)).await?;
+    vehicle_documents::ensure_roadworthy(&state.pool, vehicle_id, tournee_date).await?;
+    maintenance::ensure_available(&state.pool, vehicle_id, tournee_date).await?;
 
-    let row = sqlx::query!(
+    let mut tx = state.pool.begin().await?;
+
+    let tournee = sqlx::query_as::<_, Tournee>(&format!(
         r#"
         INSERT INTO tournees (
             company_id, driver_id, vehicle_id, tournee_date, tournee_number,
-            start_location, end_location, tournee_status, start_mileage,
-            estimated_duration_minutes, tournee_origin, external_tournee_id,
-            created_at, updated_at
-        ) VALUES (
-            $1, $2, $3, CURRENT_DATE, $4, $5, $6, ($7::text)::tournee_status, $8, $9, $10, $11, NOW(), NOW()
-        )
-        RETURNING 
-            id, company_id, driver_id, vehicle_id, tournee_date, tournee_number,
-            start_location, end_location, tournee_status as "tournee_status: crate::models::tournee::TourneeStatus",
-            start_time, end_time, start_mileage, end_mileage, total_distance,
-            fuel_consumed, fuel_cost, pre_inspection_notes, post_inspection_notes,
-            route_optimization_score, estimated_duration_minutes, actual_duration_minutes,
-            tournee_origin, external_tournee_id, integration_id,
-            created_at, updated_at, deleted_at
+            start_location, end_location, start_mileage, estimated_duration_minutes,
+            tournee_origin, external_tournee_id
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        RETURNING {}
         "#,
-        user.company_id,
-        driver_id,
-        vehicle_id,
-        tournee_data.tournee_number,
-        tournee_data.start_location,
-        tournee_data.end_location,
-        "pending",
-        tournee_data.start_mileage,
-        tournee_data.estimated_duration_minutes,
-        tournee_data.tournee_origin.unwrap_or_else(|| "manual".to_string()),
-        tournee_data.external_tournee_id
-    )
-    .fetch_one(&state.pool)
+        TOURNEE_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(driver_id)
+    .bind(vehicle_id)
+    .bind(tournee_date)
+    .bind(&tournee_data.tournee_number)
+    .bind(&tournee_data.start_location)
+    .bind(&tournee_data.end_location)
+    .bind(tournee_data.start_mileage)
+    .bind(tournee_data.estimated_duration_minutes)
+    .bind(tournee_data.tournee_origin.as_deref().unwrap_or("manual"))
+    .bind(&tournee_data.external_tournee_id)
+    .fetch_one(&mut *tx)
     .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let tournee = Tournee {
-        id: row.id,
-        company_id: row.company_id,
-        driver_id: row.driver_id,
-        vehicle_id: row.vehicle_id,
-        tournee_date: row.tournee_date,
-        tournee_number: row.tournee_number,
-        start_location: row.start_location,
-        end_location: row.end_location,
-        tournee_status: row.tournee_status,
-        start_time: row.start_time,
-        end_time: row.end_time,
-        start_mileage: row.start_mileage,
-        end_mileage: row.end_mileage,
-        total_distance: row.total_distance,
-        fuel_consumed: row.fuel_consumed,
-        fuel_cost: row.fuel_cost,
-        pre_inspection_notes: row.pre_inspection_notes,
-        post_inspection_notes: row.post_inspection_notes,
-        pre_inspection_photos: None,
-        post_inspection_photos: None,
-        route_optimization_score: row.route_optimization_score,
-        estimated_duration_minutes: row.estimated_duration_minutes,
-        actual_duration_minutes: row.actual_duration_minutes,
-        route_coordinates: None,
-        traffic_conditions: None,
-        weather_conditions: None,
-        tournee_origin: row.tournee_origin,
-        external_tournee_id: row.external_tournee_id,
-        integration_id: row.integration_id,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-        deleted_at: row.deleted_at,
-    };
+    .map_err(|e| map_unique_violation(e, DUPLICATE_TOURNEE))?;
 
-    Ok(Json(TourneeResponse::from(tournee)))
+    // Paquetes reprogramados para este día que esperaban tournée
+    failed_delivery::adopt_rescheduled(&mut tx, &tournee).await?;
+    tx.commit().await?;
+
+    log::info!("🗺️ Tournée {} creada para el chofer {}", tournee.id, driver_id);
+    Ok((StatusCode::CREATED, Json(TourneeResponse::from(tournee))))
 }
 
-/// Iniciar una tournée
-pub async fn start_tournee(
+/// Actualizar una tournée (planificación y datos operativos)
+pub async fn update_tournee(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
     State(state): State<crate::state::AppState>,
     Path(id): Path<Uuid>,
-    Json(start_data): Json<StartTourneeRequest>,
+    Json(tournee_data): Json<UpdateTourneeRequest>,
 ) -> AppResult<Json<TourneeResponse>> {
-    // Validar datos de entrada
-    start_data.validate()
+    require_admin(&user)?;
+    tournee_data.validate()
         .map_err(AppError::Validation)?;
 
-    let row = sqlx::query!(
+    let driver_id = tournee_data.driver_id.as_deref().map(|id| parse_uuid(id, "driver_id")).transpose()?;
+    let vehicle_id = tournee_data.vehicle_id.as_deref().map(|id| parse_uuid(id, "vehicle_id")).transpose()?;
+    ensure_assignable(&state.pool, user.company_id, driver_id, vehicle_id).await?;
+    let current = fetch_tournee(&state.pool, &user, id).await?;
+    lifecycle::ensure_tournee_unlocked(&state.pool, current.id).await?;
+    if vehicle_id.is_some() || tournee_data.tournee_date.is_some() {
+        let vehicle_id = vehicle_id.unwrap_or(current.vehicle_id);
+        let tournee_date = tournee_data.tournee_date.unwrap_or(current.tournee_date);
+        vehicle_documents::ensure_roadworthy(&state.pool, vehicle_id, tournee_date).await?;
+        maintenance::ensure_available(&state.pool, vehicle_id, tournee_date).await?;
+    }
+
+    let tournee = sqlx::query_as::<_, Tournee>(&format!(
         r#"
         UPDATE tournees SET
-            tournee_status = 'in_progress',
-            start_time = NOW(),
-            start_mileage = $2,
-            pre_inspection_notes = $3,
+            driver_id = COALESCE($3, driver_id),
+            vehicle_id = COALESCE($4, vehicle_id),
+            tournee_date = COALESCE($5, tournee_date),
+            tournee_number = COALESCE($6, tournee_number),
+            estimated_duration_minutes = COALESCE($7, estimated_duration_minutes),
+            start_location = COALESCE($8, start_location),
+            end_location = COALESCE($9, end_location),
+            start_mileage = COALESCE($10, start_mileage),
+            end_mileage = COALESCE($11, end_mileage),
+            fuel_consumed = COALESCE($12, fuel_consumed),
+            fuel_cost = COALESCE($13, fuel_cost),
+            pre_inspection_notes = COALESCE($14, pre_inspection_notes),
+            post_inspection_notes = COALESCE($15, post_inspection_notes),
+            route_optimization_score = COALESCE($16, route_optimization_score),
+            actual_duration_minutes = COALESCE($17, actual_duration_minutes),
             updated_at = NOW()
-        WHERE id = $1 AND company_id = $4 AND tournee_status = 'pending'
-        RETURNING 
-            id, company_id, driver_id, vehicle_id, tournee_date, tournee_number,
-            start_location, end_location, tournee_status as "tournee_status: String",
-            start_time, end_time, start_mileage, end_mileage, total_distance,
-            fuel_consumed, fuel_cost, pre_inspection_notes, post_inspection_notes,
-            route_optimization_score, estimated_duration_minutes, actual_duration_minutes,
-            tournee_origin, external_tournee_id, integration_id,
-            created_at, updated_at, deleted_at
+        WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
+        RETURNING {}
         "#,
-        id,
-        start_data.start_mileage,
-        start_data.pre_inspection_notes,
-        user.company_id
-    )
-    .fetch_one(&state.pool)
+        TOURNEE_COLUMNS
+    ))
+    .bind(id)
+    .bind(user.company_id)
+    .bind(driver_id)
+    .bind(vehicle_id)
+    .bind(tournee_data.tournee_date)
+    .bind(&tournee_data.tournee_number)
+    .bind(tournee_data.estimated_duration_minutes)
+    .bind(&tournee_data.start_location)
+    .bind(&tournee_data.end_location)
+    .bind(tournee_data.start_mileage)
+    .bind(tournee_data.end_mileage)
+    .bind(tournee_data.fuel_consumed)
+    .bind(tournee_data.fuel_cost)
+    .bind(&tournee_data.pre_inspection_notes)
+    .bind(&tournee_data.post_inspection_notes)
+    .bind(tournee_data.route_optimization_score)
+    .bind(tournee_data.actual_duration_minutes)
+    .fetch_optional(&state.pool)
     .await
-    .map_err(|e| match e {
-        sqlx::Error::RowNotFound => AppError::NotFound("Tournée no encontrada o ya iniciada".to_string()),
-        _ => AppError::Database(e),
-    })?;
-
-    let tournee = Tournee {
-        id: row.id,
-        company_id: row.company_id,
-        driver_id: row.driver_id,
-        vehicle_id: row.vehicle_id,
-        tournee_date: row.tournee_date,
-        tournee_number: row.tournee_number,
-        start_location: row.start_location,
-        end_location: row.end_location,
-        tournee_status: TourneeStatus::InProgress,
-        start_time: row.start_time,
-        end_time: row.end_time,
-        start_mileage: row.start_mileage,
-        end_mileage: row.end_mileage,
-        total_distance: row.total_distance,
-        fuel_consumed: row.fuel_consumed,
-        fuel_cost: row.fuel_cost,
-        pre_inspection_notes: row.pre_inspection_notes,
-        post_inspection_notes: row.post_inspection_notes,
-        pre_inspection_photos: None,
-        post_inspection_photos: None,
-        route_optimization_score: row.route_optimization_score,
-        estimated_duration_minutes: row.estimated_duration_minutes,
-        actual_duration_minutes: row.actual_duration_minutes,
-        route_coordinates: None,
-        traffic_conditions: None,
-        weather_conditions: None,
-        tournee_origin: row.tournee_origin,
-        external_tournee_id: row.external_tournee_id,
-        integration_id: row.integration_id,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-        deleted_at: row.deleted_at,
-    };
+    .map_err(|e| map_unique_violation(e, DUPLICATE_TOURNEE))?
+    .ok_or_else(|| AppError::NotFound("Tournée no encontrada".to_string()))?;
+
+    Ok(Json(TourneeResponse::from(tournee)))
+}
+
+/// Iniciar una tournée
+pub async fn start_tournee(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+    Json(start_data): Json<StartTourneeRequest>,
+) -> AppResult<Json<TourneeResponse>> {
+    start_data.validate()
+        .map_err(AppError::Validation)?;
+
+    let mut tx = state.pool.begin().await?;
+    let tournee = lifecycle::start_tournee(&mut tx, &user, id, &start_data).await?;
+    tx.commit().await?;
+
+    state.dispatch.publish(DispatchEvent::tournee_status(&tournee)).await;
 
     Ok(Json(TourneeResponse::from(tournee)))
 }
 
-/// Finalizar una tournée
+/// Pausar una tournée en curso
+pub async fn pause_tournee(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+    Json(pause_data): Json<TourneeTransitionRequest>,
+) -> AppResult<Json<TourneeResponse>> {
+    pause_data.validate()
+        .map_err(AppError::Validation)?;
+
+    let mut tx = state.pool.begin().await?;
+    let tournee = lifecycle::pause_tournee(&mut tx, &user, id, pause_data.reason.as_deref()).await?;
+    tx.commit().await?;
+
+    state.dispatch.publish(DispatchEvent::tournee_status(&tournee)).await;
+
+    Ok(Json(TourneeResponse::from(tournee)))
+}
+
+/// Reanudar una tournée pausada
+pub async fn resume_tournee(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<TourneeResponse>> {
+    let mut tx = state.pool.begin().await?;
+    let tournee = lifecycle::resume_tournee(&mut tx, &user, id).await?;
+    tx.commit().await?;
+
+    state.dispatch.publish(DispatchEvent::tournee_status(&tournee)).await;
+
+    Ok(Json(TourneeResponse::from(tournee)))
+}
+
+/// Finalizar una tournée (`force` solo para admins: reprograma los paquetes
+/// sin resolver)
 pub async fn end_tournee(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
     State(state): State<crate::state::AppState>,
     Path(id): Path<Uuid>,
     Json(end_data): Json<EndTourneeRequest>,
 ) -> AppResult<Json<TourneeResponse>> {
-    // Validar datos de entrada
     end_data.validate()
         .map_err(AppError::Validation)?;
 
-    // Calcular total_distance y actual_duration_minutes
-    let total_distance = if let Some(start_mileage) = sqlx::query_scalar!(
-        "SELECT start_mileage FROM tournees WHERE id = $1",
-        id
-    )
-    .fetch_optional(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?
-    .flatten()
-    {
-        Some(end_data.end_mileage - start_mileage)
-    } else {
-        None
-    };
+    let mut tx = state.pool.begin().await?;
+    let tournee = lifecycle::end_tournee(&mut tx, &user, id, &end_data).await?;
+    tx.commit().await?;
 
-    let row = sqlx::query!(
-        r#"
-        UPDATE tournees SET
-            tournee_status = 'completed',
-            end_time = NOW(),
-            end_mileage = $2,
-            total_distance = $3,
-            fuel_consumed = $4,
-            fuel_cost = $5,
-            post_inspection_notes = $6,
-            actual_duration_minutes = EXTRACT(EPOCH FROM (NOW() - start_time)) / 60,
-            updated_at = NOW()
-        WHERE id = $1 AND company_id = $7 AND tournee_status = 'in_progress'
-        RETURNING 
-            id, company_id, driver_id, vehicle_id, tournee_date, tournee_number,
-            start_location, end_location, tournee_status as "tournee_status: String",
-            start_time, end_time, start_mileage, end_mileage, total_distance,
-            fuel_consumed, fuel_cost, pre_inspection_notes, post_inspection_notes,
-            route_optimization_score, estimated_duration_minutes, actual_duration_minutes,
-            tournee_origin, external_tournee_id, integration_id,
-            created_at, updated_at, deleted_at
-        "#,
-        id,
-        end_data.end_mileage,
-        total_distance,
-        end_data.fuel_consumed,
-        end_data.fuel_cost,
-        end_data.post_inspection_notes,
-        user.company_id
-    )
-    .fetch_one(&state.pool)
-    .await
-    .map_err(|e| match e {
-        sqlx::Error::RowNotFound => AppError::NotFound("Tournée no encontrada o no está en progreso".to_string()),
-        _ => AppError::Database(e),
-    })?;
-
-    let tournee = Tournee {
-        id: row.id,
-        company_id: row.company_id,
-        driver_id: row.driver_id,
-        vehicle_id: row.vehicle_id,
-        tournee_date: row.tournee_date,
-        tournee_number: row.tournee_number,
-        start_location: row.start_location,
-        end_location: row.end_location,
-        tournee_status: TourneeStatus::Completed,
-        start_time: row.start_time,
-        end_time: row.end_time,
-        start_mileage: row.start_mileage,
-        end_mileage: row.end_mileage,
-        total_distance: row.total_distance,
-        fuel_consumed: row.fuel_consumed,
-        fuel_cost: row.fuel_cost,
-        pre_inspection_notes: row.pre_inspection_notes,
-        post_inspection_notes: row.post_inspection_notes,
-        pre_inspection_photos: None,
-        post_inspection_photos: None,
-        route_optimization_score: row.route_optimization_score,
-        estimated_duration_minutes: row.estimated_duration_minutes,
-        actual_duration_minutes: row.actual_duration_minutes,
-        route_coordinates: None,
-        traffic_conditions: None,
-        weather_conditions: None,
-        tournee_origin: row.tournee_origin,
-        external_tournee_id: row.external_tournee_id,
-        integration_id: row.integration_id,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-        deleted_at: row.deleted_at,
-    };
+    state.dispatch.publish(DispatchEvent::tournee_status(&tournee)).await;
+    // El cierre no depende del cuadre: si falla se recalcula a mano
+    match fuel_reconciliation::reconcile_closed_tournee(&state.pool, tournee.company_id, tournee.id, &state.config.fuel).await {
+        Ok((reconciliation, true)) => fuel::publish_alert(&state, &reconciliation).await,
+        Ok(_) => {}
+        Err(e) => log::error!("❌ Error cuadrando el combustible de la tournée {}: {}", tournee.id, e),
+    }
+    if end_data.force.unwrap_or(false) {
+        state.dispatch.publish(DispatchEvent::alert(
+            tournee.company_id,
+            Some(tournee.id),
+            "tournee_forced_end",
+            "Tournée finalizada con paquetes sin resolver".to_string(),
+            json!({ "reason": end_data.reason }),
+        )).await;
+    }
+
+    Ok(Json(TourneeResponse::from(tournee)))
+}
+
+/// Cancelar una tournée (los paquetes abiertos se reprograman)
+pub async fn cancel_tournee(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+    Json(cancel_data): Json<TourneeTransitionRequest>,
+) -> AppResult<Json<TourneeResponse>> {
+    require_admin(&user)?;
+    cancel_data.validate()
+        .map_err(AppError::Validation)?;
+
+    let mut tx = state.pool.begin().await?;
+    let tournee = lifecycle::cancel_tournee(&mut tx, &user, id, cancel_data.reason.as_deref()).await?;
+    tx.commit().await?;
+
+    state.dispatch.publish(DispatchEvent::tournee_status(&tournee)).await;
 
     Ok(Json(TourneeResponse::from(tournee)))
 }
 
+/// Historial de estados de una tournée
+pub async fn get_tournee_history(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Vec<StatusTransition>>> {
+    let tournee = fetch_tournee(&state.pool, &user, id).await?;
+    let history = lifecycle::history(&state.pool, user.company_id, TransitionEntity::Tournee, tournee.id).await?;
+
+    Ok(Json(history))
+}
+
 /// Eliminar una tournée (soft delete)
 pub async fn delete_tournee(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
     State(state): State<crate::state::AppState>,
     Path(id): Path<Uuid>,
 ) -> AppResult<StatusCode> {
-    let result = sqlx::query!(
+    require_admin(&user)?;
+    let tournee = fetch_tournee(&state.pool, &user, id).await?;
+    lifecycle::ensure_tournee_unlocked(&state.pool, tournee.id).await?;
+
+    let result = sqlx::query(
         r#"
-        UPDATE tournees 
+        UPDATE tournees
         SET deleted_at = NOW(), updated_at = NOW()
         WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
         "#,
-        id,
-        user.company_id
     )
+    .bind(id)
+    .bind(user.company_id)
     .execute(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
+    .await?;
 
     if result.rows_affected() == 0 {
         return Err(AppError::NotFound("Tournée no encontrada".to_string()));
     }
 
     Ok(StatusCode::NO_CONTENT)
-}
\ No newline at end of file
+}
+
+/// Obtener una tournée visible para el usuario (los choferes solo las suyas)
+pub async fn fetch_tournee(pool: &PgPool, user: &AuthenticatedUser, id: Uuid) -> AppResult<Tournee> {
+    sqlx::query_as::<_, Tournee>(&format!(
+        r#"
+        SELECT {}
+        FROM tournees
+        WHERE id = $1 AND company_id = $2 AND ($3::uuid IS NULL OR driver_id = $3)
+        AND deleted_at IS NULL
+        "#,
+        TOURNEE_COLUMNS
+    ))
+    .bind(id)
+    .bind(user.company_id)
+    .bind(driver_scope(user))
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Tournée no encontrada".to_string()))
+}
+
+/// Comprobar que el chofer y el vehículo son de la empresa y están operativos
+async fn ensure_assignable(
+    pool: &PgPool,
+    company_id: Uuid,
+    driver_id: Option<Uuid>,
+    vehicle_id: Option<Uuid>,
+) -> AppResult<()> {
+    if let Some(driver_id) = driver_id {
+        let driver_ok = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM users
+                WHERE id = $1 AND company_id = $2 AND user_type = 'driver'
+                AND user_status = 'active' AND deleted_at IS NULL
+            )
+            "#,
+        )
+        .bind(driver_id)
+        .bind(company_id)
+        .fetch_one(pool)
+        .await?;
+        if !driver_ok {
+            return Err(AppError::BadRequest("El chofer no existe o no está activo".to_string()));
+        }
+    }
+
+    if let Some(vehicle_id) = vehicle_id {
+        let vehicle_ok = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM vehicles
+                WHERE id = $1 AND company_id = $2 AND vehicle_status = 'active'
+                AND deleted_at IS NULL
+            )
+            "#,
+        )
+        .bind(vehicle_id)
+        .bind(company_id)
+        .fetch_one(pool)
+        .await?;
+        if !vehicle_ok {
+            return Err(AppError::BadRequest("El vehículo no existe o no está operativo".to_string()));
+        }
+    }
+
+    Ok(())
+}
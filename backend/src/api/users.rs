@@ -1,6 +1,8 @@
 //! Handlers de usuarios
-//! 
-//! Este módulo maneja las operaciones CRUD para usuarios.
+//!
+//! Este módulo maneja las operaciones CRUD para usuarios de la empresa del
+//! usuario autenticado. Los admins gestionan a todos los usuarios; un chofer
+//! solo puede consultar su propia ficha.
 
 use axum::{
     extract::{Extension, Path, State},
@@ -8,116 +10,96 @@ use axum::{
     Json,
 };
 use bcrypt::{hash, DEFAULT_COST};
-use sqlx::PgPool;
+use sqlx::PgConnection;
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
-    models::user::{User, UserResponse, CreateUserRequest, UserType, UserStatus},
+    api::{map_unique_violation, require_admin},
+    models::user::{User, UserResponse, CreateUserRequest, UpdateUserRequest, UserType, USER_COLUMNS},
+    services::assignment_planner::normalize_license,
     utils::errors::{AppError, AppResult},
     middleware::auth::AuthenticatedUser,
 };
 
-/// Handler para listar usuarios
+const DUPLICATE_USER: &str = "Ya existe un usuario con ese username o email en la empresa";
+
+/// Handler para listar usuarios (solo admins)
 pub async fn get_users(
     Extension(user): Extension<AuthenticatedUser>,
     State(state): State<crate::state::AppState>,
 ) -> AppResult<Json<Vec<UserResponse>>> {
-    let rows = sqlx::query!(
+    require_admin(&user)?;
+
+    let users = sqlx::query_as::<_, User>(&format!(
         r#"
-        SELECT 
-            id, company_id, user_type as "user_type: String", user_status as "user_status: String", 
-            username, password_hash, created_at, updated_at, deleted_at
-        FROM users 
-        WHERE company_id = $1 
+        SELECT {}
+        FROM users
+        WHERE company_id = $1
         AND deleted_at IS NULL
         ORDER BY created_at DESC
         LIMIT 50
         "#,
-        user.company_id
-    )
+        USER_COLUMNS
+    ))
+    .bind(user.company_id)
     .fetch_all(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let users: Vec<UserResponse> = rows
-        .into_iter()
-        .map(|row| {
-            UserResponse {
-                id: row.id,
-                company_id: row.company_id,
-                user_type: match row.user_type.as_str() {
-                    "admin" => UserType::Admin,
-                    "driver" => UserType::Driver,
-                    _ => UserType::Driver,
-                },
-                user_status: match row.user_status.as_str() {
-                    "active" => UserStatus::Active,
-                    "inactive" => UserStatus::Inactive,
-                    "suspended" => UserStatus::Suspended,
-                    _ => UserStatus::Active,
-                },
-                username: row.username,
-                email: None,
-                created_at: row.created_at.unwrap_or_else(|| chrono::Utc::now()),
-                updated_at: row.updated_at.unwrap_or_else(|| chrono::Utc::now()),
-            }
-        })
-        .collect();
-
-    Ok(Json(users))
+    .await?;
+
+    Ok(Json(users.into_iter().map(UserResponse::from).collect()))
 }
 
-/// Handler para crear usuario
+/// Handler para crear usuario (solo admins)
 pub async fn create_user(
     Extension(user): Extension<AuthenticatedUser>,
     State(state): State<crate::state::AppState>,
     Json(user_data): Json<CreateUserRequest>,
-) -> AppResult<Json<UserResponse>> {
-    // Validar datos de entrada
+) -> AppResult<(StatusCode, Json<UserResponse>)> {
+    require_admin(&user)?;
     user_data.validate()
         .map_err(AppError::Validation)?;
 
-    // Hash del password
+    let license_categories = check_license_categories(user_data.license_categories.as_deref())?;
+    check_preferred_vehicle(&state, user.company_id, user_data.preferred_vehicle_id).await?;
+
     let password_hash = hash(&user_data.password, DEFAULT_COST)
         .map_err(|e| AppError::Hash(format!("Error hasheando password: {}", e)))?;
 
-    // Crear usuario
-    let row = sqlx::query!(
+    let mut tx = state.pool.begin().await?;
+
+    // Respetar el límite de choferes de la suscripción
+    if user_data.user_type == UserType::Driver {
+        check_driver_capacity(&mut tx, user.company_id, None).await?;
+    }
+
+    let new_user = sqlx::query_as::<_, User>(&format!(
         r#"
         INSERT INTO users (
-            company_id, user_type, user_status, username, 
-            password_hash, created_at, updated_at
-        ) VALUES ($1, ($2::text)::user_type, ($3::text)::user_status, $4, $5, NOW(), NOW())
-        RETURNING 
-            id, company_id, user_type as "user_type: crate::models::user::UserType", user_status as "user_status: crate::models::user::UserStatus", 
-            username, password_hash, created_at, updated_at, deleted_at
+            company_id, user_type, user_status, username, password_hash,
+            full_name, email, phone, tournee_number, license_categories,
+            preferred_vehicle_id
+        ) VALUES ($1, $2, 'active', $3, $4, $5, $6, $7, $8, COALESCE($9, ARRAY['B']::varchar[]), $10)
+        RETURNING {}
         "#,
-        user.company_id,
-        match user_data.user_type {
-            UserType::Admin => "admin",
-            UserType::Driver => "driver",
-        },
-        "active",
-        user_data.username,
-        password_hash
-    )
-    .fetch_one(&state.pool)
+        USER_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(&user_data.user_type)
+    .bind(&user_data.username)
+    .bind(password_hash)
+    .bind(&user_data.full_name)
+    .bind(&user_data.email)
+    .bind(&user_data.phone)
+    .bind(&user_data.tournee_number)
+    .bind(license_categories)
+    .bind(user_data.preferred_vehicle_id)
+    .fetch_one(&mut *tx)
     .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let new_user_response = UserResponse {
-        id: row.id,
-        company_id: row.company_id,
-        user_type: row.user_type,
-        user_status: row.user_status,
-        username: row.username,
-        email: None,
-        created_at: row.created_at.unwrap_or_else(|| chrono::Utc::now()),
-        updated_at: row.updated_at.unwrap_or_else(|| chrono::Utc::now()),
-    };
+    .map_err(|e| map_unique_violation(e, DUPLICATE_USER))?;
 
-    Ok(Json(new_user_response))
+    tx.commit().await?;
+    log::info!("👤 Usuario '{}' creado en la empresa {}", new_user.username, user.company_id);
+    Ok((StatusCode::CREATED, Json(UserResponse::from(new_user))))
 }
 
 /// Handler para obtener usuario por ID
@@ -126,66 +108,127 @@ pub async fn get_user(
     State(state): State<crate::state::AppState>,
     Path(user_id): Path<Uuid>,
 ) -> AppResult<Json<UserResponse>> {
-    let row = sqlx::query!(
+    if user_id != user.user_id {
+        require_admin(&user)?;
+    }
+
+    let found = sqlx::query_as::<_, User>(&format!(
         r#"
-        SELECT 
-            id, company_id, user_type as "user_type: crate::models::user::UserType", user_status as "user_status: crate::models::user::UserStatus", 
-            username, password_hash, created_at, updated_at, deleted_at
-        FROM users 
-        WHERE id = $1 
+        SELECT {}
+        FROM users
+        WHERE id = $1
         AND company_id = $2
         AND deleted_at IS NULL
         "#,
-        user_id,
-        user.company_id
-    )
+        USER_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(user.company_id)
     .fetch_optional(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?
+    .await?
     .ok_or_else(|| AppError::NotFound("Usuario no encontrado".to_string()))?;
 
-    let user_response = UserResponse {
-        id: row.id,
-        company_id: row.company_id,
-        user_type: row.user_type,
-        user_status: row.user_status,
-        username: row.username,
-        email: None,
-        created_at: row.created_at.unwrap_or_else(|| chrono::Utc::now()),
-        updated_at: row.updated_at.unwrap_or_else(|| chrono::Utc::now()),
-    };
-
-    Ok(Json(user_response))
+    Ok(Json(UserResponse::from(found)))
 }
 
-/// Handler para actualizar usuario (versión simplificada)
+/// Handler para actualizar usuario (solo admins)
 pub async fn update_user(
-    Extension(_user): Extension<AuthenticatedUser>,
-    State(_state): State<crate::state::AppState>,
-    Path(_user_id): Path<Uuid>,
-) -> AppResult<StatusCode> {
-    // Por ahora retorna Not Implemented
-    Err(AppError::NotImplemented("Update user no implementado aún".to_string()))
+    Extension(user): Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(user_id): Path<Uuid>,
+    Json(user_data): Json<UpdateUserRequest>,
+) -> AppResult<Json<UserResponse>> {
+    require_admin(&user)?;
+    user_data.validate()
+        .map_err(AppError::Validation)?;
+
+    // Un admin no puede quitarse a sí mismo los permisos ni desactivarse
+    if user_id == user.user_id && (user_data.user_type.is_some() || user_data.user_status.is_some()) {
+        return Err(AppError::Forbidden(
+            "No puedes cambiar tu propio tipo ni estado de usuario".to_string(),
+        ));
+    }
+
+    let license_categories = check_license_categories(user_data.license_categories.as_deref())?;
+    check_preferred_vehicle(&state, user.company_id, user_data.preferred_vehicle_id).await?;
+
+    let password_hash = match &user_data.password {
+        Some(password) => Some(
+            hash(password, DEFAULT_COST)
+                .map_err(|e| AppError::Hash(format!("Error hasheando password: {}", e)))?,
+        ),
+        None => None,
+    };
+
+    let mut tx = state.pool.begin().await?;
+
+    // Pasar a chofer también cuenta para el límite de la suscripción
+    if user_data.user_type == Some(UserType::Driver) {
+        check_driver_capacity(&mut tx, user.company_id, Some(user_id)).await?;
+    }
+
+    let updated = sqlx::query_as::<_, User>(&format!(
+        r#"
+        UPDATE users SET
+            username = COALESCE($3, username),
+            full_name = COALESCE($4, full_name),
+            email = COALESCE($5, email),
+            phone = COALESCE($6, phone),
+            tournee_number = COALESCE($7, tournee_number),
+            user_type = COALESCE($8, user_type),
+            user_status = COALESCE($9, user_status),
+            password_hash = COALESCE($10, password_hash),
+            license_categories = COALESCE($11, license_categories),
+            preferred_vehicle_id = COALESCE($12, preferred_vehicle_id),
+            updated_at = NOW()
+        WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
+        RETURNING {}
+        "#,
+        USER_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(user.company_id)
+    .bind(&user_data.username)
+    .bind(&user_data.full_name)
+    .bind(&user_data.email)
+    .bind(&user_data.phone)
+    .bind(&user_data.tournee_number)
+    .bind(&user_data.user_type)
+    .bind(&user_data.user_status)
+    .bind(password_hash)
+    .bind(license_categories)
+    .bind(user_data.preferred_vehicle_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| map_unique_violation(e, DUPLICATE_USER))?
+    .ok_or_else(|| AppError::NotFound("Usuario no encontrado".to_string()))?;
+
+    tx.commit().await?;
+    Ok(Json(UserResponse::from(updated)))
 }
 
-/// Handler para eliminar usuario (soft delete)
+/// Handler para eliminar usuario (soft delete, solo admins)
 pub async fn delete_user(
     Extension(user): Extension<AuthenticatedUser>,
     State(state): State<crate::state::AppState>,
     Path(user_id): Path<Uuid>,
 ) -> AppResult<StatusCode> {
-    let result = sqlx::query!(
+    require_admin(&user)?;
+    if user_id == user.user_id {
+        return Err(AppError::Forbidden("No puedes eliminar tu propio usuario".to_string()));
+    }
+
+    let result = sqlx::query(
         r#"
-        UPDATE users 
+        UPDATE users
         SET deleted_at = NOW(), updated_at = NOW()
         WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
         "#,
-        user_id,
-        user.company_id
     )
+    .bind(user_id)
+    .bind(user.company_id)
     .execute(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
+    .await?;
 
     if result.rows_affected() == 0 {
         return Err(AppError::NotFound("Usuario no encontrado".to_string()));
@@ -203,3 +246,84 @@ impl From<&str> for UserType {
         }
     }
 }
+
+/// Comprobar que la empresa admite un chofer más (`except`: usuario que ya cuenta)
+///
+/// Bloquea la fila de la empresa hasta el final de la transacción para que dos
+/// altas simultáneas no superen el límite.
+async fn check_driver_capacity(
+    conn: &mut PgConnection,
+    company_id: Uuid,
+    except: Option<Uuid>,
+) -> AppResult<()> {
+    let max_drivers = sqlx::query_scalar::<_, i32>("SELECT max_drivers FROM companies WHERE id = $1 FOR UPDATE")
+        .bind(company_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    let drivers = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM users
+        WHERE company_id = $1 AND user_type = 'driver' AND deleted_at IS NULL
+        AND ($2::uuid IS NULL OR id <> $2)
+        "#,
+    )
+    .bind(company_id)
+    .bind(except)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if drivers >= max_drivers as i64 {
+        return Err(AppError::Conflict(format!(
+            "La empresa ya tiene el máximo de {} choferes de su plan",
+            max_drivers
+        )));
+    }
+    Ok(())
+}
+
+/// Normalizar las categorías de permiso (al menos una, sin repetir)
+fn check_license_categories(categories: Option<&[String]>) -> AppResult<Option<Vec<String>>> {
+    let Some(categories) = categories else {
+        return Ok(None);
+    };
+
+    let mut normalized: Vec<String> = Vec::with_capacity(categories.len());
+    for category in categories.iter().map(|category| normalize_license(category)) {
+        if category.is_empty() || category.len() > 5 {
+            return Err(AppError::BadRequest(format!("Categoría de permiso no válida: '{}'", category)));
+        }
+        if !normalized.contains(&category) {
+            normalized.push(category);
+        }
+    }
+    if normalized.is_empty() {
+        return Err(AppError::BadRequest("Indica al menos una categoría de permiso".to_string()));
+    }
+    Ok(Some(normalized))
+}
+
+/// El vehículo preferido tiene que ser de la empresa
+async fn check_preferred_vehicle(
+    state: &crate::state::AppState,
+    company_id: Uuid,
+    vehicle_id: Option<Uuid>,
+) -> AppResult<()> {
+    let Some(vehicle_id) = vehicle_id else {
+        return Ok(());
+    };
+
+    let exists = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM vehicles WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL)",
+    )
+    .bind(vehicle_id)
+    .bind(company_id)
+    .fetch_one(&state.pool)
+    .await?;
+
+    if exists {
+        Ok(())
+    } else {
+        Err(AppError::NotFound("Vehículo preferido no encontrado".to_string()))
+    }
+}
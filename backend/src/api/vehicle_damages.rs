@@ -0,0 +1,178 @@
+//! Handlers de daños e incidentes de vehículo
+//!
+//! El chofer declara desde la app (y ve sus incidentes); valoración,
+//! reparación, imputación y siniestro son cosa del admin. Las fotos se suben
+//! con `POST /media/damage-evidence/:id` (ver `services::vehicle_damages`).
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde_json::json;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    api::{driver_scope, require_admin},
+    models::dispatch::DispatchEvent,
+    models::status_history::{StatusTransition, TransitionEntity},
+    models::vehicle_damage::{
+        DamageFilters, DamageTransitionRequest, DeclareDamageRequest, LiabilityParams, LiabilityStatement,
+        UpdateDamageRequest, VehicleDamage, VEHICLE_DAMAGE_COLUMNS,
+    },
+    services::{lifecycle, vehicle_damages},
+    utils::errors::{AppError, AppResult},
+    middleware::auth::AuthenticatedUser,
+};
+
+/// Listar daños (`?vehicle_id=&driver_id=&damage_status=&from=&to=`)
+pub async fn get_damages(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Query(filters): Query<DamageFilters>,
+) -> AppResult<Json<Vec<VehicleDamage>>> {
+    let damages = sqlx::query_as::<_, VehicleDamage>(&format!(
+        r#"
+        SELECT {}
+        FROM vehicle_damages
+        WHERE deleted_at IS NULL
+        AND vehicle_id IN (SELECT id FROM vehicles WHERE company_id = $1)
+        AND ($2::uuid IS NULL OR driver_id = $2)
+        AND ($3::uuid IS NULL OR vehicle_id = $3)
+        AND ($4::uuid IS NULL OR driver_id = $4)
+        AND ($5::damage_status IS NULL OR damage_status = $5)
+        AND ($6::date IS NULL OR incident_date >= $6)
+        AND ($7::date IS NULL OR incident_date <= $7)
+        ORDER BY incident_date DESC, created_at DESC
+        "#,
+        VEHICLE_DAMAGE_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(driver_scope(&user))
+    .bind(filters.vehicle_id)
+    .bind(filters.driver_id)
+    .bind(filters.damage_status)
+    .bind(filters.from)
+    .bind(filters.to)
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(damages))
+}
+
+/// Obtener un daño
+pub async fn get_damage(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<VehicleDamage>> {
+    let mut conn = state.pool.acquire().await?;
+    Ok(Json(vehicle_damages::get(&mut conn, &user, id).await?))
+}
+
+/// Declarar un incidente
+pub async fn declare_damage(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Json(request): Json<DeclareDamageRequest>,
+) -> AppResult<(StatusCode, Json<VehicleDamage>)> {
+    request.validate()
+        .map_err(AppError::Validation)?;
+
+    let mut tx = state.pool.begin().await?;
+    let damage = vehicle_damages::declare(&mut tx, &user, &request).await?;
+    tx.commit().await?;
+
+    state.dispatch.publish(DispatchEvent::alert(
+        user.company_id,
+        damage.tournee_id,
+        "damage_declared",
+        format!("Incidente declarado: {}", damage.damage_type.as_str()),
+        json!({ "damage_id": damage.id, "vehicle_id": damage.vehicle_id, "driver_id": damage.driver_id }),
+    )).await;
+
+    log::info!("🚗 Incidente {} declarado en el vehículo {}", damage.id, damage.vehicle_id);
+    Ok((StatusCode::CREATED, Json(damage)))
+}
+
+/// Corregir los datos de un incidente
+pub async fn update_damage(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<UpdateDamageRequest>,
+) -> AppResult<Json<VehicleDamage>> {
+    request.validate()
+        .map_err(AppError::Validation)?;
+
+    let mut tx = state.pool.begin().await?;
+    let damage = vehicle_damages::update(&mut tx, &user, id, &request).await?;
+    tx.commit().await?;
+
+    Ok(Json(damage))
+}
+
+/// Cambiar el estado de un daño (valoración, reparación, imputación, siniestro, cierre)
+pub async fn transition_damage(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<DamageTransitionRequest>,
+) -> AppResult<Json<VehicleDamage>> {
+    require_admin(&user)?;
+    request.validate()
+        .map_err(AppError::Validation)?;
+
+    let mut tx = state.pool.begin().await?;
+    let damage = vehicle_damages::transition(&mut tx, &user, id, &request).await?;
+    tx.commit().await?;
+
+    Ok(Json(damage))
+}
+
+/// Borrar un daño registrado por error (soft delete)
+pub async fn delete_damage(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<StatusCode> {
+    require_admin(&user)?;
+
+    let mut tx = state.pool.begin().await?;
+    vehicle_damages::delete(&mut tx, &user, id).await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Historial de estados de un daño
+pub async fn get_damage_history(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Vec<StatusTransition>>> {
+    let damage = {
+        let mut conn = state.pool.acquire().await?;
+        vehicle_damages::get(&mut conn, &user, id).await?
+    };
+
+    let history = lifecycle::history(&state.pool, user.company_id, TransitionEntity::VehicleDamage, damage.id).await?;
+    Ok(Json(history))
+}
+
+/// Estado de responsabilidad de un chofer (`?from=&to=`); el chofer ve el suyo
+pub async fn get_driver_liability(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(driver_id): Path<Uuid>,
+    Query(params): Query<LiabilityParams>,
+) -> AppResult<Json<LiabilityStatement>> {
+    if driver_id != user.user_id {
+        require_admin(&user)?;
+    }
+
+    let statement =
+        vehicle_damages::liability_statement(&state.pool, user.company_id, driver_id, params.from, params.to).await?;
+    Ok(Json(statement))
+}
@@ -0,0 +1,222 @@
+//! Handlers de documentos de vehículo
+//!
+//! Alta y renovación (admin) de seguro, contrôle technique, carte grise...
+//! El archivo escaneado se sube después con `POST /media/vehicle-document/:id`.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    api::{map_unique_violation, require_admin},
+    models::vehicle_document::{
+        CreateVehicleDocumentRequest, DocumentStatus, DocumentType, RenewVehicleDocumentRequest, VehicleDocument,
+        VEHICLE_DOCUMENT_COLUMNS,
+    },
+    utils::errors::{AppError, AppResult},
+    middleware::auth::AuthenticatedUser,
+};
+
+const DUPLICATE_DOCUMENT: &str = "El vehículo ya tiene un documento de ese tipo: renuévalo";
+
+/// Filtros de documentos de la empresa
+#[derive(Debug, Deserialize)]
+pub struct VehicleDocumentFilters {
+    pub document_status: Option<DocumentStatus>,
+    pub document_type: Option<DocumentType>,
+}
+
+async fn ensure_vehicle(state: &crate::state::AppState, user: &AuthenticatedUser, vehicle_id: Uuid) -> AppResult<()> {
+    let exists = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(SELECT 1 FROM vehicles WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL)",
+    )
+    .bind(vehicle_id)
+    .bind(user.company_id)
+    .fetch_one(&state.pool)
+    .await?;
+
+    if !exists {
+        return Err(AppError::NotFound("Vehículo no encontrado".to_string()));
+    }
+    Ok(())
+}
+
+/// Documentos de la empresa (`?document_status=expiring_soon&document_type=`)
+pub async fn get_company_documents(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Query(filters): Query<VehicleDocumentFilters>,
+) -> AppResult<Json<Vec<VehicleDocument>>> {
+    require_admin(&user)?;
+
+    let documents = sqlx::query_as::<_, VehicleDocument>(&format!(
+        r#"
+        SELECT {}
+        FROM vehicle_documents
+        WHERE company_id = $1 AND deleted_at IS NULL
+        AND ($2::document_status IS NULL OR document_status = $2)
+        AND ($3::document_type IS NULL OR document_type = $3)
+        ORDER BY expiry_date
+        "#,
+        VEHICLE_DOCUMENT_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(filters.document_status)
+    .bind(filters.document_type)
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(documents))
+}
+
+/// Documentos de un vehículo
+pub async fn get_vehicle_documents(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(vehicle_id): Path<Uuid>,
+) -> AppResult<Json<Vec<VehicleDocument>>> {
+    ensure_vehicle(&state, &user, vehicle_id).await?;
+
+    let documents = sqlx::query_as::<_, VehicleDocument>(&format!(
+        r#"
+        SELECT {}
+        FROM vehicle_documents
+        WHERE vehicle_id = $1 AND company_id = $2 AND deleted_at IS NULL
+        ORDER BY expiry_date
+        "#,
+        VEHICLE_DOCUMENT_COLUMNS
+    ))
+    .bind(vehicle_id)
+    .bind(user.company_id)
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(documents))
+}
+
+/// Registrar un documento del vehículo
+pub async fn create_vehicle_document(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path(vehicle_id): Path<Uuid>,
+    Json(document_data): Json<CreateVehicleDocumentRequest>,
+) -> AppResult<(StatusCode, Json<VehicleDocument>)> {
+    require_admin(&user)?;
+    document_data.validate()
+        .map_err(AppError::Validation)?;
+    if document_data.issue_date.is_some_and(|issue_date| issue_date >= document_data.expiry_date) {
+        return Err(AppError::BadRequest("La emisión debe ser anterior a la caducidad".to_string()));
+    }
+    ensure_vehicle(&state, &user, vehicle_id).await?;
+
+    let document = sqlx::query_as::<_, VehicleDocument>(&format!(
+        r#"
+        INSERT INTO vehicle_documents (
+            company_id, vehicle_id, document_type, document_number, document_name,
+            issue_date, expiry_date, renewal_reminder_date, insurance_company,
+            policy_number, notes
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        RETURNING {}
+        "#,
+        VEHICLE_DOCUMENT_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(vehicle_id)
+    .bind(document_data.document_type)
+    .bind(&document_data.document_number)
+    .bind(&document_data.document_name)
+    .bind(document_data.issue_date)
+    .bind(document_data.expiry_date)
+    .bind(document_data.renewal_reminder_date)
+    .bind(&document_data.insurance_company)
+    .bind(&document_data.policy_number)
+    .bind(&document_data.notes)
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| map_unique_violation(e, DUPLICATE_DOCUMENT))?;
+
+    log::info!(
+        "📄 Documento {} del vehículo {} registrado (caduca el {})",
+        document.document_type.as_str(),
+        vehicle_id,
+        document.expiry_date
+    );
+    Ok((StatusCode::CREATED, Json(document)))
+}
+
+/// Renovar un documento: nueva caducidad y avisos a cero
+pub async fn renew_vehicle_document(
+    axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
+    State(state): State<crate::state::AppState>,
+    Path((vehicle_id, document_id)): Path<(Uuid, Uuid)>,
+    Json(renewal): Json<RenewVehicleDocumentRequest>,
+) -> AppResult<Json<VehicleDocument>> {
+    require_admin(&user)?;
+    renewal.validate()
+        .map_err(AppError::Validation)?;
+
+    let mut tx = state.pool.begin().await?;
+    let current_expiry = sqlx::query_scalar::<_, chrono::NaiveDate>(
+        r#"
+        SELECT expiry_date FROM vehicle_documents
+        WHERE id = $1 AND vehicle_id = $2 AND company_id = $3 AND deleted_at IS NULL
+        FOR UPDATE
+        "#,
+    )
+    .bind(document_id)
+    .bind(vehicle_id)
+    .bind(user.company_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Documento no encontrado".to_string()))?;
+
+    if renewal.expiry_date <= current_expiry {
+        return Err(AppError::BadRequest(format!(
+            "La nueva caducidad debe ser posterior a la actual ({})",
+            current_expiry
+        )));
+    }
+
+    // El trigger recalcula document_status con la nueva fecha
+    let document = sqlx::query_as::<_, VehicleDocument>(&format!(
+        r#"
+        UPDATE vehicle_documents SET
+            expiry_date = $2,
+            issue_date = COALESCE($3, issue_date),
+            document_number = COALESCE($4, document_number),
+            insurance_company = COALESCE($5, insurance_company),
+            policy_number = COALESCE($6, policy_number),
+            renewal_notes = COALESCE($7, renewal_notes),
+            notification_sent_30_days = FALSE,
+            notification_sent_15_days = FALSE,
+            notification_sent_expired = FALSE,
+            updated_at = NOW()
+        WHERE id = $1
+        RETURNING {}
+        "#,
+        VEHICLE_DOCUMENT_COLUMNS
+    ))
+    .bind(document_id)
+    .bind(renewal.expiry_date)
+    .bind(renewal.issue_date)
+    .bind(&renewal.document_number)
+    .bind(&renewal.insurance_company)
+    .bind(&renewal.policy_number)
+    .bind(&renewal.renewal_notes)
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    log::info!(
+        "📄 Documento {} del vehículo {} renovado hasta el {}",
+        document.document_type.as_str(),
+        vehicle_id,
+        document.expiry_date
+    );
+    Ok(Json(document))
+}
@@ -1,26 +1,30 @@
 //! Handlers de Vehicles
-//! 
-//! Este módulo maneja las operaciones CRUD para vehículos.
+//!
+//! Este módulo maneja las operaciones CRUD para vehículos de la empresa del
+//! usuario autenticado. Las altas, cambios y bajas requieren un admin.
 
 use axum::{
     extract::{Path, Query, State},
     http::StatusCode,
     Json,
 };
-use serde_json::json;
-use sqlx::PgPool;
+use rust_decimal::Decimal;
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
+    api::{map_unique_violation, parse_date, require_admin},
     models::vehicle::{
         Vehicle, VehicleStatus, VehicleResponse, VehicleListResponse,
-        CreateVehicleRequest, UpdateVehicleRequest, VehicleFilters,
+        CreateVehicleRequest, UpdateVehicleRequest, VehicleFilters, VEHICLE_COLUMNS,
     },
+    services::assignment_planner::normalize_license,
     utils::errors::{AppError, AppResult},
     middleware::auth::AuthenticatedUser,
 };
 
+const DUPLICATE_PLATE: &str = "Ya existe un vehículo con esa matrícula o tarjeta de combustible en la empresa";
+
 /// Obtener todos los vehículos con filtros
 pub async fn get_vehicles(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
@@ -29,65 +33,48 @@ pub async fn get_vehicles(
 ) -> AppResult<Json<Vec<VehicleListResponse>>> {
     let limit = filters.limit.unwrap_or(50).min(100);
     let offset = filters.offset.unwrap_or(0);
+    let status = filters
+        .vehicle_status
+        .as_deref()
+        .map(parse_status)
+        .transpose()?;
+    let created_after = filters.created_after.as_deref().map(parse_date).transpose()?;
+    let created_before = filters.created_before.as_deref().map(parse_date).transpose()?;
 
-    let rows = sqlx::query!(
+    let vehicles = sqlx::query_as::<_, Vehicle>(&format!(
         r#"
-        SELECT 
-            id, company_id, license_plate, brand, model, year, color,
-            vehicle_status as "vehicle_status: String", current_mileage, fuel_type,
-            fuel_capacity, weekly_fuel_allocation, total_damage_cost, 
-            damage_incidents_count, vin, engine_size, transmission,
-            created_at, updated_at, deleted_at
-        FROM vehicles 
-        WHERE company_id = $1 
+        SELECT {}
+        FROM vehicles
+        WHERE company_id = $1
         AND deleted_at IS NULL
+        AND ($2::vehicle_status IS NULL OR vehicle_status = $2)
+        AND ($3::text IS NULL OR fuel_type = $3)
+        AND ($4::text IS NULL OR brand ILIKE $4)
+        AND ($5::text IS NULL OR model ILIKE $5)
+        AND ($6::int IS NULL OR year >= $6)
+        AND ($7::int IS NULL OR year <= $7)
+        AND ($8::date IS NULL OR (created_at AT TIME ZONE 'Europe/Paris')::date >= $8)
+        AND ($9::date IS NULL OR (created_at AT TIME ZONE 'Europe/Paris')::date <= $9)
         ORDER BY created_at DESC
-        LIMIT $2 OFFSET $3
+        LIMIT $10 OFFSET $11
         "#,
-        user.company_id,
-        limit,
-        offset
-    )
+        VEHICLE_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(status)
+    .bind(filters.fuel_type)
+    .bind(filters.brand)
+    .bind(filters.model)
+    .bind(filters.year_from)
+    .bind(filters.year_to)
+    .bind(created_after)
+    .bind(created_before)
+    .bind(limit)
+    .bind(offset)
     .fetch_all(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
+    .await?;
 
-    let vehicles: Vec<VehicleListResponse> = rows
-        .into_iter()
-        .map(|row| {
-            let vehicle = Vehicle {
-                id: row.id,
-                company_id: row.company_id,
-                license_plate: row.license_plate,
-                brand: row.brand,
-                model: row.model,
-                year: row.year,
-                color: row.color,
-                vehicle_status: match row.vehicle_status.as_str() {
-                    "active" => VehicleStatus::Active,
-                    "maintenance" => VehicleStatus::Maintenance,
-                    "out_of_service" => VehicleStatus::OutOfService,
-                    "retired" => VehicleStatus::Retired,
-                    _ => VehicleStatus::Active,
-                },
-                current_mileage: row.current_mileage,
-                fuel_type: row.fuel_type,
-                fuel_capacity: row.fuel_capacity,
-                weekly_fuel_allocation: row.weekly_fuel_allocation,
-                total_damage_cost: row.total_damage_cost,
-                damage_incidents_count: row.damage_incidents_count,
-                vin: row.vin,
-                engine_size: row.engine_size,
-                transmission: row.transmission,
-                created_at: row.created_at,
-                updated_at: row.updated_at,
-                deleted_at: row.deleted_at,
-            };
-            VehicleListResponse::from(vehicle)
-        })
-        .collect();
-
-    Ok(Json(vehicles))
+    Ok(Json(vehicles.into_iter().map(VehicleListResponse::from).collect()))
 }
 
 /// Obtener un vehículo por ID
@@ -96,55 +83,15 @@ pub async fn get_vehicle(
     State(state): State<crate::state::AppState>,
     Path(id): Path<Uuid>,
 ) -> AppResult<Json<VehicleResponse>> {
-    let row = sqlx::query!(
-        r#"
-        SELECT 
-            id, company_id, license_plate, brand, model, year, color,
-            vehicle_status as "vehicle_status: String", current_mileage, fuel_type,
-            fuel_capacity, weekly_fuel_allocation, total_damage_cost, 
-            damage_incidents_count, vin, engine_size, transmission,
-            created_at, updated_at, deleted_at
-        FROM vehicles 
-        WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
-        "#,
-        id,
-        user.company_id
-    )
-    .fetch_one(&state.pool)
-    .await
-    .map_err(|e| match e {
-        sqlx::Error::RowNotFound => AppError::NotFound("Vehículo no encontrado".to_string()),
-        _ => AppError::Database(e),
-    })?;
-
-    let vehicle = Vehicle {
-        id: row.id,
-        company_id: row.company_id,
-        license_plate: row.license_plate,
-        brand: row.brand,
-        model: row.model,
-        year: row.year,
-        color: row.color,
-        vehicle_status: match row.vehicle_status.as_str() {
-            "active" => VehicleStatus::Active,
-            "maintenance" => VehicleStatus::Maintenance,
-            "out_of_service" => VehicleStatus::OutOfService,
-            "retired" => VehicleStatus::Retired,
-            _ => VehicleStatus::Active,
-        },
-        current_mileage: row.current_mileage,
-        fuel_type: row.fuel_type,
-        fuel_capacity: row.fuel_capacity,
-        weekly_fuel_allocation: row.weekly_fuel_allocation,
-        total_damage_cost: row.total_damage_cost,
-        damage_incidents_count: row.damage_incidents_count,
-        vin: row.vin,
-        engine_size: row.engine_size,
-        transmission: row.transmission,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-        deleted_at: row.deleted_at,
-    };
+    let vehicle = sqlx::query_as::<_, Vehicle>(&format!(
+        "SELECT {} FROM vehicles WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL",
+        VEHICLE_COLUMNS
+    ))
+    .bind(id)
+    .bind(user.company_id)
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Vehículo no encontrado".to_string()))?;
 
     Ok(Json(VehicleResponse::from(vehicle)))
 }
@@ -154,73 +101,66 @@ pub async fn create_vehicle(
     axum::extract::Extension(user): axum::extract::Extension<AuthenticatedUser>,
     State(state): State<crate::state::AppState>,
     Json(vehicle_data): Json<CreateVehicleRequest>,
-) -> AppResult<Json<VehicleResponse>> {
-    // Validar datos de entrada
+) -> AppResult<(StatusCode, Json<VehicleResponse>)> {
+    require_admin(&user)?;
     vehicle_data.validate()
         .map_err(AppError::Validation)?;
+    check_consumption(vehicle_data.expected_consumption)?;
 
-    let row = sqlx::query!(
+    // Respetar el límite de vehículos de la suscripción
+    let (vehicles, max_vehicles) = sqlx::query_as::<_, (i64, i32)>(
         r#"
-        INSERT INTO vehicles (
-            company_id, license_plate, brand, model, year, color,
-            vehicle_status, current_mileage, fuel_type, fuel_capacity,
-            weekly_fuel_allocation, total_damage_cost, damage_incidents_count,
-            vin, engine_size, transmission, created_at, updated_at
-        ) VALUES (
-            $1, $2, $3, $4, $5, $6, ($7::text)::vehicle_status, $8, $9, $10, $11, $12, $13, $14, $15, $16, NOW(), NOW()
-        )
-        RETURNING 
-            id, company_id, license_plate, brand, model, year, color,
-            vehicle_status as "vehicle_status: crate::models::vehicle::VehicleStatus", current_mileage, fuel_type,
-            fuel_capacity, weekly_fuel_allocation, total_damage_cost, 
-            damage_incidents_count, vin, engine_size, transmission,
-            created_at, updated_at, deleted_at
+        SELECT
+            (SELECT COUNT(*) FROM vehicles WHERE company_id = c.id AND deleted_at IS NULL),
+            c.max_vehicles
+        FROM companies c
+        WHERE c.id = $1
         "#,
-        user.company_id,
-        vehicle_data.license_plate,
-        vehicle_data.brand,
-        vehicle_data.model,
-        vehicle_data.year,
-        vehicle_data.color,
-        "active",
-        rust_decimal::Decimal::new(0, 0), // current_mileage = 0
-        vehicle_data.fuel_type,
-        vehicle_data.fuel_capacity,
-        vehicle_data.weekly_fuel_allocation,
-        rust_decimal::Decimal::new(0, 0), // total_damage_cost = 0
-        0, // damage_incidents_count = 0
-        vehicle_data.vin,
-        vehicle_data.engine_size,
-        vehicle_data.transmission
     )
+    .bind(user.company_id)
     .fetch_one(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
+    .await?;
 
-    let vehicle = Vehicle {
-        id: row.id,
-        company_id: row.company_id,
-        license_plate: row.license_plate,
-        brand: row.brand,
-        model: row.model,
-        year: row.year,
-        color: row.color,
-        vehicle_status: row.vehicle_status,
-        current_mileage: row.current_mileage,
-        fuel_type: row.fuel_type,
-        fuel_capacity: row.fuel_capacity,
-        weekly_fuel_allocation: row.weekly_fuel_allocation,
-        total_damage_cost: row.total_damage_cost,
-        damage_incidents_count: row.damage_incidents_count,
-        vin: row.vin,
-        engine_size: row.engine_size,
-        transmission: row.transmission,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-        deleted_at: row.deleted_at,
-    };
+    if vehicles >= max_vehicles as i64 {
+        return Err(AppError::Conflict(format!(
+            "La empresa ya tiene el máximo de {} vehículos de su plan",
+            max_vehicles
+        )));
+    }
 
-    Ok(Json(VehicleResponse::from(vehicle)))
+    let vehicle = sqlx::query_as::<_, Vehicle>(&format!(
+        r#"
+        INSERT INTO vehicles (
+            company_id, license_plate, brand, model, year, color, fuel_type,
+            fuel_capacity, weekly_fuel_allocation, vin, engine_size, transmission,
+            parcel_capacity, expected_consumption, fuel_card_number, required_license
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, COALESCE($16, 'B'))
+        RETURNING {}
+        "#,
+        VEHICLE_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(&vehicle_data.license_plate)
+    .bind(&vehicle_data.brand)
+    .bind(&vehicle_data.model)
+    .bind(vehicle_data.year)
+    .bind(&vehicle_data.color)
+    .bind(&vehicle_data.fuel_type)
+    .bind(vehicle_data.fuel_capacity)
+    .bind(vehicle_data.weekly_fuel_allocation)
+    .bind(&vehicle_data.vin)
+    .bind(&vehicle_data.engine_size)
+    .bind(&vehicle_data.transmission)
+    .bind(vehicle_data.parcel_capacity)
+    .bind(vehicle_data.expected_consumption)
+    .bind(&vehicle_data.fuel_card_number)
+    .bind(vehicle_data.required_license.as_deref().map(normalize_license))
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| map_unique_violation(e, DUPLICATE_PLATE))?;
+
+    log::info!("🚐 Vehículo {} creado en la empresa {}", vehicle.license_plate, user.company_id);
+    Ok((StatusCode::CREATED, Json(VehicleResponse::from(vehicle))))
 }
 
 /// Actualizar un vehículo existente
@@ -230,90 +170,66 @@ pub async fn update_vehicle(
     Path(id): Path<Uuid>,
     Json(vehicle_data): Json<UpdateVehicleRequest>,
 ) -> AppResult<Json<VehicleResponse>> {
-    // Validar datos de entrada
+    require_admin(&user)?;
     vehicle_data.validate()
         .map_err(AppError::Validation)?;
+    check_consumption(vehicle_data.expected_consumption)?;
 
-    // Verificar que el vehículo existe y pertenece a la empresa
-    let _existing = sqlx::query!(
-        "SELECT id FROM vehicles WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL",
-        id,
-        user.company_id
-    )
-    .fetch_one(&state.pool)
-    .await
-    .map_err(|e| match e {
-        sqlx::Error::RowNotFound => AppError::NotFound("Vehículo no encontrado".to_string()),
-        _ => AppError::Database(e),
-    })?;
+    let status = vehicle_data
+        .vehicle_status
+        .as_deref()
+        .map(parse_status)
+        .transpose()?;
 
-    let row = sqlx::query!(
+    let vehicle = sqlx::query_as::<_, Vehicle>(&format!(
         r#"
         UPDATE vehicles SET
-            license_plate = COALESCE($2, license_plate),
-            brand = COALESCE($3, brand),
-            model = COALESCE($4, model),
-            year = COALESCE($5, year),
-            color = COALESCE($6, color),
-            vehicle_status = COALESCE(($7::text)::vehicle_status, vehicle_status),
-            current_mileage = COALESCE($8, current_mileage),
-            fuel_type = COALESCE($9, fuel_type),
-            fuel_capacity = COALESCE($10, fuel_capacity),
-            weekly_fuel_allocation = COALESCE($11, weekly_fuel_allocation),
-            vin = COALESCE($12, vin),
-            engine_size = COALESCE($13, engine_size),
-            transmission = COALESCE($14, transmission),
+            license_plate = COALESCE($3, license_plate),
+            brand = COALESCE($4, brand),
+            model = COALESCE($5, model),
+            year = COALESCE($6, year),
+            color = COALESCE($7, color),
+            vehicle_status = COALESCE($8, vehicle_status),
+            current_mileage = COALESCE($9, current_mileage),
+            fuel_type = COALESCE($10, fuel_type),
+            fuel_capacity = COALESCE($11, fuel_capacity),
+            weekly_fuel_allocation = COALESCE($12, weekly_fuel_allocation),
+            vin = COALESCE($13, vin),
+            engine_size = COALESCE($14, engine_size),
+            transmission = COALESCE($15, transmission),
+            parcel_capacity = COALESCE($16, parcel_capacity),
+            expected_consumption = COALESCE($17, expected_consumption),
+            fuel_card_number = COALESCE($18, fuel_card_number),
+            required_license = COALESCE($19, required_license),
             updated_at = NOW()
-        WHERE id = $1 AND company_id = $15
-        RETURNING 
-            id, company_id, license_plate, brand, model, year, color,
-            vehicle_status as "vehicle_status: crate::models::vehicle::VehicleStatus", current_mileage, fuel_type,
-            fuel_capacity, weekly_fuel_allocation, total_damage_cost, 
-            damage_incidents_count, vin, engine_size, transmission,
-            created_at, updated_at, deleted_at
+        WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
+        RETURNING {}
         "#,
-        id,
-        vehicle_data.license_plate,
-        vehicle_data.brand,
-        vehicle_data.model,
-        vehicle_data.year,
-        vehicle_data.color,
-        vehicle_data.vehicle_status,
-        vehicle_data.current_mileage,
-        vehicle_data.fuel_type,
-        vehicle_data.fuel_capacity,
-        vehicle_data.weekly_fuel_allocation,
-        vehicle_data.vin,
-        vehicle_data.engine_size,
-        vehicle_data.transmission,
-        user.company_id
-    )
-    .fetch_one(&state.pool)
+        VEHICLE_COLUMNS
+    ))
+    .bind(id)
+    .bind(user.company_id)
+    .bind(&vehicle_data.license_plate)
+    .bind(&vehicle_data.brand)
+    .bind(&vehicle_data.model)
+    .bind(vehicle_data.year)
+    .bind(&vehicle_data.color)
+    .bind(status)
+    .bind(vehicle_data.current_mileage)
+    .bind(&vehicle_data.fuel_type)
+    .bind(vehicle_data.fuel_capacity)
+    .bind(vehicle_data.weekly_fuel_allocation)
+    .bind(&vehicle_data.vin)
+    .bind(&vehicle_data.engine_size)
+    .bind(&vehicle_data.transmission)
+    .bind(vehicle_data.parcel_capacity)
+    .bind(vehicle_data.expected_consumption)
+    .bind(&vehicle_data.fuel_card_number)
+    .bind(vehicle_data.required_license.as_deref().map(normalize_license))
+    .fetch_optional(&state.pool)
     .await
-    .map_err(|e| AppError::Database(e))?;
-
-    let vehicle = Vehicle {
-        id: row.id,
-        company_id: row.company_id,
-        license_plate: row.license_plate,
-        brand: row.brand,
-        model: row.model,
-        year: row.year,
-        color: row.color,
-        vehicle_status: row.vehicle_status,
-        current_mileage: row.current_mileage,
-        fuel_type: row.fuel_type,
-        fuel_capacity: row.fuel_capacity,
-        weekly_fuel_allocation: row.weekly_fuel_allocation,
-        total_damage_cost: row.total_damage_cost,
-        damage_incidents_count: row.damage_incidents_count,
-        vin: row.vin,
-        engine_size: row.engine_size,
-        transmission: row.transmission,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-        deleted_at: row.deleted_at,
-    };
+    .map_err(|e| map_unique_violation(e, DUPLICATE_PLATE))?
+    .ok_or_else(|| AppError::NotFound("Vehículo no encontrado".to_string()))?;
 
     Ok(Json(VehicleResponse::from(vehicle)))
 }
@@ -324,22 +240,36 @@ pub async fn delete_vehicle(
     State(state): State<crate::state::AppState>,
     Path(id): Path<Uuid>,
 ) -> AppResult<StatusCode> {
-    let result = sqlx::query!(
+    require_admin(&user)?;
+
+    let result = sqlx::query(
         r#"
-        UPDATE vehicles 
+        UPDATE vehicles
         SET deleted_at = NOW(), updated_at = NOW()
         WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
         "#,
-        id,
-        user.company_id
     )
+    .bind(id)
+    .bind(user.company_id)
     .execute(&state.pool)
-    .await
-    .map_err(|e| AppError::Database(e))?;
+    .await?;
 
     if result.rows_affected() == 0 {
         return Err(AppError::NotFound("Vehículo no encontrado".to_string()));
     }
 
     Ok(StatusCode::NO_CONTENT)
-}
\ No newline at end of file
+}
+
+/// El consumo de referencia (L/100 km) tiene que ser positivo
+fn check_consumption(consumption: Option<Decimal>) -> AppResult<()> {
+    if consumption.is_some_and(|c| c <= Decimal::ZERO) {
+        return Err(AppError::BadRequest("El consumo de referencia debe ser positivo".to_string()));
+    }
+    Ok(())
+}
+
+fn parse_status(value: &str) -> AppResult<VehicleStatus> {
+    VehicleStatus::parse(value)
+        .ok_or_else(|| AppError::BadRequest(format!("Estado de vehículo desconocido: {}", value)))
+}
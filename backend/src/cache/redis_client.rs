@@ -8,8 +8,8 @@ use super::{CacheConfig, CacheOperations};
 /// Cliente Redis con connection pooling y operaciones async
 #[derive(Clone)]
 pub struct RedisClient {
+    client: redis::Client,
     manager: ConnectionManager,
-    config: CacheConfig,
 }
 
 impl RedisClient {
@@ -18,7 +18,7 @@ impl RedisClient {
         info!("🔗 Conectando a Redis: {}", config.redis_url);
         
         let client = redis::Client::open(config.redis_url.clone())?;
-        let manager = ConnectionManager::new(client).await?;
+        let manager = ConnectionManager::new(client.clone()).await?;
         
         // Test de conexión usando un comando simple
         let mut conn = manager.clone();
@@ -26,7 +26,7 @@ impl RedisClient {
         
         info!("✅ Redis conectado exitosamente");
         
-        Ok(Self { manager, config })
+        Ok(Self { client, manager })
     }
     
     /// Generar clave de cache con prefijo
@@ -173,6 +173,19 @@ impl RedisClient {
         Ok(stats)
     }
     
+    /// Publicar un mensaje en un canal pub/sub
+    pub async fn publish(&self, channel: &str, payload: &str) -> Result<()> {
+        let mut conn = self.manager.clone();
+        let _: i64 = conn.publish(channel, payload).await?;
+        Ok(())
+    }
+
+    /// Abrir una conexión dedicada de pub/sub (no puede compartirse con el
+    /// `ConnectionManager`)
+    pub async fn pubsub(&self) -> Result<redis::aio::PubSub> {
+        Ok(self.client.get_async_connection().await?.into_pubsub())
+    }
+
     /// Limpiar cache completo (¡CUIDADO!)
     pub async fn flush_all(&self) -> Result<()> {
         let mut conn = self.manager.clone();
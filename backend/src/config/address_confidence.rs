@@ -0,0 +1,63 @@
+//! Pesos del score de confianza de direcciones
+//!
+//! Configurables por entorno (`ADDRESS_CONFIDENCE_WEIGHT_*`, y la escala del
+//! score del transportista en `ADDRESS_CONFIDENCE_CARRIER_SCALE`); el cálculo del
+//! score está en `services::address_confidence`.
+
+use serde::{Deserialize, Serialize};
+use std::env;
+
+/// Pesos de cada señal (no necesitan sumar 1, se normalizan)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfidenceWeights {
+    pub carrier_score: f64,
+    pub geocoder_relevance: f64,
+    pub provider_agreement: f64,
+    pub precision: f64,
+    pub postcode_match: f64,
+    pub driver_verification: f64,
+    /// Valor máximo del `score_geocodage` del transportista (Colis Privé: 100)
+    pub carrier_score_scale: f64,
+}
+
+impl Default for ConfidenceWeights {
+    fn default() -> Self {
+        Self {
+            carrier_score: 0.15,
+            geocoder_relevance: 0.25,
+            provider_agreement: 0.15,
+            precision: 0.20,
+            postcode_match: 0.15,
+            driver_verification: 0.10,
+            carrier_score_scale: 100.0,
+        }
+    }
+}
+
+impl ConfidenceWeights {
+    /// Cargar pesos desde variables de entorno `ADDRESS_CONFIDENCE_WEIGHT_*`
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let read = |name: &str, default: f64| -> f64 {
+            env::var(format!("ADDRESS_CONFIDENCE_WEIGHT_{}", name))
+                .ok()
+                .and_then(|v| v.parse::<f64>().ok())
+                .filter(|v| *v >= 0.0)
+                .unwrap_or(default)
+        };
+
+        Self {
+            carrier_score: read("CARRIER", defaults.carrier_score),
+            geocoder_relevance: read("RELEVANCE", defaults.geocoder_relevance),
+            provider_agreement: read("AGREEMENT", defaults.provider_agreement),
+            precision: read("PRECISION", defaults.precision),
+            postcode_match: read("POSTCODE", defaults.postcode_match),
+            driver_verification: read("DRIVER", defaults.driver_verification),
+            carrier_score_scale: env::var("ADDRESS_CONFIDENCE_CARRIER_SCALE")
+                .ok()
+                .and_then(|v| v.parse::<f64>().ok())
+                .filter(|v| *v > 0.0)
+                .unwrap_or(defaults.carrier_score_scale),
+        }
+    }
+}
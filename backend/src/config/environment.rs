@@ -4,6 +4,15 @@
 
 use std::env;
 
+use crate::config::address_confidence::ConfidenceWeights;
+use crate::services::failed_delivery::FailedDeliveryPolicy;
+use crate::services::fuel_reconciliation::FuelPolicy;
+use crate::services::geofencing::GeofenceConfig;
+use crate::services::location_tracking::LocationConfig;
+use crate::services::maintenance::MaintenancePolicy;
+use crate::services::performance_analytics::AnalyticsPolicy;
+use crate::services::media_storage::MediaConfig;
+
 /// Configuración del entorno
 #[derive(Debug, Clone)]
 pub struct EnvironmentConfig {
@@ -16,6 +25,25 @@ pub struct EnvironmentConfig {
     pub rate_limit_requests: u32,
     pub rate_limit_window: u64,
     pub mapbox_token: Option<String>,
+    /// Pesos del score de confianza de direcciones
+    pub address_confidence_weights: ConfidenceWeights,
+    /// Claves maestras de cifrado de campos (`id:base64,id2:base64`)
+    pub field_encryption_keys: Option<String>,
+    /// Id de la clave maestra activa (por defecto la última)
+    pub field_encryption_active_key: Option<String>,
+    pub media: MediaConfig,
+    /// Límites del flujo de entregas fallidas
+    pub failed_delivery: FailedDeliveryPolicy,
+    /// Ingesta y retención de posiciones GPS
+    pub location: LocationConfig,
+    /// Geocerca de paradas y ETAs
+    pub geofence: GeofenceConfig,
+    /// Márgenes del cuadre de kilometraje y combustible
+    pub fuel: FuelPolicy,
+    /// Avisos y previsión del mantenimiento preventivo
+    pub maintenance: MaintenancePolicy,
+    /// Agregación semanal del rendimiento de los choferes
+    pub analytics: AnalyticsPolicy,
     // URLs de Colis Privé
     pub colis_prive_auth_url: String,
     pub colis_prive_tournee_url: String,
@@ -52,6 +80,16 @@ impl Default for EnvironmentConfig {
                 .parse()
                 .unwrap_or(3600),
             mapbox_token: env::var("MAPBOX_TOKEN").ok(),
+            address_confidence_weights: ConfidenceWeights::from_env(),
+            field_encryption_keys: env::var("FIELD_ENCRYPTION_KEYS").ok().filter(|v| !v.trim().is_empty()),
+            field_encryption_active_key: env::var("FIELD_ENCRYPTION_ACTIVE_KEY").ok(),
+            media: MediaConfig::from_env(),
+            failed_delivery: FailedDeliveryPolicy::from_env(),
+            location: LocationConfig::from_env(),
+            geofence: GeofenceConfig::from_env(),
+            fuel: FuelPolicy::from_env(),
+            maintenance: MaintenancePolicy::from_env(),
+            analytics: AnalyticsPolicy::from_env(),
             // URLs de Colis Privé
             colis_prive_auth_url: env::var("COLIS_PRIVE_AUTH_URL")
                 .unwrap_or_else(|_| "https://wsauthentificationexterne.colisprive.com".to_string()),
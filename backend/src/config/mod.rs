@@ -3,8 +3,10 @@
 //! Este módulo contiene la configuración de base de datos, variables de entorno
 //! y otras configuraciones del sistema.
 
+pub mod address_confidence;
 pub mod database;
 pub mod environment;
 
+pub use address_confidence::ConfidenceWeights;
 pub use environment::*;
 
@@ -8,6 +8,7 @@ mod client;
 mod models;
 mod cache;
 mod analysis;
+mod middleware;
 
 use anyhow::Result;
 use axum::{
@@ -65,7 +66,37 @@ async fn main() -> Result<()> {
     };
 
     // Crear router de la API
-    let app_state = AppState::new(pool, EnvironmentConfig::default(), redis_client);
+    let app_state = match AppState::new(pool, EnvironmentConfig::default(), redis_client) {
+        Ok(state) => state,
+        Err(e) => {
+            error!("❌ Configuración inválida: {}", e);
+            return Err(e);
+        }
+    };
+
+    // Suscripción Redis del canal de seguimiento en vivo
+    app_state.dispatch.spawn_relay();
+
+    // Purga periódica de migas de pan GPS
+    services::location_tracking::spawn_retention_job(
+        app_state.pool.clone(),
+        app_state.config.location.clone(),
+    );
+
+    // Revisión diaria de caducidad de documentos de vehículo
+    services::vehicle_documents::spawn_expiry_job(app_state.pool.clone());
+
+    // Revisión diaria del mantenimiento preventivo
+    services::maintenance::spawn_maintenance_job(
+        app_state.pool.clone(),
+        app_state.config.maintenance.clone(),
+    );
+
+    // Agregación semanal del rendimiento de los choferes
+    services::performance_analytics::spawn_performance_job(
+        app_state.pool.clone(),
+        app_state.config.analytics.clone(),
+    );
     
     let app = Router::new()
         .route("/test", get(test_endpoint))
@@ -73,10 +104,16 @@ async fn main() -> Result<()> {
         .route("/api/colis-prive/companies", get(api::colis_prive::get_companies))
         .route("/api/colis-prive/auth", post(api::colis_prive::authenticate_colis_prive))
         .route("/api/colis-prive/packages-test", get(api::colis_prive::test_packages_endpoint))
-        .route("/api/colis-prive/packages", post(api::colis_prive::get_packages))
+        .route(
+            "/api/colis-prive/packages",
+            post(api::colis_prive::get_packages).route_layer(axum::middleware::from_fn_with_state(
+                app_state.clone(),
+                middleware::auth::optional_auth_middleware,
+            )),
+        )
         .route("/api/colis-prive/tournee", post(api::colis_prive::get_tournee_data))
         // migration endpoints eliminados - código legacy
-        .merge(api::create_api_router())
+        .merge(api::create_api_router(app_state.clone()))
         .with_state(app_state);
 
     // Puerto del servidor
@@ -98,6 +135,74 @@ async fn main() -> Result<()> {
     info!("   POST /api/hybrid/package-detail - Obtener datos detallados");
     info!("   POST /api/hybrid/cache/cleanup - Limpiar cache");
     info!("   POST /api/hybrid/cache/stats - Estadísticas de cache");
+    info!("📋 Jobs de validación de direcciones:");
+    info!("   POST /api/v1/address-validation/jobs - Crear job de validación");
+    info!("   GET  /api/v1/address-validation/jobs/:id - Progreso del job");
+    info!("   GET  /api/v1/address-validation/jobs/:id/results - Resultados (json/csv, filter=manual)");
+    info!("🏠 Conocimiento de terreno (JWT requerido):");
+    info!("   GET/POST /api/v1/field-data - Listar/buscar o crear información de terreno");
+    info!("   GET  /api/v1/field-data/lookup - Entrada más confiable por dirección o proximidad");
+    info!("   GET/PUT/DELETE /api/v1/field-data/:id - Consultar, actualizar o eliminar");
+    info!("   GET  /api/v1/field-data/:id/access-log - Auditoría de lecturas (admin)");
+    info!("   POST /api/v1/field-data/encryption/rotate - Rotación de claves (admin)");
+    info!("   GET  /api/v1/field-data/:id/history - Historial de revisiones (admin)");
+    info!("   POST /api/v1/field-data/:id/revert - Revertir a una revisión (admin)");
+    info!("   POST /api/v1/field-data/:id/revisions/:n/verify - Verificar revisión (admin)");
+    info!("📸 Archivos (fotos de entrega, firmas, inspecciones, daños):");
+    info!("   POST /api/v1/media/:target/:entity_id - Subir imagen multipart (JWT)");
+    info!("   GET  /api/v1/media/:id - Metadatos y URLs firmadas nuevas (JWT)");
+    info!("   GET  /api/media/*key - Descarga con URL firmada");
+    info!("🔑 Autenticación v1:");
+    info!("   POST /api/v1/auth/login - Login (JWT)");
+    info!("   POST /api/v1/auth/register - Registrar empresa y primer admin");
+    info!("   GET  /api/v1/auth/me - Usuario autenticado (JWT)");
+    info!("🗂️ Back-office v1 (JWT requerido, acotado a la empresa):");
+    info!("   GET/PUT /api/v1/companies[/:id] - Empresa del usuario");
+    info!("   GET/POST/PUT/DELETE /api/v1/users[/:id] - Usuarios (admin)");
+    info!("   GET/POST/PUT/DELETE /api/v1/vehicles[/:id] - Vehículos");
+    info!("   GET/POST /api/v1/vehicles/:id/documents - Documentos del vehículo (+ /:document_id/renew)");
+    info!("   GET  /api/v1/vehicle-documents - Documentos de la empresa por estado (admin)");
+    info!("   GET/POST/PUT/DELETE /api/v1/vehicle-damages[/:id] - Daños e incidentes (+ /status, /history)");
+    info!("   GET  /api/v1/users/:id/damage-liability - Importes a cargo del chofer");
+    info!("   GET/POST/PUT/DELETE /api/v1/maintenance-plans[/:id] - Plan de mantenimiento por modelo (admin)");
+    info!("   GET  /api/v1/vehicles/:id/maintenance-forecast - Próximos mantenimientos del vehículo");
+    info!("   GET/POST /api/v1/vehicles/:id/maintenance - Citas de taller del vehículo (POST reserva, admin)");
+    info!("   GET  /api/v1/maintenance[/due] - Citas de taller y tareas próximas o vencidas (admin)");
+    info!("   POST /api/v1/maintenance/:id/status - Empezar, completar o cancelar una cita (admin)");
+    info!("   GET  /api/v1/assignments/proposal?date= - Propuesta de vehículo por chofer para el día (admin)");
+    info!("   POST /api/v1/assignments/accept - Aceptar o cambiar la propuesta del día (admin)");
+    info!("   GET  /api/v1/assignments?date= - Asignaciones aceptadas del día (admin)");
+    info!("   POST /api/v1/fuel-cards/import - Importar CSV de la tarjeta de combustible (admin)");
+    info!("   GET  /api/v1/fuel-cards/transactions - Repostajes importados (?match_status=, admin)");
+    info!("   GET  /api/v1/fuel/reconciliations - Cuadres de kilometraje y combustible (?flagged=true, admin)");
+    info!("   GET/POST /api/v1/tournees/:id/reconciliation - Cuadre de la tournée (POST recalcula, admin)");
+    info!("   GET/POST/PUT/DELETE /api/v1/tournees[/:id] - Tournées (+ /start, /pause, /resume, /end, /cancel)");
+    info!("   GET  /api/v1/{{tournees,packages}}/:id/history - Historial de estados");
+    info!("   GET/POST/PUT/DELETE /api/v1/packages[/:id] - Paquetes (+ /delivered, /failed)");
+    info!("   GET  /api/v1/packages/:id/proof - Prueba de entrega (json/html)");
+    info!("   POST /api/v1/packages/:id/failed - Entrega fallida (reprogramación, relais, devolución)");
+    info!("   GET/POST/PUT/DELETE /api/v1/relay-points[/:id] - Puntos relais");
+    info!("   GET/POST/PUT/DELETE /api/v1/pickups[/:id] - Recogidas (DELETE cancela)");
+    info!("   POST /api/v1/pickups/:id/confirm - Confirmar recogida con los bultos leídos");
+    info!("   POST /api/v1/pickups/:id/failed - Dar una recogida por fallida");
+    info!("   GET  /api/v1/packages/:id/proof/verify - Verificar prueba de entrega (admin)");
+    info!("   GET  /api/v1/delivery-proofs/verify - Verificar cadena de pruebas (admin)");
+    info!("   POST /api/v1/sync - Sincronización offline (mutaciones en lote + delta)");
+    info!("   POST /api/v1/scans - Registrar lectura de código de barras");
+    info!("   GET  /api/v1/scans/unassigned - Lecturas sin tournée (?flagged=true, admin)");
+    info!("   GET  /api/v1/tournees/:id/scans - Lecturas de la tournée (?flagged=true)");
+    info!("   GET  /api/v1/tournees/:id/scan-report - Sin cargar, cargados sin entregar, lecturas marcadas");
+    info!("   GET/POST /api/v1/tournees/:id/compte-rendu - Compte-rendu de fin de día (?format=json|csv|pdf)");
+    info!("   POST /api/v1/tournees/:id/compte-rendu/sign - Firmar el compte-rendu y bloquear la tournée");
+    info!("   POST /api/v1/locations - Lote de posiciones GPS del chofer");
+    info!("   GET  /api/v1/users/:id/track - Recorrido del chofer (?from=&to=)");
+    info!("   GET  /api/v1/tournees/:id/stop-visits - Llegadas y salidas detectadas por geocerca");
+    info!("   GET  /api/v1/tournees/:id/eta - ETAs de las paradas pendientes");
+    info!("   POST /api/v1/dispatch/stream-token - Token de 60 s para abrir el canal con EventSource (admin)");
+    info!("   GET  /api/v1/dispatch/stream - Seguimiento en vivo por SSE (?tournee_id=, ?access_token=, admin)");
+    info!("   GET  /api/v1/analytics/{{dashboard,tournees,drivers,vehicles}} - Métricas (admin)");
+    info!("   GET  /api/v1/analytics/weekly - Rendimiento semanal por chofer (admin)");
+    info!("   POST /api/v1/analytics/weekly/recompute - Recalcular semanas tras correcciones (admin)");
     info!("📱 Endpoints Móviles (Nuevos):");
     info!("   POST /api/mobile/tournee - Obtener tournée para móvil");
     info!("   POST /api/mobile/package/update-status - Actualizar estado paquete");
@@ -4,7 +4,7 @@
 //! y verificación de usuarios autenticados.
 
 use axum::{
-    extract::{Request, State},
+    extract::{Query, Request, State},
     http::header,
     middleware::Next,
     response::Response,
@@ -17,7 +17,8 @@ use uuid::Uuid;
 
 use crate::{
     config::EnvironmentConfig,
-    user::{User, UserType, UserStatus},
+    models::user::{UserStatus, UserType},
+    state::AppState,
     utils::errors::AppError,
 };
 
@@ -29,8 +30,18 @@ pub struct Claims {
     pub user_type: String,
     pub exp: usize,
     pub iat: usize,
+    /// Tokens de vida corta que solo valen para una ruta (ver
+    /// `generate_stream_token`); los de sesión no lo llevan
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
 }
 
+/// Alcance del token del canal en vivo (`GET /api/v1/dispatch/stream`)
+pub const DISPATCH_STREAM_SCOPE: &str = "dispatch_stream";
+
+/// Vida del token del canal en vivo: solo tiene que llegar a abrir la conexión
+pub const DISPATCH_STREAM_TOKEN_TTL_SECONDS: i64 = 60;
+
 /// Usuario autenticado que se inyecta en las requests
 #[derive(Debug, Clone)]
 pub struct AuthenticatedUser {
@@ -39,55 +50,136 @@ pub struct AuthenticatedUser {
     pub user_type: UserType,
 }
 
+/// Chofer al que se restringe la consulta (`None` para los admins)
+pub fn driver_scope(user: &AuthenticatedUser) -> Option<Uuid> {
+    match user.user_type {
+        UserType::Driver => Some(user.user_id),
+        UserType::Admin => None,
+    }
+}
+
+/// Usuario activo tal como se lee de la base de datos
+#[derive(Debug, sqlx::FromRow)]
+struct ActiveUserRow {
+    id: Uuid,
+    company_id: Uuid,
+    user_type: UserType,
+    user_status: UserStatus,
+}
+
+/// Buscar el usuario del token (no eliminado) en la base de datos
+async fn find_token_user(
+    pool: &PgPool,
+    user_id: Uuid,
+    company_id: Uuid,
+) -> Result<Option<ActiveUserRow>, sqlx::Error> {
+    sqlx::query_as::<_, ActiveUserRow>(
+        r#"
+        SELECT id, company_id, user_type, user_status
+        FROM users 
+        WHERE id = $1 
+        AND company_id = $2 
+        AND deleted_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .bind(company_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Token del header `Authorization: Bearer ...`
+fn bearer_token(request: &Request) -> Option<&str> {
+    request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|auth_str| auth_str.to_str().ok())
+        .and_then(|auth_str| auth_str.strip_prefix("Bearer "))
+}
+
 /// Middleware de autenticación JWT
 pub async fn auth_middleware(
-    State(pool): State<PgPool>,
-    State(config): State<EnvironmentConfig>,
+    State(state): State<AppState>,
     mut request: Request,
     next: Next,
 ) -> Result<Response, AppError> {
     // Extraer token del header Authorization
-    let auth_header = request
-        .headers()
-        .get(header::AUTHORIZATION)
-        .and_then(|auth_str| auth_str.to_str().ok())
-        .and_then(|auth_str| auth_str.strip_prefix("Bearer "))
-        .ok_or_else(|| {
-            AppError::Unauthorized("Token de autorización requerido".to_string())
-        })?;
+    let auth_header = bearer_token(&request).ok_or_else(|| {
+        AppError::Unauthorized("Token de autorización requerido".to_string())
+    })?;
+
+    let authenticated_user = authenticate(&state, auth_header, None).await?;
+
+    // Inyectar usuario autenticado en las extensions
+    request.extensions_mut().insert(authenticated_user);
+
+    Ok(next.run(request).await)
+}
+
+/// Token del canal en vivo en la URL (`?access_token=`)
+#[derive(Debug, Deserialize)]
+struct StreamTokenParams {
+    access_token: Option<String>,
+}
+
+/// Autenticación del canal en vivo: `EventSource` no puede enviar el header
+/// `Authorization`, así que además del JWT de sesión se acepta en la URL un
+/// token de `POST /api/v1/dispatch/stream-token`. En la URL solo vale ese
+/// token de vida corta, nunca el de sesión.
+pub async fn stream_auth_middleware(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let authenticated_user = match bearer_token(&request) {
+        Some(token) => authenticate(&state, token, None).await?,
+        None => {
+            let token = Query::<StreamTokenParams>::try_from_uri(request.uri())
+                .ok()
+                .and_then(|Query(params)| params.access_token)
+                .ok_or_else(|| {
+                    AppError::Unauthorized("Token de autorización requerido".to_string())
+                })?;
+            authenticate(&state, &token, Some(DISPATCH_STREAM_SCOPE)).await?
+        }
+    };
+
+    request.extensions_mut().insert(authenticated_user);
 
+    Ok(next.run(request).await)
+}
+
+/// Validar el JWT, su alcance y que el usuario siga activo
+async fn authenticate(
+    state: &AppState,
+    token: &str,
+    scope: Option<&str>,
+) -> Result<AuthenticatedUser, AppError> {
     // Decodificar y validar JWT
     let token_data = decode::<Claims>(
-        auth_header,
-        &DecodingKey::from_secret(config.jwt_secret.as_ref()),
+        token,
+        &DecodingKey::from_secret(state.config.jwt_secret.as_ref()),
         &Validation::default(),
     )
     .map_err(|_| AppError::Unauthorized("Token inválido".to_string()))?;
 
     let claims = token_data.claims;
 
+    // Un token de alcance limitado no abre el resto de la API
+    if claims.scope.as_deref() != scope {
+        return Err(AppError::Unauthorized("Token inválido para esta ruta".to_string()));
+    }
+
     // Verificar que el usuario existe en la base de datos
-    let user_row = sqlx::query!(
-        r#"
-        SELECT 
-            id, company_id, user_type as "user_type: crate::models::user::UserType", user_status as "user_status: crate::models::user::UserStatus", username, 
-            password_hash, created_at, updated_at, deleted_at
-        FROM users 
-        WHERE id = $1 
-        AND company_id = $2 
-        AND deleted_at IS NULL
-        "#,
-        Uuid::parse_str(&claims.sub).map_err(|_| {
-            AppError::Unauthorized("ID de usuario inválido".to_string())
-        })?,
-        Uuid::parse_str(&claims.company_id).map_err(|_| {
-            AppError::Unauthorized("ID de empresa inválido".to_string())
-        })?
-    )
-    .fetch_optional(&pool)
-    .await
-    .map_err(|e| AppError::Database(e))?
-    .ok_or_else(|| AppError::Unauthorized("Usuario no encontrado".to_string()))?;
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| AppError::Unauthorized("ID de usuario inválido".to_string()))?;
+    let company_id = Uuid::parse_str(&claims.company_id)
+        .map_err(|_| AppError::Unauthorized("ID de empresa inválido".to_string()))?;
+
+    let user_row = find_token_user(&state.pool, user_id, company_id)
+        .await
+        .map_err(AppError::Database)?
+        .ok_or_else(|| AppError::Unauthorized("Usuario no encontrado".to_string()))?;
 
     // Usar los enums directamente
     let user_type = user_row.user_type;
@@ -101,73 +193,23 @@ pub async fn auth_middleware(
     }
 
     // Crear usuario autenticado
-    let authenticated_user = AuthenticatedUser {
+    Ok(AuthenticatedUser {
         user_id: user_row.id,
         company_id: user_row.company_id,
         user_type,
-    };
-
-    // Inyectar usuario autenticado en las extensions
-    request.extensions_mut().insert(authenticated_user);
-
-    Ok(next.run(request).await)
+    })
 }
 
 /// Middleware opcional de autenticación (para rutas que pueden ser públicas o privadas)
 pub async fn optional_auth_middleware(
-    State(pool): State<PgPool>,
-    State(config): State<EnvironmentConfig>,
+    State(state): State<AppState>,
     mut request: Request,
     next: Next,
 ) -> Result<Response, AppError> {
-    // Intentar extraer token del header Authorization
-    if let Some(auth_header) = request
-        .headers()
-        .get(header::AUTHORIZATION)
-        .and_then(|auth_str| auth_str.to_str().ok())
-        .and_then(|auth_str| auth_str.strip_prefix("Bearer "))
-    {
-        // Si hay token, validarlo
-        if let Ok(token_data) = decode::<Claims>(
-            auth_header,
-            &DecodingKey::from_secret(config.jwt_secret.as_ref()),
-            &Validation::default(),
-        ) {
-            let claims = token_data.claims;
-
-            // Verificar que el usuario existe
-            if let Ok(user_row) = sqlx::query!(
-                r#"
-                SELECT 
-                    id, company_id, user_type as "user_type: crate::models::user::UserType", user_status as "user_status: crate::models::user::UserStatus", username, 
-                    password_hash, created_at, updated_at, deleted_at
-                FROM users 
-                WHERE id = $1 
-                AND company_id = $2 
-                AND deleted_at IS NULL
-                "#,
-                Uuid::parse_str(&claims.sub).unwrap_or_default(),
-                Uuid::parse_str(&claims.company_id).unwrap_or_default()
-            )
-            .fetch_optional(&pool)
-            .await
-            {
-                if let Some(user_row) = user_row {
-                    // Usar los enums directamente
-                    let user_status = user_row.user_status;
-
-                    if user_status == UserStatus::Active {
-                        let user_type = user_row.user_type;
-
-                        let authenticated_user = AuthenticatedUser {
-                            user_id: user_row.id,
-                            company_id: user_row.company_id,
-                            user_type,
-                        };
-                        request.extensions_mut().insert(authenticated_user);
-                    }
-                }
-            }
+    // Si hay token de sesión válido, inyectar el usuario; si no, seguir sin él
+    if let Some(auth_header) = bearer_token(&request) {
+        if let Ok(authenticated_user) = authenticate(&state, auth_header, None).await {
+            request.extensions_mut().insert(authenticated_user);
         }
     }
 
@@ -217,6 +259,7 @@ pub fn generate_jwt_token(
         user_type: format!("{:?}", user_type).to_lowercase(),
         exp: expires_at.timestamp() as usize,
         iat: now.timestamp() as usize,
+        scope: None,
     };
 
     let encoding_key = jsonwebtoken::EncodingKey::from_secret(config.jwt_secret.as_ref());
@@ -224,3 +267,23 @@ pub fn generate_jwt_token(
     jsonwebtoken::encode(&jsonwebtoken::Header::default(), &claims, &encoding_key)
         .map_err(|e| AppError::Internal(format!("Error generando JWT: {}", e)))
 }
+
+/// Token de vida corta para abrir el canal en vivo con `?access_token=`
+pub fn generate_stream_token(user: &AuthenticatedUser, jwt_secret: &str) -> Result<String, AppError> {
+    let now = chrono::Utc::now();
+    let expires_at = now + chrono::Duration::seconds(DISPATCH_STREAM_TOKEN_TTL_SECONDS);
+
+    let claims = Claims {
+        sub: user.user_id.to_string(),
+        company_id: user.company_id.to_string(),
+        user_type: format!("{:?}", user.user_type).to_lowercase(),
+        exp: expires_at.timestamp() as usize,
+        iat: now.timestamp() as usize,
+        scope: Some(DISPATCH_STREAM_SCOPE.to_string()),
+    };
+
+    let encoding_key = jsonwebtoken::EncodingKey::from_secret(jwt_secret.as_ref());
+
+    jsonwebtoken::encode(&jsonwebtoken::Header::default(), &claims, &encoding_key)
+        .map_err(|e| AppError::Internal(format!("Error generando JWT: {}", e)))
+}
@@ -37,7 +37,7 @@ impl RateLimitState {
         Self {
             requests: Arc::new(RwLock::new(HashMap::new())),
             max_requests: config.rate_limit_requests,
-            window_duration: Duration::from_secs(config.rate_limit_window as u64),
+            window_duration: Duration::from_secs(config.rate_limit_window),
         }
     }
 
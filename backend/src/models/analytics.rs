@@ -3,9 +3,10 @@
 //! Este módulo contiene los modelos para métricas de rendimiento,
 //! análisis de datos y dashboards.
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
 use uuid::Uuid;
 use validator::Validate;
 
@@ -189,7 +190,7 @@ pub struct UpdateAnalyticsRequest {
 }
 
 /// Response de analytics
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct AnalyticsResponse {
     pub id: Uuid,
     pub company_id: Uuid,
@@ -230,19 +231,17 @@ pub struct AnalyticsResponse {
     pub updated_at: DateTime<Utc>,
 }
 
-/// Filtros para analytics
+/// Filtros para analytics (siempre acotados a la empresa del usuario)
 #[derive(Debug, Clone, Deserialize)]
 pub struct AnalyticsFilters {
-    pub company_id: Uuid,
     pub tournee_id: Option<Uuid>,
     pub driver_id: Option<Uuid>,
     pub vehicle_id: Option<Uuid>,
     pub date_from: Option<chrono::NaiveDate>,
     pub date_to: Option<chrono::NaiveDate>,
+    /// Rango de `route_efficiency` (media de `route_optimization_score`)
     pub min_efficiency: Option<f64>,
     pub max_efficiency: Option<f64>,
-    pub min_rating: Option<f64>,
-    pub max_rating: Option<f64>,
 }
 
 /// Lista paginada de analytics
@@ -254,3 +253,150 @@ pub struct AnalyticsListResponse {
     pub per_page: i32,
     pub total_pages: i32,
 }
+
+/// Semana de un chofer - mapea a la tabla performance_analytics
+///
+/// La calcula el job semanal (ver `services::performance_analytics`) a partir
+/// de las tournées completadas, sus paquetes, el combustible y los daños.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DriverWeekPerformance {
+    pub id: Uuid,
+    pub company_id: Uuid,
+    pub driver_id: Uuid,
+    pub week_start_date: NaiveDate,
+    pub week_end_date: NaiveDate,
+
+    // Métricas de entrega
+    pub total_packages: i32,
+    pub successful_deliveries: i32,
+    pub failed_deliveries: i32,
+    pub delivery_success_rate: Option<Decimal>,
+
+    // Métricas de distancia y combustible
+    pub km_driven: Decimal,
+    pub fuel_consumed: Decimal,
+    pub fuel_cost: Decimal,
+    /// L/100 km
+    pub fuel_efficiency: Option<Decimal>,
+
+    // Métricas de tiempo
+    pub total_working_hours: Option<Decimal>,
+    pub average_delivery_time_minutes: Option<Decimal>,
+    pub route_optimization_score: Option<Decimal>,
+
+    // Métricas de daños
+    pub damage_incidents: i32,
+    pub total_damage_cost: Decimal,
+    /// Incidentes por cada 1000 km
+    pub damage_score: Option<Decimal>,
+
+    // Métricas de rendimiento
+    /// Entregas por hora de trabajo
+    pub efficiency_ratio: Option<Decimal>,
+    pub cost_per_package: Option<Decimal>,
+    pub profit_margin: Option<Decimal>,
+
+    pub anomaly_flags: Option<serde_json::Value>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Columnas de performance_analytics en el orden de `DriverWeekPerformance`
+pub const DRIVER_WEEK_PERFORMANCE_COLUMNS: &str = r#"
+    id, company_id, driver_id, week_start_date, week_end_date, total_packages,
+    successful_deliveries, failed_deliveries, delivery_success_rate, km_driven,
+    fuel_consumed, fuel_cost, fuel_efficiency, total_working_hours,
+    average_delivery_time_minutes, route_optimization_score, damage_incidents,
+    total_damage_cost, damage_score, efficiency_ratio, cost_per_package,
+    profit_margin, anomaly_flags, created_at, updated_at
+"#;
+
+/// Filtros de las semanas calculadas (`?driver_id=&from=&to=`)
+#[derive(Debug, Clone, Deserialize)]
+pub struct WeeklyPerformanceFilters {
+    pub driver_id: Option<Uuid>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+/// Request para recalcular semanas pasadas tras corregir datos
+#[derive(Debug, Deserialize)]
+pub struct RecomputeWeeksRequest {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub driver_id: Option<Uuid>,
+}
+
+/// Resultado de una agregación semanal
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WeeklyAggregationSummary {
+    pub weeks: u64,
+    /// Filas de chofer y semana calculadas
+    pub drivers: u64,
+    /// Filas de vehículo y semana calculadas
+    pub vehicles: u64,
+    /// Filas borradas porque el chofer ya no tiene actividad esa semana
+    pub removed: u64,
+    /// Filas (de chofer o de vehículo) con alguna anomalía
+    pub flagged: u64,
+}
+
+/// Métrica vigilada por la detección de anomalías
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyMetric {
+    /// Porcentaje de entregas con éxito (malo si baja)
+    SuccessRate,
+    /// Permanencia media en parada, en minutos (malo si sube)
+    StopDwell,
+    /// Km por paquete entregado (malo si sube)
+    KmPerPackage,
+    /// Consumo en L/100 km (malo si sube)
+    FuelEfficiency,
+    /// Coste de combustible por km, en € (malo si sube)
+    CostPerKm,
+    /// Entregas seguidas a una velocidad imposible
+    ImplausibleSpeed,
+}
+
+impl AnomalyMetric {
+    pub fn label(&self) -> &'static str {
+        match self {
+            AnomalyMetric::SuccessRate => "tasa de éxito",
+            AnomalyMetric::StopDwell => "permanencia en parada",
+            AnomalyMetric::KmPerPackage => "km por paquete",
+            AnomalyMetric::FuelEfficiency => "consumo",
+            AnomalyMetric::CostPerKm => "coste por km",
+            AnomalyMetric::ImplausibleSpeed => "velocidad entre entregas",
+        }
+    }
+}
+
+/// Con qué se compara el valor de la semana
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyMethod {
+    /// z-score frente al histórico del propio chofer
+    DriverHistory,
+    /// Vallas de Tukey (IQR) frente al resto de choferes de la empresa
+    CompanyPeers,
+    /// z-score frente al histórico del propio vehículo
+    VehicleHistory,
+    /// Vallas de Tukey (IQR) frente al resto de vehículos de la empresa
+    FleetPeers,
+    /// Umbral fijo
+    Threshold,
+}
+
+/// Anomalía detectada en una semana (se guarda en `anomaly_flags.flags`)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AnomalyFlag {
+    pub metric: AnomalyMetric,
+    pub method: AnomalyMethod,
+    pub value: f64,
+    /// Media del histórico, mediana de la empresa o umbral
+    pub baseline: f64,
+    /// z-score, distancia a la valla en IQRs o nº de casos
+    pub score: f64,
+    pub explanation: String,
+}
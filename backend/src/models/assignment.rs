@@ -0,0 +1,145 @@
+//! Modelo del planificador de asignación de vehículos
+//!
+//! Cada mañana el planificador propone qué vehículo lleva cada chofer con
+//! tournée pendiente ese día; el despachador acepta la propuesta o cambia
+//! vehículos (con motivo) y lo aceptado queda en `vehicle_assignments` (ver
+//! `services::assignment_planner`).
+
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Tournée pendiente del día que necesita vehículo
+#[derive(Debug, Clone, FromRow)]
+pub struct PlannerDriver {
+    pub tournee_id: Uuid,
+    pub driver_id: Uuid,
+    pub driver_name: String,
+    pub license_categories: Vec<String>,
+    pub preferred_vehicle_id: Option<Uuid>,
+    pub current_vehicle_id: Uuid,
+    pub package_count: i64,
+}
+
+/// Vehículo de la flota con su disponibilidad para el día
+#[derive(Debug, Clone, FromRow)]
+pub struct PlannerVehicle {
+    pub id: Uuid,
+    pub license_plate: String,
+    pub required_license: String,
+    pub parcel_capacity: Option<i32>,
+    pub current_mileage: Decimal,
+    pub vehicle_status: String,
+    /// Seguro o contrôle technique caducado ese día
+    pub documents_expired: bool,
+    /// Cita de taller que se solapa con el día
+    pub in_maintenance: bool,
+    /// Ya lo lleva una tournée del día que no se replanifica
+    pub in_use: bool,
+}
+
+/// Por qué el planificador eligió (o no) un vehículo
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AssignmentReason {
+    /// Vehículo preferido del chofer
+    Preferred,
+    /// El de menos kilómetros entre los que puede llevar
+    LowestMileage,
+    /// Ningún vehículo disponible cumple permiso y capacidad
+    NoEligibleVehicle,
+}
+
+/// Por qué un vehículo queda fuera de la planificación
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExclusionReason {
+    Inactive,
+    Documents,
+    Maintenance,
+    InUse,
+}
+
+/// Propuesta para una tournée
+#[derive(Debug, Clone, Serialize)]
+pub struct ProposedAssignment {
+    pub tournee_id: Uuid,
+    pub driver_id: Uuid,
+    pub driver_name: String,
+    pub package_count: i64,
+    pub current_vehicle_id: Uuid,
+    pub vehicle_id: Option<Uuid>,
+    pub license_plate: Option<String>,
+    pub current_mileage: Option<Decimal>,
+    pub reason: AssignmentReason,
+}
+
+/// Vehículo descartado por el planificador
+#[derive(Debug, Clone, Serialize)]
+pub struct ExcludedVehicle {
+    pub vehicle_id: Uuid,
+    pub license_plate: String,
+    pub reason: ExclusionReason,
+}
+
+/// Respuesta de `GET /assignments/proposal`
+#[derive(Debug, Clone, Serialize)]
+pub struct AssignmentProposal {
+    pub tournee_date: NaiveDate,
+    pub assignments: Vec<ProposedAssignment>,
+    pub excluded: Vec<ExcludedVehicle>,
+}
+
+/// Vehículo elegido para una tournée
+///
+/// Si no coincide con el propuesto es un cambio del despachador y
+/// `override_reason` es obligatorio.
+#[derive(Debug, Deserialize, Validate)]
+pub struct AcceptedAssignment {
+    pub tournee_id: Uuid,
+    pub vehicle_id: Uuid,
+    #[validate(length(min = 3, max = 500))]
+    pub override_reason: Option<String>,
+}
+
+/// Request para aceptar (o corregir) la propuesta del día
+#[derive(Debug, Deserialize, Validate)]
+pub struct AcceptAssignmentsRequest {
+    pub tournee_date: NaiveDate,
+    #[validate]
+    pub assignments: Vec<AcceptedAssignment>,
+}
+
+/// Asignación aceptada - mapea a la tabla vehicle_assignments
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct VehicleAssignment {
+    pub id: Uuid,
+    pub company_id: Uuid,
+    pub tournee_id: Uuid,
+    pub driver_id: Uuid,
+    pub assignment_date: NaiveDate,
+    pub vehicle_id: Uuid,
+    pub proposed_vehicle_id: Option<Uuid>,
+    pub overridden: bool,
+    pub override_reason: Option<String>,
+    pub accepted_by: Option<Uuid>,
+    pub accepted_at: DateTime<Utc>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Columnas de vehicle_assignments en el orden de `VehicleAssignment`
+pub const VEHICLE_ASSIGNMENT_COLUMNS: &str = r#"
+    id, company_id, tournee_id, driver_id, assignment_date, vehicle_id,
+    proposed_vehicle_id, overridden, override_reason, accepted_by,
+    accepted_at, created_at, updated_at
+"#;
+
+/// Filtro de `GET /assignments`
+#[derive(Debug, Deserialize)]
+pub struct AssignmentFilters {
+    pub date: NaiveDate,
+}
@@ -24,6 +24,13 @@ pub struct Company {
     pub deleted_at: Option<DateTime<Utc>>,
 }
 
+/// Columnas de companies en el orden de `Company` (para consultas runtime)
+pub const COMPANY_COLUMNS: &str = r#"
+    id, name, address, subscription_plan, subscription_status, max_drivers,
+    max_vehicles, COALESCE(created_at, NOW()) AS created_at,
+    COALESCE(updated_at, NOW()) AS updated_at, deleted_at
+"#;
+
 /// Request para crear una nueva company
 #[derive(Debug, Deserialize, Validate)]
 pub struct CreateCompanyRequest {
@@ -0,0 +1,221 @@
+//! Modelo del compte-rendu de fin de día
+//!
+//! Al final del día cada tournée se cuadra: entregados, fallidos, relais y
+//! devueltos según nuestros estados de paquetes y lecturas, frente al
+//! recuento del transportista (`BeanTournee.nb_colis_*`, ver
+//! `models::colis_prive_web_models`). La firma del compte-rendu bloquea la
+//! tournée (ver `services::compte_rendu`).
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Estado del compte-rendu - mapea a compte_rendus.report_status
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CompteRenduStatus {
+    Draft,
+    Signed,
+}
+
+impl CompteRenduStatus {
+    /// Valor de la columna report_status
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompteRenduStatus::Draft => "draft",
+            CompteRenduStatus::Signed => "signed",
+        }
+    }
+}
+
+/// Resultado de un paquete en la tournée
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageOutcome {
+    Delivered,
+    Failed,
+    /// Redirigido a un punto relais
+    Relay,
+    Returned,
+    /// Sin resolver al cerrar la tournée, pasado a la siguiente
+    CarriedOver,
+    /// Sigue abierto en la tournée
+    Unresolved,
+    Cancelled,
+}
+
+impl PackageOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PackageOutcome::Delivered => "delivered",
+            PackageOutcome::Failed => "failed",
+            PackageOutcome::Relay => "relay",
+            PackageOutcome::Returned => "returned",
+            PackageOutcome::CarriedOver => "carried_over",
+            PackageOutcome::Unresolved => "unresolved",
+            PackageOutcome::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// Recuento del transportista, con los nombres de `BeanTournee`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate)]
+pub struct CarrierCounts {
+    #[validate(range(min = 0))]
+    pub nb_colis: Option<i32>,
+    #[validate(range(min = 0))]
+    pub nb_colis_distribue: Option<i32>,
+    #[validate(range(min = 0))]
+    pub nb_colis_traite: Option<i32>,
+    #[validate(range(min = 0))]
+    pub nb_colis_relais: Option<i32>,
+    #[validate(range(min = 0))]
+    pub nb_colis_restant_adistribue: Option<i32>,
+    #[validate(range(min = 0))]
+    pub nb_colis_acollecter: Option<i32>,
+    #[validate(range(min = 0))]
+    pub nb_colis_collecte: Option<i32>,
+}
+
+/// Recuento propio de la tournée
+#[derive(Debug, Clone, Default, Serialize, PartialEq, Eq)]
+pub struct CompteRenduCounts {
+    pub expected_packages: i32,
+    pub delivered: i32,
+    pub failed: i32,
+    pub relay: i32,
+    pub returned: i32,
+    pub carried_over: i32,
+    pub unresolved: i32,
+    pub cancelled: i32,
+    /// Paquetes con lectura de carga válida
+    pub loaded_scans: i32,
+    /// Paquetes con lectura de entrega válida
+    pub delivered_scans: i32,
+    /// Lecturas marcadas (código desconocido, otra tournée, duplicadas)
+    pub flagged_scans: i32,
+    /// Bultos previstos en las recogidas
+    pub pickup_expected_parcels: i32,
+    /// Bultos recogidos (leídos)
+    pub collected_parcels: i32,
+}
+
+impl CompteRenduCounts {
+    /// Paquetes tratados: con un resultado final en la tournée
+    pub fn processed(&self) -> i32 {
+        self.delivered + self.failed + self.relay + self.returned
+    }
+}
+
+/// Origen de la cifra con la que se compara
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MismatchSource {
+    /// Recuento del transportista
+    Carrier,
+    /// Nuestras propias lecturas de código de barras
+    Scans,
+}
+
+/// Diferencia entre nuestro recuento y una cifra de referencia
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Mismatch {
+    pub field: String,
+    pub source: MismatchSource,
+    pub ours: i32,
+    pub reference: i32,
+    /// `ours - reference`
+    pub difference: i32,
+}
+
+/// Compte-rendu - mapea a la tabla compte_rendus
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct CompteRendu {
+    pub id: Uuid,
+    pub company_id: Uuid,
+    pub tournee_id: Uuid,
+    pub driver_id: Uuid,
+    pub tournee_date: NaiveDate,
+    pub expected_packages: i32,
+    pub delivered_count: i32,
+    pub failed_count: i32,
+    pub relay_count: i32,
+    pub returned_count: i32,
+    pub carried_over_count: i32,
+    pub unresolved_count: i32,
+    pub cancelled_count: i32,
+    pub loaded_scans: i32,
+    pub delivered_scans: i32,
+    pub flagged_scans: i32,
+    pub pickup_expected_parcels: i32,
+    pub collected_parcels: i32,
+    /// `CarrierCounts` enviado al generar
+    pub carrier_counts: Option<Value>,
+    /// Lista de `Mismatch`
+    pub mismatches: Value,
+    pub report_status: String,
+    pub signed_by: Option<Uuid>,
+    pub signed_at: Option<DateTime<Utc>>,
+    pub signature_name: Option<String>,
+    pub signature_comment: Option<String>,
+    pub generated_at: DateTime<Utc>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Columnas de compte_rendus en el orden de `CompteRendu`
+pub const COMPTE_RENDU_COLUMNS: &str = r#"
+    id, company_id, tournee_id, driver_id, tournee_date, expected_packages,
+    delivered_count, failed_count, relay_count, returned_count,
+    carried_over_count, unresolved_count, cancelled_count, loaded_scans,
+    delivered_scans, flagged_scans, pickup_expected_parcels, collected_parcels,
+    carrier_counts, mismatches, report_status, signed_by, signed_at,
+    signature_name, signature_comment, generated_at, created_at, updated_at
+"#;
+
+/// Línea de paquete del compte-rendu
+#[derive(Debug, Clone, Serialize)]
+pub struct CompteRenduLine {
+    pub package_id: Uuid,
+    pub tracking_number: String,
+    pub outcome: PackageOutcome,
+    pub delivery_status: String,
+    pub failure_reason: Option<String>,
+    pub loaded: bool,
+    pub delivery_scanned: bool,
+}
+
+/// Compte-rendu con el detalle por paquete
+#[derive(Debug, Clone, Serialize)]
+pub struct CompteRenduReport {
+    #[serde(flatten)]
+    pub compte_rendu: CompteRendu,
+    pub lines: Vec<CompteRenduLine>,
+}
+
+/// Request para generar (o regenerar) el compte-rendu
+#[derive(Debug, Default, Deserialize, Validate)]
+pub struct GenerateCompteRenduRequest {
+    #[validate]
+    pub carrier_counts: Option<CarrierCounts>,
+}
+
+/// Request para firmar el compte-rendu
+#[derive(Debug, Deserialize, Validate)]
+pub struct SignCompteRenduRequest {
+    #[validate(length(min = 1, max = 255))]
+    pub signature_name: String,
+    #[validate(length(max = 1000))]
+    pub comment: Option<String>,
+    /// Obligatorio si hay diferencias
+    pub acknowledge_mismatches: Option<bool>,
+}
+
+/// Formato de exportación (`json`, `csv` o `pdf`)
+#[derive(Debug, Deserialize)]
+pub struct CompteRenduParams {
+    pub format: Option<String>,
+}
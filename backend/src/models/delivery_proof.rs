@@ -0,0 +1,153 @@
+//! Modelo de prueba de entrega (proof of delivery)
+//!
+//! Una prueba por paquete entregado: fotos, firma, nombre de quien recibe,
+//! posición GPS con su precisión y los relojes del dispositivo y del servidor.
+//! Cada prueba se encadena con la anterior de la empresa mediante SHA-256
+//! (ver `services::delivery_proof`) para que cualquier modificación posterior
+//! de la evidencia sea detectable.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Prueba de entrega - mapea a la tabla delivery_proofs
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DeliveryProof {
+    pub id: Uuid,
+    pub company_id: Uuid,
+    pub package_id: Uuid,
+    pub tournee_id: Uuid,
+    pub captured_by: Option<Uuid>,
+
+    // Evidencia
+    pub tracking_number: String,
+    pub recipient_name: String,
+    pub photo_media_ids: Vec<Uuid>,
+    pub photo_sha256: Vec<String>,
+    pub signature_media_id: Option<Uuid>,
+    pub signature_sha256: Option<String>,
+
+    // Posición GPS y tiempos
+    pub latitude: f64,
+    pub longitude: f64,
+    pub gps_accuracy_meters: f64,
+    pub device_id: Option<String>,
+    pub device_timestamp: DateTime<Utc>,
+    pub server_timestamp: DateTime<Utc>,
+
+    // Cadena de hashes
+    pub chain_position: i64,
+    pub previous_hash: String,
+    pub evidence_hash: String,
+    pub record_hash: String,
+
+    // Metadatos
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Evidencia capturada por la app al entregar
+///
+/// Las fotos y la firma se suben antes con `POST /api/v1/media/...` sobre el
+/// paquete y aquí se referencian por id.
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct ProofOfDeliveryRequest {
+    #[validate(length(min = 1, max = 255))]
+    pub recipient_name: String,
+
+    #[validate(length(max = 10))]
+    #[serde(default)]
+    pub photo_media_ids: Vec<Uuid>,
+    pub signature_media_id: Option<Uuid>,
+
+    #[validate(range(min = -90.0, max = 90.0))]
+    pub latitude: f64,
+    #[validate(range(min = -180.0, max = 180.0))]
+    pub longitude: f64,
+    #[validate(range(min = 0.0, max = 10000.0))]
+    pub gps_accuracy_meters: f64,
+
+    #[validate(length(max = 100))]
+    pub device_id: Option<String>,
+    pub device_timestamp: DateTime<Utc>,
+}
+
+/// Formato del resumen de la prueba
+#[derive(Debug, Deserialize)]
+pub struct ProofSummaryParams {
+    /// `json` (por defecto) o `html` (documento imprimible)
+    pub format: Option<String>,
+}
+
+/// Archivo de la evidencia con su URL firmada
+#[derive(Debug, Clone, Serialize)]
+pub struct ProofMedia {
+    pub media_id: Uuid,
+    pub sha256: String,
+    pub url: Option<String>,
+}
+
+/// Resumen para atención al cliente
+#[derive(Debug, Clone, Serialize)]
+pub struct DeliveryProofSummary {
+    pub proof_id: Uuid,
+    pub package_id: Uuid,
+    pub tracking_number: String,
+    pub delivery_address: String,
+    pub addressee_name: Option<String>,
+    pub recipient_name: String,
+    pub driver_name: Option<String>,
+
+    pub latitude: f64,
+    pub longitude: f64,
+    pub gps_accuracy_meters: f64,
+    pub device_id: Option<String>,
+    pub device_timestamp: DateTime<Utc>,
+    pub server_timestamp: DateTime<Utc>,
+    /// Reloj del dispositivo menos reloj del servidor
+    pub clock_skew_seconds: i64,
+
+    pub photos: Vec<ProofMedia>,
+    pub signature: Option<ProofMedia>,
+
+    pub chain_position: i64,
+    pub previous_hash: String,
+    pub evidence_hash: String,
+    pub record_hash: String,
+    pub verification: ProofVerification,
+}
+
+/// Resultado de una comprobación de integridad
+#[derive(Debug, Clone, Serialize)]
+pub struct ProofCheck {
+    pub check: String,
+    pub ok: bool,
+    pub detail: Option<String>,
+}
+
+/// Resultado de verificar una prueba o la cadena completa
+#[derive(Debug, Clone, Serialize)]
+pub struct ProofVerification {
+    pub valid: bool,
+    pub checks: Vec<ProofCheck>,
+}
+
+impl ProofVerification {
+    pub fn from_checks(checks: Vec<ProofCheck>) -> Self {
+        Self {
+            valid: checks.iter().all(|check| check.ok),
+            checks,
+        }
+    }
+}
+
+/// Resultado de verificar la cadena de pruebas de la empresa
+#[derive(Debug, Clone, Serialize)]
+pub struct ProofChainVerification {
+    pub valid: bool,
+    pub proofs_checked: usize,
+    /// Primera posición de la cadena que no cuadra
+    pub first_invalid_position: Option<i64>,
+    pub detail: Option<String>,
+}
@@ -0,0 +1,183 @@
+//! Modelo del canal de seguimiento en vivo
+//!
+//! Eventos que el back-office recibe en tiempo real por Server-Sent Events:
+//! posiciones de los choferes, cambios de estado de paquetes y tournées,
+//! cambios de ETA y alertas (ver `services::dispatch_feed`).
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::models::{
+    package::Package,
+    stop_visit::{StopEventKind, StopVisitEvent, TourneeEta},
+    tournee::Tournee,
+};
+
+/// Tipo de evento - es el campo `event` del mensaje SSE
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DispatchEventType {
+    DriverPosition,
+    PackageStatus,
+    TourneeStatus,
+    /// Llegada a una parada detectada por geocerca
+    StopArrival,
+    /// Salida de una parada, con el tiempo de permanencia
+    StopDeparture,
+    /// Horas estimadas de llegada recalculadas
+    Eta,
+    Alert,
+}
+
+impl DispatchEventType {
+    /// Nombre del evento SSE
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DispatchEventType::DriverPosition => "driver_position",
+            DispatchEventType::PackageStatus => "package_status",
+            DispatchEventType::TourneeStatus => "tournee_status",
+            DispatchEventType::StopArrival => "stop_arrival",
+            DispatchEventType::StopDeparture => "stop_departure",
+            DispatchEventType::Eta => "eta",
+            DispatchEventType::Alert => "alert",
+        }
+    }
+}
+
+/// Evento del canal en vivo (se publica en Redis como JSON)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DispatchEvent {
+    pub event_type: DispatchEventType,
+    pub company_id: Uuid,
+    pub tournee_id: Option<Uuid>,
+    pub driver_id: Option<Uuid>,
+    pub package_id: Option<Uuid>,
+    /// Contenido propio de cada tipo de evento
+    pub data: Value,
+    pub occurred_at: DateTime<Utc>,
+}
+
+impl DispatchEvent {
+    /// Posición más reciente de un chofer
+    pub fn driver_position(
+        company_id: Uuid,
+        driver_id: Uuid,
+        tournee_id: Option<Uuid>,
+        latitude: f64,
+        longitude: f64,
+        recorded_at: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            event_type: DispatchEventType::DriverPosition,
+            company_id,
+            tournee_id,
+            driver_id: Some(driver_id),
+            package_id: None,
+            data: json!({
+                "latitude": latitude,
+                "longitude": longitude,
+                "recorded_at": recorded_at,
+            }),
+            occurred_at: Utc::now(),
+        }
+    }
+
+    /// Nuevo estado de un paquete
+    pub fn package_status(package: &Package) -> Self {
+        Self {
+            event_type: DispatchEventType::PackageStatus,
+            company_id: package.company_id,
+            tournee_id: Some(package.tournee_id),
+            driver_id: None,
+            package_id: Some(package.id),
+            data: json!({
+                "tracking_number": package.tracking_number,
+                "delivery_status": package.delivery_status.as_str(),
+                "delivery_attempts": package.delivery_attempts,
+                "failure_reason": package.failure_reason.as_ref().map(|r| r.as_str()),
+                "next_action": package.next_action,
+            }),
+            occurred_at: Utc::now(),
+        }
+    }
+
+    /// Nuevo estado de una tournée
+    pub fn tournee_status(tournee: &Tournee) -> Self {
+        Self {
+            event_type: DispatchEventType::TourneeStatus,
+            company_id: tournee.company_id,
+            tournee_id: Some(tournee.id),
+            driver_id: Some(tournee.driver_id),
+            package_id: None,
+            data: json!({
+                "tournee_status": tournee.tournee_status.as_str(),
+                "tournee_number": tournee.tournee_number,
+            }),
+            occurred_at: Utc::now(),
+        }
+    }
+
+    /// Llegada o salida de una parada
+    pub fn stop_visit(company_id: Uuid, driver_id: Uuid, event: &StopVisitEvent) -> Self {
+        Self {
+            event_type: match event.kind {
+                StopEventKind::Arrival => DispatchEventType::StopArrival,
+                StopEventKind::Departure => DispatchEventType::StopDeparture,
+            },
+            company_id,
+            tournee_id: Some(event.tournee_id),
+            driver_id: Some(driver_id),
+            package_id: None,
+            data: serde_json::to_value(event).unwrap_or(Value::Null),
+            occurred_at: Utc::now(),
+        }
+    }
+
+    /// ETAs recalculadas de las paradas pendientes de una tournée
+    pub fn eta(company_id: Uuid, driver_id: Uuid, eta: &TourneeEta) -> Self {
+        Self {
+            event_type: DispatchEventType::Eta,
+            company_id,
+            tournee_id: Some(eta.tournee_id),
+            driver_id: Some(driver_id),
+            package_id: None,
+            data: serde_json::to_value(eta).unwrap_or(Value::Null),
+            occurred_at: Utc::now(),
+        }
+    }
+
+    /// Alerta para el dispatcher (`kind` identifica el origen, p. ej. `scan_flagged`)
+    pub fn alert(company_id: Uuid, tournee_id: Option<Uuid>, kind: &str, message: String, details: Value) -> Self {
+        Self {
+            event_type: DispatchEventType::Alert,
+            company_id,
+            tournee_id,
+            driver_id: None,
+            package_id: None,
+            data: json!({
+                "kind": kind,
+                "message": message,
+                "details": details,
+            }),
+            occurred_at: Utc::now(),
+        }
+    }
+}
+
+/// Token de vida corta para abrir el canal en vivo desde `EventSource`
+#[derive(Debug, Serialize)]
+pub struct DispatchStreamToken {
+    /// Se pasa como `?access_token=` en `GET /api/v1/dispatch/stream`
+    pub token: String,
+    /// Segundos que tiene el cliente para abrir la conexión
+    pub expires_in: i64,
+}
+
+/// Parámetros de suscripción al canal en vivo
+#[derive(Debug, Deserialize)]
+pub struct DispatchStreamParams {
+    /// Solo los eventos de esta tournée (por defecto, toda la empresa)
+    pub tournee_id: Option<Uuid>,
+}
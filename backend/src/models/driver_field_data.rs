@@ -0,0 +1,304 @@
+//! Modelo de DriverFieldData
+//!
+//! Este módulo contiene el struct DriverFieldData (base de conocimiento de
+//! terreno de los choferes: códigos de puerta, acceso, buzón, preferencias)
+//! y sus variantes para CRUD operations.
+
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use validator::Validate;
+use chrono::{DateTime, NaiveDate, Utc};
+use uuid::Uuid;
+
+/// DriverFieldData principal - mapea a la tabla driver_field_data
+///
+/// `coordinates` (POINT) se expone como `latitude`/`longitude`
+/// (x = longitud, y = latitud).
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DriverFieldData {
+    pub id: Uuid,
+    pub company_id: Uuid,
+    pub driver_id: Uuid,
+
+    // Ubicación
+    pub address: String,
+    pub normalized_address: Option<String>,
+    pub postal_code: Option<String>,
+    pub city: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+
+    // Información de acceso (cifrada en base de datos, ver services::field_encryption)
+    pub door_codes: Option<String>,
+    pub access_instructions: Option<String>,
+    pub security_notes: Option<String>,
+
+    // Estado del buzón
+    pub mailbox_location: Option<String>,
+    pub mailbox_working: Option<bool>,
+    pub mailbox_issues: Option<String>,
+
+    // Horarios y preferencias
+    pub preferred_delivery_time: Option<String>,
+    pub delivery_restrictions: Option<String>,
+    pub special_instructions: Option<String>,
+
+    // Calidad de los datos
+    pub confidence_score: Option<i32>,
+    pub data_source: Option<String>,
+    pub verification_count: Option<i32>,
+
+    // Última actualización
+    pub last_updated_by: Option<Uuid>,
+    pub last_verified_date: Option<NaiveDate>,
+    /// Versión actual (número de la última revisión)
+    pub version: i32,
+
+    // Metadatos
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+/// Request para crear una entrada de conocimiento de terreno
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateDriverFieldDataRequest {
+    #[validate(length(min = 5, max = 500))]
+    pub address: String,
+
+    #[validate(length(max = 20))]
+    pub postal_code: Option<String>,
+
+    #[validate(length(max = 100))]
+    pub city: Option<String>,
+
+    #[validate(range(min = -90.0, max = 90.0))]
+    pub latitude: Option<f64>,
+
+    #[validate(range(min = -180.0, max = 180.0))]
+    pub longitude: Option<f64>,
+
+    pub door_codes: Option<String>,
+    pub access_instructions: Option<String>,
+    pub security_notes: Option<String>,
+
+    pub mailbox_location: Option<String>,
+    pub mailbox_working: Option<bool>,
+    pub mailbox_issues: Option<String>,
+
+    #[validate(length(max = 100))]
+    pub preferred_delivery_time: Option<String>,
+    pub delivery_restrictions: Option<String>,
+    pub special_instructions: Option<String>,
+
+    #[validate(range(min = 1, max = 5))]
+    pub confidence_score: Option<i32>,
+
+    #[validate(length(max = 50))]
+    pub data_source: Option<String>,
+}
+
+/// Request para actualizar una entrada existente
+///
+/// `version` es la versión leída por el cliente: si otro chofer modificó la
+/// entrada entretanto, la actualización se rechaza con 409.
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateDriverFieldDataRequest {
+    #[validate(range(min = 1))]
+    pub version: i32,
+
+    #[validate(length(min = 5, max = 500))]
+    pub address: Option<String>,
+
+    #[validate(length(max = 20))]
+    pub postal_code: Option<String>,
+
+    #[validate(length(max = 100))]
+    pub city: Option<String>,
+
+    #[validate(range(min = -90.0, max = 90.0))]
+    pub latitude: Option<f64>,
+
+    #[validate(range(min = -180.0, max = 180.0))]
+    pub longitude: Option<f64>,
+
+    pub door_codes: Option<String>,
+    pub access_instructions: Option<String>,
+    pub security_notes: Option<String>,
+
+    pub mailbox_location: Option<String>,
+    pub mailbox_working: Option<bool>,
+    pub mailbox_issues: Option<String>,
+
+    #[validate(length(max = 100))]
+    pub preferred_delivery_time: Option<String>,
+    pub delivery_restrictions: Option<String>,
+    pub special_instructions: Option<String>,
+
+    #[validate(range(min = 1, max = 5))]
+    pub confidence_score: Option<i32>,
+}
+
+/// Filtros para búsqueda de conocimiento de terreno
+#[derive(Debug, Deserialize)]
+pub struct DriverFieldDataFilters {
+    /// Texto libre sobre dirección, ciudad o instrucciones especiales
+    pub q: Option<String>,
+    pub postal_code: Option<String>,
+    pub city: Option<String>,
+    pub driver_id: Option<Uuid>,
+    pub mailbox_working: Option<bool>,
+    pub min_confidence: Option<i32>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// Parámetros de lookup: por dirección normalizada o por proximidad
+#[derive(Debug, Deserialize)]
+pub struct DriverFieldDataLookup {
+    pub address: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Radio de búsqueda en metros (por defecto 30 m)
+    pub radius_meters: Option<f64>,
+}
+
+/// Resultado de un lookup con la distancia al punto buscado
+#[derive(Debug, Clone, Serialize)]
+pub struct DriverFieldDataMatch {
+    #[serde(flatten)]
+    pub entry: DriverFieldData,
+    /// Cómo se encontró la entrada: `address` o `proximity`
+    pub matched_by: String,
+    pub distance_meters: Option<f64>,
+}
+
+/// Tipo de cambio registrado en una revisión
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RevisionChangeType {
+    Create,
+    Update,
+    Revert,
+    Delete,
+}
+
+impl RevisionChangeType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RevisionChangeType::Create => "create",
+            RevisionChangeType::Update => "update",
+            RevisionChangeType::Revert => "revert",
+            RevisionChangeType::Delete => "delete",
+        }
+    }
+}
+
+/// Revisión inmutable - mapea a la tabla driver_field_data_revisions
+///
+/// `diff` tiene la forma `{"campo": {"old": ..., "new": ...}}` y `snapshot`
+/// el estado completo tras el cambio (campos sensibles cifrados).
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DriverFieldDataRevision {
+    pub id: Uuid,
+    pub company_id: Uuid,
+    pub field_data_id: Uuid,
+    pub revision_number: i32,
+    pub change_type: String,
+    pub author_id: Option<Uuid>,
+    pub diff: serde_json::Value,
+    pub snapshot: serde_json::Value,
+    pub reverted_from_revision: Option<i32>,
+    pub verified_by: Option<Uuid>,
+    pub verified_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Request para revertir una entrada a una revisión anterior
+#[derive(Debug, Deserialize, Validate)]
+pub struct RevertDriverFieldDataRequest {
+    #[validate(range(min = 1))]
+    pub revision_number: i32,
+    /// Versión actual conocida por el cliente
+    #[validate(range(min = 1))]
+    pub version: i32,
+}
+
+/// Registro de auditoría de lectura de datos sensibles
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct FieldDataAccessLog {
+    pub id: Uuid,
+    pub company_id: Uuid,
+    pub field_data_id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub field_name: String,
+    pub access_context: String,
+    pub accessed_at: Option<DateTime<Utc>>,
+}
+
+impl DriverFieldData {
+    /// Orden de confianza: más verificaciones, mayor score, verificación más reciente
+    pub fn trust_key(&self) -> (i32, i32, Option<NaiveDate>, Option<DateTime<Utc>>) {
+        (
+            self.verification_count.unwrap_or(0),
+            self.confidence_score.unwrap_or(0),
+            self.last_verified_date,
+            self.updated_at,
+        )
+    }
+}
+
+/// Normalizar una dirección para comparaciones (mayúsculas, sin acentos,
+/// sin puntuación y con abreviaturas de vía expandidas)
+pub fn normalize_address(address: &str) -> String {
+    let mut normalized = String::with_capacity(address.len());
+    for c in address.chars() {
+        let c = match c {
+            'à' | 'â' | 'ä' | 'À' | 'Â' | 'Ä' => 'A',
+            'é' | 'è' | 'ê' | 'ë' | 'É' | 'È' | 'Ê' | 'Ë' => 'E',
+            'î' | 'ï' | 'Î' | 'Ï' => 'I',
+            'ô' | 'ö' | 'Ô' | 'Ö' => 'O',
+            'ù' | 'û' | 'ü' | 'Ù' | 'Û' | 'Ü' => 'U',
+            'ç' | 'Ç' => 'C',
+            c if c.is_alphanumeric() => c.to_ascii_uppercase(),
+            _ => ' ',
+        };
+        normalized.push(c);
+    }
+
+    normalized
+        .split_whitespace()
+        .map(|word| match word {
+            "BD" | "BLD" | "BVD" => "BOULEVARD",
+            "AV" | "AVE" => "AVENUE",
+            "PL" => "PLACE",
+            "IMP" => "IMPASSE",
+            "ALL" => "ALLEE",
+            "CHE" | "CHEM" => "CHEMIN",
+            "RTE" => "ROUTE",
+            "SQ" => "SQUARE",
+            "FBG" | "FG" => "FAUBOURG",
+            "ST" => "SAINT",
+            "STE" => "SAINTE",
+            other => other,
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_address() {
+        assert_eq!(
+            normalize_address("12, av. de l'Église  75018 Paris"),
+            "12 AVENUE DE L EGLISE 75018 PARIS"
+        );
+        assert_eq!(
+            normalize_address("3 BD ST-MICHEL"),
+            normalize_address("3 boulevard saint michel")
+        );
+    }
+}
@@ -0,0 +1,192 @@
+//! Modelo del cuadre de kilometraje y combustible
+//!
+//! Cada tournée cerrada se cuadra contra tres fuentes: la distancia del
+//! cuentakilómetros frente a la ruta prevista y al recorrido GPS, y el
+//! combustible declarado frente al consumo de referencia del vehículo. Los
+//! repostajes de la tarjeta de combustible se importan desde el CSV del
+//! emisor y se asocian al vehículo y a la tournée del día (ver
+//! `services::fuel_reconciliation`).
+
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Asociación de un repostaje - mapea a fuel_card_transactions.match_status
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FuelMatchStatus {
+    /// Vehículo y tournée del día
+    Matched,
+    /// Vehículo identificado, sin tournée ese día
+    VehicleOnly,
+    /// Ni la matrícula ni la tarjeta corresponden a un vehículo
+    Unmatched,
+}
+
+impl FuelMatchStatus {
+    /// Valor de la columna match_status
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FuelMatchStatus::Matched => "matched",
+            FuelMatchStatus::VehicleOnly => "vehicle_only",
+            FuelMatchStatus::Unmatched => "unmatched",
+        }
+    }
+}
+
+/// Anomalía detectada en el cuadre - se guarda en fuel_reconciliations.flags
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FuelFlag {
+    /// La tournée se cerró sin kilometraje válido
+    OdometerMissing,
+    /// El cuentakilómetros no cuadra con el recorrido GPS
+    OdometerVsGps,
+    /// El cuentakilómetros no cuadra con la ruta prevista
+    OdometerVsPlanned,
+    /// Consumo fuera del margen del consumo de referencia
+    FuelDeviation,
+    /// La semana supera la dotación de combustible del vehículo
+    WeeklyAllocationExceeded,
+}
+
+impl FuelFlag {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FuelFlag::OdometerMissing => "odometer_missing",
+            FuelFlag::OdometerVsGps => "odometer_vs_gps",
+            FuelFlag::OdometerVsPlanned => "odometer_vs_planned",
+            FuelFlag::FuelDeviation => "fuel_deviation",
+            FuelFlag::WeeklyAllocationExceeded => "weekly_allocation_exceeded",
+        }
+    }
+
+    /// Desviaciones que generan un `fuel_consumption_alert`
+    pub fn is_alert(&self) -> bool {
+        !matches!(self, FuelFlag::OdometerMissing)
+    }
+}
+
+/// Repostaje de tarjeta - mapea a la tabla fuel_card_transactions
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct FuelCardTransaction {
+    pub id: Uuid,
+    pub company_id: Uuid,
+    pub card_number: String,
+    pub license_plate: Option<String>,
+    pub transacted_at: DateTime<Utc>,
+    pub station: Option<String>,
+    pub product: Option<String>,
+    pub liters: Decimal,
+    pub amount: Decimal,
+    pub vehicle_id: Option<Uuid>,
+    pub tournee_id: Option<Uuid>,
+    pub driver_id: Option<Uuid>,
+    pub match_status: String,
+    pub import_batch: Uuid,
+    pub imported_by: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Columnas de fuel_card_transactions en el orden de `FuelCardTransaction`
+pub const FUEL_CARD_TRANSACTION_COLUMNS: &str = r#"
+    id, company_id, card_number, license_plate, transacted_at, station, product,
+    liters, amount, vehicle_id, tournee_id, driver_id, match_status,
+    import_batch, imported_by, created_at
+"#;
+
+/// Cuadre de una tournée - mapea a la tabla fuel_reconciliations
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct FuelReconciliation {
+    pub id: Uuid,
+    pub company_id: Uuid,
+    pub tournee_id: Uuid,
+    pub vehicle_id: Uuid,
+    pub driver_id: Uuid,
+    pub tournee_date: NaiveDate,
+
+    // Distancias (km)
+    pub odometer_km: Option<Decimal>,
+    pub planned_km: Option<Decimal>,
+    /// `route` (polilínea de la tournée) o `stops` (paradas geocodificadas)
+    pub planned_source: Option<String>,
+    pub gps_km: Option<Decimal>,
+    pub odometer_gps_deviation_pct: Option<Decimal>,
+    pub odometer_planned_deviation_pct: Option<Decimal>,
+
+    // Combustible (L)
+    pub fuel_consumed: Option<Decimal>,
+    /// L/100 km usados: los del vehículo o su media histórica
+    pub expected_consumption: Option<Decimal>,
+    pub expected_fuel: Option<Decimal>,
+    pub fuel_deviation_pct: Option<Decimal>,
+    pub week_fuel_consumed: Option<Decimal>,
+    pub weekly_fuel_allocation: Option<Decimal>,
+    pub card_liters: Decimal,
+    pub card_amount: Decimal,
+
+    // Resultado
+    /// Lista de `FuelFlag`
+    pub flags: Value,
+    pub alerted_at: Option<DateTime<Utc>>,
+    pub computed_at: DateTime<Utc>,
+
+    // Metadatos
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Columnas de fuel_reconciliations en el orden de `FuelReconciliation`
+pub const FUEL_RECONCILIATION_COLUMNS: &str = r#"
+    id, company_id, tournee_id, vehicle_id, driver_id, tournee_date,
+    odometer_km, planned_km, planned_source, gps_km, odometer_gps_deviation_pct,
+    odometer_planned_deviation_pct, fuel_consumed, expected_consumption,
+    expected_fuel, fuel_deviation_pct, week_fuel_consumed, weekly_fuel_allocation,
+    card_liters, card_amount, flags, alerted_at, computed_at, created_at,
+    updated_at
+"#;
+
+/// Filtros de repostajes importados
+#[derive(Debug, Deserialize)]
+pub struct FuelTransactionFilters {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub vehicle_id: Option<Uuid>,
+    pub match_status: Option<FuelMatchStatus>,
+}
+
+/// Filtros de cuadres (`flagged=true` solo los que tienen anomalías)
+#[derive(Debug, Deserialize)]
+pub struct FuelReconciliationFilters {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub vehicle_id: Option<Uuid>,
+    pub flagged: Option<bool>,
+}
+
+/// Línea del CSV que no se pudo importar
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct FuelImportError {
+    /// Línea del fichero (la cabecera es la 1)
+    pub line: usize,
+    pub message: String,
+}
+
+/// Resultado de la importación de un CSV de tarjeta
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FuelImportSummary {
+    pub import_batch: Uuid,
+    pub rows: usize,
+    pub imported: usize,
+    /// Repostajes ya importados antes (mismo número de tarjeta, hora e importe)
+    pub duplicates: usize,
+    pub matched: usize,
+    pub vehicle_only: usize,
+    pub unmatched: usize,
+    /// Tournées cerradas que se han vuelto a cuadrar
+    pub reconciled: usize,
+    pub errors: Vec<FuelImportError>,
+}
@@ -0,0 +1,89 @@
+//! Modelo de posiciones GPS
+//!
+//! La app Android envía las posiciones del chofer en lotes; se guardan como
+//! migas de pan de su tournée en `location_breadcrumbs` y la más reciente pasa
+//! a `users.last_location` (ver `services::location_tracking`).
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::stop_visit::StopVisitEvent;
+
+/// Posición GPS tomada por el dispositivo
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct LocationFix {
+    #[validate(range(min = -90.0, max = 90.0))]
+    pub latitude: f64,
+    #[validate(range(min = -180.0, max = 180.0))]
+    pub longitude: f64,
+    #[validate(range(min = 0.0))]
+    pub accuracy_meters: Option<f64>,
+    /// Velocidad en m/s
+    #[validate(range(min = 0.0))]
+    pub speed_mps: Option<f64>,
+    /// Rumbo en grados (0 = norte)
+    #[validate(range(min = 0.0, max = 359.999))]
+    pub heading_degrees: Option<f64>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// Lote de posiciones de la app
+#[derive(Debug, Deserialize, Validate)]
+pub struct LocationBatchRequest {
+    /// Tournée en curso (por defecto, la tournée iniciada del chofer)
+    pub tournee_id: Option<Uuid>,
+    #[validate(length(min = 1))]
+    pub fixes: Vec<LocationFix>,
+}
+
+/// Resultado de la ingesta de un lote
+#[derive(Debug, Serialize)]
+pub struct LocationBatchResponse {
+    /// Tournée a la que se asociaron las posiciones (`None`: solo se
+    /// actualizó la última posición)
+    pub tournee_id: Option<Uuid>,
+    pub accepted: usize,
+    /// Posiciones ya recibidas en un lote anterior
+    pub duplicates: usize,
+    /// Posiciones descartadas (coordenadas inválidas, precisión insuficiente
+    /// u hora futura)
+    pub rejected: usize,
+    pub last_location_updated: bool,
+    /// Llegadas y salidas detectadas por la geocerca con este lote
+    pub stop_events: Vec<StopVisitEvent>,
+}
+
+/// Miga de pan - mapea a la tabla location_breadcrumbs
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Breadcrumb {
+    pub tournee_id: Uuid,
+    pub recorded_at: DateTime<Utc>,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub accuracy_meters: Option<f64>,
+    pub speed_mps: Option<f64>,
+    pub heading_degrees: Option<f64>,
+}
+
+/// Parámetros del recorrido de un chofer (RFC 3339)
+#[derive(Debug, Deserialize)]
+pub struct TrackParams {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub tournee_id: Option<Uuid>,
+}
+
+/// Recorrido de un chofer en un intervalo
+#[derive(Debug, Serialize)]
+pub struct DriverTrack {
+    pub driver_id: Uuid,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub distance_meters: f64,
+    /// `true` si se alcanzó el máximo de puntos y el recorrido está cortado
+    pub truncated: bool,
+    pub points: Vec<Breadcrumb>,
+}
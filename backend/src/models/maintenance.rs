@@ -0,0 +1,245 @@
+//! Modelo del mantenimiento preventivo
+//!
+//! Los planes se definen por modelo de vehículo (marca y modelo), una fila por
+//! tarea con su periodicidad en km y/o meses. El job diario calcula para cada
+//! vehículo el próximo vencimiento de cada tarea y avisa de lo que se acerca;
+//! las citas de taller reservan una ventana en la que el vehículo no se puede
+//! asignar a una tournée (ver `services::maintenance`).
+
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Estado de una cita de taller - mapea a vehicle_maintenance.maintenance_status
+///
+/// ```text
+/// scheduled ──► in_progress ──► completed
+///     │              │
+///     └──────────────┴──► cancelled
+/// ```
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MaintenanceStatus {
+    Scheduled,
+    InProgress,
+    Completed,
+    Cancelled,
+}
+
+impl MaintenanceStatus {
+    /// Valor de la columna maintenance_status
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MaintenanceStatus::Scheduled => "scheduled",
+            MaintenanceStatus::InProgress => "in_progress",
+            MaintenanceStatus::Completed => "completed",
+            MaintenanceStatus::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "scheduled" => Some(MaintenanceStatus::Scheduled),
+            "in_progress" => Some(MaintenanceStatus::InProgress),
+            "completed" => Some(MaintenanceStatus::Completed),
+            "cancelled" => Some(MaintenanceStatus::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+/// Situación de una tarea respecto a su vencimiento
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum DueState {
+    Ok,
+    /// Vence dentro del margen de aviso (días o km)
+    Upcoming,
+    Overdue,
+}
+
+impl DueState {
+    /// Valor de la columna due_state
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DueState::Ok => "ok",
+            DueState::Upcoming => "upcoming",
+            DueState::Overdue => "overdue",
+        }
+    }
+}
+
+/// Tarea de un plan - mapea a la tabla maintenance_plans
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct MaintenancePlan {
+    pub id: Uuid,
+    pub company_id: Uuid,
+    pub brand: String,
+    pub model: String,
+    pub task_name: String,
+    pub description: Option<String>,
+    pub interval_km: Option<i32>,
+    pub interval_months: Option<i32>,
+    pub estimated_hours: Option<Decimal>,
+    pub is_active: bool,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+/// Columnas de maintenance_plans en el orden de `MaintenancePlan`
+pub const MAINTENANCE_PLAN_COLUMNS: &str = r#"
+    id, company_id, brand, model, task_name, description, interval_km,
+    interval_months, estimated_hours, is_active, created_at, updated_at,
+    deleted_at
+"#;
+
+/// Request para añadir una tarea al plan de un modelo
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateMaintenancePlanRequest {
+    #[validate(length(min = 2, max = 100))]
+    pub brand: String,
+    #[validate(length(min = 2, max = 100))]
+    pub model: String,
+    #[validate(length(min = 2, max = 100))]
+    pub task_name: String,
+    #[validate(length(max = 2000))]
+    pub description: Option<String>,
+    #[validate(range(min = 1))]
+    pub interval_km: Option<i32>,
+    #[validate(range(min = 1, max = 120))]
+    pub interval_months: Option<i32>,
+    pub estimated_hours: Option<Decimal>,
+}
+
+/// Request para modificar una tarea del plan
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateMaintenancePlanRequest {
+    #[validate(length(min = 2, max = 100))]
+    pub task_name: Option<String>,
+    #[validate(length(max = 2000))]
+    pub description: Option<String>,
+    #[validate(range(min = 1))]
+    pub interval_km: Option<i32>,
+    #[validate(range(min = 1, max = 120))]
+    pub interval_months: Option<i32>,
+    pub estimated_hours: Option<Decimal>,
+    pub is_active: Option<bool>,
+}
+
+/// Cita de taller - mapea a la tabla vehicle_maintenance
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct VehicleMaintenance {
+    pub id: Uuid,
+    pub company_id: Uuid,
+    pub vehicle_id: Uuid,
+    pub plan_id: Option<Uuid>,
+    pub task_name: String,
+    pub maintenance_status: String,
+    pub scheduled_start: DateTime<Utc>,
+    pub scheduled_end: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub mileage_at_service: Option<Decimal>,
+    pub cost: Option<Decimal>,
+    pub workshop: Option<String>,
+    pub notes: Option<String>,
+    pub created_by: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Columnas de vehicle_maintenance en el orden de `VehicleMaintenance`
+pub const VEHICLE_MAINTENANCE_COLUMNS: &str = r#"
+    id, company_id, vehicle_id, plan_id, task_name, maintenance_status,
+    scheduled_start, scheduled_end, started_at, completed_at,
+    mileage_at_service, cost, workshop, notes, created_by, created_at,
+    updated_at
+"#;
+
+/// Request para reservar una cita de taller
+///
+/// Con `plan_id` la tarea es la del plan; sin él (reparación puntual) hay que
+/// indicar `task_name`.
+#[derive(Debug, Deserialize, Validate)]
+pub struct BookMaintenanceRequest {
+    pub plan_id: Option<Uuid>,
+    #[validate(length(min = 2, max = 100))]
+    pub task_name: Option<String>,
+    pub scheduled_start: DateTime<Utc>,
+    pub scheduled_end: DateTime<Utc>,
+    #[validate(length(max = 255))]
+    pub workshop: Option<String>,
+    #[validate(length(max = 2000))]
+    pub notes: Option<String>,
+}
+
+/// Request para pasar una cita a otro estado
+///
+/// Al completarla, `mileage_at_service` (por defecto el kilometraje actual del
+/// vehículo) es la nueva referencia de la tarea.
+#[derive(Debug, Deserialize, Validate)]
+pub struct MaintenanceTransitionRequest {
+    pub maintenance_status: MaintenanceStatus,
+    pub mileage_at_service: Option<Decimal>,
+    pub cost: Option<Decimal>,
+    #[validate(length(max = 2000))]
+    pub notes: Option<String>,
+}
+
+/// Filtros de citas de taller
+#[derive(Debug, Deserialize)]
+pub struct MaintenanceFilters {
+    pub vehicle_id: Option<Uuid>,
+    pub maintenance_status: Option<MaintenanceStatus>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+/// Próximo vencimiento de una tarea del plan para un vehículo
+#[derive(Debug, Clone, Serialize)]
+pub struct MaintenanceForecast {
+    pub plan_id: Uuid,
+    pub task_name: String,
+    pub interval_km: Option<i32>,
+    pub interval_months: Option<i32>,
+    pub last_service_date: Option<NaiveDate>,
+    pub last_service_mileage: Option<Decimal>,
+    pub due_mileage: Option<Decimal>,
+    pub due_date: Option<NaiveDate>,
+    /// Fecha prevista: la de calendario o la que marca el ritmo de km, la primera
+    pub projected_date: Option<NaiveDate>,
+    pub km_remaining: Option<Decimal>,
+    pub days_remaining: Option<i64>,
+    pub avg_daily_km: f64,
+    pub due_state: DueState,
+}
+
+/// Tarea pendiente de la empresa (`GET /maintenance/due`)
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct MaintenanceDue {
+    pub vehicle_id: Uuid,
+    pub license_plate: String,
+    pub plan_id: Uuid,
+    pub task_name: String,
+    pub current_mileage: Decimal,
+    pub due_mileage: Option<Decimal>,
+    pub due_date: Option<NaiveDate>,
+    pub projected_date: Option<NaiveDate>,
+    pub due_state: String,
+    /// Hay una cita reservada para la tarea
+    pub booked: bool,
+}
+
+/// Resultado de una pasada del job de mantenimiento
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MaintenanceCheckSummary {
+    pub vehicles: u64,
+    pub upcoming: u64,
+    pub overdue: u64,
+    /// Notificaciones registradas
+    pub notifications: u64,
+}
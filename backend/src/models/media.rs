@@ -0,0 +1,152 @@
+//! Modelo de archivos multimedia
+//!
+//! Fotos de entrega, firmas, fotos de inspección, evidencias de daños y
+//! documentos de vehículo escaneados. El binario vive en el backend de
+//! almacenamiento (ver `services::media_storage`); la tabla `media_objects`
+//! guarda la referencia y la columna de la entidad guarda la URL firmada de
+//! descarga.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Columna de destino de un archivo subido
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MediaTarget {
+    /// `packages.delivery_photo`
+    DeliveryPhoto,
+    /// `packages.signature_image`
+    SignatureImage,
+    /// `packages.signature_photo`
+    SignaturePhoto,
+    /// `tournees.pre_inspection_photos`
+    PreInspectionPhoto,
+    /// `tournees.post_inspection_photos`
+    PostInspectionPhoto,
+    /// `vehicle_damages.photo_evidence`
+    DamageEvidence,
+    /// `vehicle_documents.document_url` (documento escaneado)
+    VehicleDocument,
+}
+
+impl MediaTarget {
+    /// Identificador usado en rutas, claves de almacenamiento y base de datos
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MediaTarget::DeliveryPhoto => "delivery-photo",
+            MediaTarget::SignatureImage => "signature-image",
+            MediaTarget::SignaturePhoto => "signature-photo",
+            MediaTarget::PreInspectionPhoto => "pre-inspection-photo",
+            MediaTarget::PostInspectionPhoto => "post-inspection-photo",
+            MediaTarget::DamageEvidence => "damage-evidence",
+            MediaTarget::VehicleDocument => "vehicle-document",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        [
+            MediaTarget::DeliveryPhoto,
+            MediaTarget::SignatureImage,
+            MediaTarget::SignaturePhoto,
+            MediaTarget::PreInspectionPhoto,
+            MediaTarget::PostInspectionPhoto,
+            MediaTarget::DamageEvidence,
+            MediaTarget::VehicleDocument,
+        ]
+        .into_iter()
+        .find(|target| target.as_str() == value)
+    }
+
+    /// Tabla de la entidad propietaria
+    pub fn table(&self) -> &'static str {
+        match self {
+            MediaTarget::DeliveryPhoto | MediaTarget::SignatureImage | MediaTarget::SignaturePhoto => "packages",
+            MediaTarget::PreInspectionPhoto | MediaTarget::PostInspectionPhoto => "tournees",
+            MediaTarget::DamageEvidence => "vehicle_damages",
+            MediaTarget::VehicleDocument => "vehicle_documents",
+        }
+    }
+
+    /// Columna donde se guarda la URL firmada
+    pub fn column(&self) -> &'static str {
+        match self {
+            MediaTarget::DeliveryPhoto => "delivery_photo",
+            MediaTarget::SignatureImage => "signature_image",
+            MediaTarget::SignaturePhoto => "signature_photo",
+            MediaTarget::PreInspectionPhoto => "pre_inspection_photos",
+            MediaTarget::PostInspectionPhoto => "post_inspection_photos",
+            MediaTarget::DamageEvidence => "photo_evidence",
+            MediaTarget::VehicleDocument => "document_url",
+        }
+    }
+
+    /// Las columnas `TEXT[]` acumulan URLs; las `TEXT` se reemplazan
+    pub fn is_array(&self) -> bool {
+        matches!(
+            self,
+            MediaTarget::PreInspectionPhoto | MediaTarget::PostInspectionPhoto | MediaTarget::DamageEvidence
+        )
+    }
+}
+
+/// Archivo almacenado - mapea a la tabla media_objects
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct MediaObject {
+    pub id: Uuid,
+    pub company_id: Uuid,
+    pub target: String,
+    pub entity_id: Uuid,
+
+    // Almacenamiento
+    pub storage_backend: String,
+    pub storage_key: String,
+    pub thumbnail_key: Option<String>,
+
+    // Contenido
+    pub content_type: String,
+    pub size_bytes: i64,
+    /// SHA-256 (hex) del archivo original, para pruebas de entrega
+    pub content_sha256: Option<String>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub original_filename: Option<String>,
+
+    // Metadatos
+    pub uploaded_by: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Respuesta con URLs firmadas de descarga
+#[derive(Debug, Clone, Serialize)]
+pub struct MediaObjectResponse {
+    #[serde(flatten)]
+    pub object: MediaObject,
+    pub url: String,
+    pub thumbnail_url: Option<String>,
+    pub url_expires_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_media_target_roundtrip() {
+        for target in [
+            MediaTarget::DeliveryPhoto,
+            MediaTarget::SignatureImage,
+            MediaTarget::SignaturePhoto,
+            MediaTarget::PreInspectionPhoto,
+            MediaTarget::PostInspectionPhoto,
+            MediaTarget::DamageEvidence,
+            MediaTarget::VehicleDocument,
+        ] {
+            assert_eq!(MediaTarget::parse(target.as_str()), Some(target));
+        }
+        assert_eq!(MediaTarget::parse("avatar"), None);
+        assert!(MediaTarget::DamageEvidence.is_array());
+        assert!(!MediaTarget::SignatureImage.is_array());
+    }
+}
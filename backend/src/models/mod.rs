@@ -6,11 +6,29 @@
 pub mod auth;
 pub mod company;
 pub mod colis_prive_company;
+pub mod compte_rendu;
+pub mod fuel;
 pub mod user;
 pub mod vehicle;
+pub mod vehicle_damage;
+pub mod vehicle_document;
 pub mod tournee;
 pub mod package;
+pub mod maintenance;
+pub mod pickup;
 pub mod analytics;
+pub mod assignment;
+pub mod driver_field_data;
+pub mod media;
+pub mod notification;
+pub mod location;
+pub mod delivery_proof;
+pub mod dispatch;
+pub mod relay_point;
+pub mod scan_event;
+pub mod status_history;
+pub mod stop_visit;
+pub mod sync;
 pub mod colis_prive_web_models;
 // colis_prive_v3_models eliminado - API móvil legacy
 
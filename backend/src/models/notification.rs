@@ -0,0 +1,54 @@
+//! Modelo de notificaciones
+//!
+//! Avisos para los admins de la empresa (caducidad de documentos, daños,
+//! consumo, mantenimiento, rendimiento), guardados en `notifications_log`
+//! (ver `services::notifications`).
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::Type;
+use uuid::Uuid;
+
+/// Tipo de notificación - mapea al ENUM notification_type
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[sqlx(type_name = "notification_type", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationType {
+    #[sqlx(rename = "expiry_warning_30")]
+    #[serde(rename = "expiry_warning_30")]
+    ExpiryWarning30,
+    #[sqlx(rename = "expiry_warning_15")]
+    #[serde(rename = "expiry_warning_15")]
+    ExpiryWarning15,
+    ExpiredCritical,
+    DamageIncident,
+    PerformanceAlert,
+    FuelConsumptionAlert,
+    MaintenanceReminder,
+    SystemAlert,
+}
+
+/// Prioridad - mapea al ENUM notification_priority
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq, PartialOrd, Ord)]
+#[sqlx(type_name = "notification_priority", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationPriority {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+/// Notificación a registrar en notifications_log
+#[derive(Debug, Clone)]
+pub struct NewNotification {
+    pub company_id: Uuid,
+    pub notification_type: NotificationType,
+    pub priority: NotificationPriority,
+    pub title: String,
+    pub message: String,
+    pub document_id: Option<Uuid>,
+    pub vehicle_id: Option<Uuid>,
+    pub driver_id: Option<Uuid>,
+    pub metadata: Value,
+}
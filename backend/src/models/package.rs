@@ -4,15 +4,22 @@
 //! Mapea exactamente al schema PostgreSQL con primary key 'id'.
 
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, Type};
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::postgres::types::Oid;
+use sqlx::postgres::{PgArgumentBuffer, PgTypeInfo, PgValueFormat, PgValueRef};
+use sqlx::{FromRow, Postgres, Type};
 use validator::Validate;
 use chrono::{DateTime, Utc, NaiveDate, NaiveTime};
 use uuid::Uuid;
 use rust_decimal::Decimal;
 
+use crate::models::delivery_proof::ProofOfDeliveryRequest;
+use crate::models::relay_point::RelayPoint;
+
 /// Estado de entrega - mapea al ENUM delivery_status
 #[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq)]
-#[sqlx(type_name = "delivery_status", rename_all = "lowercase")]
+#[sqlx(type_name = "delivery_status", rename_all = "snake_case")]
 pub enum DeliveryStatus {
     Pending,
     InTransit,
@@ -25,7 +32,7 @@ pub enum DeliveryStatus {
 
 /// Razón de fallo en entrega - mapea al ENUM delivery_failure_reason
 #[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq)]
-#[sqlx(type_name = "delivery_failure_reason", rename_all = "lowercase")]
+#[sqlx(type_name = "delivery_failure_reason", rename_all = "snake_case")]
 pub enum DeliveryFailureReason {
     RecipientNotHome,
     WrongAddress,
@@ -37,6 +44,95 @@ pub enum DeliveryFailureReason {
     DriverEmergency,
 }
 
+impl DeliveryStatus {
+    /// Valor del ENUM en la base de datos
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::InTransit => "in_transit",
+            DeliveryStatus::OutForDelivery => "out_for_delivery",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+            DeliveryStatus::Returned => "returned",
+            DeliveryStatus::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "pending" => Some(DeliveryStatus::Pending),
+            "in_transit" => Some(DeliveryStatus::InTransit),
+            "out_for_delivery" => Some(DeliveryStatus::OutForDelivery),
+            "delivered" => Some(DeliveryStatus::Delivered),
+            "failed" => Some(DeliveryStatus::Failed),
+            "returned" => Some(DeliveryStatus::Returned),
+            "cancelled" => Some(DeliveryStatus::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+impl DeliveryFailureReason {
+    /// Valor del ENUM en la base de datos
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryFailureReason::RecipientNotHome => "recipient_not_home",
+            DeliveryFailureReason::WrongAddress => "wrong_address",
+            DeliveryFailureReason::PackageDamaged => "package_damaged",
+            DeliveryFailureReason::RefusedDelivery => "refused_delivery",
+            DeliveryFailureReason::SecurityRestriction => "security_restriction",
+            DeliveryFailureReason::WeatherConditions => "weather_conditions",
+            DeliveryFailureReason::VehicleBreakdown => "vehicle_breakdown",
+            DeliveryFailureReason::DriverEmergency => "driver_emergency",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "recipient_not_home" => Some(DeliveryFailureReason::RecipientNotHome),
+            "wrong_address" => Some(DeliveryFailureReason::WrongAddress),
+            "package_damaged" => Some(DeliveryFailureReason::PackageDamaged),
+            "refused_delivery" => Some(DeliveryFailureReason::RefusedDelivery),
+            "security_restriction" => Some(DeliveryFailureReason::SecurityRestriction),
+            "weather_conditions" => Some(DeliveryFailureReason::WeatherConditions),
+            "vehicle_breakdown" => Some(DeliveryFailureReason::VehicleBreakdown),
+            "driver_emergency" => Some(DeliveryFailureReason::DriverEmergency),
+            _ => None,
+        }
+    }
+}
+
+/// Siguiente acción tras un fallo de entrega - mapea a packages.next_action
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureAction {
+    /// Nueva presentación el siguiente día laborable
+    Reschedule,
+    /// Aviso de paso en el buzón y nueva presentación
+    LeaveNotice,
+    /// Depósito en el punto relais más cercano
+    RedirectToRelay,
+    /// Devolución al remitente
+    ReturnToSender,
+}
+
+impl FailureAction {
+    /// Valor de la columna next_action
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureAction::Reschedule => "reschedule",
+            FailureAction::LeaveNotice => "leave_notice",
+            FailureAction::RedirectToRelay => "redirect_to_relay",
+            FailureAction::ReturnToSender => "return_to_sender",
+        }
+    }
+
+    /// Acciones que devuelven el paquete a una tournée posterior
+    pub fn is_new_attempt(&self) -> bool {
+        matches!(self, FailureAction::Reschedule | FailureAction::LeaveNotice)
+    }
+}
+
 /// Origen del paquete - mapea al campo package_origin
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum PackageOrigin {
@@ -78,6 +174,9 @@ pub struct Package {
     pub failure_reason: Option<DeliveryFailureReason>,
     pub failure_notes: Option<String>,
     pub reschedule_date: Option<NaiveDate>,
+    /// Siguiente acción decidida tras el último fallo (ver `FailureAction`)
+    pub next_action: Option<String>,
+    pub relay_point_id: Option<Uuid>,
     
     // Evidencia de entrega
     pub delivery_photo: Option<String>,
@@ -93,20 +192,81 @@ pub struct Package {
     pub driver_notes: Option<String>,
     pub package_condition: Option<String>,
     
+    // Reloj del dispositivo del último cambio
+    pub status_changed_at: Option<DateTime<Utc>>,
+    pub notes_changed_at: Option<DateTime<Utc>>,
+    
     // Metadatos
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
     pub deleted_at: Option<DateTime<Utc>>,
 }
 
-/// Tipo Point para PostGIS - mapea al tipo POINT
-#[derive(Debug, Clone, Serialize, Deserialize, Type)]
-#[sqlx(type_name = "point")]
+/// Columnas de packages en el orden de `Package` (para consultas runtime)
+pub const PACKAGE_COLUMNS: &str = r#"
+    id, company_id, tournee_id, tracking_number, external_tracking_number,
+    package_origin, external_package_id, integration_id, package_type,
+    package_weight, package_dimensions, delivery_status, delivery_date,
+    delivery_time, COALESCE(delivery_attempts, 0) AS delivery_attempts,
+    recipient_name, recipient_phone, delivery_address, delivery_instructions,
+    failure_reason, failure_notes, reschedule_date, next_action,
+    relay_point_id, delivery_photo,
+    COALESCE(signature_required, FALSE) AS signature_required, signature_image,
+    signature_photo, delivery_coordinates, delivery_duration_minutes,
+    driver_notes, package_condition, status_changed_at, notes_changed_at,
+    created_at, updated_at, deleted_at
+"#;
+
+/// Tipo Point - mapea al tipo geométrico POINT de PostgreSQL (x = longitud, y = latitud)
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Point {
     pub x: f64,
     pub y: f64,
 }
 
+impl Type<Postgres> for Point {
+    fn type_info() -> PgTypeInfo {
+        // OID del tipo POINT nativo
+        PgTypeInfo::with_oid(Oid(600))
+    }
+}
+
+impl<'r> sqlx::Decode<'r, Postgres> for Point {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        match value.format() {
+            PgValueFormat::Binary => {
+                let bytes = value.as_bytes()?;
+                if bytes.len() != 16 {
+                    return Err(format!("POINT binario inválido ({} bytes)", bytes.len()).into());
+                }
+                Ok(Self {
+                    x: f64::from_be_bytes(bytes[..8].try_into()?),
+                    y: f64::from_be_bytes(bytes[8..].try_into()?),
+                })
+            }
+            PgValueFormat::Text => {
+                let text = value.as_str()?;
+                let (x, y) = text
+                    .trim_matches(|c| c == '(' || c == ')')
+                    .split_once(',')
+                    .ok_or_else(|| format!("POINT inválido: {}", text))?;
+                Ok(Self {
+                    x: x.trim().parse()?,
+                    y: y.trim().parse()?,
+                })
+            }
+        }
+    }
+}
+
+impl sqlx::Encode<'_, Postgres> for Point {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> IsNull {
+        buf.extend_from_slice(&self.x.to_be_bytes());
+        buf.extend_from_slice(&self.y.to_be_bytes());
+        IsNull::No
+    }
+}
+
 /// Request para crear un nuevo paquete
 #[derive(Debug, Deserialize, Validate)]
 pub struct CreatePackageRequest {
@@ -162,6 +322,9 @@ pub struct MarkDeliveredRequest {
     pub delivery_duration_minutes: Option<i32>,
     pub driver_notes: Option<String>,
     pub package_condition: Option<String>,
+    /// Prueba de entrega (obligatoria con firma si `signature_required`)
+    #[validate]
+    pub proof: Option<ProofOfDeliveryRequest>,
 }
 
 /// Request para marcar paquete como fallido
@@ -169,8 +332,26 @@ pub struct MarkDeliveredRequest {
 pub struct MarkFailedRequest {
     pub failure_reason: String,
     pub failure_notes: Option<String>,
+    /// Fecha pedida por el destinatario (si no, el siguiente día laborable)
     pub reschedule_date: Option<String>,
     pub driver_notes: Option<String>,
+    /// Posición del chofer, para buscar el punto relais más cercano
+    #[validate(range(min = -90.0, max = 90.0))]
+    pub latitude: Option<f64>,
+    #[validate(range(min = -180.0, max = 180.0))]
+    pub longitude: Option<f64>,
+}
+
+/// Resultado del flujo de entrega fallida
+#[derive(Debug, Serialize)]
+pub struct FailedDeliveryResponse {
+    #[serde(flatten)]
+    pub package: PackageResponse,
+    pub action: FailureAction,
+    /// Tournée a la que se movió el paquete (si ya existe)
+    pub next_tournee_id: Option<Uuid>,
+    pub relay_point: Option<RelayPoint>,
+    pub relay_distance_meters: Option<f64>,
 }
 
 /// Response de paquete para la API
@@ -195,6 +376,8 @@ pub struct PackageResponse {
     pub failure_reason: Option<String>,
     pub failure_notes: Option<String>,
     pub reschedule_date: Option<String>,
+    pub next_action: Option<String>,
+    pub relay_point_id: Option<String>,
     pub delivery_photo: Option<String>,
     pub signature_required: bool,
     pub signature_image: Option<String>,
@@ -235,6 +418,7 @@ pub struct PackageFilters {
     pub delivery_date_from: Option<String>,
     pub delivery_date_to: Option<String>,
     pub failure_reason: Option<String>,
+    /// Fecha de alta (YYYY-MM-DD, hora de París)
     pub created_after: Option<String>,
     pub created_before: Option<String>,
     pub limit: Option<i64>,
@@ -252,7 +436,7 @@ impl From<Package> for PackageResponse {
             package_type: package.package_type,
             package_weight: package.package_weight.map(|w| w.to_string()),
             package_dimensions: package.package_dimensions,
-            delivery_status: format!("{:?}", package.delivery_status).to_lowercase(),
+            delivery_status: package.delivery_status.as_str().to_string(),
             delivery_date: package.delivery_date.map(|d| d.to_string()),
             delivery_time: package.delivery_time.map(|t| t.to_string()),
             delivery_attempts: package.delivery_attempts,
@@ -260,9 +444,11 @@ impl From<Package> for PackageResponse {
             recipient_phone: package.recipient_phone,
             delivery_address: package.delivery_address,
             delivery_instructions: package.delivery_instructions,
-            failure_reason: package.failure_reason.map(|r| format!("{:?}", r).to_lowercase()),
+            failure_reason: package.failure_reason.map(|r| r.as_str().to_string()),
             failure_notes: package.failure_notes,
             reschedule_date: package.reschedule_date.map(|d| d.to_string()),
+            next_action: package.next_action,
+            relay_point_id: package.relay_point_id.map(|id| id.to_string()),
             delivery_photo: package.delivery_photo,
             signature_required: package.signature_required,
             signature_image: package.signature_image,
@@ -284,12 +470,12 @@ impl From<Package> for PackageListResponse {
             id: package.id.to_string(),
             tracking_number: package.tracking_number,
             external_tracking_number: package.external_tracking_number,
-            delivery_status: format!("{:?}", package.delivery_status).to_lowercase(),
+            delivery_status: package.delivery_status.as_str().to_string(),
             delivery_date: package.delivery_date.map(|d| d.to_string()),
             delivery_attempts: package.delivery_attempts,
             recipient_name: package.recipient_name,
             delivery_address: package.delivery_address,
-            failure_reason: package.failure_reason.map(|r| format!("{:?}", r).to_lowercase()),
+            failure_reason: package.failure_reason.map(|r| r.as_str().to_string()),
             tournee_id: package.tournee_id.to_string(),
             created_at: package.created_at.map(|dt| dt.to_rfc3339()),
         }
@@ -0,0 +1,167 @@
+//! Modelo de recogidas (collectes)
+//!
+//! Una recogida es una parada de la tournée donde el chofer carga bultos en
+//! lugar de entregarlos (`BeanTournee.nb_colis_acollecter` /
+//! `nb_colis_collecte`). Tiene un número de bultos previsto y una franja
+//! horaria; los bultos se confirman leyendo sus códigos (`scan_type = pickup`)
+//! y ocupan sitio en el vehículo desde ese momento (ver `services::pickups` y
+//! `services::eta`).
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Tipo de recogida - mapea a pickups.pickup_type
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PickupType {
+    /// Envíos de un comercio
+    Merchant,
+    /// Devoluciones depositadas en un punto relais
+    RelayPoint,
+    /// Devolución en el domicilio del cliente
+    CustomerReturn,
+}
+
+impl PickupType {
+    /// Valor de la columna pickup_type
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PickupType::Merchant => "merchant",
+            PickupType::RelayPoint => "relay_point",
+            PickupType::CustomerReturn => "customer_return",
+        }
+    }
+}
+
+/// Estado de la recogida - mapea a pickups.pickup_status
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PickupStatus {
+    Pending,
+    Collected,
+    /// Confirmada con menos bultos de los previstos
+    Partial,
+    Failed,
+    Cancelled,
+}
+
+impl PickupStatus {
+    /// Valor de la columna pickup_status
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PickupStatus::Pending => "pending",
+            PickupStatus::Collected => "collected",
+            PickupStatus::Partial => "partial",
+            PickupStatus::Failed => "failed",
+            PickupStatus::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "pending" => Some(PickupStatus::Pending),
+            "collected" => Some(PickupStatus::Collected),
+            "partial" => Some(PickupStatus::Partial),
+            "failed" => Some(PickupStatus::Failed),
+            "cancelled" => Some(PickupStatus::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+/// Recogida - mapea a la tabla pickups
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Pickup {
+    pub id: Uuid,
+    pub company_id: Uuid,
+    pub tournee_id: Uuid,
+    pub pickup_reference: String,
+    pub pickup_type: String,
+    pub contact_name: Option<String>,
+    pub contact_phone: Option<String>,
+    pub pickup_address: String,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub expected_parcels: i32,
+    pub window_start: Option<DateTime<Utc>>,
+    pub window_end: Option<DateTime<Utc>>,
+    pub pickup_instructions: Option<String>,
+    pub pickup_status: String,
+    pub collected_parcels: i32,
+    pub collected_at: Option<DateTime<Utc>>,
+    pub failure_notes: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+/// Columnas de pickups en el orden de `Pickup`
+pub const PICKUP_COLUMNS: &str = r#"
+    id, company_id, tournee_id, pickup_reference, pickup_type, contact_name,
+    contact_phone, pickup_address, latitude, longitude, expected_parcels,
+    window_start, window_end, pickup_instructions, pickup_status,
+    collected_parcels, collected_at, failure_notes, created_at, updated_at,
+    deleted_at
+"#;
+
+/// Request para planificar una recogida
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreatePickupRequest {
+    pub tournee_id: Uuid,
+    #[validate(length(min = 1, max = 100))]
+    pub pickup_reference: String,
+    pub pickup_type: Option<PickupType>,
+    #[validate(length(max = 255))]
+    pub contact_name: Option<String>,
+    #[validate(length(max = 20))]
+    pub contact_phone: Option<String>,
+    #[validate(length(min = 5, max = 500))]
+    pub pickup_address: String,
+    #[validate(range(min = -90.0, max = 90.0))]
+    pub latitude: Option<f64>,
+    #[validate(range(min = -180.0, max = 180.0))]
+    pub longitude: Option<f64>,
+    #[validate(range(min = 1, max = 1000))]
+    pub expected_parcels: i32,
+    pub window_start: Option<DateTime<Utc>>,
+    pub window_end: Option<DateTime<Utc>>,
+    pub pickup_instructions: Option<String>,
+}
+
+/// Request para actualizar una recogida pendiente
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdatePickupRequest {
+    #[validate(length(max = 255))]
+    pub contact_name: Option<String>,
+    #[validate(length(max = 20))]
+    pub contact_phone: Option<String>,
+    #[validate(length(min = 5, max = 500))]
+    pub pickup_address: Option<String>,
+    #[validate(range(min = -90.0, max = 90.0))]
+    pub latitude: Option<f64>,
+    #[validate(range(min = -180.0, max = 180.0))]
+    pub longitude: Option<f64>,
+    #[validate(range(min = 1, max = 1000))]
+    pub expected_parcels: Option<i32>,
+    pub window_start: Option<DateTime<Utc>>,
+    pub window_end: Option<DateTime<Utc>>,
+    pub pickup_instructions: Option<String>,
+}
+
+/// Request para dar una recogida por fallida
+#[derive(Debug, Deserialize, Validate)]
+pub struct FailPickupRequest {
+    #[validate(length(min = 1, max = 1000))]
+    pub failure_notes: String,
+}
+
+/// Filtros de recogidas
+#[derive(Debug, Deserialize)]
+pub struct PickupFilters {
+    pub tournee_id: Option<Uuid>,
+    pub date: Option<NaiveDate>,
+    pub pickup_status: Option<String>,
+}
@@ -0,0 +1,79 @@
+//! Modelo de punto relais
+//!
+//! Comercios asociados donde se depositan los paquetes que no se han podido
+//! entregar en domicilio (ver `services::failed_delivery`).
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Punto relais - mapea a la tabla relay_points
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct RelayPoint {
+    pub id: Uuid,
+    pub company_id: Uuid,
+    pub name: String,
+    pub external_code: Option<String>,
+    pub address: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Paquetes que admite a la vez (`None` = sin límite)
+    pub capacity: Option<i32>,
+    pub opening_hours: Option<String>,
+    pub is_active: bool,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Columnas de relay_points en el orden de `RelayPoint`
+pub const RELAY_POINT_COLUMNS: &str = r#"
+    id, company_id, name, external_code, address, latitude, longitude,
+    capacity, opening_hours, is_active, created_at, updated_at
+"#;
+
+/// Request para dar de alta un punto relais
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateRelayPointRequest {
+    #[validate(length(min = 1, max = 255))]
+    pub name: String,
+    #[validate(length(max = 50))]
+    pub external_code: Option<String>,
+    #[validate(length(min = 5, max = 500))]
+    pub address: String,
+    #[validate(range(min = -90.0, max = 90.0))]
+    pub latitude: f64,
+    #[validate(range(min = -180.0, max = 180.0))]
+    pub longitude: f64,
+    #[validate(range(min = 1))]
+    pub capacity: Option<i32>,
+    pub opening_hours: Option<String>,
+}
+
+/// Request para actualizar un punto relais
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateRelayPointRequest {
+    #[validate(length(min = 1, max = 255))]
+    pub name: Option<String>,
+    #[validate(length(min = 5, max = 500))]
+    pub address: Option<String>,
+    #[validate(range(min = -90.0, max = 90.0))]
+    pub latitude: Option<f64>,
+    #[validate(range(min = -180.0, max = 180.0))]
+    pub longitude: Option<f64>,
+    #[validate(range(min = 1))]
+    pub capacity: Option<i32>,
+    pub opening_hours: Option<String>,
+    pub is_active: Option<bool>,
+}
+
+/// Punto relais con su ocupación actual
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct RelayPointWithLoad {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub relay_point: RelayPoint,
+    /// Paquetes redirigidos todavía no retirados
+    pub current_load: i64,
+}
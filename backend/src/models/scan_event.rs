@@ -0,0 +1,159 @@
+//! Modelo de lecturas de código de barras
+//!
+//! Cada paquete lleva el código `codeBarreArticle` (packages.external_tracking_number)
+//! y la referencia `ref_colis` (packages.tracking_number); el chofer los lee al
+//! cargar, al entregar, al fallar y al devolver. Cada lectura se valida contra
+//! el contenido esperado de la tournée (ver `services::scan_events`).
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Momento de la lectura - mapea a scan_events.scan_type
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanType {
+    /// Carga en el vehículo al salir del depósito
+    Load,
+    Deliver,
+    Fail,
+    /// Devolución al depósito
+    Return,
+    Pickup,
+}
+
+impl ScanType {
+    /// Valor de la columna scan_type
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScanType::Load => "load",
+            ScanType::Deliver => "deliver",
+            ScanType::Fail => "fail",
+            ScanType::Return => "return",
+            ScanType::Pickup => "pickup",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "load" => Some(ScanType::Load),
+            "deliver" => Some(ScanType::Deliver),
+            "fail" => Some(ScanType::Fail),
+            "return" => Some(ScanType::Return),
+            "pickup" => Some(ScanType::Pickup),
+            _ => None,
+        }
+    }
+}
+
+/// Resultado de validar la lectura - mapea a scan_events.validation_status
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanValidation {
+    Valid,
+    /// El código no corresponde a ningún paquete de la empresa
+    UnknownBarcode,
+    /// El paquete existe pero pertenece a otra tournée
+    WrongTournee,
+    /// Misma lectura (paquete, tipo y tournée) ya registrada
+    Duplicate,
+}
+
+impl ScanValidation {
+    /// Valor de la columna validation_status
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScanValidation::Valid => "valid",
+            ScanValidation::UnknownBarcode => "unknown_barcode",
+            ScanValidation::WrongTournee => "wrong_tournee",
+            ScanValidation::Duplicate => "duplicate",
+        }
+    }
+}
+
+/// Lectura - mapea a la tabla scan_events
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ScanEvent {
+    pub id: Uuid,
+    pub company_id: Uuid,
+    pub tournee_id: Option<Uuid>,
+    pub package_id: Option<Uuid>,
+    pub scanned_by: Option<Uuid>,
+    pub barcode: String,
+    pub scan_type: String,
+    pub scanned_at: DateTime<Utc>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub device_id: Option<String>,
+    pub validation_status: String,
+    pub duplicate_of: Option<Uuid>,
+    pub received_at: Option<DateTime<Utc>>,
+    /// Recogida confirmada por la lectura (solo `pickup`)
+    pub pickup_id: Option<Uuid>,
+}
+
+/// Columnas de scan_events en el orden de `ScanEvent`
+pub const SCAN_EVENT_COLUMNS: &str = r#"
+    id, company_id, tournee_id, package_id, scanned_by, barcode, scan_type,
+    scanned_at, latitude, longitude, device_id, validation_status, duplicate_of,
+    received_at, pickup_id
+"#;
+
+/// Request para registrar una lectura (también la usa la sincronización offline)
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct ScanRequest {
+    /// `codeBarreArticle` o `ref_colis`
+    #[validate(length(min = 1, max = 100))]
+    pub barcode: String,
+    pub scan_type: ScanType,
+    /// Tournée en curso (por defecto, la del chofer en el día de la lectura)
+    pub tournee_id: Option<Uuid>,
+    /// Paquete ya identificado por la app (se omite la búsqueda por código)
+    pub package_id: Option<Uuid>,
+    /// Recogida en curso (lecturas `pickup`): cada código distinto cuenta
+    /// como un bulto recogido
+    pub pickup_id: Option<Uuid>,
+    /// Hora del dispositivo (ahora si no se indica)
+    pub scanned_at: Option<DateTime<Utc>>,
+    #[validate(range(min = -90.0, max = 90.0))]
+    pub latitude: Option<f64>,
+    #[validate(range(min = -180.0, max = 180.0))]
+    pub longitude: Option<f64>,
+    #[validate(length(max = 100))]
+    pub device_id: Option<String>,
+}
+
+/// Filtros de las lecturas de una tournée (o de las lecturas sin tournée)
+#[derive(Debug, Deserialize)]
+pub struct ScanEventFilters {
+    pub scan_type: Option<String>,
+    /// Solo las lecturas marcadas (no válidas)
+    pub flagged: Option<bool>,
+}
+
+/// Paquete citado en el informe de lecturas
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ScannedPackage {
+    pub package_id: Uuid,
+    pub tracking_number: String,
+    pub external_tracking_number: Option<String>,
+    pub delivery_status: String,
+    pub last_scan_at: Option<DateTime<Utc>>,
+}
+
+/// Informe de lecturas de una tournée
+#[derive(Debug, Serialize)]
+pub struct ScanReport {
+    pub tournee_id: Uuid,
+    pub expected_packages: i64,
+    pub loaded_packages: i64,
+    /// Paquetes de la tournée sin lectura de carga
+    pub not_loaded: Vec<ScannedPackage>,
+    /// Paquetes cargados sin lectura de entrega, fallo ni devolución
+    pub loaded_not_delivered: Vec<ScannedPackage>,
+    pub unknown_barcodes: Vec<ScanEvent>,
+    pub wrong_tournee: Vec<ScanEvent>,
+    pub duplicates: Vec<ScanEvent>,
+}
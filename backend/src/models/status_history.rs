@@ -0,0 +1,63 @@
+//! Modelo del historial de estados
+//!
+//! Cada transición de estado de una tournée, un paquete o un daño de vehículo
+//! queda registrada en `status_history`, que no admite modificaciones (ver
+//! `services::lifecycle`).
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Entidad a la que pertenece la transición - mapea a status_history.entity_type
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransitionEntity {
+    Tournee,
+    Package,
+    VehicleDamage,
+}
+
+impl TransitionEntity {
+    /// Valor de la columna entity_type
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransitionEntity::Tournee => "tournee",
+            TransitionEntity::Package => "package",
+            TransitionEntity::VehicleDamage => "vehicle_damage",
+        }
+    }
+}
+
+/// Transición registrada - mapea a la tabla status_history
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct StatusTransition {
+    pub id: Uuid,
+    pub company_id: Uuid,
+    pub entity_type: String,
+    pub entity_id: Uuid,
+    /// `None` en el primer estado registrado
+    pub from_status: Option<String>,
+    pub to_status: String,
+    pub changed_by: Option<Uuid>,
+    /// Transición hecha saltándose una condición (p. ej. finalizar con paquetes pendientes)
+    pub forced: bool,
+    pub reason: Option<String>,
+    pub metadata: Value,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Columnas de status_history en el orden de `StatusTransition`
+pub const STATUS_TRANSITION_COLUMNS: &str = r#"
+    id, company_id, entity_type, entity_id, from_status, to_status,
+    changed_by, forced, reason, metadata, created_at
+"#;
+
+/// Request para pausar o cancelar una tournée
+#[derive(Debug, Default, Deserialize, Validate)]
+pub struct TourneeTransitionRequest {
+    #[validate(length(max = 500))]
+    pub reason: Option<String>,
+}
@@ -0,0 +1,144 @@
+//! Modelo de visitas a paradas
+//!
+//! Una parada agrupa los paquetes abiertos de la tournée con las mismas
+//! coordenadas. La geocerca detecta la llegada y la salida del chofer a
+//! partir de sus posiciones GPS (ver `services::geofencing`), y los tiempos
+//! de servicio medidos alimentan las ETAs (ver `services::eta`), que
+//! planifican también las recogidas (ver `models::pickup`).
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Parada planificada de una tournée
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Stop {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub package_ids: Vec<Uuid>,
+}
+
+/// Tipo de parada en la ruta
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StopKind {
+    Delivery,
+    /// Recogida: los bultos ocupan sitio en el vehículo tras la parada
+    Pickup,
+}
+
+/// Parada a planificar: entrega (paquetes agrupados por coordenadas) o recogida
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteStop {
+    pub kind: StopKind,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub package_ids: Vec<Uuid>,
+    pub pickup_id: Option<Uuid>,
+    /// Bultos que salen (entrega) o entran (recogida) en el vehículo
+    pub parcels: i32,
+    pub window_start: Option<DateTime<Utc>>,
+    pub window_end: Option<DateTime<Utc>>,
+}
+
+impl From<Stop> for RouteStop {
+    fn from(stop: Stop) -> Self {
+        Self {
+            kind: StopKind::Delivery,
+            latitude: stop.latitude,
+            longitude: stop.longitude,
+            parcels: stop.package_ids.len() as i32,
+            package_ids: stop.package_ids,
+            pickup_id: None,
+            window_start: None,
+            window_end: None,
+        }
+    }
+}
+
+/// Visita - mapea a la tabla stop_visits
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct StopVisit {
+    pub id: Uuid,
+    pub company_id: Uuid,
+    pub tournee_id: Uuid,
+    pub driver_id: Uuid,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub package_ids: Vec<Uuid>,
+    pub entered_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub arrival_confirmed: bool,
+    pub departed_at: Option<DateTime<Utc>>,
+    pub dwell_seconds: Option<i32>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Columnas de stop_visits en el orden de `StopVisit`
+pub const STOP_VISIT_COLUMNS: &str = r#"
+    id, company_id, tournee_id, driver_id, latitude, longitude, package_ids,
+    entered_at, last_seen_at, arrival_confirmed, departed_at, dwell_seconds,
+    created_at, updated_at
+"#;
+
+/// Tipo de evento de geocerca
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StopEventKind {
+    Arrival,
+    Departure,
+}
+
+/// Llegada o salida detectada en un lote de posiciones
+#[derive(Debug, Clone, Serialize)]
+pub struct StopVisitEvent {
+    pub kind: StopEventKind,
+    pub visit_id: Uuid,
+    pub tournee_id: Uuid,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub package_ids: Vec<Uuid>,
+    pub arrived_at: DateTime<Utc>,
+    pub departed_at: Option<DateTime<Utc>>,
+    /// Tiempo en la parada (solo en las salidas)
+    pub dwell_seconds: Option<i32>,
+}
+
+/// Hora estimada de llegada a una parada pendiente
+#[derive(Debug, Clone, Serialize)]
+pub struct StopEta {
+    pub kind: StopKind,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub package_ids: Vec<Uuid>,
+    pub pickup_id: Option<Uuid>,
+    /// Distancia en línea recta desde la parada anterior
+    pub distance_meters: f64,
+    /// Llegada prevista (o apertura de la franja si se llega antes)
+    pub eta: DateTime<Utc>,
+    /// Llegada después del fin de la franja horaria
+    pub late: bool,
+    /// Bultos a bordo al salir de la parada
+    pub load_after: i32,
+    /// La recogida no cabe en el vehículo, pero no queda otra parada posible
+    pub over_capacity: bool,
+}
+
+/// ETAs de las paradas pendientes de una tournée, en el orden previsto
+#[derive(Debug, Clone, Serialize)]
+pub struct TourneeEta {
+    pub tournee_id: Uuid,
+    /// Tiempo de servicio por parada usado (mediana medida del chofer o el
+    /// valor por defecto)
+    pub service_seconds: f64,
+    /// `true` si el tiempo de servicio sale de visitas medidas
+    pub measured_service_time: bool,
+    pub computed_at: DateTime<Utc>,
+    /// Capacidad del vehículo en bultos (`None` = sin límite)
+    pub vehicle_capacity: Option<i32>,
+    /// Bultos a bordo al planificar
+    pub initial_load: i32,
+    pub stops: Vec<StopEta>,
+}
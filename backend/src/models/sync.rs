@@ -0,0 +1,152 @@
+//! Modelo de sincronización offline
+//!
+//! La app acumula mutaciones mientras no tiene cobertura y las envía en lote a
+//! `POST /api/v1/sync`. Cada mutación lleva una clave de idempotencia generada
+//! en el dispositivo y la hora del dispositivo; el servidor las aplica en
+//! orden (last-writer-wins por hora del dispositivo), informa de los
+//! conflictos y devuelve los cambios del servidor desde el último cursor.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::delivery_proof::ProofOfDeliveryRequest;
+use crate::models::package::{MarkDeliveredRequest, MarkFailedRequest, PackageResponse};
+use crate::models::scan_event::ScanRequest;
+use crate::models::tournee::TourneeResponse;
+
+/// Lote de mutaciones de la app
+#[derive(Debug, Deserialize, Validate)]
+pub struct SyncRequest {
+    #[validate(length(max = 100))]
+    pub device_id: Option<String>,
+    /// Cursor devuelto por la sincronización anterior (`None` = primera)
+    pub cursor: Option<String>,
+    /// Se parsean una a una para que una mutación mal formada no invalide el lote
+    #[validate(length(max = 500))]
+    #[serde(default)]
+    pub mutations: Vec<Value>,
+}
+
+/// Mutación generada en el dispositivo
+#[derive(Debug, Deserialize)]
+pub struct SyncMutation {
+    pub idempotency_key: String,
+    pub device_timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub operation: SyncOperation,
+}
+
+/// Operaciones admitidas (`type` en el JSON)
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SyncOperation {
+    /// Cambio de estado a entregado (con o sin prueba de entrega)
+    Delivered {
+        package_id: Uuid,
+        #[serde(flatten)]
+        data: MarkDeliveredRequest,
+    },
+    /// Cambio de estado a fallido
+    Failed {
+        package_id: Uuid,
+        #[serde(flatten)]
+        data: MarkFailedRequest,
+    },
+    /// Notas del chofer sobre el paquete
+    Note {
+        package_id: Uuid,
+        driver_notes: String,
+    },
+    /// Lectura de código de barras (se registra en scan_events)
+    Scan {
+        #[serde(flatten)]
+        data: ScanRequest,
+    },
+    /// Prueba de entrega de un paquete ya entregado offline (las fotos se
+    /// suben al recuperar cobertura)
+    Proof {
+        package_id: Uuid,
+        proof: ProofOfDeliveryRequest,
+    },
+}
+
+impl SyncOperation {
+    /// Valor de sync_mutations.mutation_type
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncOperation::Delivered { .. } => "delivered",
+            SyncOperation::Failed { .. } => "failed",
+            SyncOperation::Note { .. } => "note",
+            SyncOperation::Scan { .. } => "scan",
+            SyncOperation::Proof { .. } => "proof",
+        }
+    }
+
+    pub fn package_id(&self) -> Option<Uuid> {
+        match self {
+            SyncOperation::Delivered { package_id, .. }
+            | SyncOperation::Failed { package_id, .. }
+            | SyncOperation::Note { package_id, .. }
+            | SyncOperation::Proof { package_id, .. } => Some(*package_id),
+            SyncOperation::Scan { data } => data.package_id,
+        }
+    }
+}
+
+/// Resultado de una mutación
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncOutcome {
+    Applied,
+    /// No aplicada: el servidor tiene un cambio posterior o incompatible
+    Conflict,
+    /// No aplicada: mutación inválida
+    Rejected,
+}
+
+impl SyncOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncOutcome::Applied => "applied",
+            SyncOutcome::Conflict => "conflict",
+            SyncOutcome::Rejected => "rejected",
+        }
+    }
+}
+
+/// Resultado de una mutación (se guarda para responder igual a los reenvíos)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncMutationResult {
+    pub idempotency_key: Option<String>,
+    pub outcome: SyncOutcome,
+    pub entity_id: Option<Uuid>,
+    pub message: Option<String>,
+    /// Estado del servidor que ganó, en los conflictos
+    pub server_state: Option<Value>,
+    /// `true` si la clave ya se había procesado en un lote anterior
+    #[serde(default)]
+    pub replayed: bool,
+}
+
+/// Cambios del servidor desde el cursor
+#[derive(Debug, Serialize)]
+pub struct SyncDelta {
+    pub packages: Vec<PackageResponse>,
+    pub deleted_package_ids: Vec<Uuid>,
+    pub tournees: Vec<TourneeResponse>,
+    /// Quedan más paquetes: volver a sincronizar con el nuevo cursor
+    pub has_more: bool,
+}
+
+/// Respuesta de la sincronización
+#[derive(Debug, Serialize)]
+pub struct SyncResponse {
+    pub results: Vec<SyncMutationResult>,
+    pub conflicts: usize,
+    pub delta: SyncDelta,
+    pub cursor: String,
+    pub server_time: DateTime<Utc>,
+}
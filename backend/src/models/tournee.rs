@@ -12,7 +12,7 @@ use rust_decimal::Decimal;
 
 /// Estado de la tournée - mapea al ENUM tournee_status
 #[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq)]
-#[sqlx(type_name = "tournee_status", rename_all = "lowercase")]
+#[sqlx(type_name = "tournee_status", rename_all = "snake_case")]
 pub enum TourneeStatus {
     Pending,
     InProgress,
@@ -21,6 +21,30 @@ pub enum TourneeStatus {
     Paused,
 }
 
+impl TourneeStatus {
+    /// Valor del ENUM en la base de datos
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TourneeStatus::Pending => "pending",
+            TourneeStatus::InProgress => "in_progress",
+            TourneeStatus::Completed => "completed",
+            TourneeStatus::Cancelled => "cancelled",
+            TourneeStatus::Paused => "paused",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "pending" => Some(TourneeStatus::Pending),
+            "in_progress" => Some(TourneeStatus::InProgress),
+            "completed" => Some(TourneeStatus::Completed),
+            "cancelled" => Some(TourneeStatus::Cancelled),
+            "paused" => Some(TourneeStatus::Paused),
+            _ => None,
+        }
+    }
+}
+
 /// Origen de la tournée - mapea al campo tournee_origin
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum TourneeOrigin {
@@ -76,18 +100,37 @@ pub struct Tournee {
     pub external_tournee_id: Option<String>,
     pub integration_id: Option<Uuid>,
     
+    // Cierre del día (compte-rendu firmado)
+    pub locked_at: Option<DateTime<Utc>>,
+    pub locked_by: Option<Uuid>,
+    
     // Metadatos
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
     pub deleted_at: Option<DateTime<Utc>>,
 }
 
+/// Columnas de tournees en el orden de `Tournee` (para consultas runtime)
+pub const TOURNEE_COLUMNS: &str = r#"
+    id, company_id, driver_id, vehicle_id, tournee_date, tournee_number,
+    start_location, end_location, tournee_status, start_time, end_time,
+    start_mileage, end_mileage, total_distance, fuel_consumed, fuel_cost,
+    pre_inspection_notes, post_inspection_notes, pre_inspection_photos,
+    post_inspection_photos, route_optimization_score, estimated_duration_minutes,
+    actual_duration_minutes, route_coordinates, traffic_conditions,
+    weather_conditions, tournee_origin, external_tournee_id, integration_id,
+    locked_at, locked_by, created_at, updated_at, deleted_at
+"#;
+
 /// Request para crear una nueva tournée
 #[derive(Debug, Deserialize, Validate)]
 pub struct CreateTourneeRequest {
     pub driver_id: String,
     pub vehicle_id: String,
     
+    /// Fecha de la tournée (hoy si no se indica)
+    pub tournee_date: Option<NaiveDate>,
+    
     #[validate(length(min = 3, max = 50))]
     pub tournee_number: Option<String>,
     
@@ -109,7 +152,15 @@ pub struct CreateTourneeRequest {
 /// Request para actualizar una tournée existente
 #[derive(Debug, Deserialize, Validate)]
 pub struct UpdateTourneeRequest {
-    pub tournee_status: Option<String>,
+    pub driver_id: Option<String>,
+    pub vehicle_id: Option<String>,
+    pub tournee_date: Option<NaiveDate>,
+    
+    #[validate(length(min = 3, max = 50))]
+    pub tournee_number: Option<String>,
+    
+    #[validate(range(min = 0))]
+    pub estimated_duration_minutes: Option<i32>,
     
     #[validate(length(min = 5, max = 500))]
     pub start_location: Option<String>,
@@ -131,16 +182,15 @@ pub struct UpdateTourneeRequest {
     pub actual_duration_minutes: Option<i32>,
 }
 
-/// Request para iniciar una tournée
+/// Request para iniciar una tournée (las fotos de inspección se suben por media)
 #[derive(Debug, Deserialize, Validate)]
 pub struct StartTourneeRequest {
     pub start_mileage: Decimal,
     
     pub pre_inspection_notes: Option<String>,
-    pub pre_inspection_photos: Option<Vec<String>>,
 }
 
-/// Request para finalizar una tournée
+/// Request para finalizar una tournée (las fotos de inspección se suben por media)
 #[derive(Debug, Deserialize, Validate)]
 pub struct EndTourneeRequest {
     pub end_mileage: Decimal,
@@ -150,7 +200,11 @@ pub struct EndTourneeRequest {
     pub fuel_cost: Option<Decimal>,
     
     pub post_inspection_notes: Option<String>,
-    pub post_inspection_photos: Option<Vec<String>>,
+
+    /// Finalizar aunque queden paquetes sin resolver (solo admin; se reprograman)
+    pub force: Option<bool>,
+    #[validate(length(max = 500))]
+    pub reason: Option<String>,
 }
 
 /// Response de tournée para la API
@@ -177,6 +231,7 @@ pub struct TourneeResponse {
     pub company_id: String,
     pub tournee_origin: Option<String>,
     pub external_tournee_id: Option<String>,
+    pub locked_at: Option<String>,
     pub created_at: Option<String>,
     pub updated_at: Option<String>,
 }
@@ -207,6 +262,7 @@ pub struct TourneeFilters {
     pub tournee_date_from: Option<String>,
     pub tournee_date_to: Option<String>,
     pub tournee_origin: Option<String>,
+    /// Fecha de alta (YYYY-MM-DD, hora de París)
     pub created_after: Option<String>,
     pub created_before: Option<String>,
     pub limit: Option<i64>,
@@ -221,7 +277,7 @@ impl From<Tournee> for TourneeResponse {
             tournee_date: tournee.tournee_date.to_string(),
             start_location: tournee.start_location,
             end_location: tournee.end_location,
-            tournee_status: format!("{:?}", tournee.tournee_status).to_lowercase(),
+            tournee_status: tournee.tournee_status.as_str().to_string(),
             start_time: tournee.start_time.map(|dt| dt.to_rfc3339()),
             end_time: tournee.end_time.map(|dt| dt.to_rfc3339()),
             start_mileage: tournee.start_mileage.map(|m| m.to_string()),
@@ -237,6 +293,7 @@ impl From<Tournee> for TourneeResponse {
             company_id: tournee.company_id.to_string(),
             tournee_origin: tournee.tournee_origin,
             external_tournee_id: tournee.external_tournee_id,
+            locked_at: tournee.locked_at.map(|dt| dt.to_rfc3339()),
             created_at: tournee.created_at.map(|dt| dt.to_rfc3339()),
             updated_at: tournee.updated_at.map(|dt| dt.to_rfc3339()),
         }
@@ -249,7 +306,7 @@ impl From<Tournee> for TourneeListResponse {
             id: tournee.id.to_string(),
             tournee_number: tournee.tournee_number,
             tournee_date: tournee.tournee_date.to_string(),
-            tournee_status: format!("{:?}", tournee.tournee_status).to_lowercase(),
+            tournee_status: tournee.tournee_status.as_str().to_string(),
             start_location: tournee.start_location,
             end_location: tournee.end_location,
             total_distance: tournee.total_distance.map(|d| d.to_string()),
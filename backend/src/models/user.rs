@@ -35,11 +35,28 @@ pub struct User {
     pub user_status: UserStatus,
     pub username: String,
     pub password_hash: String,
+    pub full_name: String,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub tournee_number: Option<String>,
+    /// Categorías del permiso de conducir (B, C1, C...)
+    pub license_categories: Vec<String>,
+    /// Vehículo que el planificador intenta asignarle primero
+    pub preferred_vehicle_id: Option<Uuid>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub deleted_at: Option<DateTime<Utc>>,
 }
 
+/// Columnas de users en el orden de `User` (para consultas runtime)
+pub const USER_COLUMNS: &str = r#"
+    id, company_id, user_type, user_status, username, password_hash,
+    full_name, email, phone, tournee_number, license_categories,
+    preferred_vehicle_id,
+    COALESCE(created_at, NOW()) AS created_at,
+    COALESCE(updated_at, NOW()) AS updated_at, deleted_at
+"#;
+
 /// Request para crear un nuevo usuario
 #[derive(Debug, Deserialize, Validate)]
 pub struct CreateUserRequest {
@@ -49,10 +66,23 @@ pub struct CreateUserRequest {
     #[validate(length(min = 6, max = 100))]
     pub password: String,
     
+    #[validate(length(min = 2, max = 255))]
+    pub full_name: String,
+    
     #[validate(email)]
-    pub email: String,
+    pub email: Option<String>,
+    
+    #[validate(length(min = 6, max = 20))]
+    pub phone: Option<String>,
+    
+    #[validate(length(min = 1, max = 20))]
+    pub tournee_number: Option<String>,
     
     pub user_type: UserType,
+
+    /// Por defecto `["B"]`
+    pub license_categories: Option<Vec<String>>,
+    pub preferred_vehicle_id: Option<Uuid>,
 }
 
 /// Request para actualizar un usuario existente
@@ -61,14 +91,26 @@ pub struct UpdateUserRequest {
     #[validate(length(min = 3, max = 50))]
     pub username: Option<String>,
     
+    #[validate(length(min = 2, max = 255))]
+    pub full_name: Option<String>,
+    
     #[validate(email)]
     pub email: Option<String>,
     
+    #[validate(length(min = 6, max = 20))]
+    pub phone: Option<String>,
+    
+    #[validate(length(min = 1, max = 20))]
+    pub tournee_number: Option<String>,
+    
     pub user_type: Option<UserType>,
     pub user_status: Option<UserStatus>,
     
     #[validate(length(min = 6, max = 100))]
     pub password: Option<String>,
+
+    pub license_categories: Option<Vec<String>>,
+    pub preferred_vehicle_id: Option<Uuid>,
 }
 
 /// Response de usuario para la API
@@ -79,7 +121,12 @@ pub struct UserResponse {
     pub user_type: UserType,
     pub user_status: UserStatus,
     pub username: String,
+    pub full_name: String,
     pub email: Option<String>,
+    pub phone: Option<String>,
+    pub tournee_number: Option<String>,
+    pub license_categories: Vec<String>,
+    pub preferred_vehicle_id: Option<Uuid>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -133,7 +180,12 @@ impl From<User> for UserResponse {
             user_type: user.user_type,
             user_status: user.user_status,
             username: user.username,
-            email: None, // Campo no existe en el schema actual
+            full_name: user.full_name,
+            email: user.email,
+            phone: user.phone,
+            tournee_number: user.tournee_number,
+            license_categories: user.license_categories,
+            preferred_vehicle_id: user.preferred_vehicle_id,
             created_at: user.created_at,
             updated_at: user.updated_at,
         }
@@ -12,7 +12,7 @@ use rust_decimal::Decimal;
 
 /// Estado del vehículo - mapea al ENUM vehicle_status
 #[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq)]
-#[sqlx(type_name = "vehicle_status", rename_all = "lowercase")]
+#[sqlx(type_name = "vehicle_status", rename_all = "snake_case")]
 pub enum VehicleStatus {
     Active,
     Maintenance,
@@ -20,6 +20,28 @@ pub enum VehicleStatus {
     Retired,
 }
 
+impl VehicleStatus {
+    /// Valor del ENUM en la base de datos
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VehicleStatus::Active => "active",
+            VehicleStatus::Maintenance => "maintenance",
+            VehicleStatus::OutOfService => "out_of_service",
+            VehicleStatus::Retired => "retired",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "active" => Some(VehicleStatus::Active),
+            "maintenance" => Some(VehicleStatus::Maintenance),
+            "out_of_service" => Some(VehicleStatus::OutOfService),
+            "retired" => Some(VehicleStatus::Retired),
+            _ => None,
+        }
+    }
+}
+
 /// Vehicle principal - mapea exactamente a la tabla vehicles
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Vehicle {
@@ -37,6 +59,14 @@ pub struct Vehicle {
     pub fuel_type: String,
     pub fuel_capacity: Option<Decimal>,
     pub weekly_fuel_allocation: Option<Decimal>,
+    /// Paquetes que caben en la caja (`None` = sin límite)
+    pub parcel_capacity: Option<i32>,
+    /// Consumo de referencia en L/100 km (cuadre de combustible)
+    pub expected_consumption: Option<Decimal>,
+    /// Tarjeta de combustible asignada al vehículo
+    pub fuel_card_number: Option<String>,
+    /// Categoría de permiso necesaria (B por defecto)
+    pub required_license: String,
     
     // Métricas de daños
     pub total_damage_cost: Decimal,
@@ -53,6 +83,15 @@ pub struct Vehicle {
     pub deleted_at: Option<DateTime<Utc>>,
 }
 
+/// Columnas de vehicles en el orden de `Vehicle` (para consultas runtime)
+pub const VEHICLE_COLUMNS: &str = r#"
+    id, company_id, license_plate, brand, model, year, color, vehicle_status,
+    current_mileage, fuel_type, fuel_capacity, weekly_fuel_allocation,
+    parcel_capacity, expected_consumption, fuel_card_number, required_license,
+    total_damage_cost, damage_incidents_count, vin, engine_size, transmission,
+    created_at, updated_at, deleted_at
+"#;
+
 /// Request para crear un nuevo vehículo
 #[derive(Debug, Deserialize, Validate)]
 pub struct CreateVehicleRequest {
@@ -78,6 +117,17 @@ pub struct CreateVehicleRequest {
     
     pub weekly_fuel_allocation: Option<Decimal>,
     
+    #[validate(range(min = 1))]
+    pub parcel_capacity: Option<i32>,
+    
+    pub expected_consumption: Option<Decimal>,
+    
+    #[validate(length(min = 4, max = 50))]
+    pub fuel_card_number: Option<String>,
+    
+    #[validate(length(min = 1, max = 5))]
+    pub required_license: Option<String>,
+    
     pub vin: Option<String>,
     pub engine_size: Option<String>,
     pub transmission: Option<String>,
@@ -112,6 +162,17 @@ pub struct UpdateVehicleRequest {
     
     pub weekly_fuel_allocation: Option<Decimal>,
     
+    #[validate(range(min = 1))]
+    pub parcel_capacity: Option<i32>,
+    
+    pub expected_consumption: Option<Decimal>,
+    
+    #[validate(length(min = 4, max = 50))]
+    pub fuel_card_number: Option<String>,
+    
+    #[validate(length(min = 1, max = 5))]
+    pub required_license: Option<String>,
+    
     pub vin: Option<String>,
     pub engine_size: Option<String>,
     pub transmission: Option<String>,
@@ -131,6 +192,10 @@ pub struct VehicleResponse {
     pub fuel_type: String,
     pub fuel_capacity: Option<String>,
     pub weekly_fuel_allocation: Option<String>,
+    pub parcel_capacity: Option<i32>,
+    pub expected_consumption: Option<String>,
+    pub fuel_card_number: Option<String>,
+    pub required_license: String,
     pub total_damage_cost: String,
     pub damage_incidents_count: i32,
     pub vin: Option<String>,
@@ -167,6 +232,7 @@ pub struct VehicleFilters {
     pub model: Option<String>,
     pub year_from: Option<i32>,
     pub year_to: Option<i32>,
+    /// Fecha de alta (YYYY-MM-DD, hora de París)
     pub created_after: Option<String>,
     pub created_before: Option<String>,
     pub limit: Option<i64>,
@@ -195,11 +261,15 @@ impl From<Vehicle> for VehicleResponse {
             model: vehicle.model,
             year: vehicle.year,
             color: vehicle.color,
-            vehicle_status: format!("{:?}", vehicle.vehicle_status).to_lowercase(),
+            vehicle_status: vehicle.vehicle_status.as_str().to_string(),
             current_mileage: vehicle.current_mileage.to_string(),
             fuel_type: vehicle.fuel_type,
             fuel_capacity: vehicle.fuel_capacity.map(|f| f.to_string()),
             weekly_fuel_allocation: vehicle.weekly_fuel_allocation.map(|f| f.to_string()),
+            parcel_capacity: vehicle.parcel_capacity,
+            expected_consumption: vehicle.expected_consumption.map(|c| c.to_string()),
+            fuel_card_number: vehicle.fuel_card_number,
+            required_license: vehicle.required_license,
             total_damage_cost: vehicle.total_damage_cost.to_string(),
             damage_incidents_count: vehicle.damage_incidents_count,
             vin: vehicle.vin,
@@ -221,7 +291,7 @@ impl From<Vehicle> for VehicleListResponse {
             model: vehicle.model,
             year: vehicle.year,
             color: vehicle.color,
-            vehicle_status: format!("{:?}", vehicle.vehicle_status).to_lowercase(),
+            vehicle_status: vehicle.vehicle_status.as_str().to_string(),
             current_mileage: vehicle.current_mileage.to_string(),
             fuel_type: vehicle.fuel_type,
             total_damage_cost: vehicle.total_damage_cost.to_string(),
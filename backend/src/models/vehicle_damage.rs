@@ -0,0 +1,254 @@
+//! Modelo de daños e incidentes de vehículo
+//!
+//! El chofer declara el incidente desde la app (ligado a su tournée activa)
+//! y el admin lo valora, lo repara y decide quién paga: el chofer responde de
+//! su `responsibility_percentage` del coste que asume la empresa (la
+//! reparación, o la franquicia del seguro si hay siniestro declarado). Ver
+//! `services::vehicle_damages`.
+
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Type};
+use uuid::Uuid;
+use validator::Validate;
+
+/// Tipo de daño - mapea al ENUM damage_type
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[sqlx(type_name = "damage_type", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum DamageType {
+    Scratch,
+    Dent,
+    Mechanical,
+    Accident,
+    TrafficFine,
+    Vandalism,
+    WeatherDamage,
+    WearAndTear,
+}
+
+impl DamageType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DamageType::Scratch => "scratch",
+            DamageType::Dent => "dent",
+            DamageType::Mechanical => "mechanical",
+            DamageType::Accident => "accident",
+            DamageType::TrafficFine => "traffic_fine",
+            DamageType::Vandalism => "vandalism",
+            DamageType::WeatherDamage => "weather_damage",
+            DamageType::WearAndTear => "wear_and_tear",
+        }
+    }
+
+    /// Incidentes que pueden dejar el vehículo fuera de servicio
+    pub fn is_serious(&self) -> bool {
+        matches!(self, DamageType::Accident | DamageType::Mechanical)
+    }
+}
+
+/// Estado del daño - mapea al ENUM damage_status
+///
+/// ```text
+/// pending ──► assessed ──► repaired / driver_liable / insurance_claim ──► closed
+///    └───────────┴──────────────────────────────────────────────────────────┘
+/// ```
+///
+/// Tras la peritación, reparación, imputación al chofer y siniestro se pueden
+/// encadenar en cualquier orden.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[sqlx(type_name = "damage_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum DamageStatus {
+    Pending,
+    Assessed,
+    Repaired,
+    DriverLiable,
+    InsuranceClaim,
+    Closed,
+}
+
+impl DamageStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DamageStatus::Pending => "pending",
+            DamageStatus::Assessed => "assessed",
+            DamageStatus::Repaired => "repaired",
+            DamageStatus::DriverLiable => "driver_liable",
+            DamageStatus::InsuranceClaim => "insurance_claim",
+            DamageStatus::Closed => "closed",
+        }
+    }
+}
+
+/// Daño de vehículo - mapea a la tabla vehicle_damages
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct VehicleDamage {
+    pub id: Uuid,
+    pub vehicle_id: Uuid,
+    pub driver_id: Option<Uuid>,
+    pub tournee_id: Option<Uuid>,
+
+    // Incidente
+    pub incident_date: NaiveDate,
+    pub incident_time: Option<NaiveTime>,
+    pub damage_type: DamageType,
+    pub damage_location: Option<String>,
+    pub description: String,
+
+    // Costes y responsabilidad
+    pub estimated_repair_cost: Option<Decimal>,
+    pub actual_repair_cost: Option<Decimal>,
+    pub responsibility_percentage: Option<i32>,
+    /// Importe a cargo del chofer
+    pub driver_deductible: Option<Decimal>,
+
+    // Seguimiento
+    pub damage_status: DamageStatus,
+    pub assessment_date: Option<NaiveDate>,
+    pub repair_date: Option<NaiveDate>,
+    pub completion_date: Option<NaiveDate>,
+
+    // Evidencias (las fotos se suben con la media `damage-evidence`)
+    pub photo_evidence: Option<Vec<String>>,
+    pub police_report: Option<String>,
+    pub witness_statements: Option<String>,
+
+    // Seguro
+    pub insurance_claim: Option<bool>,
+    pub claim_number: Option<String>,
+    /// Franquicia del seguro
+    pub deductible_amount: Option<Decimal>,
+    pub insurance_company: Option<String>,
+
+    // Metadatos
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl VehicleDamage {
+    /// Coste de la reparación: el real si ya se conoce, si no el estimado
+    pub fn repair_cost(&self) -> Option<Decimal> {
+        self.actual_repair_cost.or(self.estimated_repair_cost)
+    }
+}
+
+/// Columnas de vehicle_damages en el orden de `VehicleDamage`
+pub const VEHICLE_DAMAGE_COLUMNS: &str = r#"
+    id, vehicle_id, driver_id, tournee_id, incident_date, incident_time,
+    damage_type, damage_location, description, estimated_repair_cost,
+    actual_repair_cost, responsibility_percentage, driver_deductible,
+    damage_status, assessment_date, repair_date, completion_date,
+    photo_evidence, police_report, witness_statements, insurance_claim,
+    claim_number, deductible_amount, insurance_company, created_at,
+    updated_at, deleted_at
+"#;
+
+/// Request para declarar un incidente
+///
+/// Un chofer sin `vehicle_id` lo declara sobre el vehículo de su tournée
+/// activa; un admin indica el vehículo (y el chofer, si no hay tournée).
+#[derive(Debug, Deserialize, Validate)]
+pub struct DeclareDamageRequest {
+    pub vehicle_id: Option<Uuid>,
+    pub tournee_id: Option<Uuid>,
+    pub driver_id: Option<Uuid>,
+    pub incident_date: Option<NaiveDate>,
+    pub incident_time: Option<NaiveTime>,
+    pub damage_type: DamageType,
+    #[validate(length(max = 255))]
+    pub damage_location: Option<String>,
+    #[validate(length(min = 5, max = 5000))]
+    pub description: String,
+    #[validate(length(max = 5000))]
+    pub police_report: Option<String>,
+    #[validate(length(max = 5000))]
+    pub witness_statements: Option<String>,
+}
+
+/// Request para corregir la descripción de un incidente
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateDamageRequest {
+    pub incident_date: Option<NaiveDate>,
+    pub incident_time: Option<NaiveTime>,
+    pub damage_type: Option<DamageType>,
+    #[validate(length(max = 255))]
+    pub damage_location: Option<String>,
+    #[validate(length(min = 5, max = 5000))]
+    pub description: Option<String>,
+    #[validate(length(max = 5000))]
+    pub police_report: Option<String>,
+    #[validate(length(max = 5000))]
+    pub witness_statements: Option<String>,
+}
+
+/// Request para pasar un daño a otro estado (admin)
+///
+/// Cada estado exige sus datos: `assessed` el coste estimado y la
+/// responsabilidad, `repaired` el coste real, `insurance_claim` el número de
+/// siniestro. `driver_deductible` sustituye al importe calculado.
+#[derive(Debug, Deserialize, Validate)]
+pub struct DamageTransitionRequest {
+    pub damage_status: DamageStatus,
+    pub estimated_repair_cost: Option<Decimal>,
+    pub actual_repair_cost: Option<Decimal>,
+    #[validate(range(min = 0, max = 100))]
+    pub responsibility_percentage: Option<i32>,
+    pub driver_deductible: Option<Decimal>,
+    pub repair_date: Option<NaiveDate>,
+    #[validate(length(min = 1, max = 100))]
+    pub claim_number: Option<String>,
+    pub deductible_amount: Option<Decimal>,
+    #[validate(length(max = 100))]
+    pub insurance_company: Option<String>,
+    #[validate(length(max = 1000))]
+    pub reason: Option<String>,
+}
+
+/// Filtros de daños
+#[derive(Debug, Deserialize)]
+pub struct DamageFilters {
+    pub vehicle_id: Option<Uuid>,
+    pub driver_id: Option<Uuid>,
+    pub damage_status: Option<DamageStatus>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+/// Periodo del estado de responsabilidad (por defecto, todo el historial)
+#[derive(Debug, Deserialize)]
+pub struct LiabilityParams {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+/// Línea del estado de responsabilidad del chofer
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct LiabilityLine {
+    pub damage_id: Uuid,
+    pub incident_date: NaiveDate,
+    pub license_plate: String,
+    pub damage_type: DamageType,
+    pub damage_status: DamageStatus,
+    pub repair_cost: Option<Decimal>,
+    pub insurance_claim: Option<bool>,
+    pub deductible_amount: Option<Decimal>,
+    pub responsibility_percentage: Option<i32>,
+    pub driver_deductible: Option<Decimal>,
+}
+
+/// Estado de responsabilidad del chofer: importes a su cargo
+#[derive(Debug, Clone, Serialize)]
+pub struct LiabilityStatement {
+    pub driver_id: Uuid,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub incidents: usize,
+    /// Incidentes todavía sin peritar (sin importe)
+    pub pending_assessment: usize,
+    /// Suma de `driver_deductible`
+    pub total_owed: Decimal,
+    pub lines: Vec<LiabilityLine>,
+}
@@ -0,0 +1,210 @@
+//! Modelo de documentos de vehículo
+//!
+//! Seguro, contrôle technique, carte grise... con su fecha de caducidad. El
+//! estado lo recalcula el trigger `update_document_status` y, cada día, el
+//! job de caducidades, que avisa una sola vez por umbral (30 días, 15 días,
+//! caducado) mediante los flags `notification_sent_*` (ver
+//! `services::vehicle_documents`).
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Type};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::notification::{NotificationPriority, NotificationType};
+
+/// Tipo de documento - mapea al ENUM document_type
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[sqlx(type_name = "document_type", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentType {
+    TechnicalControl,
+    Insurance,
+    CarteGrise,
+    DriverLicense,
+    VehicleRegistration,
+    MaintenanceBook,
+}
+
+impl DocumentType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocumentType::TechnicalControl => "technical_control",
+            DocumentType::Insurance => "insurance",
+            DocumentType::CarteGrise => "carte_grise",
+            DocumentType::DriverLicense => "driver_license",
+            DocumentType::VehicleRegistration => "vehicle_registration",
+            DocumentType::MaintenanceBook => "maintenance_book",
+        }
+    }
+
+    /// Sin este documento en vigor el vehículo no puede circular
+    pub fn is_blocking(&self) -> bool {
+        matches!(self, DocumentType::Insurance | DocumentType::TechnicalControl)
+    }
+}
+
+/// Estado del documento - mapea al ENUM document_status
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[sqlx(type_name = "document_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentStatus {
+    Valid,
+    ExpiringSoon,
+    Expired,
+    RenewalInProgress,
+    Missing,
+}
+
+/// Umbral de aviso de caducidad alcanzado
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpiryThreshold {
+    Days30,
+    Days15,
+    Expired,
+}
+
+impl ExpiryThreshold {
+    /// Umbral alcanzado en `today` (el documento vale hasta la víspera de
+    /// `expiry_date`, como en el trigger)
+    pub fn reached(expiry_date: NaiveDate, today: NaiveDate) -> Option<Self> {
+        match (expiry_date - today).num_days() {
+            days if days <= 0 => Some(ExpiryThreshold::Expired),
+            days if days <= 15 => Some(ExpiryThreshold::Days15),
+            days if days <= 30 => Some(ExpiryThreshold::Days30),
+            _ => None,
+        }
+    }
+
+    pub fn notification_type(&self) -> NotificationType {
+        match self {
+            ExpiryThreshold::Days30 => NotificationType::ExpiryWarning30,
+            ExpiryThreshold::Days15 => NotificationType::ExpiryWarning15,
+            ExpiryThreshold::Expired => NotificationType::ExpiredCritical,
+        }
+    }
+
+    pub fn priority(&self) -> NotificationPriority {
+        match self {
+            ExpiryThreshold::Days30 => NotificationPriority::Medium,
+            ExpiryThreshold::Days15 => NotificationPriority::High,
+            ExpiryThreshold::Expired => NotificationPriority::Critical,
+        }
+    }
+
+    /// Columna del flag de aviso enviado
+    pub fn flag_column(&self) -> &'static str {
+        match self {
+            ExpiryThreshold::Days30 => "notification_sent_30_days",
+            ExpiryThreshold::Days15 => "notification_sent_15_days",
+            ExpiryThreshold::Expired => "notification_sent_expired",
+        }
+    }
+}
+
+/// Documento de vehículo - mapea a la tabla vehicle_documents
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct VehicleDocument {
+    pub id: Uuid,
+    pub company_id: Uuid,
+    pub vehicle_id: Uuid,
+    pub document_type: DocumentType,
+    pub document_number: Option<String>,
+    pub document_name: String,
+
+    // Fechas
+    pub issue_date: Option<NaiveDate>,
+    pub expiry_date: NaiveDate,
+    pub renewal_reminder_date: Option<NaiveDate>,
+
+    // Estado y avisos
+    pub document_status: DocumentStatus,
+    pub notification_sent_30_days: Option<bool>,
+    pub notification_sent_15_days: Option<bool>,
+    pub notification_sent_expired: Option<bool>,
+
+    // Archivo (la URL firmada la pone la subida de media `vehicle-document`)
+    pub document_url: Option<String>,
+
+    // Notas
+    pub notes: Option<String>,
+    pub renewal_notes: Option<String>,
+    pub insurance_company: Option<String>,
+    pub policy_number: Option<String>,
+
+    // Metadatos
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+/// Columnas de vehicle_documents en el orden de `VehicleDocument`
+pub const VEHICLE_DOCUMENT_COLUMNS: &str = r#"
+    id, company_id, vehicle_id, document_type, document_number, document_name,
+    issue_date, expiry_date, renewal_reminder_date, document_status,
+    notification_sent_30_days, notification_sent_15_days, notification_sent_expired,
+    document_url, notes, renewal_notes, insurance_company, policy_number,
+    created_at, updated_at, deleted_at
+"#;
+
+/// Request para registrar un documento del vehículo
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateVehicleDocumentRequest {
+    pub document_type: DocumentType,
+    #[validate(length(max = 100))]
+    pub document_number: Option<String>,
+    #[validate(length(min = 1, max = 255))]
+    pub document_name: String,
+    pub issue_date: Option<NaiveDate>,
+    pub expiry_date: NaiveDate,
+    pub renewal_reminder_date: Option<NaiveDate>,
+    #[validate(length(max = 100))]
+    pub insurance_company: Option<String>,
+    #[validate(length(max = 100))]
+    pub policy_number: Option<String>,
+    pub notes: Option<String>,
+}
+
+/// Request para renovar un documento (nueva caducidad, avisos a cero)
+#[derive(Debug, Deserialize, Validate)]
+pub struct RenewVehicleDocumentRequest {
+    pub expiry_date: NaiveDate,
+    pub issue_date: Option<NaiveDate>,
+    #[validate(length(max = 100))]
+    pub document_number: Option<String>,
+    #[validate(length(max = 100))]
+    pub insurance_company: Option<String>,
+    #[validate(length(max = 100))]
+    pub policy_number: Option<String>,
+    pub renewal_notes: Option<String>,
+}
+
+/// Resultado de una pasada del job de caducidades
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExpiryCheckSummary {
+    /// Documentos cuyo estado ha cambiado
+    pub statuses_updated: u64,
+    /// Notificaciones registradas
+    pub notifications: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expiry_threshold_reached() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let at = |days: i64| ExpiryThreshold::reached(today + chrono::Duration::days(days), today);
+
+        assert_eq!(at(31), None);
+        assert_eq!(at(30), Some(ExpiryThreshold::Days30));
+        assert_eq!(at(16), Some(ExpiryThreshold::Days30));
+        assert_eq!(at(15), Some(ExpiryThreshold::Days15));
+        assert_eq!(at(1), Some(ExpiryThreshold::Days15));
+        assert_eq!(at(0), Some(ExpiryThreshold::Expired));
+        assert_eq!(at(-10), Some(ExpiryThreshold::Expired));
+    }
+}
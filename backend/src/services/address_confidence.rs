@@ -0,0 +1,279 @@
+//! Score numérico de confianza de direcciones (0–100)
+//!
+//! Combina varias señales independientes (score de geocodificación del
+//! transportista, relevancia del geocoder, acuerdo entre proveedores,
+//! precisión del resultado, coincidencia del código postal y verificaciones
+//! de choferes) en un único score ponderado. `ValidationConfidence` se deriva
+//! del score mediante umbrales.
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::ConfidenceWeights;
+use crate::services::address_validation::ValidationConfidence;
+
+/// Umbrales para derivar `ValidationConfidence` del score
+pub const HIGH_CONFIDENCE_THRESHOLD: u8 = 80;
+pub const MEDIUM_CONFIDENCE_THRESHOLD: u8 = 60;
+pub const LOW_CONFIDENCE_THRESHOLD: u8 = 35;
+
+/// Distancia (m) por debajo de la cual dos proveedores se consideran de acuerdo
+const AGREEMENT_FULL_METERS: f64 = 50.0;
+/// Distancia (m) a partir de la cual dos proveedores se consideran en desacuerdo total
+const AGREEMENT_NONE_METERS: f64 = 1000.0;
+/// Verificaciones de chofer necesarias para la señal máxima
+const DRIVER_VERIFICATIONS_FOR_FULL_SCORE: i32 = 3;
+
+/// Precisión del punto devuelto por el geocoder
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GeocodePrecision {
+    Rooftop,
+    Parcel,
+    Point,
+    Interpolated,
+    Intersection,
+    Street,
+    Approximate,
+    Area,
+}
+
+impl GeocodePrecision {
+    /// Interpretar `coordinates.accuracy` / `feature_type` de Mapbox
+    pub fn from_mapbox(accuracy: Option<&str>, feature_type: Option<&str>) -> Option<Self> {
+        match accuracy {
+            Some("rooftop") => return Some(Self::Rooftop),
+            Some("parcel") => return Some(Self::Parcel),
+            Some("point") => return Some(Self::Point),
+            Some("interpolated") => return Some(Self::Interpolated),
+            Some("intersection") => return Some(Self::Intersection),
+            Some("street") => return Some(Self::Street),
+            Some("approximate") => return Some(Self::Approximate),
+            _ => {}
+        }
+
+        match feature_type {
+            Some("address") => Some(Self::Point),
+            Some("street") => Some(Self::Street),
+            Some(_) => Some(Self::Area),
+            None => None,
+        }
+    }
+
+    fn signal(&self) -> f64 {
+        match self {
+            Self::Rooftop | Self::Parcel => 1.0,
+            Self::Point => 0.9,
+            Self::Interpolated => 0.7,
+            Self::Intersection | Self::Street => 0.5,
+            Self::Approximate => 0.3,
+            Self::Area => 0.1,
+        }
+    }
+}
+
+/// Señales disponibles para una dirección (todas opcionales)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConfidenceSignals {
+    /// `score_geocodage` del transportista (0 a `ConfidenceWeights::carrier_score_scale`)
+    pub carrier_score: Option<f64>,
+    /// Relevancia del geocoder (0–1)
+    pub geocoder_relevance: Option<f64>,
+    /// Distancia en metros entre las coordenadas del transportista y del geocoder
+    pub provider_distance_meters: Option<f64>,
+    /// Precisión del resultado del geocoder
+    pub precision: Option<GeocodePrecision>,
+    /// El código postal del resultado coincide con el de la dirección original
+    pub postcode_match: Option<bool>,
+    /// Número de verificaciones de choferes (`driver_field_data.verification_count`)
+    pub driver_verification_count: Option<i32>,
+}
+
+/// Contribución de una señal al score final
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignalContribution {
+    pub signal: String,
+    /// Valor normalizado de la señal (0–1)
+    pub value: f64,
+    pub weight: f64,
+}
+
+/// Resultado del cálculo de confianza
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfidenceScore {
+    pub score: u8,
+    pub confidence: ValidationConfidence,
+    pub contributions: Vec<SignalContribution>,
+}
+
+impl ValidationConfidence {
+    /// Derivar el nivel de confianza a partir del score numérico
+    pub fn from_score(score: u8) -> Self {
+        if score >= HIGH_CONFIDENCE_THRESHOLD {
+            ValidationConfidence::High
+        } else if score >= MEDIUM_CONFIDENCE_THRESHOLD {
+            ValidationConfidence::Medium
+        } else if score >= LOW_CONFIDENCE_THRESHOLD {
+            ValidationConfidence::Low
+        } else {
+            ValidationConfidence::None
+        }
+    }
+}
+
+/// Calcular el score ponderado de las señales disponibles
+///
+/// Las señales ausentes no penalizan: su peso se excluye de la normalización.
+/// Devuelve `None` si no hay ninguna señal con peso positivo.
+pub fn compute_confidence(signals: &ConfidenceSignals, weights: &ConfidenceWeights) -> Option<ConfidenceScore> {
+    let mut contributions = Vec::new();
+
+    if let Some(score) = signals.carrier_score {
+        contributions.push(("carrier_score", score / weights.carrier_score_scale, weights.carrier_score));
+    }
+    if let Some(relevance) = signals.geocoder_relevance {
+        contributions.push(("geocoder_relevance", relevance, weights.geocoder_relevance));
+    }
+    if let Some(distance) = signals.provider_distance_meters {
+        let value = if distance <= AGREEMENT_FULL_METERS {
+            1.0
+        } else if distance >= AGREEMENT_NONE_METERS {
+            0.0
+        } else {
+            1.0 - (distance - AGREEMENT_FULL_METERS) / (AGREEMENT_NONE_METERS - AGREEMENT_FULL_METERS)
+        };
+        contributions.push(("provider_agreement", value, weights.provider_agreement));
+    }
+    if let Some(precision) = signals.precision {
+        contributions.push(("precision", precision.signal(), weights.precision));
+    }
+    if let Some(matched) = signals.postcode_match {
+        contributions.push(("postcode_match", if matched { 1.0 } else { 0.0 }, weights.postcode_match));
+    }
+    if let Some(count) = signals.driver_verification_count {
+        let value = count.clamp(0, DRIVER_VERIFICATIONS_FOR_FULL_SCORE) as f64
+            / DRIVER_VERIFICATIONS_FOR_FULL_SCORE as f64;
+        contributions.push(("driver_verification", value, weights.driver_verification));
+    }
+
+    let total_weight: f64 = contributions.iter().map(|(_, _, w)| w).sum();
+    if total_weight <= 0.0 {
+        return None;
+    }
+
+    let weighted: f64 = contributions
+        .iter()
+        .map(|(_, value, weight)| value.clamp(0.0, 1.0) * weight)
+        .sum();
+    let score = (weighted / total_weight * 100.0).round().clamp(0.0, 100.0) as u8;
+
+    Some(ConfidenceScore {
+        score,
+        confidence: ValidationConfidence::from_score(score),
+        contributions: contributions
+            .into_iter()
+            .map(|(signal, value, weight)| SignalContribution {
+                signal: signal.to_string(),
+                value: value.clamp(0.0, 1.0),
+                weight,
+            })
+            .collect(),
+    })
+}
+
+/// Distancia haversine en metros entre dos puntos (lat, lng)
+pub fn haversine_meters(a: (f64, f64), b: (f64, f64)) -> f64 {
+    const EARTH_RADIUS_METERS: f64 = 6_371_000.0;
+
+    let (lat1, lng1) = (a.0.to_radians(), a.1.to_radians());
+    let (lat2, lng2) = (b.0.to_radians(), b.1.to_radians());
+    let dlat = lat2 - lat1;
+    let dlng = lng2 - lng1;
+
+    let h = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlng / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_METERS * h.sqrt().asin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_confidence_from_score_thresholds() {
+        assert_eq!(ValidationConfidence::from_score(95), ValidationConfidence::High);
+        assert_eq!(ValidationConfidence::from_score(80), ValidationConfidence::High);
+        assert_eq!(ValidationConfidence::from_score(79), ValidationConfidence::Medium);
+        assert_eq!(ValidationConfidence::from_score(40), ValidationConfidence::Low);
+        assert_eq!(ValidationConfidence::from_score(10), ValidationConfidence::None);
+    }
+
+    #[test]
+    fn test_compute_confidence_all_signals() {
+        let signals = ConfidenceSignals {
+            carrier_score: Some(90.0),
+            geocoder_relevance: Some(1.0),
+            provider_distance_meters: Some(20.0),
+            precision: Some(GeocodePrecision::Rooftop),
+            postcode_match: Some(true),
+            driver_verification_count: Some(3),
+        };
+
+        let result = compute_confidence(&signals, &ConfidenceWeights::default()).unwrap();
+        assert_eq!(result.score, 99);
+        assert_eq!(result.confidence, ValidationConfidence::High);
+        assert_eq!(result.contributions.len(), 6);
+    }
+
+    #[test]
+    fn test_compute_confidence_missing_signals_are_ignored() {
+        let signals = ConfidenceSignals {
+            precision: Some(GeocodePrecision::Street),
+            postcode_match: Some(false),
+            ..Default::default()
+        };
+
+        // (0.5 * 0.20 + 0.0 * 0.15) / 0.35 = 0.2857
+        let result = compute_confidence(&signals, &ConfidenceWeights::default()).unwrap();
+        assert_eq!(result.score, 29);
+        assert_eq!(result.confidence, ValidationConfidence::None);
+
+        assert!(compute_confidence(&ConfidenceSignals::default(), &ConfidenceWeights::default()).is_none());
+    }
+
+    #[test]
+    fn test_carrier_score_uses_configured_scale() {
+        let signals = ConfidenceSignals { carrier_score: Some(0.9), ..Default::default() };
+
+        let percent = compute_confidence(&signals, &ConfidenceWeights::default()).unwrap();
+        assert_eq!(percent.score, 1);
+
+        let unit = ConfidenceWeights { carrier_score_scale: 1.0, ..ConfidenceWeights::default() };
+        assert_eq!(compute_confidence(&signals, &unit).unwrap().score, 90);
+    }
+
+    #[test]
+    fn test_provider_disagreement_lowers_score() {
+        let weights = ConfidenceWeights::default();
+        let near = ConfidenceSignals { provider_distance_meters: Some(30.0), geocoder_relevance: Some(0.85), ..Default::default() };
+        let far = ConfidenceSignals { provider_distance_meters: Some(1500.0), geocoder_relevance: Some(0.85), ..Default::default() };
+
+        let near_score = compute_confidence(&near, &weights).unwrap().score;
+        let far_score = compute_confidence(&far, &weights).unwrap().score;
+        assert!(near_score > far_score);
+    }
+
+    #[test]
+    fn test_haversine_meters() {
+        // Sacré-Cœur -> Gare du Nord ≈ 1.1 km
+        let d = haversine_meters((48.8867, 2.3431), (48.8809, 2.3553));
+        assert!((d - 1100.0).abs() < 150.0, "distance was {}", d);
+        assert_eq!(haversine_meters((48.0, 2.0), (48.0, 2.0)), 0.0);
+    }
+
+    #[test]
+    fn test_precision_from_mapbox() {
+        assert_eq!(GeocodePrecision::from_mapbox(Some("rooftop"), Some("address")), Some(GeocodePrecision::Rooftop));
+        assert_eq!(GeocodePrecision::from_mapbox(None, Some("street")), Some(GeocodePrecision::Street));
+        assert_eq!(GeocodePrecision::from_mapbox(None, Some("postcode")), Some(GeocodePrecision::Area));
+        assert_eq!(GeocodePrecision::from_mapbox(None, None), None);
+    }
+}
@@ -2,9 +2,14 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use regex::Regex;
+use crate::config::ConfidenceWeights;
+use crate::services::address_confidence::{
+    compute_confidence, haversine_meters, ConfidenceSignals, GeocodePrecision,
+};
+use crate::services::colis_prive_service::AddressValidationSummary;
 use crate::services::geocoding_service::{GeocodingService, GeocodingResponse};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ValidatedAddress {
     pub success: bool,
     pub latitude: Option<f64>,
@@ -13,11 +18,25 @@ pub struct ValidatedAddress {
     pub original_address: String,
     pub validation_method: ValidationMethod,
     pub confidence: ValidationConfidence,
+    /// Score numérico de confianza (0–100), ver `address_confidence`
+    #[serde(default)]
+    pub confidence_score: u8,
     pub warnings: Vec<String>,
     pub error: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Señales externas disponibles para una dirección (transportista, choferes)
+#[derive(Debug, Clone, Default)]
+pub struct AddressContext {
+    /// `score_geocodage` del transportista
+    pub carrier_score_geocodage: Option<f64>,
+    /// Coordenadas del transportista (latitud, longitud)
+    pub carrier_coordinates: Option<(f64, f64)>,
+    /// Verificaciones de choferes de esta dirección
+    pub driver_verification_count: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ValidationMethod {
     Original,
     Cleaned,
@@ -26,15 +45,16 @@ pub enum ValidationMethod {
     ManualRequired,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Nivel de confianza derivado de `ValidatedAddress::confidence_score`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ValidationConfidence {
-    High,    // Dirección original válida
-    Medium,  // Dirección limpiada o completada
-    Low,     // Búsqueda parcial
+    High,    // score >= 80
+    Medium,  // score >= 60
+    Low,     // score >= 35
     None,    // Requiere intervención manual
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AddressValidationResult {
     pub total_addresses: usize,
     pub auto_validated: usize,
@@ -56,6 +76,8 @@ pub struct AddressValidator {
     district_in_middle_regex: Regex,
     incomplete_address_regex: Regex,
     separated_numbers_regex: Regex,
+    postcode_regex: Regex,
+    confidence_weights: ConfidenceWeights,
 }
 
 impl AddressValidator {
@@ -93,6 +115,9 @@ impl AddressValidator {
         // 🆕 REGEX para detectar números separados (ej: "6 7 IMP" -> tomar el último)
         let separated_numbers_regex = Regex::new(r"(\d+)\s+(\d+)\s+").unwrap();
 
+        // 🆕 REGEX para extraer el código postal de la dirección original
+        let postcode_regex = Regex::new(r"\b(\d{5})\b").unwrap();
+
         Self {
             geocoding_service,
             client_names,
@@ -103,14 +128,32 @@ impl AddressValidator {
             district_in_middle_regex,
             incomplete_address_regex,
             separated_numbers_regex,
+            postcode_regex,
+            confidence_weights: ConfidenceWeights::default(),
         }
     }
 
+    /// Usar pesos de confianza personalizados
+    pub fn with_confidence_weights(mut self, weights: ConfidenceWeights) -> Self {
+        self.confidence_weights = weights;
+        self
+    }
+
     /// Validación inteligente de una dirección con múltiples intentos
     pub async fn validate_address(
         &self,
         address: &str,
         username: &str,
+    ) -> Result<ValidatedAddress> {
+        self.validate_address_with_context(address, username, &AddressContext::default()).await
+    }
+
+    /// Validación de una dirección usando señales externas para el score de confianza
+    pub async fn validate_address_with_context(
+        &self,
+        address: &str,
+        username: &str,
+        context: &AddressContext,
     ) -> Result<ValidatedAddress> {
         log::info!("🔍 Validando dirección: '{}' para usuario: '{}'", address, username);
 
@@ -121,17 +164,7 @@ impl AddressValidator {
         if let Ok(result) = self.geocoding_service.geocode_address(&preprocessed_address).await {
             if self.is_valid_result(&result) {
                 log::info!("✅ Dirección original válida: {}", address);
-                return Ok(ValidatedAddress {
-                    success: true,
-                    latitude: result.latitude,
-                    longitude: result.longitude,
-                    formatted_address: result.formatted_address,
-                    original_address: address.to_string(),
-                    validation_method: ValidationMethod::Original,
-                    confidence: ValidationConfidence::High,
-                    warnings,
-                    error: None,
-                });
+                return Ok(self.accepted(address, result, ValidationMethod::Original, warnings, context));
             }
         }
 
@@ -141,17 +174,7 @@ impl AddressValidator {
             if let Ok(result) = self.geocoding_service.geocode_address(&cleaned_address).await {
                 if self.is_valid_result(&result) {
                     log::info!("✅ Dirección limpiada válida: {} -> {}", address, cleaned_address);
-                    return Ok(ValidatedAddress {
-                        success: true,
-                        latitude: result.latitude,
-                        longitude: result.longitude,
-                        formatted_address: result.formatted_address,
-                        original_address: address.to_string(),
-                        validation_method: ValidationMethod::Cleaned,
-                        confidence: ValidationConfidence::Medium,
-                        warnings: vec!["Dirección limpiada automáticamente".to_string()],
-                        error: None,
-                    });
+                    return Ok(self.accepted(address, result, ValidationMethod::Cleaned, vec!["Dirección limpiada automáticamente".to_string()], context));
                 }
             }
         }
@@ -162,17 +185,7 @@ impl AddressValidator {
             if let Ok(result) = self.geocoding_service.geocode_address(&sector_address).await {
                 if self.is_valid_result(&result) {
                     log::info!("✅ Dirección completada con sector válida: {} -> {}", address, sector_address);
-                    return Ok(ValidatedAddress {
-                        success: true,
-                        latitude: result.latitude,
-                        longitude: result.longitude,
-                        formatted_address: result.formatted_address,
-                        original_address: address.to_string(),
-                        validation_method: ValidationMethod::CompletedWithSector,
-                        confidence: ValidationConfidence::Medium,
-                        warnings: vec!["Dirección completada con sector automáticamente".to_string()],
-                        error: None,
-                    });
+                    return Ok(self.accepted(address, result, ValidationMethod::CompletedWithSector, vec!["Dirección completada con sector automáticamente".to_string()], context));
                 }
             }
         }
@@ -183,17 +196,7 @@ impl AddressValidator {
             if let Ok(result) = self.geocoding_service.geocode_address(&partial_address).await {
                 if self.is_valid_result(&result) {
                     log::info!("✅ Dirección encontrada por búsqueda parcial: {} -> {}", address, partial_address);
-                    return Ok(ValidatedAddress {
-                        success: true,
-                        latitude: result.latitude,
-                        longitude: result.longitude,
-                        formatted_address: result.formatted_address,
-                        original_address: address.to_string(),
-                        validation_method: ValidationMethod::PartialSearch,
-                        confidence: ValidationConfidence::Low,
-                        warnings: vec!["Dirección encontrada por búsqueda parcial".to_string()],
-                        error: None,
-                    });
+                    return Ok(self.accepted(address, result, ValidationMethod::PartialSearch, vec!["Dirección encontrada por búsqueda parcial".to_string()], context));
                 }
             }
         }
@@ -208,6 +211,7 @@ impl AddressValidator {
             original_address: address.to_string(),
             validation_method: ValidationMethod::ManualRequired,
             confidence: ValidationConfidence::None,
+            confidence_score: 0,
             warnings: vec![],
             error: Some("No se pudo validar automáticamente. Requiere verificación manual.".to_string()),
         })
@@ -219,6 +223,26 @@ impl AddressValidator {
         addresses: Vec<String>,
         username: &str,
     ) -> Result<AddressValidationResult> {
+        let addresses = addresses
+            .into_iter()
+            .map(|address| (address, AddressContext::default()))
+            .collect();
+        self.validate_addresses_batch_with_progress(addresses, username, |_, _| {}).await
+    }
+
+    /// Validación en lote reportando el progreso tras cada dirección
+    ///
+    /// Cada dirección lleva sus señales externas para el score de confianza.
+    /// `on_progress` recibe `(procesadas, total)`.
+    pub async fn validate_addresses_batch_with_progress<F>(
+        &self,
+        addresses: Vec<(String, AddressContext)>,
+        username: &str,
+        on_progress: F,
+    ) -> Result<AddressValidationResult>
+    where
+        F: Fn(usize, usize) + Send + Sync,
+    {
         let total_addresses = addresses.len();
         log::info!("🔍 Validando {} direcciones en lote para usuario: '{}'", total_addresses, username);
 
@@ -230,8 +254,8 @@ impl AddressValidator {
         let mut requires_manual = 0;
         let mut warnings = Vec::new();
 
-        for address in addresses {
-            match self.validate_address(&address, username).await {
+        for (address, context) in addresses {
+            match self.validate_address_with_context(&address, username, &context).await {
                 Ok(validated) => {
                     match validated.validation_method {
                         ValidationMethod::Original => auto_validated += 1,
@@ -258,11 +282,14 @@ impl AddressValidator {
                         original_address: address,
                         validation_method: ValidationMethod::ManualRequired,
                         confidence: ValidationConfidence::None,
+                        confidence_score: 0,
                         warnings: vec![],
                         error: Some(e.to_string()),
                     });
                 }
             }
+
+            on_progress(validated_addresses.len(), total_addresses);
         }
 
         // Generar resumen de warnings
@@ -288,6 +315,75 @@ impl AddressValidator {
         })
     }
 
+    /// Construir el resultado de un intento exitoso con su score de confianza
+    fn accepted(
+        &self,
+        address: &str,
+        result: GeocodingResponse,
+        method: ValidationMethod,
+        warnings: Vec<String>,
+        context: &AddressContext,
+    ) -> ValidatedAddress {
+        let confidence_score = self.confidence_score(address, &result, &method, context);
+
+        ValidatedAddress {
+            success: true,
+            latitude: result.latitude,
+            longitude: result.longitude,
+            formatted_address: result.formatted_address,
+            original_address: address.to_string(),
+            confidence: ValidationConfidence::from_score(confidence_score),
+            confidence_score,
+            validation_method: method,
+            warnings,
+            error: None,
+        }
+    }
+
+    /// Calcular el score 0–100 combinando las señales disponibles
+    ///
+    /// Si ninguna señal está disponible se usa un score base según el intento
+    /// que tuvo éxito.
+    fn confidence_score(
+        &self,
+        address: &str,
+        result: &GeocodingResponse,
+        method: &ValidationMethod,
+        context: &AddressContext,
+    ) -> u8 {
+        let provider_distance_meters = match (context.carrier_coordinates, result.latitude, result.longitude) {
+            (Some(carrier), Some(lat), Some(lng)) => Some(haversine_meters(carrier, (lat, lng))),
+            _ => None,
+        };
+
+        let postcode_match = match (
+            self.postcode_regex.captures(address).and_then(|c| c.get(1)),
+            result.postcode.as_deref(),
+        ) {
+            (Some(expected), Some(found)) => Some(expected.as_str() == found.trim()),
+            _ => None,
+        };
+
+        let signals = ConfidenceSignals {
+            carrier_score: context.carrier_score_geocodage,
+            geocoder_relevance: result.relevance,
+            provider_distance_meters,
+            precision: GeocodePrecision::from_mapbox(result.accuracy.as_deref(), result.result_type.as_deref()),
+            postcode_match,
+            driver_verification_count: context.driver_verification_count,
+        };
+
+        match compute_confidence(&signals, &self.confidence_weights) {
+            Some(score) => score.score,
+            None => match method {
+                ValidationMethod::Original => 85,
+                ValidationMethod::Cleaned | ValidationMethod::CompletedWithSector => 65,
+                ValidationMethod::PartialSearch => 40,
+                ValidationMethod::ManualRequired => 0,
+            },
+        }
+    }
+
     /// Verificar si un resultado de geocoding es válido
     fn is_valid_result(&self, result: &GeocodingResponse) -> bool {
         result.success && 
@@ -504,6 +600,30 @@ impl AddressValidator {
     }
 }
 
+impl AddressValidationResult {
+    /// Direcciones que requieren intervención manual
+    pub fn manual_required(&self) -> Vec<&ValidatedAddress> {
+        self.validated_addresses
+            .iter()
+            .filter(|a| a.validation_method == ValidationMethod::ManualRequired)
+            .collect()
+    }
+}
+
+impl From<&AddressValidationResult> for AddressValidationSummary {
+    fn from(result: &AddressValidationResult) -> Self {
+        Self {
+            total_packages: result.total_addresses,
+            auto_validated: result.auto_validated,
+            cleaned_auto: result.cleaned_auto,
+            completed_auto: result.completed_auto,
+            partial_found: result.partial_found,
+            requires_manual: result.requires_manual,
+            warnings: result.warnings.clone(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,324 @@
+//! Jobs asíncronos de validación de direcciones en lote
+//!
+//! Validar cientos de direcciones dentro de una request móvil tarda demasiado.
+//! Este módulo registra jobs en memoria, los ejecuta en background con
+//! `AddressValidator::validate_addresses_batch_with_progress` y permite
+//! exportar los resultados en JSON o CSV.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::config::ConfidenceWeights;
+use crate::services::address_validation::{
+    AddressContext, AddressValidationResult, AddressValidator, ValidatedAddress,
+};
+use crate::services::colis_prive_service::AddressValidationSummary;
+use crate::services::geocoding_service::GeocodingService;
+use crate::utils::csv::csv_row;
+
+/// Horas que se conservan los jobs terminados antes de limpiarlos
+const FINISHED_JOB_RETENTION_HOURS: i64 = 24;
+
+/// Estado de un job de validación
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressValidationJobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+}
+
+/// Job de validación registrado en memoria
+#[derive(Debug, Clone)]
+pub struct AddressValidationJob {
+    pub id: Uuid,
+    /// Empresa que creó el job (solo ella puede consultarlo)
+    pub company_id: Uuid,
+    pub username: String,
+    pub tournee_id: Option<Uuid>,
+    pub status: AddressValidationJobStatus,
+    pub total: usize,
+    processed: Arc<AtomicUsize>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub started_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub result: Option<AddressValidationResult>,
+    pub error: Option<String>,
+}
+
+/// Vista serializable del estado y progreso de un job
+#[derive(Debug, Clone, Serialize)]
+pub struct AddressValidationJobSnapshot {
+    pub id: Uuid,
+    pub username: String,
+    pub tournee_id: Option<Uuid>,
+    pub status: AddressValidationJobStatus,
+    pub total: usize,
+    pub processed: usize,
+    pub progress_percentage: f64,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub started_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub summary: Option<AddressValidationSummary>,
+    pub error: Option<String>,
+}
+
+impl AddressValidationJob {
+    fn new(company_id: Uuid, username: String, tournee_id: Option<Uuid>, total: usize) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            company_id,
+            username,
+            tournee_id,
+            status: AddressValidationJobStatus::Queued,
+            total,
+            processed: Arc::new(AtomicUsize::new(0)),
+            created_at: chrono::Utc::now(),
+            started_at: None,
+            finished_at: None,
+            result: None,
+            error: None,
+        }
+    }
+
+    /// Número de direcciones ya procesadas
+    pub fn processed(&self) -> usize {
+        self.processed.load(Ordering::Relaxed)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self.status,
+            AddressValidationJobStatus::Completed | AddressValidationJobStatus::Failed
+        )
+    }
+
+    pub fn snapshot(&self) -> AddressValidationJobSnapshot {
+        let processed = self.processed();
+        let progress_percentage = if self.total > 0 {
+            (processed as f64 / self.total as f64 * 100.0).min(100.0)
+        } else {
+            100.0
+        };
+
+        AddressValidationJobSnapshot {
+            id: self.id,
+            username: self.username.clone(),
+            tournee_id: self.tournee_id,
+            status: self.status,
+            total: self.total,
+            processed,
+            progress_percentage,
+            created_at: self.created_at,
+            started_at: self.started_at,
+            finished_at: self.finished_at,
+            summary: self.result.as_ref().map(AddressValidationSummary::from),
+            error: self.error.clone(),
+        }
+    }
+}
+
+/// Registro compartido de jobs de validación
+#[derive(Clone, Default)]
+pub struct AddressValidationJobStore {
+    jobs: Arc<RwLock<HashMap<Uuid, AddressValidationJob>>>,
+}
+
+impl AddressValidationJobStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registrar un nuevo job en estado `Queued`
+    pub async fn create(
+        &self,
+        company_id: Uuid,
+        username: String,
+        tournee_id: Option<Uuid>,
+        total: usize,
+    ) -> AddressValidationJob {
+        let job = AddressValidationJob::new(company_id, username, tournee_id, total);
+        self.jobs.write().await.insert(job.id, job.clone());
+        job
+    }
+
+    /// Obtener una copia del job si es de la empresa
+    pub async fn get(&self, id: Uuid, company_id: Uuid) -> Option<AddressValidationJob> {
+        self.jobs
+            .read()
+            .await
+            .get(&id)
+            .filter(|job| job.company_id == company_id)
+            .cloned()
+    }
+
+    async fn mark_running(&self, id: Uuid) {
+        if let Some(job) = self.jobs.write().await.get_mut(&id) {
+            job.status = AddressValidationJobStatus::Running;
+            job.started_at = Some(chrono::Utc::now());
+        }
+    }
+
+    async fn complete(&self, id: Uuid, result: AddressValidationResult) {
+        if let Some(job) = self.jobs.write().await.get_mut(&id) {
+            job.processed.store(result.total_addresses, Ordering::Relaxed);
+            job.status = AddressValidationJobStatus::Completed;
+            job.finished_at = Some(chrono::Utc::now());
+            job.result = Some(result);
+        }
+    }
+
+    async fn fail(&self, id: Uuid, error: String) {
+        if let Some(job) = self.jobs.write().await.get_mut(&id) {
+            job.status = AddressValidationJobStatus::Failed;
+            job.finished_at = Some(chrono::Utc::now());
+            job.error = Some(error);
+        }
+    }
+
+    /// Limpiar jobs terminados hace más de `FINISHED_JOB_RETENTION_HOURS`
+    pub async fn cleanup_finished(&self) {
+        let cutoff = chrono::Utc::now() - chrono::Duration::hours(FINISHED_JOB_RETENTION_HOURS);
+        let mut jobs = self.jobs.write().await;
+        jobs.retain(|_, job| !job.is_finished() || job.finished_at.is_none_or(|f| f > cutoff));
+    }
+
+    /// Registrar un job y lanzar su worker en background
+    pub async fn spawn(
+        &self,
+        mapbox_token: String,
+        weights: ConfidenceWeights,
+        addresses: Vec<(String, AddressContext)>,
+        company_id: Uuid,
+        username: String,
+        tournee_id: Option<Uuid>,
+    ) -> AddressValidationJob {
+        self.cleanup_finished().await;
+
+        let job = self.create(company_id, username.clone(), tournee_id, addresses.len()).await;
+        let store = self.clone();
+        let job_id = job.id;
+        let processed = job.processed.clone();
+
+        tokio::spawn(async move {
+            store.mark_running(job_id).await;
+            log::info!("🚀 Job de validación {} iniciado: {} direcciones", job_id, addresses.len());
+
+            let validator = AddressValidator::new(GeocodingService::new(mapbox_token))
+                .with_confidence_weights(weights);
+            let result = validator
+                .validate_addresses_batch_with_progress(addresses, &username, |done, _total| {
+                    processed.store(done, Ordering::Relaxed);
+                })
+                .await;
+
+            match result {
+                Ok(result) => {
+                    log::info!("✅ Job de validación {} completado: {} manuales", job_id, result.requires_manual);
+                    store.complete(job_id, result).await;
+                }
+                Err(e) => {
+                    log::error!("❌ Job de validación {} falló: {}", job_id, e);
+                    store.fail(job_id, e.to_string()).await;
+                }
+            }
+        });
+
+        job
+    }
+}
+
+/// Exportar direcciones validadas como CSV (separador `;` para Excel FR)
+pub fn validated_addresses_to_csv<'a, I>(addresses: I) -> String
+where
+    I: IntoIterator<Item = &'a ValidatedAddress>,
+{
+    let mut csv = String::from(
+        "original_address;success;validation_method;confidence;confidence_score;latitude;longitude;formatted_address;warnings;error\n",
+    );
+
+    for address in addresses {
+        let row = [
+            address.original_address.clone(),
+            address.success.to_string(),
+            format!("{:?}", address.validation_method),
+            format!("{:?}", address.confidence),
+            address.confidence_score.to_string(),
+            address.latitude.map(|v| v.to_string()).unwrap_or_default(),
+            address.longitude.map(|v| v.to_string()).unwrap_or_default(),
+            address.formatted_address.clone().unwrap_or_default(),
+            address.warnings.join(" | "),
+            address.error.clone().unwrap_or_default(),
+        ];
+
+        csv.push_str(&csv_row(&row));
+    }
+
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::address_validation::{ValidationConfidence, ValidationMethod};
+
+    fn validated(address: &str, method: ValidationMethod) -> ValidatedAddress {
+        let success = method != ValidationMethod::ManualRequired;
+        ValidatedAddress {
+            success,
+            latitude: if success { Some(48.89) } else { None },
+            longitude: if success { Some(2.34) } else { None },
+            formatted_address: None,
+            original_address: address.to_string(),
+            validation_method: method,
+            confidence: if success { ValidationConfidence::High } else { ValidationConfidence::None },
+            confidence_score: if success { 90 } else { 0 },
+            warnings: vec![],
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_csv_export_only_manual() {
+        let result = AddressValidationResult {
+            total_addresses: 2,
+            auto_validated: 1,
+            cleaned_auto: 0,
+            completed_auto: 0,
+            partial_found: 0,
+            requires_manual: 1,
+            validated_addresses: vec![
+                validated("12 RUE MARCADET, 75018 PARIS", ValidationMethod::Original),
+                validated("75, 75018 PARIS", ValidationMethod::ManualRequired),
+            ],
+            warnings: vec![],
+        };
+
+        let csv = validated_addresses_to_csv(result.manual_required());
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("75, 75018 PARIS;false;ManualRequired;None;0;"));
+    }
+
+    #[tokio::test]
+    async fn test_job_snapshot_progress() {
+        let store = AddressValidationJobStore::new();
+        let company_id = Uuid::new_v4();
+        let job = store.create(company_id, "TEST_USER".to_string(), None, 4).await;
+        job.processed.store(1, Ordering::Relaxed);
+
+        // Otra empresa no ve el job
+        assert!(store.get(job.id, Uuid::new_v4()).await.is_none());
+
+        let snapshot = store.get(job.id, company_id).await.unwrap().snapshot();
+        assert_eq!(snapshot.status, AddressValidationJobStatus::Queued);
+        assert_eq!(snapshot.processed, 1);
+        assert_eq!(snapshot.progress_percentage, 25.0);
+        assert!(snapshot.summary.is_none());
+    }
+}
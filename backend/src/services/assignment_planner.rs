@@ -0,0 +1,441 @@
+//! Planificador diario de asignación vehículo-chofer
+//!
+//! Solo entran los vehículos activos con seguro y contrôle technique en vigor,
+//! sin cita de taller ese día y que no lleve ya una tournée que no se
+//! replanifica (en curso, en pausa o bloqueada). Las tournées se reparten de
+//! más a menos paquetes:
+//!
+//! 1. Primero el vehículo preferido del chofer, si puede llevarlo.
+//! 2. Después, el de menos kilómetros entre los que puede llevar, para que
+//!    las tournées más cargadas gasten los vehículos menos usados y el
+//!    kilometraje de la flota se iguale con el tiempo.
+//!
+//! Puede llevarlo si su permiso incluye la categoría que pide el vehículo y
+//! los paquetes caben en la caja.
+
+use chrono::NaiveDate;
+use sqlx::PgConnection;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+use crate::{
+    middleware::auth::AuthenticatedUser,
+    models::assignment::{
+        AcceptAssignmentsRequest, AssignmentProposal, AssignmentReason, ExcludedVehicle, ExclusionReason,
+        PlannerDriver, PlannerVehicle, ProposedAssignment, VehicleAssignment, VEHICLE_ASSIGNMENT_COLUMNS,
+    },
+    utils::errors::{AppError, AppResult},
+};
+
+/// Categoría de permiso normalizada (`" c1 "` → `"C1"`)
+pub fn normalize_license(value: &str) -> String {
+    value.trim().to_uppercase()
+}
+
+/// Motivo por el que un vehículo no se puede planificar ese día
+pub fn exclusion(vehicle: &PlannerVehicle) -> Option<ExclusionReason> {
+    if vehicle.vehicle_status != "active" {
+        Some(ExclusionReason::Inactive)
+    } else if vehicle.documents_expired {
+        Some(ExclusionReason::Documents)
+    } else if vehicle.in_maintenance {
+        Some(ExclusionReason::Maintenance)
+    } else if vehicle.in_use {
+        Some(ExclusionReason::InUse)
+    } else {
+        None
+    }
+}
+
+/// El chofer puede llevar el vehículo con la carga de su tournée
+pub fn eligible(driver: &PlannerDriver, vehicle: &PlannerVehicle) -> bool {
+    let licensed = driver
+        .license_categories
+        .iter()
+        .any(|category| normalize_license(category) == normalize_license(&vehicle.required_license));
+    let fits = vehicle
+        .parcel_capacity
+        .is_none_or(|capacity| driver.package_count <= capacity as i64);
+    licensed && fits
+}
+
+/// Repartir los vehículos disponibles entre las tournées del día
+pub fn propose(drivers: &[PlannerDriver], vehicles: &[PlannerVehicle]) -> (Vec<ProposedAssignment>, Vec<ExcludedVehicle>) {
+    let mut excluded = Vec::new();
+    let mut available: Vec<&PlannerVehicle> = Vec::new();
+    for vehicle in vehicles {
+        match exclusion(vehicle) {
+            Some(reason) => excluded.push(ExcludedVehicle {
+                vehicle_id: vehicle.id,
+                license_plate: vehicle.license_plate.clone(),
+                reason,
+            }),
+            None => available.push(vehicle),
+        }
+    }
+    available.sort_by(|a, b| {
+        a.current_mileage
+            .cmp(&b.current_mileage)
+            .then_with(|| a.license_plate.cmp(&b.license_plate))
+    });
+
+    let mut ordered: Vec<&PlannerDriver> = drivers.iter().collect();
+    ordered.sort_by(|a, b| {
+        b.package_count
+            .cmp(&a.package_count)
+            .then_with(|| a.driver_name.cmp(&b.driver_name))
+    });
+
+    let mut taken: HashSet<Uuid> = HashSet::new();
+    let mut chosen: HashMap<Uuid, (&PlannerVehicle, AssignmentReason)> = HashMap::new();
+
+    for driver in &ordered {
+        let preferred = driver.preferred_vehicle_id.and_then(|id| {
+            available
+                .iter()
+                .find(|vehicle| vehicle.id == id && !taken.contains(&id) && eligible(driver, vehicle))
+        });
+        if let Some(vehicle) = preferred {
+            taken.insert(vehicle.id);
+            chosen.insert(driver.tournee_id, (vehicle, AssignmentReason::Preferred));
+        }
+    }
+
+    for driver in &ordered {
+        if chosen.contains_key(&driver.tournee_id) {
+            continue;
+        }
+        let lowest = available
+            .iter()
+            .find(|vehicle| !taken.contains(&vehicle.id) && eligible(driver, vehicle));
+        if let Some(vehicle) = lowest {
+            taken.insert(vehicle.id);
+            chosen.insert(driver.tournee_id, (vehicle, AssignmentReason::LowestMileage));
+        }
+    }
+
+    let assignments = ordered
+        .into_iter()
+        .map(|driver| {
+            let choice = chosen.get(&driver.tournee_id);
+            ProposedAssignment {
+                tournee_id: driver.tournee_id,
+                driver_id: driver.driver_id,
+                driver_name: driver.driver_name.clone(),
+                package_count: driver.package_count,
+                current_vehicle_id: driver.current_vehicle_id,
+                vehicle_id: choice.map(|(vehicle, _)| vehicle.id),
+                license_plate: choice.map(|(vehicle, _)| vehicle.license_plate.clone()),
+                current_mileage: choice.map(|(vehicle, _)| vehicle.current_mileage),
+                reason: choice.map_or(AssignmentReason::NoEligibleVehicle, |(_, reason)| *reason),
+            }
+        })
+        .collect();
+
+    (assignments, excluded)
+}
+
+/// Tournées pendientes y sin bloquear del día
+async fn load_drivers(conn: &mut PgConnection, company_id: Uuid, date: NaiveDate) -> AppResult<Vec<PlannerDriver>> {
+    Ok(sqlx::query_as::<_, PlannerDriver>(
+        r#"
+        SELECT t.id AS tournee_id, t.driver_id, u.full_name AS driver_name, u.license_categories,
+               u.preferred_vehicle_id, t.vehicle_id AS current_vehicle_id,
+               (SELECT COUNT(*) FROM packages p
+                WHERE p.tournee_id = t.id AND p.deleted_at IS NULL) AS package_count
+        FROM tournees t
+        JOIN users u ON u.id = t.driver_id AND u.deleted_at IS NULL AND u.user_status = 'active'
+        WHERE t.company_id = $1 AND t.tournee_date = $2 AND t.deleted_at IS NULL
+        AND t.tournee_status = 'pending' AND t.locked_at IS NULL
+        "#,
+    )
+    .bind(company_id)
+    .bind(date)
+    .fetch_all(&mut *conn)
+    .await?)
+}
+
+/// Flota de la empresa con su disponibilidad el día (mismas reglas que
+/// `vehicle_documents::ensure_roadworthy` y `maintenance::ensure_available`)
+async fn load_vehicles(conn: &mut PgConnection, company_id: Uuid, date: NaiveDate) -> AppResult<Vec<PlannerVehicle>> {
+    Ok(sqlx::query_as::<_, PlannerVehicle>(
+        r#"
+        SELECT v.id, v.license_plate, v.required_license, v.parcel_capacity, v.current_mileage,
+               v.vehicle_status::text AS vehicle_status,
+               EXISTS (
+                   SELECT 1 FROM vehicle_documents d
+                   WHERE d.vehicle_id = v.id AND d.deleted_at IS NULL AND d.expiry_date <= $2
+                   AND d.document_type IN ('insurance', 'technical_control')
+               ) AS documents_expired,
+               EXISTS (
+                   SELECT 1 FROM vehicle_maintenance m
+                   WHERE m.vehicle_id = v.id AND m.maintenance_status IN ('scheduled', 'in_progress')
+                   AND m.scheduled_start < (($2::date + 1)::timestamp AT TIME ZONE 'Europe/Paris')
+                   AND m.scheduled_end > ($2::date::timestamp AT TIME ZONE 'Europe/Paris')
+               ) AS in_maintenance,
+               EXISTS (
+                   SELECT 1 FROM tournees t
+                   WHERE t.vehicle_id = v.id AND t.tournee_date = $2 AND t.deleted_at IS NULL
+                   AND (t.tournee_status IN ('in_progress', 'paused')
+                        OR (t.tournee_status = 'pending' AND t.locked_at IS NOT NULL))
+               ) AS in_use
+        FROM vehicles v
+        WHERE v.company_id = $1 AND v.deleted_at IS NULL AND v.vehicle_status <> 'retired'
+        ORDER BY v.license_plate
+        "#,
+    )
+    .bind(company_id)
+    .bind(date)
+    .fetch_all(&mut *conn)
+    .await?)
+}
+
+/// Propuesta de asignación para un día
+pub async fn proposal(conn: &mut PgConnection, company_id: Uuid, date: NaiveDate) -> AppResult<AssignmentProposal> {
+    let drivers = load_drivers(conn, company_id, date).await?;
+    let vehicles = load_vehicles(conn, company_id, date).await?;
+    let (assignments, excluded) = propose(&drivers, &vehicles);
+
+    Ok(AssignmentProposal {
+        tournee_date: date,
+        assignments,
+        excluded,
+    })
+}
+
+/// Aceptar la propuesta del día, con los cambios del despachador
+///
+/// Cada vehículo elegido se valida con las mismas reglas que la propuesta;
+/// si no es el propuesto la asignación queda marcada como `overridden`.
+pub async fn accept(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    request: &AcceptAssignmentsRequest,
+) -> AppResult<Vec<VehicleAssignment>> {
+    if request.assignments.is_empty() {
+        return Err(AppError::BadRequest("No hay asignaciones que aceptar".to_string()));
+    }
+
+    // Serializar la planificación del día de la empresa
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1::text || $2::text))")
+        .bind(user.company_id)
+        .bind(request.tournee_date)
+        .execute(&mut *conn)
+        .await?;
+
+    let date = request.tournee_date;
+    let drivers = load_drivers(conn, user.company_id, date).await?;
+    let vehicles = load_vehicles(conn, user.company_id, date).await?;
+    let (proposed, _) = propose(&drivers, &vehicles);
+
+    let proposed_by_tournee: HashMap<Uuid, Option<Uuid>> = proposed
+        .iter()
+        .map(|assignment| (assignment.tournee_id, assignment.vehicle_id))
+        .collect();
+    let drivers_by_tournee: HashMap<Uuid, &PlannerDriver> =
+        drivers.iter().map(|driver| (driver.tournee_id, driver)).collect();
+    let vehicles_by_id: HashMap<Uuid, &PlannerVehicle> = vehicles.iter().map(|vehicle| (vehicle.id, vehicle)).collect();
+    let accepted_tournees: HashSet<Uuid> = request.assignments.iter().map(|a| a.tournee_id).collect();
+
+    let mut chosen_vehicles: HashSet<Uuid> = HashSet::new();
+    for item in &request.assignments {
+        let driver = drivers_by_tournee.get(&item.tournee_id).ok_or_else(|| {
+            AppError::BadRequest(format!(
+                "La tournée {} no está pendiente el {} o ya está bloqueada",
+                item.tournee_id, date
+            ))
+        })?;
+        let vehicle = vehicles_by_id
+            .get(&item.vehicle_id)
+            .ok_or_else(|| AppError::NotFound("Vehículo no encontrado".to_string()))?;
+
+        if let Some(reason) = exclusion(vehicle) {
+            return Err(AppError::BadRequest(format!(
+                "El vehículo {} no está disponible el {} ({})",
+                vehicle.license_plate,
+                date,
+                exclusion_label(reason)
+            )));
+        }
+        if !eligible(driver, vehicle) {
+            return Err(AppError::BadRequest(format!(
+                "{} no puede llevar el vehículo {}: permiso {} o capacidad insuficiente para {} paquetes",
+                driver.driver_name, vehicle.license_plate, vehicle.required_license, driver.package_count
+            )));
+        }
+        if !chosen_vehicles.insert(vehicle.id) {
+            return Err(AppError::Conflict(format!(
+                "El vehículo {} está elegido para varias tournées",
+                vehicle.license_plate
+            )));
+        }
+        // Tournée del día que se queda con su vehículo actual
+        let held = drivers.iter().any(|other| {
+            other.current_vehicle_id == vehicle.id && !accepted_tournees.contains(&other.tournee_id)
+        });
+        if held {
+            return Err(AppError::Conflict(format!(
+                "El vehículo {} sigue asignado a otra tournée del {}",
+                vehicle.license_plate, date
+            )));
+        }
+
+        let proposed_vehicle = proposed_by_tournee.get(&item.tournee_id).copied().flatten();
+        if proposed_vehicle != Some(vehicle.id) && item.override_reason.is_none() {
+            return Err(AppError::BadRequest(format!(
+                "Indica el motivo del cambio de vehículo para {}",
+                driver.driver_name
+            )));
+        }
+    }
+
+    let mut accepted = Vec::with_capacity(request.assignments.len());
+    for item in &request.assignments {
+        let driver = drivers_by_tournee[&item.tournee_id];
+        let proposed_vehicle = proposed_by_tournee.get(&item.tournee_id).copied().flatten();
+        let overridden = proposed_vehicle != Some(item.vehicle_id);
+
+        sqlx::query("UPDATE tournees SET vehicle_id = $2, updated_at = NOW() WHERE id = $1")
+            .bind(item.tournee_id)
+            .bind(item.vehicle_id)
+            .execute(&mut *conn)
+            .await?;
+
+        let assignment = sqlx::query_as::<_, VehicleAssignment>(&format!(
+            r#"
+            INSERT INTO vehicle_assignments (
+                company_id, tournee_id, driver_id, assignment_date, vehicle_id,
+                proposed_vehicle_id, overridden, override_reason, accepted_by
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT (tournee_id) DO UPDATE SET
+                driver_id = EXCLUDED.driver_id,
+                assignment_date = EXCLUDED.assignment_date,
+                vehicle_id = EXCLUDED.vehicle_id,
+                proposed_vehicle_id = EXCLUDED.proposed_vehicle_id,
+                overridden = EXCLUDED.overridden,
+                override_reason = EXCLUDED.override_reason,
+                accepted_by = EXCLUDED.accepted_by,
+                accepted_at = NOW(),
+                updated_at = NOW()
+            RETURNING {}
+            "#,
+            VEHICLE_ASSIGNMENT_COLUMNS
+        ))
+        .bind(user.company_id)
+        .bind(item.tournee_id)
+        .bind(driver.driver_id)
+        .bind(date)
+        .bind(item.vehicle_id)
+        .bind(proposed_vehicle)
+        .bind(overridden)
+        .bind(if overridden { item.override_reason.as_deref() } else { None })
+        .bind(user.user_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        accepted.push(assignment);
+    }
+
+    Ok(accepted)
+}
+
+fn exclusion_label(reason: ExclusionReason) -> &'static str {
+    match reason {
+        ExclusionReason::Inactive => "no está activo",
+        ExclusionReason::Documents => "seguro o contrôle technique caducado",
+        ExclusionReason::Maintenance => "cita de taller",
+        ExclusionReason::InUse => "ya lo lleva otra tournée",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+
+    fn driver(name: &str, packages: i64, categories: &[&str], preferred: Option<Uuid>) -> PlannerDriver {
+        PlannerDriver {
+            tournee_id: Uuid::new_v4(),
+            driver_id: Uuid::new_v4(),
+            driver_name: name.to_string(),
+            license_categories: categories.iter().map(|c| c.to_string()).collect(),
+            preferred_vehicle_id: preferred,
+            current_vehicle_id: Uuid::nil(),
+            package_count: packages,
+        }
+    }
+
+    fn vehicle(plate: &str, km: i64, license: &str, capacity: Option<i32>) -> PlannerVehicle {
+        PlannerVehicle {
+            id: Uuid::new_v4(),
+            license_plate: plate.to_string(),
+            required_license: license.to_string(),
+            parcel_capacity: capacity,
+            current_mileage: Decimal::new(km, 0),
+            vehicle_status: "active".to_string(),
+            documents_expired: false,
+            in_maintenance: false,
+            in_use: false,
+        }
+    }
+
+    fn plate_for<'a>(assignments: &'a [ProposedAssignment], name: &str) -> Option<&'a str> {
+        assignments
+            .iter()
+            .find(|a| a.driver_name == name)
+            .and_then(|a| a.license_plate.as_deref())
+    }
+
+    #[test]
+    fn test_busiest_tournee_gets_lowest_mileage() {
+        let vehicles = vec![
+            vehicle("AA-100-AA", 90_000, "B", None),
+            vehicle("BB-200-BB", 20_000, "B", None),
+        ];
+        let drivers = vec![driver("Ana", 80, &["B"], None), driver("Luc", 150, &["B"], None)];
+
+        let (assignments, excluded) = propose(&drivers, &vehicles);
+        assert!(excluded.is_empty());
+        assert_eq!(plate_for(&assignments, "Luc"), Some("BB-200-BB"));
+        assert_eq!(plate_for(&assignments, "Ana"), Some("AA-100-AA"));
+        assert!(assignments.iter().all(|a| a.reason == AssignmentReason::LowestMileage));
+    }
+
+    #[test]
+    fn test_preference_licence_and_capacity() {
+        let truck = vehicle("CC-300-CC", 10_000, "C1", None);
+        let small = vehicle("DD-400-DD", 5_000, "B", Some(50));
+        let van = vehicle("EE-500-EE", 60_000, "B", None);
+        let drivers = vec![
+            // Prefiere el camión pero no tiene el permiso
+            driver("Ana", 40, &["B"], Some(truck.id)),
+            driver("Luc", 120, &["b", "c1"], Some(van.id)),
+        ];
+
+        let (assignments, _) = propose(&drivers, &[truck, small, van]);
+        let luc = assignments.iter().find(|a| a.driver_name == "Luc").unwrap();
+        assert_eq!(luc.license_plate.as_deref(), Some("EE-500-EE"));
+        assert_eq!(luc.reason, AssignmentReason::Preferred);
+        assert_eq!(plate_for(&assignments, "Ana"), Some("DD-400-DD"));
+    }
+
+    #[test]
+    fn test_unavailable_vehicles_are_excluded() {
+        let mut in_workshop = vehicle("AA-100-AA", 1_000, "B", None);
+        in_workshop.in_maintenance = true;
+        let mut uninsured = vehicle("BB-200-BB", 2_000, "B", None);
+        uninsured.documents_expired = true;
+        let mut stopped = vehicle("CC-300-CC", 3_000, "B", None);
+        stopped.vehicle_status = "out_of_service".to_string();
+
+        let drivers = vec![driver("Ana", 10, &["B"], None)];
+        let (assignments, excluded) = propose(&drivers, &[in_workshop, uninsured, stopped]);
+        assert_eq!(assignments[0].reason, AssignmentReason::NoEligibleVehicle);
+        assert_eq!(assignments[0].vehicle_id, None);
+        let reasons: Vec<ExclusionReason> = excluded.iter().map(|e| e.reason).collect();
+        assert_eq!(
+            reasons,
+            vec![ExclusionReason::Maintenance, ExclusionReason::Documents, ExclusionReason::Inactive]
+        );
+    }
+}
@@ -55,7 +55,12 @@ pub struct PackageData {
     pub formatted_address: Option<String>,
     pub validation_method: Option<String>,
     pub validation_confidence: Option<String>,
+    /// Score numérico de confianza (0–100)
+    pub validation_confidence_score: Option<u8>,
     pub validation_warnings: Option<Vec<String>>,
+    /// Digicodes, acceso, buzón y notas (transportista + choferes)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field_knowledge: Option<crate::services::field_knowledge::PackageFieldKnowledge>,
 }
 
 /// Datos de error
@@ -0,0 +1,668 @@
+//! Compte-rendu de fin de día por tournée
+//!
+//! Cuadra la tournée a partir de los estados de sus paquetes, del historial
+//! de transiciones (los fallidos y los reprogramados al cerrar que ya se han
+//! movido a otra tournée siguen contando en la original) y de las lecturas de
+//! código de barras, más los bultos de las recogidas. Las cifras se comparan
+//! con el recuento del transportista y con nuestras propias lecturas; las
+//! diferencias quedan en el compte-rendu.
+//!
+//! Al firmarlo se bloquea la tournée (`tournees.locked_at`): ni la tournée ni
+//! sus paquetes admiten ya cambios.
+
+use chrono::Utc;
+use serde_json::json;
+use sqlx::{FromRow, PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    middleware::auth::AuthenticatedUser,
+    models::compte_rendu::{
+        CarrierCounts, CompteRendu, CompteRenduCounts, CompteRenduLine, CompteRenduReport, CompteRenduStatus,
+        Mismatch, MismatchSource, PackageOutcome, SignCompteRenduRequest, COMPTE_RENDU_COLUMNS,
+    },
+    models::package::DeliveryStatus,
+    models::tournee::{Tournee, TourneeStatus},
+    services::{lifecycle, pickups},
+    utils::csv::csv_row,
+    utils::errors::{AppError, AppResult},
+    utils::french_holidays::paris_local,
+    utils::pdf,
+};
+
+/// Situación de un paquete respecto a la tournée
+#[derive(Debug, Clone, FromRow)]
+pub struct PackageFacts {
+    pub package_id: Uuid,
+    pub tracking_number: String,
+    pub delivery_status: DeliveryStatus,
+    pub next_action: Option<String>,
+    pub failure_reason: Option<String>,
+    /// Sigue asignado a la tournée
+    pub in_tournee: bool,
+    /// Tiene un fallo registrado en esta tournée
+    pub failed_here: bool,
+    /// Se reprogramó al cerrar esta tournée
+    pub carried_over: bool,
+    pub loaded: bool,
+    pub delivery_scanned: bool,
+}
+
+/// Resultado del paquete en la tournée
+pub fn classify(facts: &PackageFacts) -> PackageOutcome {
+    if !facts.in_tournee {
+        // Ya movido a otra tournée: cuenta lo que pasó en esta
+        return if facts.carried_over && !facts.failed_here {
+            PackageOutcome::CarriedOver
+        } else {
+            PackageOutcome::Failed
+        };
+    }
+
+    match facts.delivery_status {
+        DeliveryStatus::Delivered => PackageOutcome::Delivered,
+        DeliveryStatus::Returned => PackageOutcome::Returned,
+        DeliveryStatus::Cancelled => PackageOutcome::Cancelled,
+        DeliveryStatus::InTransit if facts.next_action.as_deref() == Some("redirect_to_relay") => {
+            PackageOutcome::Relay
+        }
+        DeliveryStatus::Failed if facts.carried_over && !facts.failed_here => PackageOutcome::CarriedOver,
+        DeliveryStatus::Failed => PackageOutcome::Failed,
+        DeliveryStatus::Pending | DeliveryStatus::InTransit | DeliveryStatus::OutForDelivery => {
+            PackageOutcome::Unresolved
+        }
+    }
+}
+
+/// Recuento de la tournée a partir de sus paquetes
+pub fn count(facts: &[PackageFacts], flagged_scans: i32, pickup_parcels: (i32, i32)) -> CompteRenduCounts {
+    let mut counts = CompteRenduCounts {
+        expected_packages: facts.len() as i32,
+        flagged_scans,
+        pickup_expected_parcels: pickup_parcels.0,
+        collected_parcels: pickup_parcels.1,
+        ..Default::default()
+    };
+
+    for package in facts {
+        match classify(package) {
+            PackageOutcome::Delivered => counts.delivered += 1,
+            PackageOutcome::Failed => counts.failed += 1,
+            PackageOutcome::Relay => counts.relay += 1,
+            PackageOutcome::Returned => counts.returned += 1,
+            PackageOutcome::CarriedOver => counts.carried_over += 1,
+            PackageOutcome::Unresolved => counts.unresolved += 1,
+            PackageOutcome::Cancelled => counts.cancelled += 1,
+        }
+        counts.loaded_scans += package.loaded as i32;
+        counts.delivered_scans += package.delivery_scanned as i32;
+    }
+
+    counts
+}
+
+/// Diferencias frente al recuento del transportista y a nuestras lecturas.
+///
+/// Las lecturas solo se comparan si la tournée tiene alguna (no todas las
+/// empresas escanean). Los paquetes restantes son los sin resolver y los
+/// reprogramados al cerrar.
+pub fn find_mismatches(counts: &CompteRenduCounts, carrier: Option<&CarrierCounts>) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+    let mut compare = |field: &str, source: MismatchSource, ours: i32, reference: Option<i32>| {
+        if let Some(reference) = reference {
+            if ours != reference {
+                mismatches.push(Mismatch {
+                    field: field.to_string(),
+                    source,
+                    ours,
+                    reference,
+                    difference: ours - reference,
+                });
+            }
+        }
+    };
+
+    if let Some(carrier) = carrier {
+        compare("nb_colis", MismatchSource::Carrier, counts.expected_packages, carrier.nb_colis);
+        compare("nb_colis_distribue", MismatchSource::Carrier, counts.delivered, carrier.nb_colis_distribue);
+        compare("nb_colis_traite", MismatchSource::Carrier, counts.processed(), carrier.nb_colis_traite);
+        compare("nb_colis_relais", MismatchSource::Carrier, counts.relay, carrier.nb_colis_relais);
+        compare(
+            "nb_colis_restant_adistribue",
+            MismatchSource::Carrier,
+            counts.unresolved + counts.carried_over,
+            carrier.nb_colis_restant_adistribue,
+        );
+        compare(
+            "nb_colis_acollecter",
+            MismatchSource::Carrier,
+            counts.pickup_expected_parcels,
+            carrier.nb_colis_acollecter,
+        );
+        compare("nb_colis_collecte", MismatchSource::Carrier, counts.collected_parcels, carrier.nb_colis_collecte);
+    }
+
+    if counts.loaded_scans + counts.delivered_scans + counts.flagged_scans > 0 {
+        let to_load = counts.expected_packages - counts.cancelled;
+        compare("loaded_scans", MismatchSource::Scans, counts.loaded_scans, Some(to_load));
+        compare("delivered_scans", MismatchSource::Scans, counts.delivered_scans, Some(counts.delivered));
+    }
+
+    mismatches
+}
+
+/// Paquetes de la tournée: los asignados y los que pasaron por ella
+async fn package_facts(conn: &mut PgConnection, tournee: &Tournee) -> AppResult<Vec<PackageFacts>> {
+    let facts = sqlx::query_as::<_, PackageFacts>(
+        r#"
+        WITH passed AS (
+            SELECT entity_id,
+                   bool_or(metadata->>'tournee_id' = $1::text) AS failed_here,
+                   bool_or(metadata->>'closed_tournee_id' = $1::text) AS carried_over
+            FROM status_history
+            WHERE company_id = $2 AND entity_type = 'package'
+            AND (metadata->>'tournee_id' = $1::text OR metadata->>'closed_tournee_id' = $1::text)
+            GROUP BY entity_id
+        )
+        SELECT
+            p.id AS package_id,
+            p.tracking_number,
+            p.delivery_status,
+            p.next_action,
+            p.failure_reason::text AS failure_reason,
+            p.tournee_id = $1 AS in_tournee,
+            COALESCE(h.failed_here, FALSE) AS failed_here,
+            COALESCE(h.carried_over, FALSE) AS carried_over,
+            EXISTS (
+                SELECT 1 FROM scan_events s
+                WHERE s.package_id = p.id AND s.tournee_id = $1 AND s.scan_type = 'load'
+            ) AS loaded,
+            EXISTS (
+                SELECT 1 FROM scan_events s
+                WHERE s.package_id = p.id AND s.tournee_id = $1 AND s.scan_type = 'deliver'
+            ) AS delivery_scanned
+        FROM packages p
+        LEFT JOIN passed h ON h.entity_id = p.id
+        WHERE p.company_id = $2 AND p.deleted_at IS NULL
+        AND (p.tournee_id = $1 OR h.entity_id IS NOT NULL)
+        ORDER BY p.tracking_number
+        "#,
+    )
+    .bind(tournee.id)
+    .bind(tournee.company_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(facts)
+}
+
+async fn flagged_scans(conn: &mut PgConnection, tournee_id: Uuid) -> AppResult<i32> {
+    let flagged = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM scan_events WHERE tournee_id = $1 AND validation_status <> 'valid'",
+    )
+    .bind(tournee_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(flagged as i32)
+}
+
+fn lines(facts: &[PackageFacts]) -> Vec<CompteRenduLine> {
+    facts
+        .iter()
+        .map(|package| CompteRenduLine {
+            package_id: package.package_id,
+            tracking_number: package.tracking_number.clone(),
+            outcome: classify(package),
+            delivery_status: package.delivery_status.as_str().to_string(),
+            failure_reason: package.failure_reason.clone(),
+            loaded: package.loaded,
+            delivery_scanned: package.delivery_scanned,
+        })
+        .collect()
+}
+
+async fn existing(conn: &mut PgConnection, tournee_id: Uuid) -> AppResult<Option<CompteRendu>> {
+    let compte_rendu = sqlx::query_as::<_, CompteRendu>(&format!(
+        "SELECT {} FROM compte_rendus WHERE tournee_id = $1 FOR UPDATE",
+        COMPTE_RENDU_COLUMNS
+    ))
+    .bind(tournee_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(compte_rendu)
+}
+
+/// Recalcular y guardar el borrador del compte-rendu
+async fn refresh(
+    conn: &mut PgConnection,
+    tournee: &Tournee,
+    carrier: Option<&CarrierCounts>,
+) -> AppResult<CompteRenduReport> {
+    let facts = package_facts(&mut *conn, tournee).await?;
+    let flagged = flagged_scans(&mut *conn, tournee.id).await?;
+    let counts = count(&facts, flagged, pickups::totals(&mut *conn, tournee.id).await?);
+    let mismatches = find_mismatches(&counts, carrier);
+
+    let compte_rendu = sqlx::query_as::<_, CompteRendu>(&format!(
+        r#"
+        INSERT INTO compte_rendus (
+            company_id, tournee_id, driver_id, tournee_date, expected_packages,
+            delivered_count, failed_count, relay_count, returned_count,
+            carried_over_count, unresolved_count, cancelled_count, loaded_scans,
+            delivered_scans, flagged_scans, pickup_expected_parcels, collected_parcels,
+            carrier_counts, mismatches, generated_at
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, NOW())
+        ON CONFLICT (tournee_id) DO UPDATE SET
+            driver_id = EXCLUDED.driver_id,
+            tournee_date = EXCLUDED.tournee_date,
+            expected_packages = EXCLUDED.expected_packages,
+            delivered_count = EXCLUDED.delivered_count,
+            failed_count = EXCLUDED.failed_count,
+            relay_count = EXCLUDED.relay_count,
+            returned_count = EXCLUDED.returned_count,
+            carried_over_count = EXCLUDED.carried_over_count,
+            unresolved_count = EXCLUDED.unresolved_count,
+            cancelled_count = EXCLUDED.cancelled_count,
+            loaded_scans = EXCLUDED.loaded_scans,
+            delivered_scans = EXCLUDED.delivered_scans,
+            flagged_scans = EXCLUDED.flagged_scans,
+            pickup_expected_parcels = EXCLUDED.pickup_expected_parcels,
+            collected_parcels = EXCLUDED.collected_parcels,
+            carrier_counts = EXCLUDED.carrier_counts,
+            mismatches = EXCLUDED.mismatches,
+            generated_at = NOW(),
+            updated_at = NOW()
+        RETURNING {}
+        "#,
+        COMPTE_RENDU_COLUMNS
+    ))
+    .bind(tournee.company_id)
+    .bind(tournee.id)
+    .bind(tournee.driver_id)
+    .bind(tournee.tournee_date)
+    .bind(counts.expected_packages)
+    .bind(counts.delivered)
+    .bind(counts.failed)
+    .bind(counts.relay)
+    .bind(counts.returned)
+    .bind(counts.carried_over)
+    .bind(counts.unresolved)
+    .bind(counts.cancelled)
+    .bind(counts.loaded_scans)
+    .bind(counts.delivered_scans)
+    .bind(counts.flagged_scans)
+    .bind(counts.pickup_expected_parcels)
+    .bind(counts.collected_parcels)
+    .bind(carrier.map(|c| json!(c)))
+    .bind(json!(mismatches))
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(CompteRenduReport {
+        compte_rendu,
+        lines: lines(&facts),
+    })
+}
+
+fn stored_carrier_counts(compte_rendu: &CompteRendu) -> Option<CarrierCounts> {
+    compte_rendu
+        .carrier_counts
+        .clone()
+        .and_then(|value| serde_json::from_value(value).ok())
+}
+
+/// Generar (o regenerar) el compte-rendu de una tournée cerrada. Sin
+/// `carrier_counts` se conservan los del borrador anterior.
+pub async fn generate(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    tournee_id: Uuid,
+    carrier: Option<CarrierCounts>,
+) -> AppResult<CompteRenduReport> {
+    let tournee = lifecycle::lock_tournee(&mut *conn, user, tournee_id).await?;
+    if !matches!(tournee.tournee_status, TourneeStatus::Completed | TourneeStatus::Cancelled) {
+        return Err(AppError::Conflict(format!(
+            "La tournée está {}: el compte-rendu se genera al cerrarla",
+            tournee.tournee_status.as_str()
+        )));
+    }
+
+    let previous = existing(&mut *conn, tournee.id).await?;
+    if let Some(previous) = &previous {
+        if previous.report_status == CompteRenduStatus::Signed.as_str() {
+            return Err(AppError::Conflict("El compte-rendu ya está firmado".to_string()));
+        }
+    }
+    let carrier = carrier.or_else(|| previous.as_ref().and_then(stored_carrier_counts));
+
+    let report = refresh(&mut *conn, &tournee, carrier.as_ref()).await?;
+    log::info!(
+        "📋 Compte-rendu de la tournée {}: {} paquetes, {} entregados, {} diferencias",
+        tournee.id,
+        report.compte_rendu.expected_packages,
+        report.compte_rendu.delivered_count,
+        report.compte_rendu.mismatches.as_array().map(|m| m.len()).unwrap_or(0)
+    );
+
+    Ok(report)
+}
+
+/// Firmar el compte-rendu: se recalcula con los datos actuales, se exige
+/// reconocer las diferencias y se bloquea la tournée
+pub async fn sign(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    tournee_id: Uuid,
+    request: &SignCompteRenduRequest,
+) -> AppResult<CompteRenduReport> {
+    let tournee = lifecycle::lock_tournee(&mut *conn, user, tournee_id).await?;
+    let previous = existing(&mut *conn, tournee.id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("La tournée no tiene compte-rendu: genéralo primero".to_string()))?;
+    if previous.report_status == CompteRenduStatus::Signed.as_str() {
+        return Err(AppError::Conflict("El compte-rendu ya está firmado".to_string()));
+    }
+
+    let mut report = refresh(&mut *conn, &tournee, stored_carrier_counts(&previous).as_ref()).await?;
+    let mismatches = report.compte_rendu.mismatches.as_array().map(|m| m.len()).unwrap_or(0);
+    if mismatches > 0 && !request.acknowledge_mismatches.unwrap_or(false) {
+        return Err(AppError::Conflict(format!(
+            "El compte-rendu tiene {} diferencias: hay que reconocerlas para firmar",
+            mismatches
+        )));
+    }
+
+    let now = Utc::now();
+    report.compte_rendu = sqlx::query_as::<_, CompteRendu>(&format!(
+        r#"
+        UPDATE compte_rendus SET
+            report_status = $2,
+            signed_by = $3,
+            signed_at = $4,
+            signature_name = $5,
+            signature_comment = $6,
+            updated_at = NOW()
+        WHERE id = $1
+        RETURNING {}
+        "#,
+        COMPTE_RENDU_COLUMNS
+    ))
+    .bind(report.compte_rendu.id)
+    .bind(CompteRenduStatus::Signed.as_str())
+    .bind(user.user_id)
+    .bind(now)
+    .bind(&request.signature_name)
+    .bind(&request.comment)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    sqlx::query("UPDATE tournees SET locked_at = $2, locked_by = $3, updated_at = NOW() WHERE id = $1")
+        .bind(tournee.id)
+        .bind(now)
+        .bind(user.user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    log::info!("✍️ Compte-rendu de la tournée {} firmado por {}: tournée bloqueada", tournee.id, user.user_id);
+
+    Ok(report)
+}
+
+/// Compte-rendu guardado con el detalle actual por paquete
+pub async fn find(pool: &PgPool, tournee: &Tournee) -> AppResult<CompteRenduReport> {
+    let mut conn = pool.acquire().await?;
+    let compte_rendu = sqlx::query_as::<_, CompteRendu>(&format!(
+        "SELECT {} FROM compte_rendus WHERE tournee_id = $1",
+        COMPTE_RENDU_COLUMNS
+    ))
+    .bind(tournee.id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| AppError::NotFound("La tournée no tiene compte-rendu".to_string()))?;
+    let facts = package_facts(&mut conn, tournee).await?;
+
+    Ok(CompteRenduReport {
+        compte_rendu,
+        lines: lines(&facts),
+    })
+}
+
+fn mismatches_of(compte_rendu: &CompteRendu) -> Vec<Mismatch> {
+    serde_json::from_value(compte_rendu.mismatches.clone()).unwrap_or_default()
+}
+
+fn summary_rows(report: &CompteRenduReport) -> Vec<(&'static str, String)> {
+    let cr = &report.compte_rendu;
+    vec![
+        ("tournee_id", cr.tournee_id.to_string()),
+        ("tournee_date", cr.tournee_date.to_string()),
+        ("driver_id", cr.driver_id.to_string()),
+        ("expected_packages", cr.expected_packages.to_string()),
+        ("delivered", cr.delivered_count.to_string()),
+        ("failed", cr.failed_count.to_string()),
+        ("relay", cr.relay_count.to_string()),
+        ("returned", cr.returned_count.to_string()),
+        ("carried_over", cr.carried_over_count.to_string()),
+        ("unresolved", cr.unresolved_count.to_string()),
+        ("cancelled", cr.cancelled_count.to_string()),
+        ("loaded_scans", cr.loaded_scans.to_string()),
+        ("delivered_scans", cr.delivered_scans.to_string()),
+        ("flagged_scans", cr.flagged_scans.to_string()),
+        ("pickup_expected_parcels", cr.pickup_expected_parcels.to_string()),
+        ("collected_parcels", cr.collected_parcels.to_string()),
+        ("report_status", cr.report_status.clone()),
+        ("signature_name", cr.signature_name.clone().unwrap_or_default()),
+        ("signed_at", cr.signed_at.map(|at| at.to_rfc3339()).unwrap_or_default()),
+    ]
+}
+
+/// Exportar como CSV (separador `;`): resumen, diferencias y paquetes
+pub fn render_csv(report: &CompteRenduReport) -> String {
+    let mut csv = String::from("field;value\n");
+    for (field, value) in summary_rows(report) {
+        csv.push_str(&csv_row(&[field.to_string(), value]));
+    }
+
+    csv.push_str("\nmismatch;source;ours;reference;difference\n");
+    for mismatch in mismatches_of(&report.compte_rendu) {
+        csv.push_str(&csv_row(&[
+            mismatch.field,
+            format!("{:?}", mismatch.source).to_lowercase(),
+            mismatch.ours.to_string(),
+            mismatch.reference.to_string(),
+            mismatch.difference.to_string(),
+        ]));
+    }
+
+    csv.push_str("\ntracking_number;outcome;delivery_status;failure_reason;loaded;delivery_scanned\n");
+    for line in &report.lines {
+        csv.push_str(&csv_row(&[
+            line.tracking_number.clone(),
+            line.outcome.as_str().to_string(),
+            line.delivery_status.clone(),
+            line.failure_reason.clone().unwrap_or_default(),
+            line.loaded.to_string(),
+            line.delivery_scanned.to_string(),
+        ]));
+    }
+
+    csv
+}
+
+/// Exportar como PDF imprimible
+pub fn render_pdf(report: &CompteRenduReport) -> Vec<u8> {
+    let cr = &report.compte_rendu;
+    let mut lines = vec![
+        format!("Compte-rendu de tournée du {}", cr.tournee_date.format("%d/%m/%Y")),
+        format!("Tournée {}", cr.tournee_id),
+        String::new(),
+    ];
+    for (field, value) in summary_rows(report).into_iter().skip(3) {
+        lines.push(format!("{:<20} {}", field, value));
+    }
+
+    let mismatches = mismatches_of(cr);
+    lines.push(String::new());
+    if mismatches.is_empty() {
+        lines.push("Aucun écart".to_string());
+    } else {
+        lines.push(format!("Écarts ({})", mismatches.len()));
+        for mismatch in mismatches {
+            lines.push(format!(
+                "  {:<28} {:<8} nous {:>4}  référence {:>4}  écart {:+}",
+                mismatch.field,
+                format!("{:?}", mismatch.source).to_lowercase(),
+                mismatch.ours,
+                mismatch.reference,
+                mismatch.difference
+            ));
+        }
+    }
+
+    lines.push(String::new());
+    lines.push("Colis".to_string());
+    for line in &report.lines {
+        lines.push(format!(
+            "  {:<24} {:<13} {:<17} {}{}",
+            line.tracking_number,
+            line.outcome.as_str(),
+            line.delivery_status,
+            if line.loaded { "chargé" } else { "non chargé" },
+            line.failure_reason.as_deref().map(|r| format!(" ({})", r)).unwrap_or_default()
+        ));
+    }
+
+    if let (Some(name), Some(at)) = (&cr.signature_name, cr.signed_at) {
+        lines.push(String::new());
+        lines.push(format!("Signé par {} le {}", name, paris_local(at).format("%d/%m/%Y %H:%M")));
+        if let Some(comment) = &cr.signature_comment {
+            lines.push(format!("Commentaire : {}", comment));
+        }
+    }
+
+    pdf::render_text(&lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, TimeZone};
+    use serde_json::Value;
+
+    fn facts(status: DeliveryStatus) -> PackageFacts {
+        PackageFacts {
+            package_id: Uuid::new_v4(),
+            tracking_number: "CP0001".to_string(),
+            delivery_status: status,
+            next_action: None,
+            failure_reason: None,
+            in_tournee: true,
+            failed_here: false,
+            carried_over: false,
+            loaded: true,
+            delivery_scanned: false,
+        }
+    }
+
+    #[test]
+    fn test_classify_outcomes() {
+        assert_eq!(classify(&facts(DeliveryStatus::Delivered)), PackageOutcome::Delivered);
+        assert_eq!(classify(&facts(DeliveryStatus::OutForDelivery)), PackageOutcome::Unresolved);
+
+        let mut relay = facts(DeliveryStatus::InTransit);
+        relay.next_action = Some("redirect_to_relay".to_string());
+        assert_eq!(classify(&relay), PackageOutcome::Relay);
+
+        // Reprogramado al cerrar, sin tournée siguiente todavía
+        let mut carried = facts(DeliveryStatus::Failed);
+        carried.carried_over = true;
+        assert_eq!(classify(&carried), PackageOutcome::CarriedOver);
+
+        // Fallido aquí y movido a la tournée del día siguiente
+        let mut moved = facts(DeliveryStatus::Pending);
+        moved.in_tournee = false;
+        moved.failed_here = true;
+        assert_eq!(classify(&moved), PackageOutcome::Failed);
+        moved.failed_here = false;
+        moved.carried_over = true;
+        assert_eq!(classify(&moved), PackageOutcome::CarriedOver);
+    }
+
+    #[test]
+    fn test_find_mismatches_against_carrier_and_scans() {
+        let mut delivered = facts(DeliveryStatus::Delivered);
+        delivered.delivery_scanned = true;
+        let mut not_loaded = facts(DeliveryStatus::Returned);
+        not_loaded.loaded = false;
+        let counts = count(&[delivered, facts(DeliveryStatus::Failed), not_loaded], 0, (4, 3));
+
+        assert_eq!(counts.processed(), 3);
+        assert!(find_mismatches(&counts, None).iter().all(|m| m.source == MismatchSource::Scans));
+
+        let carrier = CarrierCounts {
+            nb_colis: Some(3),
+            nb_colis_distribue: Some(2),
+            nb_colis_traite: Some(3),
+            nb_colis_acollecter: Some(4),
+            nb_colis_collecte: Some(4),
+            ..Default::default()
+        };
+        let mismatches = find_mismatches(&counts, Some(&carrier));
+
+        assert_eq!(mismatches.len(), 3);
+        assert_eq!(mismatches[0].field, "nb_colis_distribue");
+        assert_eq!(mismatches[0].difference, -1);
+        assert_eq!(mismatches[1].field, "nb_colis_collecte");
+        assert_eq!(mismatches[1].difference, -1);
+        assert_eq!(mismatches[2].field, "loaded_scans");
+        assert_eq!((mismatches[2].ours, mismatches[2].reference), (2, 3));
+    }
+
+    #[test]
+    fn test_render_csv_and_pdf() {
+        let now = Utc::now();
+        let report = CompteRenduReport {
+            compte_rendu: CompteRendu {
+                id: Uuid::new_v4(),
+                company_id: Uuid::new_v4(),
+                tournee_id: Uuid::new_v4(),
+                driver_id: Uuid::new_v4(),
+                tournee_date: NaiveDate::from_ymd_opt(2025, 3, 10).unwrap(),
+                expected_packages: 1,
+                delivered_count: 0,
+                failed_count: 1,
+                relay_count: 0,
+                returned_count: 0,
+                carried_over_count: 0,
+                unresolved_count: 0,
+                cancelled_count: 0,
+                loaded_scans: 1,
+                delivered_scans: 0,
+                flagged_scans: 0,
+                pickup_expected_parcels: 0,
+                collected_parcels: 0,
+                carrier_counts: None,
+                mismatches: Value::Array(Vec::new()),
+                report_status: "signed".to_string(),
+                signed_by: None,
+                signed_at: Some(Utc.with_ymd_and_hms(2025, 3, 10, 17, 30, 0).unwrap()),
+                signature_name: Some("Dupont; Jean".to_string()),
+                signature_comment: None,
+                generated_at: now,
+                created_at: None,
+                updated_at: None,
+            },
+            lines: lines(&[facts(DeliveryStatus::Failed)]),
+        };
+
+        let csv = render_csv(&report);
+        assert!(csv.contains("signature_name;\"Dupont; Jean\"\n"));
+        assert!(csv.contains("CP0001;failed;failed;;true;false\n"));
+
+        let pdf = render_pdf(&report);
+        assert!(pdf.starts_with(b"%PDF-1.4"));
+        assert!(String::from_utf8_lossy(&pdf).contains("Sign\u{fffd} par Dupont; Jean le 10/03/2025 18:30"));
+    }
+}
@@ -0,0 +1,794 @@
+//! Pruebas de entrega con cadena de hashes
+//!
+//! Al entregar un paquete la app envía fotos y firma (subidas antes como
+//! `media_objects`), el nombre de quien recibe, la posición GPS y la hora del
+//! dispositivo. El servidor añade su propia hora y calcula:
+//!
+//! - `evidence_hash`: SHA-256 del documento canónico de la evidencia, que
+//!   incluye el SHA-256 de cada archivo.
+//! - `record_hash`: SHA-256 de `previous_hash || evidence_hash`, donde
+//!   `previous_hash` es el `record_hash` de la prueba anterior de la empresa.
+//!
+//! Cambiar cualquier dato de una prueba (o borrarla) rompe su hash y el enlace
+//! con la siguiente.
+
+use chrono::{DateTime, SecondsFormat, SubsecRound, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{PgConnection, PgPool};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::models::delivery_proof::{
+    DeliveryProof, DeliveryProofSummary, ProofChainVerification, ProofCheck, ProofMedia,
+    ProofOfDeliveryRequest, ProofVerification,
+};
+use crate::models::media::MediaTarget;
+use crate::models::package::Package;
+use crate::services::media_service::MediaService;
+use crate::utils::errors::{AppError, AppResult};
+
+/// `previous_hash` de la primera prueba de cada empresa
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Versión del documento canónico de evidencia
+const EVIDENCE_VERSION: u8 = 1;
+
+/// Pruebas leídas por lote al verificar la cadena completa
+const CHAIN_BATCH_SIZE: i64 = 500;
+
+const DELIVERY_PROOF_COLUMNS: &str = r#"
+    id, company_id, package_id, tournee_id, captured_by, tracking_number,
+    recipient_name, photo_media_ids, photo_sha256, signature_media_id,
+    signature_sha256, latitude, longitude, gps_accuracy_meters, device_id,
+    device_timestamp, server_timestamp, chain_position, previous_hash,
+    evidence_hash, record_hash, created_at
+"#;
+
+/// Archivo dentro del documento canónico
+#[derive(Serialize)]
+struct EvidenceFile<'a> {
+    media_id: Uuid,
+    sha256: &'a str,
+}
+
+/// Documento canónico de la evidencia (el orden de los campos es parte del hash)
+#[derive(Serialize)]
+struct Evidence<'a> {
+    version: u8,
+    package_id: Uuid,
+    tracking_number: &'a str,
+    recipient_name: &'a str,
+    photos: Vec<EvidenceFile<'a>>,
+    signature: Option<EvidenceFile<'a>>,
+    latitude: f64,
+    longitude: f64,
+    gps_accuracy_meters: f64,
+    device_id: Option<&'a str>,
+    device_timestamp: String,
+    server_timestamp: String,
+}
+
+/// Documento canónico (JSON) de la evidencia de una prueba
+pub fn canonical_evidence(proof: &DeliveryProof) -> String {
+    let evidence = Evidence {
+        version: EVIDENCE_VERSION,
+        package_id: proof.package_id,
+        tracking_number: &proof.tracking_number,
+        recipient_name: &proof.recipient_name,
+        photos: proof
+            .photo_media_ids
+            .iter()
+            .zip(&proof.photo_sha256)
+            .map(|(media_id, sha256)| EvidenceFile { media_id: *media_id, sha256 })
+            .collect(),
+        signature: proof
+            .signature_media_id
+            .zip(proof.signature_sha256.as_deref())
+            .map(|(media_id, sha256)| EvidenceFile { media_id, sha256 }),
+        latitude: proof.latitude,
+        longitude: proof.longitude,
+        gps_accuracy_meters: proof.gps_accuracy_meters,
+        device_id: proof.device_id.as_deref(),
+        device_timestamp: canonical_timestamp(proof.device_timestamp),
+        server_timestamp: canonical_timestamp(proof.server_timestamp),
+    };
+
+    serde_json::to_string(&evidence).expect("la evidencia siempre es serializable")
+}
+
+/// SHA-256 del documento canónico
+pub fn evidence_hash(proof: &DeliveryProof) -> String {
+    sha256_hex(canonical_evidence(proof).as_bytes())
+}
+
+/// SHA-256 del enlace con la prueba anterior
+pub fn record_hash(previous_hash: &str, evidence_hash: &str) -> String {
+    sha256_hex(format!("{}{}", previous_hash, evidence_hash).as_bytes())
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// PostgreSQL guarda microsegundos: se trunca antes de calcular el hash para
+/// que el documento sea idéntico al releerlo
+fn canonical_timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.trunc_subsecs(6).to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+/// Registrar la prueba de entrega de un paquete (dentro de la transacción que
+/// lo marca como entregado)
+pub async fn record(
+    conn: &mut PgConnection,
+    package: &Package,
+    captured_by: Uuid,
+    request: &ProofOfDeliveryRequest,
+) -> AppResult<DeliveryProof> {
+    if request.photo_media_ids.is_empty() && request.signature_media_id.is_none() {
+        return Err(AppError::BadRequest(
+            "La prueba de entrega necesita al menos una foto o una firma".to_string(),
+        ));
+    }
+    if package.signature_required && request.signature_media_id.is_none() {
+        return Err(AppError::BadRequest("Este paquete requiere la firma del destinatario".to_string()));
+    }
+
+    let mut media_ids = request.photo_media_ids.clone();
+    media_ids.extend(request.signature_media_id);
+    let media = package_media(&mut *conn, package, &media_ids).await?;
+
+    let photo_sha256 = request
+        .photo_media_ids
+        .iter()
+        .map(|id| media_hash(&media, *id, &[MediaTarget::DeliveryPhoto]))
+        .collect::<AppResult<Vec<_>>>()?;
+    let signature_sha256 = request
+        .signature_media_id
+        .map(|id| media_hash(&media, id, &[MediaTarget::SignatureImage, MediaTarget::SignaturePhoto]))
+        .transpose()?;
+
+    // Último eslabón de la cadena (la UNIQUE de chain_position cubre la carrera
+    // de la primera prueba de la empresa)
+    let last = sqlx::query_as::<_, (i64, String)>(
+        r#"
+        SELECT chain_position, record_hash
+        FROM delivery_proofs
+        WHERE company_id = $1
+        ORDER BY chain_position DESC
+        LIMIT 1
+        FOR UPDATE
+        "#,
+    )
+    .bind(package.company_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+    let (chain_position, previous_hash) = match last {
+        Some((position, hash)) => (position + 1, hash),
+        None => (1, GENESIS_HASH.to_string()),
+    };
+
+    let mut proof = DeliveryProof {
+        id: Uuid::new_v4(),
+        company_id: package.company_id,
+        package_id: package.id,
+        tournee_id: package.tournee_id,
+        captured_by: Some(captured_by),
+        tracking_number: package.tracking_number.clone(),
+        recipient_name: request.recipient_name.trim().to_string(),
+        photo_media_ids: request.photo_media_ids.clone(),
+        photo_sha256,
+        signature_media_id: request.signature_media_id,
+        signature_sha256,
+        latitude: request.latitude,
+        longitude: request.longitude,
+        gps_accuracy_meters: request.gps_accuracy_meters,
+        device_id: request.device_id.clone(),
+        device_timestamp: request.device_timestamp.trunc_subsecs(6),
+        server_timestamp: Utc::now().trunc_subsecs(6),
+        chain_position,
+        previous_hash,
+        evidence_hash: String::new(),
+        record_hash: String::new(),
+        created_at: None,
+    };
+    proof.evidence_hash = evidence_hash(&proof);
+    proof.record_hash = record_hash(&proof.previous_hash, &proof.evidence_hash);
+
+    let saved = sqlx::query_as::<_, DeliveryProof>(&format!(
+        r#"
+        INSERT INTO delivery_proofs (
+            id, company_id, package_id, tournee_id, captured_by, tracking_number,
+            recipient_name, photo_media_ids, photo_sha256, signature_media_id,
+            signature_sha256, latitude, longitude, gps_accuracy_meters, device_id,
+            device_timestamp, server_timestamp, chain_position, previous_hash,
+            evidence_hash, record_hash
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21)
+        RETURNING {}
+        "#,
+        DELIVERY_PROOF_COLUMNS
+    ))
+    .bind(proof.id)
+    .bind(proof.company_id)
+    .bind(proof.package_id)
+    .bind(proof.tournee_id)
+    .bind(proof.captured_by)
+    .bind(&proof.tracking_number)
+    .bind(&proof.recipient_name)
+    .bind(&proof.photo_media_ids)
+    .bind(&proof.photo_sha256)
+    .bind(proof.signature_media_id)
+    .bind(&proof.signature_sha256)
+    .bind(proof.latitude)
+    .bind(proof.longitude)
+    .bind(proof.gps_accuracy_meters)
+    .bind(&proof.device_id)
+    .bind(proof.device_timestamp)
+    .bind(proof.server_timestamp)
+    .bind(proof.chain_position)
+    .bind(&proof.previous_hash)
+    .bind(&proof.evidence_hash)
+    .bind(&proof.record_hash)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| match &e {
+        sqlx::Error::Database(db) if db.code().as_deref() == Some("23505") => {
+            if db.constraint() == Some("unique_delivery_proof_chain_position") {
+                AppError::Conflict("Otra prueba de entrega se registró a la vez, reintentar".to_string())
+            } else {
+                AppError::Conflict("El paquete ya tiene prueba de entrega".to_string())
+            }
+        }
+        _ => AppError::Database(e),
+    })?;
+
+    // La posición de la entrega queda también en el paquete (x = longitud)
+    sqlx::query("UPDATE packages SET delivery_coordinates = point($1, $2) WHERE id = $3")
+        .bind(saved.longitude)
+        .bind(saved.latitude)
+        .bind(saved.package_id)
+        .execute(&mut *conn)
+        .await?;
+
+    log::info!(
+        "🧾 Prueba de entrega registrada: paquete {} (posición {} de la cadena)",
+        saved.tracking_number,
+        saved.chain_position
+    );
+
+    Ok(saved)
+}
+
+/// Prueba de entrega de un paquete
+pub async fn find_by_package(pool: &PgPool, company_id: Uuid, package_id: Uuid) -> AppResult<Option<DeliveryProof>> {
+    let proof = sqlx::query_as::<_, DeliveryProof>(&format!(
+        "SELECT {} FROM delivery_proofs WHERE company_id = $1 AND package_id = $2",
+        DELIVERY_PROOF_COLUMNS
+    ))
+    .bind(company_id)
+    .bind(package_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(proof)
+}
+
+/// Verificar una prueba: sus hashes, los enlaces con sus vecinas y los
+/// archivos almacenados (si hay almacenamiento configurado)
+pub async fn verify(pool: &PgPool, media: Option<&MediaService>, proof: &DeliveryProof) -> AppResult<ProofVerification> {
+    let mut checks = hash_checks(proof);
+
+    let neighbours = sqlx::query_as::<_, (i64, String, String)>(
+        r#"
+        SELECT chain_position, previous_hash, record_hash
+        FROM delivery_proofs
+        WHERE company_id = $1 AND chain_position IN ($2 - 1, $2 + 1)
+        "#,
+    )
+    .bind(proof.company_id)
+    .bind(proof.chain_position)
+    .fetch_all(pool)
+    .await?;
+
+    let previous = neighbours.iter().find(|(position, _, _)| *position == proof.chain_position - 1);
+    checks.push(match previous {
+        Some((_, _, hash)) => check("previous_link", *hash == proof.previous_hash, None),
+        None if proof.chain_position == 1 => check("previous_link", proof.previous_hash == GENESIS_HASH, None),
+        None => check("previous_link", false, Some("Falta la prueba anterior de la cadena".to_string())),
+    });
+    if let Some((_, previous_hash, _)) = neighbours.iter().find(|(position, _, _)| *position == proof.chain_position + 1) {
+        checks.push(check("next_link", *previous_hash == proof.record_hash, None));
+    }
+
+    let mut ids = proof.photo_media_ids.clone();
+    ids.extend(proof.signature_media_id);
+    let objects = media_objects(pool, proof.company_id, &ids).await?;
+    for (media_id, expected) in evidence_files(proof) {
+        let name = format!("media:{}", media_id);
+        let Some((storage_key, recorded)) = objects.get(&media_id) else {
+            checks.push(check(&name, false, Some("El archivo ya no existe".to_string())));
+            continue;
+        };
+        if recorded.as_deref() != Some(expected) {
+            checks.push(check(&name, false, Some("El hash registrado del archivo no coincide".to_string())));
+            continue;
+        }
+        match media {
+            Some(media) => match media.download(storage_key).await {
+                Ok(bytes) => {
+                    let ok = sha256_hex(&bytes) == expected;
+                    checks.push(check(&name, ok, (!ok).then(|| "El contenido del archivo cambió".to_string())));
+                }
+                Err(e) => checks.push(check(&name, false, Some(format!("No se pudo leer el archivo: {}", e)))),
+            },
+            None => checks.push(check(&name, true, Some("Contenido no comprobado: almacenamiento no configurado".to_string()))),
+        }
+    }
+
+    Ok(ProofVerification::from_checks(checks))
+}
+
+/// Recorrer toda la cadena de la empresa en orden
+pub async fn verify_chain(pool: &PgPool, company_id: Uuid) -> AppResult<ProofChainVerification> {
+    let mut expected_position = 1;
+    let mut previous_hash = GENESIS_HASH.to_string();
+    let mut proofs_checked = 0;
+
+    loop {
+        let batch = sqlx::query_as::<_, DeliveryProof>(&format!(
+            r#"
+            SELECT {}
+            FROM delivery_proofs
+            WHERE company_id = $1 AND chain_position >= $2
+            ORDER BY chain_position
+            LIMIT $3
+            "#,
+            DELIVERY_PROOF_COLUMNS
+        ))
+        .bind(company_id)
+        .bind(expected_position)
+        .bind(CHAIN_BATCH_SIZE)
+        .fetch_all(pool)
+        .await?;
+
+        if batch.is_empty() {
+            break;
+        }
+
+        for proof in &batch {
+            let failure = if proof.chain_position != expected_position {
+                Some(format!("Falta la posición {}", expected_position))
+            } else if proof.previous_hash != previous_hash {
+                Some("El enlace con la prueba anterior no coincide".to_string())
+            } else {
+                hash_checks(proof)
+                    .into_iter()
+                    .find(|check| !check.ok)
+                    .map(|check| format!("Falla {} del paquete {}", check.check, proof.tracking_number))
+            };
+
+            if let Some(detail) = failure {
+                return Ok(ProofChainVerification {
+                    valid: false,
+                    proofs_checked,
+                    first_invalid_position: Some(expected_position),
+                    detail: Some(detail),
+                });
+            }
+
+            proofs_checked += 1;
+            expected_position += 1;
+            previous_hash = proof.record_hash.clone();
+        }
+    }
+
+    Ok(ProofChainVerification {
+        valid: true,
+        proofs_checked,
+        first_invalid_position: None,
+        detail: None,
+    })
+}
+
+/// Resumen para atención al cliente, con URLs firmadas de los archivos
+pub async fn summary(
+    pool: &PgPool,
+    media: Option<&MediaService>,
+    package: &Package,
+    proof: &DeliveryProof,
+) -> AppResult<DeliveryProofSummary> {
+    let verification = verify(pool, media, proof).await?;
+
+    let driver_name = match proof.captured_by {
+        Some(user_id) => sqlx::query_scalar::<_, String>("SELECT full_name FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?,
+        None => None,
+    };
+
+    let mut ids = proof.photo_media_ids.clone();
+    ids.extend(proof.signature_media_id);
+    let objects = media_objects(pool, proof.company_id, &ids).await?;
+    let now = Utc::now();
+    let file = |media_id: Uuid, sha256: &str| ProofMedia {
+        media_id,
+        sha256: sha256.to_string(),
+        url: media
+            .zip(objects.get(&media_id))
+            .map(|(media, (storage_key, _))| media.signed_url(storage_key, now).0),
+    };
+
+    Ok(DeliveryProofSummary {
+        proof_id: proof.id,
+        package_id: proof.package_id,
+        tracking_number: proof.tracking_number.clone(),
+        delivery_address: package.delivery_address.clone(),
+        addressee_name: package.recipient_name.clone(),
+        recipient_name: proof.recipient_name.clone(),
+        driver_name,
+        latitude: proof.latitude,
+        longitude: proof.longitude,
+        gps_accuracy_meters: proof.gps_accuracy_meters,
+        device_id: proof.device_id.clone(),
+        device_timestamp: proof.device_timestamp,
+        server_timestamp: proof.server_timestamp,
+        clock_skew_seconds: (proof.device_timestamp - proof.server_timestamp).num_seconds(),
+        photos: proof
+            .photo_media_ids
+            .iter()
+            .zip(&proof.photo_sha256)
+            .map(|(id, sha256)| file(*id, sha256))
+            .collect(),
+        signature: proof
+            .signature_media_id
+            .zip(proof.signature_sha256.as_deref())
+            .map(|(id, sha256)| file(id, sha256)),
+        chain_position: proof.chain_position,
+        previous_hash: proof.previous_hash.clone(),
+        evidence_hash: proof.evidence_hash.clone(),
+        record_hash: proof.record_hash.clone(),
+        verification,
+    })
+}
+
+/// Documento HTML imprimible del resumen
+pub fn render_html(summary: &DeliveryProofSummary) -> String {
+    let mut rows = vec![
+        ("Número de seguimiento", summary.tracking_number.clone()),
+        ("Dirección de entrega", summary.delivery_address.clone()),
+        ("Destinatario", summary.addressee_name.clone().unwrap_or_else(|| "-".to_string())),
+        ("Recibido por", summary.recipient_name.clone()),
+        ("Chofer", summary.driver_name.clone().unwrap_or_else(|| "-".to_string())),
+        (
+            "Posición GPS",
+            format!(
+                "{:.6}, {:.6} (± {:.0} m)",
+                summary.latitude, summary.longitude, summary.gps_accuracy_meters
+            ),
+        ),
+        ("Hora del dispositivo", summary.device_timestamp.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        ("Hora del servidor", summary.server_timestamp.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        ("Desfase del reloj", format!("{} s", summary.clock_skew_seconds)),
+    ];
+    if let Some(device_id) = &summary.device_id {
+        rows.push(("Dispositivo", device_id.clone()));
+    }
+
+    let details: String = rows
+        .iter()
+        .map(|(label, value)| format!("<tr><th>{}</th><td>{}</td></tr>\n", label, escape_html(value)))
+        .collect();
+
+    let images: String = summary
+        .photos
+        .iter()
+        .map(|photo| ("Foto", photo))
+        .chain(summary.signature.iter().map(|signature| ("Firma", signature)))
+        .map(|(label, file)| match &file.url {
+            Some(url) => format!(
+                "<figure><img src=\"{}\" alt=\"{}\"><figcaption>{} · SHA-256 {}</figcaption></figure>\n",
+                escape_html(url),
+                label,
+                label,
+                file.sha256
+            ),
+            None => format!("<p>{} · SHA-256 {}</p>\n", label, file.sha256),
+        })
+        .collect();
+
+    let verification = if summary.verification.valid {
+        "✅ Evidencia íntegra".to_string()
+    } else {
+        let failures: Vec<String> = summary
+            .verification
+            .checks
+            .iter()
+            .filter(|check| !check.ok)
+            .map(|check| escape_html(&format!("{}: {}", check.check, check.detail.as_deref().unwrap_or("no coincide"))))
+            .collect();
+        format!("❌ Evidencia alterada — {}", failures.join("; "))
+    };
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="es">
+<head>
+<meta charset="utf-8">
+<title>Prueba de entrega {tracking}</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; }}
+table {{ border-collapse: collapse; margin-bottom: 1em; }}
+th, td {{ border: 1px solid #ccc; padding: 4px 8px; text-align: left; }}
+figure {{ display: inline-block; margin: 0 1em 1em 0; }}
+img {{ max-width: 320px; max-height: 320px; }}
+code {{ font-size: 0.8em; word-break: break-all; }}
+@media print {{ body {{ margin: 0; }} }}
+</style>
+</head>
+<body>
+<h1>Prueba de entrega</h1>
+<table>
+{details}</table>
+{images}<h2>Integridad</h2>
+<p>{verification}</p>
+<p>Posición en la cadena: {position}<br>
+Hash anterior: <code>{previous}</code><br>
+Hash de la evidencia: <code>{evidence}</code><br>
+Hash del registro: <code>{record}</code></p>
+</body>
+</html>
+"#,
+        tracking = escape_html(&summary.tracking_number),
+        details = details,
+        images = images,
+        verification = verification,
+        position = summary.chain_position,
+        previous = summary.previous_hash,
+        evidence = summary.evidence_hash,
+        record = summary.record_hash,
+    )
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn check(name: &str, ok: bool, detail: Option<String>) -> ProofCheck {
+    ProofCheck {
+        check: name.to_string(),
+        ok,
+        detail,
+    }
+}
+
+/// Comprobaciones que solo dependen de la propia fila
+fn hash_checks(proof: &DeliveryProof) -> Vec<ProofCheck> {
+    let files_complete = proof.photo_media_ids.len() == proof.photo_sha256.len()
+        && proof.signature_media_id.is_some() == proof.signature_sha256.is_some();
+    vec![
+        check("evidence_files", files_complete, None),
+        check("evidence_hash", evidence_hash(proof) == proof.evidence_hash, None),
+        check(
+            "record_hash",
+            record_hash(&proof.previous_hash, &proof.evidence_hash) == proof.record_hash,
+            None,
+        ),
+    ]
+}
+
+/// (media_id, sha256) de todos los archivos de la evidencia
+fn evidence_files(proof: &DeliveryProof) -> Vec<(Uuid, &str)> {
+    proof
+        .photo_media_ids
+        .iter()
+        .copied()
+        .zip(proof.photo_sha256.iter().map(String::as_str))
+        .chain(proof.signature_media_id.zip(proof.signature_sha256.as_deref()))
+        .collect()
+}
+
+/// Archivos subidos sobre el paquete: id -> (target, content_sha256)
+async fn package_media(
+    conn: &mut PgConnection,
+    package: &Package,
+    ids: &[Uuid],
+) -> AppResult<HashMap<Uuid, (String, Option<String>)>> {
+    let mut unique = ids.to_vec();
+    unique.sort();
+    unique.dedup();
+    if unique.len() != ids.len() {
+        return Err(AppError::BadRequest("Archivo repetido en la prueba de entrega".to_string()));
+    }
+
+    let rows = sqlx::query_as::<_, (Uuid, String, Option<String>)>(
+        r#"
+        SELECT id, target, content_sha256
+        FROM media_objects
+        WHERE company_id = $1 AND entity_id = $2 AND id = ANY($3)
+        "#,
+    )
+    .bind(package.company_id)
+    .bind(package.id)
+    .bind(ids)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(rows.into_iter().map(|(id, target, sha256)| (id, (target, sha256))).collect())
+}
+
+/// Hash de un archivo de la evidencia, comprobando que es del tipo esperado
+fn media_hash(
+    media: &HashMap<Uuid, (String, Option<String>)>,
+    id: Uuid,
+    targets: &[MediaTarget],
+) -> AppResult<String> {
+    let (target, sha256) = media
+        .get(&id)
+        .ok_or_else(|| AppError::BadRequest(format!("Archivo {} no encontrado para este paquete", id)))?;
+    if !targets.iter().any(|expected| expected.as_str() == target) {
+        return Err(AppError::BadRequest(format!("El archivo {} no es de tipo {}", id, targets[0].as_str())));
+    }
+    sha256
+        .clone()
+        .ok_or_else(|| AppError::BadRequest(format!("El archivo {} no tiene hash registrado", id)))
+}
+
+/// Archivos por id: id -> (storage_key, content_sha256)
+async fn media_objects(
+    pool: &PgPool,
+    company_id: Uuid,
+    ids: &[Uuid],
+) -> AppResult<HashMap<Uuid, (String, Option<String>)>> {
+    let rows = sqlx::query_as::<_, (Uuid, String, Option<String>)>(
+        "SELECT id, storage_key, content_sha256 FROM media_objects WHERE company_id = $1 AND id = ANY($2)",
+    )
+    .bind(company_id)
+    .bind(ids)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|(id, key, sha256)| (id, (key, sha256))).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn sample_proof() -> DeliveryProof {
+        let mut proof = DeliveryProof {
+            id: Uuid::new_v4(),
+            company_id: Uuid::new_v4(),
+            package_id: Uuid::parse_str("7d7f2a0e-4b43-4c3e-9a55-0d6c1f0a2b11").unwrap(),
+            tournee_id: Uuid::new_v4(),
+            captured_by: Some(Uuid::new_v4()),
+            tracking_number: "CP123456789FR".to_string(),
+            recipient_name: "Mme Durand".to_string(),
+            photo_media_ids: vec![Uuid::parse_str("0b9d6c51-3f4e-4b8c-8d6e-2a1f0c9e7b22").unwrap()],
+            photo_sha256: vec![sha256_hex(b"photo")],
+            signature_media_id: Some(Uuid::parse_str("5e3c1a7b-9d2f-4e6a-b8c4-1f0e2d3c4b33").unwrap()),
+            signature_sha256: Some(sha256_hex(b"signature")),
+            latitude: 48.8917,
+            longitude: 2.3446,
+            gps_accuracy_meters: 8.5,
+            device_id: Some("android-42".to_string()),
+            device_timestamp: Utc.with_ymd_and_hms(2026, 3, 2, 10, 15, 0).unwrap(),
+            server_timestamp: Utc.with_ymd_and_hms(2026, 3, 2, 10, 15, 3).unwrap(),
+            chain_position: 1,
+            previous_hash: GENESIS_HASH.to_string(),
+            evidence_hash: String::new(),
+            record_hash: String::new(),
+            created_at: None,
+        };
+        proof.evidence_hash = evidence_hash(&proof);
+        proof.record_hash = record_hash(&proof.previous_hash, &proof.evidence_hash);
+        proof
+    }
+
+    #[test]
+    fn test_canonical_evidence_is_stable() {
+        let proof = sample_proof();
+        let canonical = canonical_evidence(&proof);
+
+        assert!(canonical.starts_with(r#"{"version":1,"package_id":"7d7f2a0e-4b43-4c3e-9a55-0d6c1f0a2b11""#));
+        assert!(canonical.contains(r#""device_timestamp":"2026-03-02T10:15:00.000000Z""#));
+        assert_eq!(evidence_hash(&proof), evidence_hash(&sample_proof()));
+        assert_eq!(proof.evidence_hash.len(), 64);
+    }
+
+    #[test]
+    fn test_timestamps_are_hashed_at_database_precision() {
+        let mut proof = sample_proof();
+        let original = proof.evidence_hash.clone();
+
+        proof.server_timestamp += chrono::Duration::nanoseconds(400);
+        assert_eq!(evidence_hash(&proof), original);
+
+        proof.server_timestamp += chrono::Duration::microseconds(1);
+        assert_ne!(evidence_hash(&proof), original);
+    }
+
+    #[test]
+    fn test_tampering_breaks_hash_checks() {
+        let proof = sample_proof();
+        assert!(hash_checks(&proof).iter().all(|check| check.ok));
+
+        let mut tampered = proof.clone();
+        tampered.recipient_name = "M. Martin".to_string();
+        assert!(!hash_checks(&tampered).iter().all(|check| check.ok));
+
+        let mut tampered = proof.clone();
+        tampered.photo_sha256 = vec![sha256_hex(b"otra foto")];
+        assert!(!hash_checks(&tampered).iter().all(|check| check.ok));
+
+        let mut tampered = proof.clone();
+        tampered.latitude = 48.9;
+        assert!(!hash_checks(&tampered).iter().all(|check| check.ok));
+
+        // Recalcular evidence_hash sin rehacer el enlace también se detecta
+        let mut tampered = proof;
+        tampered.recipient_name = "M. Martin".to_string();
+        tampered.evidence_hash = evidence_hash(&tampered);
+        let checks = hash_checks(&tampered);
+        assert!(checks.iter().find(|c| c.check == "evidence_hash").unwrap().ok);
+        assert!(!checks.iter().find(|c| c.check == "record_hash").unwrap().ok);
+    }
+
+    #[test]
+    fn test_record_hash_chains_previous() {
+        let first = sample_proof();
+        let mut second = sample_proof();
+        second.chain_position = 2;
+        second.previous_hash = first.record_hash.clone();
+        second.record_hash = record_hash(&second.previous_hash, &second.evidence_hash);
+
+        assert_eq!(first.evidence_hash, second.evidence_hash);
+        assert_ne!(first.record_hash, second.record_hash);
+    }
+
+    #[test]
+    fn test_render_html_escapes_values() {
+        let proof = sample_proof();
+        let summary = DeliveryProofSummary {
+            proof_id: proof.id,
+            package_id: proof.package_id,
+            tracking_number: proof.tracking_number.clone(),
+            delivery_address: "12 rue Marcadet, 75018 Paris".to_string(),
+            addressee_name: Some("Durand".to_string()),
+            recipient_name: "<script>alert(1)</script>".to_string(),
+            driver_name: None,
+            latitude: proof.latitude,
+            longitude: proof.longitude,
+            gps_accuracy_meters: proof.gps_accuracy_meters,
+            device_id: None,
+            device_timestamp: proof.device_timestamp,
+            server_timestamp: proof.server_timestamp,
+            clock_skew_seconds: -3,
+            photos: vec![ProofMedia {
+                media_id: proof.photo_media_ids[0],
+                sha256: proof.photo_sha256[0].clone(),
+                url: Some("https://cdn.example/p.jpg?a=1&b=2".to_string()),
+            }],
+            signature: None,
+            chain_position: 1,
+            previous_hash: proof.previous_hash.clone(),
+            evidence_hash: proof.evidence_hash.clone(),
+            record_hash: proof.record_hash.clone(),
+            verification: ProofVerification::from_checks(hash_checks(&proof)),
+        };
+
+        let html = render_html(&summary);
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("p.jpg?a=1&amp;b=2"));
+        assert!(html.contains("Evidencia íntegra"));
+        assert!(html.contains("48.891700, 2.344600"));
+    }
+}
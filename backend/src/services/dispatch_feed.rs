@@ -0,0 +1,151 @@
+//! Canal de seguimiento en vivo para el back-office
+//!
+//! Los handlers publican los eventos en Redis (un canal por empresa); cada
+//! instancia del backend está suscrita a todos los canales con un único
+//! `PSUBSCRIBE` y reparte los eventos a sus conexiones SSE a través de un
+//! `broadcast` local, filtrando por empresa y tournée. Si Redis no está
+//! disponible, el evento se entrega solo a las conexiones de esta instancia.
+
+use futures::StreamExt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::{cache::RedisClient, models::dispatch::DispatchEvent};
+
+/// Prefijo de los canales pub/sub del canal en vivo
+const CHANNEL_PREFIX: &str = "delivery_optimizer:dispatch:";
+
+/// Eventos retenidos para las conexiones lentas antes de que pierdan eventos
+const LOCAL_CAPACITY: usize = 1024;
+
+/// Espera antes de reconectar la suscripción a Redis
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Canal pub/sub de una empresa
+pub fn company_channel(company_id: Uuid) -> String {
+    format!("{}{}", CHANNEL_PREFIX, company_id)
+}
+
+/// ¿Debe recibir el evento una suscripción de la empresa (y tournée)?
+pub fn matches(event: &DispatchEvent, company_id: Uuid, tournee_id: Option<Uuid>) -> bool {
+    event.company_id == company_id && tournee_id.is_none_or(|id| event.tournee_id == Some(id))
+}
+
+/// Publicador y repartidor de eventos en vivo
+#[derive(Clone)]
+pub struct DispatchFeed {
+    redis: RedisClient,
+    local: broadcast::Sender<Arc<DispatchEvent>>,
+}
+
+impl DispatchFeed {
+    pub fn new(redis: RedisClient) -> Self {
+        let (local, _) = broadcast::channel(LOCAL_CAPACITY);
+        Self { redis, local }
+    }
+
+    /// Suscribirse a los eventos que recibe esta instancia
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<DispatchEvent>> {
+        self.local.subscribe()
+    }
+
+    /// Publicar un evento para todas las instancias. Nunca falla: el canal en
+    /// vivo no debe bloquear la operación que lo origina.
+    pub async fn publish(&self, event: DispatchEvent) {
+        let payload = match serde_json::to_string(&event) {
+            Ok(payload) => payload,
+            Err(e) => {
+                log::error!("❌ Evento en vivo no serializable: {}", e);
+                return;
+            }
+        };
+
+        if let Err(e) = self.redis.publish(&company_channel(event.company_id), &payload).await {
+            log::warn!("⚠️ No se pudo publicar el evento en Redis ({}), entrega solo local", e);
+            // Sin suscriptores no hay a quién entregarlo; no es un error
+            let _ = self.local.send(Arc::new(event));
+        }
+    }
+
+    /// Publicar varios eventos en orden
+    pub async fn publish_all(&self, events: impl IntoIterator<Item = DispatchEvent>) {
+        for event in events {
+            self.publish(event).await;
+        }
+    }
+
+    /// Lanzar la suscripción a Redis que alimenta el `broadcast` local
+    pub fn spawn_relay(&self) {
+        let redis = self.redis.clone();
+        let local = self.local.clone();
+
+        tokio::spawn(async move {
+            loop {
+                match redis.pubsub().await {
+                    Ok(mut pubsub) => {
+                        if let Err(e) = pubsub.psubscribe(format!("{}*", CHANNEL_PREFIX)).await {
+                            log::error!("❌ Error suscribiendo el canal en vivo: {}", e);
+                        } else {
+                            log::info!("📡 Canal en vivo suscrito a Redis");
+                            let mut messages = pubsub.on_message();
+                            while let Some(message) = messages.next().await {
+                                let event = message
+                                    .get_payload::<String>()
+                                    .ok()
+                                    .and_then(|payload| serde_json::from_str::<DispatchEvent>(&payload).ok());
+                                match event {
+                                    Some(event) => {
+                                        let _ = local.send(Arc::new(event));
+                                    }
+                                    None => log::warn!("⚠️ Mensaje inválido en {}", message.get_channel_name()),
+                                }
+                            }
+                            log::warn!("⚠️ Suscripción del canal en vivo cerrada, reconectando");
+                        }
+                    }
+                    Err(e) => log::error!("❌ Error abriendo la conexión pub/sub: {}", e),
+                }
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_matches_company_and_tournee() {
+        let company_id = Uuid::new_v4();
+        let tournee_id = Uuid::new_v4();
+        let event = DispatchEvent::alert(company_id, Some(tournee_id), "scan_flagged", "x".to_string(), json!({}));
+
+        assert!(matches(&event, company_id, None));
+        assert!(matches(&event, company_id, Some(tournee_id)));
+        assert!(!matches(&event, company_id, Some(Uuid::new_v4())));
+        assert!(!matches(&event, Uuid::new_v4(), None));
+
+        // Eventos sin tournée solo llegan a las suscripciones de toda la empresa
+        let company_wide = DispatchEvent::alert(company_id, None, "scan_flagged", "x".to_string(), json!({}));
+        assert!(matches(&company_wide, company_id, None));
+        assert!(!matches(&company_wide, company_id, Some(tournee_id)));
+    }
+
+    #[test]
+    fn test_event_roundtrip() {
+        let company_id = Uuid::new_v4();
+        let event = DispatchEvent::driver_position(company_id, Uuid::new_v4(), None, 48.85, 2.35, chrono::Utc::now());
+
+        let payload = serde_json::to_string(&event).unwrap();
+        assert!(payload.contains("\"event_type\":\"driver_position\""));
+        assert!(company_channel(company_id).ends_with(&company_id.to_string()));
+
+        let decoded: DispatchEvent = serde_json::from_str(&payload).unwrap();
+        assert_eq!(decoded.event_type, event.event_type);
+        assert_eq!(decoded.data, event.data);
+    }
+}
@@ -0,0 +1,927 @@
+//! Servicio de conocimiento de terreno (driver_field_data)
+//!
+//! Consultas CRUD y de búsqueda sobre `driver_field_data`, siempre acotadas a
+//! una empresa. Los lookups por dirección normalizada o por proximidad
+//! devuelven la entrada más confiable.
+//!
+//! Los campos sensibles (`door_codes`, `access_instructions`, `security_notes`)
+//! se cifran al escribir y se devuelven cifrados; solo los handlers que sirven
+//! a los choferes de la empresa los descifran con `reveal_sensitive`.
+//!
+//! Toda escritura registra una revisión (ver `field_data_revisions`) en la
+//! misma transacción e incrementa `version`.
+
+use sqlx::{PgConnection, PgPool};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::models::driver_field_data::{
+    normalize_address, CreateDriverFieldDataRequest, DriverFieldData, DriverFieldDataFilters,
+    DriverFieldDataLookup, DriverFieldDataMatch, FieldDataAccessLog, RevertDriverFieldDataRequest,
+    RevisionChangeType, UpdateDriverFieldDataRequest,
+};
+use crate::services::address_confidence::haversine_meters;
+use crate::services::field_data_revisions;
+use crate::services::field_encryption::FieldCipher;
+use crate::utils::errors::{map_unique_violation, AppError, AppResult};
+
+/// Radio por defecto de los lookups por proximidad
+pub const DEFAULT_LOOKUP_RADIUS_METERS: f64 = 30.0;
+/// Radio máximo aceptado en los lookups por proximidad
+pub const MAX_LOOKUP_RADIUS_METERS: f64 = 500.0;
+
+/// Metros por grado de latitud (aproximación para el bounding box)
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+/// Columnas de `driver_field_data` en el orden de `DriverFieldData`
+pub const DRIVER_FIELD_DATA_COLUMNS: &str = r#"
+    id, company_id, driver_id,
+    address, normalized_address, postal_code, city,
+    coordinates[1] AS latitude, coordinates[0] AS longitude,
+    door_codes, access_instructions, security_notes,
+    mailbox_location, mailbox_working, mailbox_issues,
+    preferred_delivery_time, delivery_restrictions, special_instructions,
+    confidence_score, data_source, verification_count,
+    last_updated_by, last_verified_date, version,
+    created_at, updated_at, deleted_at
+"#;
+
+/// Orden de confianza de las entradas (ver `DriverFieldData::trust_key`)
+const TRUST_ORDER: &str = r#"
+    verification_count DESC NULLS LAST,
+    confidence_score DESC NULLS LAST,
+    last_verified_date DESC NULLS LAST,
+    updated_at DESC NULLS LAST
+"#;
+
+/// Cifrar un campo sensible; sin cifrador configurado no se acepta el valor
+fn encrypt_sensitive(cipher: Option<&FieldCipher>, value: Option<&str>) -> AppResult<Option<String>> {
+    match (value, cipher) {
+        (None, _) => Ok(None),
+        (Some(value), Some(cipher)) => cipher
+            .encrypt(value)
+            .map(Some)
+            .map_err(|e| AppError::Internal(e.to_string())),
+        (Some(_), None) => Err(AppError::ServiceUnavailable(
+            "Cifrado de campos no configurado: no se pueden guardar datos de acceso".to_string(),
+        )),
+    }
+}
+
+/// Descifrar en el lugar los campos sensibles de una entrada
+pub fn reveal_sensitive(cipher: &FieldCipher, entry: &mut DriverFieldData) -> AppResult<()> {
+    for field in [&mut entry.door_codes, &mut entry.access_instructions, &mut entry.security_notes] {
+        if let Some(stored) = field.as_deref() {
+            let plaintext = cipher.decrypt(stored).map_err(|e| {
+                log::error!("❌ Error descifrando información de terreno {}: {}", entry.id, e);
+                AppError::Internal("No se pudo descifrar la información de acceso".to_string())
+            })?;
+            *field = Some(plaintext);
+        }
+    }
+    Ok(())
+}
+
+/// Ocultar los campos sensibles de una entrada
+pub fn redact_sensitive(entry: &mut DriverFieldData) {
+    entry.door_codes = None;
+    entry.access_instructions = None;
+    entry.security_notes = None;
+}
+
+/// Registrar en la auditoría la lectura de códigos de puerta
+pub async fn log_door_code_reads(
+    pool: &PgPool,
+    company_id: Uuid,
+    user_id: Uuid,
+    field_data_ids: &[Uuid],
+    access_context: &str,
+) -> AppResult<()> {
+    if field_data_ids.is_empty() {
+        return Ok(());
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO field_data_access_log (company_id, field_data_id, user_id, field_name, access_context)
+        SELECT $1, field_data_id, $2, 'door_codes', $3
+        FROM UNNEST($4::uuid[]) AS field_data_id
+        "#,
+    )
+    .bind(company_id)
+    .bind(user_id)
+    .bind(access_context)
+    .bind(field_data_ids)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Auditoría de lecturas de una entrada (más recientes primero)
+pub async fn access_log(
+    pool: &PgPool,
+    company_id: Uuid,
+    field_data_id: Uuid,
+) -> AppResult<Vec<FieldDataAccessLog>> {
+    let entries = sqlx::query_as::<_, FieldDataAccessLog>(
+        r#"
+        SELECT id, company_id, field_data_id, user_id, field_name, access_context, accessed_at
+        FROM field_data_access_log
+        WHERE company_id = $1 AND field_data_id = $2
+        ORDER BY accessed_at DESC
+        LIMIT 500
+        "#,
+    )
+    .bind(company_id)
+    .bind(field_data_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(entries)
+}
+
+/// Rotar las claves: re-envolver con la clave activa los campos sensibles
+/// de la empresa que usen otra clave (o que sigan sin cifrar)
+///
+/// Cada fila se actualiza solo si sus valores no cambiaron desde la lectura;
+/// una escritura concurrente ya cifra con la clave activa y no se pisa.
+pub async fn rotate_encryption(pool: &PgPool, cipher: &FieldCipher, company_id: Uuid) -> AppResult<usize> {
+    let rows = sqlx::query_as::<_, (Uuid, Option<String>, Option<String>, Option<String>)>(
+        r#"
+        SELECT id, door_codes, access_instructions, security_notes
+        FROM driver_field_data
+        WHERE company_id = $1
+        AND (door_codes IS NOT NULL OR access_instructions IS NOT NULL OR security_notes IS NOT NULL)
+        "#,
+    )
+    .bind(company_id)
+    .fetch_all(pool)
+    .await?;
+
+    let rewrap = |value: Option<String>| -> AppResult<Option<String>> {
+        value
+            .map(|v| cipher.rewrap(&v).map_err(|e| AppError::Internal(e.to_string())))
+            .transpose()
+    };
+
+    let mut rotated = 0;
+    for (id, door_codes, access_instructions, security_notes) in rows {
+        let needs_rewrap = [&door_codes, &access_instructions, &security_notes]
+            .iter()
+            .any(|v| v.as_deref().is_some_and(|v| cipher.needs_rewrap(v)));
+        if !needs_rewrap {
+            continue;
+        }
+
+        let updated = sqlx::query(
+            r#"
+            UPDATE driver_field_data
+            SET door_codes = $2, access_instructions = $3, security_notes = $4
+            WHERE id = $1
+            AND door_codes IS NOT DISTINCT FROM $5
+            AND access_instructions IS NOT DISTINCT FROM $6
+            AND security_notes IS NOT DISTINCT FROM $7
+            "#,
+        )
+        .bind(id)
+        .bind(rewrap(door_codes.clone())?)
+        .bind(rewrap(access_instructions.clone())?)
+        .bind(rewrap(security_notes.clone())?)
+        .bind(door_codes)
+        .bind(access_instructions)
+        .bind(security_notes)
+        .execute(pool)
+        .await?;
+
+        if updated.rows_affected() > 0 {
+            rotated += 1;
+        } else {
+            log::info!("🔐 Información de terreno {} modificada durante la rotación, se omite", id);
+        }
+    }
+
+    Ok(rotated)
+}
+
+/// Mensaje del 409 cuando la dirección ya tiene información de terreno
+fn duplicate_address(address: &str) -> String {
+    format!("Ya existe información de terreno para la dirección '{}'", address)
+}
+
+/// Listar/buscar entradas de la empresa
+pub async fn search(
+    pool: &PgPool,
+    company_id: Uuid,
+    filters: &DriverFieldDataFilters,
+) -> AppResult<Vec<DriverFieldData>> {
+    let limit = filters.limit.unwrap_or(50).clamp(1, 200);
+    let offset = filters.offset.unwrap_or(0).max(0);
+    let pattern = filters.q.as_ref().map(|q| format!("%{}%", q.trim()));
+
+    let query = format!(
+        r#"
+        SELECT {}
+        FROM driver_field_data
+        WHERE company_id = $1
+        AND deleted_at IS NULL
+        AND ($2::text IS NULL OR address ILIKE $2 OR city ILIKE $2 OR special_instructions ILIKE $2)
+        AND ($3::text IS NULL OR postal_code = $3)
+        AND ($4::text IS NULL OR city ILIKE $4)
+        AND ($5::uuid IS NULL OR driver_id = $5)
+        AND ($6::boolean IS NULL OR mailbox_working = $6)
+        AND ($7::int IS NULL OR confidence_score >= $7)
+        ORDER BY {}
+        LIMIT $8 OFFSET $9
+        "#,
+        DRIVER_FIELD_DATA_COLUMNS, TRUST_ORDER
+    );
+
+    let entries = sqlx::query_as::<_, DriverFieldData>(&query)
+        .bind(company_id)
+        .bind(pattern)
+        .bind(filters.postal_code.as_deref())
+        .bind(filters.city.as_deref())
+        .bind(filters.driver_id)
+        .bind(filters.mailbox_working)
+        .bind(filters.min_confidence)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(entries)
+}
+
+/// Obtener una entrada por ID
+pub async fn get(pool: &PgPool, company_id: Uuid, id: Uuid) -> AppResult<DriverFieldData> {
+    let query = format!(
+        "SELECT {} FROM driver_field_data WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL",
+        DRIVER_FIELD_DATA_COLUMNS
+    );
+
+    sqlx::query_as::<_, DriverFieldData>(&query)
+        .bind(id)
+        .bind(company_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Información de terreno no encontrada".to_string()))
+}
+
+/// Crear una entrada a nombre del chofer (revisión 1)
+pub async fn create(
+    pool: &PgPool,
+    cipher: Option<&FieldCipher>,
+    company_id: Uuid,
+    driver_id: Uuid,
+    request: &CreateDriverFieldDataRequest,
+) -> AppResult<DriverFieldData> {
+    let door_codes = encrypt_sensitive(cipher, request.door_codes.as_deref())?;
+    let access_instructions = encrypt_sensitive(cipher, request.access_instructions.as_deref())?;
+    let security_notes = encrypt_sensitive(cipher, request.security_notes.as_deref())?;
+
+    let query = format!(
+        r#"
+        INSERT INTO driver_field_data (
+            company_id, driver_id, address, normalized_address, postal_code, city, coordinates,
+            door_codes, access_instructions, security_notes,
+            mailbox_location, mailbox_working, mailbox_issues,
+            preferred_delivery_time, delivery_restrictions, special_instructions,
+            confidence_score, data_source, last_updated_by, last_verified_date
+        )
+        VALUES (
+            $1, $2, $3, $4, $5, $6,
+            CASE WHEN $7::float8 IS NOT NULL AND $8::float8 IS NOT NULL THEN point($8, $7) END,
+            $9, $10, $11, $12, $13, $14, $15, $16, $17, $18,
+            COALESCE($19, 'driver_input'), $2, CURRENT_DATE
+        )
+        RETURNING {}
+        "#,
+        DRIVER_FIELD_DATA_COLUMNS
+    );
+
+    let mut tx = pool.begin().await?;
+
+    let entry = sqlx::query_as::<_, DriverFieldData>(&query)
+        .bind(company_id)
+        .bind(driver_id)
+        .bind(request.address.trim())
+        .bind(normalize_address(&request.address))
+        .bind(request.postal_code.as_deref())
+        .bind(request.city.as_deref())
+        .bind(request.latitude)
+        .bind(request.longitude)
+        .bind(door_codes)
+        .bind(access_instructions)
+        .bind(security_notes)
+        .bind(request.mailbox_location.as_deref())
+        .bind(request.mailbox_working)
+        .bind(request.mailbox_issues.as_deref())
+        .bind(request.preferred_delivery_time.as_deref())
+        .bind(request.delivery_restrictions.as_deref())
+        .bind(request.special_instructions.as_deref())
+        .bind(request.confidence_score)
+        .bind(request.data_source.as_deref())
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| map_unique_violation(e, &duplicate_address(&request.address)))?;
+
+    let changes = field_data_revisions::diff(None, &entry)?;
+    field_data_revisions::record(&mut tx, &entry, driver_id, RevisionChangeType::Create, changes, None).await?;
+
+    tx.commit().await?;
+    Ok(entry)
+}
+
+/// Bloquear la entrada para modificarla y comprobar la versión esperada
+async fn lock_for_change(
+    conn: &mut PgConnection,
+    company_id: Uuid,
+    id: Uuid,
+    expected_version: i32,
+) -> AppResult<DriverFieldData> {
+    let query = format!(
+        r#"
+        SELECT {} FROM driver_field_data
+        WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
+        FOR UPDATE
+        "#,
+        DRIVER_FIELD_DATA_COLUMNS
+    );
+
+    let current = sqlx::query_as::<_, DriverFieldData>(&query)
+        .bind(id)
+        .bind(company_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Información de terreno no encontrada".to_string()))?;
+
+    if current.version != expected_version {
+        return Err(AppError::Conflict(format!(
+            "La información de terreno fue modificada por otro usuario (versión actual {}, recibida {})",
+            current.version, expected_version
+        )));
+    }
+
+    Ok(current)
+}
+
+/// Cifrar un campo sensible actualizado; si el valor no cambia se conserva
+/// el cifrado existente para no generar revisiones vacías
+fn encrypt_if_changed(
+    cipher: Option<&FieldCipher>,
+    current: Option<&str>,
+    value: Option<&str>,
+) -> AppResult<Option<String>> {
+    if let (Some(cipher), Some(current), Some(value)) = (cipher, current, value) {
+        if cipher.decrypt(current).ok().as_deref() == Some(value) {
+            return Ok(Some(current.to_string()));
+        }
+    }
+    encrypt_sensitive(cipher, value)
+}
+
+/// Actualizar una entrada (solo los campos presentes) con concurrencia optimista
+pub async fn update(
+    pool: &PgPool,
+    cipher: Option<&FieldCipher>,
+    company_id: Uuid,
+    updated_by: Uuid,
+    id: Uuid,
+    request: &UpdateDriverFieldDataRequest,
+) -> AppResult<DriverFieldData> {
+    let query = format!(
+        r#"
+        UPDATE driver_field_data SET
+            address = COALESCE($3, address),
+            normalized_address = COALESCE($4, normalized_address),
+            postal_code = COALESCE($5, postal_code),
+            city = COALESCE($6, city),
+            coordinates = CASE
+                WHEN $7::float8 IS NOT NULL AND $8::float8 IS NOT NULL THEN point($8, $7)
+                ELSE coordinates
+            END,
+            door_codes = COALESCE($9, door_codes),
+            access_instructions = COALESCE($10, access_instructions),
+            security_notes = COALESCE($11, security_notes),
+            mailbox_location = COALESCE($12, mailbox_location),
+            mailbox_working = COALESCE($13, mailbox_working),
+            mailbox_issues = COALESCE($14, mailbox_issues),
+            preferred_delivery_time = COALESCE($15, preferred_delivery_time),
+            delivery_restrictions = COALESCE($16, delivery_restrictions),
+            special_instructions = COALESCE($17, special_instructions),
+            confidence_score = COALESCE($18, confidence_score),
+            last_updated_by = $19,
+            version = version + 1
+        WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
+        RETURNING {}
+        "#,
+        DRIVER_FIELD_DATA_COLUMNS
+    );
+
+    let mut tx = pool.begin().await?;
+    let current = lock_for_change(&mut tx, company_id, id, request.version).await?;
+
+    let address = request.address.as_deref().map(str::trim);
+    let door_codes = encrypt_if_changed(cipher, current.door_codes.as_deref(), request.door_codes.as_deref())?;
+    let access_instructions = encrypt_if_changed(
+        cipher,
+        current.access_instructions.as_deref(),
+        request.access_instructions.as_deref(),
+    )?;
+    let security_notes =
+        encrypt_if_changed(cipher, current.security_notes.as_deref(), request.security_notes.as_deref())?;
+
+    let updated = sqlx::query_as::<_, DriverFieldData>(&query)
+        .bind(id)
+        .bind(company_id)
+        .bind(address)
+        .bind(address.map(normalize_address))
+        .bind(request.postal_code.as_deref())
+        .bind(request.city.as_deref())
+        .bind(request.latitude)
+        .bind(request.longitude)
+        .bind(door_codes)
+        .bind(access_instructions)
+        .bind(security_notes)
+        .bind(request.mailbox_location.as_deref())
+        .bind(request.mailbox_working)
+        .bind(request.mailbox_issues.as_deref())
+        .bind(request.preferred_delivery_time.as_deref())
+        .bind(request.delivery_restrictions.as_deref())
+        .bind(request.special_instructions.as_deref())
+        .bind(request.confidence_score)
+        .bind(updated_by)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| map_unique_violation(e, &duplicate_address(address.unwrap_or_default())))?;
+
+    let changes = field_data_revisions::diff(Some(&current), &updated)?;
+    if changes.is_empty() {
+        // Nada cambió: no se crea revisión ni se incrementa la versión
+        tx.rollback().await?;
+        return Ok(current);
+    }
+
+    field_data_revisions::record(&mut tx, &updated, updated_by, RevisionChangeType::Update, changes, None).await?;
+
+    tx.commit().await?;
+    Ok(updated)
+}
+
+/// Revertir una entrada al estado de una revisión anterior (nueva revisión)
+pub async fn revert(
+    pool: &PgPool,
+    company_id: Uuid,
+    reverted_by: Uuid,
+    id: Uuid,
+    request: &RevertDriverFieldDataRequest,
+) -> AppResult<DriverFieldData> {
+    let mut tx = pool.begin().await?;
+    let current = lock_for_change(&mut tx, company_id, id, request.version).await?;
+
+    let revision = field_data_revisions::get(&mut tx, company_id, id, request.revision_number).await?;
+    let target: DriverFieldData = serde_json::from_value(revision.snapshot)
+        .map_err(|e| AppError::Internal(format!("Revisión {} ilegible: {}", request.revision_number, e)))?;
+
+    // Los campos sensibles del snapshot ya están cifrados: se copian tal cual
+    let query = format!(
+        r#"
+        UPDATE driver_field_data SET
+            address = $3,
+            normalized_address = $4,
+            postal_code = $5,
+            city = $6,
+            coordinates = CASE
+                WHEN $7::float8 IS NOT NULL AND $8::float8 IS NOT NULL THEN point($8, $7)
+            END,
+            door_codes = $9,
+            access_instructions = $10,
+            security_notes = $11,
+            mailbox_location = $12,
+            mailbox_working = $13,
+            mailbox_issues = $14,
+            preferred_delivery_time = $15,
+            delivery_restrictions = $16,
+            special_instructions = $17,
+            confidence_score = $18,
+            data_source = $19,
+            last_updated_by = $20,
+            version = version + 1
+        WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
+        RETURNING {}
+        "#,
+        DRIVER_FIELD_DATA_COLUMNS
+    );
+
+    let reverted = sqlx::query_as::<_, DriverFieldData>(&query)
+        .bind(id)
+        .bind(company_id)
+        .bind(&target.address)
+        .bind(normalize_address(&target.address))
+        .bind(&target.postal_code)
+        .bind(&target.city)
+        .bind(target.latitude)
+        .bind(target.longitude)
+        .bind(&target.door_codes)
+        .bind(&target.access_instructions)
+        .bind(&target.security_notes)
+        .bind(&target.mailbox_location)
+        .bind(target.mailbox_working)
+        .bind(&target.mailbox_issues)
+        .bind(&target.preferred_delivery_time)
+        .bind(&target.delivery_restrictions)
+        .bind(&target.special_instructions)
+        .bind(target.confidence_score)
+        .bind(&target.data_source)
+        .bind(reverted_by)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| map_unique_violation(e, &duplicate_address(&target.address)))?;
+
+    let changes = field_data_revisions::diff(Some(&current), &reverted)?;
+    field_data_revisions::record(
+        &mut tx,
+        &reverted,
+        reverted_by,
+        RevisionChangeType::Revert,
+        changes,
+        Some(request.revision_number),
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(reverted)
+}
+
+/// Eliminar (soft delete) una entrada, dejando constancia en el historial
+pub async fn delete(pool: &PgPool, company_id: Uuid, deleted_by: Uuid, id: Uuid) -> AppResult<()> {
+    let mut tx = pool.begin().await?;
+
+    let query = format!(
+        "SELECT {} FROM driver_field_data WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL FOR UPDATE",
+        DRIVER_FIELD_DATA_COLUMNS
+    );
+    let current = sqlx::query_as::<_, DriverFieldData>(&query)
+        .bind(id)
+        .bind(company_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Información de terreno no encontrada".to_string()))?;
+
+    let query = format!(
+        r#"
+        UPDATE driver_field_data SET deleted_at = NOW(), last_updated_by = $3, version = version + 1
+        WHERE id = $1 AND company_id = $2
+        RETURNING {}
+        "#,
+        DRIVER_FIELD_DATA_COLUMNS
+    );
+    let deleted = sqlx::query_as::<_, DriverFieldData>(&query)
+        .bind(id)
+        .bind(company_id)
+        .bind(deleted_by)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    let changes = field_data_revisions::diff(Some(&current), &deleted)?;
+    field_data_revisions::record(&mut tx, &deleted, deleted_by, RevisionChangeType::Delete, changes, None).await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Entrada más confiable para una dirección (comparando la forma normalizada)
+pub async fn find_by_address(
+    pool: &PgPool,
+    company_id: Uuid,
+    address: &str,
+) -> AppResult<Option<DriverFieldData>> {
+    let query = format!(
+        r#"
+        SELECT {}
+        FROM driver_field_data
+        WHERE company_id = $1 AND normalized_address = $2 AND deleted_at IS NULL
+        ORDER BY {}
+        LIMIT 1
+        "#,
+        DRIVER_FIELD_DATA_COLUMNS, TRUST_ORDER
+    );
+
+    let entry = sqlx::query_as::<_, DriverFieldData>(&query)
+        .bind(company_id)
+        .bind(normalize_address(address))
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(entry)
+}
+
+/// Verificaciones de choferes por dirección normalizada (la entrada más verificada)
+pub async fn verification_counts(
+    pool: &PgPool,
+    company_id: Uuid,
+    addresses: &[String],
+) -> AppResult<HashMap<String, i32>> {
+    let normalized: Vec<String> = addresses.iter().map(|a| normalize_address(a)).collect();
+
+    let rows = sqlx::query_as::<_, (String, i32)>(
+        r#"
+        SELECT normalized_address, MAX(COALESCE(verification_count, 0))
+        FROM driver_field_data
+        WHERE company_id = $1 AND normalized_address = ANY($2) AND deleted_at IS NULL
+        GROUP BY normalized_address
+        "#,
+    )
+    .bind(company_id)
+    .bind(&normalized)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().collect())
+}
+
+/// Entrada más confiable dentro de `radius_meters` alrededor de un punto
+pub async fn find_near(
+    pool: &PgPool,
+    company_id: Uuid,
+    latitude: f64,
+    longitude: f64,
+    radius_meters: f64,
+) -> AppResult<Option<(DriverFieldData, f64)>> {
+    let delta_lat = radius_meters / METERS_PER_DEGREE;
+    let delta_lng = radius_meters / (METERS_PER_DEGREE * latitude.to_radians().cos().abs().max(0.01));
+
+    // Prefiltro por bounding box (usa el índice GIST), distancia exacta en Rust.
+    // Los candidatos más cercanos primero para que el límite no descarte
+    // entradas dentro del radio.
+    let query = format!(
+        r#"
+        SELECT {}
+        FROM driver_field_data
+        WHERE company_id = $1
+        AND deleted_at IS NULL
+        AND coordinates IS NOT NULL
+        AND coordinates <@ box(point($2, $3), point($4, $5))
+        ORDER BY coordinates <-> point($6, $7)
+        LIMIT 100
+        "#,
+        DRIVER_FIELD_DATA_COLUMNS
+    );
+
+    let candidates = sqlx::query_as::<_, DriverFieldData>(&query)
+        .bind(company_id)
+        .bind(longitude - delta_lng)
+        .bind(latitude - delta_lat)
+        .bind(longitude + delta_lng)
+        .bind(latitude + delta_lat)
+        .bind(longitude)
+        .bind(latitude)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(most_trusted_within(candidates, latitude, longitude, radius_meters))
+}
+
+/// Elegir la entrada más confiable dentro del radio (desempate: la más cercana)
+fn most_trusted_within(
+    candidates: Vec<DriverFieldData>,
+    latitude: f64,
+    longitude: f64,
+    radius_meters: f64,
+) -> Option<(DriverFieldData, f64)> {
+    candidates
+        .into_iter()
+        .filter_map(|entry| {
+            let (lat, lng) = (entry.latitude?, entry.longitude?);
+            let distance = haversine_meters((latitude, longitude), (lat, lng));
+            (distance <= radius_meters).then_some((entry, distance))
+        })
+        .max_by(|(a, da), (b, db)| {
+            a.trust_key()
+                .cmp(&b.trust_key())
+                .then_with(|| db.partial_cmp(da).unwrap_or(std::cmp::Ordering::Equal))
+        })
+}
+
+/// Lookup combinado: primero dirección normalizada, luego proximidad
+pub async fn lookup(
+    pool: &PgPool,
+    company_id: Uuid,
+    params: &DriverFieldDataLookup,
+) -> AppResult<Option<DriverFieldDataMatch>> {
+    if params.address.is_none() && (params.latitude.is_none() || params.longitude.is_none()) {
+        return Err(AppError::BadRequest(
+            "Se requiere 'address' o 'latitude' y 'longitude'".to_string(),
+        ));
+    }
+
+    if let Some(address) = params.address.as_deref().filter(|a| !a.trim().is_empty()) {
+        if let Some(entry) = find_by_address(pool, company_id, address).await? {
+            return Ok(Some(DriverFieldDataMatch {
+                entry,
+                matched_by: "address".to_string(),
+                distance_meters: None,
+            }));
+        }
+    }
+
+    if let (Some(latitude), Some(longitude)) = (params.latitude, params.longitude) {
+        let radius = params
+            .radius_meters
+            .unwrap_or(DEFAULT_LOOKUP_RADIUS_METERS)
+            .clamp(1.0, MAX_LOOKUP_RADIUS_METERS);
+
+        if let Some((entry, distance)) = find_near(pool, company_id, latitude, longitude, radius).await? {
+            return Ok(Some(DriverFieldDataMatch {
+                entry,
+                matched_by: "proximity".to_string(),
+                distance_meters: Some(distance),
+            }));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Candidato de proximidad de `lookup_many` con el índice de su consulta
+#[derive(sqlx::FromRow)]
+struct NearCandidate {
+    query_index: i64,
+    #[sqlx(flatten)]
+    entry: DriverFieldData,
+}
+
+/// `lookup` para una lista de consultas con dos queries en total (carga de tournée)
+///
+/// Devuelve un resultado por consulta, en el mismo orden. Las consultas sin
+/// dirección ni coordenadas no encuentran nada.
+pub async fn lookup_many(
+    pool: &PgPool,
+    company_id: Uuid,
+    params: &[DriverFieldDataLookup],
+) -> AppResult<Vec<Option<DriverFieldDataMatch>>> {
+    let normalized: Vec<Option<String>> = params
+        .iter()
+        .map(|p| p.address.as_deref().filter(|a| !a.trim().is_empty()).map(normalize_address))
+        .collect();
+    let addresses: Vec<&str> = normalized.iter().flatten().map(String::as_str).collect();
+
+    let query = format!(
+        r#"
+        SELECT DISTINCT ON (normalized_address) {}
+        FROM driver_field_data
+        WHERE company_id = $1 AND normalized_address = ANY($2) AND deleted_at IS NULL
+        ORDER BY normalized_address, {}
+        "#,
+        DRIVER_FIELD_DATA_COLUMNS, TRUST_ORDER
+    );
+    let by_address: HashMap<String, DriverFieldData> = sqlx::query_as::<_, DriverFieldData>(&query)
+        .bind(company_id)
+        .bind(&addresses)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .filter_map(|entry| Some((entry.normalized_address.clone()?, entry)))
+        .collect();
+
+    let mut results: Vec<Option<DriverFieldDataMatch>> = normalized
+        .iter()
+        .map(|address| {
+            let entry = by_address.get(address.as_ref()?)?.clone();
+            Some(DriverFieldDataMatch {
+                entry,
+                matched_by: "address".to_string(),
+                distance_meters: None,
+            })
+        })
+        .collect();
+
+    // Proximidad solo para las consultas sin coincidencia por dirección
+    let pending: Vec<(usize, f64, f64, f64)> = params
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| results[*index].is_none())
+        .filter_map(|(index, p)| {
+            let radius = p
+                .radius_meters
+                .unwrap_or(DEFAULT_LOOKUP_RADIUS_METERS)
+                .clamp(1.0, MAX_LOOKUP_RADIUS_METERS);
+            Some((index, p.latitude?, p.longitude?, radius))
+        })
+        .collect();
+    if pending.is_empty() {
+        return Ok(results);
+    }
+
+    let mut indexes = Vec::with_capacity(pending.len());
+    let (mut min_lng, mut min_lat, mut max_lng, mut max_lat) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    for (index, latitude, longitude, radius) in &pending {
+        let delta_lat = radius / METERS_PER_DEGREE;
+        let delta_lng = radius / (METERS_PER_DEGREE * latitude.to_radians().cos().abs().max(0.01));
+        indexes.push(*index as i64);
+        min_lng.push(longitude - delta_lng);
+        min_lat.push(latitude - delta_lat);
+        max_lng.push(longitude + delta_lng);
+        max_lat.push(latitude + delta_lat);
+    }
+
+    let query = format!(
+        r#"
+        SELECT q.query_index, {}
+        FROM UNNEST($2::bigint[], $3::float8[], $4::float8[], $5::float8[], $6::float8[])
+            AS q(query_index, min_lng, min_lat, max_lng, max_lat)
+        JOIN driver_field_data
+            ON company_id = $1
+            AND deleted_at IS NULL
+            AND coordinates IS NOT NULL
+            AND coordinates <@ box(point(q.min_lng, q.min_lat), point(q.max_lng, q.max_lat))
+        "#,
+        DRIVER_FIELD_DATA_COLUMNS
+    );
+    let candidates = sqlx::query_as::<_, NearCandidate>(&query)
+        .bind(company_id)
+        .bind(&indexes)
+        .bind(&min_lng)
+        .bind(&min_lat)
+        .bind(&max_lng)
+        .bind(&max_lat)
+        .fetch_all(pool)
+        .await?;
+
+    let mut by_query: HashMap<usize, Vec<DriverFieldData>> = HashMap::new();
+    for candidate in candidates {
+        by_query
+            .entry(candidate.query_index as usize)
+            .or_default()
+            .push(candidate.entry);
+    }
+
+    for (index, latitude, longitude, radius) in pending {
+        let candidates = by_query.remove(&index).unwrap_or_default();
+        if let Some((entry, distance)) = most_trusted_within(candidates, latitude, longitude, radius) {
+            results[index] = Some(DriverFieldDataMatch {
+                entry,
+                matched_by: "proximity".to_string(),
+                distance_meters: Some(distance),
+            });
+        }
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(latitude: f64, longitude: f64, verification_count: i32) -> DriverFieldData {
+        DriverFieldData {
+            id: Uuid::new_v4(),
+            company_id: Uuid::nil(),
+            driver_id: Uuid::nil(),
+            address: "12 RUE MARCADET, 75018 PARIS".to_string(),
+            normalized_address: None,
+            postal_code: Some("75018".to_string()),
+            city: Some("PARIS".to_string()),
+            latitude: Some(latitude),
+            longitude: Some(longitude),
+            door_codes: None,
+            access_instructions: None,
+            security_notes: None,
+            mailbox_location: None,
+            mailbox_working: None,
+            mailbox_issues: None,
+            preferred_delivery_time: None,
+            delivery_restrictions: None,
+            special_instructions: None,
+            confidence_score: Some(3),
+            data_source: None,
+            verification_count: Some(verification_count),
+            last_updated_by: None,
+            last_verified_date: None,
+            version: 1,
+            created_at: None,
+            updated_at: None,
+            deleted_at: None,
+        }
+    }
+
+    #[test]
+    fn test_most_trusted_within_prefers_verification_over_distance() {
+        let near = entry(48.89150, 2.34870, 1);
+        let trusted = entry(48.89160, 2.34890, 4);
+        let outside = entry(48.89500, 2.34870, 10);
+
+        let (best, distance) =
+            most_trusted_within(vec![near, trusted.clone(), outside], 48.89150, 2.34870, 30.0).unwrap();
+        assert_eq!(best.id, trusted.id);
+        assert!(distance < 30.0);
+    }
+
+    #[test]
+    fn test_most_trusted_within_none_in_radius() {
+        let far = entry(48.90000, 2.34870, 3);
+        assert!(most_trusted_within(vec![far], 48.89150, 2.34870, 30.0).is_none());
+    }
+}
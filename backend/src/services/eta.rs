@@ -0,0 +1,273 @@
+//! Horas estimadas de llegada a las paradas pendientes
+//!
+//! El orden previsto es el del vecino más cercano desde la posición actual
+//! del chofer, el trayecto se estima en línea recta a la velocidad media
+//! configurada y el tiempo de servicio por parada es la mediana de las
+//! visitas medidas por geocerca del propio chofer (ver `services::geofencing`).
+//!
+//! Las recogidas entran en la ruta con su franja horaria (si se llega antes,
+//! se espera a la apertura) y solo se programan cuando sus bultos caben en el
+//! vehículo: las entregas van liberando sitio y los bultos recogidos lo ocupan
+//! hasta el final de la tournée.
+
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::{
+    models::stop_visit::{RouteStop, StopEta, StopKind, TourneeEta},
+    models::tournee::Tournee,
+    services::address_confidence::haversine_meters,
+    services::geofencing::{self, GeofenceConfig},
+    services::pickups,
+    utils::errors::AppResult,
+};
+
+/// Tiempo de servicio por parada del chofer: mediana medida o valor por
+/// defecto si no hay visitas suficientes. Devuelve también si es medido.
+pub async fn service_seconds(
+    conn: &mut PgConnection,
+    driver_id: Uuid,
+    config: &GeofenceConfig,
+) -> AppResult<(f64, bool)> {
+    let (median, visits) = sqlx::query_as::<_, (Option<f64>, i64)>(
+        r#"
+        SELECT percentile_cont(0.5) WITHIN GROUP (ORDER BY dwell_seconds), COUNT(*)
+        FROM stop_visits
+        WHERE driver_id = $1 AND arrival_confirmed AND departed_at IS NOT NULL
+        AND departed_at >= NOW() - make_interval(days => $2)
+        "#,
+    )
+    .bind(driver_id)
+    .bind(config.service_time_days)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(match median {
+        Some(median) if visits >= config.min_measured_visits => (median, true),
+        _ => (config.default_service_seconds, false),
+    })
+}
+
+/// Carga del vehículo al planificar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VehicleLoad {
+    /// Bultos a bordo
+    pub on_board: i32,
+    /// Capacidad en bultos (`None` = sin límite)
+    pub capacity: Option<i32>,
+}
+
+/// Recorrer las paradas desde `origin` a partir de `start`, sumando trayecto,
+/// espera hasta la franja y tiempo de servicio. En cada paso se elige la
+/// parada alcanzable antes de entre las que caben en el vehículo; si ninguna
+/// cabe, la más temprana queda marcada como `over_capacity`.
+pub fn plan(
+    origin: Option<(f64, f64)>,
+    start: DateTime<Utc>,
+    mut stops: Vec<RouteStop>,
+    load: VehicleLoad,
+    service_seconds: f64,
+    average_speed_kmh: f64,
+) -> Vec<StopEta> {
+    let speed_mps = average_speed_kmh / 3.6;
+    let mut position = origin;
+    let mut clock = start;
+    let mut on_board = load.on_board;
+    let mut etas = Vec::with_capacity(stops.len());
+
+    while !stops.is_empty() {
+        let candidates: Vec<(usize, f64, DateTime<Utc>, bool)> = stops
+            .iter()
+            .enumerate()
+            .map(|(i, stop)| {
+                // Sin posición conocida se empieza por la primera parada
+                let distance = position
+                    .map(|from| haversine_meters(from, (stop.latitude, stop.longitude)))
+                    .unwrap_or(0.0);
+                let arrival = clock + Duration::milliseconds((distance / speed_mps * 1000.0) as i64);
+                let eta = stop.window_start.map_or(arrival, |opens| arrival.max(opens));
+                let fits = stop.kind == StopKind::Delivery
+                    || load.capacity.is_none_or(|capacity| on_board + stop.parcels <= capacity);
+                (i, distance, eta, fits)
+            })
+            .collect();
+
+        let earliest = |fitting: bool| {
+            candidates
+                .iter()
+                .filter(|candidate| !fitting || candidate.3)
+                .min_by(|a, b| a.2.cmp(&b.2).then(a.1.total_cmp(&b.1)))
+                .copied()
+        };
+        let (next, distance, eta, fits) = earliest(true).or_else(|| earliest(false)).expect("paradas pendientes");
+        let stop = stops.swap_remove(next);
+
+        on_board = match stop.kind {
+            StopKind::Delivery => (on_board - stop.parcels).max(0),
+            StopKind::Pickup => on_board + stop.parcels,
+        };
+        etas.push(StopEta {
+            kind: stop.kind,
+            latitude: stop.latitude,
+            longitude: stop.longitude,
+            package_ids: stop.package_ids,
+            pickup_id: stop.pickup_id,
+            distance_meters: distance,
+            eta,
+            late: stop.window_end.is_some_and(|closes| eta > closes),
+            load_after: on_board,
+            over_capacity: !fits,
+        });
+        clock = eta + Duration::milliseconds((service_seconds * 1000.0) as i64);
+        position = Some((stop.latitude, stop.longitude));
+    }
+
+    etas
+}
+
+/// Capacidad en bultos del vehículo de la tournée
+async fn vehicle_capacity(conn: &mut PgConnection, vehicle_id: Uuid) -> AppResult<Option<i32>> {
+    let capacity = sqlx::query_scalar::<_, Option<i32>>("SELECT parcel_capacity FROM vehicles WHERE id = $1")
+        .bind(vehicle_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .flatten();
+
+    Ok(capacity)
+}
+
+/// ETAs de las paradas pendientes (entregas y recogidas) de una tournée
+pub async fn tournee_eta(conn: &mut PgConnection, tournee: &Tournee, config: &GeofenceConfig) -> AppResult<TourneeEta> {
+    let now = Utc::now();
+    let (service, measured) = service_seconds(&mut *conn, tournee.driver_id, config).await?;
+    let mut stops = geofencing::pending_stops(&mut *conn, tournee.id).await?;
+
+    // Si el chofer está en una parada, sale de ella al acabar el servicio
+    let (origin, start) = match geofencing::open_visit(&mut *conn, tournee.id, tournee.driver_id).await? {
+        Some(visit) if visit.arrival_confirmed => {
+            stops.retain(|stop| stop.package_ids.iter().all(|id| !visit.package_ids.contains(id)));
+            let leaves_at = visit.entered_at + Duration::milliseconds((service * 1000.0) as i64);
+            (Some((visit.latitude, visit.longitude)), leaves_at.max(now))
+        }
+        _ => {
+            let last_location = sqlx::query_as::<_, (Option<f64>, Option<f64>)>(
+                "SELECT last_location[1], last_location[0] FROM users WHERE id = $1",
+            )
+            .bind(tournee.driver_id)
+            .fetch_optional(&mut *conn)
+            .await?;
+            let origin = match last_location {
+                Some((Some(latitude), Some(longitude))) => Some((latitude, longitude)),
+                _ => None,
+            };
+            (origin, now)
+        }
+    };
+
+    let mut route: Vec<RouteStop> = stops.into_iter().map(RouteStop::from).collect();
+    let deliveries_on_board: i32 = route.iter().map(|stop| stop.parcels).sum();
+    route.extend(pickups::route_stops(&mut *conn, tournee.id).await?);
+    let load = VehicleLoad {
+        on_board: deliveries_on_board + pickups::collected_on_board(&mut *conn, tournee.id).await?,
+        capacity: vehicle_capacity(&mut *conn, tournee.vehicle_id).await?,
+    };
+
+    Ok(TourneeEta {
+        tournee_id: tournee.id,
+        service_seconds: service,
+        measured_service_time: measured,
+        computed_at: now,
+        vehicle_capacity: load.capacity,
+        initial_load: load.on_board,
+        stops: plan(origin, start, route, load, service, config.average_speed_kmh),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::stop_visit::Stop;
+    use chrono::TimeZone;
+
+    fn stop(latitude: f64, longitude: f64) -> RouteStop {
+        RouteStop::from(Stop { latitude, longitude, package_ids: vec![Uuid::new_v4()] })
+    }
+
+    fn pickup(latitude: f64, longitude: f64, parcels: i32) -> RouteStop {
+        RouteStop {
+            kind: StopKind::Pickup,
+            package_ids: Vec::new(),
+            pickup_id: Some(Uuid::new_v4()),
+            parcels,
+            ..stop(latitude, longitude)
+        }
+    }
+
+    const UNLIMITED: VehicleLoad = VehicleLoad { on_board: 2, capacity: None };
+
+    #[test]
+    fn test_plan_nearest_neighbour_with_service_time() {
+        let start = Utc.with_ymd_and_hms(2025, 3, 10, 9, 0, 0).unwrap();
+        // ~1,1 km y ~2,2 km al norte del origen, en orden inverso
+        let stops = vec![stop(48.87, 2.35), stop(48.86, 2.35)];
+
+        let etas = plan(Some((48.85, 2.35)), start, stops, UNLIMITED, 120.0, 36.0);
+
+        assert_eq!(etas.len(), 2);
+        assert_eq!(etas[0].latitude, 48.86);
+        assert_eq!(etas[1].latitude, 48.87);
+        // 36 km/h = 10 m/s: ~111 s de trayecto, luego 120 s de servicio y otros ~111 s
+        let first = (etas[0].eta - start).num_seconds();
+        let second = (etas[1].eta - etas[0].eta).num_seconds();
+        assert!((110..=112).contains(&first), "primera {}", first);
+        assert!((230..=232).contains(&second), "segunda {}", second);
+        assert_eq!((etas[0].load_after, etas[1].load_after), (1, 0));
+    }
+
+    #[test]
+    fn test_plan_without_origin_starts_at_first_stop() {
+        let start = Utc.with_ymd_and_hms(2025, 3, 10, 9, 0, 0).unwrap();
+        let etas = plan(None, start, vec![stop(48.86, 2.35)], UNLIMITED, 90.0, 20.0);
+
+        assert_eq!(etas[0].eta, start);
+        assert_eq!(etas[0].distance_meters, 0.0);
+        assert!(plan(None, start, Vec::new(), UNLIMITED, 90.0, 20.0).is_empty());
+    }
+
+    #[test]
+    fn test_plan_defers_pickup_until_it_fits() {
+        let start = Utc.with_ymd_and_hms(2025, 3, 10, 9, 0, 0).unwrap();
+        // La recogida es la más cercana, pero con 2 paquetes a bordo y
+        // capacidad 3 sus 2 bultos solo caben tras la primera entrega
+        let stops = vec![stop(48.87, 2.35), pickup(48.86, 2.35, 2), stop(48.90, 2.35)];
+        let load = VehicleLoad { on_board: 2, capacity: Some(3) };
+
+        let etas = plan(Some((48.85, 2.35)), start, stops, load, 60.0, 36.0);
+
+        let kinds: Vec<StopKind> = etas.iter().map(|eta| eta.kind).collect();
+        assert_eq!(kinds, vec![StopKind::Delivery, StopKind::Pickup, StopKind::Delivery]);
+        assert_eq!(etas.iter().map(|eta| eta.load_after).collect::<Vec<_>>(), vec![1, 3, 2]);
+        assert!(etas.iter().all(|eta| !eta.over_capacity));
+    }
+
+    #[test]
+    fn test_plan_waits_for_window_and_flags_late_or_over_capacity() {
+        let start = Utc.with_ymd_and_hms(2025, 3, 10, 9, 0, 0).unwrap();
+        let mut early = pickup(48.86, 2.35, 1);
+        early.window_start = Some(start + Duration::hours(1));
+        let mut closed = pickup(48.86, 2.36, 1);
+        closed.window_end = Some(start - Duration::minutes(5));
+
+        let etas = plan(Some((48.86, 2.35)), start, vec![early, closed], UNLIMITED, 60.0, 36.0);
+        // La recogida cerrada se alcanza antes que la apertura de la otra
+        assert!(etas[0].late);
+        assert_eq!(etas[1].eta, start + Duration::hours(1));
+        assert!(!etas[1].late);
+
+        let load = VehicleLoad { on_board: 0, capacity: Some(5) };
+        let etas = plan(None, start, vec![pickup(48.86, 2.35, 8)], load, 60.0, 36.0);
+        assert!(etas[0].over_capacity);
+        assert_eq!(etas[0].load_after, 8);
+    }
+}
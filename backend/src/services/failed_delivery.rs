@@ -0,0 +1,585 @@
+//! Flujo de entregas fallidas
+//!
+//! Según el motivo del fallo y los intentos acumulados decide la siguiente
+//! acción del paquete:
+//!
+//! - Destinatario ausente o acceso imposible: aviso de paso y nueva
+//!   presentación el siguiente día laborable; a partir de
+//!   `relay_after_attempts` intentos, depósito en el punto relais más cercano.
+//! - Dirección errónea, rechazo o paquete dañado: devolución al remitente.
+//! - Causas del transportista (meteo, avería, emergencia): nueva presentación
+//!   sin contar como intento.
+//! - Al llegar a `max_attempts` intentos: devolución al remitente.
+//!
+//! Las nuevas presentaciones se mueven a la tournée del día con el mismo
+//! número de tournée (o del mismo chofer). Si todavía no existe, el paquete
+//! queda en `failed` con su `reschedule_date` y se adopta al crear la tournée
+//! (`adopt_rescheduled`).
+
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::PgConnection;
+use std::env;
+use uuid::Uuid;
+
+use crate::models::package::{
+    DeliveryFailureReason, DeliveryStatus, FailureAction, MarkFailedRequest, Package, PACKAGE_COLUMNS,
+};
+use crate::models::relay_point::{RelayPoint, RELAY_POINT_COLUMNS};
+use crate::models::tournee::Tournee;
+use crate::services::address_confidence::haversine_meters;
+use crate::services::lifecycle;
+use crate::utils::errors::{AppError, AppResult};
+use crate::utils::french_holidays::{is_working_day, next_working_day, paris_today};
+
+/// Metros por grado de latitud (aprox.)
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+/// Estados que cuentan como paquete todavía en el punto relais
+const RELAY_OPEN_STATUSES: &str = "delivery_status NOT IN ('delivered', 'returned', 'cancelled')";
+
+/// Límites del flujo de entregas fallidas
+#[derive(Debug, Clone)]
+pub struct FailedDeliveryPolicy {
+    /// Intentos tras los que el paquete vuelve al remitente
+    pub max_attempts: i32,
+    /// Intentos tras los que un ausente se redirige a punto relais
+    pub relay_after_attempts: i32,
+    /// Radio de búsqueda del punto relais
+    pub relay_search_radius_meters: f64,
+}
+
+impl Default for FailedDeliveryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            relay_after_attempts: 2,
+            relay_search_radius_meters: 5_000.0,
+        }
+    }
+}
+
+impl FailedDeliveryPolicy {
+    /// Cargar límites desde `FAILED_DELIVERY_*`
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let read_attempts = |name: &str, default: i32| -> i32 {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<i32>().ok())
+                .filter(|v| *v >= 1)
+                .unwrap_or(default)
+        };
+
+        Self {
+            max_attempts: read_attempts("FAILED_DELIVERY_MAX_ATTEMPTS", defaults.max_attempts),
+            relay_after_attempts: read_attempts("FAILED_DELIVERY_RELAY_AFTER_ATTEMPTS", defaults.relay_after_attempts),
+            relay_search_radius_meters: env::var("FAILED_DELIVERY_RELAY_RADIUS_METERS")
+                .ok()
+                .and_then(|v| v.parse::<f64>().ok())
+                .filter(|v| *v > 0.0)
+                .unwrap_or(defaults.relay_search_radius_meters),
+        }
+    }
+}
+
+/// Resultado del flujo para un paquete
+#[derive(Debug)]
+pub struct FailureOutcome {
+    pub package: Package,
+    pub action: FailureAction,
+    pub next_tournee_id: Option<Uuid>,
+    pub relay_point: Option<(RelayPoint, f64)>,
+}
+
+/// Los fallos imputables al transportista no cuentan como intento
+pub fn counts_as_attempt(reason: &DeliveryFailureReason) -> bool {
+    !matches!(
+        reason,
+        DeliveryFailureReason::WeatherConditions
+            | DeliveryFailureReason::VehicleBreakdown
+            | DeliveryFailureReason::DriverEmergency
+    )
+}
+
+/// Acción según el motivo y los intentos (incluido el actual)
+pub fn decide(reason: &DeliveryFailureReason, attempts: i32, policy: &FailedDeliveryPolicy) -> FailureAction {
+    match reason {
+        DeliveryFailureReason::WrongAddress
+        | DeliveryFailureReason::RefusedDelivery
+        | DeliveryFailureReason::PackageDamaged => FailureAction::ReturnToSender,
+        _ if attempts >= policy.max_attempts => FailureAction::ReturnToSender,
+        DeliveryFailureReason::WeatherConditions
+        | DeliveryFailureReason::VehicleBreakdown
+        | DeliveryFailureReason::DriverEmergency => FailureAction::Reschedule,
+        DeliveryFailureReason::RecipientNotHome | DeliveryFailureReason::SecurityRestriction => {
+            if attempts >= policy.relay_after_attempts {
+                FailureAction::RedirectToRelay
+            } else {
+                FailureAction::LeaveNotice
+            }
+        }
+    }
+}
+
+/// Fecha de la nueva presentación: la pedida (ajustada a laborable) o el
+/// siguiente día laborable
+pub fn reschedule_date(today: NaiveDate, requested: Option<NaiveDate>) -> AppResult<NaiveDate> {
+    match requested {
+        Some(date) if date <= today => Err(AppError::BadRequest(
+            "reschedule_date debe ser posterior a hoy".to_string(),
+        )),
+        Some(date) if is_working_day(date) => Ok(date),
+        Some(date) => Ok(next_working_day(date)),
+        None => Ok(next_working_day(today)),
+    }
+}
+
+/// Aplicar el flujo a un paquete bloqueado (`FOR UPDATE`) dentro de la
+/// transacción del fallo (`failed_at` es la hora del dispositivo)
+pub async fn process_failure(
+    conn: &mut PgConnection,
+    package: &Package,
+    reason: DeliveryFailureReason,
+    request: &MarkFailedRequest,
+    requested_date: Option<NaiveDate>,
+    policy: &FailedDeliveryPolicy,
+    failed_at: DateTime<Utc>,
+) -> AppResult<FailureOutcome> {
+    let attempts = package.delivery_attempts + counts_as_attempt(&reason) as i32;
+    let mut action = decide(&reason, attempts, policy);
+
+    let mut relay_point = None;
+    if action == FailureAction::RedirectToRelay {
+        let position = match (request.latitude, request.longitude) {
+            (Some(latitude), Some(longitude)) => Some((latitude, longitude)),
+            _ => package.delivery_coordinates.as_ref().map(|point| (point.y, point.x)),
+        };
+        if let Some((latitude, longitude)) = position {
+            relay_point = nearest_relay_point(
+                &mut *conn,
+                package.company_id,
+                latitude,
+                longitude,
+                policy.relay_search_radius_meters,
+            )
+            .await?;
+        }
+        if relay_point.is_none() {
+            log::warn!(
+                "⚠️ Sin punto relais disponible para el paquete {}: nueva presentación con aviso",
+                package.tracking_number
+            );
+            action = FailureAction::LeaveNotice;
+        }
+    }
+
+    let new_date = if action.is_new_attempt() {
+        Some(reschedule_date(paris_today(), requested_date)?)
+    } else {
+        None
+    };
+    let status = match action {
+        FailureAction::ReturnToSender => "returned",
+        FailureAction::RedirectToRelay => "in_transit",
+        FailureAction::Reschedule | FailureAction::LeaveNotice => "failed",
+    };
+
+    let mut updated = sqlx::query_as::<_, Package>(&format!(
+        r#"
+        UPDATE packages SET
+            delivery_status = $2::delivery_status,
+            failure_reason = $3,
+            failure_notes = $4,
+            reschedule_date = $5,
+            driver_notes = COALESCE($6, driver_notes),
+            delivery_attempts = $7,
+            next_action = $8,
+            relay_point_id = $9,
+            status_changed_at = $10,
+            updated_at = NOW()
+        WHERE id = $1
+        RETURNING {}
+        "#,
+        PACKAGE_COLUMNS
+    ))
+    .bind(package.id)
+    .bind(status)
+    .bind(&reason)
+    .bind(&request.failure_notes)
+    .bind(new_date)
+    .bind(&request.driver_notes)
+    .bind(attempts)
+    .bind(action.as_str())
+    .bind(relay_point.as_ref().map(|(relay, _): &(RelayPoint, f64)| relay.id))
+    .bind(failed_at)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let mut next_tournee_id = None;
+    if let Some(date) = new_date {
+        if let Some(tournee_id) = find_next_tournee(&mut *conn, &updated, date).await? {
+            if let Some(moved) = move_to_tournee(&mut *conn, &updated, tournee_id).await? {
+                updated = moved;
+                next_tournee_id = Some(tournee_id);
+            }
+        }
+    }
+
+    log::info!(
+        "📦 Paquete {} fallido ({}): {} (intento {}{})",
+        updated.tracking_number,
+        reason.as_str(),
+        action.as_str(),
+        attempts,
+        match (new_date, next_tournee_id) {
+            (Some(date), Some(tournee_id)) => format!(", {} en la tournée {}", date, tournee_id),
+            (Some(date), None) => format!(", {} pendiente de tournée", date),
+            _ => String::new(),
+        }
+    );
+
+    Ok(FailureOutcome {
+        package: updated,
+        action,
+        next_tournee_id,
+        relay_point,
+    })
+}
+
+/// Punto relais activo más cercano con capacidad libre dentro del radio
+pub async fn nearest_relay_point(
+    conn: &mut PgConnection,
+    company_id: Uuid,
+    latitude: f64,
+    longitude: f64,
+    radius_meters: f64,
+) -> AppResult<Option<(RelayPoint, f64)>> {
+    let delta_lat = radius_meters / METERS_PER_DEGREE;
+    let delta_lng = radius_meters / (METERS_PER_DEGREE * latitude.to_radians().cos().abs().max(0.01));
+
+    // Prefiltro por bounding box y capacidad, distancia exacta en Rust y
+    // capacidad confirmada con el punto bloqueado
+    let candidates = sqlx::query_as::<_, RelayPoint>(&format!(
+        r#"
+        SELECT {}
+        FROM relay_points r
+        WHERE company_id = $1
+        AND is_active
+        AND latitude BETWEEN $2 AND $3
+        AND longitude BETWEEN $4 AND $5
+        AND (
+            capacity IS NULL
+            OR capacity > (
+                SELECT COUNT(*) FROM packages p
+                WHERE p.relay_point_id = r.id AND p.deleted_at IS NULL AND p.{}
+            )
+        )
+        "#,
+        RELAY_POINT_COLUMNS, RELAY_OPEN_STATUSES
+    ))
+    .bind(company_id)
+    .bind(latitude - delta_lat)
+    .bind(latitude + delta_lat)
+    .bind(longitude - delta_lng)
+    .bind(longitude + delta_lng)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for (relay, distance) in within_radius(candidates, latitude, longitude, radius_meters) {
+        if has_free_capacity(&mut *conn, &relay).await? {
+            return Ok(Some((relay, distance)));
+        }
+    }
+    Ok(None)
+}
+
+/// Bloquear el punto relais (`FOR UPDATE`) y contar sus paquetes después del
+/// bloqueo, para que dos fallos simultáneos no llenen el último hueco
+async fn has_free_capacity(conn: &mut PgConnection, relay: &RelayPoint) -> AppResult<bool> {
+    let capacity = sqlx::query_scalar::<_, Option<i32>>(
+        "SELECT capacity FROM relay_points WHERE id = $1 AND is_active FOR UPDATE",
+    )
+    .bind(relay.id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let capacity = match capacity {
+        None => return Ok(false),
+        Some(None) => return Ok(true),
+        Some(Some(capacity)) => capacity,
+    };
+
+    let in_use = sqlx::query_scalar::<_, i64>(&format!(
+        "SELECT COUNT(*) FROM packages WHERE relay_point_id = $1 AND deleted_at IS NULL AND {}",
+        RELAY_OPEN_STATUSES
+    ))
+    .bind(relay.id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(in_use < capacity as i64)
+}
+
+/// Candidatos dentro del radio, del más cercano al más lejano
+fn within_radius(
+    candidates: Vec<RelayPoint>,
+    latitude: f64,
+    longitude: f64,
+    radius_meters: f64,
+) -> Vec<(RelayPoint, f64)> {
+    let mut within: Vec<(RelayPoint, f64)> = candidates
+        .into_iter()
+        .map(|relay| {
+            let distance = haversine_meters((latitude, longitude), (relay.latitude, relay.longitude));
+            (relay, distance)
+        })
+        .filter(|(_, distance)| *distance <= radius_meters)
+        .collect();
+    within.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    within
+}
+
+/// Tournée pendiente del día con el mismo número de tournée o, si no, del
+/// mismo chofer que la tournée actual del paquete
+async fn find_next_tournee(conn: &mut PgConnection, package: &Package, date: NaiveDate) -> AppResult<Option<Uuid>> {
+    let tournee_id = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        SELECT t.id
+        FROM tournees t
+        JOIN tournees current ON current.id = $2
+        WHERE t.company_id = $1
+        AND t.tournee_date = $3
+        AND t.tournee_status = 'pending'
+        AND t.deleted_at IS NULL
+        AND t.id <> current.id
+        AND (t.tournee_number = current.tournee_number OR t.driver_id = current.driver_id)
+        ORDER BY (t.tournee_number IS NOT DISTINCT FROM current.tournee_number) DESC, t.created_at
+        LIMIT 1
+        "#,
+    )
+    .bind(package.company_id)
+    .bind(package.tournee_id)
+    .bind(date)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(tournee_id)
+}
+
+/// Mover el paquete a otra tournée como pendiente (salvo que ya tenga ese
+/// número de seguimiento)
+async fn move_to_tournee(conn: &mut PgConnection, package: &Package, tournee_id: Uuid) -> AppResult<Option<Package>> {
+    let moved = sqlx::query_as::<_, Package>(&format!(
+        r#"
+        UPDATE packages SET
+            tournee_id = $2,
+            delivery_status = 'pending',
+            updated_at = NOW()
+        WHERE id = $1
+        AND NOT EXISTS (
+            SELECT 1 FROM packages other
+            WHERE other.tournee_id = $2 AND other.tracking_number = packages.tracking_number
+        )
+        RETURNING {}
+        "#,
+        PACKAGE_COLUMNS
+    ))
+    .bind(package.id)
+    .bind(tournee_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(moved)
+}
+
+/// Reprogramar para el siguiente día laborable los paquetes sin resolver de
+/// una tournée que se cierra (cancelada o finalizada a la fuerza). Devuelve
+/// cada paquete con su estado anterior.
+pub async fn reschedule_open_packages(
+    conn: &mut PgConnection,
+    tournee: &Tournee,
+    note: &str,
+) -> AppResult<Vec<(DeliveryStatus, Package)>> {
+    let open = sqlx::query_as::<_, (Uuid, DeliveryStatus)>(&format!(
+        r#"
+        SELECT id, delivery_status FROM packages
+        WHERE tournee_id = $1 AND deleted_at IS NULL AND {}
+        FOR UPDATE
+        "#,
+        lifecycle::UNRESOLVED_PACKAGES
+    ))
+    .bind(tournee.id)
+    .fetch_all(&mut *conn)
+    .await?;
+    if open.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let date = reschedule_date(paris_today().max(tournee.tournee_date), None)?;
+    let ids: Vec<Uuid> = open.iter().map(|(id, _)| *id).collect();
+    let updated = sqlx::query_as::<_, Package>(&format!(
+        r#"
+        UPDATE packages SET
+            delivery_status = 'failed',
+            next_action = 'reschedule',
+            reschedule_date = $2,
+            failure_notes = $3,
+            status_changed_at = NOW(),
+            updated_at = NOW()
+        WHERE id = ANY($1)
+        RETURNING {}
+        "#,
+        PACKAGE_COLUMNS
+    ))
+    .bind(&ids)
+    .bind(date)
+    .bind(note)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut rescheduled = Vec::with_capacity(updated.len());
+    for package in updated {
+        let previous = open
+            .iter()
+            .find(|(id, _)| *id == package.id)
+            .map(|(_, status)| status.clone())
+            .unwrap_or(DeliveryStatus::Pending);
+        let package = match find_next_tournee(&mut *conn, &package, date).await? {
+            Some(tournee_id) => move_to_tournee(&mut *conn, &package, tournee_id).await?.unwrap_or(package),
+            None => package,
+        };
+        rescheduled.push((previous, package));
+    }
+
+    Ok(rescheduled)
+}
+
+/// Adoptar en una tournée recién creada los paquetes reprogramados para su
+/// fecha que esperaban tournée (mismo número de tournée o mismo chofer)
+pub async fn adopt_rescheduled(conn: &mut PgConnection, tournee: &Tournee) -> AppResult<u64> {
+    let adopted = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        UPDATE packages p SET
+            tournee_id = $1,
+            delivery_status = 'pending',
+            updated_at = NOW()
+        FROM tournees previous
+        WHERE previous.id = p.tournee_id
+        AND p.company_id = $2
+        AND p.delivery_status = 'failed'
+        AND p.next_action IN ('reschedule', 'leave_notice')
+        AND p.reschedule_date = $3
+        AND p.deleted_at IS NULL
+        AND (previous.tournee_number = $4 OR previous.driver_id = $5)
+        AND NOT EXISTS (
+            SELECT 1 FROM packages other
+            WHERE other.tournee_id = $1 AND other.tracking_number = p.tracking_number
+        )
+        RETURNING p.id
+        "#,
+    )
+    .bind(tournee.id)
+    .bind(tournee.company_id)
+    .bind(tournee.tournee_date)
+    .bind(&tournee.tournee_number)
+    .bind(tournee.driver_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    if !adopted.is_empty() {
+        lifecycle::record_packages(
+            &mut *conn,
+            tournee.company_id,
+            &adopted,
+            &DeliveryStatus::Failed,
+            &DeliveryStatus::Pending,
+            "Nueva presentación",
+            serde_json::json!({ "next_tournee_id": tournee.id }),
+        )
+        .await?;
+        log::info!(
+            "📦 {} paquetes reprogramados añadidos a la tournée {}",
+            adopted.len(),
+            tournee.id
+        );
+    }
+    Ok(adopted.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn relay(name: &str, latitude: f64, longitude: f64) -> RelayPoint {
+        RelayPoint {
+            id: Uuid::new_v4(),
+            company_id: Uuid::new_v4(),
+            name: name.to_string(),
+            external_code: None,
+            address: format!("{} Paris", name),
+            latitude,
+            longitude,
+            capacity: None,
+            opening_hours: None,
+            is_active: true,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn test_decide_by_reason_and_attempts() {
+        let policy = FailedDeliveryPolicy::default();
+        use DeliveryFailureReason::*;
+
+        assert_eq!(decide(&RecipientNotHome, 1, &policy), FailureAction::LeaveNotice);
+        assert_eq!(decide(&RecipientNotHome, 2, &policy), FailureAction::RedirectToRelay);
+        assert_eq!(decide(&SecurityRestriction, 2, &policy), FailureAction::RedirectToRelay);
+        assert_eq!(decide(&RecipientNotHome, 3, &policy), FailureAction::ReturnToSender);
+        assert_eq!(decide(&RefusedDelivery, 1, &policy), FailureAction::ReturnToSender);
+        assert_eq!(decide(&WrongAddress, 1, &policy), FailureAction::ReturnToSender);
+        assert_eq!(decide(&PackageDamaged, 1, &policy), FailureAction::ReturnToSender);
+        assert_eq!(decide(&VehicleBreakdown, 1, &policy), FailureAction::Reschedule);
+        assert_eq!(decide(&WeatherConditions, 3, &policy), FailureAction::ReturnToSender);
+    }
+
+    #[test]
+    fn test_carrier_failures_do_not_count() {
+        assert!(counts_as_attempt(&DeliveryFailureReason::RecipientNotHome));
+        assert!(!counts_as_attempt(&DeliveryFailureReason::VehicleBreakdown));
+        assert!(!counts_as_attempt(&DeliveryFailureReason::DriverEmergency));
+    }
+
+    #[test]
+    fn test_reschedule_date() {
+        // Sábado 8 de mayo de 2027 es festivo; el domingo 9 tampoco se reparte
+        assert_eq!(reschedule_date(date(2027, 5, 7), None).unwrap(), date(2027, 5, 10));
+        // Fecha pedida laborable
+        assert_eq!(reschedule_date(date(2025, 3, 3), Some(date(2025, 3, 6))).unwrap(), date(2025, 3, 6));
+        // Fecha pedida en domingo -> lunes
+        assert_eq!(reschedule_date(date(2025, 3, 3), Some(date(2025, 3, 9))).unwrap(), date(2025, 3, 10));
+        // Fecha pasada
+        assert!(reschedule_date(date(2025, 3, 3), Some(date(2025, 3, 3))).is_err());
+    }
+
+    #[test]
+    fn test_within_radius_sorted_by_distance() {
+        let here = (48.8917, 2.3446);
+        let near = relay("Tabac Marcadet", 48.8921, 2.3460);
+        let far = relay("Carrefour City", 48.8840, 2.3490);
+        let outside = relay("Relais Lyon", 45.7640, 4.8357);
+
+        let within = within_radius(vec![far.clone(), near.clone(), outside], here.0, here.1, 5_000.0);
+        assert_eq!(within.len(), 2);
+        let (chosen, distance) = &within[0];
+        assert_eq!(chosen.id, near.id);
+        assert!(*distance < 200.0);
+        assert_eq!(within[1].0.id, far.id);
+
+        assert!(within_radius(vec![relay("Relais Lyon", 45.7640, 4.8357)], here.0, here.1, 5_000.0).is_empty());
+    }
+}
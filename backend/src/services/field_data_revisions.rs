@@ -0,0 +1,367 @@
+//! Historial de revisiones de driver_field_data
+//!
+//! Cada creación, actualización, reversión o borrado de una entrada queda
+//! registrado como una revisión inmutable con autor, fecha, diff y el estado
+//! completo resultante. El número de revisión coincide con
+//! `driver_field_data.version`, que se usa para la concurrencia optimista.
+//!
+//! Los campos sensibles se guardan en el historial tal como están en la tabla
+//! (cifrados) y se ocultan al listar el historial.
+
+use serde_json::{json, Map, Value};
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::models::driver_field_data::{DriverFieldData, DriverFieldDataRevision, RevisionChangeType};
+use crate::services::field_encryption::FieldCipher;
+use crate::utils::errors::{AppError, AppResult};
+
+/// Campos de contenido que se comparan entre revisiones
+pub const TRACKED_FIELDS: &[&str] = &[
+    "address",
+    "postal_code",
+    "city",
+    "latitude",
+    "longitude",
+    "door_codes",
+    "access_instructions",
+    "security_notes",
+    "mailbox_location",
+    "mailbox_working",
+    "mailbox_issues",
+    "preferred_delivery_time",
+    "delivery_restrictions",
+    "special_instructions",
+    "confidence_score",
+    "data_source",
+    "deleted_at",
+];
+
+/// Campos cifrados (nunca se muestran en el historial)
+pub const SENSITIVE_FIELDS: &[&str] = &["door_codes", "access_instructions", "security_notes"];
+
+/// Marcador de los valores sensibles ocultos
+const REDACTED: &str = "[cifrado]";
+
+const REVISION_COLUMNS: &str = r#"
+    id, company_id, field_data_id, revision_number, change_type, author_id,
+    diff, snapshot, reverted_from_revision, verified_by, verified_at, created_at
+"#;
+
+/// Diferencias entre dos estados: `{"campo": {"old": ..., "new": ...}}`
+pub fn diff(before: Option<&DriverFieldData>, after: &DriverFieldData) -> AppResult<Map<String, Value>> {
+    let before = before.map(serde_json::to_value).transpose().map_err(json_error)?;
+    let after = serde_json::to_value(after).map_err(json_error)?;
+
+    let mut changes = Map::new();
+    for field in TRACKED_FIELDS {
+        let old = before.as_ref().and_then(|b| b.get(*field)).cloned().unwrap_or(Value::Null);
+        let new = after.get(*field).cloned().unwrap_or(Value::Null);
+        if old != new {
+            changes.insert(field.to_string(), json!({ "old": old, "new": new }));
+        }
+    }
+    Ok(changes)
+}
+
+/// Registrar una revisión (dentro de la transacción del cambio)
+pub async fn record(
+    conn: &mut PgConnection,
+    entry: &DriverFieldData,
+    author_id: Uuid,
+    change_type: RevisionChangeType,
+    changes: Map<String, Value>,
+    reverted_from_revision: Option<i32>,
+) -> AppResult<DriverFieldDataRevision> {
+    let snapshot = serde_json::to_value(entry).map_err(json_error)?;
+
+    let revision = sqlx::query_as::<_, DriverFieldDataRevision>(&format!(
+        r#"
+        INSERT INTO driver_field_data_revisions (
+            company_id, field_data_id, revision_number, change_type, author_id,
+            diff, snapshot, reverted_from_revision
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING {}
+        "#,
+        REVISION_COLUMNS
+    ))
+    .bind(entry.company_id)
+    .bind(entry.id)
+    .bind(entry.version)
+    .bind(change_type.as_str())
+    .bind(author_id)
+    .bind(Value::Object(changes))
+    .bind(snapshot)
+    .bind(reverted_from_revision)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(revision)
+}
+
+/// Historial de una entrada (más recientes primero)
+pub async fn history(pool: &PgPool, company_id: Uuid, field_data_id: Uuid) -> AppResult<Vec<DriverFieldDataRevision>> {
+    let revisions = sqlx::query_as::<_, DriverFieldDataRevision>(&format!(
+        r#"
+        SELECT {}
+        FROM driver_field_data_revisions
+        WHERE company_id = $1 AND field_data_id = $2
+        ORDER BY revision_number DESC
+        "#,
+        REVISION_COLUMNS
+    ))
+    .bind(company_id)
+    .bind(field_data_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(revisions)
+}
+
+/// Obtener una revisión concreta
+pub async fn get(
+    conn: &mut PgConnection,
+    company_id: Uuid,
+    field_data_id: Uuid,
+    revision_number: i32,
+) -> AppResult<DriverFieldDataRevision> {
+    sqlx::query_as::<_, DriverFieldDataRevision>(&format!(
+        r#"
+        SELECT {}
+        FROM driver_field_data_revisions
+        WHERE company_id = $1 AND field_data_id = $2 AND revision_number = $3
+        "#,
+        REVISION_COLUMNS
+    ))
+    .bind(company_id)
+    .bind(field_data_id)
+    .bind(revision_number)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("Revisión {} no encontrada", revision_number)))
+}
+
+/// Marcar una revisión como verificada
+///
+/// Si es la revisión vigente, cuenta además como una verificación de la
+/// entrada (sube `verification_count` y su posición en los lookups).
+pub async fn verify(
+    pool: &PgPool,
+    company_id: Uuid,
+    field_data_id: Uuid,
+    revision_number: i32,
+    verified_by: Uuid,
+) -> AppResult<DriverFieldDataRevision> {
+    let mut tx = pool.begin().await?;
+
+    let revision = get(&mut tx, company_id, field_data_id, revision_number).await?;
+    if revision.verified_at.is_some() {
+        return Err(AppError::Conflict(format!("La revisión {} ya está verificada", revision_number)));
+    }
+
+    let revision = sqlx::query_as::<_, DriverFieldDataRevision>(&format!(
+        r#"
+        UPDATE driver_field_data_revisions
+        SET verified_by = $2, verified_at = NOW()
+        WHERE id = $1 AND verified_at IS NULL
+        RETURNING {}
+        "#,
+        REVISION_COLUMNS
+    ))
+    .bind(revision.id)
+    .bind(verified_by)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        UPDATE driver_field_data
+        SET verification_count = COALESCE(verification_count, 0) + 1,
+            last_verified_date = CURRENT_DATE
+        WHERE id = $1 AND company_id = $2 AND version = $3 AND deleted_at IS NULL
+        "#,
+    )
+    .bind(field_data_id)
+    .bind(company_id)
+    .bind(revision_number)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(revision)
+}
+
+/// Ocultar los valores sensibles de una revisión (se indica solo si cambiaron)
+pub fn redact(revision: &mut DriverFieldDataRevision) {
+    let hide = |value: &mut Value| {
+        if !value.is_null() {
+            *value = Value::String(REDACTED.to_string());
+        }
+    };
+
+    for field in SENSITIVE_FIELDS {
+        if let Some(change) = revision.diff.get_mut(*field) {
+            for side in ["old", "new"] {
+                if let Some(value) = change.get_mut(side) {
+                    hide(value);
+                }
+            }
+        }
+        if let Some(value) = revision.snapshot.get_mut(*field) {
+            hide(value);
+        }
+    }
+}
+
+/// Re-cifrar con la clave activa los valores sensibles del historial
+///
+/// Compara con los valores leídos para no pisar una rotación concurrente.
+pub async fn rotate_encryption(pool: &PgPool, cipher: &FieldCipher, company_id: Uuid) -> AppResult<usize> {
+    let rows = sqlx::query_as::<_, (Uuid, Value, Value)>(
+        "SELECT id, diff, snapshot FROM driver_field_data_revisions WHERE company_id = $1",
+    )
+    .bind(company_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut rotated = 0;
+    for (id, original_diff, original_snapshot) in rows {
+        let (mut changes, mut snapshot) = (original_diff.clone(), original_snapshot.clone());
+        let mut changed = false;
+        for field in SENSITIVE_FIELDS {
+            if let Some(change) = changes.get_mut(*field) {
+                for side in ["old", "new"] {
+                    if let Some(value) = change.get_mut(side) {
+                        changed |= rewrap_value(cipher, value)?;
+                    }
+                }
+            }
+            if let Some(value) = snapshot.get_mut(*field) {
+                changed |= rewrap_value(cipher, value)?;
+            }
+        }
+
+        if changed {
+            let updated = sqlx::query(
+                r#"
+                UPDATE driver_field_data_revisions SET diff = $2, snapshot = $3
+                WHERE id = $1 AND diff = $4 AND snapshot = $5
+                "#,
+            )
+            .bind(id)
+            .bind(changes)
+            .bind(snapshot)
+            .bind(original_diff)
+            .bind(original_snapshot)
+            .execute(pool)
+            .await?;
+            if updated.rows_affected() > 0 {
+                rotated += 1;
+            }
+        }
+    }
+
+    Ok(rotated)
+}
+
+fn rewrap_value(cipher: &FieldCipher, value: &mut Value) -> AppResult<bool> {
+    let Some(stored) = value.as_str() else {
+        return Ok(false);
+    };
+    if !cipher.needs_rewrap(stored) {
+        return Ok(false);
+    }
+
+    let rewrapped = cipher.rewrap(stored).map_err(|e| AppError::Internal(e.to_string()))?;
+    *value = Value::String(rewrapped);
+    Ok(true)
+}
+
+fn json_error(e: serde_json::Error) -> AppError {
+    AppError::Internal(format!("Error serializando revisión: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> DriverFieldData {
+        serde_json::from_value(json!({
+            "id": Uuid::nil(),
+            "company_id": Uuid::nil(),
+            "driver_id": Uuid::nil(),
+            "address": "12 RUE MARCADET, 75018 PARIS",
+            "normalized_address": null,
+            "postal_code": "75018",
+            "city": "PARIS",
+            "latitude": null,
+            "longitude": null,
+            "door_codes": "enc:v1:k1:aaa:bbb",
+            "access_instructions": null,
+            "security_notes": null,
+            "mailbox_location": null,
+            "mailbox_working": true,
+            "mailbox_issues": null,
+            "preferred_delivery_time": null,
+            "delivery_restrictions": null,
+            "special_instructions": null,
+            "confidence_score": 3,
+            "data_source": "driver_input",
+            "verification_count": 1,
+            "last_updated_by": null,
+            "last_verified_date": null,
+            "version": 1,
+            "created_at": null,
+            "updated_at": null,
+            "deleted_at": null
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_diff_only_tracked_changes() {
+        let before = entry();
+        let mut after = entry();
+        after.version = 2;
+        after.mailbox_working = Some(false);
+        after.door_codes = Some("enc:v1:k1:ccc:ddd".to_string());
+
+        let changes = diff(Some(&before), &after).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes["mailbox_working"], json!({ "old": true, "new": false }));
+        assert!(changes.contains_key("door_codes"));
+
+        // Creación: todos los campos con valor aparecen como nuevos
+        let created = diff(None, &before).unwrap();
+        assert_eq!(created["address"]["old"], Value::Null);
+        assert!(!created.contains_key("special_instructions"));
+    }
+
+    #[test]
+    fn test_redact_hides_sensitive_values() {
+        let before = entry();
+        let mut after = entry();
+        after.door_codes = None;
+
+        let mut revision = DriverFieldDataRevision {
+            id: Uuid::nil(),
+            company_id: Uuid::nil(),
+            field_data_id: Uuid::nil(),
+            revision_number: 2,
+            change_type: "update".to_string(),
+            author_id: None,
+            diff: Value::Object(diff(Some(&before), &after).unwrap()),
+            snapshot: serde_json::to_value(&before).unwrap(),
+            reverted_from_revision: None,
+            verified_by: None,
+            verified_at: None,
+            created_at: None,
+        };
+        redact(&mut revision);
+
+        assert_eq!(revision.diff["door_codes"], json!({ "old": REDACTED, "new": null }));
+        assert_eq!(revision.snapshot["door_codes"], json!(REDACTED));
+        assert_eq!(revision.snapshot["city"], json!("PARIS"));
+    }
+}
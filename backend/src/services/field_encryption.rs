@@ -0,0 +1,266 @@
+//! Cifrado a nivel de campo (envelope encryption)
+//!
+//! Los campos sensibles de `driver_field_data` (códigos de puerta, instrucciones
+//! de acceso, notas de seguridad) se guardan cifrados. Cada valor se cifra con
+//! una clave de datos (DEK) aleatoria con AES-256-GCM; la DEK se cifra a su vez
+//! con la clave maestra (KEK) activa de la configuración.
+//!
+//! Formato almacenado: `enc:v1:<kek_id>:<dek cifrada>:<valor cifrado>` (base64,
+//! nonce de 12 bytes como prefijo). Rotar la KEK solo requiere re-cifrar las
+//! DEK (`FieldCipher::rewrap`), no los valores.
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use std::collections::HashMap;
+
+/// Prefijo de los valores cifrados
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+/// Errores de cifrado de campos
+#[derive(Debug, thiserror::Error)]
+pub enum FieldEncryptionError {
+    #[error("Configuración de claves inválida: {0}")]
+    InvalidConfig(String),
+    #[error("Clave maestra desconocida: {0}")]
+    UnknownKey(String),
+    #[error("Valor cifrado mal formado")]
+    Malformed,
+    #[error("No se pudo cifrar el valor")]
+    Encrypt,
+    #[error("No se pudo descifrar el valor")]
+    Decrypt,
+}
+
+/// Cifrador de campos con soporte de varias claves maestras
+#[derive(Clone)]
+pub struct FieldCipher {
+    keys: HashMap<String, Aes256Gcm>,
+    active_key_id: String,
+}
+
+impl std::fmt::Debug for FieldCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FieldCipher")
+            .field("key_ids", &self.keys.keys().collect::<Vec<_>>())
+            .field("active_key_id", &self.active_key_id)
+            .finish()
+    }
+}
+
+impl FieldCipher {
+    /// Crear el cifrador desde `FIELD_ENCRYPTION_KEYS` (`id:base64,id2:base64`)
+    /// y la clave activa. Si no se indica clave activa se usa la última.
+    pub fn from_spec(spec: &str, active_key_id: Option<&str>) -> Result<Self, FieldEncryptionError> {
+        let mut keys = HashMap::new();
+        let mut last_id = None;
+
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (id, encoded) = entry
+                .split_once(':')
+                .ok_or_else(|| FieldEncryptionError::InvalidConfig(format!("entrada sin id: '{}'", entry)))?;
+            let id = id.trim();
+            if id.is_empty() || id.contains(':') {
+                return Err(FieldEncryptionError::InvalidConfig("id de clave vacío".to_string()));
+            }
+
+            let bytes = BASE64
+                .decode(encoded.trim())
+                .map_err(|_| FieldEncryptionError::InvalidConfig(format!("clave '{}' no es base64", id)))?;
+            if bytes.len() != KEY_LEN {
+                return Err(FieldEncryptionError::InvalidConfig(format!(
+                    "clave '{}' debe tener {} bytes",
+                    id, KEY_LEN
+                )));
+            }
+
+            keys.insert(id.to_string(), Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&bytes)));
+            last_id = Some(id.to_string());
+        }
+
+        let active_key_id = match active_key_id.map(str::trim).filter(|id| !id.is_empty()) {
+            Some(id) => id.to_string(),
+            None => last_id.ok_or_else(|| FieldEncryptionError::InvalidConfig("no hay claves".to_string()))?,
+        };
+
+        if !keys.contains_key(&active_key_id) {
+            return Err(FieldEncryptionError::UnknownKey(active_key_id));
+        }
+
+        Ok(Self { keys, active_key_id })
+    }
+
+    /// Id de la clave maestra activa
+    pub fn active_key_id(&self) -> &str {
+        &self.active_key_id
+    }
+
+    /// Indica si un valor almacenado está cifrado
+    #[cfg(test)]
+    pub fn is_encrypted(value: &str) -> bool {
+        value.starts_with(ENCRYPTED_PREFIX)
+    }
+
+    /// Cifrar un valor con una DEK nueva envuelta con la KEK activa
+    pub fn encrypt(&self, plaintext: &str) -> Result<String, FieldEncryptionError> {
+        let dek_bytes = Aes256Gcm::generate_key(&mut OsRng);
+        let dek = Aes256Gcm::new(&dek_bytes);
+
+        let value = seal(&dek, plaintext.as_bytes())?;
+        let wrapped_dek = seal(&self.keys[&self.active_key_id], dek_bytes.as_slice())?;
+
+        Ok(format!(
+            "{}{}:{}:{}",
+            ENCRYPTED_PREFIX,
+            self.active_key_id,
+            BASE64.encode(wrapped_dek),
+            BASE64.encode(value)
+        ))
+    }
+
+    /// Descifrar un valor. Los valores heredados sin cifrar se devuelven tal cual.
+    pub fn decrypt(&self, stored: &str) -> Result<String, FieldEncryptionError> {
+        let Some((key_id, wrapped_dek, value)) = parse(stored)? else {
+            return Ok(stored.to_string());
+        };
+
+        let dek = self.unwrap_dek(key_id, &wrapped_dek)?;
+        let plaintext = open(&dek, &value)?;
+        String::from_utf8(plaintext).map_err(|_| FieldEncryptionError::Decrypt)
+    }
+
+    /// Re-envolver la DEK con la clave activa (rotación). Los valores sin
+    /// cifrar se cifran; los que ya usan la clave activa no cambian.
+    pub fn rewrap(&self, stored: &str) -> Result<String, FieldEncryptionError> {
+        let Some((key_id, wrapped_dek, value)) = parse(stored)? else {
+            return self.encrypt(stored);
+        };
+
+        if key_id == self.active_key_id {
+            return Ok(stored.to_string());
+        }
+
+        let dek_bytes = open(self.kek(key_id)?, &wrapped_dek)?;
+        let rewrapped = seal(&self.keys[&self.active_key_id], &dek_bytes)?;
+
+        Ok(format!(
+            "{}{}:{}:{}",
+            ENCRYPTED_PREFIX,
+            self.active_key_id,
+            BASE64.encode(rewrapped),
+            BASE64.encode(value)
+        ))
+    }
+
+    /// Indica si un valor almacenado necesita rotación
+    pub fn needs_rewrap(&self, stored: &str) -> bool {
+        match parse(stored) {
+            Ok(Some((key_id, _, _))) => key_id != self.active_key_id,
+            Ok(None) => true,
+            Err(_) => false,
+        }
+    }
+
+    fn kek(&self, key_id: &str) -> Result<&Aes256Gcm, FieldEncryptionError> {
+        self.keys
+            .get(key_id)
+            .ok_or_else(|| FieldEncryptionError::UnknownKey(key_id.to_string()))
+    }
+
+    fn unwrap_dek(&self, key_id: &str, wrapped_dek: &[u8]) -> Result<Aes256Gcm, FieldEncryptionError> {
+        let dek_bytes = open(self.kek(key_id)?, wrapped_dek)?;
+        if dek_bytes.len() != KEY_LEN {
+            return Err(FieldEncryptionError::Malformed);
+        }
+        Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&dek_bytes)))
+    }
+}
+
+/// Partes de un valor cifrado: id de la KEK, DEK envuelta y valor cifrado
+type SealedParts<'a> = (&'a str, Vec<u8>, Vec<u8>);
+
+/// Separar `enc:v1:<kek_id>:<dek>:<valor>`; `None` si el valor no está cifrado
+fn parse(stored: &str) -> Result<Option<SealedParts<'_>>, FieldEncryptionError> {
+    let Some(rest) = stored.strip_prefix(ENCRYPTED_PREFIX) else {
+        return Ok(None);
+    };
+
+    let mut parts = rest.splitn(3, ':');
+    let (Some(key_id), Some(dek), Some(value)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(FieldEncryptionError::Malformed);
+    };
+
+    let dek = BASE64.decode(dek).map_err(|_| FieldEncryptionError::Malformed)?;
+    let value = BASE64.decode(value).map_err(|_| FieldEncryptionError::Malformed)?;
+    Ok(Some((key_id, dek, value)))
+}
+
+/// Cifrar con nonce aleatorio como prefijo
+fn seal(cipher: &Aes256Gcm, plaintext: &[u8]) -> Result<Vec<u8>, FieldEncryptionError> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| FieldEncryptionError::Encrypt)?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    Ok(sealed)
+}
+
+fn open(cipher: &Aes256Gcm, sealed: &[u8]) -> Result<Vec<u8>, FieldEncryptionError> {
+    if sealed.len() <= NONCE_LEN {
+        return Err(FieldEncryptionError::Malformed);
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| FieldEncryptionError::Decrypt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(byte: u8) -> String {
+        BASE64.encode([byte; KEY_LEN])
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_roundtrip() {
+        let cipher = FieldCipher::from_spec(&format!("k1:{}", key(1)), None).unwrap();
+        let stored = cipher.encrypt("A1234B").unwrap();
+
+        assert!(FieldCipher::is_encrypted(&stored));
+        assert!(!stored.contains("A1234B"));
+        assert_eq!(cipher.decrypt(&stored).unwrap(), "A1234B");
+        // Valores heredados sin cifrar
+        assert_eq!(cipher.decrypt("1234").unwrap(), "1234");
+    }
+
+    #[test]
+    fn test_key_rotation_rewraps_without_losing_data() {
+        let old = FieldCipher::from_spec(&format!("k1:{}", key(1)), None).unwrap();
+        let stored = old.encrypt("Porte code 4589").unwrap();
+
+        let rotated = FieldCipher::from_spec(&format!("k1:{},k2:{}", key(1), key(2)), Some("k2")).unwrap();
+        assert!(rotated.needs_rewrap(&stored));
+        let rewrapped = rotated.rewrap(&stored).unwrap();
+        assert!(rewrapped.starts_with("enc:v1:k2:"));
+        assert!(!rotated.needs_rewrap(&rewrapped));
+
+        // Tras retirar la clave antigua, el valor sigue siendo legible
+        let only_new = FieldCipher::from_spec(&format!("k2:{}", key(2)), None).unwrap();
+        assert_eq!(only_new.decrypt(&rewrapped).unwrap(), "Porte code 4589");
+        assert!(matches!(only_new.decrypt(&stored), Err(FieldEncryptionError::UnknownKey(_))));
+    }
+
+    #[test]
+    fn test_invalid_spec() {
+        assert!(FieldCipher::from_spec("", None).is_err());
+        assert!(FieldCipher::from_spec("k1:not-base64!", None).is_err());
+        assert!(FieldCipher::from_spec(&format!("k1:{}", BASE64.encode([0u8; 16])), None).is_err());
+        assert!(FieldCipher::from_spec(&format!("k1:{}", key(1)), Some("k9")).is_err());
+    }
+}
@@ -0,0 +1,407 @@
+//! Conocimiento de terreno adjunto a los paquetes
+//!
+//! Combina lo que envía el transportista (digicode, côte, comentarios,
+//! horarios) con la información de `driver_field_data` de la empresa para
+//! cada paquete de la tournée. Los digicodes se deduplican y cada valor indica
+//! su origen (`carrier` y/o `driver`).
+
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::client::ColisDetailData;
+use crate::models::driver_field_data::{DriverFieldData, DriverFieldDataLookup};
+use crate::services::driver_field_data_service;
+use crate::services::field_encryption::FieldCipher;
+use crate::utils::errors::AppResult;
+
+/// Origen de un valor de terreno
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldValueSource {
+    Carrier,
+    Driver,
+}
+
+/// Valor de terreno con sus orígenes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourcedValue {
+    pub value: String,
+    pub sources: Vec<FieldValueSource>,
+    /// Entrada de `driver_field_data` de la que proviene (si aplica)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field_data_id: Option<Uuid>,
+}
+
+impl SourcedValue {
+    fn carrier(value: &str) -> Self {
+        Self {
+            value: value.trim().to_string(),
+            sources: vec![FieldValueSource::Carrier],
+            field_data_id: None,
+        }
+    }
+
+    fn driver(value: &str, field_data_id: Uuid) -> Self {
+        Self {
+            value: value.trim().to_string(),
+            sources: vec![FieldValueSource::Driver],
+            field_data_id: Some(field_data_id),
+        }
+    }
+}
+
+/// Datos de terreno enviados por el transportista para un paquete
+#[derive(Debug, Clone, Default)]
+pub struct CarrierFieldHints {
+    pub digicode: Option<String>,
+    pub cote: Option<String>,
+    pub commentaire: Option<String>,
+    pub horaires: Option<String>,
+}
+
+impl CarrierFieldHints {
+    /// Extraer los datos de terreno de un elemento de `LstLieuArticle`
+    pub fn from_carrier_json(package: &serde_json::Value) -> Self {
+        let first = |keys: &[&str]| -> Option<String> {
+            keys.iter()
+                .filter_map(|key| package.get(*key).and_then(|v| v.as_str()))
+                .map(str::trim)
+                .find(|v| !v.is_empty())
+                .map(str::to_string)
+        };
+
+        Self {
+            digicode: first(&["digicode", "Digicode", "digicodeDestinataire", "DigicodeDestinataire"]),
+            cote: first(&["cote", "Cote", "coteDestinataire"]),
+            commentaire: first(&["commentaire", "Commentaire", "commentaireLivraison", "commentaireDestinataire"]),
+            horaires: first(&["horaires", "Horaires", "horairesLivraison"]),
+        }
+    }
+
+    /// Extraer los datos de terreno de la respuesta del API detalle
+    pub fn from_detail(detail: &ColisDetailData) -> Self {
+        let non_empty = |value: &Option<String>| {
+            value.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(str::to_string)
+        };
+
+        let horaires = detail.horaires_livraison.as_ref().and_then(|h| {
+            let range = match (non_empty(&h.debut), non_empty(&h.fin)) {
+                (Some(debut), Some(fin)) => format!("{}-{}", debut, fin),
+                (Some(debut), None) => format!("à partir de {}", debut),
+                (None, Some(fin)) => format!("avant {}", fin),
+                (None, None) => return None,
+            };
+            Some(match h.jours_semaine.as_ref().filter(|days| !days.is_empty()) {
+                Some(days) => format!("{} {}", days.join(","), range),
+                None => range,
+            })
+        });
+
+        Self {
+            digicode: None,
+            cote: non_empty(&detail.instructions_livraison),
+            commentaire: non_empty(&detail.commentaires),
+            horaires,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.digicode.is_none() && self.cote.is_none() && self.commentaire.is_none() && self.horaires.is_none()
+    }
+}
+
+/// Conocimiento de terreno de un paquete
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PackageFieldKnowledge {
+    pub door_codes: Vec<SourcedValue>,
+    pub access_instructions: Vec<SourcedValue>,
+    pub mailbox_notes: Vec<SourcedValue>,
+    pub preferred_delivery_times: Vec<SourcedValue>,
+    pub notes: Vec<SourcedValue>,
+    /// Verificaciones de la entrada de chofer usada
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub driver_verification_count: Option<i32>,
+}
+
+impl PackageFieldKnowledge {
+    pub fn is_empty(&self) -> bool {
+        self.door_codes.is_empty()
+            && self.access_instructions.is_empty()
+            && self.mailbox_notes.is_empty()
+            && self.preferred_delivery_times.is_empty()
+            && self.notes.is_empty()
+    }
+}
+
+/// Usuario para el que se prepara la información
+#[derive(Debug, Clone, Copy)]
+pub struct FieldKnowledgeViewer {
+    pub user_id: Uuid,
+    pub company_id: Uuid,
+    /// Solo los choferes de la empresa ven los datos de acceso descifrados
+    pub is_driver: bool,
+}
+
+/// Paquete a enriquecer
+#[derive(Debug, Clone, Default)]
+pub struct FieldKnowledgeQuery {
+    pub address: String,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub carrier: CarrierFieldHints,
+}
+
+/// Separar un campo de códigos en valores individuales
+fn split_codes(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split([',', ';', '/', '|', '\n'])
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+}
+
+/// Forma canónica de un código para comparar (sin espacios ni separadores)
+fn code_key(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+/// Añadir un valor, fusionando orígenes si ya existe uno equivalente
+fn push_dedup(values: &mut Vec<SourcedValue>, candidate: SourcedValue, key: impl Fn(&str) -> String) {
+    let candidate_key = key(&candidate.value);
+    if candidate_key.is_empty() {
+        return;
+    }
+
+    if let Some(existing) = values.iter_mut().find(|v| key(&v.value) == candidate_key) {
+        for source in candidate.sources {
+            if !existing.sources.contains(&source) {
+                existing.sources.push(source);
+            }
+        }
+        existing.field_data_id = existing.field_data_id.or(candidate.field_data_id);
+    } else {
+        values.push(candidate);
+    }
+}
+
+fn text_key(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ").to_uppercase()
+}
+
+/// Combinar datos del transportista y del chofer
+///
+/// `driver` debe venir ya descifrado (o con los datos de acceso ocultos).
+pub fn merge_field_knowledge(
+    carrier: &CarrierFieldHints,
+    driver: Option<&DriverFieldData>,
+) -> Option<PackageFieldKnowledge> {
+    if carrier.is_empty() && driver.is_none() {
+        return None;
+    }
+
+    let mut knowledge = PackageFieldKnowledge::default();
+
+    if let Some(digicode) = &carrier.digicode {
+        for code in split_codes(digicode) {
+            push_dedup(&mut knowledge.door_codes, SourcedValue::carrier(code), code_key);
+        }
+    }
+    if let Some(horaires) = &carrier.horaires {
+        push_dedup(&mut knowledge.preferred_delivery_times, SourcedValue::carrier(horaires), text_key);
+    }
+    for note in [&carrier.cote, &carrier.commentaire].into_iter().flatten() {
+        push_dedup(&mut knowledge.notes, SourcedValue::carrier(note), text_key);
+    }
+
+    if let Some(entry) = driver {
+        if let Some(door_codes) = &entry.door_codes {
+            for code in split_codes(door_codes) {
+                push_dedup(&mut knowledge.door_codes, SourcedValue::driver(code, entry.id), code_key);
+            }
+        }
+        for instructions in [&entry.access_instructions, &entry.security_notes].into_iter().flatten() {
+            push_dedup(&mut knowledge.access_instructions, SourcedValue::driver(instructions, entry.id), text_key);
+        }
+
+        let mailbox = match (&entry.mailbox_location, entry.mailbox_working, &entry.mailbox_issues) {
+            (None, None, None) => None,
+            (location, working, issues) => {
+                let mut parts = Vec::new();
+                if let Some(location) = location {
+                    parts.push(location.trim().to_string());
+                }
+                if working == Some(false) {
+                    parts.push("buzón fuera de servicio".to_string());
+                }
+                if let Some(issues) = issues {
+                    parts.push(issues.trim().to_string());
+                }
+                Some(parts.join(" - "))
+            }
+        };
+        if let Some(mailbox) = mailbox.filter(|m| !m.is_empty()) {
+            push_dedup(&mut knowledge.mailbox_notes, SourcedValue::driver(&mailbox, entry.id), text_key);
+        }
+
+        if let Some(preferred) = &entry.preferred_delivery_time {
+            push_dedup(&mut knowledge.preferred_delivery_times, SourcedValue::driver(preferred, entry.id), text_key);
+        }
+        for note in [&entry.delivery_restrictions, &entry.special_instructions].into_iter().flatten() {
+            push_dedup(&mut knowledge.notes, SourcedValue::driver(note, entry.id), text_key);
+        }
+
+        knowledge.driver_verification_count = entry.verification_count;
+    }
+
+    (!knowledge.is_empty() || knowledge.driver_verification_count.is_some()).then_some(knowledge)
+}
+
+/// Buscar y combinar el conocimiento de terreno de una lista de paquetes
+///
+/// Los datos de acceso de los choferes solo se descifran si `viewer` es un
+/// chofer de la empresa; cada código de puerta entregado queda auditado.
+pub async fn attach_field_knowledge(
+    pool: &PgPool,
+    cipher: Option<&FieldCipher>,
+    viewer: Option<FieldKnowledgeViewer>,
+    packages: &[FieldKnowledgeQuery],
+    access_context: &str,
+) -> AppResult<Vec<Option<PackageFieldKnowledge>>> {
+    let mut results = Vec::with_capacity(packages.len());
+    let mut door_code_reads = Vec::new();
+
+    let driver_entries = match viewer {
+        Some(viewer) => {
+            let lookups: Vec<DriverFieldDataLookup> = packages
+                .iter()
+                .map(|package| DriverFieldDataLookup {
+                    address: Some(package.address.clone()),
+                    latitude: package.latitude,
+                    longitude: package.longitude,
+                    radius_meters: None,
+                })
+                .collect();
+            driver_field_data_service::lookup_many(pool, viewer.company_id, &lookups).await?
+        }
+        None => vec![None; packages.len()],
+    };
+
+    for (package, found) in packages.iter().zip(driver_entries) {
+        let mut driver_entry = found.map(|found| found.entry);
+
+        if let (Some(entry), Some(viewer)) = (driver_entry.as_mut(), viewer) {
+            match cipher {
+                Some(cipher) if viewer.is_driver && entry.company_id == viewer.company_id => {
+                    driver_field_data_service::reveal_sensitive(cipher, entry)?;
+                    if entry.door_codes.is_some() && !door_code_reads.contains(&entry.id) {
+                        door_code_reads.push(entry.id);
+                    }
+                }
+                _ => driver_field_data_service::redact_sensitive(entry),
+            }
+        }
+
+        results.push(merge_field_knowledge(&package.carrier, driver_entry.as_ref()));
+    }
+
+    if let Some(viewer) = viewer {
+        driver_field_data_service::log_door_code_reads(
+            pool,
+            viewer.company_id,
+            viewer.user_id,
+            &door_code_reads,
+            access_context,
+        )
+        .await?;
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn driver_entry(door_codes: Option<&str>) -> DriverFieldData {
+        DriverFieldData {
+            id: Uuid::new_v4(),
+            company_id: Uuid::nil(),
+            driver_id: Uuid::nil(),
+            address: "12 RUE MARCADET, 75018 PARIS".to_string(),
+            normalized_address: None,
+            postal_code: None,
+            city: None,
+            latitude: None,
+            longitude: None,
+            door_codes: door_codes.map(str::to_string),
+            access_instructions: Some("Porte au fond de la cour".to_string()),
+            security_notes: None,
+            mailbox_location: Some("Hall B".to_string()),
+            mailbox_working: Some(false),
+            mailbox_issues: None,
+            preferred_delivery_time: Some("après 18h".to_string()),
+            delivery_restrictions: None,
+            special_instructions: None,
+            confidence_score: Some(4),
+            data_source: None,
+            verification_count: Some(2),
+            last_updated_by: None,
+            last_verified_date: None,
+            version: 1,
+            created_at: None,
+            updated_at: None,
+            deleted_at: None,
+        }
+    }
+
+    #[test]
+    fn test_merge_dedupes_carrier_and_driver_digicodes() {
+        let carrier = CarrierFieldHints {
+            digicode: Some("A 1234".to_string()),
+            ..Default::default()
+        };
+        let entry = driver_entry(Some("a1234; 5678B"));
+
+        let knowledge = merge_field_knowledge(&carrier, Some(&entry)).unwrap();
+        assert_eq!(knowledge.door_codes.len(), 2);
+        assert_eq!(knowledge.door_codes[0].value, "A 1234");
+        assert_eq!(
+            knowledge.door_codes[0].sources,
+            vec![FieldValueSource::Carrier, FieldValueSource::Driver]
+        );
+        assert_eq!(knowledge.door_codes[1].sources, vec![FieldValueSource::Driver]);
+        assert_eq!(knowledge.door_codes[1].field_data_id, Some(entry.id));
+        assert_eq!(knowledge.mailbox_notes[0].value, "Hall B - buzón fuera de servicio");
+        assert_eq!(knowledge.driver_verification_count, Some(2));
+    }
+
+    #[test]
+    fn test_merge_without_data() {
+        assert!(merge_field_knowledge(&CarrierFieldHints::default(), None).is_none());
+
+        let carrier = CarrierFieldHints {
+            cote: Some("3ème gauche".to_string()),
+            ..Default::default()
+        };
+        let knowledge = merge_field_knowledge(&carrier, None).unwrap();
+        assert!(knowledge.door_codes.is_empty());
+        assert_eq!(knowledge.notes[0].sources, vec![FieldValueSource::Carrier]);
+    }
+
+    #[test]
+    fn test_carrier_hints_from_json() {
+        let package = serde_json::json!({
+            "digicode": " 4589 ",
+            "cote": "",
+            "commentaireLivraison": "Sonner 2 fois",
+        });
+        let hints = CarrierFieldHints::from_carrier_json(&package);
+        assert_eq!(hints.digicode.as_deref(), Some("4589"));
+        assert!(hints.cote.is_none());
+        assert_eq!(hints.commentaire.as_deref(), Some("Sonner 2 fois"));
+    }
+}
@@ -0,0 +1,1145 @@
+//! Cuadre de kilometraje y combustible
+//!
+//! Al cerrar una tournée se compara la distancia del cuentakilómetros con la
+//! ruta prevista (la polilínea de la tournée o, si no la hay, el recorrido del
+//! vecino más cercano por sus paradas geocodificadas) y con el recorrido GPS
+//! de las migas de pan; y el combustible declarado con el consumo de
+//! referencia del vehículo (o su media de las últimas tournées). Cuando una
+//! desviación supera su margen se avisa una vez por tournée en
+//! `notifications_log` (`fuel_consumption_alert`).
+//!
+//! Los repostajes de la tarjeta de combustible llegan en el CSV del emisor:
+//! se asocian al vehículo por matrícula (o por número de tarjeta) y a su
+//! tournée del día. La hora del fichero, sin zona, es hora local de París.
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+use serde_json::json;
+use sqlx::{PgConnection, PgPool};
+use std::collections::{BTreeSet, HashMap};
+use std::env;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use crate::{
+    middleware::auth::AuthenticatedUser,
+    models::fuel::{
+        FuelFlag, FuelImportError, FuelImportSummary, FuelMatchStatus, FuelReconciliation,
+        FUEL_RECONCILIATION_COLUMNS,
+    },
+    models::location::Breadcrumb,
+    models::notification::{NewNotification, NotificationPriority, NotificationType},
+    models::tournee::{Tournee, TourneeStatus, TOURNEE_COLUMNS},
+    services::address_confidence::haversine_meters,
+    services::{geofencing, location_tracking, notifications},
+    utils::errors::{AppError, AppResult},
+    utils::french_holidays::paris_to_utc,
+};
+
+/// Tournées recientes del vehículo para su consumo medio
+const HISTORY_TOURNEES: i64 = 20;
+/// Mínimo de tournées para fiarse de la media
+const HISTORY_MIN_TOURNEES: i64 = 5;
+/// Tope de las columnas de porcentaje (DECIMAL(7,2))
+const MAX_DEVIATION_PCT: f64 = 99_999.99;
+
+/// Márgenes del cuadre
+#[derive(Debug, Clone)]
+pub struct FuelPolicy {
+    /// Desviación admitida entre cuentakilómetros y GPS (%)
+    pub distance_tolerance_pct: f64,
+    /// Desviación admitida entre cuentakilómetros y ruta prevista (%)
+    pub planned_tolerance_pct: f64,
+    /// Desviación admitida del consumo de referencia (%)
+    pub consumption_tolerance_pct: f64,
+    /// Factor de carretera sobre la línea recta entre paradas
+    pub road_factor: f64,
+}
+
+impl Default for FuelPolicy {
+    fn default() -> Self {
+        Self {
+            distance_tolerance_pct: 15.0,
+            planned_tolerance_pct: 30.0,
+            consumption_tolerance_pct: 20.0,
+            road_factor: 1.3,
+        }
+    }
+}
+
+impl FuelPolicy {
+    /// Cargar desde `FUEL_*`
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let read = |name: &str, default: f64, min: f64| {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<f64>().ok())
+                .filter(|v| *v >= min)
+                .unwrap_or(default)
+        };
+
+        Self {
+            distance_tolerance_pct: read("FUEL_DISTANCE_TOLERANCE_PCT", defaults.distance_tolerance_pct, 0.0),
+            planned_tolerance_pct: read("FUEL_PLANNED_TOLERANCE_PCT", defaults.planned_tolerance_pct, 0.0),
+            consumption_tolerance_pct: read("FUEL_CONSUMPTION_TOLERANCE_PCT", defaults.consumption_tolerance_pct, 0.0),
+            road_factor: read("FUEL_ROAD_FACTOR", defaults.road_factor, 1.0),
+        }
+    }
+}
+
+/// Datos de una tournée para el cuadre (km y litros)
+#[derive(Debug, Clone, Default)]
+pub struct ReconcileInput {
+    pub odometer_km: Option<f64>,
+    pub planned_km: Option<f64>,
+    pub gps_km: Option<f64>,
+    pub fuel_consumed: Option<f64>,
+    pub expected_fuel: Option<f64>,
+    pub week_fuel_consumed: Option<f64>,
+    pub weekly_fuel_allocation: Option<f64>,
+}
+
+/// Desviaciones y anomalías de una tournée
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReconcileOutcome {
+    pub odometer_gps_deviation_pct: Option<f64>,
+    pub odometer_planned_deviation_pct: Option<f64>,
+    pub fuel_deviation_pct: Option<f64>,
+    pub flags: Vec<FuelFlag>,
+}
+
+impl ReconcileOutcome {
+    pub fn has_alert(&self) -> bool {
+        self.flags.iter().any(FuelFlag::is_alert)
+    }
+
+    /// Prioridad del aviso: alta si el consumo dobla el margen
+    pub fn priority(&self, policy: &FuelPolicy) -> NotificationPriority {
+        match self.fuel_deviation_pct {
+            Some(pct) if pct.abs() > 2.0 * policy.consumption_tolerance_pct => NotificationPriority::High,
+            _ => NotificationPriority::Medium,
+        }
+    }
+}
+
+/// Desviación de `actual` sobre `reference` en %
+pub fn deviation_pct(actual: f64, reference: f64) -> Option<f64> {
+    (reference > 0.0).then(|| (actual - reference) / reference * 100.0)
+}
+
+/// Comparar las distancias y el consumo de una tournée con sus márgenes
+pub fn evaluate(input: &ReconcileInput, policy: &FuelPolicy) -> ReconcileOutcome {
+    let mut outcome = ReconcileOutcome::default();
+
+    match input.odometer_km.filter(|km| *km > 0.0) {
+        None => outcome.flags.push(FuelFlag::OdometerMissing),
+        Some(odometer) => {
+            outcome.odometer_gps_deviation_pct = input.gps_km.and_then(|gps| deviation_pct(odometer, gps));
+            if outcome.odometer_gps_deviation_pct.is_some_and(|pct| pct.abs() > policy.distance_tolerance_pct) {
+                outcome.flags.push(FuelFlag::OdometerVsGps);
+            }
+
+            outcome.odometer_planned_deviation_pct =
+                input.planned_km.and_then(|planned| deviation_pct(odometer, planned));
+            if outcome.odometer_planned_deviation_pct.is_some_and(|pct| pct.abs() > policy.planned_tolerance_pct) {
+                outcome.flags.push(FuelFlag::OdometerVsPlanned);
+            }
+
+            if let (Some(fuel), Some(expected)) = (input.fuel_consumed, input.expected_fuel) {
+                outcome.fuel_deviation_pct = deviation_pct(fuel, expected);
+                if outcome.fuel_deviation_pct.is_some_and(|pct| pct.abs() > policy.consumption_tolerance_pct) {
+                    outcome.flags.push(FuelFlag::FuelDeviation);
+                }
+            }
+        }
+    }
+
+    if let (Some(week), Some(allocation)) = (input.week_fuel_consumed, input.weekly_fuel_allocation) {
+        if allocation > 0.0 && week > allocation {
+            outcome.flags.push(FuelFlag::WeeklyAllocationExceeded);
+        }
+    }
+
+    outcome
+}
+
+/// Detalle legible de las anomalías que generan aviso
+pub fn alert_details(input: &ReconcileInput, outcome: &ReconcileOutcome) -> Vec<String> {
+    let value = |value: Option<f64>| value.unwrap_or_default();
+
+    outcome
+        .flags
+        .iter()
+        .filter(|flag| flag.is_alert())
+        .map(|flag| match flag {
+            FuelFlag::OdometerVsGps => format!(
+                "cuentakilómetros {:.1} km frente a {:.1} km de GPS ({:+.0}%)",
+                value(input.odometer_km),
+                value(input.gps_km),
+                value(outcome.odometer_gps_deviation_pct)
+            ),
+            FuelFlag::OdometerVsPlanned => format!(
+                "cuentakilómetros {:.1} km frente a {:.1} km previstos ({:+.0}%)",
+                value(input.odometer_km),
+                value(input.planned_km),
+                value(outcome.odometer_planned_deviation_pct)
+            ),
+            FuelFlag::FuelDeviation => format!(
+                "{:.1} L consumidos frente a {:.1} L esperados ({:+.0}%)",
+                value(input.fuel_consumed),
+                value(input.expected_fuel),
+                value(outcome.fuel_deviation_pct)
+            ),
+            FuelFlag::WeeklyAllocationExceeded => format!(
+                "{:.1} L en la semana para una dotación de {:.1} L",
+                value(input.week_fuel_consumed),
+                value(input.weekly_fuel_allocation)
+            ),
+            FuelFlag::OdometerMissing => String::new(),
+        })
+        .collect()
+}
+
+/// Longitud en km de la polilínea de la tournée (`"lat,lng"` por punto)
+pub fn route_distance_km(route: &[String]) -> Option<f64> {
+    let points = route
+        .iter()
+        .filter_map(|point| {
+            let (lat, lng) = point.split_once(',')?;
+            Some((lat.trim().parse::<f64>().ok()?, lng.trim().parse::<f64>().ok()?))
+        })
+        .collect::<Vec<_>>();
+    if points.len() < 2 {
+        return None;
+    }
+
+    Some(points.windows(2).map(|pair| haversine_meters(pair[0], pair[1])).sum::<f64>() / 1000.0)
+}
+
+/// Distancia estimada en km recorriendo las paradas por el vecino más
+/// cercano desde la primera, con el factor de carretera
+pub fn stops_distance_km(stops: &[(f64, f64)], road_factor: f64) -> Option<f64> {
+    let (&first, rest) = stops.split_first()?;
+    if rest.is_empty() {
+        return None;
+    }
+
+    let mut current = first;
+    let mut pending = rest.to_vec();
+    let mut meters = 0.0;
+    while !pending.is_empty() {
+        let (index, distance) = pending
+            .iter()
+            .enumerate()
+            .map(|(index, stop)| (index, haversine_meters(current, *stop)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))?;
+        meters += distance;
+        current = pending.swap_remove(index);
+    }
+
+    Some(meters * road_factor / 1000.0)
+}
+
+fn to_decimal(value: Option<f64>) -> Option<Decimal> {
+    value.and_then(Decimal::from_f64).map(|d| d.round_dp(2))
+}
+
+fn pct_decimal(value: Option<f64>) -> Option<Decimal> {
+    to_decimal(value.map(|pct| pct.clamp(-MAX_DEVIATION_PCT, MAX_DEVIATION_PCT)))
+}
+
+fn to_f64(value: Option<Decimal>) -> Option<f64> {
+    value.and_then(|d| d.to_f64())
+}
+
+/// Distancia prevista de la tournée y su origen (`route` o `stops`)
+async fn planned_distance(
+    conn: &mut PgConnection,
+    tournee: &Tournee,
+    policy: &FuelPolicy,
+) -> AppResult<Option<(f64, &'static str)>> {
+    if let Some(km) = tournee.route_coordinates.as_deref().and_then(route_distance_km) {
+        return Ok(Some((km, "route")));
+    }
+
+    // Entregas y recogidas geocodificadas, en el orden en que se dieron de alta
+    let points = sqlx::query_as::<_, (Uuid, f64, f64)>(
+        r#"
+        SELECT id, lat, lng FROM (
+            SELECT id, delivery_coordinates[1] AS lat, delivery_coordinates[0] AS lng, created_at
+            FROM packages
+            WHERE tournee_id = $1 AND deleted_at IS NULL AND delivery_coordinates IS NOT NULL
+            UNION ALL
+            SELECT id, latitude, longitude, created_at
+            FROM pickups
+            WHERE tournee_id = $1 AND deleted_at IS NULL AND pickup_status <> 'cancelled'
+            AND latitude IS NOT NULL AND longitude IS NOT NULL
+        ) stops
+        ORDER BY created_at, id
+        "#,
+    )
+    .bind(tournee.id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let stops = geofencing::group_stops(&points)
+        .into_iter()
+        .map(|stop| (stop.latitude, stop.longitude))
+        .collect::<Vec<_>>();
+    Ok(stops_distance_km(&stops, policy.road_factor).map(|km| (km, "stops")))
+}
+
+/// Distancia GPS de la tournée (sin migas, o ya purgadas: `None`)
+///
+/// Las migas se buscan entre la víspera y el día siguiente de la tournée
+/// para leer solo las particiones de esos días.
+async fn gps_distance(conn: &mut PgConnection, tournee: &Tournee) -> AppResult<Option<f64>> {
+    let day_start = |date: NaiveDate| Utc.from_utc_datetime(&date.and_time(NaiveTime::MIN));
+    let points = sqlx::query_as::<_, Breadcrumb>(
+        r#"
+        SELECT tournee_id, recorded_at, latitude, longitude, accuracy_meters, speed_mps, heading_degrees
+        FROM location_breadcrumbs
+        WHERE tournee_id = $1
+        AND recorded_at >= $2 AND recorded_at < $3
+        ORDER BY recorded_at
+        "#,
+    )
+    .bind(tournee.id)
+    .bind(day_start(tournee.tournee_date - Duration::days(1)))
+    .bind(day_start(tournee.tournee_date + Duration::days(2)))
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok((points.len() >= 2).then(|| location_tracking::track_distance(&points) / 1000.0))
+}
+
+/// Consumo medio (L/100 km) de las últimas tournées del vehículo
+async fn historical_consumption(conn: &mut PgConnection, vehicle_id: Uuid, tournee_id: Uuid) -> AppResult<Option<Decimal>> {
+    let (tournees, consumption) = sqlx::query_as::<_, (i64, Option<Decimal>)>(
+        r#"
+        SELECT COUNT(*), SUM(fuel_consumed) * 100 / NULLIF(SUM(total_distance), 0)
+        FROM (
+            SELECT fuel_consumed, total_distance
+            FROM tournees
+            WHERE vehicle_id = $1 AND id <> $2 AND deleted_at IS NULL
+            AND tournee_status = 'completed' AND fuel_consumed > 0 AND total_distance > 0
+            ORDER BY tournee_date DESC
+            LIMIT $3
+        ) recent
+        "#,
+    )
+    .bind(vehicle_id)
+    .bind(tournee_id)
+    .bind(HISTORY_TOURNEES)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(consumption.filter(|_| tournees >= HISTORY_MIN_TOURNEES).map(|c| c.round_dp(2)))
+}
+
+/// Cuadrar una tournée finalizada y guardar el resultado. Devuelve también
+/// si se ha emitido el aviso (solo la primera vez que aparece una anomalía).
+pub async fn reconcile_tournee(
+    conn: &mut PgConnection,
+    company_id: Uuid,
+    tournee_id: Uuid,
+    policy: &FuelPolicy,
+) -> AppResult<(FuelReconciliation, bool)> {
+    let tournee = sqlx::query_as::<_, Tournee>(&format!(
+        "SELECT {} FROM tournees WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL",
+        TOURNEE_COLUMNS
+    ))
+    .bind(tournee_id)
+    .bind(company_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Tournée no encontrada".to_string()))?;
+
+    if tournee.tournee_status != TourneeStatus::Completed {
+        return Err(AppError::BadRequest("Solo se cuadran tournées finalizadas".to_string()));
+    }
+
+    let (license_plate, vehicle_consumption, weekly_fuel_allocation) =
+        sqlx::query_as::<_, (String, Option<Decimal>, Option<Decimal>)>(
+            "SELECT license_plate, expected_consumption, weekly_fuel_allocation FROM vehicles WHERE id = $1",
+        )
+        .bind(tournee.vehicle_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    let expected_consumption = match vehicle_consumption {
+        Some(consumption) => Some(consumption),
+        None => historical_consumption(conn, tournee.vehicle_id, tournee.id).await?,
+    };
+    let odometer_km = tournee.total_distance.or_else(|| match (tournee.start_mileage, tournee.end_mileage) {
+        (Some(start), Some(end)) => Some(end - start),
+        _ => None,
+    });
+    let expected_fuel = match (odometer_km, expected_consumption) {
+        (Some(km), Some(consumption)) if km > Decimal::ZERO => Some((km * consumption / Decimal::from(100)).round_dp(2)),
+        _ => None,
+    };
+
+    let planned = planned_distance(conn, &tournee, policy).await?;
+    let gps_km = gps_distance(conn, &tournee).await?;
+
+    // Semana ISO del vehículo (date_trunc('week') empieza en lunes)
+    let week_fuel_consumed = sqlx::query_scalar::<_, Option<Decimal>>(
+        r#"
+        SELECT SUM(fuel_consumed)
+        FROM tournees
+        WHERE vehicle_id = $1 AND deleted_at IS NULL AND tournee_status <> 'cancelled'
+        AND date_trunc('week', tournee_date) = date_trunc('week', $2::date)
+        "#,
+    )
+    .bind(tournee.vehicle_id)
+    .bind(tournee.tournee_date)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let (card_liters, card_amount) = sqlx::query_as::<_, (Decimal, Decimal)>(
+        "SELECT COALESCE(SUM(liters), 0), COALESCE(SUM(amount), 0) FROM fuel_card_transactions WHERE tournee_id = $1",
+    )
+    .bind(tournee.id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let input = ReconcileInput {
+        odometer_km: to_f64(odometer_km),
+        planned_km: planned.map(|(km, _)| km),
+        gps_km,
+        fuel_consumed: to_f64(tournee.fuel_consumed),
+        expected_fuel: to_f64(expected_fuel),
+        week_fuel_consumed: to_f64(week_fuel_consumed),
+        weekly_fuel_allocation: to_f64(weekly_fuel_allocation),
+    };
+    let outcome = evaluate(&input, policy);
+    let flags = serde_json::to_value(&outcome.flags).unwrap_or_default();
+
+    let mut reconciliation = sqlx::query_as::<_, FuelReconciliation>(&format!(
+        r#"
+        INSERT INTO fuel_reconciliations (
+            company_id, tournee_id, vehicle_id, driver_id, tournee_date, odometer_km,
+            planned_km, planned_source, gps_km, odometer_gps_deviation_pct,
+            odometer_planned_deviation_pct, fuel_consumed, expected_consumption,
+            expected_fuel, fuel_deviation_pct, week_fuel_consumed, weekly_fuel_allocation,
+            card_liters, card_amount, flags, computed_at
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, NOW())
+        ON CONFLICT (tournee_id) DO UPDATE SET
+            vehicle_id = EXCLUDED.vehicle_id,
+            driver_id = EXCLUDED.driver_id,
+            tournee_date = EXCLUDED.tournee_date,
+            odometer_km = EXCLUDED.odometer_km,
+            planned_km = EXCLUDED.planned_km,
+            planned_source = EXCLUDED.planned_source,
+            gps_km = EXCLUDED.gps_km,
+            odometer_gps_deviation_pct = EXCLUDED.odometer_gps_deviation_pct,
+            odometer_planned_deviation_pct = EXCLUDED.odometer_planned_deviation_pct,
+            fuel_consumed = EXCLUDED.fuel_consumed,
+            expected_consumption = EXCLUDED.expected_consumption,
+            expected_fuel = EXCLUDED.expected_fuel,
+            fuel_deviation_pct = EXCLUDED.fuel_deviation_pct,
+            week_fuel_consumed = EXCLUDED.week_fuel_consumed,
+            weekly_fuel_allocation = EXCLUDED.weekly_fuel_allocation,
+            card_liters = EXCLUDED.card_liters,
+            card_amount = EXCLUDED.card_amount,
+            flags = EXCLUDED.flags,
+            computed_at = NOW()
+        RETURNING {}
+        "#,
+        FUEL_RECONCILIATION_COLUMNS
+    ))
+    .bind(company_id)
+    .bind(tournee.id)
+    .bind(tournee.vehicle_id)
+    .bind(tournee.driver_id)
+    .bind(tournee.tournee_date)
+    .bind(odometer_km)
+    .bind(to_decimal(input.planned_km))
+    .bind(planned.map(|(_, source)| source))
+    .bind(to_decimal(gps_km))
+    .bind(pct_decimal(outcome.odometer_gps_deviation_pct))
+    .bind(pct_decimal(outcome.odometer_planned_deviation_pct))
+    .bind(tournee.fuel_consumed)
+    .bind(expected_consumption)
+    .bind(expected_fuel)
+    .bind(pct_decimal(outcome.fuel_deviation_pct))
+    .bind(week_fuel_consumed)
+    .bind(weekly_fuel_allocation)
+    .bind(card_liters)
+    .bind(card_amount)
+    .bind(&flags)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if !outcome.has_alert() || reconciliation.alerted_at.is_some() {
+        return Ok((reconciliation, false));
+    }
+
+    // Un aviso por tournée aunque se vuelva a cuadrar
+    let alerted_at = sqlx::query_scalar::<_, chrono::DateTime<Utc>>(
+        "UPDATE fuel_reconciliations SET alerted_at = NOW() WHERE id = $1 AND alerted_at IS NULL RETURNING alerted_at",
+    )
+    .bind(reconciliation.id)
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some(alerted_at) = alerted_at else {
+        return Ok((reconciliation, false));
+    };
+    reconciliation.alerted_at = Some(alerted_at);
+
+    notifications::record(
+        conn,
+        &NewNotification {
+            company_id,
+            notification_type: NotificationType::FuelConsumptionAlert,
+            priority: outcome.priority(policy),
+            title: format!("Consumo anómalo: {} ({})", license_plate, tournee.tournee_date),
+            message: alert_details(&input, &outcome).join("; "),
+            document_id: None,
+            vehicle_id: Some(tournee.vehicle_id),
+            driver_id: Some(tournee.driver_id),
+            metadata: json!({
+                "tournee_id": tournee.id,
+                "reconciliation_id": reconciliation.id,
+                "flags": flags,
+            }),
+        },
+    )
+    .await?;
+
+    log::warn!(
+        "⛽ Cuadre de combustible con anomalías en la tournée {}: {}",
+        tournee.id,
+        outcome.flags.iter().map(FuelFlag::as_str).collect::<Vec<_>>().join(", ")
+    );
+    Ok((reconciliation, true))
+}
+
+/// Cuadrar una tournée en su propia transacción
+pub async fn reconcile_closed_tournee(
+    pool: &PgPool,
+    company_id: Uuid,
+    tournee_id: Uuid,
+    policy: &FuelPolicy,
+) -> AppResult<(FuelReconciliation, bool)> {
+    let mut tx = pool.begin().await?;
+    let result = reconcile_tournee(&mut tx, company_id, tournee_id, policy).await?;
+    tx.commit().await?;
+    Ok(result)
+}
+
+// ---------------------------------------------------------------------------
+// Importación del CSV de la tarjeta de combustible
+// ---------------------------------------------------------------------------
+
+/// Repostaje leído del CSV
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedTransaction {
+    pub line: usize,
+    pub card_number: String,
+    pub license_plate: Option<String>,
+    pub transacted_at: NaiveDateTime,
+    pub station: Option<String>,
+    pub product: Option<String>,
+    pub liters: Decimal,
+    pub amount: Decimal,
+}
+
+/// Columnas reconocidas y sus nombres habituales en los ficheros de emisores
+const COLUMN_ALIASES: &[(&str, &[&str])] = &[
+    ("date", &["date", "transaction_date", "date_transaction", "date_heure", "datetime"]),
+    ("time", &["time", "heure"]),
+    ("card", &["card", "card_number", "carte", "numero_carte", "n_carte"]),
+    ("plate", &["plate", "license_plate", "immatriculation", "immat"]),
+    ("liters", &["liters", "litres", "quantite", "volume"]),
+    ("amount", &["amount", "montant", "montant_ttc"]),
+    ("station", &["station", "station_service"]),
+    ("product", &["product", "produit", "carburant"]),
+];
+
+const REQUIRED_COLUMNS: &[&str] = &["date", "card", "liters", "amount"];
+
+/// Formatos de fecha y hora aceptados
+const DATETIME_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M:%S",
+    "%d/%m/%Y %H:%M:%S",
+    "%d/%m/%Y %H:%M",
+];
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%d/%m/%Y"];
+
+/// Separador del fichero: `;` (habitual en Francia) o `,`
+fn detect_separator(header: &str) -> char {
+    if header.matches(';').count() >= header.matches(',').count() && header.contains(';') {
+        ';'
+    } else {
+        ','
+    }
+}
+
+/// Partir una línea respetando las comillas (`""` escapa una comilla)
+fn split_line(line: &str, separator: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            if c == '"' {
+                if chars.peek() == Some(&'"') {
+                    field.push('"');
+                    chars.next();
+                } else {
+                    in_quotes = false;
+                }
+            } else {
+                field.push(c);
+            }
+        } else if c == '"' {
+            in_quotes = true;
+        } else if c == separator {
+            fields.push(field.trim().to_string());
+            field.clear();
+        } else {
+            field.push(c);
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
+
+/// Nombre de columna sin acentos ni espacios
+fn normalize_header(header: &str) -> String {
+    header
+        .trim()
+        .trim_start_matches('\u{feff}')
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'é' | 'è' | 'ê' => 'e',
+            'à' | 'â' => 'a',
+            'ç' => 'c',
+            ' ' | '-' | '.' => '_',
+            c => c,
+        })
+        .collect::<String>()
+        .trim_matches('_')
+        .replace("__", "_")
+}
+
+/// Importe en euros con coma o punto decimal y separador de miles
+/// (`1 234,56`, `1.234,56`, `1,234.56`, `45.10 €`). Con un solo separador,
+/// la coma es decimal y el punto también, salvo en los ficheros con coma
+/// decimal, donde `1.234` agrupa miles.
+fn parse_amount(value: &str, decimal_comma: bool) -> Option<Decimal> {
+    parse_number(value, decimal_comma)
+}
+
+/// Litros: como el importe, pero un punto solo es siempre decimal
+/// (`45.123` son 45,12 L, no 45.123)
+fn parse_liters(value: &str) -> Option<Decimal> {
+    parse_number(value, false)
+}
+
+fn parse_number(value: &str, dot_groups_thousands: bool) -> Option<Decimal> {
+    let cleaned = value
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '€')
+        .collect::<String>();
+    let normalized = match (cleaned.rfind(','), cleaned.rfind('.')) {
+        (Some(comma), Some(dot)) if comma > dot => cleaned.replace('.', "").replace(',', "."),
+        (Some(_), Some(_)) => cleaned.replace(',', ""),
+        (Some(_), None) => cleaned.replace(',', "."),
+        (None, Some(_)) if dot_groups_thousands && groups_thousands(&cleaned) => cleaned.replace('.', ""),
+        _ => cleaned,
+    };
+    Decimal::from_str(&normalized).ok().map(|d| d.round_dp(2))
+}
+
+/// `1.234` o `12.345.678`: grupos de tres cifras tras cada punto
+fn groups_thousands(value: &str) -> bool {
+    let mut groups = value.trim_start_matches('-').split('.');
+    let first = groups.next().unwrap_or_default();
+    (1..=3).contains(&first.len())
+        && first.chars().all(|c| c.is_ascii_digit())
+        && groups.all(|group| group.len() == 3 && group.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// El fichero escribe los decimales con coma si algún litro o importe
+/// termina en `,dd`
+fn uses_decimal_comma(rows: &[(usize, Vec<String>)], columns: &HashMap<&str, usize>) -> bool {
+    rows.iter().any(|(_, fields)| {
+        ["liters", "amount"]
+            .iter()
+            .filter_map(|column| columns.get(column).and_then(|i| fields.get(*i)))
+            .any(|value| value.rfind(',') > value.rfind('.'))
+    })
+}
+
+fn parse_datetime(date: &str, time: Option<&str>) -> Option<NaiveDateTime> {
+    let value = match time.filter(|t| !t.is_empty()) {
+        Some(time) => format!("{} {}", date, time),
+        None => date.to_string(),
+    };
+
+    DATETIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(&value, format).ok())
+        .or_else(|| {
+            DATE_FORMATS
+                .iter()
+                .find_map(|format| NaiveDate::parse_from_str(&value, format).ok())
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+}
+
+/// Matrícula comparable: mayúsculas sin guiones ni espacios
+pub fn normalize_plate(plate: &str) -> String {
+    plate
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Leer el CSV de la tarjeta. Las líneas erróneas se devuelven aparte; un
+/// fichero sin las columnas obligatorias se rechaza entero.
+pub fn parse_fuel_csv(content: &str) -> AppResult<(Vec<ParsedTransaction>, Vec<FuelImportError>)> {
+    let mut lines = content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let (_, header) = lines
+        .next()
+        .ok_or_else(|| AppError::BadRequest("El fichero está vacío".to_string()))?;
+
+    let separator = detect_separator(header);
+    let headers = split_line(header, separator)
+        .iter()
+        .map(|h| normalize_header(h))
+        .collect::<Vec<_>>();
+    let columns = COLUMN_ALIASES
+        .iter()
+        .filter_map(|(column, aliases)| {
+            headers
+                .iter()
+                .position(|h| aliases.contains(&h.as_str()))
+                .map(|index| (*column, index))
+        })
+        .collect::<HashMap<_, _>>();
+
+    let missing = REQUIRED_COLUMNS
+        .iter()
+        .filter(|column| !columns.contains_key(*column))
+        .copied()
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(AppError::BadRequest(format!(
+            "Faltan columnas en el fichero: {}",
+            missing.join(", ")
+        )));
+    }
+
+    let rows = lines
+        .map(|(index, line)| (index + 1, split_line(line, separator)))
+        .collect::<Vec<_>>();
+    let decimal_comma = uses_decimal_comma(&rows, &columns);
+
+    let mut transactions = Vec::new();
+    let mut errors = Vec::new();
+    for (number, fields) in rows {
+        let field = |column: &str| {
+            columns
+                .get(column)
+                .and_then(|i| fields.get(*i))
+                .map(String::as_str)
+                .filter(|value| !value.is_empty())
+        };
+        let error = |message: &str| FuelImportError { line: number, message: message.to_string() };
+
+        let Some(card_number) = field("card") else {
+            errors.push(error("Falta el número de tarjeta"));
+            continue;
+        };
+        let Some(transacted_at) = field("date").and_then(|date| parse_datetime(date, field("time"))) else {
+            errors.push(error("Fecha no válida"));
+            continue;
+        };
+        let Some(liters) = field("liters").and_then(parse_liters).filter(|l| *l > Decimal::ZERO) else {
+            errors.push(error("Litros no válidos"));
+            continue;
+        };
+        let Some(amount) = field("amount").and_then(|amount| parse_amount(amount, decimal_comma)) else {
+            errors.push(error("Importe no válido"));
+            continue;
+        };
+
+        transactions.push(ParsedTransaction {
+            line: number,
+            card_number: card_number.replace(' ', ""),
+            license_plate: field("plate").map(str::to_string),
+            transacted_at,
+            station: field("station").map(str::to_string),
+            product: field("product").map(str::to_string),
+            liters,
+            amount,
+        });
+    }
+
+    Ok((transactions, errors))
+}
+
+/// Tournée candidata de un repostaje: `(id, driver_id, start_time)`
+type TourneeCandidate = (Uuid, Uuid, Option<DateTime<Utc>>);
+
+/// Tournées no canceladas de los vehículos y días del fichero, en una sola
+/// consulta, por `(vehicle_id, tournee_date)`
+async fn import_tournees(
+    conn: &mut PgConnection,
+    company_id: Uuid,
+    transactions: &[ParsedTransaction],
+    vehicle_ids: &[Option<Uuid>],
+) -> AppResult<HashMap<(Uuid, NaiveDate), Vec<TourneeCandidate>>> {
+    let (vehicles, dates): (Vec<Uuid>, Vec<NaiveDate>) = transactions
+        .iter()
+        .zip(vehicle_ids)
+        .filter_map(|(transaction, vehicle_id)| vehicle_id.map(|id| (id, transaction.transacted_at.date())))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .unzip();
+    if vehicles.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let rows = sqlx::query_as::<_, (Uuid, NaiveDate, Uuid, Uuid, Option<DateTime<Utc>>)>(
+        r#"
+        SELECT t.vehicle_id, t.tournee_date, t.id, t.driver_id, t.start_time
+        FROM tournees t
+        JOIN UNNEST($2::uuid[], $3::date[]) AS wanted(vehicle_id, tournee_date)
+            ON wanted.vehicle_id = t.vehicle_id AND wanted.tournee_date = t.tournee_date
+        WHERE t.company_id = $1
+        AND t.deleted_at IS NULL AND t.tournee_status <> 'cancelled'
+        "#,
+    )
+    .bind(company_id)
+    .bind(&vehicles)
+    .bind(&dates)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut by_day: HashMap<(Uuid, NaiveDate), Vec<TourneeCandidate>> = HashMap::new();
+    for (vehicle_id, date, id, driver_id, start_time) in rows {
+        by_day.entry((vehicle_id, date)).or_default().push((id, driver_id, start_time));
+    }
+    Ok(by_day)
+}
+
+/// La tournée del día; si hay varias, la que ya había salido a la hora del
+/// repostaje (la última en salir), luego la siguiente en salir y por último
+/// las que no han salido
+fn pick_tournee(candidates: &[TourneeCandidate], transacted_at: DateTime<Utc>) -> Option<(Uuid, Uuid)> {
+    let departed = candidates
+        .iter()
+        .filter(|(_, _, start_time)| start_time.is_some_and(|start| start <= transacted_at))
+        .max_by_key(|(_, _, start_time)| *start_time);
+    let next = || {
+        candidates
+            .iter()
+            .filter(|(_, _, start_time)| start_time.is_some_and(|start| start > transacted_at))
+            .min_by_key(|(_, _, start_time)| *start_time)
+    };
+
+    departed
+        .or_else(next)
+        .or_else(|| candidates.first())
+        .map(|(id, driver_id, _)| (*id, *driver_id))
+}
+
+/// Importar el CSV de la tarjeta: asocia cada repostaje al vehículo (por
+/// matrícula o tarjeta) y a su tournée del día, ignora los ya importados y
+/// vuelve a cuadrar las tournées finalizadas afectadas.
+pub async fn import_transactions(
+    pool: &PgPool,
+    user: &AuthenticatedUser,
+    content: &str,
+    policy: &FuelPolicy,
+) -> AppResult<FuelImportSummary> {
+    let (transactions, errors) = parse_fuel_csv(content)?;
+    let mut summary = FuelImportSummary {
+        import_batch: Uuid::new_v4(),
+        rows: transactions.len() + errors.len(),
+        errors,
+        ..Default::default()
+    };
+
+    let mut tx = pool.begin().await?;
+    let vehicles = sqlx::query_as::<_, (Uuid, String, Option<String>)>(
+        "SELECT id, license_plate, fuel_card_number FROM vehicles WHERE company_id = $1 AND deleted_at IS NULL",
+    )
+    .bind(user.company_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    let by_plate = vehicles
+        .iter()
+        .map(|(id, plate, _)| (normalize_plate(plate), *id))
+        .collect::<HashMap<_, _>>();
+    let by_card = vehicles
+        .iter()
+        .filter_map(|(id, _, card)| card.as_deref().map(|card| (card.replace(' ', ""), *id)))
+        .collect::<HashMap<_, _>>();
+
+    let vehicle_ids: Vec<Option<Uuid>> = transactions
+        .iter()
+        .map(|transaction| {
+            transaction
+                .license_plate
+                .as_deref()
+                .and_then(|plate| by_plate.get(&normalize_plate(plate)))
+                .or_else(|| by_card.get(&transaction.card_number))
+                .copied()
+        })
+        .collect();
+    let day_tournees = import_tournees(&mut tx, user.company_id, &transactions, &vehicle_ids).await?;
+
+    let mut tournees = BTreeSet::new();
+    for (transaction, vehicle_id) in transactions.iter().zip(vehicle_ids) {
+        let transacted_at = paris_to_utc(transaction.transacted_at);
+
+        let tournee = vehicle_id.and_then(|vehicle_id| {
+            day_tournees
+                .get(&(vehicle_id, transaction.transacted_at.date()))
+                .and_then(|candidates| pick_tournee(candidates, transacted_at))
+        });
+        let match_status = match (vehicle_id, tournee) {
+            (Some(_), Some(_)) => FuelMatchStatus::Matched,
+            (Some(_), None) => FuelMatchStatus::VehicleOnly,
+            _ => FuelMatchStatus::Unmatched,
+        };
+
+        let inserted = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            INSERT INTO fuel_card_transactions (
+                company_id, card_number, license_plate, transacted_at, station, product,
+                liters, amount, vehicle_id, tournee_id, driver_id, match_status,
+                import_batch, imported_by
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+            ON CONFLICT ON CONSTRAINT unique_fuel_card_transaction DO NOTHING
+            RETURNING id
+            "#,
+        )
+        .bind(user.company_id)
+        .bind(&transaction.card_number)
+        .bind(&transaction.license_plate)
+        .bind(transacted_at)
+        .bind(&transaction.station)
+        .bind(&transaction.product)
+        .bind(transaction.liters)
+        .bind(transaction.amount)
+        .bind(vehicle_id)
+        .bind(tournee.map(|(id, _)| id))
+        .bind(tournee.map(|(_, driver_id)| driver_id))
+        .bind(match_status.as_str())
+        .bind(summary.import_batch)
+        .bind(user.user_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if inserted.is_none() {
+            summary.duplicates += 1;
+            continue;
+        }
+        summary.imported += 1;
+        match match_status {
+            FuelMatchStatus::Matched => summary.matched += 1,
+            FuelMatchStatus::VehicleOnly => summary.vehicle_only += 1,
+            FuelMatchStatus::Unmatched => summary.unmatched += 1,
+        }
+        if let Some((tournee_id, _)) = tournee {
+            tournees.insert(tournee_id);
+        }
+    }
+    tx.commit().await?;
+
+    let completed = sqlx::query_scalar::<_, Uuid>(
+        "SELECT id FROM tournees WHERE id = ANY($1) AND tournee_status = 'completed' AND deleted_at IS NULL",
+    )
+    .bind(tournees.into_iter().collect::<Vec<_>>())
+    .fetch_all(pool)
+    .await?;
+    for tournee_id in completed {
+        match reconcile_closed_tournee(pool, user.company_id, tournee_id, policy).await {
+            Ok(_) => summary.reconciled += 1,
+            Err(e) => log::error!("❌ Error cuadrando el combustible de la tournée {}: {}", tournee_id, e),
+        }
+    }
+
+    log::info!(
+        "⛽ CSV de tarjeta importado: {} repostajes nuevos ({} asociados, {} sin tournée, {} sin vehículo), {} duplicados, {} errores",
+        summary.imported,
+        summary.matched,
+        summary.vehicle_only,
+        summary.unmatched,
+        summary.duplicates,
+        summary.errors.len()
+    );
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(odometer_km: f64) -> ReconcileInput {
+        ReconcileInput {
+            odometer_km: Some(odometer_km),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_evaluate_flags_deviations() {
+        let policy = FuelPolicy::default();
+
+        // 120 km con 118 de GPS y 110 previstos: cuadra
+        let ok = ReconcileInput {
+            gps_km: Some(118.0),
+            planned_km: Some(110.0),
+            fuel_consumed: Some(11.0),
+            expected_fuel: Some(10.0),
+            ..input(120.0)
+        };
+        let outcome = evaluate(&ok, &policy);
+        assert!(outcome.flags.is_empty());
+        assert!(!outcome.has_alert());
+
+        // 150 km frente a 100 de GPS y 25 L para 10 esperados
+        let bad = ReconcileInput {
+            gps_km: Some(100.0),
+            fuel_consumed: Some(25.0),
+            expected_fuel: Some(10.0),
+            week_fuel_consumed: Some(90.0),
+            weekly_fuel_allocation: Some(80.0),
+            ..input(150.0)
+        };
+        let outcome = evaluate(&bad, &policy);
+        assert_eq!(
+            outcome.flags,
+            vec![FuelFlag::OdometerVsGps, FuelFlag::FuelDeviation, FuelFlag::WeeklyAllocationExceeded]
+        );
+        assert_eq!(outcome.odometer_gps_deviation_pct, Some(50.0));
+        assert_eq!(outcome.fuel_deviation_pct, Some(150.0));
+        assert_eq!(outcome.priority(&policy), NotificationPriority::High);
+
+        let details = alert_details(&bad, &outcome);
+        assert_eq!(details[0], "cuentakilómetros 150.0 km frente a 100.0 km de GPS (+50%)");
+        assert_eq!(details.len(), 3);
+
+        // Sin kilometraje no se compara nada, y no es una alerta de consumo
+        let missing = evaluate(&ReconcileInput { gps_km: Some(80.0), ..Default::default() }, &policy);
+        assert_eq!(missing.flags, vec![FuelFlag::OdometerMissing]);
+        assert!(!missing.has_alert());
+    }
+
+    #[test]
+    fn test_planned_distances() {
+        let route = vec!["48.85,2.35".to_string(), "48.86, 2.35".to_string(), "no-coords".to_string()];
+        let km = route_distance_km(&route).unwrap();
+        assert!((km - 1.112).abs() < 0.01, "{}", km);
+        assert!(route_distance_km(&route[..1]).is_none());
+
+        // Vecino más cercano: 48.85 → 48.86 → 48.87, no en el orden dado
+        let stops = [(48.85, 2.35), (48.87, 2.35), (48.86, 2.35)];
+        let km = stops_distance_km(&stops, 1.0).unwrap();
+        assert!((km - 2.224).abs() < 0.01, "{}", km);
+        assert!((stops_distance_km(&stops, 1.3).unwrap() - km * 1.3).abs() < 1e-9);
+        assert!(stops_distance_km(&stops[..1], 1.3).is_none());
+    }
+
+    #[test]
+    fn test_parse_fuel_csv_semicolon_french_headers() {
+        let csv = "\u{feff}Date;Heure;N° Carte;Immatriculation;Station;Produit;Quantité;Montant TTC\n\
+                   12/03/2024;07:42;7077 1234;AB-123-CD;\"Total; Rungis\";Gazole;45,10;81,18\n\
+                   \n\
+                   13/03/2024;08:00;7077 1234;AB-123-CD;Total;Gazole;abc;10,00\n";
+        // `N° Carte` no es un alias: la tarjeta falta
+        assert!(parse_fuel_csv(csv).is_err());
+
+        let csv = csv.replace("N° Carte", "Carte");
+        let (transactions, errors) = parse_fuel_csv(&csv).unwrap();
+        assert_eq!(transactions.len(), 1);
+        let transaction = &transactions[0];
+        assert_eq!(transaction.line, 2);
+        assert_eq!(transaction.card_number, "70771234");
+        assert_eq!(transaction.station.as_deref(), Some("Total; Rungis"));
+        assert_eq!(transaction.liters, Decimal::new(4510, 2));
+        assert_eq!(transaction.amount, Decimal::new(8118, 2));
+        assert_eq!(transaction.transacted_at.to_string(), "2024-03-12 07:42:00");
+        assert_eq!(errors, vec![FuelImportError { line: 4, message: "Litros no válidos".to_string() }]);
+    }
+
+    #[test]
+    fn test_parse_fuel_csv_comma_english_headers() {
+        let csv = "transaction_date,card_number,license_plate,liters,amount\n\
+                   2024-03-12 07:42:10,7077,ab 123 cd,40.5,\"72,90\"\n";
+        let (transactions, errors) = parse_fuel_csv(csv).unwrap();
+        assert!(errors.is_empty());
+        assert_eq!(transactions[0].amount, Decimal::new(7290, 2));
+        assert_eq!(normalize_plate(transactions[0].license_plate.as_deref().unwrap()), "AB123CD");
+        assert!(matches!(parse_fuel_csv(""), Err(AppError::BadRequest(_))));
+    }
+
+    #[test]
+    fn test_parse_amount_separators() {
+        assert_eq!(parse_amount("45,10", true), Some(Decimal::new(4510, 2)));
+        assert_eq!(parse_amount("45.10 €", false), Some(Decimal::new(4510, 2)));
+        assert_eq!(parse_amount("1 234,56", true), Some(Decimal::new(123456, 2)));
+        assert_eq!(parse_amount("1.234,56", true), Some(Decimal::new(123456, 2)));
+        assert_eq!(parse_amount("1,234.56", false), Some(Decimal::new(123456, 2)));
+        assert_eq!(parse_amount("12.345.678", true), Some(Decimal::new(12345678, 0)));
+        assert_eq!(parse_amount("12.5", true), Some(Decimal::new(125, 1)));
+        assert_eq!(parse_amount("abc", false), None);
+
+        // El punto solo agrupa miles en los ficheros con coma decimal
+        assert_eq!(parse_amount("1.234", true), Some(Decimal::new(1234, 0)));
+        assert_eq!(parse_amount("1.234", false), Some(Decimal::new(123, 2)));
+    }
+
+    #[test]
+    fn test_parse_liters_dot_is_decimal() {
+        assert_eq!(parse_liters("45.123"), Some(Decimal::new(4512, 2)));
+        assert_eq!(parse_liters("45,12"), Some(Decimal::new(4512, 2)));
+        assert_eq!(parse_liters("1.234,5"), Some(Decimal::new(12345, 1)));
+
+        // Fichero con punto decimal: ni litros ni importe agrupan miles
+        let csv = "date;carte;litres;montant\n2024-03-12 07:42;7077;45.123;81.20\n";
+        let (transactions, errors) = parse_fuel_csv(csv).unwrap();
+        assert!(errors.is_empty());
+        assert_eq!(transactions[0].liters, Decimal::new(4512, 2));
+        assert_eq!(transactions[0].amount, Decimal::new(8120, 2));
+
+        // Fichero con coma decimal: `1.234` en el importe son miles
+        let csv = "date;carte;litres;montant\n2024-03-12 07:42;7077;45,5;1.234\n";
+        let (transactions, _) = parse_fuel_csv(csv).unwrap();
+        assert_eq!(transactions[0].amount, Decimal::new(1234, 0));
+    }
+
+    #[test]
+    fn test_pick_tournee_prefers_departed() {
+        let at = |hour| paris_to_utc(NaiveDate::from_ymd_opt(2024, 3, 12).unwrap().and_hms_opt(hour, 0, 0).unwrap());
+        let morning = (Uuid::new_v4(), Uuid::new_v4(), Some(at(6)));
+        let afternoon = (Uuid::new_v4(), Uuid::new_v4(), Some(at(14)));
+        let not_started = (Uuid::new_v4(), Uuid::new_v4(), None);
+
+        let candidates = [not_started, afternoon, morning];
+        assert_eq!(pick_tournee(&candidates, at(8)), Some((morning.0, morning.1)));
+        assert_eq!(pick_tournee(&candidates, at(16)), Some((afternoon.0, afternoon.1)));
+        assert_eq!(pick_tournee(&[not_started, afternoon], at(8)), Some((afternoon.0, afternoon.1)));
+        // Ninguna ha salido: la siguiente en salir, no la última
+        let evening = (Uuid::new_v4(), Uuid::new_v4(), Some(at(18)));
+        assert_eq!(pick_tournee(&[evening, not_started, afternoon], at(8)), Some((afternoon.0, afternoon.1)));
+        assert_eq!(pick_tournee(&[not_started], at(8)), Some((not_started.0, not_started.1)));
+        assert_eq!(pick_tournee(&[], at(8)), None);
+    }
+}
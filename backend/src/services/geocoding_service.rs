@@ -12,10 +12,40 @@ pub struct GeocodingResponse {
     pub latitude: Option<f64>,
     pub longitude: Option<f64>,
     pub formatted_address: Option<String>,
+    /// Relevancia del resultado (0–1) derivada de `match_code.confidence`
+    #[serde(default)]
+    pub relevance: Option<f64>,
+    /// Precisión de las coordenadas (`rooftop`, `interpolated`, ...)
+    #[serde(default)]
+    pub accuracy: Option<String>,
+    /// Tipo de resultado (`address`, `street`, `postcode`, ...)
+    #[serde(default)]
+    pub result_type: Option<String>,
+    /// Código postal del resultado
+    #[serde(default)]
+    pub postcode: Option<String>,
     pub message: Option<String>,
     pub error: Option<String>,
 }
 
+impl GeocodingResponse {
+    /// Respuesta sin resultado
+    fn failure(message: Option<String>, error: Option<String>) -> Self {
+        Self {
+            success: false,
+            latitude: None,
+            longitude: None,
+            formatted_address: None,
+            relevance: None,
+            accuracy: None,
+            result_type: None,
+            postcode: None,
+            message,
+            error,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct MapboxGeocodingResponse {
     #[serde(rename = "type")]
@@ -45,6 +75,45 @@ struct MapboxProperties {
     name: Option<String>,
     #[serde(rename = "place_name")]
     place_name: Option<String>,
+    #[serde(default)]
+    feature_type: Option<String>,
+    #[serde(default)]
+    coordinates: Option<MapboxCoordinates>,
+    #[serde(default)]
+    match_code: Option<MapboxMatchCode>,
+    #[serde(default)]
+    context: Option<MapboxContext>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MapboxCoordinates {
+    accuracy: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MapboxMatchCode {
+    confidence: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MapboxContext {
+    postcode: Option<MapboxContextEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MapboxContextEntry {
+    name: Option<String>,
+}
+
+/// Convertir `match_code.confidence` de Mapbox en una relevancia 0–1
+fn match_confidence_to_relevance(confidence: &str) -> Option<f64> {
+    match confidence {
+        "exact" => Some(1.0),
+        "high" => Some(0.85),
+        "medium" => Some(0.6),
+        "low" => Some(0.3),
+        _ => None,
+    }
 }
 
 pub struct GeocodingService {
@@ -93,14 +162,7 @@ impl GeocodingService {
         if !status.is_success() {
             let error_text = response.text().await.unwrap_or_default();
             log::error!("❌ Geocoding failed with status {}: {}", status, error_text);
-            return Ok(GeocodingResponse {
-                success: false,
-                latitude: None,
-                longitude: None,
-                formatted_address: None,
-                message: None,
-                error: Some(format!("Geocoding failed: {}", status)),
-            });
+            return Ok(GeocodingResponse::failure(None, Some(format!("Geocoding failed: {}", status))));
         }
 
         let response_text = response.text().await?;
@@ -128,6 +190,15 @@ impl GeocodingService {
                     latitude: Some(latitude),
                     longitude: Some(longitude),
                     formatted_address,
+                    relevance: feature.properties.match_code.as_ref()
+                        .and_then(|m| m.confidence.as_deref())
+                        .and_then(match_confidence_to_relevance),
+                    accuracy: feature.properties.coordinates.as_ref()
+                        .and_then(|c| c.accuracy.clone()),
+                    result_type: feature.properties.feature_type.clone(),
+                    postcode: feature.properties.context.as_ref()
+                        .and_then(|c| c.postcode.as_ref())
+                        .and_then(|p| p.name.clone()),
                     message: Some("Geocoding successful".to_string()),
                     error: None,
                 });
@@ -135,14 +206,10 @@ impl GeocodingService {
         }
 
         log::warn!("⚠️ No coordinates found for address: {}", address);
-        Ok(GeocodingResponse {
-            success: false,
-            latitude: None,
-            longitude: None,
-            formatted_address: None,
-            message: Some("No coordinates found for this address".to_string()),
-            error: None,
-        })
+        Ok(GeocodingResponse::failure(
+            Some("No coordinates found for this address".to_string()),
+            None,
+        ))
     }
 
     pub async fn batch_geocode(&self, addresses: Vec<String>) -> Result<Vec<GeocodingResponse>> {
@@ -167,14 +234,7 @@ impl GeocodingService {
                     Ok(response) => results.push(response),
                     Err(e) => {
                         log::error!("❌ Batch geocoding error: {}", e);
-                        results.push(GeocodingResponse {
-                            success: false,
-                            latitude: None,
-                            longitude: None,
-                            formatted_address: None,
-                            message: None,
-                            error: Some(e.to_string()),
-                        });
+                        results.push(GeocodingResponse::failure(None, Some(e.to_string())));
                     }
                 }
             }
@@ -0,0 +1,507 @@
+//! Detección automática de llegadas y salidas por geocerca
+//!
+//! Cada lote de posiciones de una tournée en curso se evalúa contra sus
+//! paradas pendientes (paquetes abiertos agrupados por coordenadas). Una
+//! visita empieza con la primera posición dentro del radio de llegada, se
+//! confirma tras `min_dwell_seconds` y termina con la primera posición fuera
+//! del radio de salida (mayor, para no oscilar en el borde). Las salidas antes
+//! de confirmar la llegada son pasos sin parar y se descartan.
+//!
+//! Al salir se rellena `delivery_duration_minutes` de los paquetes resueltos
+//! durante la visita y el tiempo medido alimenta las ETAs (`services::eta`).
+
+use chrono::{DateTime, Utc};
+use sqlx::PgConnection;
+use std::collections::HashMap;
+use std::env;
+use uuid::Uuid;
+
+use crate::{
+    models::location::LocationFix,
+    models::stop_visit::{Stop, StopEventKind, StopVisit, StopVisitEvent, STOP_VISIT_COLUMNS},
+    services::address_confidence::haversine_meters,
+    utils::errors::AppResult,
+};
+
+/// Configuración de la geocerca y de las ETAs
+#[derive(Debug, Clone)]
+pub struct GeofenceConfig {
+    pub arrival_radius_meters: f64,
+    /// Mayor que el de llegada para no oscilar en el borde
+    pub departure_radius_meters: f64,
+    /// Permanencia mínima para confirmar una llegada
+    pub min_dwell_seconds: i64,
+    /// Tiempo de servicio por parada sin visitas medidas suficientes
+    pub default_service_seconds: f64,
+    /// Visitas medidas necesarias para usar la mediana del chofer
+    pub min_measured_visits: i64,
+    /// Días de historial para la mediana del tiempo de servicio
+    pub service_time_days: i32,
+    /// Velocidad media entre paradas (en línea recta)
+    pub average_speed_kmh: f64,
+}
+
+impl Default for GeofenceConfig {
+    fn default() -> Self {
+        Self {
+            arrival_radius_meters: 50.0,
+            departure_radius_meters: 80.0,
+            min_dwell_seconds: 30,
+            default_service_seconds: 120.0,
+            min_measured_visits: 5,
+            service_time_days: 30,
+            average_speed_kmh: 20.0,
+        }
+    }
+}
+
+impl GeofenceConfig {
+    /// Cargar desde `GEOFENCE_*`
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let read_f64 = |name: &str, default: f64| {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<f64>().ok())
+                .filter(|v| *v > 0.0)
+                .unwrap_or(default)
+        };
+
+        let arrival_radius_meters = read_f64("GEOFENCE_ARRIVAL_RADIUS_METERS", defaults.arrival_radius_meters);
+        Self {
+            arrival_radius_meters,
+            departure_radius_meters: read_f64("GEOFENCE_DEPARTURE_RADIUS_METERS", defaults.departure_radius_meters)
+                .max(arrival_radius_meters),
+            min_dwell_seconds: env::var("GEOFENCE_MIN_DWELL_SECONDS")
+                .ok()
+                .and_then(|v| v.parse::<i64>().ok())
+                .filter(|v| *v >= 0)
+                .unwrap_or(defaults.min_dwell_seconds),
+            default_service_seconds: read_f64("ETA_DEFAULT_SERVICE_SECONDS", defaults.default_service_seconds),
+            min_measured_visits: env::var("ETA_MIN_MEASURED_VISITS")
+                .ok()
+                .and_then(|v| v.parse::<i64>().ok())
+                .filter(|v| *v >= 1)
+                .unwrap_or(defaults.min_measured_visits),
+            service_time_days: env::var("ETA_SERVICE_TIME_DAYS")
+                .ok()
+                .and_then(|v| v.parse::<i32>().ok())
+                .filter(|v| *v >= 1)
+                .unwrap_or(defaults.service_time_days),
+            average_speed_kmh: read_f64("ETA_AVERAGE_SPEED_KMH", defaults.average_speed_kmh),
+        }
+    }
+}
+
+/// Visita en curso
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenVisit {
+    pub id: Uuid,
+    pub stop: Stop,
+    pub entered_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub confirmed: bool,
+}
+
+/// Cambio producido por una posición
+#[derive(Debug, Clone, PartialEq)]
+pub enum VisitChange {
+    Arrived(OpenVisit),
+    /// La salida es la última posición dentro de la geocerca
+    Departed(OpenVisit),
+    /// Salida antes de confirmar la llegada
+    PassedBy(OpenVisit),
+}
+
+/// Agrupar paquetes `(id, lat, lng)` en paradas (coordenadas iguales al metro)
+pub fn group_stops(packages: &[(Uuid, f64, f64)]) -> Vec<Stop> {
+    let mut index: HashMap<(i64, i64), usize> = HashMap::new();
+    let mut stops: Vec<Stop> = Vec::new();
+
+    for &(id, latitude, longitude) in packages {
+        let key = ((latitude * 1e5).round() as i64, (longitude * 1e5).round() as i64);
+        match index.get(&key) {
+            Some(&i) => stops[i].package_ids.push(id),
+            None => {
+                index.insert(key, stops.len());
+                stops.push(Stop { latitude, longitude, package_ids: vec![id] });
+            }
+        }
+    }
+    stops
+}
+
+fn distance_to(stop: &Stop, latitude: f64, longitude: f64) -> f64 {
+    haversine_meters((stop.latitude, stop.longitude), (latitude, longitude))
+}
+
+/// Evaluar una posición; `open` es la visita en curso antes y después
+pub fn step(
+    open: &mut Option<OpenVisit>,
+    stops: &[Stop],
+    latitude: f64,
+    longitude: f64,
+    at: DateTime<Utc>,
+    config: &GeofenceConfig,
+) -> Vec<VisitChange> {
+    let mut changes = Vec::new();
+
+    if let Some(visit) = open.as_mut() {
+        if distance_to(&visit.stop, latitude, longitude) <= config.departure_radius_meters {
+            visit.last_seen_at = at;
+            if !visit.confirmed && (at - visit.entered_at).num_seconds() >= config.min_dwell_seconds {
+                visit.confirmed = true;
+                changes.push(VisitChange::Arrived(visit.clone()));
+            }
+            return changes;
+        }
+
+        let closed = open.take().expect("visita en curso");
+        changes.push(if closed.confirmed {
+            VisitChange::Departed(closed)
+        } else {
+            VisitChange::PassedBy(closed)
+        });
+    }
+
+    let nearest = stops
+        .iter()
+        .map(|stop| (stop, distance_to(stop, latitude, longitude)))
+        .filter(|(_, distance)| *distance <= config.arrival_radius_meters)
+        .min_by(|a, b| a.1.total_cmp(&b.1));
+
+    if let Some((stop, _)) = nearest {
+        let visit = OpenVisit {
+            id: Uuid::new_v4(),
+            stop: stop.clone(),
+            entered_at: at,
+            last_seen_at: at,
+            confirmed: config.min_dwell_seconds == 0,
+        };
+        if visit.confirmed {
+            changes.push(VisitChange::Arrived(visit.clone()));
+        }
+        *open = Some(visit);
+    }
+
+    changes
+}
+
+fn dwell_seconds(visit: &OpenVisit) -> i32 {
+    (visit.last_seen_at - visit.entered_at).num_seconds() as i32
+}
+
+fn to_event(tournee_id: Uuid, change: &VisitChange) -> Option<StopVisitEvent> {
+    let (kind, visit) = match change {
+        VisitChange::Arrived(visit) => (StopEventKind::Arrival, visit),
+        VisitChange::Departed(visit) => (StopEventKind::Departure, visit),
+        VisitChange::PassedBy(_) => return None,
+    };
+    let departed = kind == StopEventKind::Departure;
+
+    Some(StopVisitEvent {
+        kind,
+        visit_id: visit.id,
+        tournee_id,
+        latitude: visit.stop.latitude,
+        longitude: visit.stop.longitude,
+        package_ids: visit.stop.package_ids.clone(),
+        arrived_at: visit.entered_at,
+        departed_at: departed.then_some(visit.last_seen_at),
+        dwell_seconds: departed.then(|| dwell_seconds(visit)),
+    })
+}
+
+/// Paradas pendientes de una tournée (paquetes abiertos con coordenadas)
+pub async fn pending_stops(conn: &mut PgConnection, tournee_id: Uuid) -> AppResult<Vec<Stop>> {
+    let packages = sqlx::query_as::<_, (Uuid, f64, f64)>(
+        r#"
+        SELECT id, delivery_coordinates[1], delivery_coordinates[0]
+        FROM packages
+        WHERE tournee_id = $1 AND deleted_at IS NULL AND delivery_coordinates IS NOT NULL
+        AND delivery_status IN ('pending', 'in_transit', 'out_for_delivery')
+        ORDER BY created_at, id
+        "#,
+    )
+    .bind(tournee_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(group_stops(&packages))
+}
+
+/// Visita abierta del chofer en la tournée
+pub async fn open_visit(conn: &mut PgConnection, tournee_id: Uuid, driver_id: Uuid) -> AppResult<Option<StopVisit>> {
+    let visit = sqlx::query_as::<_, StopVisit>(&format!(
+        "SELECT {} FROM stop_visits WHERE tournee_id = $1 AND driver_id = $2 AND departed_at IS NULL",
+        STOP_VISIT_COLUMNS
+    ))
+    .bind(tournee_id)
+    .bind(driver_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(visit)
+}
+
+/// Visitas de una tournée
+pub async fn list_for_tournee(conn: &mut PgConnection, tournee_id: Uuid) -> AppResult<Vec<StopVisit>> {
+    let visits = sqlx::query_as::<_, StopVisit>(&format!(
+        "SELECT {} FROM stop_visits WHERE tournee_id = $1 ORDER BY entered_at",
+        STOP_VISIT_COLUMNS
+    ))
+    .bind(tournee_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(visits)
+}
+
+/// Guardar el estado de una visita (insertada con la primera posición que la
+/// persiste, actualizada en las siguientes)
+async fn save_visit(
+    conn: &mut PgConnection,
+    company_id: Uuid,
+    tournee_id: Uuid,
+    driver_id: Uuid,
+    visit: &OpenVisit,
+    departed: bool,
+) -> AppResult<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO stop_visits (
+            id, company_id, tournee_id, driver_id, latitude, longitude, package_ids,
+            entered_at, last_seen_at, arrival_confirmed, departed_at, dwell_seconds
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10,
+                CASE WHEN $11 THEN $9 END, CASE WHEN $11 THEN $12 END)
+        ON CONFLICT (id) DO UPDATE SET
+            last_seen_at = EXCLUDED.last_seen_at,
+            arrival_confirmed = EXCLUDED.arrival_confirmed,
+            departed_at = EXCLUDED.departed_at,
+            dwell_seconds = EXCLUDED.dwell_seconds
+        "#,
+    )
+    .bind(visit.id)
+    .bind(company_id)
+    .bind(tournee_id)
+    .bind(driver_id)
+    .bind(visit.stop.latitude)
+    .bind(visit.stop.longitude)
+    .bind(&visit.stop.package_ids)
+    .bind(visit.entered_at)
+    .bind(visit.last_seen_at)
+    .bind(visit.confirmed)
+    .bind(departed)
+    .bind(dwell_seconds(visit))
+    .execute(&mut *conn)
+    .await?;
+
+    if departed {
+        // El tiempo medido de la parada pasa a los paquetes resueltos en ella
+        // (si la app no envió uno)
+        let minutes = ((dwell_seconds(visit) as f64) / 60.0).round().max(1.0) as i32;
+        sqlx::query(
+            r#"
+            UPDATE packages SET delivery_duration_minutes = $2, updated_at = NOW()
+            WHERE id = ANY($1) AND delivery_duration_minutes IS NULL
+            AND delivery_status IN ('delivered', 'failed')
+            "#,
+        )
+        .bind(&visit.stop.package_ids)
+        .bind(minutes)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Cerrar las visitas abiertas al terminar la tournée: las confirmadas
+/// salen con su última posición y las demás se descartan
+pub async fn close_open_visits(conn: &mut PgConnection, tournee_id: Uuid) -> AppResult<()> {
+    sqlx::query("DELETE FROM stop_visits WHERE tournee_id = $1 AND departed_at IS NULL AND NOT arrival_confirmed")
+        .bind(tournee_id)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query(
+        r#"
+        UPDATE stop_visits SET
+            departed_at = last_seen_at,
+            dwell_seconds = EXTRACT(EPOCH FROM last_seen_at - entered_at)::int
+        WHERE tournee_id = $1 AND departed_at IS NULL
+        "#,
+    )
+    .bind(tournee_id)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Evaluar las posiciones nuevas de un chofer en su tournée en curso
+///
+/// `fixes` deben estar ordenadas por `recorded_at` y ser posteriores a las ya
+/// evaluadas; la llamada tiene que hacerse dentro de la transacción de la
+/// ingesta, que serializa los lotes del chofer.
+pub async fn evaluate(
+    conn: &mut PgConnection,
+    company_id: Uuid,
+    driver_id: Uuid,
+    tournee_id: Uuid,
+    fixes: &[&LocationFix],
+    config: &GeofenceConfig,
+) -> AppResult<Vec<StopVisitEvent>> {
+    let in_progress = sqlx::query_scalar::<_, bool>(
+        "SELECT tournee_status = 'in_progress' FROM tournees WHERE id = $1",
+    )
+    .bind(tournee_id)
+    .fetch_one(&mut *conn)
+    .await?;
+    if !in_progress || fixes.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let stops = pending_stops(&mut *conn, tournee_id).await?;
+    let loaded = open_visit(&mut *conn, tournee_id, driver_id).await?;
+    let mut open = loaded.map(|visit| OpenVisit {
+        id: visit.id,
+        stop: Stop {
+            latitude: visit.latitude,
+            longitude: visit.longitude,
+            package_ids: visit.package_ids,
+        },
+        entered_at: visit.entered_at,
+        last_seen_at: visit.last_seen_at,
+        confirmed: visit.arrival_confirmed,
+    });
+    let initial = open.clone();
+
+    let mut changes = Vec::new();
+    for fix in fixes {
+        changes.extend(step(&mut open, &stops, fix.latitude, fix.longitude, fix.recorded_at, config));
+    }
+
+    // Primero las visitas cerradas: solo puede haber una abierta por chofer
+    for change in &changes {
+        match change {
+            VisitChange::Departed(visit) => {
+                save_visit(&mut *conn, company_id, tournee_id, driver_id, visit, true).await?;
+            }
+            VisitChange::PassedBy(visit) => {
+                sqlx::query("DELETE FROM stop_visits WHERE id = $1")
+                    .bind(visit.id)
+                    .execute(&mut *conn)
+                    .await?;
+            }
+            VisitChange::Arrived(_) => {}
+        }
+    }
+    if let Some(visit) = &open {
+        if initial.as_ref() != Some(visit) {
+            save_visit(&mut *conn, company_id, tournee_id, driver_id, visit, false).await?;
+        }
+    }
+
+    let events: Vec<StopVisitEvent> = changes.iter().filter_map(|c| to_event(tournee_id, c)).collect();
+    for event in &events {
+        log::info!(
+            "📍 {:?} en parada ({} paquetes) de la tournée {}",
+            event.kind,
+            event.package_ids.len(),
+            tournee_id
+        );
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn config() -> GeofenceConfig {
+        GeofenceConfig::default()
+    }
+
+    fn stop(latitude: f64, longitude: f64) -> Stop {
+        Stop { latitude, longitude, package_ids: vec![Uuid::new_v4()] }
+    }
+
+    #[test]
+    fn test_group_stops_by_coordinates() {
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let stops = group_stops(&[(a, 48.85661, 2.35222), (b, 48.856612, 2.352221), (c, 48.86, 2.36)]);
+
+        assert_eq!(stops.len(), 2);
+        assert_eq!(stops[0].package_ids, vec![a, b]);
+        assert_eq!(stops[1].package_ids, vec![c]);
+    }
+
+    #[test]
+    fn test_arrival_and_departure_with_dwell() {
+        let stops = [stop(48.8566, 2.3522), stop(48.8600, 2.3600)];
+        let t0 = Utc.with_ymd_and_hms(2025, 3, 10, 9, 0, 0).unwrap();
+        let mut open = None;
+
+        // Aproximación: fuera de todas las geocercas
+        assert!(step(&mut open, &stops, 48.8530, 2.3522, t0, &config()).is_empty());
+        assert!(open.is_none());
+
+        // Entrada (~10 m): visita abierta sin confirmar
+        let t1 = t0 + Duration::seconds(60);
+        assert!(step(&mut open, &stops, 48.8567, 2.3522, t1, &config()).is_empty());
+        assert_eq!(open.as_ref().unwrap().stop, stops[0]);
+
+        // Permanencia suficiente: llegada
+        let t2 = t1 + Duration::seconds(40);
+        let changes = step(&mut open, &stops, 48.8566, 2.3523, t2, &config());
+        assert!(matches!(changes.as_slice(), [VisitChange::Arrived(v)] if v.entered_at == t1));
+
+        // ~60 m: dentro del radio de salida, sigue en la parada
+        let t3 = t2 + Duration::seconds(200);
+        assert!(step(&mut open, &stops, 48.8571, 2.3522, t3, &config()).is_empty());
+
+        // Fuera: salida con la última posición dentro
+        let t4 = t3 + Duration::seconds(30);
+        let changes = step(&mut open, &stops, 48.8580, 2.3550, t4, &config());
+        match changes.as_slice() {
+            [VisitChange::Departed(visit)] => {
+                assert_eq!(visit.last_seen_at, t3);
+                assert_eq!(dwell_seconds(visit), 240);
+            }
+            other => panic!("se esperaba una salida: {:?}", other),
+        }
+        assert!(open.is_none());
+    }
+
+    #[test]
+    fn test_drive_by_is_discarded() {
+        let stops = [stop(48.8566, 2.3522), stop(48.8570, 2.3530)];
+        let t0 = Utc.with_ymd_and_hms(2025, 3, 10, 9, 0, 0).unwrap();
+        let mut open = None;
+
+        step(&mut open, &stops, 48.8566, 2.3522, t0, &config());
+        let first = open.clone().unwrap();
+
+        // 10 s después ya está lejos: paso sin parar
+        let changes = step(&mut open, &stops, 48.8620, 2.3522, t0 + Duration::seconds(10), &config());
+        assert_eq!(changes, vec![VisitChange::PassedBy(first)]);
+        assert!(open.is_none());
+    }
+
+    #[test]
+    fn test_departure_into_next_stop() {
+        // Paradas a ~90 m: la misma posición cierra una visita y abre la siguiente
+        let stops = [stop(48.8566, 2.3522), stop(48.8574, 2.3522)];
+        let t0 = Utc.with_ymd_and_hms(2025, 3, 10, 9, 0, 0).unwrap();
+        let config = GeofenceConfig { min_dwell_seconds: 0, ..config() };
+        let mut open = None;
+
+        let changes = step(&mut open, &stops, 48.8566, 2.3522, t0, &config);
+        assert!(matches!(changes.as_slice(), [VisitChange::Arrived(_)]));
+
+        let changes = step(&mut open, &stops, 48.8575, 2.3522, t0 + Duration::seconds(120), &config);
+        assert!(matches!(changes.as_slice(), [VisitChange::Departed(_), VisitChange::Arrived(v)] if v.stop == stops[1]));
+    }
+}
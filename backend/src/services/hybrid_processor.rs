@@ -12,6 +12,7 @@ use crate::cache::{DetailCache, CacheStrategy};
 use crate::analysis::delivery_classifier::DeliveryType;
 use crate::models::package::{Package, DeliveryStatus};
 use crate::config::environment::EnvironmentConfig;
+use crate::services::field_knowledge::{merge_field_knowledge, CarrierFieldHints, PackageFieldKnowledge};
 
 /// Procesador híbrido para optimización de rutas
 pub struct HybridProcessor {
@@ -70,6 +71,9 @@ pub struct EnrichedData {
     pub tracking_history: Option<Vec<TrackingEvent>>,
     /// Comentarios especiales
     pub special_comments: Option<String>,
+    /// Digicodes, acceso, buzón y notas con su origen (transportista/chofer)
+    #[serde(default)]
+    pub field_knowledge: Option<PackageFieldKnowledge>,
 }
 
 /// Datos físicos del paquete
@@ -325,6 +329,7 @@ impl HybridProcessor {
             delivery_instructions: None,
             tracking_history: None,
             special_comments: None,
+            field_knowledge: None,
         };
 
         Ok(EnrichedPackage {
@@ -498,6 +503,9 @@ impl HybridProcessor {
             if let Some(comments) = &detail_data.commentaires {
                 enriched.special_comments = Some(comments.clone());
             }
+
+            enriched.field_knowledge =
+                merge_field_knowledge(&CarrierFieldHints::from_detail(detail_data), None);
         }
         
         Ok(enriched)
@@ -514,6 +522,7 @@ impl HybridProcessor {
             delivery_instructions: None,
             tracking_history: None,
             special_comments: None,
+            field_knowledge: None,
         })
     }
 }
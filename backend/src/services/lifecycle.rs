@@ -0,0 +1,755 @@
+//! Ciclo de vida de tournées y paquetes
+//!
+//! Máquina de estados explícita: solo se aceptan las transiciones previstas,
+//! con sus condiciones (no finalizar con paquetes sin resolver salvo que un
+//! admin lo fuerce, no tocar paquetes de una tournée cerrada) y sus efectos
+//! (kilometraje de la tournée y del vehículo, reprogramación de los paquetes
+//! abiertos al cancelar). Cada transición se registra en `status_history`.
+//!
+//! Tournées:
+//!
+//! ```text
+//! pending ──► in_progress ◄──► paused
+//!    │             │              │
+//!    │             ├──► completed ◄┤
+//!    └─────────────┴──► cancelled ◄┘
+//! ```
+
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{json, Value};
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    middleware::auth::{driver_scope, AuthenticatedUser},
+    models::package::{DeliveryStatus, Package},
+    models::status_history::{StatusTransition, TransitionEntity, STATUS_TRANSITION_COLUMNS},
+    models::tournee::{EndTourneeRequest, StartTourneeRequest, Tournee, TourneeStatus, TOURNEE_COLUMNS},
+    models::user::UserType,
+    services::{failed_delivery, geofencing, location_tracking, pickups},
+    utils::errors::{AppError, AppResult},
+};
+
+/// Paquetes que la tournée todavía tiene que resolver (los redirigidos a
+/// punto relais ya tienen destino)
+pub const UNRESOLVED_PACKAGES: &str = r#"
+    (delivery_status IN ('pending', 'out_for_delivery')
+     OR (delivery_status = 'in_transit' AND next_action IS DISTINCT FROM 'redirect_to_relay'))
+"#;
+
+/// Transiciones de tournée permitidas
+pub fn tournee_transition_allowed(from: &TourneeStatus, to: &TourneeStatus) -> bool {
+    use TourneeStatus::*;
+
+    matches!(
+        (from, to),
+        (Pending, InProgress)
+            | (Pending, Cancelled)
+            | (InProgress, Paused)
+            | (InProgress, Completed)
+            | (InProgress, Cancelled)
+            | (Paused, InProgress)
+            | (Paused, Completed)
+            | (Paused, Cancelled)
+    )
+}
+
+/// Transiciones de paquete permitidas: solo hacia delante
+/// (`pending → in_transit → out_for_delivery → delivered/failed/returned`);
+/// un fallido vuelve a presentarse (`failed → pending/out_for_delivery`) y
+/// `failed → failed` es un nuevo intento
+pub fn package_transition_allowed(from: &DeliveryStatus, to: &DeliveryStatus) -> bool {
+    use DeliveryStatus::*;
+
+    matches!(
+        (from, to),
+        (Pending, InTransit | OutForDelivery | Delivered | Failed | Returned | Cancelled)
+            | (InTransit, OutForDelivery | Delivered | Failed | Returned | Cancelled)
+            | (OutForDelivery, Delivered | Failed | Returned | Cancelled)
+            | (Failed, Pending | OutForDelivery | Delivered | Failed | Returned | Cancelled)
+    )
+}
+
+/// Los paquetes solo cambian de estado con la tournée en curso (o en pausa)
+pub fn accepts_package_changes(status: &TourneeStatus) -> bool {
+    matches!(status, TourneeStatus::InProgress | TourneeStatus::Paused)
+}
+
+/// Las tournées cerradas no admiten nuevas recogidas; las pendientes sí
+pub fn accepts_planning(status: &TourneeStatus) -> bool {
+    !matches!(status, TourneeStatus::Completed | TourneeStatus::Cancelled)
+}
+
+/// Minutos de trabajo efectivo entre `start` y `end`, descontando las pausas
+/// (`transitions`: estado destino y hora, en orden)
+pub fn active_minutes(start: DateTime<Utc>, end: DateTime<Utc>, transitions: &[(String, DateTime<Utc>)]) -> i32 {
+    let mut paused = Duration::zero();
+    let mut paused_since: Option<DateTime<Utc>> = None;
+
+    for (status, at) in transitions.iter().filter(|(_, at)| *at >= start && *at <= end) {
+        match (status.as_str(), paused_since) {
+            ("paused", None) => paused_since = Some(*at),
+            ("paused", Some(_)) => {}
+            (_, Some(since)) => {
+                paused += *at - since;
+                paused_since = None;
+            }
+            (_, None) => {}
+        }
+    }
+    if let Some(since) = paused_since {
+        paused += end - since;
+    }
+
+    ((end - start - paused).num_minutes()).max(0) as i32
+}
+
+fn transition_error(entity: &str, from: &str, to: &str) -> AppError {
+    AppError::Conflict(format!("Transición de {} no permitida: {} → {}", entity, from, to))
+}
+
+/// Transición a registrar en el historial
+#[derive(Debug)]
+pub struct NewTransition<'a> {
+    pub company_id: Uuid,
+    pub entity: TransitionEntity,
+    pub entity_id: Uuid,
+    pub from_status: Option<&'a str>,
+    pub to_status: &'a str,
+    pub changed_by: Option<Uuid>,
+    pub forced: bool,
+    pub reason: Option<&'a str>,
+    pub metadata: Value,
+}
+
+/// Registrar una transición
+pub async fn record(conn: &mut PgConnection, transition: &NewTransition<'_>) -> AppResult<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO status_history (
+            company_id, entity_type, entity_id, from_status, to_status,
+            changed_by, forced, reason, metadata
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        "#,
+    )
+    .bind(transition.company_id)
+    .bind(transition.entity.as_str())
+    .bind(transition.entity_id)
+    .bind(transition.from_status)
+    .bind(transition.to_status)
+    .bind(transition.changed_by)
+    .bind(transition.forced)
+    .bind(transition.reason)
+    .bind(&transition.metadata)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Registrar la misma transición para varios paquetes
+pub async fn record_packages(
+    conn: &mut PgConnection,
+    company_id: Uuid,
+    package_ids: &[Uuid],
+    from_status: &DeliveryStatus,
+    to_status: &DeliveryStatus,
+    reason: &str,
+    metadata: Value,
+) -> AppResult<()> {
+    if package_ids.is_empty() {
+        return Ok(());
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO status_history (company_id, entity_type, entity_id, from_status, to_status, reason, metadata)
+        SELECT $1, 'package', id, $3, $4, $5, $6
+        FROM UNNEST($2::uuid[]) AS id
+        "#,
+    )
+    .bind(company_id)
+    .bind(package_ids)
+    .bind(from_status.as_str())
+    .bind(to_status.as_str())
+    .bind(reason)
+    .bind(&metadata)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Historial de una entidad, del más antiguo al más reciente
+pub async fn history(
+    pool: &PgPool,
+    company_id: Uuid,
+    entity: TransitionEntity,
+    entity_id: Uuid,
+) -> AppResult<Vec<StatusTransition>> {
+    let transitions = sqlx::query_as::<_, StatusTransition>(&format!(
+        r#"
+        SELECT {}
+        FROM status_history
+        WHERE company_id = $1 AND entity_type = $2 AND entity_id = $3
+        ORDER BY created_at, id
+        "#,
+        STATUS_TRANSITION_COLUMNS
+    ))
+    .bind(company_id)
+    .bind(entity.as_str())
+    .bind(entity_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(transitions)
+}
+
+// ---------------------------------------------------------------------------
+// Paquetes
+// ---------------------------------------------------------------------------
+
+/// Comprobar que un paquete bloqueado puede pasar a `to`
+pub async fn ensure_package_transition(
+    conn: &mut PgConnection,
+    package: &Package,
+    to: &DeliveryStatus,
+) -> AppResult<()> {
+    let tournee_status = sqlx::query_scalar::<_, TourneeStatus>(
+        "SELECT tournee_status FROM tournees WHERE id = $1",
+    )
+    .bind(package.tournee_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if !accepts_package_changes(&tournee_status) {
+        return Err(AppError::Conflict(format!(
+            "La tournée del paquete está {}: sus paquetes ya no se pueden modificar",
+            tournee_status.as_str()
+        )));
+    }
+    if !package_transition_allowed(&package.delivery_status, to) {
+        return Err(transition_error("paquete", package.delivery_status.as_str(), to.as_str()));
+    }
+    Ok(())
+}
+
+/// Registrar el cambio de estado de un paquete
+pub async fn record_package(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    package_id: Uuid,
+    from: &DeliveryStatus,
+    to: &DeliveryStatus,
+    metadata: Value,
+) -> AppResult<()> {
+    record(
+        conn,
+        &NewTransition {
+            company_id: user.company_id,
+            entity: TransitionEntity::Package,
+            entity_id: package_id,
+            from_status: Some(from.as_str()),
+            to_status: to.as_str(),
+            changed_by: Some(user.user_id),
+            forced: false,
+            reason: None,
+            metadata,
+        },
+    )
+    .await
+}
+
+// ---------------------------------------------------------------------------
+// Tournées
+// ---------------------------------------------------------------------------
+
+/// Bloquear una tournée visible para el usuario
+pub async fn lock_tournee(conn: &mut PgConnection, user: &AuthenticatedUser, id: Uuid) -> AppResult<Tournee> {
+    sqlx::query_as::<_, Tournee>(&format!(
+        r#"
+        SELECT {}
+        FROM tournees
+        WHERE id = $1 AND company_id = $2 AND ($3::uuid IS NULL OR driver_id = $3)
+        AND deleted_at IS NULL
+        FOR UPDATE
+        "#,
+        TOURNEE_COLUMNS
+    ))
+    .bind(id)
+    .bind(user.company_id)
+    .bind(driver_scope(user))
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Tournée no encontrada".to_string()))
+}
+
+/// Rechazar cambios en una tournée bloqueada por la firma de su compte-rendu
+pub async fn ensure_tournee_unlocked<'e, E: sqlx::PgExecutor<'e>>(executor: E, tournee_id: Uuid) -> AppResult<()> {
+    let locked_at = sqlx::query_scalar::<_, Option<DateTime<Utc>>>("SELECT locked_at FROM tournees WHERE id = $1")
+        .bind(tournee_id)
+        .fetch_optional(executor)
+        .await?
+        .flatten();
+
+    match locked_at {
+        Some(locked_at) => Err(AppError::Conflict(format!(
+            "La tournée está bloqueada desde {}: su compte-rendu ya está firmado",
+            locked_at.format("%d/%m/%Y %H:%M")
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn ensure_tournee_transition(tournee: &Tournee, to: &TourneeStatus) -> AppResult<()> {
+    if tournee_transition_allowed(&tournee.tournee_status, to) {
+        Ok(())
+    } else {
+        Err(transition_error("tournée", tournee.tournee_status.as_str(), to.as_str()))
+    }
+}
+
+async fn set_tournee_status(conn: &mut PgConnection, id: Uuid, status: &TourneeStatus) -> AppResult<Tournee> {
+    let tournee = sqlx::query_as::<_, Tournee>(&format!(
+        r#"
+        UPDATE tournees SET tournee_status = $2::tournee_status, updated_at = NOW()
+        WHERE id = $1
+        RETURNING {}
+        "#,
+        TOURNEE_COLUMNS
+    ))
+    .bind(id)
+    .bind(status.as_str())
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(tournee)
+}
+
+async fn record_tournee(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    tournee: &Tournee,
+    to: &TourneeStatus,
+    forced: bool,
+    reason: Option<&str>,
+    metadata: Value,
+) -> AppResult<()> {
+    record(
+        conn,
+        &NewTransition {
+            company_id: tournee.company_id,
+            entity: TransitionEntity::Tournee,
+            entity_id: tournee.id,
+            from_status: Some(tournee.tournee_status.as_str()),
+            to_status: to.as_str(),
+            changed_by: Some(user.user_id),
+            forced,
+            reason,
+            metadata,
+        },
+    )
+    .await
+}
+
+async fn unresolved_count(conn: &mut PgConnection, tournee_id: Uuid) -> AppResult<i64> {
+    let count = sqlx::query_scalar::<_, i64>(&format!(
+        "SELECT COUNT(*) FROM packages WHERE tournee_id = $1 AND deleted_at IS NULL AND {}",
+        UNRESOLVED_PACKAGES
+    ))
+    .bind(tournee_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(count)
+}
+
+/// Reprogramar los paquetes abiertos de una tournée que se cierra y
+/// registrar sus transiciones
+async fn reschedule_open_packages(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    tournee: &Tournee,
+    note: &str,
+) -> AppResult<usize> {
+    let rescheduled = failed_delivery::reschedule_open_packages(&mut *conn, tournee, note).await?;
+
+    for (previous_status, package) in &rescheduled {
+        record_package(
+            &mut *conn,
+            user,
+            package.id,
+            previous_status,
+            &package.delivery_status,
+            json!({ "closed_tournee_id": tournee.id, "next_tournee_id": (package.tournee_id != tournee.id).then_some(package.tournee_id) }),
+        )
+        .await?;
+    }
+
+    Ok(rescheduled.len())
+}
+
+/// Iniciar: captura el kilometraje inicial (no inferior al del vehículo)
+pub async fn start_tournee(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    id: Uuid,
+    request: &StartTourneeRequest,
+) -> AppResult<Tournee> {
+    let tournee = lock_tournee(&mut *conn, user, id).await?;
+    ensure_tournee_transition(&tournee, &TourneeStatus::InProgress)?;
+
+    let vehicle_mileage = sqlx::query_scalar::<_, rust_decimal::Decimal>(
+        "SELECT current_mileage FROM vehicles WHERE id = $1",
+    )
+    .bind(tournee.vehicle_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+    if let Some(vehicle_mileage) = vehicle_mileage {
+        if request.start_mileage < vehicle_mileage {
+            return Err(AppError::BadRequest(format!(
+                "El kilometraje inicial ({}) es inferior al del vehículo ({})",
+                request.start_mileage, vehicle_mileage
+            )));
+        }
+    }
+
+    let other_active = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        SELECT id FROM tournees
+        WHERE driver_id = $1 AND id <> $2
+        AND tournee_status IN ('in_progress', 'paused')
+        AND deleted_at IS NULL
+        LIMIT 1
+        "#,
+    )
+    .bind(tournee.driver_id)
+    .bind(tournee.id)
+    .fetch_optional(&mut *conn)
+    .await?;
+    if let Some(other) = other_active {
+        return Err(AppError::Conflict(format!(
+            "El chofer ya tiene otra tournée en curso ({})",
+            other
+        )));
+    }
+
+    let started = sqlx::query_as::<_, Tournee>(&format!(
+        r#"
+        UPDATE tournees SET
+            tournee_status = 'in_progress',
+            start_time = NOW(),
+            start_mileage = $2,
+            pre_inspection_notes = $3,
+            updated_at = NOW()
+        WHERE id = $1
+        RETURNING {}
+        "#,
+        TOURNEE_COLUMNS
+    ))
+    .bind(tournee.id)
+    .bind(request.start_mileage)
+    .bind(&request.pre_inspection_notes)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| match &e {
+        // Dos inicios simultáneos: idx_tournees_one_active_per_driver
+        sqlx::Error::Database(db) if db.code().as_deref() == Some("23505") => {
+            AppError::Conflict("El chofer ya tiene otra tournée en curso".to_string())
+        }
+        _ => AppError::Database(e),
+    })?;
+
+    record_tournee(
+        &mut *conn,
+        user,
+        &tournee,
+        &TourneeStatus::InProgress,
+        false,
+        None,
+        json!({ "start_mileage": request.start_mileage }),
+    )
+    .await?;
+
+    // Dejar lista la partición de hoy para las posiciones GPS
+    location_tracking::ensure_partition(&mut *conn, Utc::now().date_naive()).await?;
+
+    log::info!("🚚 Tournée {} iniciada ({} km)", tournee.id, request.start_mileage);
+    Ok(started)
+}
+
+/// Pausar una tournée en curso
+pub async fn pause_tournee(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    id: Uuid,
+    reason: Option<&str>,
+) -> AppResult<Tournee> {
+    let tournee = lock_tournee(&mut *conn, user, id).await?;
+    ensure_tournee_transition(&tournee, &TourneeStatus::Paused)?;
+
+    let paused = set_tournee_status(&mut *conn, tournee.id, &TourneeStatus::Paused).await?;
+    record_tournee(&mut *conn, user, &tournee, &TourneeStatus::Paused, false, reason, json!({})).await?;
+
+    Ok(paused)
+}
+
+/// Reanudar una tournée pausada
+pub async fn resume_tournee(conn: &mut PgConnection, user: &AuthenticatedUser, id: Uuid) -> AppResult<Tournee> {
+    let tournee = lock_tournee(&mut *conn, user, id).await?;
+    if tournee.tournee_status != TourneeStatus::Paused {
+        return Err(transition_error("tournée", tournee.tournee_status.as_str(), "in_progress"));
+    }
+
+    let resumed = set_tournee_status(&mut *conn, tournee.id, &TourneeStatus::InProgress).await?;
+    record_tournee(&mut *conn, user, &tournee, &TourneeStatus::InProgress, false, None, json!({})).await?;
+
+    Ok(resumed)
+}
+
+/// Finalizar: exige todos los paquetes y recogidas resueltos salvo que un
+/// admin lo fuerce (los paquetes abiertos se reprograman y las recogidas
+/// pendientes fallan); captura el kilometraje final, la distancia
+/// y la duración sin pausas, y actualiza el kilometraje del vehículo
+pub async fn end_tournee(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    id: Uuid,
+    request: &EndTourneeRequest,
+) -> AppResult<Tournee> {
+    let tournee = lock_tournee(&mut *conn, user, id).await?;
+    ensure_tournee_transition(&tournee, &TourneeStatus::Completed)?;
+
+    if let Some(start_mileage) = tournee.start_mileage {
+        if request.end_mileage < start_mileage {
+            return Err(AppError::BadRequest(format!(
+                "El kilometraje final ({}) es inferior al inicial ({})",
+                request.end_mileage, start_mileage
+            )));
+        }
+    }
+
+    let forced = request.force.unwrap_or(false);
+    if forced && user.user_type != UserType::Admin {
+        return Err(AppError::Forbidden(
+            "Solo un administrador puede forzar el fin de una tournée".to_string(),
+        ));
+    }
+    let unresolved = unresolved_count(&mut *conn, tournee.id).await?;
+    if unresolved > 0 && !forced {
+        return Err(AppError::Conflict(format!(
+            "Quedan {} paquetes sin resolver en la tournée",
+            unresolved
+        )));
+    }
+    let pending_pickups = pickups::pending_count(&mut *conn, tournee.id).await?;
+    if pending_pickups > 0 && !forced {
+        return Err(AppError::Conflict(format!(
+            "Quedan {} recogidas pendientes en la tournée",
+            pending_pickups
+        )));
+    }
+    let rescheduled = if unresolved > 0 {
+        reschedule_open_packages(&mut *conn, user, &tournee, "Tournée finalizada sin resolver el paquete").await?
+    } else {
+        0
+    };
+    let failed_pickups = pickups::close_pending(&mut *conn, tournee.id, "Tournée finalizada sin recoger").await?;
+
+    let now = Utc::now();
+    let actual_duration_minutes = match tournee.start_time {
+        Some(start_time) => {
+            let transitions = sqlx::query_as::<_, (String, DateTime<Utc>)>(
+                r#"
+                SELECT to_status, created_at FROM status_history
+                WHERE entity_type = 'tournee' AND entity_id = $1
+                ORDER BY created_at, id
+                "#,
+            )
+            .bind(tournee.id)
+            .fetch_all(&mut *conn)
+            .await?;
+            Some(active_minutes(start_time, now, &transitions))
+        }
+        None => None,
+    };
+
+    let ended = sqlx::query_as::<_, Tournee>(&format!(
+        r#"
+        UPDATE tournees SET
+            tournee_status = 'completed',
+            end_time = $2,
+            end_mileage = $3,
+            total_distance = $3 - start_mileage,
+            fuel_consumed = $4,
+            fuel_cost = $5,
+            post_inspection_notes = $6,
+            actual_duration_minutes = $7,
+            updated_at = NOW()
+        WHERE id = $1
+        RETURNING {}
+        "#,
+        TOURNEE_COLUMNS
+    ))
+    .bind(tournee.id)
+    .bind(now)
+    .bind(request.end_mileage)
+    .bind(request.fuel_consumed)
+    .bind(request.fuel_cost)
+    .bind(&request.post_inspection_notes)
+    .bind(actual_duration_minutes)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"
+        UPDATE vehicles SET current_mileage = GREATEST(current_mileage, $2), updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(tournee.vehicle_id)
+    .bind(request.end_mileage)
+    .execute(&mut *conn)
+    .await?;
+
+    geofencing::close_open_visits(&mut *conn, tournee.id).await?;
+
+    record_tournee(
+        &mut *conn,
+        user,
+        &tournee,
+        &TourneeStatus::Completed,
+        forced && (unresolved > 0 || pending_pickups > 0),
+        request.reason.as_deref(),
+        json!({
+            "end_mileage": request.end_mileage,
+            "total_distance": ended.total_distance,
+            "rescheduled_packages": rescheduled,
+            "failed_pickups": failed_pickups,
+        }),
+    )
+    .await?;
+
+    log::info!(
+        "🏁 Tournée {} finalizada ({} km{})",
+        tournee.id,
+        request.end_mileage,
+        if rescheduled > 0 { format!(", {} paquetes reprogramados", rescheduled) } else { String::new() }
+    );
+    Ok(ended)
+}
+
+/// Cancelar: los paquetes abiertos se reprograman para el siguiente día
+/// laborable y las recogidas pendientes se dan por fallidas
+pub async fn cancel_tournee(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    id: Uuid,
+    reason: Option<&str>,
+) -> AppResult<Tournee> {
+    let tournee = lock_tournee(&mut *conn, user, id).await?;
+    ensure_tournee_transition(&tournee, &TourneeStatus::Cancelled)?;
+
+    let rescheduled = reschedule_open_packages(&mut *conn, user, &tournee, "Tournée cancelada").await?;
+    let failed_pickups = pickups::close_pending(&mut *conn, tournee.id, "Tournée cancelada").await?;
+    geofencing::close_open_visits(&mut *conn, tournee.id).await?;
+    let cancelled = set_tournee_status(&mut *conn, tournee.id, &TourneeStatus::Cancelled).await?;
+    record_tournee(
+        &mut *conn,
+        user,
+        &tournee,
+        &TourneeStatus::Cancelled,
+        false,
+        reason,
+        json!({ "rescheduled_packages": rescheduled, "failed_pickups": failed_pickups }),
+    )
+    .await?;
+
+    log::info!("🚫 Tournée {} cancelada ({} paquetes reprogramados)", tournee.id, rescheduled);
+    Ok(cancelled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(h: u32, m: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 3, 10, h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn test_tournee_transitions() {
+        use TourneeStatus::*;
+
+        assert!(tournee_transition_allowed(&Pending, &InProgress));
+        assert!(tournee_transition_allowed(&InProgress, &Paused));
+        assert!(tournee_transition_allowed(&Paused, &InProgress));
+        assert!(tournee_transition_allowed(&Paused, &Completed));
+        assert!(tournee_transition_allowed(&Pending, &Cancelled));
+
+        // No se puede reiniciar una tournée cerrada ni finalizar sin empezar
+        assert!(!tournee_transition_allowed(&Completed, &InProgress));
+        assert!(!tournee_transition_allowed(&Cancelled, &InProgress));
+        assert!(!tournee_transition_allowed(&Completed, &Cancelled));
+        assert!(!tournee_transition_allowed(&Pending, &Completed));
+        assert!(!tournee_transition_allowed(&Pending, &Paused));
+        assert!(!tournee_transition_allowed(&InProgress, &InProgress));
+    }
+
+    #[test]
+    fn test_package_transitions() {
+        use DeliveryStatus::*;
+
+        assert!(package_transition_allowed(&Pending, &Delivered));
+        assert!(package_transition_allowed(&OutForDelivery, &Failed));
+        assert!(package_transition_allowed(&Failed, &Failed));
+        assert!(package_transition_allowed(&Failed, &Pending));
+        assert!(package_transition_allowed(&Failed, &Delivered));
+
+        assert!(!package_transition_allowed(&Delivered, &Failed));
+        assert!(!package_transition_allowed(&Returned, &Pending));
+        assert!(!package_transition_allowed(&Cancelled, &Delivered));
+        assert!(!package_transition_allowed(&Pending, &Pending));
+
+        // Sin marcha atrás
+        assert!(!package_transition_allowed(&InTransit, &Pending));
+        assert!(!package_transition_allowed(&OutForDelivery, &Pending));
+        assert!(!package_transition_allowed(&OutForDelivery, &InTransit));
+    }
+
+    #[test]
+    fn test_closed_tournees_lock_packages() {
+        assert!(accepts_package_changes(&TourneeStatus::InProgress));
+        assert!(accepts_package_changes(&TourneeStatus::Paused));
+        assert!(!accepts_package_changes(&TourneeStatus::Completed));
+        assert!(!accepts_package_changes(&TourneeStatus::Cancelled));
+        // Sin empezar la tournée no se entrega ni se falla nada
+        assert!(!accepts_package_changes(&TourneeStatus::Pending));
+
+        assert!(accepts_planning(&TourneeStatus::Pending));
+        assert!(!accepts_planning(&TourneeStatus::Completed));
+    }
+
+    #[test]
+    fn test_active_minutes_discounts_pauses() {
+        let transitions = vec![
+            ("in_progress".to_string(), at(8, 0)),
+            ("paused".to_string(), at(12, 0)),
+            ("in_progress".to_string(), at(12, 45)),
+        ];
+        assert_eq!(active_minutes(at(8, 0), at(16, 0), &transitions), 8 * 60 - 45);
+
+        // Sin pausas
+        assert_eq!(active_minutes(at(8, 0), at(9, 30), &transitions[..1]), 90);
+    }
+
+    #[test]
+    fn test_active_minutes_open_pause_runs_to_end() {
+        let transitions = vec![
+            ("in_progress".to_string(), at(8, 0)),
+            ("paused".to_string(), at(15, 0)),
+        ];
+        assert_eq!(active_minutes(at(8, 0), at(16, 0), &transitions), 7 * 60);
+    }
+}
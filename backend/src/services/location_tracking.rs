@@ -0,0 +1,496 @@
+//! Ingesta de posiciones GPS y migas de pan
+//!
+//! Las posiciones llegan en lotes desde la app y se guardan en
+//! `location_breadcrumbs`, particionada por día (UTC) de `recorded_at`: cada
+//! día tiene su partición (`location_breadcrumbs_d_<aaaammdd>`), creada al
+//! iniciar una tournée o al llegar la primera posición del día. Las lecturas
+//! por chofer y periodo solo tocan las particiones de ese periodo, y la
+//! retención consiste en borrar las particiones de los días más antiguos que
+//! `LOCATION_RETENTION_DAYS`. Solo se aceptan posiciones de tournées en curso
+//! o en pausa.
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use sqlx::{PgConnection, PgPool};
+use std::collections::BTreeSet;
+use std::env;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    middleware::auth::AuthenticatedUser,
+    models::location::{Breadcrumb, DriverTrack, LocationBatchResponse, LocationFix, TrackParams},
+    models::tournee::TourneeStatus,
+    services::address_confidence::haversine_meters,
+    services::geofencing::{self, GeofenceConfig},
+    utils::errors::{AppError, AppResult},
+};
+
+/// Prefijo de las particiones por día
+const PARTITION_PREFIX: &str = "location_breadcrumbs_d_";
+
+/// Formato del día en el nombre de la partición
+const PARTITION_DAY_FORMAT: &str = "%Y%m%d";
+
+/// Margen para relojes de dispositivo ligeramente adelantados
+const FUTURE_TOLERANCE_SECONDS: i64 = 300;
+
+/// Configuración de la ingesta y la retención de posiciones
+#[derive(Debug, Clone)]
+pub struct LocationConfig {
+    /// Días que se conservan las migas de pan (por día de la posición)
+    pub retention_days: i64,
+    /// Posiciones máximas por lote
+    pub max_batch_size: usize,
+    /// Posiciones con peor precisión se descartan
+    pub max_accuracy_meters: f64,
+    /// Puntos máximos devueltos en un recorrido
+    pub max_track_points: i64,
+    /// Cada cuántas horas se ejecuta la purga
+    pub retention_interval_hours: u64,
+}
+
+impl Default for LocationConfig {
+    fn default() -> Self {
+        Self {
+            retention_days: 90,
+            max_batch_size: 500,
+            max_accuracy_meters: 100.0,
+            max_track_points: 20_000,
+            retention_interval_hours: 24,
+        }
+    }
+}
+
+impl LocationConfig {
+    /// Cargar desde `LOCATION_*`
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let read = |name: &str| env::var(name).ok();
+
+        Self {
+            retention_days: read("LOCATION_RETENTION_DAYS")
+                .and_then(|v| v.parse::<i64>().ok())
+                .filter(|v| *v >= 1)
+                .unwrap_or(defaults.retention_days),
+            max_batch_size: read("LOCATION_MAX_BATCH_SIZE")
+                .and_then(|v| v.parse::<usize>().ok())
+                .filter(|v| *v >= 1)
+                .unwrap_or(defaults.max_batch_size),
+            max_accuracy_meters: read("LOCATION_MAX_ACCURACY_METERS")
+                .and_then(|v| v.parse::<f64>().ok())
+                .filter(|v| *v > 0.0)
+                .unwrap_or(defaults.max_accuracy_meters),
+            max_track_points: read("LOCATION_MAX_TRACK_POINTS")
+                .and_then(|v| v.parse::<i64>().ok())
+                .filter(|v| *v >= 1)
+                .unwrap_or(defaults.max_track_points),
+            retention_interval_hours: read("LOCATION_RETENTION_INTERVAL_HOURS")
+                .and_then(|v| v.parse::<u64>().ok())
+                .filter(|v| *v >= 1)
+                .unwrap_or(defaults.retention_interval_hours),
+        }
+    }
+}
+
+/// Nombre de la partición de un día
+pub fn partition_name(day: NaiveDate) -> String {
+    format!("{}{}", PARTITION_PREFIX, day.format(PARTITION_DAY_FORMAT))
+}
+
+/// Día de una partición (`None` si no es de las nuestras)
+pub fn partition_day(name: &str) -> Option<NaiveDate> {
+    name.strip_prefix(PARTITION_PREFIX)
+        .and_then(|day| NaiveDate::parse_from_str(day, PARTITION_DAY_FORMAT).ok())
+}
+
+/// Días (UTC) en los que caen las posiciones
+pub fn fix_days<'a>(fixes: impl IntoIterator<Item = &'a LocationFix>) -> BTreeSet<NaiveDate> {
+    fixes.into_iter().map(|fix| fix.recorded_at.date_naive()).collect()
+}
+
+/// Posiciones que se guardan: válidas, con precisión suficiente y sin hora futura
+pub fn accept_fix(fix: &LocationFix, now: DateTime<Utc>, max_accuracy_meters: f64) -> bool {
+    fix.validate().is_ok()
+        && fix.accuracy_meters.is_none_or(|accuracy| accuracy <= max_accuracy_meters)
+        && fix.recorded_at <= now + Duration::seconds(FUTURE_TOLERANCE_SECONDS)
+}
+
+/// Posición aceptada más reciente del lote
+pub fn latest_fix(fixes: &[LocationFix], now: DateTime<Utc>, max_accuracy_meters: f64) -> Option<&LocationFix> {
+    fixes
+        .iter()
+        .filter(|fix| accept_fix(fix, now, max_accuracy_meters))
+        .max_by_key(|fix| fix.recorded_at)
+}
+
+/// Distancia recorrida siguiendo los puntos en orden
+pub fn track_distance(points: &[Breadcrumb]) -> f64 {
+    points
+        .windows(2)
+        .map(|pair| {
+            haversine_meters(
+                (pair[0].latitude, pair[0].longitude),
+                (pair[1].latitude, pair[1].longitude),
+            )
+        })
+        .sum()
+}
+
+/// Crear la partición del día si todavía no existe
+pub async fn ensure_partition(conn: &mut PgConnection, day: NaiveDate) -> AppResult<()> {
+    let name = partition_name(day);
+
+    let exists = sqlx::query_scalar::<_, bool>("SELECT to_regclass($1) IS NOT NULL")
+        .bind(&name)
+        .fetch_one(&mut *conn)
+        .await?;
+    if exists {
+        return Ok(());
+    }
+
+    // Serializa la creación entre lotes concurrentes del mismo día
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+        .bind(&name)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query(&format!(
+        "CREATE TABLE IF NOT EXISTS {} PARTITION OF location_breadcrumbs FOR VALUES FROM ('{} 00:00:00+00') TO ('{} 00:00:00+00')",
+        name,
+        day,
+        day + Duration::days(1)
+    ))
+    .execute(&mut *conn)
+    .await?;
+
+    log::info!("🗂️ Partición {} creada", name);
+    Ok(())
+}
+
+/// Tournée iniciada (o pausada) del chofer, o la indicada si es suya y está
+/// en curso
+async fn resolve_tournee(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    requested: Option<Uuid>,
+) -> AppResult<Option<Uuid>> {
+    let tournee = sqlx::query_as::<_, (Uuid, TourneeStatus)>(
+        r#"
+        SELECT id, tournee_status FROM tournees
+        WHERE company_id = $1 AND driver_id = $2
+        AND ($3::uuid IS NULL AND tournee_status IN ('in_progress', 'paused') OR id = $3)
+        AND deleted_at IS NULL
+        ORDER BY start_time DESC NULLS LAST
+        LIMIT 1
+        "#,
+    )
+    .bind(user.company_id)
+    .bind(user.user_id)
+    .bind(requested)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    match tournee {
+        Some((id, TourneeStatus::InProgress | TourneeStatus::Paused)) => Ok(Some(id)),
+        Some((id, status)) => Err(AppError::Conflict(format!(
+            "La tournée {} no está en curso ({})",
+            id,
+            status.as_str()
+        ))),
+        None if requested.is_some() => Err(AppError::NotFound("Tournée no encontrada".to_string())),
+        None => Ok(None),
+    }
+}
+
+/// Ingerir un lote de posiciones del usuario autenticado
+pub async fn ingest(
+    pool: &PgPool,
+    user: &AuthenticatedUser,
+    requested_tournee: Option<Uuid>,
+    fixes: &[LocationFix],
+    config: &LocationConfig,
+    geofence: &GeofenceConfig,
+) -> AppResult<LocationBatchResponse> {
+    if fixes.len() > config.max_batch_size {
+        return Err(AppError::BadRequest(format!(
+            "Máximo {} posiciones por lote",
+            config.max_batch_size
+        )));
+    }
+
+    let now = Utc::now();
+    let accepted: Vec<&LocationFix> = fixes
+        .iter()
+        .filter(|fix| accept_fix(fix, now, config.max_accuracy_meters))
+        .collect();
+    let rejected = fixes.len() - accepted.len();
+
+    let mut tx = pool.begin().await?;
+    // Bloquea al chofer: sus lotes se evalúan de uno en uno y en orden
+    let previous_location_at = sqlx::query_scalar::<_, Option<DateTime<Utc>>>(
+        "SELECT last_location_at FROM users WHERE id = $1 FOR UPDATE",
+    )
+    .bind(user.user_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    let tournee_id = resolve_tournee(&mut tx, user, requested_tournee).await?;
+
+    let mut inserted = 0;
+    if let (Some(tournee_id), false) = (tournee_id, accepted.is_empty()) {
+        for day in fix_days(accepted.iter().copied()) {
+            ensure_partition(&mut tx, day).await?;
+        }
+        let result = sqlx::query(
+            r#"
+            INSERT INTO location_breadcrumbs (
+                tournee_id, company_id, driver_id, recorded_at, latitude, longitude,
+                accuracy_meters, speed_mps, heading_degrees
+            )
+            SELECT $1, $2, $3, fix.*
+            FROM UNNEST($4::timestamptz[], $5::float8[], $6::float8[], $7::float8[], $8::float8[], $9::float8[])
+                AS fix(recorded_at, latitude, longitude, accuracy_meters, speed_mps, heading_degrees)
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(tournee_id)
+        .bind(user.company_id)
+        .bind(user.user_id)
+        .bind(accepted.iter().map(|f| f.recorded_at).collect::<Vec<_>>())
+        .bind(accepted.iter().map(|f| f.latitude).collect::<Vec<_>>())
+        .bind(accepted.iter().map(|f| f.longitude).collect::<Vec<_>>())
+        .bind(accepted.iter().map(|f| f.accuracy_meters).collect::<Vec<_>>())
+        .bind(accepted.iter().map(|f| f.speed_mps).collect::<Vec<_>>())
+        .bind(accepted.iter().map(|f| f.heading_degrees).collect::<Vec<_>>())
+        .execute(&mut *tx)
+        .await?;
+        inserted = result.rows_affected() as usize;
+    }
+
+    // La geocerca solo ve las posiciones posteriores a las ya evaluadas
+    let mut stop_events = Vec::new();
+    if let Some(tournee_id) = tournee_id {
+        let mut fresh: Vec<&LocationFix> = accepted
+            .iter()
+            .copied()
+            .filter(|fix| previous_location_at.is_none_or(|previous| fix.recorded_at > previous))
+            .collect();
+        fresh.sort_by_key(|fix| fix.recorded_at);
+        stop_events = geofencing::evaluate(&mut tx, user.company_id, user.user_id, tournee_id, &fresh, geofence).await?;
+    }
+
+    // La última posición solo avanza: un lote atrasado no la sobrescribe
+    let mut last_location_updated = false;
+    if let Some(latest) = latest_fix(fixes, now, config.max_accuracy_meters) {
+        let result = sqlx::query(
+            r#"
+            UPDATE users SET
+                last_location = point($2, $3),
+                last_location_at = $4
+            WHERE id = $1 AND (last_location_at IS NULL OR last_location_at < $4)
+            "#,
+        )
+        .bind(user.user_id)
+        .bind(latest.longitude)
+        .bind(latest.latitude)
+        .bind(latest.recorded_at)
+        .execute(&mut *tx)
+        .await?;
+        last_location_updated = result.rows_affected() > 0;
+    }
+
+    tx.commit().await?;
+
+    let duplicates = if tournee_id.is_some() { accepted.len() - inserted } else { 0 };
+    Ok(LocationBatchResponse {
+        tournee_id,
+        accepted: inserted,
+        duplicates,
+        rejected,
+        last_location_updated,
+        stop_events,
+    })
+}
+
+/// Recorrido de un chofer entre dos instantes
+pub async fn driver_track(
+    pool: &PgPool,
+    company_id: Uuid,
+    driver_id: Uuid,
+    params: &TrackParams,
+    config: &LocationConfig,
+) -> AppResult<DriverTrack> {
+    if params.to <= params.from {
+        return Err(AppError::BadRequest("`to` debe ser posterior a `from`".to_string()));
+    }
+
+    let mut points = sqlx::query_as::<_, Breadcrumb>(
+        r#"
+        SELECT tournee_id, recorded_at, latitude, longitude, accuracy_meters, speed_mps, heading_degrees
+        FROM location_breadcrumbs
+        WHERE company_id = $1 AND driver_id = $2
+        AND recorded_at >= $3 AND recorded_at < $4
+        AND ($5::uuid IS NULL OR tournee_id = $5)
+        ORDER BY recorded_at
+        LIMIT $6
+        "#,
+    )
+    .bind(company_id)
+    .bind(driver_id)
+    .bind(params.from)
+    .bind(params.to)
+    .bind(params.tournee_id)
+    .bind(config.max_track_points + 1)
+    .fetch_all(pool)
+    .await?;
+
+    let truncated = points.len() as i64 > config.max_track_points;
+    points.truncate(config.max_track_points as usize);
+
+    Ok(DriverTrack {
+        driver_id,
+        from: params.from,
+        to: params.to,
+        distance_meters: track_distance(&points),
+        truncated,
+        points,
+    })
+}
+
+/// Borrar las particiones de los días anteriores a la retención (las
+/// tournées eliminadas pierden sus posiciones con su día). Devuelve el número
+/// de particiones borradas.
+pub async fn purge_expired(pool: &PgPool, retention_days: i64) -> AppResult<usize> {
+    let partitions = sqlx::query_scalar::<_, String>(
+        r#"
+        SELECT c.relname::text
+        FROM pg_inherits i
+        JOIN pg_class c ON c.oid = i.inhrelid
+        JOIN pg_class parent ON parent.oid = i.inhparent
+        WHERE parent.relname = 'location_breadcrumbs'
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let cutoff = Utc::now().date_naive() - Duration::days(retention_days);
+    let mut dropped = 0;
+    for name in expired_partitions(&partitions, cutoff) {
+        sqlx::query(&format!("DROP TABLE IF EXISTS {}", name))
+            .execute(pool)
+            .await?;
+        dropped += 1;
+    }
+
+    if dropped > 0 {
+        log::info!("🧹 {} particiones de migas de pan borradas (retención {} días)", dropped, retention_days);
+    }
+    Ok(dropped)
+}
+
+/// Particiones de días anteriores a `cutoff`
+pub fn expired_partitions(partitions: &[String], cutoff: NaiveDate) -> Vec<&str> {
+    partitions
+        .iter()
+        .filter(|name| partition_day(name).is_some_and(|day| day < cutoff))
+        .map(String::as_str)
+        .collect()
+}
+
+/// Lanzar la purga periódica de migas de pan
+pub fn spawn_retention_job(pool: PgPool, config: LocationConfig) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+            config.retention_interval_hours * 3600,
+        ));
+        loop {
+            interval.tick().await;
+            if let Err(e) = purge_expired(&pool, config.retention_days).await {
+                log::error!("❌ Error en la purga de migas de pan: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn fix(accuracy_meters: Option<f64>, recorded_at: DateTime<Utc>) -> LocationFix {
+        LocationFix {
+            latitude: 48.8566,
+            longitude: 2.3522,
+            accuracy_meters,
+            speed_mps: Some(8.3),
+            heading_degrees: Some(90.0),
+            recorded_at,
+        }
+    }
+
+    fn crumb(latitude: f64, longitude: f64) -> Breadcrumb {
+        Breadcrumb {
+            tournee_id: Uuid::nil(),
+            recorded_at: Utc::now(),
+            latitude,
+            longitude,
+            accuracy_meters: None,
+            speed_mps: None,
+            heading_degrees: None,
+        }
+    }
+
+    #[test]
+    fn test_partition_name_roundtrip() {
+        let day = NaiveDate::from_ymd_opt(2024, 3, 12).unwrap();
+        let name = partition_name(day);
+
+        assert_eq!(name, "location_breadcrumbs_d_20240312");
+        assert_eq!(partition_day(&name), Some(day));
+        assert_eq!(partition_day("location_breadcrumbs"), None);
+        assert_eq!(partition_day("location_breadcrumbs_d_basura"), None);
+    }
+
+    #[test]
+    fn test_expired_partitions_and_fix_days() {
+        let day = |d| NaiveDate::from_ymd_opt(2024, 3, d).unwrap();
+        let partitions: Vec<String> = [day(10), day(11), day(12)].into_iter().map(partition_name).collect();
+        let partitions = [partitions, vec!["otra_tabla".to_string()]].concat();
+
+        assert_eq!(expired_partitions(&partitions, day(12)), vec!["location_breadcrumbs_d_20240310", "location_breadcrumbs_d_20240311"]);
+        assert!(expired_partitions(&partitions, day(10)).is_empty());
+
+        // Un lote que cruza la medianoche UTC va a dos particiones
+        let fixes = [
+            fix(None, Utc.with_ymd_and_hms(2024, 3, 12, 23, 59, 0).unwrap()),
+            fix(None, Utc.with_ymd_and_hms(2024, 3, 13, 0, 1, 0).unwrap()),
+            fix(None, Utc.with_ymd_and_hms(2024, 3, 13, 0, 2, 0).unwrap()),
+        ];
+        assert_eq!(fix_days(&fixes).into_iter().collect::<Vec<_>>(), vec![day(12), day(13)]);
+    }
+
+    #[test]
+    fn test_accept_fix() {
+        let now = Utc.with_ymd_and_hms(2025, 3, 10, 10, 0, 0).unwrap();
+
+        assert!(accept_fix(&fix(Some(12.0), now), now, 100.0));
+        assert!(accept_fix(&fix(None, now - Duration::hours(2)), now, 100.0));
+        // Precisión insuficiente
+        assert!(!accept_fix(&fix(Some(250.0), now), now, 100.0));
+        // Reloj muy adelantado
+        assert!(!accept_fix(&fix(Some(12.0), now + Duration::hours(1)), now, 100.0));
+        // Coordenadas imposibles
+        let mut invalid = fix(Some(12.0), now);
+        invalid.latitude = 123.0;
+        assert!(!accept_fix(&invalid, now, 100.0));
+    }
+
+    #[test]
+    fn test_track_distance() {
+        assert_eq!(track_distance(&[]), 0.0);
+        assert_eq!(track_distance(&[crumb(48.8566, 2.3522)]), 0.0);
+
+        // Dos tramos de ~1,1 km hacia el norte
+        let points = [crumb(48.85, 2.35), crumb(48.86, 2.35), crumb(48.87, 2.35)];
+        let distance = track_distance(&points);
+        assert!((distance - 2_224.0).abs() < 10.0, "distancia {}", distance);
+    }
+}
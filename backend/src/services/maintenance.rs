@@ -0,0 +1,818 @@
+//! Mantenimiento preventivo por kilometraje y calendario
+//!
+//! Cada tarea del plan del modelo vence a los N km o a los M meses del último
+//! servicio (lo que llegue antes). Con el kilometraje actual y la media diaria
+//! de las últimas tournées se prevé la fecha en que se alcanzarán los km; el
+//! job diario guarda el vencimiento de cada tarea en
+//! `vehicle_maintenance_schedule` y avisa en `notifications_log`
+//! (`maintenance_reminder`) una vez al entrar en el margen de aviso y otra al
+//! vencer. Los flags vuelven a FALSE al completar el servicio.
+//!
+//! Las citas de taller reservan una ventana: mientras dura, el vehículo no se
+//! puede asignar a una tournée (los días se cuentan en hora de París), y al
+//! empezar el servicio pasa a `maintenance` hasta que se completa o se
+//! cancela. Dos citas activas del mismo vehículo no se solapan
+//! (`no_overlapping_maintenance`).
+
+use chrono::{DateTime, Duration, Months, NaiveDate, Utc};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde_json::json;
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use std::collections::HashMap;
+use std::env;
+use uuid::Uuid;
+
+use crate::{
+    middleware::auth::AuthenticatedUser,
+    models::maintenance::{
+        BookMaintenanceRequest, DueState, MaintenanceCheckSummary, MaintenanceForecast, MaintenancePlan,
+        MaintenanceStatus, MaintenanceTransitionRequest, VehicleMaintenance, MAINTENANCE_PLAN_COLUMNS,
+        VEHICLE_MAINTENANCE_COLUMNS,
+    },
+    models::notification::{NewNotification, NotificationPriority, NotificationType},
+    services::notifications,
+    utils::errors::{AppError, AppResult},
+    utils::french_holidays::{paris_local, paris_today},
+};
+
+/// Configuración del mantenimiento preventivo
+#[derive(Debug, Clone)]
+pub struct MaintenancePolicy {
+    /// Días de antelación del aviso
+    pub reminder_days: i64,
+    /// Km de antelación del aviso
+    pub reminder_km: i64,
+    /// Días de tournées para la media de km diarios
+    pub mileage_window_days: i64,
+    /// Cada cuántas horas se ejecuta el job
+    pub check_interval_hours: u64,
+}
+
+impl Default for MaintenancePolicy {
+    fn default() -> Self {
+        Self {
+            reminder_days: 14,
+            reminder_km: 1000,
+            mileage_window_days: 30,
+            check_interval_hours: 24,
+        }
+    }
+}
+
+impl MaintenancePolicy {
+    /// Cargar desde `MAINTENANCE_*`
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let read = |name: &str| env::var(name).ok();
+
+        Self {
+            reminder_days: read("MAINTENANCE_REMINDER_DAYS")
+                .and_then(|v| v.parse::<i64>().ok())
+                .filter(|v| *v >= 0)
+                .unwrap_or(defaults.reminder_days),
+            reminder_km: read("MAINTENANCE_REMINDER_KM")
+                .and_then(|v| v.parse::<i64>().ok())
+                .filter(|v| *v >= 0)
+                .unwrap_or(defaults.reminder_km),
+            mileage_window_days: read("MAINTENANCE_MILEAGE_WINDOW_DAYS")
+                .and_then(|v| v.parse::<i64>().ok())
+                .filter(|v| *v >= 1)
+                .unwrap_or(defaults.mileage_window_days),
+            check_interval_hours: read("MAINTENANCE_CHECK_INTERVAL_HOURS")
+                .and_then(|v| v.parse::<u64>().ok())
+                .filter(|v| *v >= 1)
+                .unwrap_or(defaults.check_interval_hours),
+        }
+    }
+}
+
+/// Uso del vehículo para la previsión
+#[derive(Debug, Clone)]
+pub struct VehicleUsage {
+    pub current_mileage: Decimal,
+    /// Alta del vehículo: referencia de calendario si nunca se ha hecho la tarea
+    pub in_service_since: NaiveDate,
+    pub avg_daily_km: f64,
+}
+
+/// Último servicio completado de una tarea (fecha y km)
+pub type LastService = (NaiveDate, Decimal);
+
+/// Próximo vencimiento de una tarea
+///
+/// Sin servicio previo, los km vencen en el siguiente múltiplo del intervalo
+/// y los meses cuentan desde el alta del vehículo.
+pub fn forecast(
+    plan: &MaintenancePlan,
+    last: Option<LastService>,
+    usage: &VehicleUsage,
+    today: NaiveDate,
+    policy: &MaintenancePolicy,
+) -> MaintenanceForecast {
+    let due_mileage = plan.interval_km.map(|interval| {
+        let interval = Decimal::from(interval);
+        match last {
+            Some((_, mileage)) => mileage + interval,
+            None => ((usage.current_mileage / interval).floor() + Decimal::ONE) * interval,
+        }
+    });
+    let due_date = plan.interval_months.and_then(|months| {
+        let since = last.map(|(date, _)| date).unwrap_or(usage.in_service_since);
+        since.checked_add_months(Months::new(months as u32))
+    });
+
+    let km_remaining = due_mileage.map(|due| due - usage.current_mileage);
+    let km_date = km_remaining.and_then(|remaining| {
+        if remaining <= Decimal::ZERO {
+            Some(today)
+        } else if usage.avg_daily_km > 0.0 {
+            let days = (remaining.to_f64().unwrap_or_default() / usage.avg_daily_km).ceil() as i64;
+            Some(today + Duration::days(days))
+        } else {
+            None
+        }
+    });
+    let projected_date = match (due_date, km_date) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    let days_remaining = projected_date.map(|date| (date - today).num_days());
+
+    let overdue = km_remaining.is_some_and(|km| km <= Decimal::ZERO) || due_date.is_some_and(|date| date <= today);
+    let upcoming = days_remaining.is_some_and(|days| days <= policy.reminder_days)
+        || km_remaining.is_some_and(|km| km <= Decimal::from(policy.reminder_km));
+    let due_state = if overdue {
+        DueState::Overdue
+    } else if upcoming {
+        DueState::Upcoming
+    } else {
+        DueState::Ok
+    };
+
+    MaintenanceForecast {
+        plan_id: plan.id,
+        task_name: plan.task_name.clone(),
+        interval_km: plan.interval_km,
+        interval_months: plan.interval_months,
+        last_service_date: last.map(|(date, _)| date),
+        last_service_mileage: last.map(|(_, mileage)| mileage),
+        due_mileage,
+        due_date,
+        projected_date,
+        km_remaining,
+        days_remaining,
+        avg_daily_km: usage.avg_daily_km,
+        due_state,
+    }
+}
+
+/// Cambios de estado permitidos de una cita
+pub fn maintenance_transition_allowed(from: MaintenanceStatus, to: MaintenanceStatus) -> bool {
+    use MaintenanceStatus::*;
+    matches!(
+        (from, to),
+        (Scheduled, InProgress) | (Scheduled, Completed) | (Scheduled, Cancelled) | (InProgress, Completed) | (InProgress, Cancelled)
+    )
+}
+
+/// Vehículo con lo necesario para la previsión
+#[derive(Debug, sqlx::FromRow)]
+struct VehicleRow {
+    id: Uuid,
+    company_id: Uuid,
+    brand: String,
+    model: String,
+    license_plate: String,
+    current_mileage: Decimal,
+    created_at: Option<DateTime<Utc>>,
+}
+
+const VEHICLE_ROW_COLUMNS: &str = "id, company_id, brand, model, license_plate, current_mileage, created_at";
+
+/// Tareas activas del plan del modelo del vehículo
+async fn plans_for(conn: &mut PgConnection, vehicle: &VehicleRow) -> AppResult<Vec<MaintenancePlan>> {
+    Ok(sqlx::query_as::<_, MaintenancePlan>(&format!(
+        r#"
+        SELECT {}
+        FROM maintenance_plans
+        WHERE company_id = $1 AND deleted_at IS NULL AND is_active
+        AND lower(brand) = lower($2) AND lower(model) = lower($3)
+        ORDER BY task_name
+        "#,
+        MAINTENANCE_PLAN_COLUMNS
+    ))
+    .bind(vehicle.company_id)
+    .bind(vehicle.brand.trim())
+    .bind(vehicle.model.trim())
+    .fetch_all(&mut *conn)
+    .await?)
+}
+
+/// Último servicio completado de cada tarea del vehículo
+async fn last_services(conn: &mut PgConnection, vehicle_id: Uuid) -> AppResult<HashMap<Uuid, LastService>> {
+    let rows = sqlx::query_as::<_, (Uuid, NaiveDate, Decimal)>(
+        r#"
+        SELECT DISTINCT ON (plan_id) plan_id, completed_at::date, mileage_at_service
+        FROM vehicle_maintenance
+        WHERE vehicle_id = $1 AND plan_id IS NOT NULL AND maintenance_status = 'completed'
+        AND completed_at IS NOT NULL AND mileage_at_service IS NOT NULL
+        ORDER BY plan_id, completed_at DESC
+        "#,
+    )
+    .bind(vehicle_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(rows.into_iter().map(|(plan_id, date, mileage)| (plan_id, (date, mileage))).collect())
+}
+
+/// Km diarios medios del vehículo en las tournées de la ventana
+async fn average_daily_km(conn: &mut PgConnection, vehicle_id: Uuid, today: NaiveDate, window_days: i64) -> AppResult<f64> {
+    let total = sqlx::query_scalar::<_, Option<Decimal>>(
+        r#"
+        SELECT SUM(total_distance)
+        FROM tournees
+        WHERE vehicle_id = $1 AND deleted_at IS NULL AND tournee_status = 'completed'
+        AND tournee_date > $2::date - $3::int AND tournee_date <= $2
+        "#,
+    )
+    .bind(vehicle_id)
+    .bind(today)
+    .bind(window_days as i32)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(total.and_then(|km| km.to_f64()).unwrap_or_default() / window_days as f64)
+}
+
+async fn forecasts_for(
+    conn: &mut PgConnection,
+    vehicle: &VehicleRow,
+    today: NaiveDate,
+    policy: &MaintenancePolicy,
+) -> AppResult<Vec<MaintenanceForecast>> {
+    let plans = plans_for(conn, vehicle).await?;
+    if plans.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let last = last_services(conn, vehicle.id).await?;
+    let usage = VehicleUsage {
+        current_mileage: vehicle.current_mileage,
+        in_service_since: vehicle.created_at.map(|at| paris_local(at).date()).unwrap_or(today),
+        avg_daily_km: average_daily_km(conn, vehicle.id, today, policy.mileage_window_days).await?,
+    };
+
+    Ok(plans
+        .iter()
+        .map(|plan| forecast(plan, last.get(&plan.id).copied(), &usage, today, policy))
+        .collect())
+}
+
+/// Próximos vencimientos de un vehículo, ordenados por fecha prevista
+pub async fn vehicle_forecast(
+    conn: &mut PgConnection,
+    company_id: Uuid,
+    vehicle_id: Uuid,
+    policy: &MaintenancePolicy,
+) -> AppResult<Vec<MaintenanceForecast>> {
+    let vehicle = sqlx::query_as::<_, VehicleRow>(&format!(
+        "SELECT {} FROM vehicles WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL",
+        VEHICLE_ROW_COLUMNS
+    ))
+    .bind(vehicle_id)
+    .bind(company_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Vehículo no encontrado".to_string()))?;
+
+    let today = paris_today();
+    let mut forecasts = forecasts_for(conn, &vehicle, today, policy).await?;
+    forecasts.sort_by_key(|forecast| (std::cmp::Reverse(forecast.due_state), forecast.projected_date));
+    Ok(forecasts)
+}
+
+/// Guardar el vencimiento de una tarea y avisar si toca (una vez por umbral)
+async fn save_and_notify(conn: &mut PgConnection, vehicle: &VehicleRow, forecast: &MaintenanceForecast) -> AppResult<bool> {
+    // Un servicio nuevo (otra referencia) vuelve a habilitar los avisos
+    let (schedule_id, reminder_sent, overdue_sent) = sqlx::query_as::<_, (Uuid, bool, bool)>(
+        r#"
+        INSERT INTO vehicle_maintenance_schedule (
+            company_id, vehicle_id, plan_id, last_service_date, last_service_mileage,
+            due_mileage, due_date, projected_date, avg_daily_km, due_state, computed_at
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, NOW())
+        ON CONFLICT (vehicle_id, plan_id) DO UPDATE SET
+            reminder_sent = vehicle_maintenance_schedule.reminder_sent
+                AND vehicle_maintenance_schedule.last_service_date IS NOT DISTINCT FROM EXCLUDED.last_service_date,
+            overdue_sent = vehicle_maintenance_schedule.overdue_sent
+                AND vehicle_maintenance_schedule.last_service_date IS NOT DISTINCT FROM EXCLUDED.last_service_date,
+            last_service_date = EXCLUDED.last_service_date,
+            last_service_mileage = EXCLUDED.last_service_mileage,
+            due_mileage = EXCLUDED.due_mileage,
+            due_date = EXCLUDED.due_date,
+            projected_date = EXCLUDED.projected_date,
+            avg_daily_km = EXCLUDED.avg_daily_km,
+            due_state = EXCLUDED.due_state,
+            computed_at = NOW()
+        RETURNING id, reminder_sent, overdue_sent
+        "#,
+    )
+    .bind(vehicle.company_id)
+    .bind(vehicle.id)
+    .bind(forecast.plan_id)
+    .bind(forecast.last_service_date)
+    .bind(forecast.last_service_mileage)
+    .bind(forecast.due_mileage)
+    .bind(forecast.due_date)
+    .bind(forecast.projected_date)
+    .bind(Decimal::from_f64_retain(forecast.avg_daily_km).map(|km| km.round_dp(2)))
+    .bind(forecast.due_state.as_str())
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let (claim, priority, title) = match forecast.due_state {
+        DueState::Overdue if !overdue_sent => (
+            "overdue_sent = TRUE, reminder_sent = TRUE",
+            NotificationPriority::High,
+            format!("Mantenimiento vencido: {} ({})", forecast.task_name, vehicle.license_plate),
+        ),
+        DueState::Upcoming if !reminder_sent => (
+            "reminder_sent = TRUE",
+            NotificationPriority::Medium,
+            format!("Mantenimiento próximo: {} ({})", forecast.task_name, vehicle.license_plate),
+        ),
+        _ => return Ok(false),
+    };
+    sqlx::query(&format!("UPDATE vehicle_maintenance_schedule SET {} WHERE id = $1", claim))
+        .bind(schedule_id)
+        .execute(&mut *conn)
+        .await?;
+
+    let mut due = Vec::new();
+    if let Some(mileage) = forecast.due_mileage {
+        due.push(format!("a los {} km (actual {} km)", mileage.round(), vehicle.current_mileage.round()));
+    }
+    if let Some(date) = forecast.due_date {
+        due.push(format!("el {}", date));
+    }
+    let projected = forecast
+        .projected_date
+        .map(|date| format!(", previsto para el {}", date))
+        .unwrap_or_default();
+
+    notifications::record(
+        conn,
+        &NewNotification {
+            company_id: vehicle.company_id,
+            notification_type: NotificationType::MaintenanceReminder,
+            priority,
+            title,
+            message: format!(
+                "{} del vehículo {} vence {}{}",
+                forecast.task_name,
+                vehicle.license_plate,
+                due.join(" o "),
+                projected
+            ),
+            document_id: None,
+            vehicle_id: Some(vehicle.id),
+            driver_id: None,
+            metadata: json!({
+                "plan_id": forecast.plan_id,
+                "due_state": forecast.due_state,
+                "due_mileage": forecast.due_mileage,
+                "due_date": forecast.due_date,
+                "projected_date": forecast.projected_date,
+                "km_remaining": forecast.km_remaining,
+            }),
+        },
+    )
+    .await?;
+
+    Ok(true)
+}
+
+/// Pasada del job: vencimientos de todos los vehículos con plan
+pub async fn run_maintenance_check(pool: &PgPool, policy: &MaintenancePolicy) -> AppResult<MaintenanceCheckSummary> {
+    let today = paris_today();
+
+    let vehicles = sqlx::query_as::<_, VehicleRow>(&format!(
+        r#"
+        SELECT {}
+        FROM vehicles v
+        WHERE deleted_at IS NULL AND vehicle_status <> 'retired'
+        AND EXISTS (
+            SELECT 1 FROM maintenance_plans p
+            WHERE p.company_id = v.company_id AND p.deleted_at IS NULL AND p.is_active
+            AND lower(p.brand) = lower(trim(v.brand)) AND lower(p.model) = lower(trim(v.model))
+        )
+        "#,
+        VEHICLE_ROW_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    let mut summary = MaintenanceCheckSummary::default();
+    for vehicle in &vehicles {
+        let result: AppResult<()> = async {
+            let mut tx = pool.begin().await?;
+            for forecast in forecasts_for(&mut tx, vehicle, today, policy).await? {
+                match forecast.due_state {
+                    DueState::Overdue => summary.overdue += 1,
+                    DueState::Upcoming => summary.upcoming += 1,
+                    DueState::Ok => {}
+                }
+                if save_and_notify(&mut tx, vehicle, &forecast).await? {
+                    summary.notifications += 1;
+                }
+            }
+            tx.commit().await?;
+            Ok(())
+        }
+        .await;
+
+        match result {
+            Ok(()) => summary.vehicles += 1,
+            Err(e) => log::error!("❌ Error en el mantenimiento del vehículo {}: {}", vehicle.id, e),
+        }
+    }
+
+    if summary.upcoming > 0 || summary.overdue > 0 {
+        log::info!(
+            "🔧 Mantenimiento: {} vehículos, {} tareas próximas, {} vencidas, {} avisos",
+            summary.vehicles,
+            summary.upcoming,
+            summary.overdue,
+            summary.notifications
+        );
+    }
+    Ok(summary)
+}
+
+/// Lanzar la revisión periódica del mantenimiento
+pub fn spawn_maintenance_job(pool: PgPool, policy: MaintenancePolicy) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(policy.check_interval_hours * 3600));
+        loop {
+            interval.tick().await;
+            if let Err(e) = run_maintenance_check(&pool, &policy).await {
+                log::error!("❌ Error en la revisión de mantenimiento: {}", e);
+            }
+        }
+    });
+}
+
+/// Rechazar la asignación de un vehículo con una cita de taller el día de la
+/// tournée
+pub async fn ensure_available<'e>(executor: impl PgExecutor<'e>, vehicle_id: Uuid, date: NaiveDate) -> AppResult<()> {
+    let booking = sqlx::query_as::<_, (String, DateTime<Utc>, DateTime<Utc>)>(
+        r#"
+        SELECT task_name, scheduled_start, scheduled_end
+        FROM vehicle_maintenance
+        WHERE vehicle_id = $1 AND maintenance_status IN ('scheduled', 'in_progress')
+        AND scheduled_start < (($2::date + 1)::timestamp AT TIME ZONE 'Europe/Paris')
+        AND scheduled_end > ($2::date::timestamp AT TIME ZONE 'Europe/Paris')
+        ORDER BY scheduled_start
+        LIMIT 1
+        "#,
+    )
+    .bind(vehicle_id)
+    .bind(date)
+    .fetch_optional(executor)
+    .await?;
+
+    match booking {
+        None => Ok(()),
+        Some((task_name, start, end)) => Err(AppError::BadRequest(format!(
+            "El vehículo está en el taller el {}: {} ({} - {})",
+            date,
+            task_name,
+            paris_local(start).format("%Y-%m-%d %H:%M"),
+            paris_local(end).format("%Y-%m-%d %H:%M")
+        ))),
+    }
+}
+
+/// Reservar una cita de taller. La ventana no puede solaparse con otra cita
+/// ni con tournées ya asignadas al vehículo.
+pub async fn book(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    vehicle_id: Uuid,
+    request: &BookMaintenanceRequest,
+) -> AppResult<VehicleMaintenance> {
+    if request.scheduled_end <= request.scheduled_start {
+        return Err(AppError::BadRequest("El fin de la cita debe ser posterior al inicio".to_string()));
+    }
+
+    let vehicle_ok = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(SELECT 1 FROM vehicles WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL)",
+    )
+    .bind(vehicle_id)
+    .bind(user.company_id)
+    .fetch_one(&mut *conn)
+    .await?;
+    if !vehicle_ok {
+        return Err(AppError::NotFound("Vehículo no encontrado".to_string()));
+    }
+
+    let task_name = match request.plan_id {
+        Some(plan_id) => sqlx::query_scalar::<_, String>(
+            "SELECT task_name FROM maintenance_plans WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL",
+        )
+        .bind(plan_id)
+        .bind(user.company_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Tarea de mantenimiento no encontrada".to_string()))?,
+        None => request
+            .task_name
+            .clone()
+            .ok_or_else(|| AppError::BadRequest("Indica la tarea (`plan_id` o `task_name`)".to_string()))?,
+    };
+
+    let overlapping = sqlx::query_scalar::<_, bool>(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM vehicle_maintenance
+            WHERE vehicle_id = $1 AND maintenance_status IN ('scheduled', 'in_progress')
+            AND scheduled_start < $3 AND scheduled_end > $2
+        )
+        "#,
+    )
+    .bind(vehicle_id)
+    .bind(request.scheduled_start)
+    .bind(request.scheduled_end)
+    .fetch_one(&mut *conn)
+    .await?;
+    if overlapping {
+        return Err(AppError::Conflict("El vehículo ya tiene una cita de taller en esa ventana".to_string()));
+    }
+
+    let tournee_dates = sqlx::query_scalar::<_, NaiveDate>(
+        r#"
+        SELECT tournee_date FROM tournees
+        WHERE vehicle_id = $1 AND deleted_at IS NULL
+        AND tournee_status NOT IN ('completed', 'cancelled')
+        AND tournee_date >= ($2 AT TIME ZONE 'Europe/Paris')::date
+        AND tournee_date <= (($3 - INTERVAL '1 microsecond') AT TIME ZONE 'Europe/Paris')::date
+        ORDER BY tournee_date
+        "#,
+    )
+    .bind(vehicle_id)
+    .bind(request.scheduled_start)
+    .bind(request.scheduled_end)
+    .fetch_all(&mut *conn)
+    .await?;
+    if !tournee_dates.is_empty() {
+        let dates = tournee_dates.iter().map(NaiveDate::to_string).collect::<Vec<_>>().join(", ");
+        return Err(AppError::Conflict(format!(
+            "El vehículo tiene tournées asignadas en la ventana: {}",
+            dates
+        )));
+    }
+
+    sqlx::query_as::<_, VehicleMaintenance>(&format!(
+        r#"
+        INSERT INTO vehicle_maintenance (
+            company_id, vehicle_id, plan_id, task_name, scheduled_start, scheduled_end,
+            workshop, notes, created_by
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        RETURNING {}
+        "#,
+        VEHICLE_MAINTENANCE_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(vehicle_id)
+    .bind(request.plan_id)
+    .bind(&task_name)
+    .bind(request.scheduled_start)
+    .bind(request.scheduled_end)
+    .bind(&request.workshop)
+    .bind(&request.notes)
+    .bind(user.user_id)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| match &e {
+        // Otra reserva simultánea: no_overlapping_maintenance
+        sqlx::Error::Database(db) if db.code().as_deref() == Some("23P01") => {
+            AppError::Conflict("El vehículo ya tiene una cita de taller en esa ventana".to_string())
+        }
+        _ => AppError::Database(e),
+    })
+}
+
+/// Devolver el vehículo a servicio si ya no tiene otra cita en curso
+async fn release_vehicle(conn: &mut PgConnection, vehicle_id: Uuid) -> AppResult<()> {
+    sqlx::query(
+        r#"
+        UPDATE vehicles SET vehicle_status = 'active', updated_at = NOW()
+        WHERE id = $1 AND vehicle_status = 'maintenance'
+        AND NOT EXISTS (
+            SELECT 1 FROM vehicle_maintenance
+            WHERE vehicle_id = $1 AND maintenance_status = 'in_progress'
+        )
+        "#,
+    )
+    .bind(vehicle_id)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Pasar una cita a otro estado
+pub async fn transition(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    id: Uuid,
+    request: &MaintenanceTransitionRequest,
+) -> AppResult<VehicleMaintenance> {
+    let booking = sqlx::query_as::<_, VehicleMaintenance>(&format!(
+        "SELECT {} FROM vehicle_maintenance WHERE id = $1 AND company_id = $2 FOR UPDATE",
+        VEHICLE_MAINTENANCE_COLUMNS
+    ))
+    .bind(id)
+    .bind(user.company_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Cita de taller no encontrada".to_string()))?;
+
+    let from = MaintenanceStatus::parse(&booking.maintenance_status)
+        .ok_or_else(|| AppError::Internal(format!("Estado de cita desconocido: {}", booking.maintenance_status)))?;
+    let to = request.maintenance_status;
+    if !maintenance_transition_allowed(from, to) {
+        return Err(AppError::BadRequest(format!(
+            "No se puede pasar una cita de '{}' a '{}'",
+            from.as_str(),
+            to.as_str()
+        )));
+    }
+
+    let current_mileage = sqlx::query_scalar::<_, Decimal>("SELECT current_mileage FROM vehicles WHERE id = $1 FOR UPDATE")
+        .bind(booking.vehicle_id)
+        .fetch_one(&mut *conn)
+        .await?;
+    let mileage_at_service = match to {
+        MaintenanceStatus::Completed => Some(request.mileage_at_service.unwrap_or(current_mileage)),
+        _ => None,
+    };
+
+    let updated = sqlx::query_as::<_, VehicleMaintenance>(&format!(
+        r#"
+        UPDATE vehicle_maintenance SET
+            maintenance_status = $2,
+            started_at = CASE WHEN $2 = 'in_progress' THEN NOW() ELSE started_at END,
+            completed_at = CASE WHEN $2 = 'completed' THEN NOW() ELSE completed_at END,
+            mileage_at_service = COALESCE($3, mileage_at_service),
+            cost = COALESCE($4, cost),
+            notes = COALESCE($5, notes)
+        WHERE id = $1
+        RETURNING {}
+        "#,
+        VEHICLE_MAINTENANCE_COLUMNS
+    ))
+    .bind(booking.id)
+    .bind(to.as_str())
+    .bind(mileage_at_service)
+    .bind(request.cost)
+    .bind(&request.notes)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    match to {
+        MaintenanceStatus::InProgress => {
+            sqlx::query(
+                "UPDATE vehicles SET vehicle_status = 'maintenance', updated_at = NOW() WHERE id = $1 AND vehicle_status = 'active'",
+            )
+            .bind(booking.vehicle_id)
+            .execute(&mut *conn)
+            .await?;
+        }
+        MaintenanceStatus::Completed => {
+            sqlx::query(
+                "UPDATE vehicles SET current_mileage = GREATEST(current_mileage, $2), updated_at = NOW() WHERE id = $1",
+            )
+            .bind(booking.vehicle_id)
+            .bind(mileage_at_service)
+            .execute(&mut *conn)
+            .await?;
+
+            // La tarea vuelve a contar desde este servicio; el job recalcula el vencimiento
+            if let Some(plan_id) = booking.plan_id {
+                sqlx::query(
+                    r#"
+                    UPDATE vehicle_maintenance_schedule SET
+                        last_service_date = (NOW() AT TIME ZONE 'Europe/Paris')::date,
+                        last_service_mileage = $3,
+                        due_state = 'ok',
+                        reminder_sent = FALSE,
+                        overdue_sent = FALSE
+                    WHERE vehicle_id = $1 AND plan_id = $2
+                    "#,
+                )
+                .bind(booking.vehicle_id)
+                .bind(plan_id)
+                .bind(mileage_at_service)
+                .execute(&mut *conn)
+                .await?;
+            }
+            release_vehicle(conn, booking.vehicle_id).await?;
+        }
+        MaintenanceStatus::Cancelled => release_vehicle(conn, booking.vehicle_id).await?,
+        MaintenanceStatus::Scheduled => {}
+    }
+
+    log::info!(
+        "🔧 Cita {} ({}) del vehículo {}: {} → {}",
+        updated.id,
+        updated.task_name,
+        updated.vehicle_id,
+        from.as_str(),
+        to.as_str()
+    );
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(interval_km: Option<i32>, interval_months: Option<i32>) -> MaintenancePlan {
+        MaintenancePlan {
+            id: Uuid::nil(),
+            company_id: Uuid::nil(),
+            brand: "Renault".to_string(),
+            model: "Master".to_string(),
+            task_name: "Vidange".to_string(),
+            description: None,
+            interval_km,
+            interval_months,
+            estimated_hours: None,
+            is_active: true,
+            created_at: None,
+            updated_at: None,
+            deleted_at: None,
+        }
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn usage(current_mileage: i64, avg_daily_km: f64) -> VehicleUsage {
+        VehicleUsage {
+            current_mileage: Decimal::from(current_mileage),
+            in_service_since: date(2023, 1, 10),
+            avg_daily_km,
+        }
+    }
+
+    #[test]
+    fn test_forecast_by_mileage_and_calendar() {
+        let policy = MaintenancePolicy::default();
+        let today = date(2024, 3, 1);
+        let last = Some((date(2023, 12, 1), Decimal::from(40_000)));
+
+        // 30 000 km o 12 meses: a 100 km/día faltan 5 000 km = 50 días
+        let forecast = forecast(&plan(Some(30_000), Some(12)), last, &usage(65_000, 100.0), today, &policy);
+        assert_eq!(forecast.due_mileage, Some(Decimal::from(70_000)));
+        assert_eq!(forecast.due_date, Some(date(2024, 12, 1)));
+        assert_eq!(forecast.projected_date, Some(date(2024, 4, 20)));
+        assert_eq!(forecast.days_remaining, Some(50));
+        assert_eq!(forecast.due_state, DueState::Ok);
+
+        // A 600 km del vencimiento: dentro del margen de 1 000 km
+        let forecast = super::forecast(&plan(Some(30_000), None), last, &usage(69_400, 0.0), today, &policy);
+        assert_eq!(forecast.projected_date, None);
+        assert_eq!(forecast.due_state, DueState::Upcoming);
+
+        // Calendario vencido aunque queden km
+        let forecast = super::forecast(&plan(Some(30_000), Some(3)), last, &usage(50_000, 50.0), today, &policy);
+        assert_eq!(forecast.due_date, Some(date(2024, 3, 1)));
+        assert_eq!(forecast.due_state, DueState::Overdue);
+    }
+
+    #[test]
+    fn test_forecast_without_previous_service() {
+        let policy = MaintenancePolicy::default();
+        let today = date(2024, 3, 1);
+
+        // Siguiente múltiplo del intervalo y meses desde el alta
+        let forecast = forecast(&plan(Some(20_000), Some(24)), None, &usage(45_500, 0.0), today, &policy);
+        assert_eq!(forecast.due_mileage, Some(Decimal::from(60_000)));
+        assert_eq!(forecast.due_date, Some(date(2025, 1, 10)));
+        assert_eq!(forecast.due_state, DueState::Ok);
+    }
+
+    #[test]
+    fn test_maintenance_transitions() {
+        use MaintenanceStatus::*;
+        assert!(maintenance_transition_allowed(Scheduled, InProgress));
+        assert!(maintenance_transition_allowed(InProgress, Completed));
+        assert!(maintenance_transition_allowed(Scheduled, Cancelled));
+        assert!(!maintenance_transition_allowed(Completed, InProgress));
+        assert!(!maintenance_transition_allowed(Cancelled, Scheduled));
+        assert!(!maintenance_transition_allowed(InProgress, Scheduled));
+    }
+}
@@ -0,0 +1,477 @@
+//! Servicio de archivos multimedia
+//!
+//! Valida las imágenes subidas (tamaño, tipo real del contenido, dimensiones),
+//! genera miniaturas JPEG, las guarda en el `StorageBackend` configurado y
+//! escribe la URL firmada de descarga en la columna de la entidad
+//! (`packages.delivery_photo`, `tournees.pre_inspection_photos`, ...).
+
+use chrono::{DateTime, Duration, Utc};
+use image::{ImageFormat, ImageReader, Limits};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::io::Cursor;
+use uuid::Uuid;
+
+use crate::models::media::{MediaObject, MediaObjectResponse, MediaTarget};
+use crate::services::media_storage::{hmac_sha256, MediaConfig, StorageBackend, StorageError};
+use crate::utils::errors::{AppError, AppResult};
+
+/// Lado máximo de la miniatura en píxeles
+const THUMBNAIL_SIZE: u32 = 320;
+/// Dimensión máxima aceptada (protege contra imágenes "bomba")
+const MAX_IMAGE_DIMENSION: u32 = 10_000;
+const THUMBNAIL_JPEG_QUALITY: u8 = 80;
+
+const MEDIA_OBJECT_COLUMNS: &str = r#"
+    id, company_id, target, entity_id, storage_backend, storage_key, thumbnail_key,
+    content_type, size_bytes, content_sha256, width, height, original_filename, uploaded_by, created_at
+"#;
+
+/// Imagen validada lista para guardar
+#[derive(Debug)]
+pub struct ValidatedImage {
+    pub content_type: &'static str,
+    pub extension: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub thumbnail: Vec<u8>,
+}
+
+/// Servicio de almacenamiento y firma de URLs
+pub struct MediaService {
+    backend: Box<dyn StorageBackend>,
+    signing_key: Vec<u8>,
+    url_ttl: Duration,
+    public_base_url: String,
+    max_upload_bytes: usize,
+}
+
+impl MediaService {
+    /// Crear el servicio; `MEDIA_SIGNING_KEY` es obligatoria
+    pub fn new(config: &MediaConfig, backend: Box<dyn StorageBackend>) -> Result<Self, StorageError> {
+        let signing_key = config
+            .signing_key
+            .as_deref()
+            .ok_or_else(|| StorageError::Config("MEDIA_SIGNING_KEY no configurada".to_string()))?;
+
+        Ok(Self {
+            backend,
+            signing_key: signing_key.as_bytes().to_vec(),
+            url_ttl: Duration::seconds(config.url_ttl_seconds),
+            public_base_url: config.public_base_url.clone(),
+            max_upload_bytes: config.max_upload_bytes,
+        })
+    }
+
+    pub fn backend_name(&self) -> &'static str {
+        self.backend.name()
+    }
+
+    pub fn max_upload_bytes(&self) -> usize {
+        self.max_upload_bytes
+    }
+
+    /// URL de descarga firmada (HMAC de clave + expiración)
+    pub fn signed_url(&self, key: &str, now: DateTime<Utc>) -> (String, DateTime<Utc>) {
+        let expires_at = now + self.url_ttl;
+        let expires = expires_at.timestamp();
+        let signature = hex::encode(hmac_sha256(&self.signing_key, format!("{}\n{}", key, expires).as_bytes()));
+
+        (
+            format!(
+                "{}/api/media/{}?expires={}&signature={}",
+                self.public_base_url, key, expires, signature
+            ),
+            expires_at,
+        )
+    }
+
+    /// Verificar la firma de una URL de descarga
+    pub fn verify_signature(&self, key: &str, expires: i64, signature: &str, now: DateTime<Utc>) -> bool {
+        if expires < now.timestamp() {
+            return false;
+        }
+        let Ok(signature) = hex::decode(signature) else {
+            return false;
+        };
+
+        use hmac::Mac;
+        let mut mac = <hmac::Hmac<sha2::Sha256>>::new_from_slice(&self.signing_key)
+            .expect("HMAC acepta claves de cualquier longitud");
+        mac.update(format!("{}\n{}", key, expires).as_bytes());
+        mac.verify_slice(&signature).is_ok()
+    }
+
+    /// Leer un objeto del backend
+    pub async fn download(&self, key: &str) -> AppResult<Vec<u8>> {
+        self.backend.get(key).await.map_err(storage_error)
+    }
+
+    /// Validar, guardar y asociar una imagen a su entidad
+    #[allow(clippy::too_many_arguments)]
+    pub async fn upload(
+        &self,
+        pool: &PgPool,
+        company_id: Uuid,
+        uploaded_by: Uuid,
+        target: MediaTarget,
+        entity_id: Uuid,
+        original_filename: Option<String>,
+        declared_content_type: Option<String>,
+        bytes: Vec<u8>,
+    ) -> AppResult<MediaObjectResponse> {
+        if !entity_exists(pool, company_id, target, entity_id).await? {
+            return Err(AppError::NotFound(format!("{} {} no encontrado", target.table(), entity_id)));
+        }
+
+        let max_upload_bytes = self.max_upload_bytes;
+        let (bytes, image) = tokio::task::spawn_blocking(move || {
+            let image = validate_image(&bytes, declared_content_type.as_deref(), max_upload_bytes)?;
+            Ok::<_, AppError>((bytes, image))
+        })
+        .await
+        .map_err(|e| AppError::Internal(format!("Error procesando imagen: {}", e)))??;
+
+        let object_id = Uuid::new_v4();
+        let prefix = format!("{}/{}/{}/{}", company_id, target.as_str(), entity_id, object_id);
+        let storage_key = format!("{}.{}", prefix, image.extension);
+        let thumbnail_key = format!("{}_thumb.jpg", prefix);
+        let size_bytes = bytes.len() as i64;
+        let content_sha256 = hex::encode(Sha256::digest(&bytes));
+
+        self.backend
+            .put(&storage_key, bytes, image.content_type)
+            .await
+            .map_err(storage_error)?;
+        if let Err(e) = self.backend.put(&thumbnail_key, image.thumbnail, "image/jpeg").await {
+            self.discard(&[&storage_key]).await;
+            return Err(storage_error(e));
+        }
+
+        let now = Utc::now();
+        let (url, url_expires_at) = self.signed_url(&storage_key, now);
+
+        let saved = async {
+            let mut tx = pool.begin().await?;
+
+            let object = sqlx::query_as::<_, MediaObject>(&format!(
+                r#"
+                INSERT INTO media_objects (
+                    id, company_id, target, entity_id, storage_backend, storage_key, thumbnail_key,
+                    content_type, size_bytes, content_sha256, width, height, original_filename, uploaded_by
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+                RETURNING {}
+                "#,
+                MEDIA_OBJECT_COLUMNS
+            ))
+            .bind(object_id)
+            .bind(company_id)
+            .bind(target.as_str())
+            .bind(entity_id)
+            .bind(self.backend.name())
+            .bind(&storage_key)
+            .bind(&thumbnail_key)
+            .bind(image.content_type)
+            .bind(size_bytes)
+            .bind(&content_sha256)
+            .bind(image.width as i32)
+            .bind(image.height as i32)
+            .bind(&original_filename)
+            .bind(uploaded_by)
+            .fetch_one(&mut *tx)
+            .await?;
+
+            sqlx::query(&column_update_sql(target))
+                .bind(&url)
+                .bind(entity_id)
+                .bind(company_id)
+                .execute(&mut *tx)
+                .await?;
+
+            tx.commit().await?;
+            Ok::<_, AppError>(object)
+        }
+        .await;
+
+        let object = match saved {
+            Ok(object) => object,
+            Err(e) => {
+                self.discard(&[&storage_key, &thumbnail_key]).await;
+                return Err(e);
+            }
+        };
+
+        log::info!(
+            "📸 Archivo guardado ({}): {} {} -> {} ({} bytes, {}x{})",
+            self.backend.name(),
+            target.as_str(),
+            entity_id,
+            storage_key,
+            size_bytes,
+            image.width,
+            image.height
+        );
+
+        let (thumbnail_url, _) = self.signed_url(&thumbnail_key, now);
+        Ok(MediaObjectResponse {
+            object,
+            url,
+            thumbnail_url: Some(thumbnail_url),
+            url_expires_at,
+        })
+    }
+
+    /// Obtener un archivo con URLs firmadas nuevas
+    pub async fn get(&self, pool: &PgPool, company_id: Uuid, id: Uuid) -> AppResult<MediaObjectResponse> {
+        let object = sqlx::query_as::<_, MediaObject>(&format!(
+            "SELECT {} FROM media_objects WHERE id = $1 AND company_id = $2",
+            MEDIA_OBJECT_COLUMNS
+        ))
+        .bind(id)
+        .bind(company_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Archivo no encontrado".to_string()))?;
+
+        let now = Utc::now();
+        let (url, url_expires_at) = self.signed_url(&object.storage_key, now);
+        let thumbnail_url = object.thumbnail_key.as_deref().map(|key| self.signed_url(key, now).0);
+
+        Ok(MediaObjectResponse {
+            object,
+            url,
+            thumbnail_url,
+            url_expires_at,
+        })
+    }
+
+    /// Borrar objetos huérfanos tras un fallo (mejor esfuerzo)
+    async fn discard(&self, keys: &[&str]) {
+        for key in keys {
+            if let Err(e) = self.backend.delete(key).await {
+                log::warn!("⚠️ No se pudo borrar el objeto huérfano {}: {}", key, e);
+            }
+        }
+    }
+}
+
+/// Validar tamaño, tipo real y dimensiones; generar la miniatura
+pub fn validate_image(
+    bytes: &[u8],
+    declared_content_type: Option<&str>,
+    max_upload_bytes: usize,
+) -> AppResult<ValidatedImage> {
+    if bytes.is_empty() {
+        return Err(AppError::BadRequest("El archivo está vacío".to_string()));
+    }
+    if bytes.len() > max_upload_bytes {
+        return Err(AppError::BadRequest(format!(
+            "El archivo supera el tamaño máximo de {} bytes",
+            max_upload_bytes
+        )));
+    }
+
+    // El tipo se determina por el contenido, no por el nombre ni la cabecera
+    let format = image::guess_format(bytes)
+        .map_err(|_| AppError::BadRequest("Formato de imagen no reconocido".to_string()))?;
+    let (content_type, extension) = match format {
+        ImageFormat::Jpeg => ("image/jpeg", "jpg"),
+        ImageFormat::Png => ("image/png", "png"),
+        ImageFormat::WebP => ("image/webp", "webp"),
+        other => {
+            return Err(AppError::BadRequest(format!(
+                "Formato {:?} no permitido (solo JPEG, PNG o WebP)",
+                other
+            )))
+        }
+    };
+
+    if let Some(declared) = declared_content_type.map(|ct| ct.split(';').next().unwrap_or(ct).trim().to_lowercase()) {
+        let declared = if declared == "image/jpg" { "image/jpeg".to_string() } else { declared };
+        if declared != "application/octet-stream" && declared != content_type {
+            return Err(AppError::BadRequest(format!(
+                "El tipo declarado ({}) no coincide con el contenido ({})",
+                declared, content_type
+            )));
+        }
+    }
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+    limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+
+    let mut reader = ImageReader::with_format(Cursor::new(bytes), format);
+    reader.limits(limits);
+    let decoded = reader
+        .decode()
+        .map_err(|e| AppError::BadRequest(format!("Imagen inválida: {}", e)))?;
+
+    let mut thumbnail = Vec::new();
+    let thumb = decoded.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).to_rgb8();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut thumbnail, THUMBNAIL_JPEG_QUALITY)
+        .encode_image(&thumb)
+        .map_err(|e| AppError::Internal(format!("Error generando miniatura: {}", e)))?;
+
+    Ok(ValidatedImage {
+        content_type,
+        extension,
+        width: decoded.width(),
+        height: decoded.height(),
+        thumbnail,
+    })
+}
+
+/// Tipo de contenido a partir de la extensión de la clave
+pub fn content_type_for_key(key: &str) -> &'static str {
+    match key.rsplit('.').next() {
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Comprobar que la entidad existe y pertenece a la empresa
+async fn entity_exists(pool: &PgPool, company_id: Uuid, target: MediaTarget, entity_id: Uuid) -> AppResult<bool> {
+    let sql = match target.table() {
+        "vehicle_damages" => {
+            r#"
+            SELECT EXISTS(
+                SELECT 1 FROM vehicle_damages vd
+                JOIN vehicles v ON v.id = vd.vehicle_id
+                WHERE vd.id = $1 AND v.company_id = $2 AND vd.deleted_at IS NULL
+            )
+            "#
+            .to_string()
+        }
+        table => format!(
+            "SELECT EXISTS(SELECT 1 FROM {} WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL)",
+            table
+        ),
+    };
+
+    Ok(sqlx::query_scalar::<_, bool>(&sql)
+        .bind(entity_id)
+        .bind(company_id)
+        .fetch_one(pool)
+        .await?)
+}
+
+/// UPDATE de la columna de la entidad ($1 url, $2 id, $3 company_id)
+fn column_update_sql(target: MediaTarget) -> String {
+    let column = target.column();
+    let value = if target.is_array() {
+        format!("array_append(COALESCE({}, '{{}}'), $1)", column)
+    } else {
+        "$1".to_string()
+    };
+
+    match target.table() {
+        "vehicle_damages" => format!(
+            r#"
+            UPDATE vehicle_damages vd SET {} = {}
+            FROM vehicles v
+            WHERE vd.id = $2 AND v.id = vd.vehicle_id AND v.company_id = $3
+            "#,
+            column,
+            value.replace(column, &format!("vd.{}", column))
+        ),
+        table => format!(
+            "UPDATE {} SET {} = {} WHERE id = $2 AND company_id = $3",
+            table, column, value
+        ),
+    }
+}
+
+fn storage_error(error: StorageError) -> AppError {
+    match error {
+        StorageError::NotFound(key) => AppError::NotFound(format!("Archivo no encontrado: {}", key)),
+        StorageError::InvalidKey(key) => AppError::BadRequest(format!("Clave inválida: {}", key)),
+        StorageError::Remote(msg) => AppError::ServiceUnavailable(format!("Almacenamiento no disponible: {}", msg)),
+        other => AppError::Internal(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::media_storage::LocalStorage;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image::RgbImage::new(width, height)
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_validate_image_and_thumbnail() {
+        let image = validate_image(&png(1200, 600), Some("image/png"), 1024 * 1024).unwrap();
+        assert_eq!(image.content_type, "image/png");
+        assert_eq!((image.width, image.height), (1200, 600));
+
+        let thumb = image::load_from_memory(&image.thumbnail).unwrap();
+        assert_eq!((thumb.width(), thumb.height()), (THUMBNAIL_SIZE, THUMBNAIL_SIZE / 2));
+    }
+
+    #[test]
+    fn test_validate_image_rejects_invalid_uploads() {
+        let bytes = png(10, 10);
+        // Tipo declarado distinto del contenido
+        assert!(validate_image(&bytes, Some("image/jpeg"), 1024).is_err());
+        // Tamaño máximo
+        assert!(validate_image(&bytes, None, 16).is_err());
+        // No es una imagen
+        assert!(validate_image(b"%PDF-1.4 not an image", None, 1024).is_err());
+        assert!(validate_image(&[], None, 1024).is_err());
+    }
+
+    #[test]
+    fn test_signing_key_is_required() {
+        let backend = Box::new(LocalStorage::new(std::env::temp_dir()));
+        assert!(matches!(
+            MediaService::new(&MediaConfig::default(), backend),
+            Err(StorageError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn test_signed_urls() {
+        let config = MediaConfig {
+            signing_key: Some("secret".to_string()),
+            ..MediaConfig::default()
+        };
+        let service = MediaService::new(&config, Box::new(LocalStorage::new(std::env::temp_dir()))).unwrap();
+        let now = Utc::now();
+        let (url, expires_at) = service.signed_url("c1/delivery-photo/p1/a.jpg", now);
+
+        let query = url.split_once('?').unwrap().1;
+        let params: std::collections::HashMap<_, _> = query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .collect();
+        let expires: i64 = params["expires"].parse().unwrap();
+        assert_eq!(expires, expires_at.timestamp());
+
+        assert!(service.verify_signature("c1/delivery-photo/p1/a.jpg", expires, params["signature"], now));
+        assert!(!service.verify_signature("c1/delivery-photo/p1/b.jpg", expires, params["signature"], now));
+        assert!(!service.verify_signature(
+            "c1/delivery-photo/p1/a.jpg",
+            expires,
+            params["signature"],
+            expires_at + Duration::seconds(1)
+        ));
+    }
+
+    #[test]
+    fn test_column_update_sql() {
+        assert_eq!(
+            column_update_sql(MediaTarget::DeliveryPhoto),
+            "UPDATE packages SET delivery_photo = $1 WHERE id = $2 AND company_id = $3"
+        );
+        assert!(column_update_sql(MediaTarget::DamageEvidence)
+            .contains("photo_evidence = array_append(COALESCE(vd.photo_evidence, '{}'), $1)"));
+    }
+}
@@ -10,8 +10,36 @@ pub mod colis_prive_companies_service;
 // pub mod colis_prive_flow_service; // Comentado temporalmente por errores de compilación
 // pub mod colis_prive_complete_flow_service; // Comentado temporalmente por errores de compilación
 pub mod colis_prive_web_service;
+pub mod compte_rendu;
 pub mod geocoding_service;
 pub mod address_validation;
+pub mod address_confidence;
+pub mod address_validation_jobs;
+pub mod assignment_planner;
+pub mod driver_field_data_service;
+pub mod field_encryption;
+pub mod field_data_revisions;
+pub mod field_knowledge;
+pub mod media_storage;
+pub mod media_service;
+pub mod delivery_proof;
+pub mod dispatch_feed;
+pub mod eta;
+pub mod failed_delivery;
+pub mod fuel_reconciliation;
+pub mod geofencing;
+pub mod lifecycle;
+pub mod location_tracking;
+pub mod maintenance;
+pub mod notifications;
+pub mod offline_sync;
+pub mod package_delivery;
+pub mod performance_analytics;
+pub mod performance_anomalies;
+pub mod pickups;
+pub mod scan_events;
+pub mod vehicle_damages;
+pub mod vehicle_documents;
 pub mod hybrid_processor;
 
 pub use colis_prive_service::*;
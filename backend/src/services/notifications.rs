@@ -0,0 +1,37 @@
+//! Registro de notificaciones en notifications_log
+//!
+//! Las notificaciones son de la empresa (`admin_id` vacío): las ven todos
+//! sus admins. Quien las genera decide cuándo registrar cada una; aquí solo
+//! se guardan.
+
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::{models::notification::NewNotification, utils::errors::AppResult};
+
+/// Guardar una notificación y devolver su id
+pub async fn record(conn: &mut PgConnection, notification: &NewNotification) -> AppResult<Uuid> {
+    let id = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        INSERT INTO notifications_log (
+            company_id, document_id, vehicle_id, driver_id, notification_type,
+            notification_priority, title, message, metadata
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        RETURNING id
+        "#,
+    )
+    .bind(notification.company_id)
+    .bind(notification.document_id)
+    .bind(notification.vehicle_id)
+    .bind(notification.driver_id)
+    .bind(notification.notification_type)
+    .bind(notification.priority)
+    .bind(&notification.title)
+    .bind(&notification.message)
+    .bind(&notification.metadata)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    log::info!("🔔 {}: {}", notification.title, notification.message);
+    Ok(id)
+}
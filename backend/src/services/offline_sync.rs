@@ -0,0 +1,547 @@
+//! Sincronización offline
+//!
+//! Aplica en orden las mutaciones que la app acumuló sin cobertura y calcula
+//! los cambios del servidor desde el último cursor.
+//!
+//! - Idempotencia: cada mutación se guarda en `sync_mutations` con su clave
+//!   en la misma transacción que la aplica; si el lote se reenvía, se
+//!   devuelve el resultado guardado sin volver a aplicarla.
+//! - Conflictos: last-writer-wins por hora del dispositivo. Si el servidor
+//!   cambió el estado (o las notas) del paquete después de la hora de la
+//!   mutación, o el paquete ya está cerrado, gana el servidor y se devuelve
+//!   su estado.
+//! - Delta: paquetes de las tournées del chofer (desde anteayer) ordenados por
+//!   `(updated_at, id)`, paginados con un cursor opaco.
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde_json::Value;
+use sqlx::{PgConnection, PgPool};
+use tracing::{info, warn};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    middleware::auth::{driver_scope, AuthenticatedUser},
+    models::package::{DeliveryStatus, Package, PackageResponse, PACKAGE_COLUMNS},
+    models::scan_event::ScanValidation,
+    models::sync::{SyncDelta, SyncMutation, SyncMutationResult, SyncOperation, SyncOutcome},
+    models::tournee::{Tournee, TourneeResponse, TOURNEE_COLUMNS},
+    services::{delivery_proof, package_delivery, scan_events},
+    services::failed_delivery::FailedDeliveryPolicy,
+    utils::errors::{AppError, AppResult},
+};
+
+/// Paquetes por página del delta
+pub const DELTA_PAGE_SIZE: i64 = 1000;
+
+/// Días hacia atrás de las tournées que se sincronizan
+const DELTA_WINDOW_DAYS: i64 = 2;
+
+/// Solape del cursor final, para no perder cambios de transacciones que
+/// todavía no habían hecho commit al leer
+const CURSOR_OVERLAP_SECONDS: i64 = 5;
+
+/// Cursor opaco `<updated_at RFC 3339>_<id>`
+pub fn encode_cursor(updated_at: DateTime<Utc>, id: Uuid) -> String {
+    format!("{}_{}", updated_at.to_rfc3339(), id)
+}
+
+pub fn decode_cursor(cursor: &str) -> AppResult<(DateTime<Utc>, Uuid)> {
+    let invalid = || AppError::BadRequest(format!("Cursor de sincronización inválido: {}", cursor));
+
+    let (timestamp, id) = cursor.rsplit_once('_').ok_or_else(invalid)?;
+    let updated_at = DateTime::parse_from_rfc3339(timestamp)
+        .map_err(|_| invalid())?
+        .with_timezone(&Utc);
+    let id = Uuid::parse_str(id).map_err(|_| invalid())?;
+
+    Ok((updated_at, id))
+}
+
+/// Cursor para la siguiente sincronización cuando el delta se ha agotado
+pub fn final_cursor(started_at: DateTime<Utc>) -> String {
+    encode_cursor(started_at - Duration::seconds(CURSOR_OVERLAP_SECONDS), Uuid::nil())
+}
+
+/// Last-writer-wins: la mutación pierde si el servidor cambió el dato después
+pub fn server_wins(server_changed_at: Option<DateTime<Utc>>, device_timestamp: DateTime<Utc>) -> bool {
+    server_changed_at.is_some_and(|changed_at| changed_at > device_timestamp)
+}
+
+/// Hora efectiva de una mutación: un reloj adelantado no puede ganar a
+/// cambios posteriores del servidor
+pub fn effective_timestamp(device_timestamp: DateTime<Utc>, now: DateTime<Utc>) -> DateTime<Utc> {
+    device_timestamp.min(now)
+}
+
+fn is_closed(status: &DeliveryStatus) -> bool {
+    matches!(
+        status,
+        DeliveryStatus::Delivered | DeliveryStatus::Returned | DeliveryStatus::Cancelled
+    )
+}
+
+/// Aplicar un lote de mutaciones en orden
+///
+/// Los errores de base de datos cortan el lote (las mutaciones ya aplicadas
+/// quedan guardadas y el reenvío es seguro); los errores de la propia
+/// mutación la rechazan y el lote continúa.
+pub async fn apply_batch(
+    pool: &PgPool,
+    user: &AuthenticatedUser,
+    device_id: Option<&str>,
+    mutations: &[Value],
+    policy: &FailedDeliveryPolicy,
+) -> AppResult<Vec<SyncMutationResult>> {
+    let mut results = Vec::with_capacity(mutations.len());
+
+    for raw in mutations {
+        let mutation = match serde_json::from_value::<SyncMutation>(raw.clone()) {
+            Ok(mutation) => mutation,
+            Err(e) => {
+                results.push(rejected(
+                    raw.get("idempotency_key").and_then(Value::as_str).map(str::to_string),
+                    None,
+                    format!("Mutación mal formada: {}", e),
+                ));
+                continue;
+            }
+        };
+
+        if mutation.idempotency_key.is_empty() || mutation.idempotency_key.len() > 100 {
+            results.push(rejected(
+                Some(mutation.idempotency_key.clone()),
+                mutation.operation.package_id(),
+                "La clave de idempotencia debe tener entre 1 y 100 caracteres".to_string(),
+            ));
+            continue;
+        }
+
+        results.push(apply_mutation(pool, user, device_id, raw, &mutation, policy).await?);
+    }
+
+    let conflicts = results.iter().filter(|r| r.outcome == SyncOutcome::Conflict).count();
+    info!(
+        "🔄 Sync de {}: {} mutaciones, {} conflictos",
+        user.user_id,
+        results.len(),
+        conflicts
+    );
+
+    Ok(results)
+}
+
+async fn apply_mutation(
+    pool: &PgPool,
+    user: &AuthenticatedUser,
+    device_id: Option<&str>,
+    raw: &Value,
+    mutation: &SyncMutation,
+    policy: &FailedDeliveryPolicy,
+) -> AppResult<SyncMutationResult> {
+    if let Some(stored) = stored_result(pool, user, &mutation.idempotency_key).await? {
+        return Ok(stored);
+    }
+
+    let mut tx = pool.begin().await?;
+    let result = match apply_operation(&mut tx, user, device_id, mutation, policy).await {
+        Ok(result) => result,
+        Err(AppError::Database(e)) => return Err(AppError::Database(e)),
+        Err(e) => {
+            tx.rollback().await?;
+            let result = rejected(
+                Some(mutation.idempotency_key.clone()),
+                mutation.operation.package_id(),
+                e.to_string(),
+            );
+            store_result(pool, user, device_id, raw, mutation, &result).await?;
+            return Ok(result);
+        }
+    };
+
+    // Si otro envío del mismo lote se adelantó, la clave ya existe: se
+    // descarta lo aplicado aquí y se devuelve el resultado guardado
+    if !store_result(&mut *tx, user, device_id, raw, mutation, &result).await? {
+        tx.rollback().await?;
+        return stored_result(pool, user, &mutation.idempotency_key)
+            .await?
+            .ok_or_else(|| AppError::Internal("Mutación de sincronización perdida".to_string()));
+    }
+    tx.commit().await?;
+
+    Ok(result)
+}
+
+async fn apply_operation(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    device_id: Option<&str>,
+    mutation: &SyncMutation,
+    policy: &FailedDeliveryPolicy,
+) -> AppResult<SyncMutationResult> {
+    let key = Some(mutation.idempotency_key.clone());
+    let timestamp = effective_timestamp(mutation.device_timestamp, Utc::now());
+
+    let package = match &mutation.operation {
+        SyncOperation::Scan { .. } => None,
+        operation => match operation.package_id() {
+            Some(package_id) => Some(package_delivery::lock_package(&mut *conn, user, package_id).await?),
+            None => None,
+        },
+    };
+
+    match (&mutation.operation, package) {
+        (SyncOperation::Delivered { package_id, data }, Some(package)) => {
+            data.validate().map_err(AppError::Validation)?;
+            if is_closed(&package.delivery_status) || server_wins(package.status_changed_at, timestamp) {
+                return Ok(conflict(key, package, "El estado del paquete cambió en el servidor"));
+            }
+            let package = package_delivery::deliver_package(&mut *conn, user, *package_id, data, timestamp).await?;
+            Ok(applied(key, Some(package.id)))
+        }
+        (SyncOperation::Failed { package_id, data }, Some(package)) => {
+            data.validate().map_err(AppError::Validation)?;
+            if is_closed(&package.delivery_status) || server_wins(package.status_changed_at, timestamp) {
+                return Ok(conflict(key, package, "El estado del paquete cambió en el servidor"));
+            }
+            let outcome = package_delivery::fail_package(&mut *conn, user, *package_id, data, timestamp, policy).await?;
+            Ok(applied(key, Some(outcome.package.id)))
+        }
+        (SyncOperation::Note { package_id, driver_notes }, Some(package)) => {
+            if server_wins(package.notes_changed_at, timestamp) {
+                return Ok(conflict(key, package, "Las notas del paquete cambiaron en el servidor"));
+            }
+            sqlx::query(
+                r#"
+                UPDATE packages
+                SET driver_notes = $2, notes_changed_at = $3, updated_at = NOW()
+                WHERE id = $1
+                "#,
+            )
+            .bind(package_id)
+            .bind(driver_notes)
+            .bind(timestamp)
+            .execute(&mut *conn)
+            .await?;
+            Ok(applied(key, Some(*package_id)))
+        }
+        (SyncOperation::Proof { proof, .. }, Some(package)) => {
+            proof.validate().map_err(AppError::Validation)?;
+            if package.delivery_status != DeliveryStatus::Delivered {
+                return Err(AppError::BadRequest(
+                    "Solo se puede adjuntar la prueba de un paquete entregado".to_string(),
+                ));
+            }
+            if proof_exists(&mut *conn, package.id).await? {
+                return Ok(conflict(key, package, "El paquete ya tiene prueba de entrega"));
+            }
+            let saved = delivery_proof::record(&mut *conn, &package, user.user_id, proof).await?;
+            Ok(applied(key, Some(saved.package_id)))
+        }
+        (SyncOperation::Scan { data }, _) => {
+            data.validate().map_err(AppError::Validation)?;
+            let mut data = data.clone();
+            data.scanned_at.get_or_insert(mutation.device_timestamp);
+            if data.device_id.is_none() {
+                data.device_id = device_id.map(str::to_string);
+            }
+            let event = scan_events::record(&mut *conn, user, &data).await?;
+            let mut result = applied(key, event.package_id);
+            if event.validation_status != ScanValidation::Valid.as_str() {
+                result.message = Some(event.validation_status);
+            }
+            Ok(result)
+        }
+        (_, None) => Err(AppError::BadRequest("La mutación no indica el paquete".to_string())),
+    }
+}
+
+/// Bloquear el paquete mientras se decide el conflicto
+async fn proof_exists(conn: &mut PgConnection, package_id: Uuid) -> AppResult<bool> {
+    let exists = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM delivery_proofs WHERE package_id = $1)",
+    )
+    .bind(package_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(exists)
+}
+
+/// Resultado guardado de una clave ya procesada
+async fn stored_result(
+    pool: &PgPool,
+    user: &AuthenticatedUser,
+    idempotency_key: &str,
+) -> AppResult<Option<SyncMutationResult>> {
+    let stored = sqlx::query_scalar::<_, Value>(
+        r#"
+        SELECT result FROM sync_mutations
+        WHERE company_id = $1 AND user_id = $2 AND idempotency_key = $3
+        "#,
+    )
+    .bind(user.company_id)
+    .bind(user.user_id)
+    .bind(idempotency_key)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(stored.and_then(|value| match serde_json::from_value::<SyncMutationResult>(value) {
+        Ok(mut result) => {
+            result.replayed = true;
+            Some(result)
+        }
+        Err(e) => {
+            warn!("⚠️ Resultado de sincronización ilegible ({}): {}", idempotency_key, e);
+            None
+        }
+    }))
+}
+
+/// Guardar el resultado de una mutación; `false` si la clave ya existía
+async fn store_result<'c, E>(
+    executor: E,
+    user: &AuthenticatedUser,
+    device_id: Option<&str>,
+    raw: &Value,
+    mutation: &SyncMutation,
+    result: &SyncMutationResult,
+) -> AppResult<bool>
+where
+    E: sqlx::PgExecutor<'c>,
+{
+    let inserted = sqlx::query(
+        r#"
+        INSERT INTO sync_mutations (
+            company_id, user_id, device_id, idempotency_key, mutation_type,
+            entity_id, payload, device_timestamp, outcome, result
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        ON CONFLICT (company_id, user_id, idempotency_key) DO NOTHING
+        "#,
+    )
+    .bind(user.company_id)
+    .bind(user.user_id)
+    .bind(device_id)
+    .bind(&mutation.idempotency_key)
+    .bind(mutation.operation.as_str())
+    .bind(result.entity_id)
+    .bind(raw)
+    .bind(mutation.device_timestamp)
+    .bind(result.outcome.as_str())
+    .bind(serde_json::to_value(result).map_err(|e| AppError::Internal(e.to_string()))?)
+    .execute(executor)
+    .await?;
+
+    Ok(inserted.rows_affected() == 1)
+}
+
+fn applied(idempotency_key: Option<String>, entity_id: Option<Uuid>) -> SyncMutationResult {
+    SyncMutationResult {
+        idempotency_key,
+        outcome: SyncOutcome::Applied,
+        entity_id,
+        message: None,
+        server_state: None,
+        replayed: false,
+    }
+}
+
+fn conflict(idempotency_key: Option<String>, package: Package, message: &str) -> SyncMutationResult {
+    SyncMutationResult {
+        idempotency_key,
+        outcome: SyncOutcome::Conflict,
+        entity_id: Some(package.id),
+        message: Some(message.to_string()),
+        server_state: serde_json::to_value(PackageResponse::from(package)).ok(),
+        replayed: false,
+    }
+}
+
+fn rejected(idempotency_key: Option<String>, entity_id: Option<Uuid>, message: String) -> SyncMutationResult {
+    SyncMutationResult {
+        idempotency_key,
+        outcome: SyncOutcome::Rejected,
+        entity_id,
+        message: Some(message),
+        server_state: None,
+        replayed: false,
+    }
+}
+
+/// Estado actual de los paquetes modificados por el lote (para el canal en
+/// vivo); las mutaciones repetidas de lotes anteriores no cuentan
+pub async fn applied_packages(
+    pool: &PgPool,
+    company_id: Uuid,
+    results: &[SyncMutationResult],
+) -> AppResult<Vec<Package>> {
+    let mut ids: Vec<Uuid> = results
+        .iter()
+        .filter(|r| r.outcome == SyncOutcome::Applied && !r.replayed)
+        .filter_map(|r| r.entity_id)
+        .collect();
+    ids.sort();
+    ids.dedup();
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let packages = sqlx::query_as::<_, Package>(&format!(
+        "SELECT {} FROM packages WHERE company_id = $1 AND id = ANY($2)",
+        PACKAGE_COLUMNS
+    ))
+    .bind(company_id)
+    .bind(&ids)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(packages)
+}
+
+/// Cambios del servidor desde el cursor; devuelve también el siguiente cursor
+pub async fn delta(
+    pool: &PgPool,
+    user: &AuthenticatedUser,
+    cursor: Option<&str>,
+    started_at: DateTime<Utc>,
+) -> AppResult<(SyncDelta, String)> {
+    let (since, since_id) = match cursor {
+        Some(cursor) => decode_cursor(cursor)?,
+        None => (DateTime::<Utc>::UNIX_EPOCH, Uuid::nil()),
+    };
+    let window_start: NaiveDate = started_at.date_naive() - Duration::days(DELTA_WINDOW_DAYS);
+
+    let mut rows = sqlx::query_as::<_, Package>(&format!(
+        r#"
+        SELECT {}
+        FROM packages
+        WHERE company_id = $1
+        AND tournee_id IN (
+            SELECT id FROM tournees
+            WHERE company_id = $1 AND tournee_date >= $3
+            AND ($2::uuid IS NULL OR driver_id = $2)
+        )
+        AND (updated_at, id) > ($4, $5)
+        ORDER BY updated_at, id
+        LIMIT $6
+        "#,
+        PACKAGE_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(driver_scope(user))
+    .bind(window_start)
+    .bind(since)
+    .bind(since_id)
+    .bind(DELTA_PAGE_SIZE + 1)
+    .fetch_all(pool)
+    .await?;
+
+    let has_more = rows.len() as i64 > DELTA_PAGE_SIZE;
+    rows.truncate(DELTA_PAGE_SIZE as usize);
+
+    let next_cursor = match rows.last() {
+        Some(last) if has_more => encode_cursor(last.updated_at.unwrap_or(since), last.id),
+        _ => final_cursor(started_at),
+    };
+
+    let tournees = sqlx::query_as::<_, Tournee>(&format!(
+        r#"
+        SELECT {}
+        FROM tournees
+        WHERE company_id = $1 AND tournee_date >= $3
+        AND ($2::uuid IS NULL OR driver_id = $2)
+        AND updated_at > $4
+        AND deleted_at IS NULL
+        ORDER BY tournee_date, tournee_number
+        "#,
+        TOURNEE_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(driver_scope(user))
+    .bind(window_start)
+    .bind(since)
+    .fetch_all(pool)
+    .await?;
+
+    let (deleted, live): (Vec<Package>, Vec<Package>) = rows.into_iter().partition(|p| p.deleted_at.is_some());
+
+    Ok((
+        SyncDelta {
+            packages: live.into_iter().map(PackageResponse::from).collect(),
+            deleted_package_ids: deleted.into_iter().map(|p| p.id).collect(),
+            tournees: tournees.into_iter().map(TourneeResponse::from).collect(),
+            has_more,
+        },
+        next_cursor,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(h: u32, m: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 3, 10, h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn test_cursor_roundtrip() {
+        let id = Uuid::new_v4();
+        let updated_at = at(9, 30) + Duration::microseconds(123_456);
+
+        let cursor = encode_cursor(updated_at, id);
+        assert_eq!(decode_cursor(&cursor).unwrap(), (updated_at, id));
+    }
+
+    #[test]
+    fn test_decode_cursor_rejects_garbage() {
+        assert!(decode_cursor("").is_err());
+        assert!(decode_cursor("ayer_no-es-un-uuid").is_err());
+        assert!(decode_cursor(&format!("2025-03-10_{}", Uuid::nil())).is_err());
+    }
+
+    #[test]
+    fn test_final_cursor_overlaps() {
+        let (since, id) = decode_cursor(&final_cursor(at(10, 0))).unwrap();
+        assert_eq!(since, at(10, 0) - Duration::seconds(CURSOR_OVERLAP_SECONDS));
+        assert_eq!(id, Uuid::nil());
+    }
+
+    #[test]
+    fn test_last_writer_wins() {
+        // El servidor no ha tocado el dato: gana la mutación
+        assert!(!server_wins(None, at(9, 0)));
+        // Cambio del servidor anterior a la mutación offline
+        assert!(!server_wins(Some(at(8, 59)), at(9, 0)));
+        // Cambio del servidor posterior (p. ej. el admin canceló el paquete)
+        assert!(server_wins(Some(at(9, 1)), at(9, 0)));
+    }
+
+    #[test]
+    fn test_effective_timestamp_clamps_future_clocks() {
+        assert_eq!(effective_timestamp(at(9, 0), at(10, 0)), at(9, 0));
+        assert_eq!(effective_timestamp(at(11, 0), at(10, 0)), at(10, 0));
+    }
+
+    #[test]
+    fn test_mutation_parsing() {
+        let mutation: SyncMutation = serde_json::from_value(serde_json::json!({
+            "idempotency_key": "k-1",
+            "device_timestamp": "2025-03-10T09:00:00Z",
+            "type": "failed",
+            "package_id": Uuid::nil(),
+            "failure_reason": "recipient_not_home"
+        }))
+        .unwrap();
+        assert_eq!(mutation.operation.as_str(), "failed");
+        assert_eq!(mutation.operation.package_id(), Some(Uuid::nil()));
+
+        let unknown = serde_json::from_value::<SyncMutation>(serde_json::json!({
+            "idempotency_key": "k-2",
+            "device_timestamp": "2025-03-10T09:00:00Z",
+            "type": "teleport"
+        }));
+        assert!(unknown.is_err());
+    }
+}
@@ -0,0 +1,182 @@
+//! Entregas y fallos de paquetes
+//!
+//! Cambios de estado que hace el chofer sobre un paquete de sus tournées,
+//! compartidos por los handlers de `api::packages` y la sincronización
+//! offline. Todo se ejecuta dentro de la transacción del llamador con el
+//! paquete bloqueado (`FOR UPDATE`).
+
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::{
+    middleware::auth::{driver_scope, AuthenticatedUser},
+    models::delivery_proof::ProofOfDeliveryRequest,
+    models::package::{
+        DeliveryFailureReason, DeliveryStatus, MarkDeliveredRequest, MarkFailedRequest, Package,
+        PACKAGE_COLUMNS,
+    },
+    services::delivery_proof,
+    services::failed_delivery::{self, FailedDeliveryPolicy, FailureOutcome},
+    services::lifecycle,
+    utils::errors::{AppError, AppResult},
+    utils::validation::parse_date,
+};
+
+/// Condición de visibilidad: los choferes solo acceden a sus tournées (`$3`)
+pub const DRIVER_SCOPE_FILTER: &str =
+    "($3::uuid IS NULL OR tournee_id IN (SELECT id FROM tournees WHERE driver_id = $3))";
+
+/// Bloquear un paquete visible para el usuario dentro de la transacción
+pub async fn lock_package(conn: &mut PgConnection, user: &AuthenticatedUser, id: Uuid) -> AppResult<Package> {
+    sqlx::query_as::<_, Package>(&format!(
+        r#"
+        SELECT {}
+        FROM packages
+        WHERE id = $1 AND company_id = $2 AND {}
+        AND deleted_at IS NULL
+        FOR UPDATE
+        "#,
+        PACKAGE_COLUMNS, DRIVER_SCOPE_FILTER
+    ))
+    .bind(id)
+    .bind(user.company_id)
+    .bind(driver_scope(user))
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Paquete no encontrado".to_string()))
+}
+
+/// Entregar un paquete dentro de una transacción (`delivered_at` es la hora
+/// del dispositivo)
+pub async fn deliver_package(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    id: Uuid,
+    delivery_data: &MarkDeliveredRequest,
+    delivered_at: DateTime<Utc>,
+) -> AppResult<Package> {
+    let current = lock_package(&mut *conn, user, id).await?;
+    lifecycle::ensure_package_transition(&mut *conn, &current, &DeliveryStatus::Delivered).await?;
+
+    let package = sqlx::query_as::<_, Package>(&format!(
+        r#"
+        UPDATE packages SET
+            delivery_status = 'delivered',
+            delivery_date = ($8::timestamptz AT TIME ZONE 'Europe/Paris')::date,
+            delivery_time = ($8::timestamptz AT TIME ZONE 'Europe/Paris')::time,
+            delivery_attempts = COALESCE(delivery_attempts, 0) + 1,
+            delivery_photo = COALESCE($2, delivery_photo),
+            signature_image = COALESCE($3, signature_image),
+            signature_photo = COALESCE($4, signature_photo),
+            delivery_duration_minutes = COALESCE($5, delivery_duration_minutes),
+            driver_notes = $6,
+            package_condition = $7,
+            failure_reason = NULL,
+            next_action = NULL,
+            status_changed_at = $8,
+            updated_at = NOW()
+        WHERE id = $1
+        RETURNING {}
+        "#,
+        PACKAGE_COLUMNS
+    ))
+    .bind(current.id)
+    .bind(&delivery_data.delivery_photo)
+    .bind(&delivery_data.signature_image)
+    .bind(&delivery_data.signature_photo)
+    .bind(delivery_data.delivery_duration_minutes)
+    .bind(&delivery_data.driver_notes)
+    .bind(&delivery_data.package_condition)
+    .bind(delivered_at)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    lifecycle::record_package(
+        &mut *conn,
+        user,
+        package.id,
+        &current.delivery_status,
+        &package.delivery_status,
+        json!({}),
+    )
+    .await?;
+
+    record_delivery_proof(&mut *conn, user, &package, delivery_data.proof.as_ref()).await?;
+
+    Ok(package)
+}
+
+/// Registrar la prueba de entrega de un paquete recién entregado
+///
+/// Sin prueba solo se aceptan los paquetes sin `signature_required`.
+pub async fn record_delivery_proof(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    package: &Package,
+    proof: Option<&ProofOfDeliveryRequest>,
+) -> AppResult<()> {
+    match proof {
+        Some(proof) => {
+            delivery_proof::record(&mut *conn, package, user.user_id, proof).await?;
+        }
+        None if package.signature_required => {
+            return Err(AppError::BadRequest(
+                "Este paquete requiere una prueba de entrega con firma".to_string(),
+            ));
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+/// Registrar un fallo de entrega dentro de una transacción (`failed_at` es la
+/// hora del dispositivo)
+pub async fn fail_package(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    id: Uuid,
+    failure_data: &MarkFailedRequest,
+    failed_at: DateTime<Utc>,
+    policy: &FailedDeliveryPolicy,
+) -> AppResult<FailureOutcome> {
+    let failure_reason = parse_failure_reason(&failure_data.failure_reason)?;
+    let reschedule_date = failure_data.reschedule_date.as_deref().map(parse_date).transpose()?;
+
+    let package = lock_package(&mut *conn, user, id).await?;
+    lifecycle::ensure_package_transition(&mut *conn, &package, &DeliveryStatus::Failed).await?;
+
+    let outcome = failed_delivery::process_failure(
+        &mut *conn,
+        &package,
+        failure_reason,
+        failure_data,
+        reschedule_date,
+        policy,
+        failed_at,
+    )
+    .await?;
+
+    lifecycle::record_package(
+        &mut *conn,
+        user,
+        package.id,
+        &package.delivery_status,
+        &outcome.package.delivery_status,
+        json!({
+            "failure_reason": failure_data.failure_reason,
+            "tournee_id": package.tournee_id,
+            "action": outcome.action,
+            "next_tournee_id": outcome.next_tournee_id,
+        }),
+    )
+    .await?;
+
+    Ok(outcome)
+}
+
+pub fn parse_failure_reason(value: &str) -> AppResult<DeliveryFailureReason> {
+    DeliveryFailureReason::parse(value)
+        .ok_or_else(|| AppError::BadRequest(format!("Motivo de fallo desconocido: {}", value)))
+}
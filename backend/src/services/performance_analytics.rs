@@ -0,0 +1,761 @@
+//! Agregación semanal del rendimiento de los choferes
+//!
+//! Cada semana (lunes a domingo) de cada chofer se calcula a partir de sus
+//! tournées completadas, los paquetes de esas tournées, el combustible (el
+//! de la tournée o, si falta, el de la tarjeta en el cuadre) y los daños
+//! declarados esa semana, y se guarda en `performance_analytics` con upsert
+//! sobre `(driver_id, week_start_date)`.
+//!
+//! El job recalcula cada día las últimas semanas cerradas para recoger
+//! correcciones tardías; `POST /analytics/weekly/recompute` recalcula
+//! cualquier rango. Si un chofer ya no tiene actividad en una semana
+//! recalculada su fila se borra.
+//!
+//! Cada vehículo tiene además su fila semanal en `vehicle_performance_weeks`
+//! (km, combustible, consumo y coste por km de sus tournées completadas),
+//! que se recalcula con la empresa entera: un recálculo de un solo chofer no
+//! la toca.
+//!
+//! Después de cada semana se buscan anomalías (ver
+//! `services::performance_anomalies`).
+
+use chrono::{Datelike, Duration, NaiveDate};
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+use sqlx::{FromRow, PgConnection, PgPool};
+use std::env;
+use uuid::Uuid;
+
+use crate::{
+    models::analytics::WeeklyAggregationSummary,
+    services::performance_anomalies,
+    utils::errors::{AppError, AppResult},
+};
+
+/// Semanas que se pueden recalcular de una vez
+const MAX_RECOMPUTE_WEEKS: i64 = 53;
+
+/// Configuración de la agregación semanal
+#[derive(Debug, Clone)]
+pub struct AnalyticsPolicy {
+    /// Semanas cerradas que recalcula cada pasada del job
+    pub recompute_weeks: i64,
+    /// Cada cuántas horas se ejecuta el job
+    pub check_interval_hours: u64,
+    /// Semanas anteriores del chofer con las que se compara
+    pub history_weeks: i64,
+    /// Semanas de histórico necesarias para el z-score
+    pub min_history_weeks: usize,
+    /// |z| a partir del que se marca la semana
+    pub z_threshold: f64,
+    /// Choferes de la empresa necesarios para comparar con la mediana
+    pub min_peers: usize,
+    /// Factor k de las vallas de Tukey
+    pub iqr_factor: f64,
+    /// Con una referencia sin dispersión (histórico constante o IQR nulo),
+    /// desviación mínima en la dirección mala, en % de la referencia
+    pub flat_min_delta_pct: f64,
+    /// Velocidad máxima creíble entre dos entregas seguidas (km/h)
+    pub max_speed_kmh: f64,
+    /// Entregas a velocidad imposible a partir de las que se marca la semana
+    pub min_implausible_hops: usize,
+}
+
+impl Default for AnalyticsPolicy {
+    fn default() -> Self {
+        Self {
+            recompute_weeks: 2,
+            check_interval_hours: 24,
+            history_weeks: 8,
+            min_history_weeks: 4,
+            z_threshold: 2.0,
+            min_peers: 4,
+            iqr_factor: 1.5,
+            flat_min_delta_pct: 10.0,
+            max_speed_kmh: 130.0,
+            min_implausible_hops: 3,
+        }
+    }
+}
+
+impl AnalyticsPolicy {
+    /// Cargar desde `ANALYTICS_*`
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let read = |name: &str| env::var(name).ok();
+
+        Self {
+            recompute_weeks: read("ANALYTICS_RECOMPUTE_WEEKS")
+                .and_then(|v| v.parse::<i64>().ok())
+                .filter(|v| (1..=MAX_RECOMPUTE_WEEKS).contains(v))
+                .unwrap_or(defaults.recompute_weeks),
+            check_interval_hours: read("ANALYTICS_CHECK_INTERVAL_HOURS")
+                .and_then(|v| v.parse::<u64>().ok())
+                .filter(|v| *v > 0)
+                .unwrap_or(defaults.check_interval_hours),
+            history_weeks: read("ANALYTICS_HISTORY_WEEKS")
+                .and_then(|v| v.parse::<i64>().ok())
+                .filter(|v| *v > 0)
+                .unwrap_or(defaults.history_weeks),
+            min_history_weeks: read("ANALYTICS_MIN_HISTORY_WEEKS")
+                .and_then(|v| v.parse::<usize>().ok())
+                .filter(|v| *v >= 2)
+                .unwrap_or(defaults.min_history_weeks),
+            z_threshold: read("ANALYTICS_Z_THRESHOLD")
+                .and_then(|v| v.parse::<f64>().ok())
+                .filter(|v| *v > 0.0)
+                .unwrap_or(defaults.z_threshold),
+            min_peers: read("ANALYTICS_MIN_PEERS")
+                .and_then(|v| v.parse::<usize>().ok())
+                .filter(|v| *v >= 2)
+                .unwrap_or(defaults.min_peers),
+            iqr_factor: read("ANALYTICS_IQR_FACTOR")
+                .and_then(|v| v.parse::<f64>().ok())
+                .filter(|v| *v > 0.0)
+                .unwrap_or(defaults.iqr_factor),
+            flat_min_delta_pct: read("ANALYTICS_FLAT_MIN_DELTA_PCT")
+                .and_then(|v| v.parse::<f64>().ok())
+                .filter(|v| *v > 0.0)
+                .unwrap_or(defaults.flat_min_delta_pct),
+            max_speed_kmh: read("ANALYTICS_MAX_SPEED_KMH")
+                .and_then(|v| v.parse::<f64>().ok())
+                .filter(|v| *v > 0.0)
+                .unwrap_or(defaults.max_speed_kmh),
+            min_implausible_hops: read("ANALYTICS_MIN_IMPLAUSIBLE_HOPS")
+                .and_then(|v| v.parse::<usize>().ok())
+                .filter(|v| *v > 0)
+                .unwrap_or(defaults.min_implausible_hops),
+        }
+    }
+}
+
+/// Lunes de la semana de `date`
+pub fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+/// Totales de un chofer en una semana
+#[derive(Debug, Clone, Default, FromRow)]
+pub struct WeekInputs {
+    pub company_id: Uuid,
+    pub driver_id: Uuid,
+    pub total_packages: i64,
+    pub delivered: i64,
+    pub failed: i64,
+    pub km: Decimal,
+    pub fuel_liters: Decimal,
+    pub fuel_cost: Decimal,
+    pub working_minutes: f64,
+    /// Suma de `delivery_duration_minutes` de las entregas que lo tienen
+    pub delivery_minutes: f64,
+    pub timed_deliveries: i64,
+    pub route_score: Option<Decimal>,
+    pub damage_incidents: i64,
+    pub damage_cost: Decimal,
+}
+
+/// Horas de una semana: más horas de trabajo son datos de tournée erróneos
+const WEEK_HOURS: f64 = 168.0;
+
+/// Métricas de la semana, ya ajustadas a la precisión de las columnas
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeekMetrics {
+    pub total_packages: i32,
+    pub successful_deliveries: i32,
+    pub failed_deliveries: i32,
+    pub delivery_success_rate: Option<Decimal>,
+    pub km_driven: Decimal,
+    pub fuel_consumed: Decimal,
+    pub fuel_cost: Decimal,
+    pub fuel_efficiency: Option<Decimal>,
+    pub total_working_hours: Option<Decimal>,
+    pub average_delivery_time_minutes: Option<Decimal>,
+    pub route_optimization_score: Option<Decimal>,
+    pub damage_incidents: i32,
+    pub total_damage_cost: Decimal,
+    pub damage_score: Option<Decimal>,
+    pub efficiency_ratio: Option<Decimal>,
+    pub cost_per_package: Option<Decimal>,
+}
+
+/// Redondear a 2 decimales sin pasar del máximo de la columna
+fn fit(value: f64, max: f64) -> Option<Decimal> {
+    fit_dp(value, max, 2)
+}
+
+fn fit_dp(value: f64, max: f64, dp: u32) -> Option<Decimal> {
+    if !value.is_finite() {
+        return None;
+    }
+    Decimal::from_f64(value.clamp(0.0, max)).map(|d| d.round_dp(dp))
+}
+
+fn fit_decimal(value: Decimal, max: f64) -> Decimal {
+    fit(value.to_f64().unwrap_or(0.0), max).unwrap_or(Decimal::ZERO)
+}
+
+/// Calcular las métricas de la semana
+///
+/// - Éxito: entregados sobre el total de paquetes.
+/// - Consumo en L/100 km.
+/// - Tiempo medio de entrega: el registrado en los paquetes o, si no hay,
+///   las horas de trabajo repartidas entre las entregas.
+/// - Daños: incidentes por cada 1000 km.
+/// - Eficiencia: entregas por hora de trabajo.
+/// - Coste por paquete: combustible entre entregas (los daños van aparte).
+pub fn compute(inputs: &WeekInputs) -> WeekMetrics {
+    let km = inputs.km.to_f64().unwrap_or(0.0);
+    let liters = inputs.fuel_liters.to_f64().unwrap_or(0.0);
+    let fuel_cost = inputs.fuel_cost.to_f64().unwrap_or(0.0);
+    let delivered = inputs.delivered as f64;
+    let mut hours = inputs.working_minutes / 60.0;
+    if hours > WEEK_HOURS {
+        log::error!(
+            "❌ {:.1} horas de trabajo del chofer {} en una semana: se descartan (revisar sus tournées)",
+            hours,
+            inputs.driver_id
+        );
+        hours = 0.0;
+    }
+
+    let average_delivery = if inputs.timed_deliveries > 0 {
+        Some(inputs.delivery_minutes / inputs.timed_deliveries as f64)
+    } else if inputs.delivered > 0 && hours > 0.0 {
+        Some(hours * 60.0 / delivered)
+    } else {
+        None
+    };
+
+    WeekMetrics {
+        total_packages: inputs.total_packages as i32,
+        successful_deliveries: inputs.delivered as i32,
+        failed_deliveries: inputs.failed as i32,
+        delivery_success_rate: (inputs.total_packages > 0)
+            .then(|| fit(delivered / inputs.total_packages as f64 * 100.0, 100.0))
+            .flatten(),
+        km_driven: fit_decimal(inputs.km, 999_999.99),
+        fuel_consumed: fit_decimal(inputs.fuel_liters, 999.99),
+        fuel_cost: fit_decimal(inputs.fuel_cost, 999_999.99),
+        fuel_efficiency: (km > 0.0 && liters > 0.0)
+            .then(|| fit(liters / km * 100.0, 9_999.99))
+            .flatten(),
+        total_working_hours: (hours > 0.0).then(|| fit(hours, WEEK_HOURS)).flatten(),
+        average_delivery_time_minutes: average_delivery.and_then(|minutes| fit(minutes, 999.99)),
+        route_optimization_score: inputs.route_score.map(|score| fit_decimal(score, 9.99)),
+        damage_incidents: inputs.damage_incidents as i32,
+        total_damage_cost: fit_decimal(inputs.damage_cost, 99_999_999.99),
+        damage_score: (km > 0.0)
+            .then(|| fit(inputs.damage_incidents as f64 / km * 1000.0, 9.99))
+            .flatten(),
+        efficiency_ratio: (hours > 0.0).then(|| fit(delivered / hours, 999.99)).flatten(),
+        cost_per_package: (inputs.delivered > 0)
+            .then(|| fit(fuel_cost / delivered, 9_999.99))
+            .flatten(),
+    }
+}
+
+/// Totales de un vehículo en una semana
+#[derive(Debug, Clone, Default, FromRow)]
+pub struct VehicleWeekInputs {
+    pub company_id: Uuid,
+    pub vehicle_id: Uuid,
+    pub km: Decimal,
+    pub fuel_liters: Decimal,
+    pub fuel_cost: Decimal,
+}
+
+/// Métricas de la semana de un vehículo
+#[derive(Debug, Clone, PartialEq)]
+pub struct VehicleWeekMetrics {
+    pub km_driven: Decimal,
+    pub fuel_consumed: Decimal,
+    pub fuel_cost: Decimal,
+    /// L/100 km
+    pub fuel_efficiency: Option<Decimal>,
+    /// € de combustible por km
+    pub cost_per_km: Option<Decimal>,
+}
+
+/// Calcular el consumo y el coste por km de un vehículo
+pub fn compute_vehicle(inputs: &VehicleWeekInputs) -> VehicleWeekMetrics {
+    let km = inputs.km.to_f64().unwrap_or(0.0);
+    let liters = inputs.fuel_liters.to_f64().unwrap_or(0.0);
+    let cost = inputs.fuel_cost.to_f64().unwrap_or(0.0);
+
+    VehicleWeekMetrics {
+        km_driven: fit_decimal(inputs.km, 999_999.99),
+        fuel_consumed: fit_decimal(inputs.fuel_liters, 99_999.99),
+        fuel_cost: fit_decimal(inputs.fuel_cost, 999_999.99),
+        fuel_efficiency: (km > 0.0 && liters > 0.0)
+            .then(|| fit(liters / km * 100.0, 9_999.99))
+            .flatten(),
+        cost_per_km: (km > 0.0 && cost > 0.0)
+            .then(|| fit_dp(cost / km, 999.999, 3))
+            .flatten(),
+    }
+}
+
+/// Tournées completadas de la semana con su km, litros y coste (los de la
+/// tournée o, si faltan, los del cuadre)
+const WEEK_TOURNEES_CTE: &str = r#"
+        week_tournees AS (
+            SELECT t.id, t.company_id, t.driver_id, t.vehicle_id, t.route_optimization_score,
+                   COALESCE(t.total_distance, t.end_mileage - t.start_mileage, r.odometer_km, 0) AS km,
+                   COALESCE(t.fuel_consumed, NULLIF(r.card_liters, 0), 0) AS liters,
+                   COALESCE(t.fuel_cost, NULLIF(r.card_amount, 0), 0) AS cost,
+                   COALESCE(
+                       t.actual_duration_minutes::float8,
+                       EXTRACT(EPOCH FROM (t.end_time - t.start_time))::float8 / 60,
+                       0
+                   ) AS minutes
+            FROM tournees t
+            LEFT JOIN fuel_reconciliations r ON r.tournee_id = t.id
+            WHERE t.tournee_date BETWEEN $1 AND $1 + 6
+            AND t.tournee_status = 'completed' AND t.deleted_at IS NULL
+            AND ($2::uuid IS NULL OR t.company_id = $2)
+            AND ($3::uuid IS NULL OR t.driver_id = $3)
+        )"#;
+
+/// Totales por vehículo de la semana `[start, start + 6]`
+async fn load_vehicle_inputs(
+    conn: &mut PgConnection,
+    start: NaiveDate,
+    company_id: Option<Uuid>,
+) -> AppResult<Vec<VehicleWeekInputs>> {
+    Ok(sqlx::query_as::<_, VehicleWeekInputs>(&format!(
+        r#"
+        WITH {}
+        SELECT company_id, vehicle_id,
+               SUM(km)::numeric AS km,
+               SUM(liters)::numeric AS fuel_liters,
+               SUM(cost)::numeric AS fuel_cost
+        FROM week_tournees
+        GROUP BY company_id, vehicle_id
+        "#,
+        WEEK_TOURNEES_CTE
+    ))
+    .bind(start)
+    .bind(company_id)
+    .bind(None::<Uuid>)
+    .fetch_all(&mut *conn)
+    .await?)
+}
+
+/// Guardar la semana de un vehículo (las banderas de anomalías no se tocan)
+async fn upsert_vehicle_week(conn: &mut PgConnection, start: NaiveDate, inputs: &VehicleWeekInputs) -> AppResult<()> {
+    let metrics = compute_vehicle(inputs);
+
+    sqlx::query(
+        r#"
+        INSERT INTO vehicle_performance_weeks (
+            company_id, vehicle_id, week_start_date, km_driven, fuel_consumed,
+            fuel_cost, fuel_efficiency, cost_per_km
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (vehicle_id, week_start_date) DO UPDATE SET
+            company_id = EXCLUDED.company_id,
+            km_driven = EXCLUDED.km_driven,
+            fuel_consumed = EXCLUDED.fuel_consumed,
+            fuel_cost = EXCLUDED.fuel_cost,
+            fuel_efficiency = EXCLUDED.fuel_efficiency,
+            cost_per_km = EXCLUDED.cost_per_km,
+            updated_at = NOW()
+        "#,
+    )
+    .bind(inputs.company_id)
+    .bind(inputs.vehicle_id)
+    .bind(start)
+    .bind(metrics.km_driven)
+    .bind(metrics.fuel_consumed)
+    .bind(metrics.fuel_cost)
+    .bind(metrics.fuel_efficiency)
+    .bind(metrics.cost_per_km)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Recalcular las filas de vehículo de la semana y borrar las de vehículos
+/// sin tournées; devuelve las filas calculadas
+async fn aggregate_vehicle_week(conn: &mut PgConnection, start: NaiveDate, company_id: Option<Uuid>) -> AppResult<u64> {
+    let inputs = load_vehicle_inputs(&mut *conn, start, company_id).await?;
+    for vehicle in &inputs {
+        upsert_vehicle_week(&mut *conn, start, vehicle).await?;
+    }
+
+    let computed: Vec<Uuid> = inputs.iter().map(|vehicle| vehicle.vehicle_id).collect();
+    sqlx::query(
+        r#"
+        DELETE FROM vehicle_performance_weeks
+        WHERE week_start_date = $1
+        AND ($2::uuid IS NULL OR company_id = $2)
+        AND NOT (vehicle_id = ANY($3))
+        "#,
+    )
+    .bind(start)
+    .bind(company_id)
+    .bind(&computed)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(inputs.len() as u64)
+}
+
+/// Totales por chofer de la semana `[start, start + 6]`
+async fn load_inputs(
+    conn: &mut PgConnection,
+    start: NaiveDate,
+    company_id: Option<Uuid>,
+    driver_id: Option<Uuid>,
+) -> AppResult<Vec<WeekInputs>> {
+    Ok(sqlx::query_as::<_, WeekInputs>(&format!(
+        r#"
+        WITH {},
+        week_packages AS (
+            SELECT p.tournee_id,
+                   COUNT(*) AS total,
+                   COUNT(*) FILTER (WHERE p.delivery_status = 'delivered') AS delivered,
+                   COUNT(*) FILTER (WHERE p.delivery_status = 'failed') AS failed,
+                   SUM(p.delivery_duration_minutes) FILTER (WHERE p.delivery_status = 'delivered') AS delivery_minutes,
+                   COUNT(p.delivery_duration_minutes) FILTER (WHERE p.delivery_status = 'delivered') AS timed
+            FROM packages p
+            JOIN week_tournees w ON w.id = p.tournee_id
+            WHERE p.deleted_at IS NULL
+            GROUP BY p.tournee_id
+        ),
+        driver_tournees AS (
+            SELECT w.company_id, w.driver_id,
+                   SUM(COALESCE(p.total, 0)) AS total_packages,
+                   SUM(COALESCE(p.delivered, 0)) AS delivered,
+                   SUM(COALESCE(p.failed, 0)) AS failed,
+                   SUM(w.km) AS km,
+                   SUM(w.liters) AS liters,
+                   SUM(w.cost) AS cost,
+                   SUM(w.minutes) AS minutes,
+                   SUM(COALESCE(p.delivery_minutes, 0)) AS delivery_minutes,
+                   SUM(COALESCE(p.timed, 0)) AS timed,
+                   AVG(w.route_optimization_score) AS route_score
+            FROM week_tournees w
+            LEFT JOIN week_packages p ON p.tournee_id = w.id
+            GROUP BY w.company_id, w.driver_id
+        ),
+        driver_damages AS (
+            SELECT v.company_id, d.driver_id,
+                   COUNT(*) AS incidents,
+                   SUM(COALESCE(d.actual_repair_cost, d.estimated_repair_cost, 0)) AS cost
+            FROM vehicle_damages d
+            JOIN vehicles v ON v.id = d.vehicle_id
+            WHERE d.deleted_at IS NULL AND d.driver_id IS NOT NULL
+            AND d.incident_date BETWEEN $1 AND $1 + 6
+            AND ($2::uuid IS NULL OR v.company_id = $2)
+            AND ($3::uuid IS NULL OR d.driver_id = $3)
+            GROUP BY v.company_id, d.driver_id
+        )
+        SELECT COALESCE(t.company_id, d.company_id) AS company_id,
+               COALESCE(t.driver_id, d.driver_id) AS driver_id,
+               COALESCE(t.total_packages, 0)::bigint AS total_packages,
+               COALESCE(t.delivered, 0)::bigint AS delivered,
+               COALESCE(t.failed, 0)::bigint AS failed,
+               COALESCE(t.km, 0)::numeric AS km,
+               COALESCE(t.liters, 0)::numeric AS fuel_liters,
+               COALESCE(t.cost, 0)::numeric AS fuel_cost,
+               COALESCE(t.minutes, 0)::float8 AS working_minutes,
+               COALESCE(t.delivery_minutes, 0)::float8 AS delivery_minutes,
+               COALESCE(t.timed, 0)::bigint AS timed_deliveries,
+               t.route_score::numeric AS route_score,
+               COALESCE(d.incidents, 0)::bigint AS damage_incidents,
+               COALESCE(d.cost, 0)::numeric AS damage_cost
+        FROM driver_tournees t
+        FULL OUTER JOIN driver_damages d ON d.driver_id = t.driver_id AND d.company_id = t.company_id
+        "#,
+        WEEK_TOURNEES_CTE
+    ))
+    .bind(start)
+    .bind(company_id)
+    .bind(driver_id)
+    .fetch_all(&mut *conn)
+    .await?)
+}
+
+/// Guardar la semana de un chofer (las banderas de anomalías no se tocan)
+async fn upsert_week(conn: &mut PgConnection, start: NaiveDate, inputs: &WeekInputs) -> AppResult<()> {
+    let metrics = compute(inputs);
+
+    sqlx::query(
+        r#"
+        INSERT INTO performance_analytics (
+            company_id, driver_id, week_start_date, week_end_date, total_packages,
+            successful_deliveries, failed_deliveries, delivery_success_rate, km_driven,
+            fuel_consumed, fuel_cost, fuel_efficiency, total_working_hours,
+            average_delivery_time_minutes, route_optimization_score, damage_incidents,
+            total_damage_cost, damage_score, efficiency_ratio, cost_per_package
+        ) VALUES ($1, $2, $3, $3 + 6, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19)
+        ON CONFLICT (driver_id, week_start_date) DO UPDATE SET
+            company_id = EXCLUDED.company_id,
+            week_end_date = EXCLUDED.week_end_date,
+            total_packages = EXCLUDED.total_packages,
+            successful_deliveries = EXCLUDED.successful_deliveries,
+            failed_deliveries = EXCLUDED.failed_deliveries,
+            delivery_success_rate = EXCLUDED.delivery_success_rate,
+            km_driven = EXCLUDED.km_driven,
+            fuel_consumed = EXCLUDED.fuel_consumed,
+            fuel_cost = EXCLUDED.fuel_cost,
+            fuel_efficiency = EXCLUDED.fuel_efficiency,
+            total_working_hours = EXCLUDED.total_working_hours,
+            average_delivery_time_minutes = EXCLUDED.average_delivery_time_minutes,
+            route_optimization_score = EXCLUDED.route_optimization_score,
+            damage_incidents = EXCLUDED.damage_incidents,
+            total_damage_cost = EXCLUDED.total_damage_cost,
+            damage_score = EXCLUDED.damage_score,
+            efficiency_ratio = EXCLUDED.efficiency_ratio,
+            cost_per_package = EXCLUDED.cost_per_package,
+            updated_at = NOW()
+        "#,
+    )
+    .bind(inputs.company_id)
+    .bind(inputs.driver_id)
+    .bind(start)
+    .bind(metrics.total_packages)
+    .bind(metrics.successful_deliveries)
+    .bind(metrics.failed_deliveries)
+    .bind(metrics.delivery_success_rate)
+    .bind(metrics.km_driven)
+    .bind(metrics.fuel_consumed)
+    .bind(metrics.fuel_cost)
+    .bind(metrics.fuel_efficiency)
+    .bind(metrics.total_working_hours)
+    .bind(metrics.average_delivery_time_minutes)
+    .bind(metrics.route_optimization_score)
+    .bind(metrics.damage_incidents)
+    .bind(metrics.total_damage_cost)
+    .bind(metrics.damage_score)
+    .bind(metrics.efficiency_ratio)
+    .bind(metrics.cost_per_package)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Recalcular una semana (de toda la flota, de una empresa o de un chofer)
+/// y buscar sus anomalías
+pub async fn aggregate_week(
+    pool: &PgPool,
+    start: NaiveDate,
+    company_id: Option<Uuid>,
+    driver_id: Option<Uuid>,
+    policy: &AnalyticsPolicy,
+) -> AppResult<WeeklyAggregationSummary> {
+    let start = week_start(start);
+    let mut tx = pool.begin().await?;
+
+    let inputs = load_inputs(&mut tx, start, company_id, driver_id).await?;
+    for driver in &inputs {
+        upsert_week(&mut tx, start, driver).await?;
+    }
+
+    let computed: Vec<Uuid> = inputs.iter().map(|driver| driver.driver_id).collect();
+    let removed = sqlx::query(
+        r#"
+        DELETE FROM performance_analytics
+        WHERE week_start_date = $1
+        AND ($2::uuid IS NULL OR company_id = $2)
+        AND ($3::uuid IS NULL OR driver_id = $3)
+        AND NOT (driver_id = ANY($4))
+        "#,
+    )
+    .bind(start)
+    .bind(company_id)
+    .bind(driver_id)
+    .bind(&computed)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    let mut flagged = performance_anomalies::detect_week(&mut tx, start, company_id, driver_id, policy).await?;
+
+    // Un vehículo lo comparten varios choferes: solo con la empresa entera
+    let mut vehicles = 0;
+    if driver_id.is_none() {
+        vehicles = aggregate_vehicle_week(&mut tx, start, company_id).await?;
+        flagged += performance_anomalies::detect_vehicle_week(&mut tx, start, company_id, policy).await?;
+    }
+    tx.commit().await?;
+
+    Ok(WeeklyAggregationSummary {
+        weeks: 1,
+        drivers: inputs.len() as u64,
+        vehicles,
+        removed,
+        flagged,
+    })
+}
+
+/// Recalcular las semanas entre `from` y `to` de una empresa
+pub async fn recompute(
+    pool: &PgPool,
+    company_id: Uuid,
+    from: NaiveDate,
+    to: NaiveDate,
+    driver_id: Option<Uuid>,
+    policy: &AnalyticsPolicy,
+) -> AppResult<WeeklyAggregationSummary> {
+    if from > to {
+        return Err(AppError::BadRequest("from debe ser anterior a to".to_string()));
+    }
+    let first = week_start(from);
+    let last = week_start(to);
+    if (last - first).num_weeks() >= MAX_RECOMPUTE_WEEKS {
+        return Err(AppError::BadRequest(format!(
+            "Como mucho se pueden recalcular {} semanas de una vez",
+            MAX_RECOMPUTE_WEEKS
+        )));
+    }
+
+    let mut summary = WeeklyAggregationSummary::default();
+    let mut week = first;
+    while week <= last {
+        let result = aggregate_week(pool, week, Some(company_id), driver_id, policy).await?;
+        summary.weeks += 1;
+        summary.drivers += result.drivers;
+        summary.vehicles += result.vehicles;
+        summary.removed += result.removed;
+        summary.flagged += result.flagged;
+        week += Duration::weeks(1);
+    }
+
+    log::info!(
+        "📊 Rendimiento recalculado para la empresa {}: {} semanas, {} filas",
+        company_id,
+        summary.weeks,
+        summary.drivers
+    );
+    Ok(summary)
+}
+
+/// Pasada del job: las últimas semanas cerradas de todas las empresas
+pub async fn run_weekly_aggregation(pool: &PgPool, policy: &AnalyticsPolicy) -> AppResult<WeeklyAggregationSummary> {
+    let today = sqlx::query_scalar::<_, NaiveDate>("SELECT CURRENT_DATE")
+        .fetch_one(pool)
+        .await?;
+    let current_week = week_start(today);
+
+    let mut summary = WeeklyAggregationSummary::default();
+    for weeks_back in 1..=policy.recompute_weeks {
+        let start = current_week - Duration::weeks(weeks_back);
+        match aggregate_week(pool, start, None, None, policy).await {
+            Ok(result) => {
+                summary.weeks += 1;
+                summary.drivers += result.drivers;
+                summary.vehicles += result.vehicles;
+                summary.removed += result.removed;
+                summary.flagged += result.flagged;
+            }
+            Err(e) => log::error!("❌ Error agregando la semana del {}: {}", start, e),
+        }
+    }
+
+    log::info!(
+        "📊 Rendimiento semanal: {} semanas, {} filas de chofer, {} de vehículo, {} con anomalías",
+        summary.weeks,
+        summary.drivers,
+        summary.vehicles,
+        summary.flagged
+    );
+    Ok(summary)
+}
+
+/// Lanzar la agregación periódica del rendimiento
+pub fn spawn_performance_job(pool: PgPool, policy: AnalyticsPolicy) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(policy.check_interval_hours * 3600));
+        loop {
+            interval.tick().await;
+            if let Err(e) = run_weekly_aggregation(&pool, &policy).await {
+                log::error!("❌ Error en la agregación semanal de rendimiento: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_week_start_is_monday() {
+        let monday = NaiveDate::from_ymd_opt(2024, 3, 11).unwrap();
+        assert_eq!(week_start(monday), monday);
+        assert_eq!(week_start(NaiveDate::from_ymd_opt(2024, 3, 17).unwrap()), monday);
+        assert_eq!(week_start(NaiveDate::from_ymd_opt(2024, 3, 13).unwrap()), monday);
+    }
+
+    #[test]
+    fn test_compute_metrics() {
+        let inputs = WeekInputs {
+            total_packages: 400,
+            delivered: 380,
+            failed: 20,
+            km: Decimal::new(50_000, 2),
+            fuel_liters: Decimal::new(4_000, 2),
+            fuel_cost: Decimal::new(7_600, 2),
+            working_minutes: 2_400.0,
+            damage_incidents: 1,
+            damage_cost: Decimal::new(35_000, 2),
+            ..WeekInputs::default()
+        };
+
+        let metrics = compute(&inputs);
+        assert_eq!(metrics.delivery_success_rate, Some(Decimal::new(9_500, 2)));
+        assert_eq!(metrics.fuel_efficiency, Some(Decimal::new(800, 2)));
+        assert_eq!(metrics.total_working_hours, Some(Decimal::new(4_000, 2)));
+        assert_eq!(metrics.efficiency_ratio, Some(Decimal::new(950, 2)));
+        assert_eq!(metrics.cost_per_package, Some(Decimal::new(20, 2)));
+        assert_eq!(metrics.damage_score, Some(Decimal::new(200, 2)));
+        // Sin duración por paquete: horas de trabajo entre entregas
+        assert_eq!(metrics.average_delivery_time_minutes, Some(Decimal::new(632, 2)));
+    }
+
+    #[test]
+    fn test_compute_without_activity_and_clamping() {
+        let empty = compute(&WeekInputs::default());
+        assert_eq!(empty.delivery_success_rate, None);
+        assert_eq!(empty.fuel_efficiency, None);
+        assert_eq!(empty.total_working_hours, None);
+        assert_eq!(empty.cost_per_package, None);
+
+        let long_week = WeekInputs {
+            delivered: 10,
+            working_minutes: 7_000.0,
+            delivery_minutes: 50.0,
+            timed_deliveries: 5,
+            ..WeekInputs::default()
+        };
+        let metrics = compute(&long_week);
+        assert_eq!(metrics.total_working_hours, Some(Decimal::new(11_667, 2)));
+        assert_eq!(metrics.average_delivery_time_minutes, Some(Decimal::new(1_000, 2)));
+
+        // Más horas que las de una semana: se descartan en vez de recortarse
+        let impossible = compute(&WeekInputs { working_minutes: 12_000.0, ..long_week });
+        assert_eq!(impossible.total_working_hours, None);
+        assert_eq!(impossible.efficiency_ratio, None);
+    }
+
+    #[test]
+    fn test_compute_vehicle_metrics() {
+        let inputs = VehicleWeekInputs {
+            km: Decimal::new(80_000, 2),
+            fuel_liters: Decimal::new(6_400, 2),
+            fuel_cost: Decimal::new(11_520, 2),
+            ..VehicleWeekInputs::default()
+        };
+
+        let metrics = compute_vehicle(&inputs);
+        assert_eq!(metrics.fuel_efficiency, Some(Decimal::new(800, 2)));
+        assert_eq!(metrics.cost_per_km, Some(Decimal::new(144, 3)));
+
+        let parked = compute_vehicle(&VehicleWeekInputs::default());
+        assert_eq!(parked.fuel_efficiency, None);
+        assert_eq!(parked.cost_per_km, None);
+    }
+}
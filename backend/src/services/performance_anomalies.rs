@@ -0,0 +1,789 @@
+//! Detección de anomalías en el rendimiento semanal
+//!
+//! Tras agregar una semana (ver `services::performance_analytics`) cada fila
+//! de chofer se compara por dos vías:
+//!
+//! - Con su propio histórico (las semanas anteriores): z-score.
+//! - Con el resto de choferes de la empresa esa semana: vallas de Tukey
+//!   (Q1 - k·IQR / Q3 + k·IQR) alrededor de la mediana.
+//!
+//! Las filas de vehículo (consumo y coste por km) se comparan igual con su
+//! propio histórico y con el resto de la flota, y se guardan en
+//! `vehicle_performance_weeks.anomaly_flags`.
+//!
+//! Si la referencia no tiene dispersión (histórico constante, IQR nulo) se
+//! marca cualquier desviación mala de al menos `flat_min_delta_pct` % y el
+//! score es la diferencia absoluta.
+//!
+//! Solo se marca la dirección mala de cada métrica (baja la tasa de éxito;
+//! suben la permanencia en parada, los km por paquete y el consumo). Además
+//! se cuentan las entregas seguidas cuya distancia y tiempo implican una
+//! velocidad imposible. Las banderas se guardan con su explicación en
+//! `performance_analytics.anomaly_flags` y las nuevas se avisan como
+//! `performance_alert`; recalcular la semana no repite el aviso.
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde_json::{json, Value};
+use sqlx::{FromRow, PgConnection};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+use crate::{
+    models::analytics::{AnomalyFlag, AnomalyMethod, AnomalyMetric},
+    models::notification::{NewNotification, NotificationPriority, NotificationType},
+    services::address_confidence::haversine_meters,
+    services::notifications,
+    services::performance_analytics::AnalyticsPolicy,
+    utils::errors::AppResult,
+};
+
+/// Por debajo de estos paquetes la tasa de éxito y los km por paquete son ruido
+const MIN_WEEK_PACKAGES: i32 = 20;
+
+/// Por debajo de estos km el consumo y el coste por km de un vehículo son ruido
+const MIN_WEEK_KM: f64 = 100.0;
+
+/// Entregas a menos de esta distancia se consideran el mismo sitio
+const SAME_PLACE_METERS: f64 = 50.0;
+
+const METRICS: [AnomalyMetric; 4] = [
+    AnomalyMetric::SuccessRate,
+    AnomalyMetric::StopDwell,
+    AnomalyMetric::KmPerPackage,
+    AnomalyMetric::FuelEfficiency,
+];
+
+const VEHICLE_METRICS: [AnomalyMetric; 2] = [AnomalyMetric::FuelEfficiency, AnomalyMetric::CostPerKm];
+
+/// Media y desviación típica muestral
+pub fn mean_std(values: &[f64]) -> Option<(f64, f64)> {
+    if values.len() < 2 {
+        return None;
+    }
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    Some((mean, variance.sqrt()))
+}
+
+/// (Q1, mediana, Q3) con interpolación lineal
+pub fn quartiles(values: &[f64]) -> Option<(f64, f64, f64)> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+
+    let percentile = |p: f64| {
+        let rank = p * (sorted.len() - 1) as f64;
+        let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+        sorted[low] + (sorted[high] - sorted[low]) * (rank - low as f64)
+    };
+    Some((percentile(0.25), percentile(0.5), percentile(0.75)))
+}
+
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// ¿Se aleja `value` en la dirección mala de una referencia sin dispersión?
+fn beyond_flat_baseline(value: f64, baseline: f64, higher_is_worse: bool, policy: &AnalyticsPolicy) -> bool {
+    let worse = if higher_is_worse { value - baseline } else { baseline - value };
+    worse > f64::EPSILON && worse >= baseline.abs() * policy.flat_min_delta_pct / 100.0
+}
+
+/// Comparar el valor de la semana con el histórico del chofer y con la empresa
+pub fn evaluate(
+    metric: AnomalyMetric,
+    value: f64,
+    history: &[f64],
+    peers: &[f64],
+    policy: &AnalyticsPolicy,
+) -> Vec<AnomalyFlag> {
+    evaluate_against(metric, value, history, peers, policy, (AnomalyMethod::DriverHistory, AnomalyMethod::CompanyPeers))
+}
+
+/// Comparar el valor de la semana de un vehículo con su histórico y con la flota
+pub fn evaluate_vehicle(
+    metric: AnomalyMetric,
+    value: f64,
+    history: &[f64],
+    peers: &[f64],
+    policy: &AnalyticsPolicy,
+) -> Vec<AnomalyFlag> {
+    evaluate_against(metric, value, history, peers, policy, (AnomalyMethod::VehicleHistory, AnomalyMethod::FleetPeers))
+}
+
+fn evaluate_against(
+    metric: AnomalyMetric,
+    value: f64,
+    history: &[f64],
+    peers: &[f64],
+    policy: &AnalyticsPolicy,
+    (history_method, peers_method): (AnomalyMethod, AnomalyMethod),
+) -> Vec<AnomalyFlag> {
+    let higher_is_worse = metric != AnomalyMetric::SuccessRate;
+    let mut flags = Vec::new();
+
+    if history.len() >= policy.min_history_weeks {
+        if let Some((mean, std)) = mean_std(history) {
+            if std > f64::EPSILON {
+                let z = (value - mean) / std;
+                let worse = if higher_is_worse { z } else { -z };
+                if worse >= policy.z_threshold {
+                    flags.push(AnomalyFlag {
+                        metric,
+                        method: history_method,
+                        value: round2(value),
+                        baseline: round2(mean),
+                        score: round2(z),
+                        explanation: format!(
+                            "{} de {:.2} frente a una media de {:.2} en sus {} semanas anteriores (z = {:.1})",
+                            metric.label(),
+                            value,
+                            mean,
+                            history.len(),
+                            z
+                        ),
+                    });
+                }
+            } else if beyond_flat_baseline(value, mean, higher_is_worse, policy) {
+                flags.push(AnomalyFlag {
+                    metric,
+                    method: history_method,
+                    value: round2(value),
+                    baseline: round2(mean),
+                    score: round2(value - mean),
+                    explanation: format!(
+                        "{} de {:.2} frente a {:.2} constante en sus {} semanas anteriores",
+                        metric.label(),
+                        value,
+                        mean,
+                        history.len()
+                    ),
+                });
+            }
+        }
+    }
+
+    if peers.len() >= policy.min_peers {
+        if let Some((q1, median, q3)) = quartiles(peers) {
+            let iqr = q3 - q1;
+            if iqr > f64::EPSILON {
+                let fence = if higher_is_worse { q3 + policy.iqr_factor * iqr } else { q1 - policy.iqr_factor * iqr };
+                let beyond = if higher_is_worse { value > fence } else { value < fence };
+                if beyond {
+                    flags.push(AnomalyFlag {
+                        metric,
+                        method: peers_method,
+                        value: round2(value),
+                        baseline: round2(median),
+                        score: round2((value - median).abs() / iqr),
+                        explanation: format!(
+                            "{} de {:.2} frente a una mediana de {:.2} en la empresa (valla {:.2})",
+                            metric.label(),
+                            value,
+                            median,
+                            fence
+                        ),
+                    });
+                }
+            } else if beyond_flat_baseline(value, median, higher_is_worse, policy) {
+                flags.push(AnomalyFlag {
+                    metric,
+                    method: peers_method,
+                    value: round2(value),
+                    baseline: round2(median),
+                    score: round2(value - median),
+                    explanation: format!(
+                        "{} de {:.2} frente a {:.2} en la mitad central de la empresa (sin dispersión)",
+                        metric.label(),
+                        value,
+                        median
+                    ),
+                });
+            }
+        }
+    }
+
+    flags
+}
+
+/// Entrega con posición (filas ordenadas por tournée y hora)
+#[derive(Debug, Clone, FromRow)]
+pub struct DeliveryFix {
+    pub driver_id: Uuid,
+    pub tournee_id: Uuid,
+    pub delivered_at: DateTime<Utc>,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Entregas seguidas de una misma tournée a más de `max_speed_kmh`
+pub fn implausible_hops(fixes: &[DeliveryFix], max_speed_kmh: f64) -> usize {
+    fixes
+        .windows(2)
+        .filter(|pair| pair[0].tournee_id == pair[1].tournee_id)
+        .filter(|pair| {
+            let meters = haversine_meters(
+                (pair[0].latitude, pair[0].longitude),
+                (pair[1].latitude, pair[1].longitude),
+            );
+            if meters < SAME_PLACE_METERS {
+                return false;
+            }
+            let hours = (pair[1].delivered_at - pair[0].delivered_at).num_seconds() as f64 / 3600.0;
+            hours <= 0.0 || meters / 1000.0 / hours > max_speed_kmh
+        })
+        .count()
+}
+
+/// Fila de rendimiento con las métricas vigiladas
+#[derive(Debug, Clone, FromRow)]
+struct WeekRow {
+    company_id: Uuid,
+    driver_id: Uuid,
+    driver_name: String,
+    week_start_date: NaiveDate,
+    total_packages: i32,
+    success_rate: Option<f64>,
+    km_per_package: Option<f64>,
+    fuel_efficiency: Option<f64>,
+    stop_dwell: Option<f64>,
+    anomaly_flags: Option<Value>,
+}
+
+impl WeekRow {
+    fn value(&self, metric: AnomalyMetric) -> Option<f64> {
+        let enough_packages = self.total_packages >= MIN_WEEK_PACKAGES;
+        match metric {
+            AnomalyMetric::SuccessRate => self.success_rate.filter(|_| enough_packages),
+            AnomalyMetric::KmPerPackage => self.km_per_package.filter(|_| enough_packages),
+            AnomalyMetric::FuelEfficiency => self.fuel_efficiency,
+            AnomalyMetric::StopDwell => self.stop_dwell,
+            AnomalyMetric::CostPerKm | AnomalyMetric::ImplausibleSpeed => None,
+        }
+    }
+
+    fn previous_flags(&self) -> Vec<AnomalyFlag> {
+        self.anomaly_flags
+            .as_ref()
+            .and_then(|flags| flags.get("flags"))
+            .and_then(|flags| serde_json::from_value(flags.clone()).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Fila semanal de un vehículo
+#[derive(Debug, Clone, FromRow)]
+struct VehicleWeekRow {
+    company_id: Uuid,
+    vehicle_id: Uuid,
+    license_plate: String,
+    week_start_date: NaiveDate,
+    km_driven: f64,
+    fuel_efficiency: Option<f64>,
+    cost_per_km: Option<f64>,
+    anomaly_flags: Option<Value>,
+}
+
+impl VehicleWeekRow {
+    fn value(&self, metric: AnomalyMetric) -> Option<f64> {
+        if self.km_driven < MIN_WEEK_KM {
+            return None;
+        }
+        match metric {
+            AnomalyMetric::FuelEfficiency => self.fuel_efficiency,
+            AnomalyMetric::CostPerKm => self.cost_per_km,
+            _ => None,
+        }
+    }
+
+    fn previous_flags(&self) -> Vec<AnomalyFlag> {
+        self.anomaly_flags
+            .as_ref()
+            .and_then(|flags| flags.get("flags"))
+            .and_then(|flags| serde_json::from_value(flags.clone()).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Semana `start` y su histórico (con la permanencia media en parada de
+/// `stop_visits`)
+async fn load_rows(
+    conn: &mut PgConnection,
+    start: NaiveDate,
+    company_id: Option<Uuid>,
+    policy: &AnalyticsPolicy,
+) -> AppResult<Vec<WeekRow>> {
+    let history_start = start - Duration::weeks(policy.history_weeks);
+
+    Ok(sqlx::query_as::<_, WeekRow>(
+        r#"
+        WITH dwell AS (
+            SELECT v.driver_id, date_trunc('week', t.tournee_date)::date AS week_start_date,
+                   AVG(v.dwell_seconds)::float8 / 60 AS stop_dwell
+            FROM stop_visits v
+            JOIN tournees t ON t.id = v.tournee_id
+            WHERE v.dwell_seconds IS NOT NULL AND t.tournee_status = 'completed' AND t.deleted_at IS NULL
+            AND t.tournee_date BETWEEN $1 AND $2 + 6
+            AND ($3::uuid IS NULL OR v.company_id = $3)
+            GROUP BY 1, 2
+        )
+        SELECT pa.company_id, pa.driver_id, u.full_name AS driver_name, pa.week_start_date,
+               pa.total_packages,
+               pa.delivery_success_rate::float8 AS success_rate,
+               (pa.km_driven / NULLIF(pa.successful_deliveries, 0))::float8 AS km_per_package,
+               pa.fuel_efficiency::float8 AS fuel_efficiency,
+               d.stop_dwell,
+               pa.anomaly_flags
+        FROM performance_analytics pa
+        JOIN users u ON u.id = pa.driver_id
+        LEFT JOIN dwell d ON d.driver_id = pa.driver_id AND d.week_start_date = pa.week_start_date
+        WHERE pa.week_start_date BETWEEN $1 AND $2
+        AND ($3::uuid IS NULL OR pa.company_id = $3)
+        ORDER BY pa.week_start_date
+        "#,
+    )
+    .bind(history_start)
+    .bind(start)
+    .bind(company_id)
+    .fetch_all(&mut *conn)
+    .await?)
+}
+
+/// Semana `start` de los vehículos y su histórico
+async fn load_vehicle_rows(
+    conn: &mut PgConnection,
+    start: NaiveDate,
+    company_id: Option<Uuid>,
+    policy: &AnalyticsPolicy,
+) -> AppResult<Vec<VehicleWeekRow>> {
+    let history_start = start - Duration::weeks(policy.history_weeks);
+
+    Ok(sqlx::query_as::<_, VehicleWeekRow>(
+        r#"
+        SELECT w.company_id, w.vehicle_id, v.license_plate, w.week_start_date,
+               w.km_driven::float8 AS km_driven,
+               w.fuel_efficiency::float8 AS fuel_efficiency,
+               w.cost_per_km::float8 AS cost_per_km,
+               w.anomaly_flags
+        FROM vehicle_performance_weeks w
+        JOIN vehicles v ON v.id = w.vehicle_id
+        WHERE w.week_start_date BETWEEN $1 AND $2
+        AND ($3::uuid IS NULL OR w.company_id = $3)
+        ORDER BY w.week_start_date
+        "#,
+    )
+    .bind(history_start)
+    .bind(start)
+    .bind(company_id)
+    .fetch_all(&mut *conn)
+    .await?)
+}
+
+/// Entregas con posición de la semana, por chofer
+async fn load_fixes(
+    conn: &mut PgConnection,
+    start: NaiveDate,
+    company_id: Option<Uuid>,
+    driver_id: Option<Uuid>,
+) -> AppResult<HashMap<Uuid, Vec<DeliveryFix>>> {
+    let fixes = sqlx::query_as::<_, DeliveryFix>(
+        r#"
+        SELECT t.driver_id, p.tournee_id, p.status_changed_at AS delivered_at,
+               p.delivery_coordinates[1] AS latitude, p.delivery_coordinates[0] AS longitude
+        FROM packages p
+        JOIN tournees t ON t.id = p.tournee_id
+        WHERE t.tournee_date BETWEEN $1 AND $1 + 6
+        AND t.tournee_status = 'completed' AND t.deleted_at IS NULL
+        AND p.deleted_at IS NULL AND p.delivery_status = 'delivered'
+        AND p.delivery_coordinates IS NOT NULL AND p.status_changed_at IS NOT NULL
+        AND ($2::uuid IS NULL OR t.company_id = $2)
+        AND ($3::uuid IS NULL OR t.driver_id = $3)
+        ORDER BY p.tournee_id, p.status_changed_at
+        "#,
+    )
+    .bind(start)
+    .bind(company_id)
+    .bind(driver_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut by_driver: HashMap<Uuid, Vec<DeliveryFix>> = HashMap::new();
+    for fix in fixes {
+        by_driver.entry(fix.driver_id).or_default().push(fix);
+    }
+    Ok(by_driver)
+}
+
+/// Banderas de una fila frente a su histórico y a la empresa
+fn flags_for(row: &WeekRow, rows: &[WeekRow], fixes: &[DeliveryFix], policy: &AnalyticsPolicy) -> Vec<AnomalyFlag> {
+    let mut flags = Vec::new();
+
+    for metric in METRICS {
+        let Some(value) = row.value(metric) else {
+            continue;
+        };
+        let history: Vec<f64> = rows
+            .iter()
+            .filter(|other| other.driver_id == row.driver_id && other.week_start_date < row.week_start_date)
+            .filter_map(|other| other.value(metric))
+            .collect();
+        let peers: Vec<f64> = rows
+            .iter()
+            .filter(|other| {
+                other.company_id == row.company_id
+                    && other.week_start_date == row.week_start_date
+                    && other.driver_id != row.driver_id
+            })
+            .filter_map(|other| other.value(metric))
+            .collect();
+        flags.extend(evaluate(metric, value, &history, &peers, policy));
+    }
+
+    let hops = implausible_hops(fixes, policy.max_speed_kmh);
+    if hops >= policy.min_implausible_hops {
+        flags.push(AnomalyFlag {
+            metric: AnomalyMetric::ImplausibleSpeed,
+            method: AnomalyMethod::Threshold,
+            value: hops as f64,
+            baseline: policy.max_speed_kmh,
+            score: hops as f64,
+            explanation: format!(
+                "{} entregas seguidas a más de {:.0} km/h de la anterior",
+                hops, policy.max_speed_kmh
+            ),
+        });
+    }
+
+    flags
+}
+
+/// Banderas de un vehículo frente a su histórico y al resto de la flota
+fn vehicle_flags_for(row: &VehicleWeekRow, rows: &[VehicleWeekRow], policy: &AnalyticsPolicy) -> Vec<AnomalyFlag> {
+    let mut flags = Vec::new();
+
+    for metric in VEHICLE_METRICS {
+        let Some(value) = row.value(metric) else {
+            continue;
+        };
+        let history: Vec<f64> = rows
+            .iter()
+            .filter(|other| other.vehicle_id == row.vehicle_id && other.week_start_date < row.week_start_date)
+            .filter_map(|other| other.value(metric))
+            .collect();
+        let peers: Vec<f64> = rows
+            .iter()
+            .filter(|other| {
+                other.company_id == row.company_id
+                    && other.week_start_date == row.week_start_date
+                    && other.vehicle_id != row.vehicle_id
+            })
+            .filter_map(|other| other.value(metric))
+            .collect();
+        flags.extend(evaluate_vehicle(metric, value, &history, &peers, policy));
+    }
+
+    flags
+}
+
+/// Banderas nuevas respecto a las que ya se avisaron
+fn new_flags<'a>(flags: &'a [AnomalyFlag], previous: &[AnomalyFlag]) -> Vec<&'a AnomalyFlag> {
+    let known: HashSet<(AnomalyMetric, AnomalyMethod)> =
+        previous.iter().map(|flag| (flag.metric, flag.method)).collect();
+    flags
+        .iter()
+        .filter(|flag| !known.contains(&(flag.metric, flag.method)))
+        .collect()
+}
+
+/// Detectar las anomalías de los vehículos en la semana `start` y avisar de
+/// las nuevas
+///
+/// Devuelve cuántas filas quedan con alguna bandera.
+pub async fn detect_vehicle_week(
+    conn: &mut PgConnection,
+    start: NaiveDate,
+    company_id: Option<Uuid>,
+    policy: &AnalyticsPolicy,
+) -> AppResult<u64> {
+    let rows = load_vehicle_rows(conn, start, company_id, policy).await?;
+
+    let mut flagged = 0;
+    for row in rows.iter().filter(|row| row.week_start_date == start) {
+        let flags = vehicle_flags_for(row, &rows, policy);
+
+        sqlx::query(
+            "UPDATE vehicle_performance_weeks SET anomaly_flags = $3 WHERE vehicle_id = $1 AND week_start_date = $2",
+        )
+        .bind(row.vehicle_id)
+        .bind(start)
+        .bind(json!({ "checked_at": Utc::now(), "flags": flags }))
+        .execute(&mut *conn)
+        .await?;
+
+        if flags.is_empty() {
+            continue;
+        }
+        flagged += 1;
+
+        let new_flags = new_flags(&flags, &row.previous_flags());
+        if new_flags.is_empty() {
+            continue;
+        }
+
+        log::warn!(
+            "📉 {} anomalías nuevas del vehículo {} en la semana del {}",
+            new_flags.len(),
+            row.license_plate,
+            start
+        );
+
+        notifications::record(
+            conn,
+            &NewNotification {
+                company_id: row.company_id,
+                notification_type: NotificationType::PerformanceAlert,
+                priority: NotificationPriority::Medium,
+                title: format!("Rendimiento anómalo del vehículo {}", row.license_plate),
+                message: format!(
+                    "Semana del {}: {}",
+                    start,
+                    new_flags
+                        .iter()
+                        .map(|flag| flag.explanation.as_str())
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                ),
+                document_id: None,
+                vehicle_id: Some(row.vehicle_id),
+                driver_id: None,
+                metadata: json!({
+                    "week_start_date": start,
+                    "flags": new_flags,
+                }),
+            },
+        )
+        .await?;
+    }
+
+    Ok(flagged)
+}
+
+/// Detectar las anomalías de la semana `start` y avisar de las nuevas
+///
+/// Devuelve cuántas filas quedan con alguna bandera.
+pub async fn detect_week(
+    conn: &mut PgConnection,
+    start: NaiveDate,
+    company_id: Option<Uuid>,
+    driver_id: Option<Uuid>,
+    policy: &AnalyticsPolicy,
+) -> AppResult<u64> {
+    let rows = load_rows(conn, start, company_id, policy).await?;
+    let fixes = load_fixes(conn, start, company_id, driver_id).await?;
+
+    let mut flagged = 0;
+    for row in rows
+        .iter()
+        .filter(|row| row.week_start_date == start && driver_id.is_none_or(|id| id == row.driver_id))
+    {
+        let driver_fixes = fixes.get(&row.driver_id).map(Vec::as_slice).unwrap_or(&[]);
+        let flags = flags_for(row, &rows, driver_fixes, policy);
+
+        sqlx::query(
+            "UPDATE performance_analytics SET anomaly_flags = $3 WHERE driver_id = $1 AND week_start_date = $2",
+        )
+        .bind(row.driver_id)
+        .bind(start)
+        .bind(json!({ "checked_at": Utc::now(), "flags": flags }))
+        .execute(&mut *conn)
+        .await?;
+
+        if flags.is_empty() {
+            continue;
+        }
+        flagged += 1;
+
+        let new_flags = new_flags(&flags, &row.previous_flags());
+        if new_flags.is_empty() {
+            continue;
+        }
+
+        let severe = new_flags
+            .iter()
+            .any(|flag| matches!(flag.metric, AnomalyMetric::SuccessRate | AnomalyMetric::ImplausibleSpeed));
+        log::warn!(
+            "📉 {} anomalías nuevas de {} en la semana del {}",
+            new_flags.len(),
+            row.driver_name,
+            start
+        );
+
+        notifications::record(
+            conn,
+            &NewNotification {
+                company_id: row.company_id,
+                notification_type: NotificationType::PerformanceAlert,
+                priority: if severe { NotificationPriority::High } else { NotificationPriority::Medium },
+                title: format!("Rendimiento anómalo de {}", row.driver_name),
+                message: format!(
+                    "Semana del {}: {}",
+                    start,
+                    new_flags
+                        .iter()
+                        .map(|flag| flag.explanation.as_str())
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                ),
+                document_id: None,
+                vehicle_id: None,
+                driver_id: Some(row.driver_id),
+                metadata: json!({
+                    "week_start_date": start,
+                    "flags": new_flags,
+                }),
+            },
+        )
+        .await?;
+    }
+
+    Ok(flagged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> AnalyticsPolicy {
+        AnalyticsPolicy::default()
+    }
+
+    #[test]
+    fn test_quartiles_and_std() {
+        assert_eq!(quartiles(&[4.0, 1.0, 3.0, 2.0, 5.0]), Some((2.0, 3.0, 4.0)));
+        let (mean, std) = mean_std(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]).unwrap();
+        assert_eq!(mean, 5.0);
+        assert!((std - 2.138).abs() < 0.001);
+        assert_eq!(mean_std(&[1.0]), None);
+    }
+
+    #[test]
+    fn test_success_rate_drop_against_history_and_peers() {
+        let history = [96.0, 97.5, 95.0, 96.5, 97.0, 96.0];
+        let peers = [95.0, 96.0, 97.0, 94.0, 96.5];
+
+        let flags = evaluate(AnomalyMetric::SuccessRate, 82.0, &history, &peers, &policy());
+        let methods: Vec<AnomalyMethod> = flags.iter().map(|f| f.method).collect();
+        assert_eq!(methods, vec![AnomalyMethod::DriverHistory, AnomalyMethod::CompanyPeers]);
+        assert!(flags[0].score < -2.0);
+
+        // Subir la tasa de éxito nunca es una anomalía
+        assert!(evaluate(AnomalyMetric::SuccessRate, 100.0, &history, &peers, &policy()).is_empty());
+    }
+
+    #[test]
+    fn test_needs_enough_history_and_peers() {
+        let flags = evaluate(AnomalyMetric::KmPerPackage, 9.0, &[1.0, 1.1], &[1.0, 1.2], &policy());
+        assert!(flags.is_empty());
+
+        let peers = [1.0, 1.1, 1.2, 1.3, 1.1];
+        let flags = evaluate(AnomalyMetric::KmPerPackage, 9.0, &[], &peers, &policy());
+        assert_eq!(flags.len(), 1);
+        assert_eq!(flags[0].method, AnomalyMethod::CompanyPeers);
+    }
+
+    #[test]
+    fn test_implausible_hops() {
+        let tournee = Uuid::new_v4();
+        let at = |minutes: i64| DateTime::<Utc>::from_timestamp(1_700_000_000 + minutes * 60, 0).unwrap();
+        let fix = |minutes: i64, latitude: f64| DeliveryFix {
+            driver_id: Uuid::nil(),
+            tournee_id: tournee,
+            delivered_at: at(minutes),
+            latitude,
+            longitude: 2.35,
+        };
+
+        let fixes = vec![
+            fix(0, 48.80),
+            // ~1,1 km en 5 minutos: normal
+            fix(5, 48.81),
+            // ~11 km en 1 minuto: imposible
+            fix(6, 48.91),
+            // Mismo edificio en el mismo minuto: no cuenta
+            fix(6, 48.9101),
+        ];
+        assert_eq!(implausible_hops(&fixes, 130.0), 1);
+    }
+
+    #[test]
+    fn test_flat_baseline_flags_minimum_delta() {
+        // Siempre 100 %: bajar a 85 % se marca aunque la desviación típica sea 0
+        let history = [100.0; 6];
+        let peers = [100.0, 100.0, 100.0, 100.0, 99.0];
+        let flags = evaluate(AnomalyMetric::SuccessRate, 85.0, &history, &peers, &policy());
+        let methods: Vec<AnomalyMethod> = flags.iter().map(|f| f.method).collect();
+        assert_eq!(methods, vec![AnomalyMethod::DriverHistory, AnomalyMethod::CompanyPeers]);
+        assert_eq!(flags[0].score, -15.0);
+
+        // Por debajo del mínimo (10 %) o en la dirección buena no se marca
+        assert!(evaluate(AnomalyMetric::SuccessRate, 95.0, &history, &peers, &policy()).is_empty());
+        let dwell = [4.0; 6];
+        assert!(evaluate(AnomalyMetric::StopDwell, 2.0, &dwell, &dwell, &policy()).is_empty());
+        assert_eq!(evaluate(AnomalyMetric::StopDwell, 6.0, &dwell, &dwell, &policy()).len(), 2);
+    }
+
+    fn vehicle_week(vehicle_id: Uuid, weeks_back: i64, km: f64, fuel_efficiency: f64, cost_per_km: f64) -> VehicleWeekRow {
+        VehicleWeekRow {
+            company_id: Uuid::nil(),
+            vehicle_id,
+            license_plate: "AB-123-CD".to_string(),
+            week_start_date: NaiveDate::from_ymd_opt(2024, 3, 11).unwrap() - Duration::weeks(weeks_back),
+            km_driven: km,
+            fuel_efficiency: Some(fuel_efficiency),
+            cost_per_km: Some(cost_per_km),
+            anomaly_flags: None,
+        }
+    }
+
+    #[test]
+    fn test_vehicle_consumption_and_cost_against_history_and_fleet() {
+        let van = Uuid::new_v4();
+        let mut rows: Vec<VehicleWeekRow> = [8.0, 8.2, 7.9, 8.1, 8.0]
+            .iter()
+            .zip(1..)
+            .map(|(consumption, weeks_back)| vehicle_week(van, weeks_back, 600.0, *consumption, consumption * 0.018))
+            .collect();
+        for consumption in [7.8, 8.1, 8.3, 8.0] {
+            rows.push(vehicle_week(Uuid::new_v4(), 0, 600.0, consumption, consumption * 0.018));
+        }
+        // Esta semana gasta un 40 % más
+        rows.push(vehicle_week(van, 0, 600.0, 11.2, 0.202));
+
+        let current = rows.last().unwrap();
+        let flags = vehicle_flags_for(current, &rows, &policy());
+        let found: Vec<(AnomalyMetric, AnomalyMethod)> = flags.iter().map(|f| (f.metric, f.method)).collect();
+        assert_eq!(
+            found,
+            vec![
+                (AnomalyMetric::FuelEfficiency, AnomalyMethod::VehicleHistory),
+                (AnomalyMetric::FuelEfficiency, AnomalyMethod::FleetPeers),
+                (AnomalyMetric::CostPerKm, AnomalyMethod::VehicleHistory),
+                (AnomalyMetric::CostPerKm, AnomalyMethod::FleetPeers),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_vehicle_needs_enough_km() {
+        let van = Uuid::new_v4();
+        let rows: Vec<VehicleWeekRow> = (1..=5)
+            .map(|weeks_back| vehicle_week(van, weeks_back, 600.0, 8.0, 0.144))
+            .chain([vehicle_week(van, 0, 40.0, 20.0, 0.36)])
+            .collect();
+
+        assert!(vehicle_flags_for(rows.last().unwrap(), &rows, &policy()).is_empty());
+    }
+}
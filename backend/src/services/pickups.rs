@@ -0,0 +1,258 @@
+//! Recogidas (collectes) de una tournée
+//!
+//! El chofer lee cada bulto recogido (`scan_type = pickup` con el `pickup_id`)
+//! y confirma la recogida: el número de bultos es el de códigos distintos
+//! leídos, y la recogida queda `collected` o `partial` según el previsto. Al
+//! cerrar la tournée las recogidas pendientes se dan por fallidas.
+
+use chrono::Utc;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::{
+    middleware::auth::{driver_scope, AuthenticatedUser},
+    models::pickup::{Pickup, PickupStatus, PICKUP_COLUMNS},
+    models::stop_visit::{RouteStop, StopKind},
+    models::tournee::TourneeStatus,
+    services::lifecycle,
+    utils::errors::{AppError, AppResult},
+};
+
+/// Estados en los que los bultos recogidos van a bordo
+const ON_BOARD_STATUSES: &str = "pickup_status IN ('collected', 'partial')";
+
+/// Estado al confirmar con `collected` bultos leídos (`None` si no hay ninguno)
+pub fn confirmation_status(expected: i32, collected: i32) -> Option<PickupStatus> {
+    match collected {
+        0 => None,
+        c if c >= expected => Some(PickupStatus::Collected),
+        _ => Some(PickupStatus::Partial),
+    }
+}
+
+/// Recogida visible para el usuario (choferes: solo las de sus tournées),
+/// bloqueada dentro de la transacción
+pub async fn lock(conn: &mut PgConnection, user: &AuthenticatedUser, id: Uuid) -> AppResult<Pickup> {
+    sqlx::query_as::<_, Pickup>(&format!(
+        r#"
+        SELECT {}
+        FROM pickups
+        WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
+        AND ($3::uuid IS NULL OR tournee_id IN (SELECT id FROM tournees WHERE driver_id = $3))
+        FOR UPDATE
+        "#,
+        PICKUP_COLUMNS
+    ))
+    .bind(id)
+    .bind(user.company_id)
+    .bind(driver_scope(user))
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Recogida no encontrada".to_string()))
+}
+
+/// Comprobar que la recogida sigue pendiente y su tournée abierta
+async fn ensure_open(conn: &mut PgConnection, pickup: &Pickup) -> AppResult<()> {
+    if pickup.pickup_status != PickupStatus::Pending.as_str() {
+        return Err(AppError::Conflict(format!("La recogida ya está {}", pickup.pickup_status)));
+    }
+
+    let tournee_status = sqlx::query_scalar::<_, TourneeStatus>("SELECT tournee_status FROM tournees WHERE id = $1")
+        .bind(pickup.tournee_id)
+        .fetch_one(&mut *conn)
+        .await?;
+    if !lifecycle::accepts_package_changes(&tournee_status) {
+        return Err(AppError::Conflict(format!(
+            "La tournée de la recogida está {}",
+            tournee_status.as_str()
+        )));
+    }
+    Ok(())
+}
+
+/// Recogida a la que se asocia una lectura (debe seguir pendiente)
+pub async fn for_scan(conn: &mut PgConnection, user: &AuthenticatedUser, id: Uuid) -> AppResult<Pickup> {
+    let pickup = lock(&mut *conn, user, id).await?;
+    ensure_open(&mut *conn, &pickup).await?;
+    Ok(pickup)
+}
+
+/// Recontar los bultos leídos de una recogida pendiente
+pub async fn refresh_collected(conn: &mut PgConnection, pickup_id: Uuid) -> AppResult<i32> {
+    let collected = sqlx::query_scalar::<_, i32>(
+        r#"
+        UPDATE pickups SET
+            collected_parcels = (
+                SELECT COUNT(DISTINCT barcode)::int FROM scan_events
+                WHERE pickup_id = $1 AND scan_type = 'pickup' AND validation_status = 'valid'
+            ),
+            updated_at = NOW()
+        WHERE id = $1
+        RETURNING collected_parcels
+        "#,
+    )
+    .bind(pickup_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(collected)
+}
+
+/// Confirmar una recogida con los bultos leídos
+pub async fn confirm(conn: &mut PgConnection, user: &AuthenticatedUser, id: Uuid) -> AppResult<Pickup> {
+    let pickup = lock(&mut *conn, user, id).await?;
+    ensure_open(&mut *conn, &pickup).await?;
+
+    let collected = refresh_collected(&mut *conn, pickup.id).await?;
+    let status = confirmation_status(pickup.expected_parcels, collected).ok_or_else(|| {
+        AppError::Conflict("No se ha leído ningún bulto: lee los bultos recogidos antes de confirmar".to_string())
+    })?;
+
+    let confirmed = sqlx::query_as::<_, Pickup>(&format!(
+        r#"
+        UPDATE pickups SET pickup_status = $2, collected_at = $3, updated_at = NOW()
+        WHERE id = $1
+        RETURNING {}
+        "#,
+        PICKUP_COLUMNS
+    ))
+    .bind(pickup.id)
+    .bind(status.as_str())
+    .bind(Utc::now())
+    .fetch_one(&mut *conn)
+    .await?;
+
+    log::info!(
+        "📥 Recogida {} confirmada: {}/{} bultos",
+        confirmed.pickup_reference,
+        confirmed.collected_parcels,
+        confirmed.expected_parcels
+    );
+    Ok(confirmed)
+}
+
+/// Dar una recogida por fallida
+pub async fn fail(conn: &mut PgConnection, user: &AuthenticatedUser, id: Uuid, notes: &str) -> AppResult<Pickup> {
+    let pickup = lock(&mut *conn, user, id).await?;
+    ensure_open(&mut *conn, &pickup).await?;
+
+    let failed = sqlx::query_as::<_, Pickup>(&format!(
+        r#"
+        UPDATE pickups SET pickup_status = 'failed', failure_notes = $2, updated_at = NOW()
+        WHERE id = $1
+        RETURNING {}
+        "#,
+        PICKUP_COLUMNS
+    ))
+    .bind(pickup.id)
+    .bind(notes)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    log::warn!("⚠️ Recogida {} fallida: {}", failed.pickup_reference, notes);
+    Ok(failed)
+}
+
+/// Recogidas pendientes de la tournée
+pub async fn pending_count(conn: &mut PgConnection, tournee_id: Uuid) -> AppResult<i64> {
+    let pending = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM pickups WHERE tournee_id = $1 AND pickup_status = 'pending' AND deleted_at IS NULL",
+    )
+    .bind(tournee_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(pending)
+}
+
+/// Dar por fallidas las recogidas pendientes de una tournée que se cierra
+pub async fn close_pending(conn: &mut PgConnection, tournee_id: Uuid, note: &str) -> AppResult<u64> {
+    let closed = sqlx::query(
+        r#"
+        UPDATE pickups SET pickup_status = 'failed', failure_notes = $2, updated_at = NOW()
+        WHERE tournee_id = $1 AND pickup_status = 'pending' AND deleted_at IS NULL
+        "#,
+    )
+    .bind(tournee_id)
+    .bind(note)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(closed.rows_affected())
+}
+
+/// Recogidas pendientes con coordenadas, como paradas de la ruta
+pub async fn route_stops(conn: &mut PgConnection, tournee_id: Uuid) -> AppResult<Vec<RouteStop>> {
+    let pickups = sqlx::query_as::<_, Pickup>(&format!(
+        r#"
+        SELECT {}
+        FROM pickups
+        WHERE tournee_id = $1 AND pickup_status = 'pending' AND deleted_at IS NULL
+        AND latitude IS NOT NULL AND longitude IS NOT NULL
+        ORDER BY window_start NULLS LAST, created_at
+        "#,
+        PICKUP_COLUMNS
+    ))
+    .bind(tournee_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(pickups
+        .into_iter()
+        .filter_map(|pickup| {
+            Some(RouteStop {
+                kind: StopKind::Pickup,
+                latitude: pickup.latitude?,
+                longitude: pickup.longitude?,
+                package_ids: Vec::new(),
+                pickup_id: Some(pickup.id),
+                parcels: pickup.expected_parcels,
+                window_start: pickup.window_start,
+                window_end: pickup.window_end,
+            })
+        })
+        .collect())
+}
+
+/// Bultos recogidos que van a bordo
+pub async fn collected_on_board(conn: &mut PgConnection, tournee_id: Uuid) -> AppResult<i32> {
+    let on_board = sqlx::query_scalar::<_, Option<i64>>(&format!(
+        "SELECT SUM(collected_parcels) FROM pickups WHERE tournee_id = $1 AND deleted_at IS NULL AND {}",
+        ON_BOARD_STATUSES
+    ))
+    .bind(tournee_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(on_board.unwrap_or(0) as i32)
+}
+
+/// Bultos previstos y recogidos de la tournée (sin las recogidas canceladas)
+pub async fn totals(conn: &mut PgConnection, tournee_id: Uuid) -> AppResult<(i32, i32)> {
+    let (expected, collected) = sqlx::query_as::<_, (Option<i64>, Option<i64>)>(
+        r#"
+        SELECT SUM(expected_parcels), SUM(collected_parcels)
+        FROM pickups
+        WHERE tournee_id = $1 AND pickup_status <> 'cancelled' AND deleted_at IS NULL
+        "#,
+    )
+    .bind(tournee_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok((expected.unwrap_or(0) as i32, collected.unwrap_or(0) as i32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_confirmation_status() {
+        assert_eq!(confirmation_status(5, 0), None);
+        assert_eq!(confirmation_status(5, 3), Some(PickupStatus::Partial));
+        assert_eq!(confirmation_status(5, 5), Some(PickupStatus::Collected));
+        // Bultos de más: se recoge todo lo leído
+        assert_eq!(confirmation_status(5, 6), Some(PickupStatus::Collected));
+    }
+}
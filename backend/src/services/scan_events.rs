@@ -0,0 +1,461 @@
+//! Registro y validación de lecturas de código de barras
+//!
+//! Cada lectura se asocia al paquete cuyo `tracking_number` (ref_colis) o
+//! `external_tracking_number` (codeBarreArticle) coincide con el código leído
+//! y se valida contra la tournée en curso: código desconocido, paquete de otra
+//! tournée o lectura repetida quedan marcados en `validation_status`. El
+//! informe por tournée añade los paquetes cargados que nunca se leyeron al
+//! entregar. Las lecturas de recogida con `pickup_id` se validan contra la
+//! tournée de la recogida y actualizan sus bultos recogidos. Una lectura sin
+//! tournée (en el depósito) se asigna a la del paquete; los códigos
+//! desconocidos sin tournée se consultan aparte (`list_unassigned`).
+
+use chrono::Utc;
+use sqlx::{PgConnection, PgPool};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    middleware::auth::{driver_scope, AuthenticatedUser},
+    models::scan_event::{
+        ScanEvent, ScanReport, ScanRequest, ScanType, ScanValidation, ScannedPackage,
+        SCAN_EVENT_COLUMNS,
+    },
+    services::{package_delivery::DRIVER_SCOPE_FILTER, pickups},
+    utils::{
+        errors::{AppError, AppResult},
+        french_holidays::paris_local,
+    },
+};
+
+/// Paquete al que corresponde el código leído
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct MatchedPackage {
+    id: Uuid,
+    tournee_id: Uuid,
+}
+
+/// Validar una lectura
+///
+/// `package_tournee` es la tournée del paquete encontrado (`None` si el código
+/// no corresponde a ningún paquete). En las recogidas el paquete todavía no
+/// existe en el sistema, así que un código desconocido es lo normal.
+pub fn classify(
+    scan_type: ScanType,
+    package_tournee: Option<Uuid>,
+    scan_tournee: Option<Uuid>,
+    duplicate_of: Option<Uuid>,
+) -> ScanValidation {
+    match package_tournee {
+        None if scan_type == ScanType::Pickup => {}
+        None => return ScanValidation::UnknownBarcode,
+        Some(package_tournee) => {
+            if scan_tournee.is_some_and(|scan_tournee| scan_tournee != package_tournee) {
+                return ScanValidation::WrongTournee;
+            }
+        }
+    }
+
+    if duplicate_of.is_some() {
+        ScanValidation::Duplicate
+    } else {
+        ScanValidation::Valid
+    }
+}
+
+/// Registrar una lectura
+///
+/// Debe llamarse dentro de una transacción: el bloqueo que serializa las
+/// lecturas repetidas dura hasta el commit.
+pub async fn record(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    request: &ScanRequest,
+) -> AppResult<ScanEvent> {
+    let barcode = request.barcode.trim();
+    if barcode.is_empty() {
+        return Err(AppError::BadRequest("Código de barras vacío".to_string()));
+    }
+    let scanned_at = request.scanned_at.unwrap_or_else(Utc::now);
+
+    let pickup = match request.pickup_id {
+        Some(_) if request.scan_type != ScanType::Pickup => {
+            return Err(AppError::BadRequest("pickup_id solo se admite en lecturas de recogida".to_string()));
+        }
+        Some(pickup_id) => Some(pickups::for_scan(&mut *conn, user, pickup_id).await?),
+        None => None,
+    };
+
+    let tournee_id = match (request.tournee_id, &pickup) {
+        (Some(tournee_id), _) => Some(ensure_tournee(&mut *conn, user, tournee_id).await?),
+        (None, Some(pickup)) => Some(pickup.tournee_id),
+        (None, None) => current_tournee(&mut *conn, user, paris_local(scanned_at).date()).await?,
+    };
+
+    let package = match request.package_id {
+        Some(package_id) => Some(find_package_by_id(&mut *conn, user, package_id).await?),
+        None => find_package_by_barcode(&mut *conn, user, barcode, tournee_id).await?,
+    };
+    // Sin tournée en curso (lectura en el depósito) cuenta la del paquete,
+    // para que la lectura salga en su informe
+    let tournee_id = tournee_id.or_else(|| package.as_ref().map(|p| p.tournee_id));
+
+    // Dos lecturas simultáneas del mismo paquete (o código) no pueden verse
+    // ambas como la primera: se serializan hasta el commit
+    let scan_key = format!(
+        "scan:{}:{}:{}:{}",
+        user.company_id,
+        request.scan_type.as_str(),
+        tournee_id.map(|id| id.to_string()).unwrap_or_default(),
+        package.as_ref().map(|p| p.id.to_string()).unwrap_or_else(|| barcode.to_string()),
+    );
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))")
+        .bind(&scan_key)
+        .execute(&mut *conn)
+        .await?;
+
+    let duplicate_of = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        SELECT id FROM scan_events
+        WHERE company_id = $1 AND scan_type = $2
+        AND tournee_id IS NOT DISTINCT FROM $3
+        AND CASE WHEN $4::uuid IS NULL THEN barcode = $5 ELSE package_id = $4 END
+        ORDER BY scanned_at, received_at
+        LIMIT 1
+        "#,
+    )
+    .bind(user.company_id)
+    .bind(request.scan_type.as_str())
+    .bind(tournee_id)
+    .bind(package.as_ref().map(|p| p.id))
+    .bind(barcode)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    // En una recogida cuenta la tournée de la recogida, no la del paquete
+    let expected_tournee = match &pickup {
+        Some(pickup) => Some(pickup.tournee_id),
+        None => package.as_ref().map(|p| p.tournee_id),
+    };
+    let validation = classify(request.scan_type, expected_tournee, tournee_id, duplicate_of);
+    if validation != ScanValidation::Valid {
+        warn!(
+            "⚠️ Lectura {} de {} marcada: {}",
+            request.scan_type.as_str(),
+            barcode,
+            validation.as_str()
+        );
+    }
+
+    let event = sqlx::query_as::<_, ScanEvent>(&format!(
+        r#"
+        INSERT INTO scan_events (
+            company_id, tournee_id, package_id, scanned_by, barcode, scan_type,
+            scanned_at, latitude, longitude, device_id, validation_status, duplicate_of,
+            pickup_id
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+        RETURNING {}
+        "#,
+        SCAN_EVENT_COLUMNS
+    ))
+    .bind(user.company_id)
+    .bind(tournee_id)
+    .bind(package.as_ref().map(|p| p.id))
+    .bind(user.user_id)
+    .bind(barcode)
+    .bind(request.scan_type.as_str())
+    .bind(scanned_at)
+    .bind(request.latitude)
+    .bind(request.longitude)
+    .bind(&request.device_id)
+    .bind(validation.as_str())
+    .bind(duplicate_of)
+    .bind(pickup.as_ref().map(|p| p.id))
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if let Some(pickup) = &pickup {
+        if validation == ScanValidation::Valid {
+            pickups::refresh_collected(&mut *conn, pickup.id).await?;
+        }
+    }
+
+    Ok(event)
+}
+
+/// Comprobar que la tournée indicada es visible para el usuario
+async fn ensure_tournee(conn: &mut PgConnection, user: &AuthenticatedUser, tournee_id: Uuid) -> AppResult<Uuid> {
+    sqlx::query_scalar::<_, Uuid>(
+        r#"
+        SELECT id FROM tournees
+        WHERE id = $1 AND company_id = $2
+        AND ($3::uuid IS NULL OR driver_id = $3)
+        AND deleted_at IS NULL
+        "#,
+    )
+    .bind(tournee_id)
+    .bind(user.company_id)
+    .bind(driver_scope(user))
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Tournée no encontrada".to_string()))
+}
+
+/// Tournée del chofer en el día de la lectura (la más reciente si hay varias)
+async fn current_tournee(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    date: chrono::NaiveDate,
+) -> AppResult<Option<Uuid>> {
+    let Some(driver_id) = driver_scope(user) else {
+        return Ok(None);
+    };
+
+    let tournee_id = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        SELECT id FROM tournees
+        WHERE company_id = $1 AND driver_id = $2 AND tournee_date = $3
+        AND tournee_status <> 'cancelled'
+        AND deleted_at IS NULL
+        ORDER BY created_at DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(user.company_id)
+    .bind(driver_id)
+    .bind(date)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(tournee_id)
+}
+
+/// Paquete indicado por la app, visible para el usuario (un chofer solo ve
+/// los de sus tournées)
+async fn find_package_by_id(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    package_id: Uuid,
+) -> AppResult<MatchedPackage> {
+    sqlx::query_as::<_, MatchedPackage>(&format!(
+        r#"
+        SELECT id, tournee_id FROM packages
+        WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
+        AND {}
+        "#,
+        DRIVER_SCOPE_FILTER
+    ))
+    .bind(package_id)
+    .bind(user.company_id)
+    .bind(driver_scope(user))
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Paquete no encontrado".to_string()))
+}
+
+/// Buscar el paquete por código; si hay varios (reenvíos del mismo código),
+/// se prefiere el de la tournée en curso y después el más reciente
+async fn find_package_by_barcode(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    barcode: &str,
+    tournee_id: Option<Uuid>,
+) -> AppResult<Option<MatchedPackage>> {
+    let package = sqlx::query_as::<_, MatchedPackage>(
+        r#"
+        SELECT id, tournee_id FROM packages
+        WHERE company_id = $1
+        AND (tracking_number = $2 OR external_tracking_number = $2)
+        AND deleted_at IS NULL
+        ORDER BY (tournee_id IS NOT DISTINCT FROM $3) DESC, created_at DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(user.company_id)
+    .bind(barcode)
+    .bind(tournee_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(package)
+}
+
+/// Lecturas de una tournée
+pub async fn list_for_tournee(
+    pool: &PgPool,
+    company_id: Uuid,
+    tournee_id: Uuid,
+    scan_type: Option<ScanType>,
+    flagged_only: bool,
+) -> AppResult<Vec<ScanEvent>> {
+    let events = sqlx::query_as::<_, ScanEvent>(&format!(
+        r#"
+        SELECT {}
+        FROM scan_events
+        WHERE company_id = $1 AND tournee_id = $2
+        AND ($3::text IS NULL OR scan_type = $3)
+        AND (NOT $4 OR validation_status <> 'valid')
+        ORDER BY scanned_at, received_at
+        "#,
+        SCAN_EVENT_COLUMNS
+    ))
+    .bind(company_id)
+    .bind(tournee_id)
+    .bind(scan_type.map(|t| t.as_str()))
+    .bind(flagged_only)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(events)
+}
+
+/// Lecturas sin tournée (códigos desconocidos leídos fuera de una tournée)
+pub async fn list_unassigned(
+    pool: &PgPool,
+    company_id: Uuid,
+    scan_type: Option<ScanType>,
+    flagged_only: bool,
+) -> AppResult<Vec<ScanEvent>> {
+    let events = sqlx::query_as::<_, ScanEvent>(&format!(
+        r#"
+        SELECT {}
+        FROM scan_events
+        WHERE company_id = $1 AND tournee_id IS NULL
+        AND ($2::text IS NULL OR scan_type = $2)
+        AND (NOT $3 OR validation_status <> 'valid')
+        ORDER BY scanned_at DESC, received_at DESC
+        "#,
+        SCAN_EVENT_COLUMNS
+    ))
+    .bind(company_id)
+    .bind(scan_type.map(|t| t.as_str()))
+    .bind(flagged_only)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(events)
+}
+
+/// Informe de lecturas de una tournée
+pub async fn tournee_report(pool: &PgPool, company_id: Uuid, tournee_id: Uuid) -> AppResult<ScanReport> {
+    let (expected_packages, loaded_packages) = sqlx::query_as::<_, (i64, i64)>(
+        r#"
+        SELECT
+            COUNT(*),
+            COUNT(*) FILTER (WHERE EXISTS (
+                SELECT 1 FROM scan_events s
+                WHERE s.package_id = p.id AND s.tournee_id = p.tournee_id AND s.scan_type = 'load'
+            ))
+        FROM packages p
+        WHERE p.company_id = $1 AND p.tournee_id = $2 AND p.deleted_at IS NULL
+        "#,
+    )
+    .bind(company_id)
+    .bind(tournee_id)
+    .fetch_one(pool)
+    .await?;
+
+    let not_loaded = scanned_packages(
+        pool,
+        company_id,
+        tournee_id,
+        "NOT EXISTS (SELECT 1 FROM scan_events s WHERE s.package_id = p.id AND s.tournee_id = p.tournee_id AND s.scan_type = 'load')",
+    )
+    .await?;
+    let loaded_not_delivered = scanned_packages(
+        pool,
+        company_id,
+        tournee_id,
+        r#"EXISTS (SELECT 1 FROM scan_events s WHERE s.package_id = p.id AND s.tournee_id = p.tournee_id AND s.scan_type = 'load')
+        AND NOT EXISTS (
+            SELECT 1 FROM scan_events s
+            WHERE s.package_id = p.id AND s.tournee_id = p.tournee_id
+            AND s.scan_type IN ('deliver', 'fail', 'return')
+        )"#,
+    )
+    .await?;
+
+    let flagged = list_for_tournee(pool, company_id, tournee_id, None, true).await?;
+    let by_status = |status: ScanValidation| -> Vec<ScanEvent> {
+        flagged
+            .iter()
+            .filter(|event| event.validation_status == status.as_str())
+            .cloned()
+            .collect()
+    };
+
+    Ok(ScanReport {
+        tournee_id,
+        expected_packages,
+        loaded_packages,
+        not_loaded,
+        loaded_not_delivered,
+        unknown_barcodes: by_status(ScanValidation::UnknownBarcode),
+        wrong_tournee: by_status(ScanValidation::WrongTournee),
+        duplicates: by_status(ScanValidation::Duplicate),
+    })
+}
+
+async fn scanned_packages(
+    pool: &PgPool,
+    company_id: Uuid,
+    tournee_id: Uuid,
+    condition: &str,
+) -> AppResult<Vec<ScannedPackage>> {
+    let packages = sqlx::query_as::<_, ScannedPackage>(&format!(
+        r#"
+        SELECT
+            p.id AS package_id, p.tracking_number, p.external_tracking_number,
+            p.delivery_status::text AS delivery_status,
+            (SELECT MAX(s.scanned_at) FROM scan_events s WHERE s.package_id = p.id) AS last_scan_at
+        FROM packages p
+        WHERE p.company_id = $1 AND p.tournee_id = $2 AND p.deleted_at IS NULL
+        AND {}
+        ORDER BY p.tracking_number
+        "#,
+        condition
+    ))
+    .bind(company_id)
+    .bind(tournee_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(packages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_unknown_and_wrong_tournee() {
+        let tournee = Uuid::new_v4();
+        let other = Uuid::new_v4();
+
+        assert_eq!(classify(ScanType::Load, None, Some(tournee), None), ScanValidation::UnknownBarcode);
+        assert_eq!(classify(ScanType::Deliver, Some(other), Some(tournee), None), ScanValidation::WrongTournee);
+        assert_eq!(classify(ScanType::Deliver, Some(tournee), Some(tournee), None), ScanValidation::Valid);
+        // Sin tournée en curso (lectura en el depósito) no hay con qué comparar
+        assert_eq!(classify(ScanType::Return, Some(other), None, None), ScanValidation::Valid);
+    }
+
+    #[test]
+    fn test_classify_duplicates() {
+        let tournee = Uuid::new_v4();
+        let previous = Some(Uuid::new_v4());
+
+        assert_eq!(classify(ScanType::Load, Some(tournee), Some(tournee), previous), ScanValidation::Duplicate);
+        // Un código desconocido repetido sigue siendo desconocido
+        assert_eq!(classify(ScanType::Load, None, Some(tournee), previous), ScanValidation::UnknownBarcode);
+        // Un paquete de otra tournée se marca como tal aunque se repita
+        assert_eq!(classify(ScanType::Load, Some(Uuid::new_v4()), Some(tournee), previous), ScanValidation::WrongTournee);
+    }
+
+    #[test]
+    fn test_pickup_of_unregistered_parcel_is_valid() {
+        let tournee = Some(Uuid::new_v4());
+
+        assert_eq!(classify(ScanType::Pickup, None, tournee, None), ScanValidation::Valid);
+        assert_eq!(classify(ScanType::Pickup, None, tournee, Some(Uuid::new_v4())), ScanValidation::Duplicate);
+    }
+}
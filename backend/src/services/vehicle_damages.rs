@@ -0,0 +1,557 @@
+//! Daños e incidentes de vehículo
+//!
+//! Declaración (chofer o admin), transiciones de `damage_status` con sus
+//! datos obligatorios y cálculo de lo que paga el chofer. Cada cambio
+//! recalcula `vehicles.total_damage_cost` y `damage_incidents_count` a partir
+//! de los daños vivos del vehículo, y queda en `status_history`.
+
+use chrono::{NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde_json::json;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    middleware::auth::{driver_scope, AuthenticatedUser},
+    models::notification::{NewNotification, NotificationPriority, NotificationType},
+    models::status_history::TransitionEntity,
+    models::tournee::{Tournee, TOURNEE_COLUMNS},
+    models::user::UserType,
+    models::vehicle_damage::{
+        DamageStatus, DamageTransitionRequest, DeclareDamageRequest, LiabilityLine, LiabilityStatement,
+        UpdateDamageRequest, VehicleDamage, VEHICLE_DAMAGE_COLUMNS,
+    },
+    services::{lifecycle, notifications},
+    utils::errors::{AppError, AppResult},
+};
+
+/// `to_status` del historial al borrar un daño (no es un `DamageStatus`)
+const DELETED_STATUS: &str = "deleted";
+
+/// Transiciones de daño permitidas
+pub fn damage_transition_allowed(from: &DamageStatus, to: &DamageStatus) -> bool {
+    use DamageStatus::*;
+
+    match (from, to) {
+        (Closed, _) | (_, Pending) => false,
+        (Pending, to) => matches!(to, Assessed | Closed),
+        (_, Assessed) => false,
+        (from, to) => from != to,
+    }
+}
+
+/// Importe a cargo del chofer: su porcentaje del coste que asume la empresa
+/// (la franquicia si hay siniestro, si no la reparación), redondeado al céntimo
+pub fn driver_liability(
+    repair_cost: Option<Decimal>,
+    responsibility_percentage: Option<i32>,
+    insurance_claim: bool,
+    insurance_deductible: Option<Decimal>,
+) -> Option<Decimal> {
+    let percentage = responsibility_percentage?;
+    let base = if insurance_claim {
+        insurance_deductible.or(repair_cost)?
+    } else {
+        repair_cost?
+    };
+
+    Some((base * Decimal::from(percentage) / Decimal::from(100)).round_dp(2))
+}
+
+fn ensure_amount(value: Option<Decimal>, field: &str) -> AppResult<()> {
+    match value {
+        Some(amount) if amount < Decimal::ZERO => {
+            Err(AppError::BadRequest(format!("{} no puede ser negativo", field)))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Daño visible para el usuario (choferes: solo los suyos)
+pub async fn get(conn: &mut PgConnection, user: &AuthenticatedUser, id: Uuid) -> AppResult<VehicleDamage> {
+    fetch(conn, user, id, "").await
+}
+
+/// Daño visible para el usuario, bloqueado hasta el final de la transacción
+pub async fn lock(conn: &mut PgConnection, user: &AuthenticatedUser, id: Uuid) -> AppResult<VehicleDamage> {
+    fetch(conn, user, id, "FOR UPDATE").await
+}
+
+async fn fetch(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    id: Uuid,
+    locking: &str,
+) -> AppResult<VehicleDamage> {
+    sqlx::query_as::<_, VehicleDamage>(&format!(
+        r#"
+        SELECT {}
+        FROM vehicle_damages
+        WHERE id = $1 AND deleted_at IS NULL
+        AND vehicle_id IN (SELECT id FROM vehicles WHERE company_id = $2)
+        AND ($3::uuid IS NULL OR driver_id = $3)
+        {}
+        "#,
+        VEHICLE_DAMAGE_COLUMNS, locking
+    ))
+    .bind(id)
+    .bind(user.company_id)
+    .bind(driver_scope(user))
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Daño no encontrado".to_string()))
+}
+
+/// Recalcular el coste y el número de incidentes del vehículo
+pub async fn refresh_vehicle_totals(conn: &mut PgConnection, vehicle_id: Uuid) -> AppResult<()> {
+    sqlx::query(
+        r#"
+        UPDATE vehicles SET
+            total_damage_cost = totals.cost,
+            damage_incidents_count = totals.incidents,
+            updated_at = NOW()
+        FROM (
+            SELECT COALESCE(SUM(COALESCE(actual_repair_cost, estimated_repair_cost, 0)), 0) AS cost,
+                   COUNT(*)::int AS incidents
+            FROM vehicle_damages
+            WHERE vehicle_id = $1 AND deleted_at IS NULL
+        ) totals
+        WHERE vehicles.id = $1
+        "#,
+    )
+    .bind(vehicle_id)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+async fn record_transition(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    damage: &VehicleDamage,
+    from: Option<DamageStatus>,
+    reason: Option<&str>,
+) -> AppResult<()> {
+    lifecycle::record(
+        &mut *conn,
+        &lifecycle::NewTransition {
+            company_id: user.company_id,
+            entity: TransitionEntity::VehicleDamage,
+            entity_id: damage.id,
+            from_status: from.as_ref().map(DamageStatus::as_str),
+            to_status: damage.damage_status.as_str(),
+            changed_by: Some(user.user_id),
+            forced: false,
+            reason,
+            metadata: json!({
+                "repair_cost": damage.repair_cost(),
+                "responsibility_percentage": damage.responsibility_percentage,
+                "driver_deductible": damage.driver_deductible,
+            }),
+        },
+    )
+    .await
+}
+
+/// Tournée del incidente: la indicada o, para un chofer, su tournée activa
+async fn incident_tournee(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    tournee_id: Option<Uuid>,
+) -> AppResult<Option<Tournee>> {
+    if let Some(tournee_id) = tournee_id {
+        let tournee = sqlx::query_as::<_, Tournee>(&format!(
+            r#"
+            SELECT {} FROM tournees
+            WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL
+            AND ($3::uuid IS NULL OR driver_id = $3)
+            "#,
+            TOURNEE_COLUMNS
+        ))
+        .bind(tournee_id)
+        .bind(user.company_id)
+        .bind(driver_scope(user))
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Tournée no encontrada".to_string()))?;
+        return Ok(Some(tournee));
+    }
+
+    if user.user_type != UserType::Driver {
+        return Ok(None);
+    }
+    Ok(sqlx::query_as::<_, Tournee>(&format!(
+        r#"
+        SELECT {} FROM tournees
+        WHERE driver_id = $1 AND company_id = $2 AND deleted_at IS NULL
+        AND tournee_status IN ('in_progress', 'paused')
+        ORDER BY tournee_date DESC
+        LIMIT 1
+        "#,
+        TOURNEE_COLUMNS
+    ))
+    .bind(user.user_id)
+    .bind(user.company_id)
+    .fetch_optional(&mut *conn)
+    .await?)
+}
+
+/// Declarar un incidente
+pub async fn declare(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    request: &DeclareDamageRequest,
+) -> AppResult<VehicleDamage> {
+    let tournee = incident_tournee(&mut *conn, user, request.tournee_id).await?;
+
+    let vehicle_id = match (request.vehicle_id, tournee.as_ref()) {
+        (Some(vehicle_id), Some(tournee)) if vehicle_id != tournee.vehicle_id => {
+            return Err(AppError::BadRequest("El vehículo no es el de la tournée".to_string()));
+        }
+        (Some(vehicle_id), _) => vehicle_id,
+        (None, Some(tournee)) => tournee.vehicle_id,
+        (None, None) => {
+            return Err(AppError::BadRequest(
+                "Sin tournée activa hay que indicar el vehículo".to_string(),
+            ));
+        }
+    };
+    let driver_id = match user.user_type {
+        UserType::Driver => Some(user.user_id),
+        UserType::Admin => request.driver_id.or(tournee.as_ref().map(|tournee| tournee.driver_id)),
+    };
+    if let (UserType::Admin, Some(requested)) = (&user.user_type, request.driver_id) {
+        let is_driver = sqlx::query_scalar::<_, i32>(
+            r#"
+            SELECT 1 FROM users
+            WHERE id = $1 AND company_id = $2 AND user_type = 'driver' AND deleted_at IS NULL
+            "#,
+        )
+        .bind(requested)
+        .bind(user.company_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+        if is_driver.is_none() {
+            return Err(AppError::NotFound("Chofer no encontrado".to_string()));
+        }
+    }
+
+    let license_plate = sqlx::query_scalar::<_, String>(
+        "SELECT license_plate FROM vehicles WHERE id = $1 AND company_id = $2 AND deleted_at IS NULL",
+    )
+    .bind(vehicle_id)
+    .bind(user.company_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Vehículo no encontrado".to_string()))?;
+
+    let damage = sqlx::query_as::<_, VehicleDamage>(&format!(
+        r#"
+        INSERT INTO vehicle_damages (
+            vehicle_id, driver_id, tournee_id, incident_date, incident_time,
+            damage_type, damage_location, description, police_report, witness_statements
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        RETURNING {}
+        "#,
+        VEHICLE_DAMAGE_COLUMNS
+    ))
+    .bind(vehicle_id)
+    .bind(driver_id)
+    .bind(tournee.as_ref().map(|tournee| tournee.id))
+    .bind(request.incident_date.unwrap_or_else(|| Utc::now().date_naive()))
+    .bind(request.incident_time)
+    .bind(request.damage_type)
+    .bind(&request.damage_location)
+    .bind(&request.description)
+    .bind(&request.police_report)
+    .bind(&request.witness_statements)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    refresh_vehicle_totals(&mut *conn, vehicle_id).await?;
+    record_transition(&mut *conn, user, &damage, None, None).await?;
+    notifications::record(
+        &mut *conn,
+        &NewNotification {
+            company_id: user.company_id,
+            notification_type: NotificationType::DamageIncident,
+            priority: if damage.damage_type.is_serious() {
+                NotificationPriority::High
+            } else {
+                NotificationPriority::Medium
+            },
+            title: format!("Incidente declarado: {} ({})", damage.damage_type.as_str(), license_plate),
+            message: damage.description.clone(),
+            document_id: None,
+            vehicle_id: Some(vehicle_id),
+            driver_id,
+            metadata: json!({ "damage_id": damage.id, "tournee_id": damage.tournee_id }),
+        },
+    )
+    .await?;
+
+    Ok(damage)
+}
+
+/// Corregir los datos del incidente (el chofer, solo mientras está pendiente)
+pub async fn update(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    id: Uuid,
+    request: &UpdateDamageRequest,
+) -> AppResult<VehicleDamage> {
+    let current = lock(&mut *conn, user, id).await?;
+    if user.user_type == UserType::Driver && current.damage_status != DamageStatus::Pending {
+        return Err(AppError::Conflict("El daño ya está valorado: solo un admin puede cambiarlo".to_string()));
+    }
+
+    let damage = sqlx::query_as::<_, VehicleDamage>(&format!(
+        r#"
+        UPDATE vehicle_damages SET
+            incident_date = COALESCE($2, incident_date),
+            incident_time = COALESCE($3, incident_time),
+            damage_type = COALESCE($4, damage_type),
+            damage_location = COALESCE($5, damage_location),
+            description = COALESCE($6, description),
+            police_report = COALESCE($7, police_report),
+            witness_statements = COALESCE($8, witness_statements),
+            updated_at = NOW()
+        WHERE id = $1
+        RETURNING {}
+        "#,
+        VEHICLE_DAMAGE_COLUMNS
+    ))
+    .bind(current.id)
+    .bind(request.incident_date)
+    .bind(request.incident_time)
+    .bind(request.damage_type)
+    .bind(&request.damage_location)
+    .bind(&request.description)
+    .bind(&request.police_report)
+    .bind(&request.witness_statements)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(damage)
+}
+
+/// Pasar un daño a otro estado con los datos que exige
+pub async fn transition(
+    conn: &mut PgConnection,
+    user: &AuthenticatedUser,
+    id: Uuid,
+    request: &DamageTransitionRequest,
+) -> AppResult<VehicleDamage> {
+    let current = lock(&mut *conn, user, id).await?;
+    let to = request.damage_status;
+    if !damage_transition_allowed(&current.damage_status, &to) {
+        return Err(AppError::Conflict(format!(
+            "Transición de daño no permitida: {} → {}",
+            current.damage_status.as_str(),
+            to.as_str()
+        )));
+    }
+    ensure_amount(request.estimated_repair_cost, "El coste estimado")?;
+    ensure_amount(request.actual_repair_cost, "El coste real")?;
+    ensure_amount(request.driver_deductible, "El importe del chofer")?;
+    ensure_amount(request.deductible_amount, "La franquicia")?;
+
+    let today = Utc::now().date_naive();
+    let estimated_repair_cost = request.estimated_repair_cost.or(current.estimated_repair_cost);
+    let actual_repair_cost = request.actual_repair_cost.or(current.actual_repair_cost);
+    let responsibility_percentage = request.responsibility_percentage.or(current.responsibility_percentage);
+    let claim_number = request.claim_number.clone().or_else(|| current.claim_number.clone());
+    let deductible_amount = request.deductible_amount.or(current.deductible_amount);
+    let insurance_claim = to == DamageStatus::InsuranceClaim || current.insurance_claim.unwrap_or(false);
+
+    let missing = match to {
+        DamageStatus::Assessed if estimated_repair_cost.is_none() => Some("el coste estimado"),
+        DamageStatus::Assessed if responsibility_percentage.is_none() => Some("el porcentaje de responsabilidad"),
+        DamageStatus::Repaired if actual_repair_cost.is_none() => Some("el coste real"),
+        DamageStatus::InsuranceClaim if claim_number.is_none() => Some("el número de siniestro"),
+        DamageStatus::DriverLiable if responsibility_percentage.unwrap_or(0) == 0 => {
+            Some("un porcentaje de responsabilidad mayor que cero")
+        }
+        _ => None,
+    };
+    if let Some(missing) = missing {
+        return Err(AppError::BadRequest(format!("Para pasar a {} falta {}", to.as_str(), missing)));
+    }
+
+    // El importe del chofer se recalcula cuando cambia algo de lo que depende,
+    // salvo que el admin lo fije
+    let inputs_changed = request.estimated_repair_cost.is_some()
+        || request.actual_repair_cost.is_some()
+        || request.responsibility_percentage.is_some()
+        || request.deductible_amount.is_some()
+        || insurance_claim != current.insurance_claim.unwrap_or(false);
+    let driver_deductible = match request.driver_deductible {
+        Some(amount) => Some(amount),
+        None if inputs_changed || current.driver_deductible.is_none() => driver_liability(
+            actual_repair_cost.or(estimated_repair_cost),
+            responsibility_percentage,
+            insurance_claim,
+            deductible_amount,
+        ),
+        None => current.driver_deductible,
+    };
+
+    let damage = sqlx::query_as::<_, VehicleDamage>(&format!(
+        r#"
+        UPDATE vehicle_damages SET
+            damage_status = $2,
+            estimated_repair_cost = $3,
+            actual_repair_cost = $4,
+            responsibility_percentage = $5,
+            driver_deductible = $6,
+            insurance_claim = $7,
+            claim_number = $8,
+            deductible_amount = $9,
+            insurance_company = COALESCE($10, insurance_company),
+            assessment_date = CASE WHEN $2 = 'assessed'::damage_status THEN $11 ELSE assessment_date END,
+            repair_date = CASE WHEN $2 = 'repaired'::damage_status THEN COALESCE($12, $11) ELSE repair_date END,
+            completion_date = CASE WHEN $2 = 'closed'::damage_status THEN $11 ELSE completion_date END,
+            updated_at = NOW()
+        WHERE id = $1
+        RETURNING {}
+        "#,
+        VEHICLE_DAMAGE_COLUMNS
+    ))
+    .bind(current.id)
+    .bind(to)
+    .bind(estimated_repair_cost)
+    .bind(actual_repair_cost)
+    .bind(responsibility_percentage)
+    .bind(driver_deductible)
+    .bind(insurance_claim)
+    .bind(&claim_number)
+    .bind(deductible_amount)
+    .bind(&request.insurance_company)
+    .bind(today)
+    .bind(request.repair_date)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    refresh_vehicle_totals(&mut *conn, damage.vehicle_id).await?;
+    record_transition(&mut *conn, user, &damage, Some(current.damage_status), request.reason.as_deref()).await?;
+
+    log::info!(
+        "🚗 Daño {}: {} → {} (chofer: {:?})",
+        damage.id,
+        current.damage_status.as_str(),
+        damage.damage_status.as_str(),
+        damage.driver_deductible
+    );
+    Ok(damage)
+}
+
+/// Borrar un daño registrado por error (soft delete)
+pub async fn delete(conn: &mut PgConnection, user: &AuthenticatedUser, id: Uuid) -> AppResult<()> {
+    let damage = lock(&mut *conn, user, id).await?;
+
+    sqlx::query("UPDATE vehicle_damages SET deleted_at = NOW(), updated_at = NOW() WHERE id = $1")
+        .bind(damage.id)
+        .execute(&mut *conn)
+        .await?;
+    refresh_vehicle_totals(&mut *conn, damage.vehicle_id).await?;
+
+    lifecycle::record(
+        &mut *conn,
+        &lifecycle::NewTransition {
+            company_id: user.company_id,
+            entity: TransitionEntity::VehicleDamage,
+            entity_id: damage.id,
+            from_status: Some(damage.damage_status.as_str()),
+            to_status: DELETED_STATUS,
+            changed_by: Some(user.user_id),
+            forced: false,
+            reason: None,
+            metadata: json!({
+                "repair_cost": damage.repair_cost(),
+                "driver_deductible": damage.driver_deductible,
+            }),
+        },
+    )
+    .await?;
+
+    log::info!("🗑️ Daño {} borrado ({})", damage.id, damage.damage_status.as_str());
+    Ok(())
+}
+
+/// Estado de responsabilidad del chofer en el periodo
+pub async fn liability_statement(
+    pool: &PgPool,
+    company_id: Uuid,
+    driver_id: Uuid,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> AppResult<LiabilityStatement> {
+    let lines = sqlx::query_as::<_, LiabilityLine>(
+        r#"
+        SELECT d.id AS damage_id, d.incident_date, v.license_plate, d.damage_type, d.damage_status,
+               COALESCE(d.actual_repair_cost, d.estimated_repair_cost) AS repair_cost,
+               d.insurance_claim, d.deductible_amount, d.responsibility_percentage,
+               d.driver_deductible
+        FROM vehicle_damages d
+        JOIN vehicles v ON v.id = d.vehicle_id
+        WHERE d.driver_id = $1 AND v.company_id = $2 AND d.deleted_at IS NULL
+        AND ($3::date IS NULL OR d.incident_date >= $3)
+        AND ($4::date IS NULL OR d.incident_date <= $4)
+        ORDER BY d.incident_date, d.created_at
+        "#,
+    )
+    .bind(driver_id)
+    .bind(company_id)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(LiabilityStatement {
+        driver_id,
+        from,
+        to,
+        incidents: lines.len(),
+        pending_assessment: lines
+            .iter()
+            .filter(|line| line.damage_status == DamageStatus::Pending)
+            .count(),
+        total_owed: lines.iter().filter_map(|line| line.driver_deductible).sum(),
+        lines,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_damage_transitions() {
+        use DamageStatus::*;
+
+        assert!(damage_transition_allowed(&Pending, &Assessed));
+        assert!(damage_transition_allowed(&Pending, &Closed));
+        assert!(!damage_transition_allowed(&Pending, &Repaired));
+        assert!(damage_transition_allowed(&Assessed, &InsuranceClaim));
+        assert!(damage_transition_allowed(&InsuranceClaim, &Repaired));
+        assert!(damage_transition_allowed(&Repaired, &DriverLiable));
+        assert!(!damage_transition_allowed(&Repaired, &Repaired));
+        assert!(!damage_transition_allowed(&Repaired, &Assessed));
+        assert!(!damage_transition_allowed(&Assessed, &Pending));
+        assert!(!damage_transition_allowed(&Closed, &Assessed));
+    }
+
+    #[test]
+    fn test_driver_liability() {
+        let euros = |cents: i64| Some(Decimal::new(cents, 2));
+
+        // Sin siniestro: porcentaje de la reparación
+        assert_eq!(driver_liability(euros(85000), Some(30), false, None), euros(25500));
+        // Con siniestro: porcentaje de la franquicia
+        assert_eq!(driver_liability(euros(240000), Some(50), true, euros(50000)), euros(25000));
+        assert_eq!(driver_liability(euros(9999), Some(33), false, None), euros(3300));
+        assert_eq!(driver_liability(euros(85000), None, false, None), None);
+        assert_eq!(driver_liability(None, Some(100), false, None), None);
+    }
+}
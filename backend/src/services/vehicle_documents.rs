@@ -0,0 +1,267 @@
+//! Caducidad de documentos de vehículo
+//!
+//! Una vez al día se recalcula `document_status` y se avisa en
+//! `notifications_log` de los documentos que cruzan un umbral (30 días, 15
+//! días, caducado). Cada umbral se avisa una sola vez: el aviso y su flag
+//! `notification_sent_*` se guardan en la misma transacción, y el flag solo
+//! vuelve a FALSE al renovar el documento (ver el trigger
+//! `update_document_status`). Un vehículo con el seguro o el contrôle
+//! technique caducado no se puede asignar a una tournée.
+
+use chrono::NaiveDate;
+use serde_json::json;
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    models::notification::NewNotification,
+    models::vehicle_document::{DocumentType, ExpiryCheckSummary, ExpiryThreshold, VehicleDocument, VEHICLE_DOCUMENT_COLUMNS},
+    services::notifications,
+    utils::errors::{AppError, AppResult},
+};
+
+/// Estado según la caducidad ($1 = hoy), con los mismos cortes que el trigger
+const STATUS_FOR_EXPIRY: &str = r#"
+    CASE
+        WHEN expiry_date > $1::date + 30 THEN 'valid'::document_status
+        WHEN expiry_date > $1::date THEN 'expiring_soon'::document_status
+        ELSE 'expired'::document_status
+    END
+"#;
+
+/// Intervalo entre pasadas del job
+const CHECK_INTERVAL_SECONDS: u64 = 24 * 3600;
+
+/// Documento pendiente de aviso, con la matrícula para el mensaje
+#[derive(Debug, sqlx::FromRow)]
+struct DueDocument {
+    id: Uuid,
+    company_id: Uuid,
+    vehicle_id: Uuid,
+    document_type: DocumentType,
+    document_name: String,
+    expiry_date: NaiveDate,
+    license_plate: String,
+}
+
+/// Título y mensaje del aviso de un umbral
+fn notification_text(document: &DueDocument, threshold: ExpiryThreshold, today: NaiveDate) -> (String, String) {
+    let days = (document.expiry_date - today).num_days();
+    match threshold {
+        ExpiryThreshold::Expired => (
+            format!("Documento caducado: {} ({})", document.document_name, document.license_plate),
+            format!(
+                "{} del vehículo {} caducó el {}",
+                document.document_name, document.license_plate, document.expiry_date
+            ),
+        ),
+        _ => (
+            format!("Documento a renovar: {} ({})", document.document_name, document.license_plate),
+            format!(
+                "{} del vehículo {} caduca el {} (en {} días)",
+                document.document_name, document.license_plate, document.expiry_date, days
+            ),
+        ),
+    }
+}
+
+/// Recalcular el estado de los documentos que han cambiado de tramo
+async fn refresh_statuses(pool: &PgPool, today: NaiveDate) -> AppResult<u64> {
+    let updated = sqlx::query(&format!(
+        r#"
+        UPDATE vehicle_documents SET document_status = {status}, updated_at = NOW()
+        WHERE deleted_at IS NULL AND document_status <> {status}
+        "#,
+        status = STATUS_FOR_EXPIRY
+    ))
+    .bind(today)
+    .execute(pool)
+    .await?;
+
+    Ok(updated.rows_affected())
+}
+
+/// Avisar del umbral alcanzado por un documento (una sola vez)
+async fn notify(pool: &PgPool, document: &DueDocument, threshold: ExpiryThreshold, today: NaiveDate) -> AppResult<bool> {
+    let mut tx = pool.begin().await?;
+
+    // Se marcan también los umbrales anteriores: un documento dado de alta a
+    // 10 días de caducar avisa una vez (15 días), no dos
+    let flags = [ExpiryThreshold::Days30, ExpiryThreshold::Days15, ExpiryThreshold::Expired]
+        .into_iter()
+        .filter(|reached| *reached <= threshold)
+        .map(|reached| format!("{} = TRUE", reached.flag_column()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let claimed = sqlx::query(&format!(
+        "UPDATE vehicle_documents SET {} WHERE id = $1 AND NOT COALESCE({}, FALSE)",
+        flags,
+        threshold.flag_column()
+    ))
+    .bind(document.id)
+    .execute(&mut *tx)
+    .await?;
+    if claimed.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    let (title, message) = notification_text(document, threshold, today);
+    notifications::record(
+        &mut tx,
+        &NewNotification {
+            company_id: document.company_id,
+            notification_type: threshold.notification_type(),
+            priority: threshold.priority(),
+            title,
+            message,
+            document_id: Some(document.id),
+            vehicle_id: Some(document.vehicle_id),
+            driver_id: None,
+            metadata: json!({
+                "document_type": document.document_type.as_str(),
+                "expiry_date": document.expiry_date,
+                "days_left": (document.expiry_date - today).num_days(),
+                "blocking": document.document_type.is_blocking(),
+            }),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Pasada del job: estados y avisos pendientes
+pub async fn run_expiry_check(pool: &PgPool) -> AppResult<ExpiryCheckSummary> {
+    // La fecha de la base de datos, la misma que usa el trigger
+    let today = sqlx::query_scalar::<_, NaiveDate>("SELECT CURRENT_DATE")
+        .fetch_one(pool)
+        .await?;
+
+    let mut summary = ExpiryCheckSummary {
+        statuses_updated: refresh_statuses(pool, today).await?,
+        ..Default::default()
+    };
+
+    let due = sqlx::query_as::<_, DueDocument>(
+        r#"
+        SELECT d.id, d.company_id, d.vehicle_id, d.document_type, d.document_name,
+               d.expiry_date, v.license_plate
+        FROM vehicle_documents d
+        JOIN vehicles v ON v.id = d.vehicle_id AND v.deleted_at IS NULL
+        WHERE d.deleted_at IS NULL AND d.expiry_date <= $1::date + 30
+        AND (
+            NOT COALESCE(d.notification_sent_30_days, FALSE)
+            OR (d.expiry_date <= $1::date + 15 AND NOT COALESCE(d.notification_sent_15_days, FALSE))
+            OR (d.expiry_date <= $1::date AND NOT COALESCE(d.notification_sent_expired, FALSE))
+        )
+        ORDER BY d.expiry_date
+        "#,
+    )
+    .bind(today)
+    .fetch_all(pool)
+    .await?;
+
+    for document in &due {
+        let Some(threshold) = ExpiryThreshold::reached(document.expiry_date, today) else {
+            continue;
+        };
+        match notify(pool, document, threshold, today).await {
+            Ok(true) => summary.notifications += 1,
+            Ok(false) => {}
+            Err(e) => log::error!("❌ Error avisando de la caducidad del documento {}: {}", document.id, e),
+        }
+    }
+
+    if summary.statuses_updated > 0 || summary.notifications > 0 {
+        log::info!(
+            "📄 Caducidades: {} estados actualizados, {} avisos",
+            summary.statuses_updated,
+            summary.notifications
+        );
+    }
+    Ok(summary)
+}
+
+/// Lanzar la revisión diaria de caducidades
+pub fn spawn_expiry_job(pool: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(CHECK_INTERVAL_SECONDS));
+        loop {
+            interval.tick().await;
+            if let Err(e) = run_expiry_check(&pool).await {
+                log::error!("❌ Error en la revisión de caducidades: {}", e);
+            }
+        }
+    });
+}
+
+/// Documentos obligatorios (seguro, contrôle technique) caducados en `date`
+pub async fn expired_blocking<'e>(
+    executor: impl PgExecutor<'e>,
+    vehicle_id: Uuid,
+    date: NaiveDate,
+) -> AppResult<Vec<VehicleDocument>> {
+    Ok(sqlx::query_as::<_, VehicleDocument>(&format!(
+        r#"
+        SELECT {}
+        FROM vehicle_documents
+        WHERE vehicle_id = $1 AND deleted_at IS NULL AND expiry_date <= $2
+        AND document_type IN ('insurance', 'technical_control')
+        ORDER BY expiry_date
+        "#,
+        VEHICLE_DOCUMENT_COLUMNS
+    ))
+    .bind(vehicle_id)
+    .bind(date)
+    .fetch_all(executor)
+    .await?)
+}
+
+/// Rechazar la asignación de un vehículo sin seguro o contrôle technique en
+/// vigor el día de la tournée
+pub async fn ensure_roadworthy<'e>(executor: impl PgExecutor<'e>, vehicle_id: Uuid, date: NaiveDate) -> AppResult<()> {
+    let expired = expired_blocking(executor, vehicle_id, date).await?;
+    if expired.is_empty() {
+        return Ok(());
+    }
+
+    let documents = expired
+        .iter()
+        .map(|document| format!("{} (caducado el {})", document.document_type.as_str(), document.expiry_date))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Err(AppError::BadRequest(format!(
+        "El vehículo no puede circular el {}: {}",
+        date, documents
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(expiry_date: NaiveDate) -> DueDocument {
+        DueDocument {
+            id: Uuid::nil(),
+            company_id: Uuid::nil(),
+            vehicle_id: Uuid::nil(),
+            document_type: DocumentType::Insurance,
+            document_name: "Assurance flotte".to_string(),
+            expiry_date,
+            license_plate: "AB-123-CD".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_notification_text() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+
+        let (title, message) = notification_text(&document(today + chrono::Duration::days(12)), ExpiryThreshold::Days15, today);
+        assert_eq!(title, "Documento a renovar: Assurance flotte (AB-123-CD)");
+        assert!(message.ends_with("caduca el 2024-03-13 (en 12 días)"));
+
+        let (title, _) = notification_text(&document(today), ExpiryThreshold::Expired, today);
+        assert!(title.starts_with("Documento caducado"));
+    }
+}
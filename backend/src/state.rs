@@ -10,6 +10,11 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use crate::config::EnvironmentConfig;
 use crate::cache::RedisClient;
+use crate::services::address_validation_jobs::AddressValidationJobStore;
+use crate::services::dispatch_feed::DispatchFeed;
+use crate::services::field_encryption::FieldCipher;
+use crate::services::media_service::MediaService;
+use crate::services::media_storage::StorageError;
 
 /// Estructura para almacenar tokens de autenticación
 #[derive(Clone, Debug)]
@@ -42,17 +47,87 @@ pub struct AppState {
     pub redis: RedisClient,
     pub http_client: Client,
     pub auth_tokens: Arc<RwLock<HashMap<String, AuthToken>>>,
+    pub address_validation_jobs: AddressValidationJobStore,
+    /// Cifrador de campos sensibles (None si no hay claves configuradas)
+    pub field_cipher: Option<Arc<FieldCipher>>,
+    /// Almacenamiento de fotos y firmas (None si la configuración es inválida)
+    pub media: Option<Arc<MediaService>>,
+    /// Canal de seguimiento en vivo (Redis pub/sub + SSE)
+    pub dispatch: DispatchFeed,
 }
 
 impl AppState {
-    pub fn new(pool: PgPool, config: EnvironmentConfig, redis: RedisClient) -> Self {
-        Self {
+    /// Construir el estado compartido
+    ///
+    /// Falla si las claves de cifrado de campos están configuradas pero son
+    /// inválidas: arrancar sin cifrador dejaría de servir los códigos de acceso.
+    pub fn new(pool: PgPool, config: EnvironmentConfig, redis: RedisClient) -> anyhow::Result<Self> {
+        let field_cipher = match &config.field_encryption_keys {
+            Some(spec) => {
+                let cipher = FieldCipher::from_spec(spec, config.field_encryption_active_key.as_deref())
+                    .map_err(|e| anyhow::anyhow!("FIELD_ENCRYPTION_KEYS inválida: {}", e))?;
+                log::info!("🔐 Cifrado de campos activo (clave '{}')", cipher.active_key_id());
+                Some(Arc::new(cipher))
+            }
+            None => {
+                log::warn!("⚠️ FIELD_ENCRYPTION_KEYS no configurada: no se podrán guardar códigos de acceso");
+                None
+            }
+        };
+
+        let http_client = Client::new();
+
+        let media = match config
+            .media
+            .build_backend(http_client.clone())
+            .and_then(|backend| {
+                if config.media.signing_key.as_deref() == Some(config.jwt_secret.as_str()) {
+                    return Err(StorageError::Config("MEDIA_SIGNING_KEY debe ser distinta de JWT_SECRET".to_string()));
+                }
+                MediaService::new(&config.media, backend)
+            })
+        {
+            Ok(service) => {
+                log::info!(
+                    "📸 Almacenamiento de archivos: {} (máx. {} bytes)",
+                    service.backend_name(),
+                    service.max_upload_bytes()
+                );
+                Some(Arc::new(service))
+            }
+            Err(e) => {
+                log::error!("❌ Configuración de almacenamiento de archivos inválida: {}", e);
+                None
+            }
+        };
+
+        let dispatch = DispatchFeed::new(redis.clone());
+
+        Ok(Self {
             pool,
             config,
             redis,
-            http_client: Client::new(),
+            http_client,
             auth_tokens: Arc::new(RwLock::new(HashMap::new())),
-        }
+            address_validation_jobs: AddressValidationJobStore::new(),
+            field_cipher,
+            media,
+            dispatch,
+        })
+    }
+
+    /// Cifrador de campos sensibles o error si no está configurado
+    pub fn field_cipher(&self) -> Result<&FieldCipher, crate::utils::errors::AppError> {
+        self.field_cipher.as_deref().ok_or_else(|| {
+            crate::utils::errors::AppError::ServiceUnavailable("Cifrado de campos no configurado".to_string())
+        })
+    }
+
+    /// Servicio de archivos o error si no está configurado
+    pub fn media(&self) -> Result<&MediaService, crate::utils::errors::AppError> {
+        self.media.as_deref().ok_or_else(|| {
+            crate::utils::errors::AppError::ServiceUnavailable("Almacenamiento de archivos no configurado".to_string())
+        })
     }
 
     /// Obtener token de autenticación para un usuario específico
@@ -0,0 +1,37 @@
+//! Exportación CSV
+//!
+//! Los CSV del sistema usan `;` como separador (el que abre Excel en francés)
+//! y comillas dobles para los campos que lo necesitan.
+
+/// Escapar un campo CSV si contiene separadores, comillas o saltos de línea
+pub fn escape_csv_field(field: &str) -> String {
+    if field.contains(';') || field.contains('"') || field.contains('\n') || field.contains('\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Fila CSV terminada en salto de línea
+pub fn csv_row(fields: &[String]) -> String {
+    let escaped: Vec<String> = fields.iter().map(|field| escape_csv_field(field)).collect();
+    format!("{}\n", escaped.join(";"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_csv_field() {
+        assert_eq!(escape_csv_field("12 RUE MARCADET"), "12 RUE MARCADET");
+        assert_eq!(escape_csv_field("BAT A; 3EME"), "\"BAT A; 3EME\"");
+        assert_eq!(escape_csv_field("dit \"le grand\""), "\"dit \"\"le grand\"\"\"");
+    }
+
+    #[test]
+    fn test_csv_row() {
+        let row = csv_row(&["CP1".to_string(), "BAT A; 3EME".to_string(), String::new()]);
+        assert_eq!(row, "CP1;\"BAT A; 3EME\";\n");
+    }
+}
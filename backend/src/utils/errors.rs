@@ -275,6 +275,16 @@ pub fn validation_error(field: &'static str, message: &'static str) -> AppError
     AppError::Validation(errors)
 }
 
+/// Convertir una violación de unicidad en 409 con un mensaje legible
+pub fn map_unique_violation(e: sqlx::Error, message: &str) -> AppError {
+    match &e {
+        sqlx::Error::Database(db) if db.code().as_deref() == Some("23505") => {
+            AppError::Conflict(message.to_string())
+        }
+        _ => AppError::Database(e),
+    }
+}
+
 /// Función helper para crear errores de recurso no encontrado
 pub fn not_found_error(resource: &str, id: &str) -> AppError {
     AppError::NotFound(format!("{} with id '{}' not found", resource, id))
@@ -0,0 +1,175 @@
+//! Calendario laboral francés
+//!
+//! Días festivos nacionales (jours fériés) de Francia metropolitana. Los
+//! festivos propios de Alsacia-Mosela (Viernes Santo, 26 de diciembre) no se
+//! incluyen. Los sábados son laborables para la entrega de paquetes; los
+//! domingos no.
+//!
+//! Los días se cuentan en hora de París (CET/CEST), no en UTC.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, Utc, Weekday};
+
+/// Domingo de Pascua (algoritmo anónimo gregoriano de Meeus/Jones/Butcher)
+pub fn easter_sunday(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32).expect("fecha de Pascua válida")
+}
+
+/// Los 11 festivos nacionales del año
+pub fn public_holidays(year: i32) -> Vec<NaiveDate> {
+    let fixed = [(1, 1), (5, 1), (5, 8), (7, 14), (8, 15), (11, 1), (11, 11), (12, 25)];
+    let easter = easter_sunday(year);
+
+    let mut holidays: Vec<NaiveDate> = fixed
+        .iter()
+        .filter_map(|(month, day)| NaiveDate::from_ymd_opt(year, *month, *day))
+        .collect();
+    holidays.extend([
+        easter + Duration::days(1),  // Lundi de Pâques
+        easter + Duration::days(39), // Ascension
+        easter + Duration::days(50), // Lundi de Pentecôte
+    ]);
+    holidays.sort();
+    holidays
+}
+
+pub fn is_public_holiday(date: NaiveDate) -> bool {
+    public_holidays(date.year()).contains(&date)
+}
+
+/// Día en el que se puede entregar: ni domingo ni festivo
+pub fn is_working_day(date: NaiveDate) -> bool {
+    date.weekday() != Weekday::Sun && !is_public_holiday(date)
+}
+
+/// Primer día laborable estrictamente posterior a `date`
+pub fn next_working_day(date: NaiveDate) -> NaiveDate {
+    let mut next = date + Duration::days(1);
+    while !is_working_day(next) {
+        next += Duration::days(1);
+    }
+    next
+}
+
+/// Último domingo del mes
+fn last_sunday(year: i32, month: u32) -> NaiveDate {
+    let next_month = if month == 12 {
+        NaiveDate::from_ymd_opt(year + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(year, month + 1, 1)
+    }
+    .expect("mes válido");
+    let last_day = next_month - Duration::days(1);
+    last_day - Duration::days(last_day.weekday().num_days_from_sunday() as i64)
+}
+
+/// Hora local de París: CEST (UTC+2) entre el último domingo de marzo y el
+/// último domingo de octubre a la 01:00 UTC, CET (UTC+1) el resto del año
+pub fn paris_local(at: DateTime<Utc>) -> NaiveDateTime {
+    let utc = at.naive_utc();
+    let year = utc.year();
+    let summer_start = last_sunday(year, 3).and_hms_opt(1, 0, 0).expect("hora válida");
+    let summer_end = last_sunday(year, 10).and_hms_opt(1, 0, 0).expect("hora válida");
+    let offset = if utc >= summer_start && utc < summer_end { 2 } else { 1 };
+    utc + Duration::hours(offset)
+}
+
+/// Instante UTC de una hora local de París. En la hora repetida del cambio de
+/// octubre se toma la de verano; las horas que no existen en marzo avanzan
+/// una hora.
+pub fn paris_to_utc(local: NaiveDateTime) -> DateTime<Utc> {
+    let summer = local - Duration::hours(2);
+    let utc = if paris_local(summer.and_utc()) == local { summer } else { local - Duration::hours(1) };
+    utc.and_utc()
+}
+
+/// Día actual en París
+pub fn paris_today() -> NaiveDate {
+    paris_local(Utc::now()).date()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_easter_sunday() {
+        assert_eq!(easter_sunday(2024), date(2024, 3, 31));
+        assert_eq!(easter_sunday(2025), date(2025, 4, 20));
+        assert_eq!(easter_sunday(2026), date(2026, 4, 5));
+        assert_eq!(easter_sunday(2038), date(2038, 4, 25));
+    }
+
+    #[test]
+    fn test_public_holidays_2025() {
+        let holidays = public_holidays(2025);
+        assert_eq!(holidays.len(), 11);
+        assert!(holidays.contains(&date(2025, 4, 21))); // Lundi de Pâques
+        assert!(holidays.contains(&date(2025, 5, 29))); // Ascension
+        assert!(holidays.contains(&date(2025, 6, 9))); // Lundi de Pentecôte
+        assert!(holidays.contains(&date(2025, 7, 14)));
+        assert!(!holidays.contains(&date(2025, 4, 18))); // Vendredi saint (solo Alsacia-Mosela)
+    }
+
+    #[test]
+    fn test_next_working_day_skips_sundays_and_holidays() {
+        // Sábado -> lunes
+        assert_eq!(next_working_day(date(2025, 3, 8)), date(2025, 3, 10));
+        // Viernes -> sábado (laborable)
+        assert_eq!(next_working_day(date(2025, 3, 7)), date(2025, 3, 8));
+        // Sábado de Pascua -> domingo y lunes de Pascua -> martes
+        assert_eq!(next_working_day(date(2025, 4, 19)), date(2025, 4, 22));
+        // 24 de diciembre -> 26
+        assert_eq!(next_working_day(date(2025, 12, 24)), date(2025, 12, 26));
+        // Fin de año -> 2 de enero
+        assert_eq!(next_working_day(date(2025, 12, 31)), date(2026, 1, 2));
+    }
+
+    #[test]
+    fn test_paris_local_follows_daylight_saving() {
+        let utc = |y, m, d, h, min| date(y, m, d).and_hms_opt(h, min, 0).unwrap().and_utc();
+        // Invierno: UTC+1, las 23:30 UTC ya son el día siguiente
+        assert_eq!(paris_local(utc(2025, 1, 14, 23, 30)).date(), date(2025, 1, 15));
+        assert_eq!(paris_local(utc(2025, 1, 14, 22, 30)).date(), date(2025, 1, 14));
+        // Verano: UTC+2
+        assert_eq!(paris_local(utc(2025, 7, 1, 22, 30)).date(), date(2025, 7, 2));
+        // Cambios de hora de 2025: 30 de marzo y 26 de octubre a la 01:00 UTC
+        assert_eq!(paris_local(utc(2025, 3, 30, 0, 59)), date(2025, 3, 30).and_hms_opt(1, 59, 0).unwrap());
+        assert_eq!(paris_local(utc(2025, 3, 30, 1, 0)), date(2025, 3, 30).and_hms_opt(3, 0, 0).unwrap());
+        assert_eq!(paris_local(utc(2025, 10, 26, 0, 59)), date(2025, 10, 26).and_hms_opt(2, 59, 0).unwrap());
+        assert_eq!(paris_local(utc(2025, 10, 26, 1, 0)), date(2025, 10, 26).and_hms_opt(2, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn test_paris_to_utc() {
+        let local = |y, m, d, h, min| date(y, m, d).and_hms_opt(h, min, 0).unwrap();
+        assert_eq!(paris_to_utc(local(2024, 3, 12, 7, 42)).naive_utc(), local(2024, 3, 12, 6, 42));
+        assert_eq!(paris_to_utc(local(2024, 7, 12, 7, 42)).naive_utc(), local(2024, 7, 12, 5, 42));
+        // 02:30 del 26 de octubre de 2025 existe dos veces: la de verano
+        assert_eq!(paris_to_utc(local(2025, 10, 26, 2, 30)).naive_utc(), local(2025, 10, 26, 0, 30));
+        // 02:30 del 30 de marzo de 2025 no existe
+        assert_eq!(paris_to_utc(local(2025, 3, 30, 2, 30)).naive_utc(), local(2025, 3, 30, 1, 30));
+        for hour in 0..24 {
+            let at = local(2025, 6, 1, hour, 0);
+            assert_eq!(paris_local(paris_to_utc(at)), at);
+        }
+    }
+}
@@ -29,8 +29,6 @@ pub struct JwtClaims {
 pub struct JwtConfig {
     pub secret: String,
     pub expiration: u64,
-    pub issuer: Option<String>,
-    pub audience: Option<String>,
 }
 
 impl From<&EnvironmentConfig> for JwtConfig {
@@ -38,8 +36,6 @@ impl From<&EnvironmentConfig> for JwtConfig {
         Self {
             secret: config.jwt_secret.clone(),
             expiration: config.jwt_expiration,
-            issuer: None,
-            audience: None,
         }
     }
 }
@@ -188,8 +184,6 @@ mod tests {
         JwtConfig {
             secret: "test-secret-key".to_string(),
             expiration: 3600, // 1 hora
-            issuer: None,
-            audience: None,
         }
     }
 
@@ -213,8 +207,6 @@ mod tests {
         let config = JwtConfig {
             secret: "test-secret".to_string(),
             expiration: 1, // 1 segundo
-            issuer: None,
-            audience: None,
         };
 
         let user_id = Uuid::new_v4();
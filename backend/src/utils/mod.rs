@@ -6,6 +6,9 @@
 pub mod errors;
 pub mod validation;
 pub mod jwt;
+pub mod french_holidays;
+pub mod pdf;
+pub mod csv;
 // encoding eliminado - era para reverse engineering de API móvil
 // pub mod headers; // Módulo eliminado
 
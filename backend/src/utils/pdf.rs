@@ -0,0 +1,220 @@
+//! Generación de PDF de texto
+//!
+//! PDF 1.4 mínimo con líneas de texto en Helvetica (WinAnsiEncoding), A4,
+//! cortes de línea por ancho y paginación automática. Suficiente para
+//! informes imprimibles como el compte-rendu de fin de día, sin depender de
+//! un crate de PDF.
+
+/// Ancho de la página A4 en puntos
+const PAGE_WIDTH: u32 = 595;
+/// Alto de la página A4 en puntos
+const PAGE_HEIGHT: u32 = 842;
+const MARGIN: u32 = 40;
+const FONT_SIZE: u32 = 10;
+const LEADING: u32 = 13;
+
+/// Anchos de Helvetica (milésimas de em) de los caracteres ASCII 32..=126
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, // ' '..'/'
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, // '0'..'?'
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778, // '@'..'O'
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556, // 'P'..'_'
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556, // '`'..'o'
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584, // 'p'..'~'
+];
+
+/// Ancho de un carácter en milésimas de em; fuera de ASCII se toma el de una
+/// mayúscula ancha (À..ß) o una minúscula (resto), por exceso
+fn char_width(c: char) -> u32 {
+    match c as u32 {
+        code @ 32..=126 => HELVETICA_WIDTHS[(code - 32) as usize] as u32,
+        0xc0..=0xdf => 778,
+        _ => 556,
+    }
+}
+
+/// Ancho de un texto en puntos
+fn text_width(text: &str) -> f64 {
+    text.chars().map(char_width).sum::<u32>() as f64 * FONT_SIZE as f64 / 1000.0
+}
+
+/// Partir una línea en las que caben entre márgenes: por palabras y, si una
+/// palabra sola no cabe, por caracteres
+fn wrap_line(line: &str) -> Vec<String> {
+    let max_width = (PAGE_WIDTH - 2 * MARGIN) as f64;
+    let line = line.replace('\t', "    ");
+    if text_width(&line) <= max_width {
+        return vec![line];
+    }
+
+    let mut wrapped = Vec::new();
+    let mut current = String::new();
+    for word in line.split(' ') {
+        let candidate = if current.is_empty() { word.to_string() } else { format!("{} {}", current, word) };
+        if text_width(&candidate) <= max_width {
+            current = candidate;
+            continue;
+        }
+        if !current.is_empty() {
+            wrapped.push(std::mem::take(&mut current));
+        }
+        for c in word.chars() {
+            current.push(c);
+            if text_width(&current) > max_width {
+                current.pop();
+                wrapped.push(std::mem::replace(&mut current, c.to_string()));
+            }
+        }
+    }
+    wrapped.push(current);
+    wrapped
+}
+
+/// Líneas que caben en una página
+pub fn lines_per_page() -> usize {
+    ((PAGE_HEIGHT - 2 * MARGIN) / LEADING) as usize
+}
+
+/// Codificar una línea como cadena literal PDF: los caracteres Latin-1 pasan
+/// tal cual (coinciden con WinAnsi), el resto se sustituye por `?`
+fn encode_line(line: &str) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(line.len() + 2);
+    encoded.push(b'(');
+    for c in line.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                encoded.push(b'\\');
+                encoded.push(c as u8);
+            }
+            '\t' => encoded.extend_from_slice(b"    "),
+            c if (c as u32) < 0x20 => {}
+            c if (c as u32) < 0x7f || (0xa0..=0xff).contains(&(c as u32)) => encoded.push(c as u32 as u8),
+            _ => encoded.push(b'?'),
+        }
+    }
+    encoded.push(b')');
+    encoded
+}
+
+/// Contenido de una página
+fn page_content(lines: &[String]) -> Vec<u8> {
+    let mut content = format!(
+        "BT\n/F1 {} Tf\n{} TL\n{} {} Td\n",
+        FONT_SIZE,
+        LEADING,
+        MARGIN,
+        PAGE_HEIGHT - MARGIN - FONT_SIZE
+    )
+    .into_bytes();
+    for line in lines {
+        content.extend(encode_line(line));
+        content.extend_from_slice(b" Tj T*\n");
+    }
+    content.extend_from_slice(b"ET\n");
+    content
+}
+
+/// Generar un PDF con las líneas de texto dadas (las largas se parten)
+pub fn render_text(lines: &[String]) -> Vec<u8> {
+    let lines: Vec<String> = lines.iter().flat_map(|line| wrap_line(line)).collect();
+    let empty = [String::new()];
+    let pages: Vec<&[String]> = if lines.is_empty() {
+        vec![&empty[..]]
+    } else {
+        lines.chunks(lines_per_page()).collect()
+    };
+
+    // 1: catálogo, 2: árbol de páginas, 3: fuente, luego página y contenido
+    let mut objects: Vec<Vec<u8>> = Vec::with_capacity(3 + pages.len() * 2);
+    let kids: Vec<String> = (0..pages.len()).map(|i| format!("{} 0 R", 4 + i * 2)).collect();
+    objects.push(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+    objects.push(format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), pages.len()).into_bytes());
+    objects.push(b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_vec());
+    for (i, page) in pages.iter().enumerate() {
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+                PAGE_WIDTH,
+                PAGE_HEIGHT,
+                5 + i * 2
+            )
+            .into_bytes(),
+        );
+        let content = page_content(page);
+        let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+        stream.extend(content);
+        stream.extend_from_slice(b"endstream");
+        objects.push(stream);
+    }
+
+    let mut pdf = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend(format!("{} 0 obj\n", i + 1).into_bytes());
+        pdf.extend_from_slice(object);
+        pdf.extend_from_slice(b"\nendobj\n");
+    }
+
+    let xref = pdf.len();
+    pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).into_bytes());
+    for offset in offsets {
+        pdf.extend(format!("{:010} 00000 n \n", offset).into_bytes());
+    }
+    pdf.extend(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref
+        )
+        .into_bytes(),
+    );
+    pdf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_line_escapes_and_latin1() {
+        assert_eq!(encode_line("a (b) \\c"), b"(a \\(b\\) \\\\c)".to_vec());
+        assert_eq!(encode_line("Tournée"), b"(Tourn\xe9e)".to_vec());
+        assert_eq!(encode_line("✓ ok"), b"(? ok)".to_vec());
+    }
+
+    #[test]
+    fn test_wrap_line_fits_between_margins() {
+        let max_width = (PAGE_WIDTH - 2 * MARGIN) as f64;
+        assert_eq!(wrap_line("CP123456789 entregado"), vec!["CP123456789 entregado".to_string()]);
+
+        let address = "12 rue Marcadet, bâtiment B, escalier 3, 4ème étage, porte gauche, ".repeat(4);
+        let wrapped = wrap_line(address.trim_end());
+        assert!(wrapped.len() > 1);
+        assert!(wrapped.iter().all(|line| text_width(line) <= max_width));
+        assert_eq!(wrapped.join(" "), address.trim_end());
+
+        // Una palabra más larga que la línea se corta por caracteres
+        let token = "W".repeat(100);
+        let wrapped = wrap_line(&token);
+        assert!(wrapped.len() > 1);
+        assert!(wrapped.iter().all(|line| text_width(line) <= max_width));
+        assert_eq!(wrapped.concat(), token);
+    }
+
+    #[test]
+    fn test_render_text_paginates_with_valid_xref() {
+        let lines: Vec<String> = (0..lines_per_page() + 5).map(|i| format!("Línea {}", i)).collect();
+        let pdf = render_text(&lines);
+        let text = String::from_utf8_lossy(&pdf);
+
+        assert!(pdf.starts_with(b"%PDF-1.4"));
+        assert!(text.ends_with("%%EOF\n"));
+        assert!(text.contains("/Count 2"));
+
+        // startxref apunta a la tabla xref
+        let start = text.rfind("startxref\n").unwrap() + "startxref\n".len();
+        let offset: usize = text[start..].lines().next().unwrap().parse().unwrap();
+        assert!(pdf[offset..].starts_with(b"xref"));
+    }
+}
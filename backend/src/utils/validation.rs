@@ -8,6 +8,8 @@ use uuid::Uuid;
 use validator::ValidationError;
 use serde::Serialize;
 
+use crate::utils::errors::{AppError, AppResult};
+
 /// Validar y convertir string a UUID
 pub fn validate_uuid(value: &str) -> Result<Uuid, ValidationError> {
     Uuid::parse_str(value).map_err(|_| {
@@ -27,6 +29,12 @@ pub fn validate_date(value: &str) -> Result<NaiveDate, ValidationError> {
     })
 }
 
+/// Convertir string a fecha (400 si no es AAAA-MM-DD)
+pub fn parse_date(value: &str) -> AppResult<NaiveDate> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| AppError::BadRequest(format!("Fecha inválida (AAAA-MM-DD): {}", value)))
+}
+
 /// Validar y convertir string a tiempo
 pub fn validate_time(value: &str) -> Result<NaiveTime, ValidationError> {
     NaiveTime::parse_from_str(value, "%H:%M:%S").map_err(|_| {
@@ -0,0 +1,272 @@
+//! Tests de integración de `/api/v1`
+//!
+//! Se ejecutan contra un servidor levantado con el mismo `JWT_SECRET` y su
+//! base de datos (schema cargado):
+//! `TEST_API_URL=http://localhost:3000 TEST_DATABASE_URL=postgres://... JWT_SECRET=... cargo test --test api_v1 -- --ignored`
+
+use jsonwebtoken::{encode, EncodingKey, Header};
+use reqwest::{Method, StatusCode};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+struct TestCompany {
+    id: Uuid,
+    admin_token: String,
+    driver_id: Uuid,
+    driver_token: String,
+    vehicle_id: Uuid,
+}
+
+struct TestContext {
+    pool: PgPool,
+    client: reqwest::Client,
+    base_url: String,
+}
+
+/// Claims con el formato de `utils::jwt`
+#[derive(Serialize)]
+struct Claims {
+    sub: String,
+    company_id: String,
+    user_type: String,
+    exp: usize,
+    iat: usize,
+}
+
+fn token(user_id: Uuid, company_id: Uuid, user_type: &str) -> String {
+    let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET no configurada");
+    let now = chrono::Utc::now().timestamp() as usize;
+    let claims = Claims {
+        sub: user_id.to_string(),
+        company_id: company_id.to_string(),
+        user_type: user_type.to_string(),
+        exp: now + 3600,
+        iat: now,
+    };
+    encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_bytes())).unwrap()
+}
+
+async fn context() -> TestContext {
+    let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL no configurada");
+    TestContext {
+        pool: PgPool::connect(&url).await.expect("No se pudo conectar a PostgreSQL"),
+        client: reqwest::Client::new(),
+        base_url: std::env::var("TEST_API_URL").unwrap_or_else(|_| "http://localhost:3000".to_string()),
+    }
+}
+
+/// Empresa con un admin, un chofer y un vehículo
+async fn seed_company(ctx: &TestContext, name: &str) -> TestCompany {
+    let id: Uuid = sqlx::query_scalar("INSERT INTO companies (name, address) VALUES ($1, '1 rue de Test, Paris') RETURNING id")
+        .bind(name)
+        .fetch_one(&ctx.pool)
+        .await
+        .unwrap();
+
+    let mut users = Vec::new();
+    for (username, user_type) in [("admin", "admin"), ("driver", "driver")] {
+        let user_id: Uuid = sqlx::query_scalar(
+            r#"
+            INSERT INTO users (company_id, user_type, username, password_hash, full_name)
+            VALUES ($1, $2::user_type, $3, 'x', $3)
+            RETURNING id
+            "#,
+        )
+        .bind(id)
+        .bind(user_type)
+        .bind(username)
+        .fetch_one(&ctx.pool)
+        .await
+        .unwrap();
+        users.push((user_id, token(user_id, id, user_type)));
+    }
+
+    let vehicle_id: Uuid = sqlx::query_scalar(
+        "INSERT INTO vehicles (company_id, license_plate, brand, model) VALUES ($1, 'AB-123-CD', 'Renault', 'Kangoo') RETURNING id",
+    )
+    .bind(id)
+    .fetch_one(&ctx.pool)
+    .await
+    .unwrap();
+
+    let (driver_id, driver_token) = users.pop().unwrap();
+    let (_, admin_token) = users.pop().unwrap();
+    TestCompany { id, admin_token, driver_id, driver_token, vehicle_id }
+}
+
+async fn cleanup(ctx: &TestContext, companies: &[&TestCompany]) {
+    for company in companies {
+        sqlx::query("DELETE FROM companies WHERE id = $1")
+            .bind(company.id)
+            .execute(&ctx.pool)
+            .await
+            .unwrap();
+    }
+}
+
+async fn call(
+    ctx: &TestContext,
+    method: Method,
+    uri: &str,
+    token: Option<&str>,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let mut request = ctx.client.request(method, format!("{}{}", ctx.base_url, uri));
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+
+    let response = request.send().await.unwrap();
+    let status = response.status();
+    let bytes = response.bytes().await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_v1_requires_jwt_except_public_auth() {
+    let ctx = context().await;
+
+    for uri in ["/api/v1/companies", "/api/v1/users", "/api/v1/vehicles", "/api/v1/tournees", "/api/v1/packages", "/api/v1/analytics/dashboard", "/api/v1/auth/me"] {
+        let (status, _) = call(&ctx, Method::GET, uri, None, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{} debería exigir JWT", uri);
+    }
+
+    let (status, _) = call(&ctx, Method::GET, "/api/v1/vehicles", Some("no-es-un-jwt"), None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Login es público: llega al handler (validación) sin token
+    let (status, _) = call(&ctx, Method::POST, "/api/v1/auth/login", None, Some(json!({ "username": "x", "password": "123456" }))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_v1_is_scoped_to_company() {
+    let ctx = context().await;
+    let a = seed_company(&ctx, "Empresa A").await;
+    let b = seed_company(&ctx, "Empresa B").await;
+
+    let (status, vehicle) = call(&ctx, Method::GET, &format!("/api/v1/vehicles/{}", a.vehicle_id), Some(&a.admin_token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(vehicle["license_plate"], "AB-123-CD");
+
+    let (status, _) = call(&ctx, Method::GET, &format!("/api/v1/vehicles/{}", a.vehicle_id), Some(&b.admin_token), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (_, vehicles) = call(&ctx, Method::GET, "/api/v1/vehicles", Some(&b.admin_token), None).await;
+    let ids: Vec<&str> = vehicles.as_array().unwrap().iter().map(|v| v["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, vec![b.vehicle_id.to_string()]);
+
+    let (status, _) = call(&ctx, Method::GET, &format!("/api/v1/companies/{}", a.id), Some(&b.admin_token), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = call(&ctx, Method::DELETE, &format!("/api/v1/users/{}", a.driver_id), Some(&b.admin_token), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    cleanup(&ctx, &[&a, &b]).await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_v1_tournee_and_package_flow() {
+    let ctx = context().await;
+    let a = seed_company(&ctx, "Empresa Flujo").await;
+
+    // Los choferes no planifican ni gestionan usuarios
+    let (status, _) = call(&ctx, Method::GET, "/api/v1/users", Some(&a.driver_token), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, me) = call(&ctx, Method::GET, &format!("/api/v1/users/{}", a.driver_id), Some(&a.driver_token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(me["user_type"], "Driver");
+
+    let (status, tournee) = call(
+        &ctx,
+        Method::POST,
+        "/api/v1/tournees",
+        Some(&a.admin_token),
+        Some(json!({ "driver_id": a.driver_id, "vehicle_id": a.vehicle_id })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(tournee["tournee_status"], "pending");
+    let tournee_id = tournee["id"].as_str().unwrap().to_string();
+
+    let (status, package) = call(
+        &ctx,
+        Method::POST,
+        "/api/v1/packages",
+        Some(&a.admin_token),
+        Some(json!({ "tournee_id": tournee_id, "tracking_number": "CP123456789", "delivery_address": "12 rue Marcadet, 75018 Paris" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let package_id = package["id"].as_str().unwrap().to_string();
+
+    let (status, started) = call(
+        &ctx,
+        Method::POST,
+        &format!("/api/v1/tournees/{}/start", tournee_id),
+        Some(&a.driver_token),
+        Some(json!({ "start_mileage": "1000" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(started["tournee_status"], "in_progress");
+
+    let (status, delivered) = call(
+        &ctx,
+        Method::POST,
+        &format!("/api/v1/packages/{}/delivered", package_id),
+        Some(&a.driver_token),
+        Some(json!({})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(delivered["delivery_status"], "delivered");
+    assert_eq!(delivered["delivery_attempts"], 1);
+
+    let (_, summary) = call(&ctx, Method::GET, "/api/v1/analytics/dashboard", Some(&a.admin_token), None).await;
+    assert_eq!(summary["total_packages"], 1);
+    assert_eq!(summary["delivered_packages"], 1);
+    assert_eq!(summary["active_tournees"], 1);
+
+    cleanup(&ctx, &[&a]).await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_dispatch_stream_accepts_short_lived_token() {
+    let ctx = context().await;
+    let a = seed_company(&ctx, "Empresa Stream").await;
+    let stream_status = |token: &str| {
+        let request = ctx
+            .client
+            .get(format!("{}/api/v1/dispatch/stream", ctx.base_url))
+            .query(&[("access_token", token)]);
+        async move { request.send().await.unwrap().status() }
+    };
+
+    // El JWT de sesión no vale en la URL
+    assert_eq!(stream_status(&a.admin_token).await, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = call(&ctx, Method::POST, "/api/v1/dispatch/stream-token", Some(&a.driver_token), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, body) = call(&ctx, Method::POST, "/api/v1/dispatch/stream-token", Some(&a.admin_token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let stream_token = body["token"].as_str().unwrap().to_string();
+
+    // El token del canal no abre el resto de la API
+    let (status, _) = call(&ctx, Method::GET, "/api/v1/vehicles", Some(&stream_token), None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    assert_eq!(stream_status(&stream_token).await, StatusCode::OK);
+
+    cleanup(&ctx, &[&a]).await;
+}
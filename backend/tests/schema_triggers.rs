@@ -0,0 +1,138 @@
+//! Tests de los triggers del schema
+//!
+//! Solo necesitan la base de datos con el schema cargado:
+//! `TEST_DATABASE_URL=postgres://... cargo test --test schema_triggers -- --ignored`
+
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Filas mínimas para colgar pruebas de entrega e historial
+struct Seed {
+    company_id: Uuid,
+    tournee_id: Uuid,
+    package_id: Uuid,
+}
+
+async fn pool() -> PgPool {
+    let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL no configurada");
+    PgPool::connect(&url).await.expect("No se pudo conectar a PostgreSQL")
+}
+
+async fn seed(pool: &PgPool) -> Seed {
+    let company_id: Uuid = sqlx::query_scalar("INSERT INTO companies (name, address) VALUES ('Empresa Triggers', '1 rue de Test, Paris') RETURNING id")
+        .fetch_one(pool)
+        .await
+        .unwrap();
+    let driver_id: Uuid = sqlx::query_scalar(
+        "INSERT INTO users (company_id, user_type, username, password_hash, full_name) VALUES ($1, 'driver', 'driver', 'x', 'driver') RETURNING id",
+    )
+    .bind(company_id)
+    .fetch_one(pool)
+    .await
+    .unwrap();
+    let vehicle_id: Uuid = sqlx::query_scalar(
+        "INSERT INTO vehicles (company_id, license_plate, brand, model) VALUES ($1, 'AB-123-CD', 'Renault', 'Kangoo') RETURNING id",
+    )
+    .bind(company_id)
+    .fetch_one(pool)
+    .await
+    .unwrap();
+    let tournee_id: Uuid = sqlx::query_scalar(
+        "INSERT INTO tournees (company_id, driver_id, vehicle_id, tournee_date) VALUES ($1, $2, $3, CURRENT_DATE) RETURNING id",
+    )
+    .bind(company_id)
+    .bind(driver_id)
+    .bind(vehicle_id)
+    .fetch_one(pool)
+    .await
+    .unwrap();
+    let package_id: Uuid = sqlx::query_scalar(
+        "INSERT INTO packages (company_id, tournee_id, tracking_number, delivery_address) VALUES ($1, $2, 'CP123456789', '12 rue Marcadet, 75018 Paris') RETURNING id",
+    )
+    .bind(company_id)
+    .bind(tournee_id)
+    .fetch_one(pool)
+    .await
+    .unwrap();
+
+    Seed { company_id, tournee_id, package_id }
+}
+
+/// Ejecutar una sentencia en una transacción que se descarta
+async fn rejected(pool: &PgPool, sql: &str, id: Uuid) -> bool {
+    let mut tx: Transaction<'_, Postgres> = pool.begin().await.unwrap();
+    let result = sqlx::query(sql).bind(id).execute(&mut *tx).await;
+    tx.rollback().await.unwrap();
+    result.is_err()
+}
+
+async fn cleanup(pool: &PgPool, seed: &Seed) {
+    // Borrar la empresa entera sí arrastra sus filas inmutables
+    sqlx::query("DELETE FROM companies WHERE id = $1")
+        .bind(seed.company_id)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_delivery_proofs_cannot_be_updated_or_deleted() {
+    let pool = pool().await;
+    let seed = seed(&pool).await;
+
+    let proof_id: Uuid = sqlx::query_scalar(
+        r#"
+        INSERT INTO delivery_proofs (
+            company_id, package_id, tournee_id, tracking_number, recipient_name,
+            latitude, longitude, gps_accuracy_meters, device_timestamp, server_timestamp,
+            chain_position, previous_hash, evidence_hash, record_hash
+        ) VALUES ($1, $2, $3, 'CP123456789', 'Mme Martin', 48.89, 2.34, 8, NOW(), NOW(), 1, 'genesis', 'e', 'r')
+        RETURNING id
+        "#,
+    )
+    .bind(seed.company_id)
+    .bind(seed.package_id)
+    .bind(seed.tournee_id)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+
+    assert!(rejected(&pool, "UPDATE delivery_proofs SET recipient_name = 'Otro' WHERE id = $1", proof_id).await);
+    assert!(rejected(&pool, "DELETE FROM delivery_proofs WHERE id = $1", proof_id).await);
+
+    cleanup(&pool, &seed).await;
+    let left: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM delivery_proofs WHERE id = $1")
+        .bind(proof_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(left, 0);
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_status_history_cannot_be_updated_or_deleted() {
+    let pool = pool().await;
+    let seed = seed(&pool).await;
+
+    let history_id: Uuid = sqlx::query_scalar(
+        "INSERT INTO status_history (company_id, entity_type, entity_id, from_status, to_status) VALUES ($1, 'package', $2, 'pending', 'delivered') RETURNING id",
+    )
+    .bind(seed.company_id)
+    .bind(seed.package_id)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+
+    assert!(rejected(&pool, "UPDATE status_history SET to_status = 'failed' WHERE id = $1", history_id).await);
+    assert!(rejected(&pool, "DELETE FROM status_history WHERE id = $1", history_id).await);
+
+    cleanup(&pool, &seed).await;
+    let left: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM status_history WHERE id = $1")
+        .bind(history_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(left, 0);
+}